@@ -5,8 +5,9 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use bitcoin::hashes::hex::ToHex;
+use bitcoin::util::merkleblock::PartialMerkleTree;
 use bitcoin::util::uint::Uint256;
-use bitcoin::{Block, BlockHash, BlockHeader};
+use bitcoin::{Block, BlockHash, BlockHeader, Transaction, Txid};
 use jsonrpc_async::error::Error::Rpc;
 use jsonrpc_async::simple_http::SimpleHttpTransport;
 use jsonrpc_async::Client;
@@ -35,6 +36,8 @@ pub enum Error {
     Json(serde_json::error::Error),
     /// IO Error
     Io(std::io::Error),
+    /// Esplora HTTP or response Error
+    Esplora(String),
 }
 
 impl Display for Error {
@@ -150,6 +153,30 @@ pub trait BlockSource: Sync + Send {
     async fn get_best_block(&self) -> BlockSourceResult<(BlockHash, u32)>;
 }
 
+/// The transactions of a block that match, with the proof of their
+/// inclusion that [lightning_signer::chain::tracker::ChainTracker::add_block]
+/// expects, or no proof if none match.
+///
+/// `matches` has an entry for each transaction of the block.
+pub fn block_txs_proof(
+    block: Block,
+    matches: &[bool],
+) -> (Vec<Transaction>, Option<PartialMerkleTree>) {
+    let txids: Vec<Txid> = block.txdata.iter().map(|tx| tx.txid()).collect();
+    let matched_txs: Vec<Transaction> = block
+        .txdata
+        .into_iter()
+        .zip(matches.iter())
+        .filter(|(_, m)| **m)
+        .map(|(tx, _)| tx)
+        .collect();
+    if matched_txs.is_empty() {
+        (vec![], None)
+    } else {
+        (matched_txs, Some(PartialMerkleTree::from_txids(&txids, matches)))
+    }
+}
+
 /// A block header and some associated data. This information should be available from most block
 /// sources (and, notably, is available in Bitcoin Core's RPC and REST interfaces).
 #[derive(Clone, Copy, Debug, PartialEq)]
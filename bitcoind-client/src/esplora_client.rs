@@ -0,0 +1,130 @@
+use std::io::Read;
+
+use async_trait::async_trait;
+use bitcoin::consensus::deserialize;
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::util::uint::Uint256;
+use bitcoin::{Block, BlockHash, BlockHeader};
+use lightning_signer::bitcoin;
+use log::error;
+use serde_json::Value;
+
+use crate::bitcoind_client::{BlockHeaderData, BlockSource, BlockSourceResult, Error};
+
+/// Async client for the Esplora HTTP API of an electrs or Esplora server,
+/// such as `https://blockstream.info/testnet/api`, so that the chain can be
+/// followed without a bitcoind.
+///
+/// Esplora doesn't report the chain work, which is left at zero in the
+/// [BlockHeaderData].
+#[derive(Clone)]
+pub struct EsploraClient {
+    base_url: String,
+    agent: ureq::Agent,
+}
+
+impl EsploraClient {
+    /// Create a new EsploraClient for the API at the URL
+    pub fn new(url: &str) -> Self {
+        let base_url = url.trim_end_matches('/').to_string();
+        Self { base_url, agent: ureq::Agent::new() }
+    }
+
+    // Get the body at the path, or None if it is not found
+    async fn get(&self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        let url = format!("{}{}", self.base_url, path);
+        let agent = self.agent.clone();
+        tokio::task::spawn_blocking(move || match agent.get(&url).call() {
+            Ok(response) => {
+                let mut body = Vec::new();
+                response.into_reader().read_to_end(&mut body)?;
+                Ok(Some(body))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => {
+                error!("{}: {}", url, e);
+                Err(Error::Esplora(format!("{}: {}", url, e)))
+            }
+        })
+        .await
+        .map_err(|e| Error::Esplora(format!("{}: {}", path, e)))?
+    }
+
+    async fn get_existing(&self, path: &str) -> Result<Vec<u8>, Error> {
+        self.get(path).await?.ok_or_else(|| Error::Esplora(format!("{}: not found", path)))
+    }
+
+    async fn get_text(&self, path: &str) -> Result<String, Error> {
+        let body = self.get_existing(path).await?;
+        let text = String::from_utf8(body)
+            .map_err(|_| Error::Esplora(format!("{}: response is not text", path)))?;
+        Ok(text.trim().to_string())
+    }
+
+    async fn get_height(&self, hash: &BlockHash) -> Result<u32, Error> {
+        let body = self.get_existing(&format!("/block/{}", hash)).await?;
+        let value: Value = serde_json::from_slice(&body)?;
+        value["height"]
+            .as_u64()
+            .map(|height| height as u32)
+            .ok_or_else(|| Error::Esplora(format!("block {}: missing height", hash)))
+    }
+}
+
+fn decode<T: bitcoin::consensus::Decodable>(what: &str, bytes: &[u8]) -> Result<T, Error> {
+    deserialize(bytes).map_err(|e| Error::Esplora(format!("{}: {}", what, e)))
+}
+
+#[async_trait]
+impl BlockSource for EsploraClient {
+    async fn get_header(
+        &self,
+        header_hash: &BlockHash,
+        _height_hint: Option<u32>,
+    ) -> BlockSourceResult<BlockHeaderData> {
+        let path = format!("/block/{}/header", header_hash);
+        let hex = self.get_text(&path).await?;
+        let bytes = Vec::from_hex(&hex).map_err(|e| Error::Esplora(format!("{}: {}", path, e)))?;
+        let header: BlockHeader = decode(&path, &bytes)?;
+        if header.block_hash() != *header_hash {
+            return Err(Error::Esplora(format!(
+                "{}: header has hash {}",
+                path,
+                header.block_hash()
+            )));
+        }
+        let height = self.get_height(header_hash).await?;
+        Ok(BlockHeaderData { header, height, chainwork: Uint256::from_u64(0).unwrap() })
+    }
+
+    async fn get_block(&self, header_hash: &BlockHash) -> BlockSourceResult<Block> {
+        let path = format!("/block/{}/raw", header_hash);
+        let block: Block = decode(&path, &self.get_existing(&path).await?)?;
+        if block.block_hash() != *header_hash {
+            return Err(Error::Esplora(format!("{}: block has hash {}", path, block.block_hash())));
+        }
+        Ok(block)
+    }
+
+    async fn get_block_hash(&self, height: u32) -> BlockSourceResult<Option<BlockHash>> {
+        let path = format!("/block-height/{}", height);
+        match self.get(&path).await? {
+            None => Ok(None),
+            Some(body) => {
+                let hex = String::from_utf8_lossy(&body);
+                let hash = BlockHash::from_hex(hex.trim())
+                    .map_err(|e| Error::Esplora(format!("{}: {}", path, e)))?;
+                Ok(Some(hash))
+            }
+        }
+    }
+
+    async fn get_best_block(&self) -> BlockSourceResult<(BlockHash, u32)> {
+        let path = "/blocks/tip/hash";
+        let hash = BlockHash::from_hex(&self.get_text(path).await?)
+            .map_err(|e| Error::Esplora(format!("{}: {}", path, e)))?;
+        // the height of that block, in case the tip moved in between
+        let height = self.get_height(&hash).await?;
+        Ok((hash, height))
+    }
+}
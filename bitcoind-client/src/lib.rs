@@ -1,6 +1,7 @@
 #![crate_name = "bitcoind_client"]
 
-//! A bitcoind RPC client.
+//! A bitcoind RPC client, and an Esplora client for following the chain
+//! without a bitcoind.
 
 #![forbid(unsafe_code)]
 #![allow(bare_trait_objects)]
@@ -11,5 +12,8 @@
 /// Bitcoind RPC client
 pub mod bitcoind_client;
 mod convert;
+/// Esplora HTTP API client
+pub mod esplora_client;
 
-pub use self::bitcoind_client::{BitcoindClient, BlockSource};
+pub use self::bitcoind_client::{block_txs_proof, BitcoindClient, BlockSource};
+pub use self::esplora_client::EsploraClient;
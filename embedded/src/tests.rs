@@ -127,6 +127,8 @@ pub fn test_lightning_signer(postscript: fn()) {
     let config = NodeConfig {
         network: bitcoin::Network::Signet,
         key_derivation_style: KeyDerivationStyle::Native,
+        chain_params: None,
+        checkpoint: None,
     };
     let seed = [0u8; 32];
     let seed1 = [1u8; 32];
@@ -135,8 +137,10 @@ pub fn test_lightning_signer(postscript: fn()) {
     policy.require_invoices = true;
     policy.enforce_balance = true;
     let factory = Arc::new(SimpleValidatorFactory::new_with_policy(policy));
-    let node = Arc::new(Node::new(config, &seed, &persister, Vec::new(), factory.clone()));
-    let node1 = Arc::new(Node::new(config, &seed1, &persister, Vec::new(), factory));
+    let node = Arc::new(
+        Node::new(config.clone(), &seed, &persister, Vec::new(), factory.clone()).unwrap(),
+    );
+    let node1 = Arc::new(Node::new(config, &seed1, &persister, Vec::new(), factory).unwrap());
 
     assert_eq!(node.ecdh(&node1.get_id()), node1.ecdh(&node.get_id()));
 
@@ -204,9 +208,9 @@ pub fn test_lightning_signer(postscript: fn()) {
     // these are just to lightly cover these functions
     node.add_allowlist(&vec!["helloworld".to_string()]).expect_err("bad address");
     node.remove_allowlist(&vec!["helloworld".to_string()]).expect_err("bad address");
-    node.sign_node_announcement(&vec![]).unwrap();
-    node.sign_channel_update(&vec![]).unwrap();
-    channel.sign_channel_announcement(&vec![]);
+    node.sign_node_announcement(&vec![]).expect_err("too short");
+    node.sign_channel_update(&vec![]).expect_err("too short");
+    channel.sign_channel_announcement(&vec![]).unwrap();
 
     postscript();
 }
@@ -304,8 +308,8 @@ fn next_state(
         .unwrap();
 
     if commit_num > 0 {
-        let revoke = channel.get_per_commitment_secret(commit_num - 1).unwrap();
-        let revoke1 = channel1.get_per_commitment_secret(commit_num - 1).unwrap();
+        let revoke = channel.get_revocation(commit_num - 1).unwrap().per_commitment_secret;
+        let revoke1 = channel1.get_revocation(commit_num - 1).unwrap().per_commitment_secret;
         channel1.validate_counterparty_revocation(commit_num - 1, &revoke).unwrap();
         channel.validate_counterparty_revocation(commit_num - 1, &revoke1).unwrap();
     }
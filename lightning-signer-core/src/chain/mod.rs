@@ -1,2 +1,25 @@
+macro_rules! error_invalid_chain {
+    ($($arg:tt)*) => {{
+        error!("InvalidChain: {}", format!($($arg)*));
+        Error::InvalidChain
+    }};
+}
+
+macro_rules! error_invalid_block {
+    ($($arg:tt)*) => {{
+        error!("InvalidBlock: {}", format!($($arg)*));
+        Error::InvalidBlock
+    }};
+}
+
+macro_rules! error_invalid_spv_proof {
+    ($($arg:tt)*) => {{
+        error!("InvalidSpvProof: {}", format!($($arg)*));
+        Error::InvalidSpvProof
+    }};
+}
+
+/// Block header and transaction inclusion proof verification
+pub mod proof;
 /// Chain tracking
 pub mod tracker;
@@ -0,0 +1,176 @@
+use bitcoin::consensus::encode::serialize;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::util::merkleblock::PartialMerkleTree;
+use bitcoin::{BlockHeader, OutPoint, Transaction};
+
+use log::error;
+
+use crate::chain::tracker::Error;
+use crate::prelude::*;
+
+/// Verify that `header` builds on `prev` and is correctly mined.
+///
+/// Difficulty transitions need the chain context, and are checked by the
+/// [crate::chain::tracker::ChainTracker].
+pub fn verify_header_chain(prev: &BlockHeader, header: &BlockHeader) -> Result<(), Error> {
+    // Check hash is correctly chained
+    if header.prev_blockhash != prev.block_hash() {
+        return Err(error_invalid_chain!(
+            "header.prev_blockhash {} != prev.block_hash {}",
+            header.prev_blockhash.to_hex(),
+            prev.block_hash().to_hex()
+        ));
+    }
+    // Ensure correctly mined (hash is under target)
+    header
+        .validate_pow(&header.target())
+        .map_err(|e| error_invalid_block!("validate pow {}: {}", header.target(), e))?;
+    Ok(())
+}
+
+/// Verify that `proof` binds exactly `txs`, in block order, to the merkle
+/// root of `header`.
+///
+/// Every transaction must be matched by the proof, and the proof must not
+/// match any other transaction, so that transactions can be neither
+/// injected nor withheld.  Without a proof, no transactions may be
+/// supplied.
+pub fn verify_txs_proof(
+    header: &BlockHeader,
+    txs: &[Transaction],
+    proof: Option<&PartialMerkleTree>,
+) -> Result<(), Error> {
+    let proof = match proof {
+        Some(proof) => proof,
+        None if txs.is_empty() => return Ok(()),
+        None => return Err(error_invalid_spv_proof!("txs not empty")),
+    };
+    let mut matches = Vec::new();
+    let mut indexes = Vec::new();
+    let root = proof
+        .extract_matches(&mut matches, &mut indexes)
+        .map_err(|e| error_invalid_spv_proof!("extract matches failed: {:?}", e))?;
+    if root != header.merkle_root {
+        return Err(error_invalid_spv_proof!(
+            "root {} != header.merkle_root {}",
+            root,
+            header.merkle_root
+        ));
+    }
+    if matches.len() != txs.len() {
+        return Err(error_invalid_spv_proof!(
+            "proof matches {} txs, but {} were supplied",
+            matches.len(),
+            txs.len()
+        ));
+    }
+    for (tx, txid) in txs.iter().zip(matches) {
+        if tx.txid() != txid {
+            return Err(error_invalid_spv_proof!("tx.txid {} != txid {}", tx.txid(), txid));
+        }
+        // A 64 byte transaction can be passed off as an inner node of the
+        // merkle tree
+        if serialize(tx).len() == 64 {
+            return Err(error_invalid_spv_proof!("tx {} is 64 bytes long", txid));
+        }
+    }
+    Ok(())
+}
+
+/// Verify that `tx` spends `outpoint` and is included in the block of
+/// `header`.  The proof must match `tx` alone.
+pub fn verify_spend_proof(
+    header: &BlockHeader,
+    tx: &Transaction,
+    outpoint: &OutPoint,
+    proof: &PartialMerkleTree,
+) -> Result<(), Error> {
+    if !tx.input.iter().any(|input| input.previous_output == *outpoint) {
+        return Err(error_invalid_spv_proof!("tx {} does not spend {}", tx.txid(), outpoint));
+    }
+    verify_txs_proof(header, core::slice::from_ref(tx), Some(proof))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::util::hash::bitcoin_merkle_root;
+    use bitcoin::{Network, TxIn, TxOut, Txid};
+
+    use crate::util::test_utils::*;
+
+    use super::*;
+
+    use test_log::test;
+
+    fn make_tx(vout: u32) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Default::default(), vout),
+                ..Default::default()
+            }],
+            output: vec![Default::default()],
+        }
+    }
+
+    #[test]
+    fn verify_txs_proof_test() {
+        let genesis = genesis_block(Network::Regtest).header;
+        let txs = vec![make_tx(0), make_tx(1), make_tx(2)];
+        let txids: Vec<Txid> = txs.iter().map(|tx| tx.txid()).collect();
+        let merkle_root = bitcoin_merkle_root(txids.iter().map(Txid::as_hash)).into();
+        let header = make_header(genesis, merkle_root);
+        verify_header_chain(&genesis, &header).unwrap();
+        assert_eq!(verify_header_chain(&header, &genesis), Err(Error::InvalidChain));
+
+        let proof = PartialMerkleTree::from_txids(&txids, &[true, false, true]);
+        let matched = vec![txs[0].clone(), txs[2].clone()];
+        verify_txs_proof(&header, &matched, Some(&proof)).unwrap();
+        verify_txs_proof(&header, &[], None).unwrap();
+        assert_eq!(verify_txs_proof(&header, &matched, None), Err(Error::InvalidSpvProof));
+
+        // a matched transaction can't be withheld, or another one injected
+        assert_eq!(
+            verify_txs_proof(&header, &matched[..1], Some(&proof)),
+            Err(Error::InvalidSpvProof)
+        );
+        let mut injected = matched.clone();
+        injected.push(make_tx(3));
+        assert_eq!(verify_txs_proof(&header, &injected, Some(&proof)), Err(Error::InvalidSpvProof));
+        let swapped = vec![txs[2].clone(), txs[0].clone()];
+        assert_eq!(verify_txs_proof(&header, &swapped, Some(&proof)), Err(Error::InvalidSpvProof));
+
+        let proof = PartialMerkleTree::from_txids(&txids, &[false, true, false]);
+        let outpoint = OutPoint::new(Default::default(), 1);
+        verify_spend_proof(&header, &txs[1], &outpoint, &proof).unwrap();
+        assert_eq!(
+            verify_spend_proof(&header, &txs[0], &outpoint, &proof),
+            Err(Error::InvalidSpvProof)
+        );
+        let other_header = make_header(genesis, Default::default());
+        assert_eq!(
+            verify_spend_proof(&other_header, &txs[1], &outpoint, &proof),
+            Err(Error::InvalidSpvProof)
+        );
+    }
+
+    #[test]
+    fn reject_64_byte_tx_test() {
+        let genesis = genesis_block(Network::Regtest).header;
+        // a 4 byte output script makes the transaction 64 bytes long
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![Default::default()],
+            output: vec![TxOut { value: 0, script_pubkey: vec![0x6a, 1, 2, 3].into() }],
+        };
+        assert_eq!(serialize(&tx).len(), 64);
+        let txids = [tx.txid()];
+        let merkle_root = bitcoin_merkle_root(txids.iter().map(Txid::as_hash)).into();
+        let header = make_header(genesis, merkle_root);
+        let proof = PartialMerkleTree::from_txids(&txids, &[true]);
+        assert_eq!(verify_txs_proof(&header, &[tx], Some(&proof)), Err(Error::InvalidSpvProof));
+    }
+}
@@ -1,13 +1,14 @@
 use alloc::collections::VecDeque;
+use core::iter;
 
-use bitcoin::blockdata::constants::DIFFCHANGE_INTERVAL;
-use bitcoin::hashes::hex::ToHex;
+use bitcoin::blockdata::constants::{genesis_block, DIFFCHANGE_INTERVAL, DIFFCHANGE_TIMESPAN};
 use bitcoin::util::merkleblock::PartialMerkleTree;
 use bitcoin::util::uint::Uint256;
-use bitcoin::{BlockHeader, Network, OutPoint, Transaction, Txid};
+use bitcoin::{BlockHash, BlockHeader, Network, OutPoint, Transaction, Txid};
 
 use log::error;
 
+use crate::chain::proof::{verify_header_chain, verify_txs_proof};
 use crate::prelude::*;
 
 /// Error
@@ -17,32 +18,21 @@ pub enum Error {
     InvalidChain,
     /// Block is invalid (e.g. block hash not under target)
     InvalidBlock,
-    /// Reorg size greater than [`ChainTracker::MAX_REORG_SIZE`]
+    /// Reorg deeper than [`ChainTracker::max_reorg_depth`], or past the
+    /// block the tracker started from
     ReorgTooDeep,
     /// The SPV (merkle) proof was incorrect
     InvalidSpvProof,
 }
 
-macro_rules! error_invalid_chain {
-    ($($arg:tt)*) => {{
-        error!("InvalidChain: {}", format!($($arg)*));
-        Error::InvalidChain
-    }};
-}
+/// The default maximum reorg depth
+pub const DEFAULT_MAX_REORG_DEPTH: u32 = 100;
 
-macro_rules! error_invalid_block {
-    ($($arg:tt)*) => {{
-        error!("InvalidBlock: {}", format!($($arg)*));
-        Error::InvalidBlock
-    }};
-}
+// The target time between blocks
+const TARGET_SPACING: u32 = 10 * 60;
 
-macro_rules! error_invalid_spv_proof {
-    ($($arg:tt)*) => {{
-        error!("InvalidSpvProof: {}", format!($($arg)*));
-        Error::InvalidSpvProof
-    }};
-}
+// The number of blocks in the median time past
+const MEDIAN_TIME_SPAN: usize = 11;
 
 /// A listener entry
 #[derive(Debug, Clone)]
@@ -55,30 +45,96 @@ pub struct ListenSlot {
     pub seen: OrderedSet<OutPoint>,
 }
 
+/// A trusted block to start tracking the chain from, so that the headers
+/// before it don't have to be followed
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    /// The height of the block
+    pub height: u32,
+    /// The block header
+    pub header: BlockHeader,
+    /// The total work of the chain up to and including the block
+    pub chainwork: Uint256,
+}
+
+impl Checkpoint {
+    /// The genesis block of the standard chain of the network
+    pub fn genesis(network: Network) -> Self {
+        let header = genesis_block(network).header;
+        Checkpoint { height: 0, header, chainwork: header.work() }
+    }
+
+    /// The hash of the block
+    pub fn block_hash(&self) -> BlockHash {
+        self.header.block_hash()
+    }
+}
+
 /// Track chain, with basic validation
 pub struct ChainTracker<L: ChainListener + Ord> {
-    /// headers past the tip
+    /// headers past the tip.  At least 10 are kept for the median time
+    /// past, even if [`ChainTracker::max_reorg_depth`] is lower.
     pub headers: VecDeque<BlockHeader>,
     /// tip header
     pub tip: BlockHeader,
     /// height
     pub height: u32,
+    /// The total work of the chain up to the tip.  If the tracker was not
+    /// created from a checkpoint, the work before the first header is taken
+    /// to be the least that a chain of that height can have.
+    pub chainwork: Uint256,
     /// The network
     pub network: Network,
     /// listeners
     pub listeners: OrderedMap<L, ListenSlot>,
+    /// The deepest reorg that is followed
+    pub max_reorg_depth: u32,
+    /// Whether a deeper reorg was refused.  No more blocks are removed
+    /// until this is cleared by the operator.
+    pub suspect: bool,
+    /// The time of the first block of the current difficulty period, if
+    /// the tracker followed it
+    pub period_start_time: Option<u32>,
 }
 
 impl<L: ChainListener + Ord> ChainTracker<L> {
-    const MAX_REORG_SIZE: usize = 100;
-
     /// Create a new tracker
     pub fn new(network: Network, height: u32, tip: BlockHeader) -> Result<Self, Error> {
         tip.validate_pow(&tip.target())
             .map_err(|e| error_invalid_block!("validate pow {}: {}", tip.target(), e))?;
         let headers = VecDeque::new();
+        let chainwork =
+            if height == 0 { tip.work() } else { min_chainwork(network, height - 1) + tip.work() };
         let listeners = OrderedMap::new();
-        Ok(ChainTracker { headers, tip, height, network, listeners })
+        let period_start_time =
+            if height % DIFFCHANGE_INTERVAL == 0 { Some(tip.time) } else { None };
+        Ok(ChainTracker {
+            headers,
+            tip,
+            height,
+            chainwork,
+            network,
+            listeners,
+            max_reorg_depth: DEFAULT_MAX_REORG_DEPTH,
+            suspect: false,
+            period_start_time,
+        })
+    }
+
+    /// Create a new tracker, starting at a trusted checkpoint
+    pub fn from_checkpoint(network: Network, checkpoint: &Checkpoint) -> Result<Self, Error> {
+        let mut tracker = Self::new(network, checkpoint.height, checkpoint.header)?;
+        let min_chainwork = min_chainwork(network, checkpoint.height);
+        if checkpoint.chainwork < min_chainwork {
+            return Err(error_invalid_chain!(
+                "checkpoint chainwork {} < minimum {} at height {}",
+                checkpoint.chainwork,
+                min_chainwork,
+                checkpoint.height
+            ));
+        }
+        tracker.chainwork = checkpoint.chainwork;
+        Ok(tracker)
     }
 
     /// Current chain tip header
@@ -96,21 +152,62 @@ impl<L: ChainListener + Ord> ChainTracker<L> {
         self.height
     }
 
-    /// Remove block at tip due to reorg
+    /// Change the deepest reorg that is followed, forgetting any headers
+    /// beyond it that are not needed for the median time past
+    pub fn set_max_reorg_depth(&mut self, depth: u32) {
+        self.max_reorg_depth = depth;
+        self.headers.truncate(self.headers_kept());
+    }
+
+    // The number of headers past the tip that are kept
+    fn headers_kept(&self) -> usize {
+        (self.max_reorg_depth as usize).max(MEDIAN_TIME_SPAN - 1)
+    }
+
+    /// Follow reorgs again after a deep reorg was refused
+    pub fn clear_suspect(&mut self) {
+        self.suspect = false;
+    }
+
+    /// Remove block at tip due to reorg.
+    ///
+    /// A reorg deeper than [`ChainTracker::max_reorg_depth`] is refused,
+    /// as is one past the block the tracker started from, such as
+    /// a checkpoint.  The tracker is then marked as suspect.
     pub fn remove_block(
         &mut self,
         txs: Vec<Transaction>,
         txs_proof: Option<PartialMerkleTree>,
     ) -> Result<BlockHeader, Error> {
-        if self.headers.is_empty() {
+        if self.suspect {
+            return Err(Error::ReorgTooDeep);
+        }
+        // The headers past max_reorg_depth are only kept for the median
+        // time past
+        if self.headers.len().min(self.max_reorg_depth as usize) == 0 {
+            error!(
+                "ReorgTooDeep: refusing to remove block {} at height {}",
+                self.tip.block_hash(),
+                self.height
+            );
+            self.suspect = true;
             return Err(Error::ReorgTooDeep);
         }
         let header = self.tip;
-        Self::validate_spv(&header, &txs, txs_proof)?;
+        verify_txs_proof(&header, &txs, txs_proof.as_ref())?;
         self.notify_listeners_remove(&txs);
 
+        if self.height % DIFFCHANGE_INTERVAL == 0 {
+            self.period_start_time = None;
+        }
         self.tip = self.headers.pop_front().expect("already checked for empty");
+        // Forget the headers that were only kept for the median time past,
+        // so that the removed block doesn't make room for a deeper reorg.
+        // The median time past is checked again once enough blocks are
+        // added.
+        self.headers.truncate(self.max_reorg_depth as usize - 1);
         self.height -= 1;
+        self.chainwork = self.chainwork - header.work();
         Ok(header)
     }
 
@@ -149,7 +246,13 @@ impl<L: ChainListener + Ord> ChainTracker<L> {
         }
     }
 
-    /// Add a block, which becomes the new tip
+    /// Add a block, which becomes the new tip.
+    ///
+    /// At a retarget, if the tracker started after the first block of the
+    /// period, the time the period took is not known, and the difficulty
+    /// is refused if it decreases.  On testnet, the difficulty is not
+    /// checked while the tracker has only seen minimum difficulty blocks
+    /// since it started.
     pub fn add_block(
         &mut self,
         header: BlockHeader,
@@ -160,10 +263,14 @@ impl<L: ChainListener + Ord> ChainTracker<L> {
 
         self.notify_listeners_add(&txs);
 
-        self.headers.truncate(Self::MAX_REORG_SIZE - 1);
         self.headers.push_front(self.tip);
+        self.headers.truncate(self.headers_kept());
         self.tip = header;
         self.height += 1;
+        self.chainwork = self.chainwork + header.work();
+        if self.height % DIFFCHANGE_INTERVAL == 0 {
+            self.period_start_time = Some(header.time);
+        }
         Ok(())
     }
 
@@ -200,6 +307,11 @@ impl<L: ChainListener + Ord> ChainTracker<L> {
         self.listeners.insert(listener, slot);
     }
 
+    /// Remove a listener, such as for a pruned channel
+    pub fn remove_listener(&mut self, listener: &L) {
+        self.listeners.remove(listener);
+    }
+
     /// Add more watches to a listener
     pub fn add_listener_watches(&mut self, listener: L, watches: OrderedSet<OutPoint>) {
         let slot = self
@@ -215,84 +327,133 @@ impl<L: ChainListener + Ord> ChainTracker<L> {
         txs: &Vec<Transaction>,
         txs_proof: Option<PartialMerkleTree>,
     ) -> Result<(), Error> {
-        // Check hash is correctly chained
-        if header.prev_blockhash != self.tip.block_hash() {
+        verify_header_chain(&self.tip, header)?;
+        let chain_max = max_target(self.network);
+        if header.target() > chain_max {
+            return Err(error_invalid_block!(
+                "target {} > chain_max {}",
+                header.target(),
+                chain_max
+            ));
+        }
+        self.validate_bits(header)?;
+        let chainwork = self.chainwork + header.work();
+        let min_chainwork = min_chainwork(self.network, self.height + 1);
+        if chainwork < min_chainwork {
             return Err(error_invalid_chain!(
-                "header.prev_blockhash {} != self.tip.block_hash {}",
-                header.prev_blockhash.to_hex(),
-                self.tip.block_hash().to_hex()
+                "chainwork {} < minimum {} at height {}",
+                chainwork,
+                min_chainwork,
+                self.height + 1
             ));
         }
-        // Ensure correctly mined (hash is under target)
-        header.validate_pow(&header.target()).map_err(|_| Error::InvalidBlock)?;
-        if self.network == Network::Testnet
-            && header.target() == max_target(self.network)
-            && header.time > self.tip.time + 60 * 20
-        {
-            // special case for Testnet - 20 minute rule
-        } else if (self.height + 1) % DIFFCHANGE_INTERVAL == 0 {
-            let prev_target = self.tip.target();
-            let target = header.target();
-            let network = self.network;
-            validate_retarget(prev_target, target, network)?;
-        } else {
-            if header.bits != self.tip.bits && self.network != Network::Testnet {
-                return Err(error_invalid_chain!(
-                    "header.bits {} != self.tip.bits {}",
-                    header.bits,
-                    self.tip.bits
+        if let Some(median_time) = self.median_time_past() {
+            if header.time <= median_time {
+                return Err(error_invalid_block!(
+                    "header.time {} <= median time past {}",
+                    header.time,
+                    median_time
                 ));
             }
         }
 
-        Self::validate_spv(header, txs, txs_proof)?;
-        Ok(())
+        verify_txs_proof(header, txs, txs_proof.as_ref())
     }
 
-    fn validate_spv(
-        header: &BlockHeader,
-        txs: &Vec<Transaction>,
-        txs_proof: Option<PartialMerkleTree>,
-    ) -> Result<(), Error> {
-        // Check SPV proof
-        if let Some(txs_proof) = txs_proof {
-            let mut matches = Vec::new();
-            let mut indexes = Vec::new();
-
-            let root = txs_proof
-                .extract_matches(&mut matches, &mut indexes)
-                .map_err(|e| error_invalid_spv_proof!("extract matches failed: {:?}", e))?;
-            if root != header.merkle_root {
-                return Err(error_invalid_spv_proof!(
-                    "root {} != header.merkle_root {}",
-                    root,
-                    header.merkle_root
-                ));
+    // The difficulty of the next block, as in GetNextWorkRequired in
+    // bitcoind.  Where the tracker doesn't have the blocks to compute it,
+    // see add_block, the bits are only partly checked.
+    fn validate_bits(&self, header: &BlockHeader) -> Result<(), Error> {
+        let network = self.network;
+        let expected_bits = if (self.height + 1) % DIFFCHANGE_INTERVAL == 0 {
+            if network == Network::Regtest {
+                // Regtest doesn't retarget
+                self.tip.bits
+            } else if let Some(start_time) = self.period_start_time {
+                next_work_required(self.tip.bits, start_time, self.tip.time, network)
+            } else {
+                // The period started before the tracker, so only the bounds
+                // of the retarget can be checked, and the difficulty may
+                // not decrease
+                return validate_retarget(self.tip.target(), header.target(), network);
             }
-            for (tx, txid) in txs.iter().zip(matches) {
-                if tx.txid() != txid {
-                    return Err(error_invalid_spv_proof!("tx.txid {} != txid {}", tx.txid(), txid));
+        } else if network == Network::Testnet || network == Network::Regtest {
+            if header.time > self.tip.time + TARGET_SPACING * 2 {
+                // The 20 minute rule allows a minimum difficulty block
+                BlockHeader::compact_target_from_u256(&max_target(network))
+            } else {
+                match self.last_non_min_difficulty_bits() {
+                    Some(bits) => bits,
+                    // Only minimum difficulty blocks since the tracker started
+                    None => return Ok(()),
                 }
             }
         } else {
-            if !txs.is_empty() {
-                return Err(error_invalid_spv_proof!("txs not empty"));
-            }
+            self.tip.bits
+        };
+        if header.bits != expected_bits {
+            return Err(error_invalid_chain!(
+                "header.bits {} != expected bits {}",
+                header.bits,
+                expected_bits
+            ));
         }
         Ok(())
     }
+
+    // The difficulty of the last block that was not a minimum difficulty
+    // block under the 20 minute rule, or the first block of the period
+    fn last_non_min_difficulty_bits(&self) -> Option<u32> {
+        let min_difficulty_bits = BlockHeader::compact_target_from_u256(&max_target(self.network));
+        let mut height = self.height;
+        for header in iter::once(&self.tip).chain(self.headers.iter()) {
+            if height % DIFFCHANGE_INTERVAL == 0 || header.bits != min_difficulty_bits {
+                return Some(header.bits);
+            }
+            height -= 1;
+        }
+        None
+    }
+
+    // The median time of the last blocks, if the tracker has all of them.
+    // The median of fewer blocks could be later than the real one, and
+    // refuse a valid block, such as right after a checkpoint.
+    fn median_time_past(&self) -> Option<u32> {
+        let mut times: Vec<u32> = iter::once(&self.tip)
+            .chain(self.headers.iter())
+            .take(MEDIAN_TIME_SPAN)
+            .map(|h| h.time)
+            .collect();
+        if times.len() < MEDIAN_TIME_SPAN {
+            return None;
+        }
+        times.sort_unstable();
+        Some(times[times.len() / 2])
+    }
 }
 
-fn validate_retarget(prev_target: Uint256, target: Uint256, network: Network) -> Result<(), Error> {
-    // TODO do actual retargeting with timestamps, requires remembering start timestamp
+// The difficulty after a retarget, as in CalculateNextWorkRequired in bitcoind
+fn next_work_required(last_bits: u32, first_time: u32, last_time: u32, network: Network) -> u32 {
+    let timespan = (last_time as i64 - first_time as i64)
+        .max(DIFFCHANGE_TIMESPAN as i64 / 4)
+        .min(DIFFCHANGE_TIMESPAN as i64 * 4) as u32;
+    let target = BlockHeader::u256_from_compact_target(last_bits) * timespan
+        / Uint256::from_u64(DIFFCHANGE_TIMESPAN as u64).unwrap();
+    let chain_max = max_target(network);
+    BlockHeader::compact_target_from_u256(if target > chain_max { &chain_max } else { &target })
+}
 
+// The bounds of a retarget, for when the start of the period is not known.
+// The target may not rise, since a chain source could otherwise make
+// a period look like it took longer than it did.
+fn validate_retarget(prev_target: Uint256, target: Uint256, network: Network) -> Result<(), Error> {
     // Round trip the target bounds, to simulate the way bitcoind checks them
     fn round_trip_target(prev_target: &Uint256) -> Uint256 {
         BlockHeader::u256_from_compact_target(BlockHeader::compact_target_from_u256(prev_target))
     }
 
     let min = round_trip_target(&(prev_target >> 2));
-    let max = round_trip_target(&(prev_target << 2));
+    let max = round_trip_target(&prev_target);
     let chain_max = max_target(network);
 
     if target.gt(&chain_max) {
@@ -317,10 +478,22 @@ pub trait ChainListener: SendSync {
     fn on_remove_block(&self, txs: Vec<&Transaction>);
 }
 
-/// The one in rust-bitcoin is incorrect for Regtest at least
+/// The least total work that a chain up to and including the block at
+/// `height` can have, since each block has at least the work of the
+/// easiest target
+pub fn min_chainwork(network: Network, height: u32) -> Uint256 {
+    let easiest = BlockHeader {
+        bits: BlockHeader::compact_target_from_u256(&max_target(network)),
+        ..genesis_block(network).header
+    };
+    easiest.work() * (height + 1)
+}
+
+/// The one in rust-bitcoin is incorrect for Regtest and Signet
 pub fn max_target(network: Network) -> Uint256 {
     match network {
         Network::Regtest => Uint256::from_u64(0x7fffff).unwrap() << (256 - 24),
+        Network::Signet => Uint256::from_u64(0x0377ae).unwrap() << 216,
         _ => Uint256::from_u64(0xFFFF).unwrap() << 208,
     }
 }
@@ -352,8 +525,12 @@ mod tests {
         // Difficulty can't change within the retarget period
         let bad_bits = header.bits - 1;
         // println!("{:x} {} {}", header.bits, BlockHeader::u256_from_compact_target(header.bits), BlockHeader::u256_from_compact_target(bad_bits));
-        let header_bad_bits =
-            mine_header_with_bits(tracker.tip.block_hash(), Default::default(), bad_bits);
+        let header_bad_bits = mine_header_with_bits(
+            tracker.tip.block_hash(),
+            Default::default(),
+            bad_bits,
+            tracker.tip.time + 1,
+        );
         assert_eq!(
             tracker.add_block(header_bad_bits, vec![], None).err(),
             Some(Error::InvalidChain)
@@ -362,6 +539,38 @@ mod tests {
         let header_removed = tracker.remove_block(vec![], None)?;
         assert_eq!(header, header_removed);
         assert_eq!(tracker.remove_block(vec![], None).err(), Some(Error::ReorgTooDeep));
+        assert!(tracker.suspect);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_reorg_depth() -> Result<(), Error> {
+        let mut tracker = make_tracker()?;
+        for _ in 0..5 {
+            let header = make_header(tracker.tip(), Default::default());
+            tracker.add_block(header, vec![], None)?;
+        }
+        tracker.set_max_reorg_depth(2);
+        // The headers for the median time past are kept
+        assert_eq!(tracker.headers().len(), 5);
+        let header = make_header(tracker.tip(), Default::default());
+        tracker.add_block(header, vec![], None)?;
+        assert_eq!(tracker.headers().len(), 6);
+
+        tracker.remove_block(vec![], None)?;
+        tracker.remove_block(vec![], None)?;
+        assert_eq!(tracker.height(), 4);
+        assert_eq!(tracker.remove_block(vec![], None).err(), Some(Error::ReorgTooDeep));
+        assert!(tracker.suspect);
+        assert_eq!(tracker.height(), 4);
+
+        // The chain source can't unwind any further while suspect, even
+        // after it added more blocks
+        let header = make_header(tracker.tip(), Default::default());
+        tracker.add_block(header, vec![], None)?;
+        assert_eq!(tracker.remove_block(vec![], None).err(), Some(Error::ReorgTooDeep));
+        tracker.clear_suspect();
+        assert_eq!(tracker.remove_block(vec![], None)?, header);
         Ok(())
     }
 
@@ -552,21 +761,130 @@ mod tests {
             tracker.add_block(header, vec![], None)?;
         }
         assert_eq!(tracker.height, DIFFCHANGE_INTERVAL - 1);
+        let genesis_time = genesis_block(Network::Regtest).header.time;
+        assert_eq!(tracker.period_start_time, Some(genesis_time));
         let target = tracker.tip().target();
+        let time = tracker.tip().time + 1;
 
         // Decrease difficulty by 2 fails because of chain max
         let bits = BlockHeader::compact_target_from_u256(&(target << 1));
-        let header = mine_header_with_bits(tracker.tip().block_hash(), Default::default(), bits);
+        let header =
+            mine_header_with_bits(tracker.tip().block_hash(), Default::default(), bits, time);
         assert_eq!(tracker.add_block(header, vec![], None).err(), Some(Error::InvalidBlock));
 
-        // Increase difficulty by 8 fails because of max retarget
+        // Increase difficulty by 8 fails
         let bits = BlockHeader::compact_target_from_u256(&(target >> 3));
-        let header = mine_header_with_bits(tracker.tip().block_hash(), Default::default(), bits);
+        let header =
+            mine_header_with_bits(tracker.tip().block_hash(), Default::default(), bits, time);
         assert_eq!(tracker.add_block(header, vec![], None).err(), Some(Error::InvalidChain));
 
-        // Increase difficulty by 2
+        // Regtest doesn't retarget
         let bits = BlockHeader::compact_target_from_u256(&(target >> 1));
-        let header = mine_header_with_bits(tracker.tip().block_hash(), Default::default(), bits);
+        let header =
+            mine_header_with_bits(tracker.tip().block_hash(), Default::default(), bits, time);
+        assert_eq!(tracker.add_block(header, vec![], None).err(), Some(Error::InvalidChain));
+
+        let header = make_header(tracker.tip(), Default::default());
+        tracker.add_block(header, vec![], None)?;
+        assert_eq!(tracker.period_start_time, Some(header.time));
+        tracker.remove_block(vec![], None)?;
+        assert_eq!(tracker.period_start_time, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_work_required() {
+        // The vectors of pow_tests.cpp in bitcoind
+        let network = Network::Bitcoin;
+        assert_eq!(next_work_required(0x1d00ffff, 1261130161, 1262152739, network), 0x1d00d86a);
+        // Capped at the chain max
+        assert_eq!(next_work_required(0x1d00ffff, 1231006505, 1233061996, network), 0x1d00ffff);
+        // At most four times harder
+        assert_eq!(next_work_required(0x1c05a3f4, 1279008237, 1279297671, network), 0x1c0168fd);
+        // At most four times easier
+        assert_eq!(next_work_required(0x1c387f6f, 1263163443, 1269211443, network), 0x1d00e1fd);
+    }
+
+    #[test]
+    fn test_min_difficulty_bits() -> Result<(), Error> {
+        let mut tracker = make_tracker()?;
+        tracker.network = Network::Testnet;
+        let min_bits = 0x1d00ffff;
+        let tip = tracker.tip();
+        let make = |bits| BlockHeader { bits, ..tip };
+
+        // The last block that was not at minimum difficulty
+        tracker.height = DIFFCHANGE_INTERVAL * 2 + 5;
+        tracker.headers = vec![make(min_bits), make(0x1c0ffff0)].into_iter().collect();
+        tracker.tip = make(min_bits);
+        assert_eq!(tracker.last_non_min_difficulty_bits(), Some(0x1c0ffff0));
+
+        // The first block of the period
+        tracker.height = DIFFCHANGE_INTERVAL + 1;
+        assert_eq!(tracker.last_non_min_difficulty_bits(), Some(min_bits));
+
+        // Not known
+        tracker.height = DIFFCHANGE_INTERVAL * 2 + 100;
+        tracker.headers.clear();
+        assert_eq!(tracker.last_non_min_difficulty_bits(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_median_time_past() -> Result<(), Error> {
+        let mut tracker = make_tracker()?;
+        tracker.set_max_reorg_depth(2);
+        for _ in 0..MEDIAN_TIME_SPAN {
+            assert_eq!(tracker.median_time_past(), None);
+            let header = make_header(tracker.tip(), Default::default());
+            tracker.add_block(header, vec![], None)?;
+        }
+        // The median of the last 11 blocks is the 6th last
+        let median_time = tracker.headers()[4].time;
+        assert_eq!(tracker.median_time_past(), Some(median_time));
+
+        let bits = tracker.tip().bits;
+        let header = mine_header_with_bits(
+            tracker.tip().block_hash(),
+            Default::default(),
+            bits,
+            median_time,
+        );
+        assert_eq!(tracker.add_block(header, vec![], None).err(), Some(Error::InvalidBlock));
+        let header = mine_header_with_bits(
+            tracker.tip().block_hash(),
+            Default::default(),
+            bits,
+            median_time + 1,
+        );
+        tracker.add_block(header, vec![], None)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_median_time_past_after_checkpoint() -> Result<(), Error> {
+        let mut tracker = make_tracker()?;
+        for _ in 0..MEDIAN_TIME_SPAN {
+            let header = make_header(tracker.tip(), Default::default());
+            tracker.add_block(header, vec![], None)?;
+        }
+        let checkpoint = Checkpoint {
+            height: tracker.height(),
+            header: tracker.tip(),
+            chainwork: tracker.chainwork,
+        };
+        let mut restarted: ChainTracker<MockListener> =
+            ChainTracker::from_checkpoint(Network::Regtest, &checkpoint)?;
+
+        // A block earlier than the tip, but later than the median time past
+        // of the chain, is valid even though the restarted tracker only
+        // knows the tip
+        let time = tracker.tip().time - 1;
+        assert!(time > tracker.median_time_past().unwrap());
+        let bits = tracker.tip().bits;
+        let header =
+            mine_header_with_bits(tracker.tip().block_hash(), Default::default(), bits, time);
+        restarted.add_block(header, vec![], None)?;
         tracker.add_block(header, vec![], None)?;
         Ok(())
     }
@@ -581,6 +899,75 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_retarget_unknown_start() -> Result<(), Error> {
+        let prev_target = BlockHeader::u256_from_compact_target(0x1c063051);
+        let network = Network::Testnet;
+        validate_retarget(prev_target, prev_target, network)?;
+        validate_retarget(prev_target, prev_target >> 2, network)?;
+        assert_eq!(
+            validate_retarget(prev_target, prev_target >> 3, network).err(),
+            Some(Error::InvalidChain)
+        );
+        // The difficulty may not decrease
+        assert_eq!(
+            validate_retarget(prev_target, prev_target << 1, network).err(),
+            Some(Error::InvalidChain)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_chainwork() -> Result<(), Error> {
+        let genesis = genesis_block(Network::Regtest).header;
+        assert_eq!(min_chainwork(Network::Regtest, 0), genesis.work());
+        let mut tracker = make_tracker()?;
+        let header = make_header(tracker.tip(), Default::default());
+        let restarted: ChainTracker<MockListener> = ChainTracker::new(Network::Regtest, 1, header)?;
+        assert_eq!(restarted.chainwork, min_chainwork(Network::Regtest, 1));
+
+        // A chain with less work than the minimum for its height is refused
+        tracker.chainwork = Uint256::from_u64(0).unwrap();
+        assert_eq!(tracker.add_block(header, vec![], None).err(), Some(Error::InvalidChain));
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint() -> Result<(), Error> {
+        let mut tracker = make_tracker()?;
+        assert_eq!(tracker.chainwork, Checkpoint::genesis(Network::Regtest).chainwork);
+        for _ in 0..3 {
+            let header = make_header(tracker.tip(), Default::default());
+            tracker.add_block(header, vec![], None)?;
+        }
+        let checkpoint = Checkpoint {
+            height: tracker.height(),
+            header: tracker.tip(),
+            chainwork: tracker.chainwork,
+        };
+        let mut restarted: ChainTracker<MockListener> =
+            ChainTracker::from_checkpoint(Network::Regtest, &checkpoint)?;
+        assert_eq!(restarted.height(), 3);
+        assert_eq!(restarted.tip().block_hash(), checkpoint.block_hash());
+
+        // Both trackers follow the same chain from the checkpoint on
+        let header = make_header(tracker.tip(), Default::default());
+        tracker.add_block(header, vec![], None)?;
+        restarted.add_block(header, vec![], None)?;
+        assert_eq!(restarted.height(), 4);
+        assert_eq!(restarted.chainwork, tracker.chainwork);
+        restarted.remove_block(vec![], None)?;
+        assert_eq!(restarted.chainwork, checkpoint.chainwork);
+
+        // The chainwork must at least cover the checkpoint block
+        let bad_checkpoint = Checkpoint { chainwork: Uint256::from_u64(0).unwrap(), ..checkpoint };
+        assert_eq!(
+            ChainTracker::<MockListener>::from_checkpoint(Network::Regtest, &bad_checkpoint).err(),
+            Some(Error::InvalidChain)
+        );
+        Ok(())
+    }
+
     fn make_tracker() -> Result<ChainTracker<MockListener>, Error> {
         let genesis = genesis_block(Network::Regtest);
         let tracker = ChainTracker::new(Network::Regtest, 0, genesis.header)?;
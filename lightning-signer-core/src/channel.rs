@@ -2,42 +2,62 @@ use core::any::Any;
 use core::fmt;
 use core::fmt::{Debug, Error, Formatter};
 
+use bitcoin::blockdata::script::Builder;
 use bitcoin::hashes::hex;
 use bitcoin::hashes::sha256::Hash as Sha256Hash;
 use bitcoin::hashes::sha256d::Hash as Sha256dHash;
 use bitcoin::hashes::Hash;
 use bitcoin::secp256k1::{self, All, Message, PublicKey, Secp256k1, SecretKey, Signature};
 use bitcoin::util::bip143::SigHashCache;
-use bitcoin::{Network, OutPoint, Script, SigHashType, Transaction};
+use bitcoin::{Network, OutPoint, Script, SigHashType, Transaction, TxIn, TxOut, Txid};
 use lightning::chain;
 use lightning::chain::keysinterface::{BaseSign, InMemorySigner, KeysInterface};
 use lightning::ln::chan_utils::{
-    build_htlc_transaction, derive_private_key, get_htlc_redeemscript, make_funding_redeemscript,
-    ChannelPublicKeys, ChannelTransactionParameters, ClosingTransaction, CommitmentTransaction,
-    CounterpartyChannelTransactionParameters, HTLCOutputInCommitment, HolderCommitmentTransaction,
-    TxCreationKeys,
+    build_htlc_transaction, derive_private_key, get_anchor_redeemscript, get_htlc_redeemscript,
+    make_funding_redeemscript, ChannelPublicKeys, ChannelTransactionParameters, ClosingTransaction,
+    CommitmentTransaction, CounterpartyChannelTransactionParameters, HTLCOutputInCommitment,
+    HolderCommitmentTransaction, TxCreationKeys,
 };
 use lightning::ln::{chan_utils, PaymentHash, PaymentPreimage};
 #[allow(unused_imports)]
 use log::{debug, trace, warn};
 
+use crate::chain::tracker::ChainTracker;
 use crate::monitor::ChainMonitor;
-use crate::node::Node;
-use crate::policy::error::policy_error;
-use crate::policy::validator::{ChainState, EnforcementState, Validator};
+use crate::node::{Node, NodeEvent};
+use crate::persist::model::AuditRecord;
+use crate::persist::PersistBatch;
+use crate::policy::error::{policy_error, PolicyTag};
+use crate::policy::validator::{
+    ChainState, CoreTypes, EnforcementState, SigningIntent, SpliceState, Validator,
+};
 use crate::prelude::*;
+use crate::tx::interactive::{InteractiveInput, InteractiveOutput};
+#[cfg(feature = "simple_close")]
+use crate::tx::simple_close::build_simple_close_tx;
 use crate::tx::tx::{
-    build_commitment_tx, get_commitment_transaction_number_obscure_factor, CommitmentInfo2,
-    HTLCInfo2,
+    build_commitment_tx, get_commitment_transaction_number_obscure_factor,
+    recompose_htlc_redeemscript, CommitmentInfo2, HTLCInfo2, JusticeOutput, JusticeOutputKind,
+    ANCHOR_SAT,
 };
 use crate::util::crypto_utils::{
-    derive_private_revocation_key, derive_public_key, derive_revocation_pubkey,
+    derive_private_revocation_key, derive_public_key, derive_revocation_pubkey, scrub_secret_key,
+    sign_ecdsa_verified, signature_to_bitcoin_vec,
 };
 use crate::util::debug_utils::{DebugHTLCOutputInCommitment, DebugInMemorySigner, DebugVecVecU8};
-use crate::util::status::{internal_error, invalid_argument, Status};
+use crate::util::ldk_monitor::{LdkMonitorSummary, MonitorDivergence};
+use crate::util::musig2::{
+    nonce_agg, nonce_gen, partial_sign, tagged_hash, KeyAggContext, PartialSig, PubNonce, SecNonce,
+};
+use crate::util::status::{
+    failed_precondition, internal_error, invalid_argument, persist_error, Status,
+};
+use crate::util::transaction_utils::{taproot_key_spend_sighash, MIN_DUST_LIMIT_SATOSHIS};
 use crate::util::INITIAL_COMMITMENT_NUMBER;
 use crate::wallet::Wallet;
+use crate::watchtower::WatchtowerBlob;
 use crate::{Arc, Weak};
+use zeroize::Zeroize;
 
 /// Channel identifier
 ///
@@ -93,6 +113,13 @@ pub enum CommitmentType {
     StaticRemoteKey,
     /// Anchors
     Anchors,
+    /// Anchors, with zero-fee second level HTLC transactions
+    /// (option_anchors_zero_fee_htlc_tx)
+    AnchorsZeroFeeHtlc,
+    /// Taproot funding output with a MuSig2 aggregate key, and zero-fee
+    /// HTLC anchors (option_taproot).  Only the funding spend is supported
+    /// so far.
+    Taproot,
 }
 
 /// The negotiated parameters for the [Channel]
@@ -148,6 +175,30 @@ impl ChannelSetup {
     /// True if this channel uses anchors.
     pub fn option_anchor_outputs(&self) -> bool {
         self.commitment_type == CommitmentType::Anchors
+            || self.commitment_type == CommitmentType::AnchorsZeroFeeHtlc
+            || self.commitment_type == CommitmentType::Taproot
+    }
+
+    /// True if the second level HTLC transactions pay no fee, so that
+    /// fees are added when they are broadcast.
+    pub fn option_anchors_zero_fee_htlc(&self) -> bool {
+        self.commitment_type == CommitmentType::AnchorsZeroFeeHtlc
+            || self.commitment_type == CommitmentType::Taproot
+    }
+
+    /// True if the funding output is a taproot output, spent with MuSig2
+    pub fn option_taproot(&self) -> bool {
+        self.commitment_type == CommitmentType::Taproot
+    }
+
+    /// The feerate of the second level HTLC transactions of a commitment
+    /// transaction with the given feerate
+    pub fn htlc_feerate_per_kw(&self, feerate_per_kw: u32) -> u32 {
+        if self.option_anchors_zero_fee_htlc() {
+            0
+        } else {
+            feerate_per_kw
+        }
     }
 }
 
@@ -157,16 +208,23 @@ pub trait ChannelBase: Any {
     fn get_channel_basepoints(&self) -> ChannelPublicKeys;
     /// Get the per-commitment point for a holder commitment transaction
     fn get_per_commitment_point(&self, commitment_number: u64) -> Result<PublicKey, Status>;
-    /// Get the per-commitment secret for a holder commitment transaction
-    // TODO leaking secret
-    fn get_per_commitment_secret(&self, commitment_number: u64) -> Result<SecretKey, Status>;
+    /// Get the per-commitment secret for a holder commitment transaction.
+    /// Use [ChannelBase::get_revocation] to build the message revealing it
+    /// to the counterparty.  The commitment is recorded as revoked, so
+    /// that it can't be signed for broadcast afterwards.
+    #[cfg(feature = "secret_access")]
+    fn get_per_commitment_secret(&mut self, commitment_number: u64) -> Result<SecretKey, Status>;
+    /// Get the revocation of a holder commitment transaction, for the
+    /// `revoke_and_ack` message to the counterparty.  The commitment is
+    /// recorded as revoked before the secret is released.
+    fn get_revocation(&mut self, commitment_number: u64) -> Result<Revocation, Status>;
     /// Check a future secret to support `option_data_loss_protect`
     fn check_future_secret(&self, commit_num: u64, suggested: &SecretKey) -> Result<bool, Status>;
     /// Get the channel nonce, used to derive the channel keys
     // TODO should this be exposed?
     fn nonce(&self) -> Vec<u8>;
     /// Returns the validator for this channel
-    fn validator(&self) -> Arc<dyn Validator>;
+    fn validator(&self) -> Arc<dyn Validator<CoreTypes>>;
 
     // TODO remove when LDK workaround is removed in LoopbackSigner
     #[allow(missing_docs)]
@@ -176,15 +234,102 @@ pub trait ChannelBase: Any {
     }
 }
 
-/// A channel can be in two states - before [Node::ready_channel] it's a
-/// [ChannelStub], afterwards it's a [Channel].  This enum keeps track
-/// of the two different states.
+/// The contents of a `revoke_and_ack` message, revoking a holder
+/// commitment transaction
+pub struct Revocation {
+    /// The per-commitment secret of the revoked commitment, revealed to the
+    /// counterparty
+    pub per_commitment_secret: SecretKey,
+    /// The per-commitment point of the commitment after the next one
+    pub next_per_commitment_point: PublicKey,
+}
+
+/// The number of confirmations of the closing transaction, beyond the
+/// delay on our outputs, after which a channel is considered closed
+pub const CLOSED_DEPTH: u32 = 100;
+
+/// The balances of a commitment transaction, from our point of view
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommitmentBalance {
+    /// Our output value
+    pub to_holder_sat: u64,
+    /// The counterparty's output value
+    pub to_counterparty_sat: u64,
+    /// The value of the HTLCs we offered
+    pub offered_htlc_sat: u64,
+    /// The value of the HTLCs the counterparty offered
+    pub received_htlc_sat: u64,
+}
+
+impl CommitmentBalance {
+    fn new(info: &CommitmentInfo2) -> Self {
+        let (to_holder_sat, to_counterparty_sat) = info.value_to_parties();
+        // The offered HTLCs of the commitment are offered by its broadcaster
+        let (offered, received) = if info.is_counterparty_broadcaster {
+            (&info.received_htlcs, &info.offered_htlcs)
+        } else {
+            (&info.offered_htlcs, &info.received_htlcs)
+        };
+        CommitmentBalance {
+            to_holder_sat,
+            to_counterparty_sat,
+            offered_htlc_sat: offered.iter().map(|h| h.value_sat).sum(),
+            received_htlc_sat: received.iter().map(|h| h.value_sat).sum(),
+        }
+    }
+}
+
+/// The signer-side state of a ready, closing or closed channel, for
+/// inspection by operators
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelStatus {
+    /// The channel
+    pub channel_id: ChannelId,
+    /// The channel value
+    pub channel_value_sat: u64,
+    /// The funding outpoint
+    pub funding_outpoint: OutPoint,
+    /// The confirmations of the funding transaction, zero if unconfirmed
+    pub funding_depth: u32,
+    /// The confirmations of a double-spend of the funding inputs, zero if
+    /// there is none
+    pub funding_double_spent_depth: u32,
+    /// The confirmations of the closing transaction, zero if unconfirmed
+    pub closing_depth: u32,
+    /// The next holder commitment number
+    pub next_holder_commit_num: u64,
+    /// Holder commitments below this are revoked
+    pub next_holder_revoke_num: u64,
+    /// The next counterparty commitment number
+    pub next_counterparty_commit_num: u64,
+    /// Counterparty commitments below this are revoked
+    pub next_counterparty_revoke_num: u64,
+    /// The balances of the current holder commitment, if any
+    pub holder_commitment: Option<CommitmentBalance>,
+    /// The balances of the current counterparty commitment, if any
+    pub counterparty_commitment: Option<CommitmentBalance>,
+    /// Whether a mutual close was signed
+    pub mutual_close_signed: bool,
+}
+
+/// A channel can be in four states - before [Node::ready_channel] it's a
+/// [ChannelStub], afterwards it's a [Channel].  Once a commitment
+/// transaction is confirmed on-chain, the channel is closing and only
+/// sweeps can be signed.  Once the closing transaction is deeply
+/// confirmed, the channel is closed and can be pruned.
+/// This enum keeps track of the different states.
 #[derive(Debug)]
 pub enum ChannelSlot {
     /// Initial state, not ready
     Stub(ChannelStub),
     /// Ready after negotiation is complete
     Ready(Channel),
+    /// A commitment transaction was confirmed, no further commitments
+    /// may be signed
+    Closing(Channel),
+    /// Terminal state, the closing and sweep outputs are deeply confirmed.
+    /// Nothing may be signed.
+    Closed(Channel),
 }
 
 impl ChannelSlot {
@@ -192,7 +337,8 @@ impl ChannelSlot {
     pub fn nonce(&self) -> Vec<u8> {
         match self {
             ChannelSlot::Stub(stub) => stub.nonce(),
-            ChannelSlot::Ready(chan) => chan.nonce(),
+            ChannelSlot::Ready(chan) | ChannelSlot::Closing(chan) | ChannelSlot::Closed(chan) =>
+                chan.nonce(),
         }
     }
 
@@ -200,7 +346,8 @@ impl ChannelSlot {
     pub fn id(&self) -> ChannelId {
         match self {
             ChannelSlot::Stub(stub) => stub.id0,
-            ChannelSlot::Ready(chan) => chan.id0,
+            ChannelSlot::Ready(chan) | ChannelSlot::Closing(chan) | ChannelSlot::Closed(chan) =>
+                chan.id0,
         }
     }
 
@@ -208,7 +355,8 @@ impl ChannelSlot {
     pub fn get_channel_basepoints(&self) -> ChannelPublicKeys {
         match self {
             ChannelSlot::Stub(stub) => stub.get_channel_basepoints(),
-            ChannelSlot::Ready(chan) => chan.get_channel_basepoints(),
+            ChannelSlot::Ready(chan) | ChannelSlot::Closing(chan) | ChannelSlot::Closed(chan) =>
+                chan.get_channel_basepoints(),
         }
     }
 }
@@ -257,7 +405,13 @@ impl ChannelBase for ChannelStub {
         ))
     }
 
-    fn get_per_commitment_secret(&self, _commitment_number: u64) -> Result<SecretKey, Status> {
+    #[cfg(feature = "secret_access")]
+    fn get_per_commitment_secret(&mut self, _commitment_number: u64) -> Result<SecretKey, Status> {
+        // We can't release a commitment_secret from a ChannelStub ever.
+        Err(policy_error(format!("channel stub cannot release commitment secret")).into())
+    }
+
+    fn get_revocation(&mut self, _commitment_number: u64) -> Result<Revocation, Status> {
         // We can't release a commitment_secret from a ChannelStub ever.
         Err(policy_error(format!("channel stub cannot release commitment secret")).into())
     }
@@ -276,7 +430,7 @@ impl ChannelBase for ChannelStub {
         self.nonce.clone()
     }
 
-    fn validator(&self) -> Arc<dyn Validator> {
+    fn validator(&self) -> Arc<dyn Validator<CoreTypes>> {
         let node = self.node.upgrade().unwrap();
         let v = node.validator_factory.lock().unwrap().make_validator(
             node.network(),
@@ -287,6 +441,23 @@ impl ChannelBase for ChannelStub {
     }
 }
 
+impl Drop for ChannelStub {
+    fn drop(&mut self) {
+        scrub_channel_keys(&mut self.keys);
+    }
+}
+
+// Overwrite the channel secrets, so that they don't linger in freed memory
+// after the channel is dropped
+fn scrub_channel_keys(keys: &mut InMemorySigner) {
+    scrub_secret_key(&mut keys.funding_key);
+    scrub_secret_key(&mut keys.revocation_base_key);
+    scrub_secret_key(&mut keys.payment_key);
+    scrub_secret_key(&mut keys.delayed_payment_base_key);
+    scrub_secret_key(&mut keys.htlc_base_key);
+    keys.commitment_seed.zeroize();
+}
+
 impl ChannelStub {
     pub(crate) fn channel_keys_with_channel_value(&self, channel_value_sat: u64) -> InMemorySigner {
         let secp_ctx = Secp256k1::signing_only();
@@ -365,20 +536,16 @@ impl ChannelBase for Channel {
         ))
     }
 
-    fn get_per_commitment_secret(&self, commitment_number: u64) -> Result<SecretKey, Status> {
-        let next_holder_commit_num = self.enforcement_state.next_holder_commit_num;
-        // policy-revoke-new-commitment-signed
-        if commitment_number + 2 > next_holder_commit_num {
-            return Err(policy_error(format!(
-                "get_per_commitment_secret: \
-                 commitment_number {} invalid when next_holder_commit_num is {}",
-                commitment_number, next_holder_commit_num,
-            ))
-            .into());
-        }
-        let secret =
-            self.keys.release_commitment_secret(INITIAL_COMMITMENT_NUMBER - commitment_number);
-        Ok(SecretKey::from_slice(&secret).unwrap())
+    #[cfg(feature = "secret_access")]
+    fn get_per_commitment_secret(&mut self, commitment_number: u64) -> Result<SecretKey, Status> {
+        self.revoke_holder_commitment(commitment_number)
+    }
+
+    fn get_revocation(&mut self, commitment_number: u64) -> Result<Revocation, Status> {
+        Ok(Revocation {
+            per_commitment_secret: self.revoke_holder_commitment(commitment_number)?,
+            next_per_commitment_point: self.get_per_commitment_point(commitment_number + 2)?,
+        })
     }
 
     fn check_future_secret(
@@ -395,7 +562,7 @@ impl ChannelBase for Channel {
         self.nonce.clone()
     }
 
-    fn validator(&self) -> Arc<dyn Validator> {
+    fn validator(&self) -> Arc<dyn Validator<CoreTypes>> {
         let node = self.get_node();
         let v = node.validator_factory.lock().unwrap().make_validator(
             self.network(),
@@ -406,6 +573,12 @@ impl ChannelBase for Channel {
     }
 }
 
+impl Drop for Channel {
+    fn drop(&mut self) {
+        scrub_channel_keys(&mut self.keys);
+    }
+}
+
 impl Channel {
     /// The channel ID
     pub fn id(&self) -> ChannelId {
@@ -428,8 +601,50 @@ impl Channel {
         self.enforcement_state.set_next_counterparty_revoke_num_for_testing(num);
     }
 
+    /// Set the commitment numbers of a channel imported from another
+    /// signer, such as a CLN node.
+    ///
+    /// The channel must be quiescent, with only the current commitments
+    /// unrevoked.  The details of the current commitments are not known,
+    /// so the balance policies start applying at the next commitments.
+    pub fn import_commitment_state(
+        &mut self,
+        next_holder_commit_num: u64,
+        next_counterparty_commit_num: u64,
+        current_counterparty_point: Option<PublicKey>,
+    ) -> Result<(), Status> {
+        let estate = &mut self.enforcement_state;
+        if estate.next_holder_commit_num != 0 || estate.next_counterparty_commit_num != 0 {
+            return Err(failed_precondition("channel already has commitments"));
+        }
+        if next_counterparty_commit_num > 0 && current_counterparty_point.is_none() {
+            return Err(invalid_argument("missing current counterparty point"));
+        }
+        estate.next_holder_commit_num = next_holder_commit_num;
+        estate.next_holder_revoke_num = next_holder_commit_num.saturating_sub(1);
+        estate.next_counterparty_commit_num = next_counterparty_commit_num;
+        estate.next_counterparty_revoke_num = next_counterparty_commit_num.saturating_sub(1);
+        estate.current_counterparty_point = current_counterparty_point;
+        self.persist()
+    }
+
     fn get_chain_state(&self) -> ChainState {
-        self.monitor.as_chain_state()
+        let mut cstate = self.monitor.as_chain_state();
+        // Don't lock the node state here, callers may be holding it
+        let height = cstate.current_height;
+        cstate.clock_skew_secs =
+            self.node.upgrade().and_then(|node| node.fresh_clock_skew_secs(height));
+        cstate
+    }
+
+    // Record the height of a counterparty commitment, for the review policy
+    fn note_activity(&mut self) {
+        let height = self.monitor.as_chain_state().current_height;
+        let estate = &mut self.enforcement_state;
+        estate.last_activity_height = height;
+        if estate.review_height == 0 {
+            estate.review_height = height;
+        }
     }
 }
 
@@ -539,7 +754,6 @@ impl Channel {
 
     /// Sign a counterparty commitment transaction after rebuilding it
     /// from the supplied arguments.
-    // TODO anchors support once LDK supports it
     pub fn sign_counterparty_commitment_tx_phase2(
         &mut self,
         remote_per_commitment_point: &PublicKey,
@@ -550,9 +764,23 @@ impl Channel {
         offered_htlcs: Vec<HTLCInfo2>,
         received_htlcs: Vec<HTLCInfo2>,
     ) -> Result<(Signature, Vec<Signature>), Status> {
+        if self.setup.option_taproot() {
+            return Err(invalid_argument("taproot channels are signed with MuSig2"));
+        }
+
+        let audit = AuditRecord::new(
+            Some(self.id0),
+            "sign_counterparty_commitment_tx",
+            Some(commitment_number),
+            vec![to_holder_value_sat, to_counterparty_value_sat],
+            vec![],
+        );
+
         // Since we didn't have the value at the real open, validate it now.
         let validator = self.validator();
-        validator.validate_channel_value(&self.setup)?;
+        validator
+            .validate_channel_value(&self.setup)
+            .map_err(|ve| self.get_node().audit_failure(&audit, ve))?;
 
         let info2 = self.build_counterparty_commitment_info(
             remote_per_commitment_point,
@@ -571,14 +799,16 @@ impl Channel {
         let incoming_payment_summary =
             self.enforcement_state.incoming_payments_summary(None, Some(&info2));
 
-        validator.validate_counterparty_commitment_tx(
-            &self.enforcement_state,
-            commitment_number,
-            &remote_per_commitment_point,
-            &self.setup,
-            &self.get_chain_state(),
-            &info2,
-        )?;
+        validator
+            .validate_counterparty_commitment_tx(
+                &self.enforcement_state,
+                commitment_number,
+                &remote_per_commitment_point,
+                &self.setup,
+                &self.get_chain_state(),
+                &info2,
+            )
+            .map_err(|ve| node.audit_failure(&audit, ve))?;
 
         let htlcs = Self::htlcs_info2_to_oic(offered_htlcs, received_htlcs);
 
@@ -591,37 +821,48 @@ impl Channel {
             htlcs,
         );
 
-        let (sig, htlc_sigs) = self
-            .keys
-            .sign_counterparty_commitment(&commitment_tx, Vec::new(), &self.secp_ctx)
-            .map_err(|_| internal_error("failed to sign"))?;
-
+        let cstate = self.get_chain_state();
         let outgoing_payment_summary = self.enforcement_state.payments_summary(None, Some(&info2));
-        state.validate_payments(
-            &self.id0,
-            &incoming_payment_summary,
-            &outgoing_payment_summary,
-            &delta,
-            validator.clone(),
-        )?;
+        state
+            .validate_payments(
+                &self.id0,
+                &incoming_payment_summary,
+                &outgoing_payment_summary,
+                &delta,
+                &cstate,
+                validator.clone(),
+            )
+            .map_err(|ve| node.audit_failure(&audit, ve))?;
+
+        self.begin_signing("sign_counterparty_commitment_tx", commitment_number)?;
+        let (sig, htlc_sigs) =
+            self.sign_counterparty_commitment(&commitment_tx).map_err(|s| self.abort_signing(s))?;
 
         // Only advance the state if nothing goes wrong.
-        self.enforcement_state.set_next_counterparty_commit_num(
-            commitment_number + 1,
-            remote_per_commitment_point.clone(),
-            info2,
-        )?;
+        self.enforcement_state
+            .set_next_counterparty_commit_num(
+                commitment_number + 1,
+                remote_per_commitment_point.clone(),
+                info2,
+            )
+            .map_err(|ve| self.abort_signing(ve.into()))?;
+        self.note_activity();
+        self.enforcement_state.signing_intent = None;
 
         state.apply_payments(
             &self.id0,
             &incoming_payment_summary,
             &outgoing_payment_summary,
             &delta,
+            &cstate,
             validator,
         );
+        node.persist_ledger(&mut state)?;
 
         trace_enforcement_state!(&self.enforcement_state);
+        self.update_monitor_commitments();
         self.persist()?;
+        node.append_audit_record(audit)?;
         Ok((sig, htlc_sigs))
     }
 
@@ -735,7 +976,7 @@ impl Channel {
 
             let recomposed_htlc_tx = build_htlc_transaction(
                 &commitment_txid,
-                feerate_per_kw,
+                self.setup.htlc_feerate_per_kw(feerate_per_kw),
                 to_self_delay,
                 htlc,
                 self.setup.option_anchor_outputs(),
@@ -762,6 +1003,46 @@ impl Channel {
         Ok(())
     }
 
+    // The per-commitment secret of a revoked holder commitment
+    pub(crate) fn per_commitment_secret(
+        &self,
+        commitment_number: u64,
+    ) -> Result<SecretKey, Status> {
+        let next_holder_commit_num = self.enforcement_state.next_holder_commit_num;
+        // policy-revoke-new-commitment-signed
+        if commitment_number + 2 > next_holder_commit_num {
+            return Err(policy_error(format!(
+                "get_per_commitment_secret: \
+                 commitment_number {} invalid when next_holder_commit_num is {}",
+                commitment_number, next_holder_commit_num,
+            ))
+            .with_policy(
+                PolicyTag::RevocationOrder,
+                vec![
+                    ("commitment_number".to_string(), commitment_number.to_string()),
+                    ("next_holder_commit_num".to_string(), next_holder_commit_num.to_string()),
+                ],
+                Some(self.id().to_string()),
+            )
+            .into());
+        }
+        let secret =
+            self.keys.release_commitment_secret(INITIAL_COMMITMENT_NUMBER - commitment_number);
+        Ok(SecretKey::from_slice(&secret).unwrap())
+    }
+
+    // The per-commitment secret of a holder commitment, which is recorded
+    // and persisted as revoked before the secret is returned
+    fn revoke_holder_commitment(&mut self, commitment_number: u64) -> Result<SecretKey, Status> {
+        let secret = self.per_commitment_secret(commitment_number)?;
+        // An older secret may be released again
+        if commitment_number >= self.enforcement_state.next_holder_revoke_num {
+            self.enforcement_state.set_next_holder_revoke_num(commitment_number + 1)?;
+            self.persist()?;
+        }
+        Ok(secret)
+    }
+
     fn advance_holder_commitment_state(
         &mut self,
         commitment_number: u64,
@@ -775,7 +1056,9 @@ impl Channel {
         let next_holder_commitment_point =
             self.get_per_commitment_point(commitment_number + 1).unwrap();
         let maybe_old_secret = if commitment_number >= 1 {
-            Some(self.get_per_commitment_secret(commitment_number - 1).unwrap())
+            // Record the revocation before releasing the secret
+            self.enforcement_state.set_next_holder_revoke_num(commitment_number)?;
+            Some(self.per_commitment_secret(commitment_number - 1).unwrap())
         } else {
             None
         };
@@ -857,27 +1140,35 @@ impl Channel {
             recomposed_tx,
         )?;
 
+        let cstate = self.get_chain_state();
         let outgoing_payment_summary = self.enforcement_state.payments_summary(Some(&info2), None);
         state.validate_payments(
             &self.id0,
             &incoming_payment_summary,
             &outgoing_payment_summary,
             &delta,
+            &cstate,
             validator.clone(),
         )?;
 
-        let (next_holder_commitment_point, maybe_old_secret) =
-            self.advance_holder_commitment_state(commitment_number, info2)?;
+        self.begin_signing("validate_holder_commitment_tx", commitment_number)?;
+        let (next_holder_commitment_point, maybe_old_secret) = self
+            .advance_holder_commitment_state(commitment_number, info2)
+            .map_err(|s| self.abort_signing(s))?;
+        self.enforcement_state.signing_intent = None;
 
         state.apply_payments(
             &self.id0,
             &incoming_payment_summary,
             &outgoing_payment_summary,
             &delta,
+            &cstate,
             validator,
         );
+        node.persist_ledger(&mut state)?;
 
         trace_enforcement_state!(&self.enforcement_state);
+        self.update_monitor_commitments();
         self.persist()?;
 
         Ok((next_holder_commitment_point, maybe_old_secret))
@@ -889,6 +1180,18 @@ impl Channel {
         commitment_number: u64,
     ) -> Result<(Signature, Vec<Signature>), Status> {
         let info2 = self.enforcement_state.get_current_holder_commitment_info(commitment_number)?;
+        let audit = AuditRecord::new(
+            Some(self.id0),
+            "sign_holder_commitment_tx",
+            Some(commitment_number),
+            vec![info2.to_broadcaster_value_sat, info2.to_countersigner_value_sat],
+            vec![],
+        );
+
+        self.enforcement_state
+            .check_no_signing_intent()
+            .and_then(|_| self.enforcement_state.check_holder_commit_not_revoked(commitment_number))
+            .map_err(|ve| self.get_node().audit_failure(&audit, ve))?;
 
         let htlcs =
             Self::htlcs_info2_to_oic(info2.offered_htlcs.clone(), info2.received_htlcs.clone());
@@ -923,13 +1226,11 @@ impl Channel {
         );
 
         // Sign the recomposed commitment.
-        let (sig, htlc_sigs) = self
-            .keys
-            .sign_holder_commitment_and_htlcs(&recomposed_holder_tx, &self.secp_ctx)
-            .map_err(|_| internal_error("failed to sign"))?;
+        let (sig, htlc_sigs) = self.sign_holder_commitment(&recomposed_holder_tx)?;
 
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
+        self.get_node().append_audit_record(audit)?;
         Ok((sig, htlc_sigs))
     }
 
@@ -938,7 +1239,6 @@ impl Channel {
     /// Use [`sign_counterparty_commitment_tx_phase2`] instead of this,
     /// since that one uses the last counter-signed holder tx, which is simpler
     /// and doesn't require re-validation of the holder tx.
-    // TODO anchors support once upstream supports it
     pub fn sign_holder_commitment_tx_phase2_redundant(
         &self,
         commitment_number: u64,
@@ -948,6 +1248,9 @@ impl Channel {
         offered_htlcs: Vec<HTLCInfo2>,
         received_htlcs: Vec<HTLCInfo2>,
     ) -> Result<(Signature, Vec<Signature>), Status> {
+        self.enforcement_state.check_no_signing_intent()?;
+        self.enforcement_state.check_holder_commit_not_revoked(commitment_number)?;
+
         let commitment_point = &self.get_per_commitment_point(commitment_number)?;
 
         let info2 = self.build_holder_commitment_info(
@@ -998,10 +1301,7 @@ impl Channel {
             &self.keys.counterparty_pubkeys().funding_pubkey,
         );
 
-        let (sig, htlc_sigs) = self
-            .keys
-            .sign_holder_commitment_and_htlcs(&holder_commitment_tx, &self.secp_ctx)
-            .map_err(|_| internal_error("failed to sign"))?;
+        let (sig, htlc_sigs) = self.sign_holder_commitment(&holder_commitment_tx)?;
 
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
@@ -1115,6 +1415,150 @@ impl Channel {
         self.node.upgrade().unwrap()
     }
 
+    // Sign, grinding for a low R value and verifying the signature if the
+    // policy asks for it
+    fn sign_ecdsa(&self, message: &Message, key: &SecretKey) -> Result<Signature, Status> {
+        let validator = self.validator();
+        sign_ecdsa_verified(
+            &self.secp_ctx,
+            message,
+            key,
+            validator.grind_low_r(),
+            validator.verify_signatures(),
+        )
+    }
+
+    // The LDK signer is built with grind_signatures, so it already grinds
+    // for a low R value.  It doesn't verify its signatures and doesn't know
+    // about zero-fee HTLC transactions, so sign ourselves in those cases.
+    fn use_ldk_signer(&self) -> bool {
+        !self.validator().verify_signatures() && !self.setup.option_anchors_zero_fee_htlc()
+    }
+
+    fn sign_counterparty_commitment(
+        &self,
+        commitment_tx: &CommitmentTransaction,
+    ) -> Result<(Signature, Vec<Signature>), Status> {
+        if !self.use_ldk_signer() {
+            return self.sign_commitment_and_htlcs(commitment_tx, true);
+        }
+        self.keys
+            .sign_counterparty_commitment(commitment_tx, Vec::new(), &self.secp_ctx)
+            .map_err(|_| internal_error("failed to sign"))
+    }
+
+    fn sign_holder_commitment(
+        &self,
+        holder_commitment_tx: &HolderCommitmentTransaction,
+    ) -> Result<(Signature, Vec<Signature>), Status> {
+        if !self.use_ldk_signer() {
+            return self.sign_commitment_and_htlcs(holder_commitment_tx, false);
+        }
+        self.keys
+            .sign_holder_commitment_and_htlcs(holder_commitment_tx, &self.secp_ctx)
+            .map_err(|_| internal_error("failed to sign"))
+    }
+
+    fn sign_closing(&self, closing_tx: &ClosingTransaction) -> Result<Signature, Status> {
+        if !self.use_ldk_signer() {
+            let trusted_tx = closing_tx.trust();
+            let sighash = self.funding_sighash(trusted_tx.built_transaction())?;
+            return self.sign_ecdsa(&sighash, &self.keys.funding_key);
+        }
+        self.keys
+            .sign_closing_transaction(closing_tx, &self.secp_ctx)
+            .map_err(|_| internal_error("failed to sign"))
+    }
+
+    // Reject a supplied redeemscript that doesn't match the one
+    // reconstructed from the channel keys
+    fn validate_redeemscript(
+        function: &str,
+        redeemscript: &Script,
+        expected: Option<Script>,
+    ) -> Result<(), Status> {
+        if expected.as_ref() != Some(redeemscript) {
+            return Err(policy_error(format!("{}: redeemscript mismatch", function)).into());
+        }
+        Ok(())
+    }
+
+    fn funding_sighash(&self, tx: &Transaction) -> Result<Message, Status> {
+        let redeemscript = make_funding_redeemscript(
+            &self.keys.pubkeys().funding_pubkey,
+            &self.setup.counterparty_points.funding_pubkey,
+        );
+        Message::from_slice(
+            &SigHashCache::new(tx).signature_hash(
+                0,
+                &redeemscript,
+                self.setup.channel_value_sat,
+                SigHashType::All,
+            )[..],
+        )
+        .map_err(|err| internal_error(format!("sighash failed: {}", err)))
+    }
+
+    // This mirrors the LDK signer's signing of the commitment and the
+    // second level HTLC transactions.
+    fn sign_commitment_and_htlcs(
+        &self,
+        commitment_tx: &CommitmentTransaction,
+        is_counterparty: bool,
+    ) -> Result<(Signature, Vec<Signature>), Status> {
+        let trusted_tx = commitment_tx.trust();
+        let built_tx = trusted_tx.built_transaction();
+        let txkeys = trusted_tx.keys();
+        let sighash = self.funding_sighash(&built_tx.transaction)?;
+        let sig = self.sign_ecdsa(&sighash, &self.keys.funding_key)?;
+
+        // The delay is imposed by the other side on the broadcaster
+        let to_self_delay = if is_counterparty {
+            self.setup.holder_selected_contest_delay
+        } else {
+            self.setup.counterparty_selected_contest_delay
+        };
+        // Only our signatures on the counterparty's HTLC transactions
+        // allow them to attach fees
+        let sig_hash_type = if is_counterparty && self.setup.option_anchor_outputs() {
+            SigHashType::SinglePlusAnyoneCanPay
+        } else {
+            SigHashType::All
+        };
+        let htlc_privkey = derive_private_key(
+            &self.secp_ctx,
+            &txkeys.per_commitment_point,
+            &self.keys.htlc_base_key,
+        )
+        .map_err(|_| internal_error("failed to derive key"))?;
+
+        let mut htlc_sigs = Vec::with_capacity(commitment_tx.htlcs().len());
+        for htlc in commitment_tx.htlcs() {
+            let htlc_tx = build_htlc_transaction(
+                &built_tx.txid,
+                self.setup.htlc_feerate_per_kw(commitment_tx.feerate_per_kw()),
+                to_self_delay,
+                htlc,
+                self.setup.option_anchor_outputs(),
+                &txkeys.broadcaster_delayed_payment_key,
+                &txkeys.revocation_key,
+            );
+            let htlc_redeemscript =
+                get_htlc_redeemscript(htlc, self.setup.option_anchor_outputs(), txkeys);
+            let htlc_sighash = Message::from_slice(
+                &SigHashCache::new(&htlc_tx).signature_hash(
+                    0,
+                    &htlc_redeemscript,
+                    htlc.amount_msat / 1000,
+                    sig_hash_type,
+                )[..],
+            )
+            .map_err(|err| internal_error(format!("htlc sighash failed: {}", err)))?;
+            htlc_sigs.push(self.sign_ecdsa(&htlc_sighash, &htlc_privkey)?);
+        }
+        Ok((sig, htlc_sigs))
+    }
+
     /// Sign a mutual close transaction after rebuilding it from the supplied arguments
     pub fn sign_mutual_close_tx_phase2(
         &mut self,
@@ -1124,16 +1568,26 @@ impl Channel {
         counterparty_script: &Option<Script>,
         holder_wallet_path_hint: &Vec<u32>,
     ) -> Result<Signature, Status> {
-        self.validator().validate_mutual_close_tx(
-            &*self.get_node(),
-            &self.setup,
-            &self.enforcement_state,
-            to_holder_value_sat,
-            to_counterparty_value_sat,
-            holder_script,
-            counterparty_script,
-            holder_wallet_path_hint,
-        )?;
+        let audit = AuditRecord::new(
+            Some(self.id0),
+            "sign_mutual_close_tx",
+            None,
+            vec![to_holder_value_sat, to_counterparty_value_sat],
+            holder_script.iter().chain(counterparty_script.iter()).cloned().collect(),
+        );
+        let node = self.get_node();
+        self.validator()
+            .validate_mutual_close_tx(
+                &*node,
+                &self.setup,
+                &self.enforcement_state,
+                to_holder_value_sat,
+                to_counterparty_value_sat,
+                holder_script,
+                counterparty_script,
+                holder_wallet_path_hint,
+            )
+            .map_err(|ve| node.audit_failure(&audit, ve))?;
 
         let tx = ClosingTransaction::new(
             to_holder_value_sat,
@@ -1143,13 +1597,68 @@ impl Channel {
             self.setup.funding_outpoint,
         );
 
-        let sig = self
-            .keys
-            .sign_closing_transaction(&tx, &self.secp_ctx)
-            .map_err(|_| Status::internal("failed to sign"))?;
+        let sig = self.sign_closing(&tx)?;
+        self.enforcement_state.mutual_close_signed = true;
+        trace_enforcement_state!(&self.enforcement_state);
+        self.persist()?;
+        node.append_audit_record(audit)?;
+        Ok(sig)
+    }
+
+    /// Sign an option_simple_close closing transaction after rebuilding it
+    /// from the supplied arguments.
+    ///
+    /// The closer pays the whole fee from its output.  An output is omitted
+    /// if its value is zero, which is allowed for the closee's output only
+    /// if it is dust.
+    #[cfg(feature = "simple_close")]
+    pub fn sign_simple_close_tx(
+        &mut self,
+        holder_is_closer: bool,
+        locktime: u32,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<Signature, Status> {
+        let audit = AuditRecord::new(
+            Some(self.id0),
+            "sign_simple_close_tx",
+            None,
+            vec![to_holder_value_sat, to_counterparty_value_sat],
+            holder_script.iter().chain(counterparty_script.iter()).cloned().collect(),
+        );
+        let node = self.get_node();
+        self.validator()
+            .validate_simple_close_tx(
+                &*node,
+                &self.setup,
+                &self.enforcement_state,
+                holder_is_closer,
+                locktime,
+                to_holder_value_sat,
+                to_counterparty_value_sat,
+                holder_script,
+                counterparty_script,
+                holder_wallet_path_hint,
+            )
+            .map_err(|ve| node.audit_failure(&audit, ve))?;
+
+        let tx = build_simple_close_tx(
+            self.setup.funding_outpoint,
+            locktime,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+        );
+        let sighash = self.funding_sighash(&tx)?;
+        let sig = self.sign_ecdsa(&sighash, &self.keys.funding_key)?;
         self.enforcement_state.mutual_close_signed = true;
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
+        node.append_audit_record(audit)?;
         Ok(sig)
     }
 
@@ -1172,14 +1681,32 @@ impl Channel {
         }
         let per_commitment_point = self.get_per_commitment_point(commitment_number)?;
 
-        self.validator().validate_delayed_sweep(
-            &*self.get_node(),
-            &self.setup,
-            &self.get_chain_state(),
-            tx,
-            input,
-            amount_sat,
-            wallet_path,
+        let audit = AuditRecord::for_tx(Some(self.id0), "sign_delayed_sweep", None, tx);
+        let node = self.get_node();
+        self.validator()
+            .validate_delayed_sweep(
+                &*node,
+                &self.setup,
+                &self.get_chain_state(),
+                tx,
+                input,
+                amount_sat,
+                wallet_path,
+            )
+            .map_err(|ve| node.audit_failure(&audit, ve))?;
+
+        // Our to-local output and our HTLC transaction outputs have the
+        // same script
+        let txkeys = self.make_holder_tx_keys(&per_commitment_point)?;
+        let expected_redeemscript = chan_utils::get_revokeable_redeemscript(
+            &txkeys.revocation_key,
+            self.setup.counterparty_selected_contest_delay,
+            &txkeys.broadcaster_delayed_payment_key,
+        );
+        Self::validate_redeemscript(
+            "sign_delayed_sweep",
+            redeemscript,
+            Some(expected_redeemscript),
         )?;
 
         let sighash = Message::from_slice(
@@ -1199,39 +1726,109 @@ impl Channel {
         )
         .map_err(|_| Status::internal("failed to derive key"))?;
 
-        let sig = self.secp_ctx.sign(&sighash, &privkey);
+        let sig = self.sign_ecdsa(&sighash, &privkey)?;
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
+        node.append_audit_record(audit)?;
         Ok(sig)
     }
 
-    /// Sign an offered or received HTLC output from a commitment the counterparty broadcast.
-    pub fn sign_counterparty_htlc_sweep(
+    /// Sign the to-remote output that goes to us, from a commitment the
+    /// counterparty broadcast.  `remote_per_commitment_point` is None with
+    /// `option_static_remotekey`.
+    pub fn sign_counterparty_payment_sweep(
         &self,
         tx: &bitcoin::Transaction,
         input: usize,
-        remote_per_commitment_point: &PublicKey,
-        redeemscript: &Script,
-        htlc_amount_sat: u64,
+        remote_per_commitment_point: &Option<PublicKey>,
+        amount_sat: u64,
         wallet_path: &Vec<u32>,
     ) -> Result<Signature, Status> {
         if input >= tx.input.len() {
             return Err(invalid_argument(format!(
-                "sign_counterparty_htlc_sweep: bad input index: {} >= {}",
+                "sign_counterparty_payment_sweep: bad input index: {} >= {}",
                 input,
                 tx.input.len()
             )));
         }
 
-        self.validator().validate_counterparty_htlc_sweep(
-            &*self.get_node(),
-            &self.setup,
-            &self.get_chain_state(),
-            tx,
+        let audit =
+            AuditRecord::for_tx(Some(self.id0), "sign_counterparty_payment_sweep", None, tx);
+        let node = self.get_node();
+        self.validator()
+            .validate_counterparty_payment_sweep(
+                &*node,
+                &self.setup,
+                &self.get_chain_state(),
+                tx,
+                input,
+                amount_sat,
+                wallet_path,
+            )
+            .map_err(|ve| node.audit_failure(&audit, ve))?;
+
+        let (privkey, _) = self.unilateral_close_key(remote_per_commitment_point, &None)?;
+        let pubkey = bitcoin::PublicKey {
+            compressed: true,
+            key: PublicKey::from_secret_key(&self.secp_ctx, &privkey),
+        };
+        // the script code of a P2WPKH output is the P2PKH script
+        let script_code = Script::new_p2pkh(&pubkey.pubkey_hash());
+        let sighash = Message::from_slice(
+            &SigHashCache::new(tx).signature_hash(
+                input,
+                &script_code,
+                amount_sat,
+                SigHashType::All,
+            )[..],
+        )
+        .map_err(|_| Status::internal("failed to sighash"))?;
+
+        let sig = self.sign_ecdsa(&sighash, &privkey)?;
+        trace_enforcement_state!(&self.enforcement_state);
+        self.persist()?;
+        node.append_audit_record(audit)?;
+        Ok(sig)
+    }
+
+    /// Sign an offered or received HTLC output from a commitment the counterparty broadcast.
+    pub fn sign_counterparty_htlc_sweep(
+        &self,
+        tx: &bitcoin::Transaction,
+        input: usize,
+        remote_per_commitment_point: &PublicKey,
+        redeemscript: &Script,
+        htlc_amount_sat: u64,
+        wallet_path: &Vec<u32>,
+    ) -> Result<Signature, Status> {
+        if input >= tx.input.len() {
+            return Err(invalid_argument(format!(
+                "sign_counterparty_htlc_sweep: bad input index: {} >= {}",
+                input,
+                tx.input.len()
+            )));
+        }
+
+        let audit = AuditRecord::for_tx(Some(self.id0), "sign_counterparty_htlc_sweep", None, tx);
+        let node = self.get_node();
+        self.validator()
+            .validate_counterparty_htlc_sweep(
+                &*node,
+                &self.setup,
+                &self.get_chain_state(),
+                tx,
+                redeemscript,
+                input,
+                htlc_amount_sat,
+                wallet_path,
+            )
+            .map_err(|ve| node.audit_failure(&audit, ve))?;
+
+        let txkeys = self.make_counterparty_tx_keys(remote_per_commitment_point)?;
+        Self::validate_redeemscript(
+            "sign_counterparty_htlc_sweep",
             redeemscript,
-            input,
-            htlc_amount_sat,
-            wallet_path,
+            recompose_htlc_redeemscript(redeemscript, self.setup.option_anchor_outputs(), &txkeys),
         )?;
 
         let htlc_sighash = Message::from_slice(
@@ -1251,9 +1848,10 @@ impl Channel {
         )
         .map_err(|_| Status::internal("failed to derive key"))?;
 
-        let sig = self.secp_ctx.sign(&htlc_sighash, &htlc_privkey);
+        let sig = self.sign_ecdsa(&htlc_sighash, &htlc_privkey)?;
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
+        node.append_audit_record(audit)?;
         Ok(sig)
     }
 
@@ -1274,15 +1872,35 @@ impl Channel {
                 tx.input.len()
             )));
         }
-        self.validator().validate_justice_sweep(
-            &*self.get_node(),
-            &self.setup,
-            &self.get_chain_state(),
-            tx,
-            input,
-            amount_sat,
-            wallet_path,
-        )?;
+        let audit = AuditRecord::for_tx(Some(self.id0), "sign_justice_sweep", None, tx);
+        let node = self.get_node();
+        self.validator()
+            .validate_justice_sweep(
+                &*node,
+                &self.setup,
+                &self.get_chain_state(),
+                tx,
+                input,
+                amount_sat,
+                wallet_path,
+            )
+            .map_err(|ve| node.audit_failure(&audit, ve))?;
+
+        // The revoked output is either the counterparty's to-local output
+        // or an HTLC output
+        let per_commitment_point = PublicKey::from_secret_key(&self.secp_ctx, revocation_secret);
+        let txkeys = self.make_counterparty_tx_keys(&per_commitment_point)?;
+        let to_local_redeemscript = chan_utils::get_revokeable_redeemscript(
+            &txkeys.revocation_key,
+            self.setup.holder_selected_contest_delay,
+            &txkeys.broadcaster_delayed_payment_key,
+        );
+        let expected_redeemscript = if *redeemscript == to_local_redeemscript {
+            Some(to_local_redeemscript)
+        } else {
+            recompose_htlc_redeemscript(redeemscript, self.setup.option_anchor_outputs(), &txkeys)
+        };
+        Self::validate_redeemscript("sign_justice_sweep", redeemscript, expected_redeemscript)?;
 
         let sighash = Message::from_slice(
             &SigHashCache::new(tx).signature_hash(
@@ -1301,29 +1919,351 @@ impl Channel {
         )
         .map_err(|_| Status::internal("failed to derive key"))?;
 
-        let sig = self.secp_ctx.sign(&sighash, &privkey);
+        let sig = self.sign_ecdsa(&sighash, &privkey)?;
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
+        node.append_audit_record(audit)?;
         Ok(sig)
     }
 
+    /// Sign the anchor output of our commitment transaction, so that a
+    /// child transaction can bump its fee (CPFP).
+    ///
+    /// The signature commits to the anchor script, so it cannot be used
+    /// to spend anything but our anchor output.  The other inputs of the
+    /// child transaction are signed with [Node::sign_onchain_tx], and
+    /// should be taken from the node's fee reserve if it has one - see
+    /// [Node::add_fee_reserve].
+    pub fn sign_holder_anchor_input(
+        &self,
+        anchor_tx: &bitcoin::Transaction,
+        input: usize,
+    ) -> Result<Signature, Status> {
+        if !self.setup.option_anchor_outputs() {
+            return Err(policy_error(format!(
+                "sign_holder_anchor_input: channel {} does not have anchors",
+                self.id0
+            ))
+            .into());
+        }
+        if input >= anchor_tx.input.len() {
+            return Err(invalid_argument(format!(
+                "sign_holder_anchor_input: bad input index: {} >= {}",
+                input,
+                anchor_tx.input.len()
+            )));
+        }
+
+        let node = self.get_node();
+        if !node.fee_reserve().is_empty() && !node.spends_fee_reserve(anchor_tx) {
+            warn!(
+                "sign_holder_anchor_input: channel {} fee bump doesn't use the fee reserve",
+                self.id0
+            );
+        }
+
+        let redeemscript = get_anchor_redeemscript(&self.keys.pubkeys().funding_pubkey);
+        let sighash = Message::from_slice(
+            &SigHashCache::new(anchor_tx).signature_hash(
+                input,
+                &redeemscript,
+                ANCHOR_SAT,
+                SigHashType::All,
+            )[..],
+        )
+        .map_err(|_| Status::internal("failed to sighash"))?;
+
+        self.sign_ecdsa(&sighash, &self.keys.funding_key)
+    }
+
+    /// Cross-check a serialized LDK `ChannelMonitor` backup against our
+    /// state, so that divergence is detected before it matters at
+    /// force-close time.
+    ///
+    /// Returns the divergences found, which is empty if the monitor is
+    /// consistent with our state.
+    pub fn check_ldk_monitor(&self, serialized: &[u8]) -> Result<Vec<MonitorDivergence>, Status> {
+        let summary = LdkMonitorSummary::decode(serialized)
+            .map_err(|e| invalid_argument(format!("could not decode channel monitor: {:?}", e)))?;
+        let divergences = summary.divergences(self);
+        for divergence in divergences.iter() {
+            warn!(
+                "{}: channel monitor diverges on {}: monitor {} signer {}",
+                self.id(),
+                divergence.field,
+                divergence.monitor,
+                divergence.signer
+            );
+        }
+        Ok(divergences)
+    }
+
+    /// Build and sign a justice transaction sweeping outputs of a revoked
+    /// counterparty commitment transaction to the layer-1 wallet.
+    ///
+    /// The revocation secret for `commit_num` must have been previously
+    /// revealed by the counterparty.  The fee is deducted from the swept
+    /// value.  The returned transaction is fully witnessed.
+    pub fn build_and_sign_justice_tx(
+        &self,
+        breach_txid: &Txid,
+        commit_num: u64,
+        outputs: &Vec<JusticeOutput>,
+        feerate_per_kw: u32,
+        wallet_path: &Vec<u32>,
+    ) -> Result<Transaction, Status> {
+        if outputs.is_empty() {
+            return Err(invalid_argument("build_and_sign_justice_tx: no outputs to sweep"));
+        }
+        let revocation_secret =
+            self.get_counterparty_revocation_secret(commit_num).ok_or_else(|| {
+                invalid_argument(format!(
+                    "build_and_sign_justice_tx: no revocation secret for commit_num {}",
+                    commit_num
+                ))
+            })?;
+        let per_commitment_point = PublicKey::from_secret_key(&self.secp_ctx, &revocation_secret);
+        let keys = self.make_counterparty_tx_keys(&per_commitment_point)?;
+
+        let mut redeemscripts = Vec::new();
+        let mut witness_weight = 2; // segwit marker and flag
+        for output in outputs.iter() {
+            let (redeemscript, selector) = match &output.kind {
+                JusticeOutputKind::ToLocal => (
+                    chan_utils::get_revokeable_redeemscript(
+                        &keys.revocation_key,
+                        self.setup.holder_selected_contest_delay,
+                        &keys.broadcaster_delayed_payment_key,
+                    ),
+                    vec![1],
+                ),
+                JusticeOutputKind::OfferedHtlc(htlc) | JusticeOutputKind::ReceivedHtlc(htlc) => {
+                    let offered = matches!(output.kind, JusticeOutputKind::OfferedHtlc(_));
+                    let htlc = HTLCOutputInCommitment {
+                        offered,
+                        amount_msat: htlc.value_sat * 1000,
+                        cltv_expiry: htlc.cltv_expiry,
+                        payment_hash: htlc.payment_hash,
+                        transaction_output_index: Some(output.vout),
+                    };
+                    (
+                        get_htlc_redeemscript(&htlc, self.setup.option_anchor_outputs(), &keys),
+                        keys.revocation_key.serialize().to_vec(),
+                    )
+                }
+            };
+            // item count, then length-prefixed signature, selector and redeemscript
+            witness_weight += 1 + (1 + 73) + (1 + selector.len()) + (1 + redeemscript.len());
+            redeemscripts.push((redeemscript, selector));
+        }
+
+        let total_sat: u64 = outputs.iter().map(|o| o.value_sat).sum();
+        let destination = self.get_node().get_native_address(wallet_path)?;
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: outputs
+                .iter()
+                .map(|o| TxIn {
+                    previous_output: OutPoint { txid: *breach_txid, vout: o.vout },
+                    script_sig: Script::new(),
+                    sequence: 0xffff_ffff,
+                    witness: vec![],
+                })
+                .collect(),
+            output: vec![TxOut { value: total_sat, script_pubkey: destination.script_pubkey() }],
+        };
+        let weight = (tx.get_weight() + witness_weight) as u64;
+        let fee_sat = feerate_per_kw as u64 * weight / 1000;
+        if total_sat <= fee_sat + MIN_DUST_LIMIT_SATOSHIS {
+            return Err(invalid_argument(format!(
+                "build_and_sign_justice_tx: swept value {} too small for fee {}",
+                total_sat, fee_sat
+            )));
+        }
+        tx.output[0].value = total_sat - fee_sat;
+
+        let mut witnesses = Vec::new();
+        for (idx, (output, (redeemscript, selector))) in
+            outputs.iter().zip(redeemscripts.into_iter()).enumerate()
+        {
+            let sig = self.sign_justice_sweep(
+                &tx,
+                idx,
+                &revocation_secret,
+                &redeemscript,
+                output.value_sat,
+                wallet_path,
+            )?;
+            witnesses.push(vec![signature_to_bitcoin_vec(sig), selector, redeemscript.to_bytes()]);
+        }
+        for (input, witness) in tx.input.iter_mut().zip(witnesses.into_iter()) {
+            input.witness = witness;
+        }
+        Ok(tx)
+    }
+
     /// Sign a channel announcement with both the node key and the funding key
-    pub fn sign_channel_announcement(&self, announcement: &Vec<u8>) -> (Signature, Signature) {
+    pub fn sign_channel_announcement(
+        &self,
+        announcement: &Vec<u8>,
+    ) -> Result<(Signature, Signature), Status> {
         let ann_hash = Sha256dHash::hash(announcement);
         let encmsg = secp256k1::Message::from_slice(&ann_hash[..]).expect("encmsg failed");
+        let verify = self.validator().verify_signatures();
+        let sign =
+            |key: &SecretKey| sign_ecdsa_verified(&self.secp_ctx, &encmsg, key, false, verify);
 
-        (
-            self.secp_ctx.sign(&encmsg, &self.get_node().get_node_secret()),
-            self.secp_ctx.sign(&encmsg, &self.keys.funding_key),
-        )
+        Ok((sign(&self.get_node().get_node_secret())?, sign(&self.keys.funding_key)?))
+    }
+
+    /// Let the chain monitor know about the current commitment
+    /// transactions, so that it can detect breaches.
+    ///
+    /// Called whenever the commitment state advances, and on restore.
+    pub(crate) fn update_monitor_commitments(&self) {
+        let estate = &self.enforcement_state;
+        let mut txids = Vec::new();
+        if let Some(info) = &estate.current_holder_commit_info {
+            let htlcs =
+                Self::htlcs_info2_to_oic(info.offered_htlcs.clone(), info.received_htlcs.clone());
+            if let Ok(tx) = self.make_holder_commitment_tx(
+                estate.next_holder_commit_num - 1,
+                info.feerate_per_kw,
+                info.to_broadcaster_value_sat,
+                info.to_countersigner_value_sat,
+                htlcs,
+            ) {
+                txids.push(tx.trust().txid());
+            }
+        }
+        let counterparty_commitments = [
+            (1, &estate.current_counterparty_point, &estate.current_counterparty_commit_info),
+            (2, &estate.previous_counterparty_point, &estate.previous_counterparty_commit_info),
+        ];
+        for (back, point, info) in counterparty_commitments.iter() {
+            if let (Some(point), Some(info)) = (point, info) {
+                if estate.next_counterparty_commit_num < *back {
+                    continue;
+                }
+                let commit_num = estate.next_counterparty_commit_num - back;
+                if commit_num < estate.next_counterparty_revoke_num {
+                    // revoked
+                    continue;
+                }
+                let htlcs = Self::htlcs_info2_to_oic(
+                    info.offered_htlcs.clone(),
+                    info.received_htlcs.clone(),
+                );
+                let tx = self.make_counterparty_commitment_tx(
+                    point,
+                    commit_num,
+                    info.feerate_per_kw,
+                    info.to_countersigner_value_sat,
+                    info.to_broadcaster_value_sat,
+                    htlcs,
+                );
+                txids.push(tx.trust().txid());
+            }
+        }
+        self.monitor.set_current_commitments(
+            self.node.clone(),
+            self.id(),
+            self.get_commitment_transaction_number_obscure_factor(),
+            txids,
+        );
+    }
+
+    /// The signer-side state of the channel
+    pub fn status(&self) -> ChannelStatus {
+        let estate = &self.enforcement_state;
+        ChannelStatus {
+            channel_id: self.id(),
+            channel_value_sat: self.setup.channel_value_sat,
+            funding_outpoint: self.setup.funding_outpoint,
+            funding_depth: self.monitor.funding_depth(),
+            funding_double_spent_depth: self.monitor.funding_double_spent_depth(),
+            closing_depth: self.monitor.closing_depth(),
+            next_holder_commit_num: estate.next_holder_commit_num,
+            next_holder_revoke_num: estate.next_holder_revoke_num,
+            next_counterparty_commit_num: estate.next_counterparty_commit_num,
+            next_counterparty_revoke_num: estate.next_counterparty_revoke_num,
+            holder_commitment: estate
+                .current_holder_commit_info
+                .as_ref()
+                .map(CommitmentBalance::new),
+            counterparty_commitment: estate
+                .current_counterparty_commit_info
+                .as_ref()
+                .map(CommitmentBalance::new),
+            mutual_close_signed: estate.mutual_close_signed,
+        }
+    }
+
+    /// Whether the closing transaction is confirmed deep enough that
+    /// the closing and sweep outputs are settled.
+    ///
+    /// This is [CLOSED_DEPTH] beyond the delay on our outputs, so that
+    /// they had a chance to be swept.
+    pub fn is_closed(&self) -> bool {
+        let depth = self.monitor.closing_depth();
+        depth > 0 && depth >= self.setup.counterparty_selected_contest_delay as u32 + CLOSED_DEPTH
+    }
+
+    /// Record that a commitment transaction closed the channel.
+    ///
+    /// The closing transaction is persisted, and the channel should be
+    /// moved to [ChannelSlot::Closing].
+    pub(crate) fn set_closing(&mut self, txid: Txid, height: u32) -> Result<(), Status> {
+        warn!(
+            "{}: commitment {} confirmed at height {}, channel is closing",
+            self.id(),
+            txid,
+            height
+        );
+        self.enforcement_state.closing_txid = Some(txid);
+        self.enforcement_state.closing_height = Some(height);
+        self.persist()
     }
 
     fn persist(&self) -> Result<(), Status> {
+        self.persist_with_tracker(None)
+    }
+
+    // Persist the intent to sign before signing, so that a crash before the
+    // resulting state is persisted leaves the channel suspect on restart.
+    // The caller clears the intent along with the resulting state.
+    fn begin_signing(&mut self, operation: &str, commit_num: u64) -> Result<(), Status> {
+        self.enforcement_state.check_no_signing_intent()?;
+        self.enforcement_state.signing_intent =
+            Some(SigningIntent { operation: operation.to_string(), commit_num });
+        self.persist().map_err(|s| {
+            self.enforcement_state.signing_intent = None;
+            s
+        })
+    }
+
+    // Abandon the intent after a failure that produced no signature
+    fn abort_signing(&mut self, status: Status) -> Status {
+        self.enforcement_state.signing_intent = None;
+        if let Err(e) = self.persist() {
+            warn!("{}: could not clear the signing intent: {}", self.id0, e);
+        }
+        status
+    }
+
+    // Persist the channel, together with the tracker if its watches
+    // changed along with the channel
+    pub(crate) fn persist_with_tracker(
+        &self,
+        tracker: Option<&ChainTracker<ChainMonitor>>,
+    ) -> Result<(), Status> {
         let node_id = self.get_node().get_id();
+        let batch = PersistBatch { tracker, channels: vec![self] };
         self.get_node()
             .persister
-            .update_channel(&node_id, &self)
-            .map_err(|_| Status::internal("persist failed"))
+            .update_batch(&node_id, &batch)
+            .map_err(|e| persist_error("channel persist failed", e))
     }
 
     /// The node's network
@@ -1342,6 +2282,125 @@ impl Channel {
 
         // self.monitor.add_funding(tx, vout);
     }
+
+    /// Begin a splice of the channel funding, by signing our side of a
+    /// splice transaction.  The transaction spends the current funding
+    /// output and our wallet inputs, into a new funding output at `vout`
+    /// and our outputs.
+    ///
+    /// The channel switches to the new funding outpoint immediately.  If the
+    /// splice transaction is reorged-out before the splice is locked, the
+    /// channel rolls back to the previous funding outpoint.
+    ///
+    /// The channel is not persisted.  The caller must persist it together
+    /// with the tracker, since the monitor starts watching for the splice
+    /// transaction.
+    ///
+    /// Returns the signature on the funding input and the witnesses for
+    /// our wallet inputs.
+    pub fn begin_splice(
+        &mut self,
+        tx: &Transaction,
+        vout: u32,
+        inputs: &Vec<InteractiveInput>,
+        outputs: &Vec<InteractiveOutput>,
+    ) -> Result<(Signature, Vec<Vec<Vec<u8>>>), Status> {
+        let node = self.get_node();
+        let validator = self.validator();
+        validator.validate_splice_tx(&*node, self, tx, vout, inputs, outputs)?;
+
+        let funding_ndx = tx
+            .input
+            .iter()
+            .position(|i| i.previous_output == self.setup.funding_outpoint)
+            .ok_or_else(|| invalid_argument("splice does not spend the funding outpoint"))?;
+        let redeemscript = make_funding_redeemscript(
+            &self.keys.pubkeys().funding_pubkey,
+            &self.setup.counterparty_points.funding_pubkey,
+        );
+        let sighash = Message::from_slice(
+            &SigHashCache::new(tx).signature_hash(
+                funding_ndx,
+                &redeemscript,
+                self.setup.channel_value_sat,
+                SigHashType::All,
+            )[..],
+        )
+        .map_err(|err| internal_error(format!("sighash failed: {}", err)))?;
+        let sig = self.sign_ecdsa(&sighash, &self.keys.funding_key)?;
+        let witvec = node.sign_wallet_inputs(
+            tx,
+            inputs,
+            validator.grind_low_r(),
+            validator.verify_signatures(),
+        )?;
+
+        self.enforcement_state.splice = Some(SpliceState {
+            previous_outpoint: self.setup.funding_outpoint,
+            previous_value_sat: self.setup.channel_value_sat,
+            first_holder_commit_num: self.enforcement_state.next_holder_commit_num,
+            first_counterparty_commit_num: self.enforcement_state.next_counterparty_commit_num,
+        });
+        self.set_funding(OutPoint::new(tx.txid(), vout), tx.output[vout as usize].value);
+        self.monitor.add_splice(tx, vout);
+        Ok((sig, witvec))
+    }
+
+    /// Roll back the splice in progress, returning to the previous funding
+    /// outpoint.  This is done automatically if the splice transaction is
+    /// reorged-out.
+    pub fn rollback_splice(&mut self) -> Result<(), Status> {
+        let splice = self
+            .enforcement_state
+            .splice
+            .take()
+            .ok_or_else(|| failed_precondition("no splice in progress"))?;
+        warn!(
+            "{}: rolling back splice to funding outpoint {}",
+            self.id(),
+            splice.previous_outpoint
+        );
+        self.set_funding(splice.previous_outpoint, splice.previous_value_sat);
+        self.monitor.remove_splice();
+        self.persist()
+    }
+
+    /// The splice transaction is deep enough, and the splice can no longer
+    /// be rolled back
+    pub fn splice_locked(&mut self) -> Result<(), Status> {
+        if self.enforcement_state.splice.is_none() {
+            return Err(failed_precondition("no splice in progress"));
+        }
+        if self.monitor.splice_depth() == 0 {
+            return Err(failed_precondition("splice transaction not confirmed"));
+        }
+        self.enforcement_state.splice = None;
+        self.persist()
+    }
+
+    // Switch to a new funding outpoint.  The signer holds the channel
+    // value and the funding outpoint, so it is rebuilt.
+    fn set_funding(&mut self, funding_outpoint: OutPoint, channel_value_sat: u64) {
+        self.setup.funding_outpoint = funding_outpoint;
+        self.setup.channel_value_sat = channel_value_sat;
+        let keys = &self.keys;
+        let mut keys = InMemorySigner::new(
+            &self.secp_ctx,
+            self.get_node().get_node_secret(),
+            keys.funding_key,
+            keys.revocation_base_key,
+            keys.payment_key,
+            keys.delayed_payment_base_key,
+            keys.htlc_base_key,
+            keys.commitment_seed,
+            channel_value_sat,
+            keys.channel_keys_id(),
+        );
+        let channel_transaction_parameters =
+            Node::channel_setup_to_channel_transaction_parameters(&self.setup, keys.pubkeys());
+        keys.ready_channel(&channel_transaction_parameters);
+        self.keys = keys;
+    }
 }
 
 // Phase 1
@@ -1456,13 +2515,25 @@ impl Channel {
         offered_htlcs: Vec<HTLCInfo2>,
         received_htlcs: Vec<HTLCInfo2>,
     ) -> Result<Signature, Status> {
+        if self.setup.option_taproot() {
+            return Err(invalid_argument("taproot channels are signed with MuSig2"));
+        }
         if tx.output.len() != output_witscripts.len() {
             return Err(invalid_argument("len(tx.output) != len(witscripts)"));
         }
 
+        let audit = AuditRecord::for_tx(
+            Some(self.id0),
+            "sign_counterparty_commitment_tx",
+            Some(commitment_number),
+            tx,
+        );
+
         // Since we didn't have the value at the real open, validate it now.
         let validator = self.validator();
-        validator.validate_channel_value(&self.setup)?;
+        validator
+            .validate_channel_value(&self.setup)
+            .map_err(|ve| self.get_node().audit_failure(&audit, ve))?;
 
         // Derive a CommitmentInfo first, convert to CommitmentInfo2 below ...
         let is_counterparty = true;
@@ -1509,7 +2580,7 @@ impl Channel {
                     &self.get_chain_state(),
                     &info2,
                 );
-                ve
+                node.audit_failure(&audit, ve)
             })?;
 
         let htlcs =
@@ -1549,39 +2620,155 @@ impl Channel {
 
         let point = recomposed_tx.trust().keys().per_commitment_point;
 
-        // Sign the recomposed commitment.
-        let sigs = self
-            .keys
-            .sign_counterparty_commitment(&recomposed_tx, Vec::new(), &self.secp_ctx)
-            .map_err(|_| internal_error(format!("sign_counterparty_commitment failed")))?;
-
+        let cstate = self.get_chain_state();
         let outgoing_payment_summary = self.enforcement_state.payments_summary(None, Some(&info2));
-        state.validate_payments(
-            &self.id0,
-            &incoming_payment_summary,
-            &outgoing_payment_summary,
-            &delta,
-            validator.clone(),
-        )?;
+        state
+            .validate_payments(
+                &self.id0,
+                &incoming_payment_summary,
+                &outgoing_payment_summary,
+                &delta,
+                &cstate,
+                validator.clone(),
+            )
+            .map_err(|ve| node.audit_failure(&audit, ve))?;
+
+        // Sign the recomposed commitment.
+        self.begin_signing("sign_counterparty_commitment_tx", commit_num)?;
+        let sigs =
+            self.sign_counterparty_commitment(&recomposed_tx).map_err(|s| self.abort_signing(s))?;
 
         // Only advance the state if nothing goes wrong.
-        self.enforcement_state.set_next_counterparty_commit_num(commit_num + 1, point, info2)?;
+        self.enforcement_state
+            .set_next_counterparty_commit_num(commit_num + 1, point, info2)
+            .map_err(|ve| self.abort_signing(ve.into()))?;
+        self.note_activity();
+        self.enforcement_state.signing_intent = None;
 
         state.apply_payments(
             &self.id0,
             &incoming_payment_summary,
             &outgoing_payment_summary,
             &delta,
+            &cstate,
             validator,
         );
+        node.persist_ledger(&mut state)?;
 
         trace_enforcement_state!(&self.enforcement_state);
+        self.update_monitor_commitments();
         self.persist()?;
+        node.append_audit_record(audit)?;
 
         // Discard the htlc signatures for now.
         Ok(sigs.0)
     }
 
+    // The MuSig2 key aggregation context of the taproot funding output
+    fn musig_key_agg_context(&self) -> Result<KeyAggContext, Status> {
+        let mut pubkeys =
+            vec![self.keys.pubkeys().funding_pubkey, self.setup.counterparty_points.funding_pubkey];
+        pubkeys.sort_by_key(|pk| pk.serialize());
+        let mut ctx = KeyAggContext::new(&self.secp_ctx, &pubkeys)
+            .map_err(|err| internal_error(format!("key aggregation failed: {:?}", err)))?;
+        ctx.apply_bip86_tweak(&self.secp_ctx)
+            .map_err(|err| internal_error(format!("funding key tweak failed: {:?}", err)))?;
+        Ok(ctx)
+    }
+
+    /// The script of the taproot funding output
+    pub fn taproot_funding_script(&self) -> Result<Script, Status> {
+        let ctx = self.musig_key_agg_context()?;
+        Ok(Builder::new().push_int(1).push_slice(&ctx.xonly_pubkey()).into_script())
+    }
+
+    // The MuSig2 nonce with the given index.  The nonce is derived from the
+    // commitment seed, so an index must never be used for two signatures.
+    fn musig_nonce(&self, index: u64, ctx: &KeyAggContext) -> Result<(SecNonce, PubNonce), Status> {
+        let rand =
+            tagged_hash("VLS/musig2 nonce", &[&self.keys.commitment_seed, &index.to_be_bytes()]);
+        nonce_gen(
+            &self.secp_ctx,
+            &rand,
+            &self.keys.funding_key,
+            Some(&ctx.xonly_pubkey()),
+            None,
+            &[],
+        )
+        .map_err(|err| internal_error(format!("nonce generation failed: {:?}", err)))
+    }
+
+    /// Get our MuSig2 nonce for the next counterparty commitment signature.
+    ///
+    /// The same nonce is returned until it is consumed by
+    /// [Channel::sign_counterparty_commitment_tx_musig], and a new index
+    /// is persisted before a nonce is given out, so that no nonce is used
+    /// for two signatures.
+    pub fn get_musig_nonce(&mut self) -> Result<PubNonce, Status> {
+        if !self.setup.option_taproot() {
+            return Err(invalid_argument("not a taproot channel"));
+        }
+        let index = match self.enforcement_state.pending_musig_nonce_index {
+            Some(index) => index,
+            None => {
+                let index = self.enforcement_state.next_musig_nonce_index;
+                self.enforcement_state.next_musig_nonce_index = index + 1;
+                self.enforcement_state.pending_musig_nonce_index = Some(index);
+                self.persist()?;
+                index
+            }
+        };
+        let ctx = self.musig_key_agg_context()?;
+        Ok(self.musig_nonce(index, &ctx)?.1)
+    }
+
+    /// Create a MuSig2 partial signature for the funding input of a
+    /// counterparty commitment transaction on a taproot channel.
+    ///
+    /// Our nonce is the pending one from [Channel::get_musig_nonce], and it
+    /// is consumed.  Only the funding spend is checked, since taproot
+    /// commitment transactions are not decoded yet.
+    pub fn sign_counterparty_commitment_tx_musig(
+        &mut self,
+        tx: &Transaction,
+        counterparty_nonce: &PubNonce,
+    ) -> Result<PartialSig, Status> {
+        if !self.setup.option_taproot() {
+            return Err(invalid_argument("not a taproot channel"));
+        }
+        if tx.input.len() != 1 || tx.input[0].previous_output != self.setup.funding_outpoint {
+            return Err(policy_error("commitment tx must only spend the funding outpoint").into());
+        }
+        let index = self
+            .enforcement_state
+            .pending_musig_nonce_index
+            .ok_or_else(|| failed_precondition("no pending musig2 nonce"))?;
+        let ctx = self.musig_key_agg_context()?;
+        let (secnonce, pubnonce) = self.musig_nonce(index, &ctx)?;
+        let aggnonce = nonce_agg(&[pubnonce, *counterparty_nonce])
+            .map_err(|_| invalid_argument("invalid counterparty nonce"))?;
+        let funding_output = TxOut {
+            value: self.setup.channel_value_sat,
+            script_pubkey: self.taproot_funding_script()?,
+        };
+        let sighash = taproot_key_spend_sighash(tx, 0, &[funding_output])
+            .map_err(|_| internal_error("taproot sighash failed"))?;
+        let sig = partial_sign(
+            &self.secp_ctx,
+            secnonce,
+            &self.keys.funding_key,
+            &ctx,
+            &aggnonce,
+            &sighash,
+        )
+        .map_err(|err| internal_error(format!("partial signature failed: {:?}", err)))?;
+
+        // Consume the nonce before releasing the signature
+        self.enforcement_state.pending_musig_nonce_index = None;
+        self.persist()?;
+        Ok(sig)
+    }
+
     fn make_validated_recomposed_holder_commitment_tx(
         &self,
         tx: &bitcoin::Transaction,
@@ -1732,27 +2919,35 @@ impl Channel {
             recomposed_tx,
         )?;
 
+        let cstate = self.get_chain_state();
         let outgoing_payment_summary = self.enforcement_state.payments_summary(Some(&info2), None);
         state.validate_payments(
             &self.id0,
             &incoming_payment_summary,
             &outgoing_payment_summary,
             &delta,
+            &cstate,
             validator.clone(),
         )?;
 
-        let (next_holder_commitment_point, maybe_old_secret) =
-            self.advance_holder_commitment_state(commitment_number, info2)?;
+        self.begin_signing("validate_holder_commitment_tx", commitment_number)?;
+        let (next_holder_commitment_point, maybe_old_secret) = self
+            .advance_holder_commitment_state(commitment_number, info2)
+            .map_err(|s| self.abort_signing(s))?;
+        self.enforcement_state.signing_intent = None;
 
         state.apply_payments(
             &self.id0,
             &incoming_payment_summary,
             &outgoing_payment_summary,
             &delta,
+            &cstate,
             validator,
         );
+        node.persist_ledger(&mut state)?;
 
         trace_enforcement_state!(&self.enforcement_state);
+        self.update_monitor_commitments();
         self.persist()?;
 
         Ok((next_holder_commitment_point, maybe_old_secret))
@@ -1767,20 +2962,155 @@ impl Channel {
         revoke_num: u64,
         old_secret: &SecretKey,
     ) -> Result<(), Status> {
-        // TODO - need to store the revealed secret.
-
         self.validator().validate_counterparty_revocation(
             &self.enforcement_state,
             revoke_num,
             old_secret,
         )?;
+        // Capture the revoked commitment before its info is dropped.
+        // A retried revocation was already announced.
+        let revoked = if revoke_num == self.enforcement_state.next_counterparty_revoke_num {
+            self.counterparty_justice_outputs(revoke_num)
+        } else {
+            None
+        };
+        self.enforcement_state.set_counterparty_revocation_secret(revoke_num, old_secret)?;
         self.enforcement_state.set_next_counterparty_revoke_num(revoke_num + 1)?;
 
         trace_enforcement_state!(&self.enforcement_state);
+        self.update_monitor_commitments();
         self.persist()?;
+        if let Some((txid, outputs)) = revoked {
+            let node = self.get_node();
+            if let Some(export) = node.watchtower_export() {
+                match self.build_and_sign_justice_tx(
+                    &txid,
+                    revoke_num,
+                    &outputs,
+                    export.feerate_per_kw,
+                    &export.wallet_path,
+                ) {
+                    Ok(tx) => node.queue_watchtower_blob(WatchtowerBlob::new(
+                        self.id(),
+                        revoke_num,
+                        &txid,
+                        &tx,
+                    )),
+                    Err(e) => warn!(
+                        "{}: watchtower export of commit_num {} failed: {:?}",
+                        short_function!(),
+                        revoke_num,
+                        e
+                    ),
+                }
+            }
+            node.notify_event(NodeEvent::CounterpartyRevocation {
+                channel_id: self.id(),
+                txid,
+                commit_num: revoke_num,
+                outputs,
+            });
+        }
         Ok(())
     }
 
+    /// Export the justice data for a revoked counterparty commitment, for
+    /// an external watchtower.
+    ///
+    /// The commitment must be the current or previous counterparty
+    /// commitment, since older ones can no longer be rebuilt.  The justice
+    /// transaction sweeps to the layer-1 wallet at `wallet_path`.
+    pub fn export_watchtower_blob(
+        &self,
+        commit_num: u64,
+        feerate_per_kw: u32,
+        wallet_path: &Vec<u32>,
+    ) -> Result<WatchtowerBlob, Status> {
+        let (txid, outputs) = self.counterparty_justice_outputs(commit_num).ok_or_else(|| {
+            failed_precondition(format!(
+                "export_watchtower_blob: commitment {} is no longer available",
+                commit_num
+            ))
+        })?;
+        let tx = self.build_and_sign_justice_tx(
+            &txid,
+            commit_num,
+            &outputs,
+            feerate_per_kw,
+            wallet_path,
+        )?;
+        Ok(WatchtowerBlob::new(self.id(), commit_num, &txid, &tx))
+    }
+
+    // The txid of a current or previous counterparty commitment, and its
+    // outputs that can be swept by a justice transaction once revoked
+    fn counterparty_justice_outputs(&self, commit_num: u64) -> Option<(Txid, Vec<JusticeOutput>)> {
+        let estate = &self.enforcement_state;
+        let (point, info) = if commit_num + 1 == estate.next_counterparty_commit_num {
+            (estate.current_counterparty_point?, estate.current_counterparty_commit_info.as_ref()?)
+        } else if commit_num + 2 == estate.next_counterparty_commit_num {
+            (
+                estate.previous_counterparty_point?,
+                estate.previous_counterparty_commit_info.as_ref()?,
+            )
+        } else {
+            return None;
+        };
+        let htlcs =
+            Self::htlcs_info2_to_oic(info.offered_htlcs.clone(), info.received_htlcs.clone());
+        let commitment_tx = self.make_counterparty_commitment_tx(
+            &point,
+            commit_num,
+            info.feerate_per_kw,
+            info.to_countersigner_value_sat,
+            info.to_broadcaster_value_sat,
+            htlcs,
+        );
+        let trusted_tx = commitment_tx.trust();
+        let keys = trusted_tx.keys();
+        let built_tx = trusted_tx.built_transaction();
+        let to_local_script = chan_utils::get_revokeable_redeemscript(
+            &keys.revocation_key,
+            self.setup.holder_selected_contest_delay,
+            &keys.broadcaster_delayed_payment_key,
+        )
+        .to_v0_p2wsh();
+
+        let mut outputs = Vec::new();
+        for (vout, txout) in built_tx.transaction.output.iter().enumerate() {
+            if txout.script_pubkey == to_local_script {
+                outputs.push(JusticeOutput {
+                    vout: vout as u32,
+                    value_sat: txout.value,
+                    kind: JusticeOutputKind::ToLocal,
+                });
+            }
+        }
+        for htlc in trusted_tx.htlcs() {
+            if let Some(vout) = htlc.transaction_output_index {
+                let info = HTLCInfo2 {
+                    value_sat: htlc.amount_msat / 1000,
+                    payment_hash: htlc.payment_hash,
+                    cltv_expiry: htlc.cltv_expiry,
+                };
+                let kind = if htlc.offered {
+                    JusticeOutputKind::OfferedHtlc(info)
+                } else {
+                    JusticeOutputKind::ReceivedHtlc(info)
+                };
+                let value_sat = built_tx.transaction.output[vout as usize].value;
+                outputs.push(JusticeOutput { vout, value_sat, kind });
+            }
+        }
+        Some((built_tx.txid, outputs))
+    }
+
+    /// The per-commitment secret the counterparty revealed when revoking
+    /// commitment `commit_num`, for use in justice transactions
+    pub fn get_counterparty_revocation_secret(&self, commit_num: u64) -> Option<SecretKey> {
+        self.enforcement_state.get_counterparty_revocation_secret(commit_num)
+    }
+
     /// Phase 1
     pub fn sign_mutual_close_tx(
         &mut self,
@@ -1801,21 +3131,24 @@ impl Channel {
             )));
         }
 
-        let recomposed_tx = self.validator().decode_and_validate_mutual_close_tx(
-            &*self.get_node(),
-            &self.setup,
-            &self.enforcement_state,
-            tx,
-            opaths,
-        )?;
+        let audit = AuditRecord::for_tx(Some(self.id0), "sign_mutual_close_tx", None, tx);
+        let node = self.get_node();
+        let recomposed_tx = self
+            .validator()
+            .decode_and_validate_mutual_close_tx(
+                &*node,
+                &self.setup,
+                &self.enforcement_state,
+                tx,
+                opaths,
+            )
+            .map_err(|ve| node.audit_failure(&audit, ve))?;
 
-        let sig = self
-            .keys
-            .sign_closing_transaction(&recomposed_tx, &self.secp_ctx)
-            .map_err(|_| Status::internal("failed to sign"))?;
+        let sig = self.sign_closing(&recomposed_tx)?;
         self.enforcement_state.mutual_close_signed = true;
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
+        node.append_audit_record(audit)?;
         Ok(sig)
     }
 
@@ -1884,8 +3217,11 @@ impl Channel {
         is_counterparty: bool,
         txkeys: TxCreationKeys,
     ) -> Result<TypedSignature, Status> {
-        let (feerate_per_kw, htlc, recomposed_tx_sighash, sighashtype) =
-            self.validator().decode_and_validate_htlc_tx(
+        let audit = AuditRecord::for_tx(Some(self.id0), "sign_htlc_tx", None, tx);
+        let node = self.get_node();
+        let (feerate_per_kw, htlc, recomposed_tx_sighash, sighashtype) = self
+            .validator()
+            .decode_and_validate_htlc_tx(
                 is_counterparty,
                 &self.setup,
                 &txkeys,
@@ -1893,7 +3229,8 @@ impl Channel {
                 &redeemscript,
                 htlc_amount_sat,
                 output_witscript,
-            )?;
+            )
+            .map_err(|ve| node.audit_failure(&audit, ve))?;
 
         self.validator()
             .validate_htlc_tx(
@@ -1920,7 +3257,7 @@ impl Channel {
                     DebugHTLCOutputInCommitment(&htlc),
                     feerate_per_kw,
                 );
-                ve
+                node.audit_failure(&audit, ve)
             })?;
 
         let htlc_privkey =
@@ -1930,19 +3267,29 @@ impl Channel {
         let htlc_sighash = Message::from_slice(&recomposed_tx_sighash[..])
             .map_err(|_| Status::internal("failed to sighash recomposed"))?;
 
-        Ok(TypedSignature {
-            sig: self.secp_ctx.sign(&htlc_sighash, &htlc_privkey),
-            typ: sighashtype,
-        })
+        let sig = self.sign_ecdsa(&htlc_sighash, &htlc_privkey)?;
+        node.append_audit_record(audit)?;
+        Ok(TypedSignature { sig, typ: sighashtype })
     }
 
     /// Get the unilateral close key and the witness stack suffix,
-    /// for sweeping the to-remote output of a counterparty's force-close
-    // TODO(devrandom) key leaking from this layer
+    /// for sweeping the to-remote output of a counterparty's force-close.
+    /// Use [Channel::sign_counterparty_payment_sweep] or
+    /// [Node::sign_onchain_tx] to sign with it instead.
+    #[cfg(feature = "secret_access")]
     pub fn get_unilateral_close_key(
         &self,
         commitment_point_opt: &Option<PublicKey>,
         revocation_pubkey: &Option<PublicKey>,
+    ) -> Result<(SecretKey, Vec<Vec<u8>>), Status> {
+        self.unilateral_close_key(commitment_point_opt, revocation_pubkey)
+    }
+
+    // The unilateral close key and the witness stack suffix
+    pub(crate) fn unilateral_close_key(
+        &self,
+        commitment_point_opt: &Option<PublicKey>,
+        revocation_pubkey: &Option<PublicKey>,
     ) -> Result<(SecretKey, Vec<Vec<u8>>), Status> {
         Ok(match commitment_point_opt {
             Some(commitment_point) => {
@@ -1997,3 +3344,15 @@ pub fn channel_nonce_to_id(nonce: &Vec<u8>) -> ChannelId {
     let hash = Sha256Hash::hash(nonce);
     ChannelId(hash.into_inner())
 }
+
+/// The channel nonce of a channel, from the peer's node ID and the
+/// channel's database ID, as CLN derives its per-peer seed.
+///
+/// With the native key derivation style, the channel keys are then the
+/// same as CLN's, and can be recovered from the node seed and this channel
+/// metadata alone.
+pub fn channel_nonce_from_peer_dbid(peer_id: &PublicKey, dbid: u64) -> Vec<u8> {
+    let mut nonce = peer_id.serialize().to_vec();
+    nonce.extend_from_slice(&dbid.to_le_bytes());
+    nonce
+}
@@ -0,0 +1,117 @@
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::Script;
+
+use crate::channel::ChannelId;
+use crate::prelude::*;
+
+/// A mutual close in a [ClosePlan]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedClose {
+    /// The channel to close
+    pub channel_id: ChannelId,
+    /// Our output value
+    pub to_holder_value_sat: u64,
+    /// The counterparty's output value
+    pub to_counterparty_value_sat: u64,
+    /// Our output script
+    pub holder_script: Option<Script>,
+    /// The counterparty's output script
+    pub counterparty_script: Option<Script>,
+    /// The wallet path of our output script, if it is in our wallet
+    pub holder_wallet_path_hint: Vec<u32>,
+    /// Whether the closing transaction was signed
+    pub signed: bool,
+}
+
+/// A plan to mutually close a set of channels, for the orderly wind-down
+/// of a node.
+///
+/// The plan is validated as a whole, including aggregate checks on the
+/// closing fees and on the value leaving our wallet, and is approved by
+/// the operator once.  It is persisted, so that signing can resume after
+/// a restart.  See [crate::node::Node::plan_mutual_closes].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClosePlan {
+    /// The plan ID, a hash of the planned closes
+    pub id: [u8; 32],
+    /// The planned closes
+    pub closes: Vec<PlannedClose>,
+    /// The sum of the closing fees in satoshi
+    pub fee_sat: u64,
+    /// The value in satoshi sent to destinations outside our wallet
+    pub non_wallet_sat: u64,
+    /// Whether the operator approved the plan
+    pub approved: bool,
+}
+
+impl ClosePlan {
+    /// Create an unapproved plan
+    pub fn new(closes: Vec<PlannedClose>, fee_sat: u64, non_wallet_sat: u64) -> Self {
+        ClosePlan {
+            id: Self::compute_id(&closes),
+            closes,
+            fee_sat,
+            non_wallet_sat,
+            approved: false,
+        }
+    }
+
+    /// The plan ID of a set of closes, the SHA256 of their channel IDs,
+    /// values, scripts and wallet paths
+    pub fn compute_id(closes: &[PlannedClose]) -> [u8; 32] {
+        let mut engine = Sha256Hash::engine();
+        for close in closes {
+            engine.input(&close.channel_id.0);
+            engine.input(&close.to_holder_value_sat.to_be_bytes());
+            engine.input(&close.to_counterparty_value_sat.to_be_bytes());
+            for script in [&close.holder_script, &close.counterparty_script].iter() {
+                let bytes = script.as_ref().map(|s| s.to_bytes()).unwrap_or_default();
+                engine.input(&(bytes.len() as u32).to_be_bytes());
+                engine.input(&bytes);
+            }
+            engine.input(&(close.holder_wallet_path_hint.len() as u32).to_be_bytes());
+            for index in &close.holder_wallet_path_hint {
+                engine.input(&index.to_be_bytes());
+            }
+        }
+        Sha256Hash::from_engine(engine).into_inner()
+    }
+
+    /// Whether all the closes were signed
+    pub fn is_complete(&self) -> bool {
+        self.closes.iter().all(|c| c.signed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(channel_byte: u8, to_holder_value_sat: u64) -> PlannedClose {
+        PlannedClose {
+            channel_id: ChannelId([channel_byte; 32]),
+            to_holder_value_sat,
+            to_counterparty_value_sat: 1000,
+            holder_script: Some(Script::new()),
+            counterparty_script: None,
+            holder_wallet_path_hint: vec![7],
+            signed: false,
+        }
+    }
+
+    #[test]
+    fn close_plan_id_test() {
+        let plan = ClosePlan::new(vec![close(1, 2000), close(2, 3000)], 500, 0);
+        assert!(!plan.approved);
+        assert!(!plan.is_complete());
+        assert_eq!(plan.id, ClosePlan::compute_id(&plan.closes));
+        assert_ne!(plan.id, ClosePlan::new(vec![close(1, 2000), close(2, 3001)], 499, 0).id);
+        assert_ne!(plan.id, ClosePlan::new(vec![close(2, 3000), close(1, 2000)], 500, 0).id);
+
+        // the signing progress is not part of the ID
+        let mut closes = plan.closes.clone();
+        closes[0].signed = true;
+        assert_eq!(ClosePlan::compute_id(&closes), plan.id);
+    }
+}
@@ -27,6 +27,7 @@ extern crate tonic;
 pub use bitcoin;
 pub use lightning;
 pub use lightning_invoice;
+pub use vls_policy;
 
 /// Chain tracking and validation
 pub mod chain;
@@ -35,6 +36,10 @@ pub mod chain;
 pub mod util;
 /// Channel
 pub mod channel;
+/// Multi-channel mutual close plans
+pub mod close_plan;
+/// Migration of a node to a new seed
+pub mod migration;
 /// Channel on-chain monitor
 pub mod monitor;
 /// Node
@@ -49,6 +54,8 @@ pub mod signer;
 pub mod tx;
 /// Layer-1 wallet
 pub mod wallet;
+/// Justice data export for watchtowers
+pub mod watchtower;
 
 #[cfg(not(feature = "std"))]
 mod io_extras {
@@ -127,6 +134,8 @@ mod sync;
 #[cfg(test)]
 mod ready_channel_tests;
 #[cfg(test)]
+mod sign_close_plan_tests;
+#[cfg(test)]
 mod sign_counterparty_commitment_tests;
 #[cfg(test)]
 mod sign_counterparty_htlc_sweep_tests;
@@ -137,11 +146,17 @@ mod sign_holder_commitment_tests;
 #[cfg(test)]
 mod sign_htlc_tx_tests;
 #[cfg(test)]
+mod sign_interactive_funding_tests;
+#[cfg(test)]
 mod sign_justice_sweep_tests;
 #[cfg(test)]
 mod sign_mutual_close_tests;
 #[cfg(test)]
 mod sign_onchain_tx_tests;
+#[cfg(all(test, feature = "simple_close"))]
+mod sign_simple_close_tests;
+#[cfg(test)]
+mod splice_tests;
 #[cfg(test)]
 mod validate_counterparty_revocation_tests;
 #[cfg(test)]
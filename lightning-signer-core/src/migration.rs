@@ -0,0 +1,51 @@
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::secp256k1::{PublicKey, Signature};
+use bitcoin::util::bip32::ExtendedPubKey;
+use bitcoin::{Transaction, Txid};
+
+use crate::prelude::*;
+
+/// A plan to move a node to a new seed, after a suspected compromise of
+/// the old seed.
+///
+/// The layer-1 wallet outputs of the old node are swept to the wallet of
+/// the new node, in a transaction signed with the old wallet keys.  The
+/// old node keeps its channel keys, and keeps signing for its channels
+/// until they close, so that the channels don't have to be closed at once.
+/// See [crate::node::Node::plan_seed_migration].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MigrationPlan {
+    /// The old node
+    pub old_node_id: PublicKey,
+    /// The node with the new seed
+    pub new_node_id: PublicKey,
+    /// The layer-1 xpub of the new node
+    pub new_account_xpub: ExtendedPubKey,
+    /// The sweep of the old wallet outputs to the new wallet, with witnesses
+    pub sweep_tx: Transaction,
+    /// The fee of the sweep in satoshi
+    pub fee_sat: u64,
+    /// The signature of the old node key on [MigrationPlan::digest], which
+    /// shows that the old node handed over to the new node
+    pub signature: Signature,
+}
+
+impl MigrationPlan {
+    /// The digest signed by the old node, the SHA256 of a tag, the node IDs,
+    /// the new layer-1 xpub and the sweep txid
+    pub fn digest(
+        old_node_id: &PublicKey,
+        new_node_id: &PublicKey,
+        new_account_xpub: &ExtendedPubKey,
+        sweep_txid: &Txid,
+    ) -> [u8; 32] {
+        let mut engine = Sha256Hash::engine();
+        engine.input("vls seed migration".as_bytes());
+        engine.input(&old_node_id.serialize());
+        engine.input(&new_node_id.serialize());
+        engine.input(&new_account_xpub.encode());
+        engine.input(&sweep_txid[..]);
+        Sha256Hash::from_engine(engine).into_inner()
+    }
+}
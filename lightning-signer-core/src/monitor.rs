@@ -1,12 +1,15 @@
 use alloc::collections::BTreeSet as Set;
+use core::iter::FromIterator;
 
 use bitcoin::{OutPoint, Transaction, Txid};
 
 use crate::bitcoin::hashes::_export::_core::cmp::Ordering;
 use crate::chain::tracker::ChainListener;
+use crate::channel::ChannelId;
+use crate::node::{Node, NodeEvent};
 use crate::policy::validator::ChainState;
 use crate::prelude::*;
-use crate::Arc;
+use crate::{Arc, Weak};
 
 /// State
 #[derive(Clone, Debug)]
@@ -28,6 +31,30 @@ pub struct State {
     pub funding_double_spent_height: Option<u32>,
     /// Number of confirmations of the closing transaction
     pub closing_height: Option<u32>,
+    /// The closing transaction, if it was a commitment transaction
+    pub closing_commitment_txid: Option<Txid>,
+    /// The new funding outpoint of a splice in progress
+    pub splice_outpoint: Option<OutPoint>,
+    /// Number of confirmations of the splice transaction
+    pub splice_height: Option<u32>,
+    /// The funding outpoint spent by the confirmed splice transaction
+    pub previous_funding_outpoint: Option<OutPoint>,
+    /// Whether the confirmed splice transaction was reorged-out
+    pub splice_reorged: bool,
+    /// The channel value registered before the channel was ready, if any
+    pub expected_funding_value_sat: Option<u64>,
+    /// The value of the confirmed funding output
+    pub funding_value_sat: Option<u64>,
+}
+
+// The channel's current commitments, for breach detection.
+// Not persisted - the channel supplies it when created or restored.
+#[derive(Default)]
+struct CommitmentWatch {
+    node: Weak<Node>,
+    channel_id: Option<ChannelId>,
+    obscure_factor: u64,
+    current_txids: OrderedSet<Txid>,
 }
 
 /// Keep track of channel on-chain events.
@@ -38,6 +65,7 @@ pub struct ChainMonitor {
     pub funding_outpoint: OutPoint,
     /// the monitor state
     pub state: Arc<Mutex<State>>,
+    watch: Arc<Mutex<CommitmentWatch>>,
 }
 
 impl Eq for ChainMonitor {}
@@ -73,14 +101,25 @@ impl ChainMonitor {
             funding_outpoint: None,
             funding_double_spent_height: None,
             closing_height: None,
+            closing_commitment_txid: None,
+            splice_outpoint: None,
+            splice_height: None,
+            previous_funding_outpoint: None,
+            splice_reorged: false,
+            expected_funding_value_sat: None,
+            funding_value_sat: None,
         };
 
-        Self { funding_outpoint, state: Arc::new(Mutex::new(state)) }
+        Self::new_from_persistence(funding_outpoint, state)
     }
 
     /// recreate this monitor after restoring from persistence
     pub fn new_from_persistence(funding_outpoint: OutPoint, state: State) -> Self {
-        Self { funding_outpoint, state: Arc::new(Mutex::new(state)) }
+        Self {
+            funding_outpoint,
+            state: Arc::new(Mutex::new(state)),
+            watch: Arc::new(Mutex::new(CommitmentWatch::default())),
+        }
     }
 
     /// Get the locked state
@@ -109,6 +148,48 @@ impl ChainMonitor {
         state.funding_inputs.extend(tx.input.iter().map(|i| i.previous_output));
     }
 
+    /// Record the channel value registered for the funding outpoint before
+    /// the channel is ready, see [Node::register_funding]
+    pub fn expect_funding_value(&self, value_sat: u64) {
+        self.state.lock().expect("lock").expected_funding_value_sat = Some(value_sat);
+    }
+
+    /// Add a splice transaction to keep track of.  Once it confirms, its
+    /// output at `vout` is the funding outpoint of the channel.
+    pub fn add_splice(&self, tx: &Transaction, vout: u32) {
+        let mut state = self.state.lock().expect("lock");
+        state.splice_outpoint = Some(OutPoint::new(tx.txid(), vout));
+        state.splice_height = None;
+        state.splice_reorged = false;
+    }
+
+    /// Stop tracking the splice transaction, after it was rolled back
+    pub fn remove_splice(&self) {
+        let mut state = self.state.lock().expect("lock");
+        state.splice_outpoint = None;
+        state.splice_height = None;
+        state.previous_funding_outpoint = None;
+        state.splice_reorged = false;
+    }
+
+    /// Returns the number of confirmations of the splice transaction, or zero
+    /// if it wasn't confirmed yet.
+    pub fn splice_depth(&self) -> u32 {
+        let state = self.state.lock().expect("lock");
+        state.splice_height.map(|h| state.height + 1 - h).unwrap_or(0)
+    }
+
+    /// Whether the splice transaction was confirmed and then reorged-out
+    pub fn splice_reorged(&self) -> bool {
+        self.state.lock().expect("lock").splice_reorged
+    }
+
+    /// Returns the height of the block that confirmed the funding transaction,
+    /// if any
+    pub fn funding_height(&self) -> Option<u32> {
+        self.state.lock().expect("lock").funding_height
+    }
+
     /// Returns the number of confirmations of the funding transaction, or zero
     /// if it wasn't confirmed yet.
     pub fn funding_depth(&self) -> u32 {
@@ -123,17 +204,93 @@ impl ChainMonitor {
         state.funding_double_spent_height.map(|h| state.height + 1 - h).unwrap_or(0)
     }
 
+    /// Returns the number of confirmations of the closing transaction, or
+    /// zero if the channel wasn't closed on-chain yet.
+    pub fn closing_depth(&self) -> u32 {
+        let state = self.state.lock().expect("lock");
+        state.closing_height.map(|h| state.height + 1 - h).unwrap_or(0)
+    }
+
+    /// Set the txids of the channel's current (unrevoked) commitment
+    /// transactions.
+    ///
+    /// A confirmed commitment transaction spending the funding outpoint
+    /// that is not in this set is reported to the node as a
+    /// [NodeEvent::Breach].
+    pub(crate) fn set_current_commitments(
+        &self,
+        node: Weak<Node>,
+        channel_id: ChannelId,
+        obscure_factor: u64,
+        txids: Vec<Txid>,
+    ) {
+        let mut watch = self.watch.lock().expect("lock");
+        watch.node = node;
+        watch.channel_id = Some(channel_id);
+        watch.obscure_factor = obscure_factor;
+        watch.current_txids = OrderedSet::from_iter(txids);
+    }
+
+    /// The txid and height of the confirmed commitment transaction, if
+    /// the channel was closed unilaterally
+    pub fn closing_commitment(&self) -> Option<(Txid, u32)> {
+        let state = self.state.lock().expect("lock");
+        match (state.closing_commitment_txid, state.closing_height) {
+            (Some(txid), Some(height)) => Some((txid, height)),
+            _ => None,
+        }
+    }
+
+    // Commitment transactions are recognizable by the upper bytes of the
+    // locktime and sequence, which encode the obscured commitment number.
+    fn is_commitment_tx(tx: &Transaction) -> bool {
+        tx.input.len() == 1 && tx.lock_time >> 24 == 0x20 && tx.input[0].sequence >> 24 == 0x80
+    }
+
+    // Returns the (forward counting) commitment number
+    fn decode_commitment_number(tx: &Transaction, obscure_factor: u64) -> Option<u64> {
+        if !Self::is_commitment_tx(tx) {
+            return None;
+        }
+        let obscured =
+            ((tx.input[0].sequence as u64 & 0xffffff) << 24) | (tx.lock_time as u64 & 0xffffff);
+        Some(obscured ^ obscure_factor)
+    }
+
+    fn check_breach(&self, tx: &Transaction, height: u32) {
+        let watch = self.watch.lock().expect("lock");
+        let channel_id = match watch.channel_id {
+            Some(channel_id) => channel_id,
+            None => return,
+        };
+        let txid = tx.txid();
+        if watch.current_txids.contains(&txid) {
+            return;
+        }
+        // Not a commitment, such as a mutual close
+        let commit_num = match Self::decode_commitment_number(tx, watch.obscure_factor) {
+            Some(commit_num) => commit_num,
+            None => return,
+        };
+        if let Some(node) = watch.node.upgrade() {
+            node.notify_event(NodeEvent::Breach { channel_id, txid, commit_num, height });
+        }
+    }
+
     /// Convert to a ChainState, to be used for validation
     pub fn as_chain_state(&self) -> ChainState {
         let state = self.state.lock().expect("lock");
         ChainState {
             current_height: state.height,
             funding_depth: state.funding_height.map(|h| state.height + 1 - h).unwrap_or(0),
+            // The node adds the funding when it registers the monitor with the tracker
+            funding_watched: !state.funding_txids.is_empty(),
             funding_double_spent_depth: state
                 .funding_double_spent_height
                 .map(|h| state.height + 1 - h)
                 .unwrap_or(0),
             closing_depth: state.closing_height.map(|h| state.height + 1 - h).unwrap_or(0),
+            clock_skew_secs: None,
         }
     }
 }
@@ -157,6 +314,18 @@ impl ChainListener for ChainMonitor {
                 );
                 state.funding_height = Some(state.height);
                 state.funding_outpoint = Some(outpoint);
+                state.funding_value_sat = Some(tx.output[outpoint.vout as usize].value);
+                outpoints.push(outpoint);
+            } else if state.splice_outpoint.map(|o| o.txid) == Some(txid) {
+                // The splice tx was confirmed, and replaces the funding outpoint
+                let outpoint = state.splice_outpoint.expect("splice outpoint");
+                assert!(
+                    outpoint.vout < tx.output.len() as u32,
+                    "tx doesn't have splice output index"
+                );
+                state.previous_funding_outpoint = state.funding_outpoint;
+                state.funding_outpoint = Some(outpoint);
+                state.splice_height = Some(state.height);
                 outpoints.push(outpoint);
             } else if spent.iter().any(|i| state.funding_inputs.contains(&i)) {
                 // A funding input was spent, but no funding tx was confirmed,
@@ -170,6 +339,10 @@ impl ChainListener for ChainMonitor {
             } else if spent.iter().any(|i| Some(*i) == state.funding_outpoint) {
                 // Closed on-chain
                 state.closing_height = Some(state.height);
+                if Self::is_commitment_tx(tx) {
+                    state.closing_commitment_txid = Some(txid);
+                }
+                self.check_breach(tx, state.height);
             } else {
                 panic!("unknown tx confirmed")
             }
@@ -188,6 +361,13 @@ impl ChainListener for ChainMonitor {
                 assert_eq!(state.funding_height, Some(state.height));
                 state.funding_height = None;
                 state.funding_outpoint = None;
+                state.funding_value_sat = None;
+            } else if state.splice_outpoint.map(|o| o.txid) == Some(txid) {
+                // The splice tx was reorged-out, the channel must roll back
+                assert_eq!(state.splice_height, Some(state.height));
+                state.funding_outpoint = state.previous_funding_outpoint.take();
+                state.splice_height = None;
+                state.splice_reorged = true;
             } else if spent.iter().any(|i| state.funding_inputs.contains(&i)) {
                 // A funding double-spent was reorged-out
                 // we may have seen some other funding input double-spent, so
@@ -201,6 +381,7 @@ impl ChainListener for ChainMonitor {
                 // A closing tx was reorged-out
                 assert_eq!(state.closing_height, Some(state.height));
                 state.closing_height = None;
+                state.closing_commitment_txid = None;
             } else {
                 panic!("unknown reorged tx");
             }
@@ -213,6 +394,8 @@ impl SendSync for ChainMonitor {}
 
 #[cfg(test)]
 mod tests {
+    use bitcoin::TxIn;
+
     use crate::util::test_utils::*;
 
     use super::*;
@@ -227,12 +410,14 @@ mod tests {
         monitor.on_add_block(vec![&tx]);
         assert_eq!(monitor.funding_depth(), 1);
         assert_eq!(monitor.funding_double_spent_depth(), 0);
+        assert_eq!(monitor.get_state().funding_value_sat, Some(tx.output[0].value));
         monitor.on_add_block(vec![]);
         assert_eq!(monitor.funding_depth(), 2);
         monitor.on_remove_block(vec![]);
         assert_eq!(monitor.funding_depth(), 1);
         monitor.on_remove_block(vec![&tx]);
         assert_eq!(monitor.funding_depth(), 0);
+        assert_eq!(monitor.get_state().funding_value_sat, None);
         monitor.on_remove_block(vec![]);
         assert_eq!(monitor.funding_depth(), 0);
     }
@@ -258,4 +443,31 @@ mod tests {
         monitor.on_remove_block(vec![]);
         assert_eq!(monitor.funding_double_spent_depth(), 0);
     }
+
+    #[test]
+    fn test_splice() {
+        let tx = make_tx(vec![make_txin(1)]);
+        let outpoint = OutPoint::new(tx.txid(), 0);
+        let monitor = ChainMonitor::new(outpoint, 0);
+        monitor.add_funding(&tx, 0);
+        monitor.on_add_block(vec![&tx]);
+        let splice_tx = make_tx(vec![TxIn {
+            previous_output: outpoint,
+            script_sig: Default::default(),
+            sequence: 0,
+            witness: vec![],
+        }]);
+        let splice_outpoint = OutPoint::new(splice_tx.txid(), 0);
+        monitor.add_splice(&splice_tx, 0);
+        assert_eq!(monitor.on_add_block(vec![&splice_tx]), vec![splice_outpoint]);
+        assert_eq!(monitor.splice_depth(), 1);
+        assert_eq!(monitor.get_state().funding_outpoint, Some(splice_outpoint));
+        monitor.on_remove_block(vec![&splice_tx]);
+        assert_eq!(monitor.splice_depth(), 0);
+        assert!(monitor.splice_reorged());
+        assert_eq!(monitor.get_state().funding_outpoint, Some(outpoint));
+        monitor.remove_splice();
+        assert!(!monitor.splice_reorged());
+        assert_eq!(monitor.funding_depth(), 1);
+    }
 }
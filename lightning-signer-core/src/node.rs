@@ -3,12 +3,13 @@ use core::convert::TryInto;
 use core::fmt::{self, Debug, Formatter};
 use core::iter::FromIterator;
 use core::str::FromStr;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use core::time::Duration;
 
 use bitcoin;
 use bitcoin::bech32::{u5, FromBase32};
 use bitcoin::blockdata::constants::genesis_block;
-use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::hex::{FromHex, ToHex};
 use bitcoin::hashes::sha256::Hash as Sha256Hash;
 use bitcoin::hashes::sha256d::Hash as Sha256dHash;
 use bitcoin::hashes::Hash;
@@ -16,8 +17,10 @@ use bitcoin::secp256k1::ecdh::SharedSecret;
 use bitcoin::secp256k1::recovery::RecoverableSignature;
 use bitcoin::secp256k1::{schnorrsig, All, Message, PublicKey, Secp256k1, SecretKey, Signature};
 use bitcoin::util::bip143::SigHashCache;
-use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey};
-use bitcoin::{secp256k1, Address, Transaction, TxOut};
+use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey, Fingerprint, KeySource};
+use bitcoin::util::merkleblock::PartialMerkleTree;
+use bitcoin::util::psbt::{self, PartiallySignedTransaction};
+use bitcoin::{secp256k1, Address, BlockHash, BlockHeader, Transaction, TxIn, TxOut, Txid};
 use bitcoin::{Network, OutPoint, Script, SigHashType};
 use lightning::chain;
 use lightning::chain::keysinterface::{
@@ -33,33 +36,108 @@ use lightning::util::logger::Logger;
 use lightning_invoice::{Invoice, RawDataPart, RawHrp, RawInvoice, SignedRawInvoice};
 
 #[allow(unused_imports)]
-use log::{debug, info, trace, warn};
+use log::{debug, error, info, trace, warn};
 use secp256k1_xonly::XOnlyPublicKey;
+use zeroize::Zeroize;
 
-use crate::chain::tracker::ChainTracker;
-use crate::channel::{Channel, ChannelBase, ChannelId, ChannelSetup, ChannelSlot, ChannelStub};
+use crate::chain::tracker::{ChainTracker, Checkpoint};
+use crate::channel::{
+    channel_nonce_from_peer_dbid, channel_nonce_to_id, Channel, ChannelBase, ChannelId,
+    ChannelSetup, ChannelSlot, ChannelStub,
+};
+use crate::close_plan::{ClosePlan, PlannedClose};
+use crate::migration::MigrationPlan;
 use crate::monitor::ChainMonitor;
-use crate::persist::model::NodeEntry;
-use crate::persist::Persist;
+use crate::persist::model::{AuditRecord, NodeEntry, PaymentLedgerEntry, PaymentResolution};
+use crate::persist::{Persist, PersistBatch};
 use crate::policy::error::{policy_error, unbalanced_error, ValidationError};
-use crate::policy::validator::{BalanceDelta, ValidatorFactory};
-use crate::policy::validator::{EnforcementState, Validator};
+use crate::policy::validator::{BalanceDelta, ChainState, ValidatorFactory};
+use crate::policy::validator::{CoreTypes, EnforcementState, Validator};
 use crate::prelude::*;
 use crate::signer::my_keys_manager::{KeyDerivationStyle, MyKeysManager};
 use crate::sync::{Arc, Weak};
-use crate::tx::tx::PreimageMap;
-use crate::util::crypto_utils::signature_to_bitcoin_vec;
-use crate::util::status::{failed_precondition, internal_error, invalid_argument, Status};
-use crate::wallet::Wallet;
+use crate::tx::interactive::{InteractiveFunding, InteractiveInput, InteractiveOutput};
+use crate::tx::tx::{JusticeOutput, PreimageMap};
+use crate::util::crypto_utils::{
+    bip86_tweaked_keypair, payload_for_p2tr, sign_ecdsa_verified, signature_to_bitcoin_vec,
+    verify_schnorr,
+};
+use crate::util::status::{
+    failed_precondition, internal_error, invalid_argument, persist_error, Status,
+};
+use crate::util::transaction_utils::{taproot_key_spend_sighash, MIN_DUST_LIMIT_SATOSHIS};
+use crate::wallet::{add_descriptor_checksum, Wallet};
+use crate::watchtower::{WatchtowerBlob, WatchtowerExport, WatchtowerQueue};
+
+/// Maximum number of operator-defined metadata entries per node or channel
+pub const MAX_METADATA_ENTRIES: usize = 32;
+/// Maximum length of a metadata key, in bytes
+pub const MAX_METADATA_KEY_LEN: usize = 64;
+/// Maximum length of a metadata value, in bytes
+pub const MAX_METADATA_VALUE_LEN: usize = 256;
+/// Maximum number of value approval tokens held by a node, the oldest
+/// tokens are dropped first
+pub const MAX_VALUE_APPROVALS: usize = 16;
+
+// The BOLT 7 message types signed with the node key
+const NODE_ANNOUNCEMENT_TYPE: u16 = 257;
+const CHANNEL_UPDATE_TYPE: u16 = 258;
+
+/// The block signing challenge of the default signet
+const DEFAULT_SIGNET_CHALLENGE: &str = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+
+/// Chain parameters, for chains that are not fully identified by their
+/// network type - a custom signet, or a regtest chain with a non-standard
+/// genesis block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainParams {
+    /// The genesis block header
+    pub genesis: BlockHeader,
+    /// The block signing challenge, for a signet
+    pub signet_challenge: Option<Script>,
+}
+
+impl ChainParams {
+    /// The parameters of the standard chain of the network
+    pub fn for_network(network: Network) -> Self {
+        let signet_challenge = if network == Network::Signet {
+            Some(Script::from(
+                Vec::from_hex(DEFAULT_SIGNET_CHALLENGE).expect("default signet challenge"),
+            ))
+        } else {
+            None
+        };
+        ChainParams { genesis: genesis_block(network).header, signet_challenge }
+    }
+
+    /// The genesis block hash, which identifies the chain
+    pub fn genesis_hash(&self) -> BlockHash {
+        self.genesis.block_hash()
+    }
+}
 
 /// Node configuration parameters.
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct NodeConfig {
     /// The network type
     pub network: Network,
     /// The derivation style to use when deriving purpose-specific keys
     pub key_derivation_style: KeyDerivationStyle,
+    /// Non-standard chain parameters, or None for the standard chain of
+    /// the network.  These are persisted with the node.
+    pub chain_params: Option<ChainParams>,
+    /// A trusted block to start following the chain from when the node is
+    /// created, or None for the genesis block.  This is not persisted with
+    /// the node, since the chain tracker is.
+    pub checkpoint: Option<Checkpoint>,
+}
+
+impl NodeConfig {
+    /// The chain parameters in effect
+    pub fn chain_params(&self) -> ChainParams {
+        self.chain_params.clone().unwrap_or_else(|| ChainParams::for_network(self.network))
+    }
 }
 
 /// Invoice payment details and payment state
@@ -88,12 +166,26 @@ pub struct RoutedPayment {
     pub outgoing: OrderedMap<ChannelId, u64>,
     /// The preimage for the hash, filled in on success
     pub preimage: Option<PaymentPreimage>,
+    /// The heights at which outgoing attempts at this payment started.
+    /// Every attempt but the last one has failed.
+    pub attempt_heights: Vec<u32>,
 }
 
 impl RoutedPayment {
     /// Create an empty routed payment
     pub fn new() -> RoutedPayment {
-        RoutedPayment { incoming: OrderedMap::new(), outgoing: OrderedMap::new(), preimage: None }
+        RoutedPayment {
+            incoming: OrderedMap::new(),
+            outgoing: OrderedMap::new(),
+            preimage: None,
+            attempt_heights: Vec::new(),
+        }
+    }
+
+    /// Whether an outgoing amount on a channel starts a new attempt at the
+    /// payment, because nothing was outgoing before
+    pub fn is_new_attempt(&self, outgoing_amount: u64) -> bool {
+        outgoing_amount > 0 && !self.is_fulfilled() && self.outgoing.values().all(|a| *a == 0)
     }
 
     /// Whether we know the preimage, and therefore the incoming is claimable
@@ -149,6 +241,43 @@ pub struct NodeState {
     pub excess_amount: u64,
     /// Prefix for emitted logs lines
     pub log_prefix: String,
+    /// The payment ledger, linking payments to their invoices, indexed by
+    /// payment hash
+    pub ledger: OrderedMap<PaymentHash, PaymentLedgerEntry>,
+    // Ledger entries changed since they were last persisted
+    ledger_updates: UnorderedSet<PaymentHash>,
+    /// The heights and values of the recent on-chain spends from the
+    /// wallet, for the velocity policy
+    pub onchain_spends: Vec<(u32, u64)>,
+}
+
+// Spends older than this no longer count against the velocity policy
+const MAX_VELOCITY_WINDOW_BLOCKS: u32 = 2016;
+
+/// A clock skew sample expires once the chain moves this many blocks past
+/// the tip it was taken against, so that time-delay dependent operations
+/// need a recent [Node::heartbeat]
+pub const MAX_CLOCK_SKEW_SAMPLE_AGE_BLOCKS: u32 = 6;
+
+/// A cross-check of the host clock against the chain tip, taken by
+/// [Node::heartbeat]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClockSkewSample {
+    /// The host clock minus the tip timestamp, in seconds
+    pub skew_secs: i64,
+    /// The host clock when the sample was taken, in seconds since the UNIX epoch
+    pub sampled_at: u64,
+    /// The height of the tip the sample was taken against
+    pub height: u32,
+    /// Whether the skew was within the policy range
+    pub within_policy: bool,
+}
+
+impl ClockSkewSample {
+    /// Whether the sample is still current at chain height `height`
+    pub fn is_fresh(&self, height: u32) -> bool {
+        height.saturating_sub(self.height) <= MAX_CLOCK_SKEW_SAMPLE_AGE_BLOCKS
+    }
 }
 
 impl PreimageMap for NodeState {
@@ -166,6 +295,9 @@ impl NodeState {
             payments: Map::new(),
             excess_amount: 0,
             log_prefix: String::new(),
+            ledger: OrderedMap::new(),
+            ledger_updates: UnorderedSet::new(),
+            onchain_spends: Vec::new(),
         }
     }
 
@@ -176,9 +308,70 @@ impl NodeState {
             payments: self.payments,
             excess_amount: self.excess_amount,
             log_prefix,
+            ledger: self.ledger,
+            ledger_updates: self.ledger_updates,
+            onchain_spends: self.onchain_spends,
+        }
+    }
+
+    // Record a spend from the wallet for the velocity policy, and forget
+    // the spends that are too old to count
+    fn record_onchain_spend(&mut self, height: u32, value_sat: u64) {
+        self.onchain_spends.retain(|(h, _)| height.saturating_sub(*h) < MAX_VELOCITY_WINDOW_BLOCKS);
+        if value_sat > 0 {
+            self.onchain_spends.push((height, value_sat));
+        }
+    }
+
+    /// Record a payment in the ledger, replacing any previous entry for
+    /// its payment hash
+    pub fn record_payment(&mut self, entry: PaymentLedgerEntry) {
+        self.ledger_updates.insert(entry.payment_hash);
+        self.ledger.insert(entry.payment_hash, entry);
+    }
+
+    // Update the resolution of a payment in the ledger, if it is there
+    fn resolve_payment(&mut self, hash: &PaymentHash, resolution: PaymentResolution) {
+        if let Some(entry) = self.ledger.get_mut(hash) {
+            if entry.resolution != resolution {
+                debug!(
+                    "{} payment {} resolved {:?} -> {:?}",
+                    self.log_prefix,
+                    hash.0.to_hex(),
+                    entry.resolution,
+                    resolution
+                );
+                entry.resolution = resolution;
+                self.ledger_updates.insert(*hash);
+            }
+        }
+    }
+
+    /// Mark pending payments whose invoice expired before `now`, in seconds
+    /// since the UNIX epoch, as expired.  Payments with HTLCs in flight are
+    /// left pending.
+    pub fn expire_payments(&mut self, now: u64) {
+        let expired: Vec<PaymentHash> = self
+            .ledger
+            .values()
+            .filter(|e| e.resolution == PaymentResolution::Pending && e.expiry_time < now)
+            .filter(|e| {
+                self.payments.get(&e.payment_hash).map(|p| p.incoming_outgoing()).unwrap_or((0, 0))
+                    == (0, 0)
+            })
+            .map(|e| e.payment_hash)
+            .collect();
+        for hash in expired {
+            self.resolve_payment(&hash, PaymentResolution::Expired);
         }
     }
 
+    /// Take the ledger entries changed since the last call, for persisting
+    pub(crate) fn take_ledger_updates(&mut self) -> Vec<PaymentLedgerEntry> {
+        let updates = core::mem::replace(&mut self.ledger_updates, UnorderedSet::new());
+        updates.iter().filter_map(|hash| self.ledger.get(hash).cloned()).collect()
+    }
+
     #[cfg(test)]
     pub(crate) fn validate_and_apply_payments(
         &mut self,
@@ -186,13 +379,15 @@ impl NodeState {
         incoming_payment_summary: &Map<PaymentHash, u64>,
         outgoing_payment_summary: &Map<PaymentHash, u64>,
         balance_delta: &BalanceDelta,
-        validator: Arc<dyn Validator>,
+        validator: Arc<dyn Validator<CoreTypes>>,
     ) -> Result<(), ValidationError> {
+        let cstate = crate::util::test_utils::make_test_chain_state();
         self.validate_payments(
             channel_id,
             incoming_payment_summary,
             outgoing_payment_summary,
             balance_delta,
+            &cstate,
             validator.clone(),
         )?;
         self.apply_payments(
@@ -200,6 +395,7 @@ impl NodeState {
             incoming_payment_summary,
             outgoing_payment_summary,
             balance_delta,
+            &cstate,
             validator.clone(),
         );
         Ok(())
@@ -210,13 +406,15 @@ impl NodeState {
     /// - no overpayment for any invoice.
     /// - Sends without invoices (e.g. keysend) are only allowed if
     /// `policy.require_invoices` is false.
+    /// - a failing payment is not retried too often.
     pub fn validate_payments(
         &self,
         channel_id: &ChannelId,
         incoming_payment_summary: &Map<PaymentHash, u64>,
         outgoing_payment_summary: &Map<PaymentHash, u64>,
         balance_delta: &BalanceDelta,
-        validator: Arc<dyn Validator>,
+        cstate: &ChainState,
+        validator: Arc<dyn Validator<CoreTypes>>,
     ) -> Result<(), ValidationError> {
         debug!(
             "validating payments on channel {} - in {:?} out {:?}",
@@ -250,6 +448,17 @@ impl NodeState {
             return Err(unbalanced_error(unbalanced));
         }
 
+        for (hash, outgoing_for_chan) in outgoing_payment_summary.iter() {
+            if let Some(p) = self.payments.get(hash) {
+                if p.is_new_attempt(*outgoing_for_chan) {
+                    validator.validate_payment_retry(&p.attempt_heights, cstate)?;
+                    if let Some(entry) = self.ledger.get(hash) {
+                        validator.validate_payment_duplicate(entry)?;
+                    }
+                }
+            }
+        }
+
         if validator.enforce_balance() {
             info!(
                 "{} validate payments adjust excess {} +{} -{}",
@@ -278,7 +487,8 @@ impl NodeState {
         incoming_payment_summary: &Map<PaymentHash, u64>,
         outgoing_payment_summary: &Map<PaymentHash, u64>,
         balance_delta: &BalanceDelta,
-        validator: Arc<dyn Validator>,
+        cstate: &ChainState,
+        validator: Arc<dyn Validator<CoreTypes>>,
     ) {
         debug!("applying payments on channel {}", channel_id);
 
@@ -329,6 +539,7 @@ impl NodeState {
                 // to the excess_amount, because we set the preimage after the balance
                 // delta has already been calculated.
                 payment.preimage = Some(PaymentPreimage([0; 32]));
+                self.resolve_payment(hash, PaymentResolution::Fulfilled);
             }
             self.excess_amount = excess_amount;
         }
@@ -342,7 +553,21 @@ impl NodeState {
             let incoming = incoming_payment_summary.get(hash).map(|a| *a).unwrap_or(0);
             let outgoing = outgoing_payment_summary.get(hash).map(|a| *a).unwrap_or(0);
             let payment = self.payments.get_mut(hash).expect("created above");
+            let is_new_attempt = payment.is_new_attempt(outgoing);
+            if is_new_attempt {
+                payment.attempt_heights.push(cstate.current_height);
+            }
             payment.apply(channel_id, incoming, outgoing);
+            let is_failed = !payment.is_fulfilled()
+                && !payment.attempt_heights.is_empty()
+                && payment.incoming_outgoing().1 == 0;
+            if self.ledger.get(*hash).map(|e| e.outgoing).unwrap_or(false) {
+                if is_new_attempt {
+                    self.resolve_payment(hash, PaymentResolution::Pending);
+                } else if is_failed {
+                    self.resolve_payment(hash, PaymentResolution::Failed);
+                }
+            }
         }
     }
 
@@ -352,7 +577,7 @@ impl NodeState {
         &mut self,
         channel_id: &ChannelId,
         preimage: PaymentPreimage,
-        validator: Arc<dyn Validator>,
+        validator: Arc<dyn Validator<CoreTypes>>,
     ) {
         let payment_hash = PaymentHash(Sha256Hash::hash(&preimage.0).into_inner());
 
@@ -407,11 +632,19 @@ impl NodeState {
                     }
                 }
                 payment.preimage = Some(preimage);
+                self.resolve_payment(&payment_hash, PaymentResolution::Fulfilled);
             }
         }
     }
 }
 
+/// The number of addresses matched by an allowlisted xpub when no gap limit
+/// is specified
+pub const DEFAULT_ALLOWLIST_GAP_LIMIT: u32 = 20;
+/// The largest gap limit of an allowlisted xpub, bounding the work done
+/// when checking a destination
+pub const MAX_ALLOWLIST_GAP_LIMIT: u32 = 10_000;
+
 /// Allowlist entry
 #[derive(Eq, PartialEq, Hash, Clone)]
 pub enum Allowable {
@@ -419,6 +652,25 @@ pub enum Allowable {
     Script(Script),
     /// A layer-2 payee (node_id)
     Payee(PublicKey),
+    /// A range of layer-1 destinations - the native segwit addresses derived
+    /// from an extended public key at `path/i`, for `i < gap_limit`
+    XPub {
+        /// The extended public key, e.g. of a cold wallet account
+        xpub: ExtendedPubKey,
+        /// The non-hardened derivation path below the xpub
+        path: Vec<u32>,
+        /// The number of addresses in the range
+        gap_limit: u32,
+    },
+    /// A cold storage destination.  Unilateral close and justice sweeps of
+    /// more than `threshold_sat` must pay to a cold storage destination,
+    /// rather than to the wallet or to another allowlisted destination.
+    ColdStorage {
+        /// The amount of a swept output above which the rule applies
+        threshold_sat: u64,
+        /// An address or a range of xpub addresses
+        destination: Box<Allowable>,
+    },
 }
 
 /// Convert to String for a specified Bitcoin network type
@@ -437,6 +689,13 @@ impl ToStringForNetwork for Allowable {
                     .unwrap_or_else(|| format!("invalid_script:{}", script.to_hex()))
             }
             Allowable::Payee(pubkey) => format!("payee:{}", pubkey.to_hex()),
+            Allowable::XPub { xpub, path, gap_limit } => {
+                let path_str: String = path.iter().map(|i| format!("/{}", i)).collect();
+                format!("xpub:{}{}:{}", xpub, path_str, gap_limit)
+            }
+            Allowable::ColdStorage { threshold_sat, destination } => {
+                format!("cold:{}:{}", threshold_sat, destination.to_string(network))
+            }
         }
     }
 }
@@ -456,9 +715,37 @@ impl Allowable {
             } else if prefix == "payee" {
                 let pubkey = PublicKey::from_str(body).map_err(|_| s.to_string())?;
                 Ok(Allowable::Payee(pubkey))
+            } else if prefix == "xpub" {
+                // xpub:<xpub>[/<index>]*[:<gap_limit>]
+                let mut parts = body.splitn(2, ":");
+                let key_expr = parts.next().expect("splitn");
+                let gap_limit = match parts.next() {
+                    Some(limit) => limit.parse::<u32>().map_err(|_| s.to_string())?,
+                    None => DEFAULT_ALLOWLIST_GAP_LIMIT,
+                };
+                Self::xpub_from_str(s, key_expr, gap_limit, network)
+            } else if prefix == "cold" {
+                // cold:<threshold_sat>:<address or xpub entry>
+                let mut parts = body.splitn(2, ":");
+                let threshold_sat =
+                    parts.next().expect("splitn").parse::<u64>().map_err(|_| s.to_string())?;
+                let destination =
+                    Self::from_str(parts.next().ok_or_else(|| s.to_string())?, network)?;
+                match destination {
+                    Allowable::Script(_) | Allowable::XPub { .. } => Ok(Allowable::ColdStorage {
+                        threshold_sat,
+                        destination: Box::new(destination),
+                    }),
+                    _ => Err(format!("{}: cold storage must be a layer-1 destination", s)),
+                }
             } else {
                 Err(s.to_string())
             }
+        } else if let Some(key_expr) =
+            prefix.strip_prefix("wpkh(").and_then(|d| d.strip_suffix("/*)"))
+        {
+            // A native segwit output descriptor, wpkh(<xpub>[/<index>]*/*)
+            Self::xpub_from_str(s, key_expr, DEFAULT_ALLOWLIST_GAP_LIMIT, network)
         } else {
             let address = Address::from_str(prefix).map_err(|_| s.to_string())?;
             if address.network != network {
@@ -467,6 +754,181 @@ impl Allowable {
             Ok(Allowable::Script(address.script_pubkey()))
         }
     }
+
+    // Parse <xpub>[/<index>]*, where all indices are non-hardened
+    fn xpub_from_str(
+        s: &str,
+        key_expr: &str,
+        gap_limit: u32,
+        network: Network,
+    ) -> Result<Allowable, String> {
+        let mut splits = key_expr.split("/");
+        let xpub =
+            ExtendedPubKey::from_str(splits.next().expect("split")).map_err(|_| s.to_string())?;
+        // xpubs don't distinguish between the test networks
+        if (xpub.network == Network::Bitcoin) != (network == Network::Bitcoin) {
+            return Err(format!("{}: expected network {}", s, network));
+        }
+        let path = splits
+            .map(|i| match i.parse::<u32>() {
+                Ok(index) if ChildNumber::from_normal_idx(index).is_ok() => Ok(index),
+                _ => Err(format!("{}: bad derivation index {}", s, i)),
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+        if gap_limit == 0 || gap_limit > MAX_ALLOWLIST_GAP_LIMIT {
+            return Err(format!("{}: gap limit out of range", s));
+        }
+        Ok(Allowable::XPub { xpub, path, gap_limit })
+    }
+
+    /// True if this entry allows a layer-1 destination
+    pub fn matches_script<C: secp256k1::Verification>(
+        &self,
+        secp_ctx: &Secp256k1<C>,
+        script_pubkey: &Script,
+        network: Network,
+    ) -> bool {
+        match self {
+            Allowable::Script(script) => script == script_pubkey,
+            Allowable::Payee(_) => false,
+            Allowable::ColdStorage { destination, .. } => {
+                destination.matches_script(secp_ctx, script_pubkey, network)
+            }
+            Allowable::XPub { xpub, path, gap_limit } => {
+                let mut base = *xpub;
+                for index in path {
+                    match base.ckd_pub(secp_ctx, ChildNumber::Normal { index: *index }) {
+                        Ok(child) => base = child,
+                        Err(_) => return false,
+                    }
+                }
+                (0..*gap_limit).any(|index| {
+                    base.ckd_pub(secp_ctx, ChildNumber::Normal { index })
+                        .map(|child| {
+                            Address::p2wpkh(&child.public_key, network)
+                                .expect("p2wpkh failed")
+                                .script_pubkey()
+                                == *script_pubkey
+                        })
+                        .unwrap_or(false)
+                })
+            }
+        }
+    }
+}
+
+/// An event raised by the node's chain monitoring or channels
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeEvent {
+    /// A commitment transaction that is not one of the channel's current
+    /// commitments was confirmed.  This is most likely a revoked
+    /// counterparty commitment, which should be answered with a justice
+    /// transaction.
+    Breach {
+        /// The channel
+        channel_id: ChannelId,
+        /// The confirmed commitment transaction
+        txid: Txid,
+        /// The commitment number, decoded from the transaction
+        commit_num: u64,
+        /// The block height at which it was confirmed
+        height: u32,
+    },
+    /// The counterparty revoked a commitment transaction.  A justice
+    /// transaction for it can be built with
+    /// [Channel::build_and_sign_justice_tx], for example to hand to a
+    /// watchtower.
+    CounterpartyRevocation {
+        /// The channel
+        channel_id: ChannelId,
+        /// The revoked commitment transaction
+        txid: Txid,
+        /// The revoked commitment number
+        commit_num: u64,
+        /// The outputs of the revoked commitment that a justice transaction
+        /// can sweep
+        outputs: Vec<JusticeOutput>,
+    },
+    /// The chain source tried to unwind the chain tracker deeper than its
+    /// maximum reorg depth, or past the block it started from.  The reorg
+    /// was refused, and the operator must investigate before calling
+    /// [Node::clear_chain_suspect].
+    ChainSuspect {
+        /// The height of the tip that was not removed
+        height: u32,
+        /// The hash of the tip that was not removed
+        block_hash: BlockHash,
+    },
+    /// A heartbeat found the host clock too far from the chain tip
+    /// timestamp.  Either the host clock is wrong or the chain source is
+    /// feeding a stale chain, and time-based policies can't be trusted.
+    ClockSkew {
+        /// The host clock minus the tip timestamp, in seconds
+        clock_skew_secs: i64,
+        /// The height of the tip
+        height: u32,
+    },
+}
+
+/// Receives node events
+///
+/// Listeners are called while the chain tracker or a channel is locked,
+/// so they must not call back into the node.
+pub trait NodeEventListener: SendSync {
+    /// Called on each event
+    fn on_event(&self, event: &NodeEvent);
+}
+
+/// The signer-side state of a node, for inspection by operators
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeStatus {
+    /// The height of the chain tracker
+    pub height: u32,
+    /// The tip of the chain tracker
+    pub tip: BlockHash,
+    /// The channels that are not ready yet
+    pub stub_channels: usize,
+    /// The ready channels
+    pub ready_channels: usize,
+    /// The channels with a confirmed commitment transaction
+    pub closing_channels: usize,
+    /// The channels with a deeply confirmed closing transaction
+    pub closed_channels: usize,
+}
+
+/// A discrepancy between the channels of a node and the watches of its
+/// chain tracker, see [Node::check_tracker_watches]
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrackerDiscrepancy {
+    /// An open or closing channel was not watched by the tracker.
+    /// A watch was added for it, but on-chain events before now were missed.
+    UnwatchedChannel {
+        /// The channel
+        channel_id: ChannelId,
+        /// The funding outpoint of the channel
+        funding_outpoint: OutPoint,
+    },
+    /// The tracker watches a channel that does not exist
+    OrphanedWatch {
+        /// The funding outpoint of the watch
+        funding_outpoint: OutPoint,
+    },
+}
+
+// The number of signing operations and of policy violations recorded in
+// the audit logs
+static SIGN_OPERATIONS: AtomicUsize = AtomicUsize::new(0);
+static POLICY_VIOLATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of signing operations since startup, across all nodes
+pub fn sign_operations() -> usize {
+    SIGN_OPERATIONS.load(Ordering::Relaxed)
+}
+
+/// The number of signing operations refused by policy since startup,
+/// across all nodes
+pub fn policy_violations() -> usize {
+    POLICY_VIOLATIONS.load(Ordering::Relaxed)
 }
 
 /// A signer for one Lightning node.
@@ -486,7 +948,7 @@ impl Allowable {
 /// let seed = [0; 32];
 /// let config = TEST_NODE_CONFIG;
 /// let validator_factory = Arc::new(SimpleValidatorFactory::new());
-/// let node = Arc::new(Node::new(config, &seed, &persister, vec![], validator_factory));
+/// let node = Arc::new(Node::new(config, &seed, &persister, vec![], validator_factory).unwrap());
 /// let (channel_id, opt_stub) = node.new_channel(None, None, &node).expect("new channel");
 /// assert!(opt_stub.is_some());
 /// let channel_slot_mutex = node.get_channel(&channel_id).expect("get channel");
@@ -496,18 +958,35 @@ impl Allowable {
 ///         // Do things with the stub, such as readying it or getting the points
 ///         let holder_basepoints = stub.get_channel_basepoints();
 ///     }
-///     ChannelSlot::Ready(_) => panic!("expected a stub")
+///     _ => panic!("expected a stub")
 /// }
 /// ```
 pub struct Node {
     pub(crate) node_config: NodeConfig,
     pub(crate) keys_manager: MyKeysManager,
     channels: Mutex<OrderedMap<ChannelId, Arc<Mutex<ChannelSlot>>>>,
-    pub(crate) validator_factory: Mutex<Arc<dyn ValidatorFactory>>,
+    pub(crate) validator_factory: Mutex<Arc<dyn ValidatorFactory<CoreTypes>>>,
     pub(crate) persister: Arc<dyn Persist>,
     allowlist: Mutex<UnorderedSet<Allowable>>,
     tracker: Mutex<ChainTracker<ChainMonitor>>,
     pub(crate) state: Mutex<NodeState>,
+    // the last heartbeat cross-check of the host clock, not persisted
+    clock_skew: Mutex<Option<ClockSkewSample>>,
+    event_listeners: Mutex<Vec<Arc<dyn NodeEventListener>>>,
+    // interactive funding constructions in progress, not persisted
+    interactive_fundings: Mutex<OrderedMap<ChannelId, InteractiveFunding>>,
+    // value approval tokens, oldest first, not persisted
+    value_approvals: Mutex<Vec<[u8; 32]>>,
+    // the multi-channel close plan in progress, if any
+    close_plan: Mutex<Option<ClosePlan>>,
+    // the hash of the last audit record of each chain, loaded on first use
+    audit_heads: Mutex<OrderedMap<Option<ChannelId>, [u8; 32]>>,
+    // wallet UTXOs reserved for fee bumping
+    fee_reserve: Mutex<OrderedSet<OutPoint>>,
+    // how justice transactions are built for watchtowers, if exported
+    watchtower_export: Mutex<Option<WatchtowerExport>>,
+    // the exported watchtower blobs, not persisted
+    watchtower_blobs: Mutex<WatchtowerQueue>,
 }
 
 impl Wallet for Node {
@@ -520,12 +999,14 @@ impl Wallet for Node {
         let secp_ctx = Secp256k1::signing_only();
         let pubkey = self.get_wallet_pubkey(&secp_ctx, child_path)?;
 
-        // Lightning layer-1 wallets can spend native segwit or wrapped segwit addresses.
+        // Lightning layer-1 wallets can spend native segwit or wrapped segwit addresses,
+        // and taproot outputs of the same keys.
         let native_addr = Address::p2wpkh(&pubkey, self.network()).expect("p2wpkh failed");
         let wrapped_addr = Address::p2shwpkh(&pubkey, self.network()).expect("p2shwpkh failed");
 
         Ok(*script_pubkey == native_addr.script_pubkey()
-            || *script_pubkey == wrapped_addr.script_pubkey())
+            || *script_pubkey == wrapped_addr.script_pubkey()
+            || *script_pubkey == self.get_taproot_script_pubkey(child_path)?)
     }
 
     fn get_native_address(&self, child_path: &Vec<u32>) -> Result<Address, Status> {
@@ -548,14 +1029,54 @@ impl Wallet for Node {
         Ok(Address::p2shwpkh(&pubkey, self.network()).expect("p2wpkh failed"))
     }
 
-    /// Returns true if script_pubkey is in the node's allowlist.
+    fn get_taproot_script_pubkey(&self, child_path: &Vec<u32>) -> Result<Script, Status> {
+        if child_path.len() == 0 {
+            return Err(invalid_argument("empty child path"));
+        }
+
+        let key = self.get_wallet_privkey(&Secp256k1::signing_only(), child_path)?;
+        let keypair = bip86_tweaked_keypair(&Secp256k1::new(), &key.key);
+        Ok(payload_for_p2tr(&XOnlyPublicKey::from_keypair(&keypair)).script_pubkey())
+    }
+
+    /// Returns true if script_pubkey is in the node's allowlist, either
+    /// literally or derived from an allowlisted xpub.
     fn allowlist_contains(&self, script_pubkey: &Script) -> bool {
-        self.allowlist.lock().unwrap().contains(&Allowable::Script(script_pubkey.clone()))
+        let alset = self.allowlist.lock().unwrap();
+        if alset.contains(&Allowable::Script(script_pubkey.clone())) {
+            return true;
+        }
+        let secp_ctx = Secp256k1::verification_only();
+        alset.iter().any(|a| a.matches_script(&secp_ctx, script_pubkey, self.network()))
+    }
+
+    fn cold_storage_threshold(&self) -> Option<u64> {
+        let alset = self.allowlist.lock().unwrap();
+        alset
+            .iter()
+            .filter_map(|a| match a {
+                Allowable::ColdStorage { threshold_sat, .. } => Some(*threshold_sat),
+                _ => None,
+            })
+            .min()
+    }
+
+    fn cold_storage_contains(&self, script_pubkey: &Script) -> bool {
+        let alset = self.allowlist.lock().unwrap();
+        let secp_ctx = Secp256k1::verification_only();
+        alset.iter().any(|a| {
+            matches!(a, Allowable::ColdStorage { .. })
+                && a.matches_script(&secp_ctx, script_pubkey, self.network())
+        })
     }
 
     fn network(&self) -> Network {
         self.node_config.network
     }
+
+    fn has_value_approval(&self, token: &[u8; 32]) -> bool {
+        self.value_approvals.lock().unwrap().contains(token)
+    }
 }
 
 impl Node {
@@ -567,15 +1088,17 @@ impl Node {
         seed: &[u8],
         persister: &Arc<Persist>,
         allowlist: Vec<Allowable>,
-        validator_factory: Arc<dyn ValidatorFactory>,
-    ) -> Node {
-        let genesis = genesis_block(node_config.network);
+        validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
+    ) -> Result<Node, Status> {
+        let genesis = node_config.chain_params().genesis;
 
-        // TODO supply current tip
-        let tracker =
-            ChainTracker::new(node_config.network, 0, genesis.header).expect("bad  chain tip");
+        let tracker = match node_config.checkpoint {
+            Some(ref checkpoint) => ChainTracker::from_checkpoint(node_config.network, checkpoint),
+            None => ChainTracker::new(node_config.network, 0, genesis),
+        }
+        .map_err(|e| invalid_argument(format!("bad chain tip: {:?}", e)))?;
 
-        Self::new_extended(node_config, seed, persister, allowlist, tracker, validator_factory)
+        Ok(Self::new_extended(node_config, seed, persister, allowlist, tracker, validator_factory))
     }
 
     /// Create a node
@@ -587,7 +1110,7 @@ impl Node {
         persister: &Arc<dyn Persist>,
         allowlist: Vec<Allowable>,
         tracker: ChainTracker<ChainMonitor>,
-        validator_factory: Arc<dyn ValidatorFactory>,
+        validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
     ) -> Node {
         let state = NodeState::new();
         Self::new_from_persistence(
@@ -608,7 +1131,7 @@ impl Node {
         persister: &Arc<Persist>,
         allowlist: Vec<Allowable>,
         tracker: ChainTracker<ChainMonitor>,
-        validator_factory: Arc<dyn ValidatorFactory>,
+        validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
         state: NodeState,
     ) -> Node {
         let genesis = genesis_block(node_config.network);
@@ -634,6 +1157,17 @@ impl Node {
             allowlist: Mutex::new(UnorderedSet::from_iter(allowlist)),
             tracker: Mutex::new(tracker),
             state,
+            clock_skew: Mutex::new(None),
+            event_listeners: Mutex::new(Vec::new()),
+            interactive_fundings: Mutex::new(OrderedMap::new()),
+            value_approvals: Mutex::new(Vec::new()),
+            close_plan: Mutex::new(persister.get_close_plan(&node_id).expect("close plan")),
+            audit_heads: Mutex::new(OrderedMap::new()),
+            fee_reserve: Mutex::new(OrderedSet::from_iter(
+                persister.get_fee_reserve(&node_id).expect("fee reserve"),
+            )),
+            watchtower_export: Mutex::new(None),
+            watchtower_blobs: Mutex::new(WatchtowerQueue::new()),
         }
     }
 
@@ -650,13 +1184,28 @@ impl Node {
         merkleroot: &[u8; 32],
         publictweak_opt: Option<&[u8]>,
     ) -> Result<schnorrsig::Signature, Status> {
-        self.keys_manager
+        let (sig, message, pubkey) = self
+            .keys_manager
             .sign_bolt12(messagename, fieldname, merkleroot, publictweak_opt)
-            .map_err(|_| internal_error("signature operation failed"))
+            .map_err(|_| internal_error("signature operation failed"))?;
+        if self.verify_signatures() {
+            verify_schnorr(&message, &sig, &pubkey)?;
+        }
+        Ok(sig)
+    }
+
+    // Whether the policy asks for signatures to be verified before release
+    fn verify_signatures(&self) -> bool {
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            None,
+        );
+        validator.verify_signatures()
     }
 
     /// Set the node's validator factory
-    pub fn set_validator_factory(&self, validator_factory: Arc<dyn ValidatorFactory>) {
+    pub fn set_validator_factory(&self, validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>) {
         let mut vfac = self.validator_factory.lock().unwrap();
         *vfac = validator_factory;
     }
@@ -713,7 +1262,8 @@ impl Node {
         let mut slot = slot_arc.lock().unwrap();
         let base = match &mut *slot {
             ChannelSlot::Stub(stub) => stub as &mut ChannelBase,
-            ChannelSlot::Ready(chan) => chan as &mut ChannelBase,
+            ChannelSlot::Ready(chan) | ChannelSlot::Closing(chan) | ChannelSlot::Closed(chan) =>
+                chan as &mut ChannelBase,
         };
         f(base)
     }
@@ -721,17 +1271,207 @@ impl Node {
     /// Execute a function with an existing ready channel.
     ///
     /// An invalid_argument [Status] will be returned if the channel does not exist.
+    /// A failed_precondition [Status] will be returned if the channel is closing
+    /// or closed.
     pub fn with_ready_channel<F: Sized, T>(&self, channel_id: &ChannelId, f: F) -> Result<T, Status>
     where
         F: Fn(&mut Channel) -> Result<T, Status>,
     {
         let slot_arc = self.get_channel(channel_id)?;
         let mut slot = slot_arc.lock().unwrap();
+        Self::update_channel_state(&mut slot)?;
         match &mut *slot {
             ChannelSlot::Stub(_) =>
                 Err(invalid_argument(format!("channel not ready: {}", &channel_id))),
             ChannelSlot::Ready(chan) => f(chan),
+            ChannelSlot::Closing(_) =>
+                Err(failed_precondition(format!("channel is closing: {}", &channel_id))),
+            ChannelSlot::Closed(_) =>
+                Err(failed_precondition(format!("channel is closed: {}", &channel_id))),
+        }
+    }
+
+    /// Execute a function with an existing ready or closing channel.
+    ///
+    /// Use this for operations that are still allowed after a commitment
+    /// transaction was confirmed, such as sweeps.
+    /// An invalid_argument [Status] will be returned if the channel does not exist.
+    /// A failed_precondition [Status] will be returned if the channel is closed.
+    pub fn with_ready_or_closing_channel<F: Sized, T>(
+        &self,
+        channel_id: &ChannelId,
+        f: F,
+    ) -> Result<T, Status>
+    where
+        F: Fn(&mut Channel) -> Result<T, Status>,
+    {
+        let slot_arc = self.get_channel(channel_id)?;
+        let mut slot = slot_arc.lock().unwrap();
+        Self::update_channel_state(&mut slot)?;
+        match &mut *slot {
+            ChannelSlot::Stub(_) =>
+                Err(invalid_argument(format!("channel not ready: {}", &channel_id))),
+            ChannelSlot::Ready(chan) | ChannelSlot::Closing(chan) => f(chan),
+            ChannelSlot::Closed(_) =>
+                Err(failed_precondition(format!("channel is closed: {}", &channel_id))),
+        }
+    }
+
+    // Advance the channel state based on what the chain monitor has seen.
+    // A confirmed commitment transaction moves a ready channel to closing,
+    // and a deeply confirmed closing transaction moves it to closed.  These
+    // states are kept even if the transactions are later reorged out.
+    // A splice that was reorged-out before it was locked is rolled back.
+    fn update_channel_state(slot: &mut ChannelSlot) -> Result<(), Status> {
+        if let ChannelSlot::Ready(chan) = slot {
+            if chan.monitor.splice_reorged() && chan.enforcement_state.splice.is_some() {
+                chan.rollback_splice()?;
+            }
+            if let Some((txid, height)) = chan.monitor.closing_commitment() {
+                chan.set_closing(txid, height)?;
+                // TODO this clone is expensive
+                let chan = chan.clone();
+                *slot = ChannelSlot::Closing(chan);
+            }
+        }
+        let closed_chan = match slot {
+            ChannelSlot::Ready(chan) | ChannelSlot::Closing(chan) if chan.is_closed() =>
+                Some(chan.clone()),
+            _ => None,
+        };
+        if let Some(chan) = closed_chan {
+            info!("{}: closing transaction is deeply confirmed, channel is closed", chan.id());
+            *slot = ChannelSlot::Closed(chan);
+        }
+        Ok(())
+    }
+
+    /// Delete closed channels whose closing transaction has at least
+    /// `min_depth` confirmations, from memory and from the persister.
+    ///
+    /// This keeps the state bounded over a long period of operation.
+    /// Returns the initial IDs of the pruned channels.
+    pub fn prune_closed_channels(&self, min_depth: u32) -> Result<Vec<ChannelId>, Status> {
+        let mut tracker = self.tracker.lock().unwrap();
+        let mut channels = self.channels.lock().unwrap();
+        let mut pruned = Vec::new();
+        for (channel_id, slot_arc) in channels.iter() {
+            let mut slot = slot_arc.lock().unwrap();
+            Self::update_channel_state(&mut slot)?;
+            if let ChannelSlot::Closed(chan) = &*slot {
+                // A channel may be in the map under two IDs
+                if chan.id0 == *channel_id && chan.monitor.closing_depth() >= min_depth {
+                    pruned.push((chan.id0, chan.id, chan.monitor.clone()));
+                }
+            }
+        }
+        if pruned.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let node_id = self.get_id();
+        let mut pruned_ids = Vec::new();
+        for (id0, id, monitor) in pruned {
+            info!("{}: pruning closed channel", id0);
+            channels.remove(&id0);
+            id.map(|id| channels.remove(&id));
+            tracker.remove_listener(&monitor);
+            self.persister
+                .delete_channel(&node_id, &id0)
+                .map_err(|e| persist_error("channel delete failed", e))?;
+            pruned_ids.push(id0);
+        }
+        self.persister
+            .update_tracker(&node_id, &tracker)
+            .map_err(|e| persist_error("tracker persist failed", e))?;
+        Ok(pruned_ids)
+    }
+
+    /// The signer-side state of the node
+    pub fn status(&self) -> NodeStatus {
+        let (height, tip) = {
+            let tracker = self.get_tracker();
+            (tracker.height(), tracker.tip().block_hash())
+        };
+        let mut status = NodeStatus {
+            height,
+            tip,
+            stub_channels: 0,
+            ready_channels: 0,
+            closing_channels: 0,
+            closed_channels: 0,
+        };
+        for (channel_id, slot_arc) in self.channels.lock().unwrap().iter() {
+            let slot = slot_arc.lock().unwrap();
+            // A channel may be in the map under two IDs
+            if slot.id() != *channel_id {
+                continue;
+            }
+            match &*slot {
+                ChannelSlot::Stub(_) => status.stub_channels += 1,
+                ChannelSlot::Ready(_) => status.ready_channels += 1,
+                ChannelSlot::Closing(_) => status.closing_channels += 1,
+                ChannelSlot::Closed(_) => status.closed_channels += 1,
+            }
+        }
+        status
+    }
+
+    /// The ready channels that are due for operator review under the
+    /// channel review policy, with the reason.
+    ///
+    /// Returns the initial IDs of the channels.  A review is acknowledged
+    /// with [Node::acknowledge_channel_review].
+    pub fn channels_due_for_review(&self) -> Vec<(ChannelId, String)> {
+        let channels = self.channels.lock().unwrap();
+        let mut due = Vec::new();
+        for (channel_id, slot_arc) in channels.iter() {
+            let slot = slot_arc.lock().unwrap();
+            if let ChannelSlot::Ready(chan) = &*slot {
+                // A channel may be in the map under two IDs
+                if chan.id0 != *channel_id {
+                    continue;
+                }
+                if let Some(intent) = &chan.enforcement_state.signing_intent {
+                    due.push((chan.id0, format!("incomplete {}", intent)));
+                    continue;
+                }
+                let cstate = chan.monitor.as_chain_state();
+                if let Some(reason) =
+                    chan.validator().channel_review_due(&chan.enforcement_state, &cstate)
+                {
+                    due.push((chan.id0, reason));
+                }
+            }
         }
+        due
+    }
+
+    /// Acknowledge the operator review of a channel, restarting its age and
+    /// inactivity periods at the current height.
+    ///
+    /// This also clears a signature left incomplete by a crash, after the
+    /// operator has checked that the state of the channel is current.
+    /// The acknowledgment is recorded in the audit log.
+    pub fn acknowledge_channel_review(&self, channel_id: &ChannelId) -> Result<(), Status> {
+        self.with_ready_channel(channel_id, |chan| {
+            let height = chan.monitor.as_chain_state().current_height;
+            chan.enforcement_state.review_height = height;
+            chan.enforcement_state.last_activity_height = height;
+            if let Some(intent) = chan.enforcement_state.signing_intent.take() {
+                info!("{}: acknowledged incomplete {}", chan.id0, intent);
+            }
+            chan.persist_with_tracker(None)?;
+            info!("{}: acknowledged review at height {}", chan.id0, height);
+            let audit = AuditRecord::new(
+                Some(chan.id0),
+                "acknowledge_channel_review",
+                None,
+                vec![],
+                vec![],
+            );
+            self.append_audit_record(audit)
+        })
     }
 
     /// Get a channel given its funding outpoint, or None if no such channel exists.
@@ -743,6 +1483,27 @@ impl Node {
         find_channel_with_funding_outpoint(&channels_lock, outpoint)
     }
 
+    /// Create a new channel, with keys derived from the peer's node ID and
+    /// the channel's database ID rather than from a generated nonce, see
+    /// [channel_nonce_from_peer_dbid].  The channel ID is the SHA256 of the
+    /// nonce, as in the gRPC driver.
+    ///
+    /// The LND key derivation style is not supported, because its channel
+    /// keys depend on the order the channels are created in.
+    pub fn new_channel_with_dbid(
+        &self,
+        peer_id: &PublicKey,
+        dbid: u64,
+        arc_self: &Arc<Node>,
+    ) -> Result<(ChannelId, Option<ChannelStub>), Status> {
+        if let KeyDerivationStyle::Lnd = self.node_config.key_derivation_style {
+            return Err(invalid_argument("channel keys can't be derived from the dbid with lnd"));
+        }
+        let channel_nonce0 = channel_nonce_from_peer_dbid(peer_id, dbid);
+        let channel_id = channel_nonce_to_id(&channel_nonce0);
+        self.new_channel(Some(channel_id), Some(channel_nonce0), arc_self)
+    }
+
     /// Create a new channel, which starts out as a stub.
     ///
     /// The initial channel ID may be specified in `opt_channel_id`.  If the channel
@@ -789,7 +1550,7 @@ impl Node {
                     // in negotiation.  It's ok to just use this stub.
                     return Ok((channel_id, Some(stub.clone())));
                 }
-                ChannelSlot::Ready(_) => {
+                ChannelSlot::Ready(_) | ChannelSlot::Closing(_) | ChannelSlot::Closed(_) => {
                     // Calling new_channel on a channel that's already been marked
                     // ready is not allowed.
                     return Err(invalid_argument(format!("channel already ready: {}", channel_id)));
@@ -811,13 +1572,13 @@ impl Node {
             keys,
             id0: channel_id,
         };
-        // TODO this clone is expensive
-        channels.insert(channel_id, Arc::new(Mutex::new(ChannelSlot::Stub(stub.clone()))));
+        // Persist before adding to memory, so that a failed write leaves
+        // no trace and the call can be retried
         self.persister
             .new_channel(&self.get_id(), &stub)
-            // Persist.new_channel should only fail if the channel was previously persisted.
-            // So if it did fail, we have an internal error.
-            .expect("channel was in storage but not in memory");
+            .map_err(|e| persist_error("channel persist failed", e))?;
+        // TODO this clone is expensive
+        channels.insert(channel_id, Arc::new(Mutex::new(ChannelSlot::Stub(stub.clone()))));
         Ok((channel_id, Some(stub)))
     }
 
@@ -831,6 +1592,21 @@ impl Node {
         enforcement_state: EnforcementState,
         arc_self: &Arc<Node>,
     ) -> Result<Arc<Mutex<ChannelSlot>>, ()> {
+        // Share the monitor with the restored tracker, if it is there.
+        // Look it up before locking the channels, to keep the lock order.
+        let tracker_monitor = channel_setup.as_ref().and_then(|setup| {
+            self.get_tracker()
+                .listeners
+                .keys()
+                .find(|m| {
+                    // The channel may have been spliced since the monitor was created
+                    let state = m.get_state();
+                    m.funding_outpoint == setup.funding_outpoint
+                        || state.funding_outpoint == Some(setup.funding_outpoint)
+                        || state.splice_outpoint == Some(setup.funding_outpoint)
+                })
+                .cloned()
+        });
         let mut channels = self.channels.lock().unwrap();
         assert!(!channels.contains_key(&channel_id0));
         let mut keys = self.keys_manager.get_channel_keys_with_id(
@@ -860,7 +1636,8 @@ impl Node {
                 keys.ready_channel(&channel_transaction_parameters);
                 let funding_outpoint = setup.funding_outpoint;
                 // FIXME correct persistence
-                let monitor = ChainMonitor::new(funding_outpoint, 0);
+                let monitor =
+                    tracker_monitor.unwrap_or_else(|| ChainMonitor::new(funding_outpoint, 0));
                 let channel = Channel {
                     node: Arc::downgrade(arc_self),
                     nonce,
@@ -872,8 +1649,21 @@ impl Node {
                     id: channel_id,
                     monitor,
                 };
+                channel.update_monitor_commitments();
+                if let Some(intent) = &channel.enforcement_state.signing_intent {
+                    warn!(
+                        "{}: incomplete {} before restart, the channel requires acknowledgement",
+                        channel_id0, intent
+                    );
+                }
                 // TODO this clone is expensive
-                let slot = Arc::new(Mutex::new(ChannelSlot::Ready(channel.clone())));
+                let slot = if channel.is_closed() {
+                    Arc::new(Mutex::new(ChannelSlot::Closed(channel.clone())))
+                } else if channel.enforcement_state.closing_txid.is_some() {
+                    Arc::new(Mutex::new(ChannelSlot::Closing(channel.clone())))
+                } else {
+                    Arc::new(Mutex::new(ChannelSlot::Ready(channel.clone())))
+                };
                 channels.insert(channel_id0, Arc::clone(&slot));
                 channel_id.map(|id| channels.insert(id, Arc::clone(&slot)));
                 slot
@@ -890,26 +1680,32 @@ impl Node {
     /// The channels are also restored from the `persister`.
     pub fn restore_node(
         node_id: &PublicKey,
-        node_entry: NodeEntry,
+        mut node_entry: NodeEntry,
         persister: Arc<dyn Persist>,
-        validator_factory: Arc<dyn ValidatorFactory>,
+        validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
     ) -> Arc<Node> {
         let network = Network::from_str(node_entry.network.as_str()).expect("bad network");
         let config = NodeConfig {
             network,
             key_derivation_style: KeyDerivationStyle::try_from(node_entry.key_derivation_style)
                 .unwrap(),
+            chain_params: node_entry.chain_params,
+            checkpoint: None,
         };
 
         let allowlist = persister
             .get_node_allowlist(node_id)
+            .expect("allowlist")
             .iter()
             .map(|e| Allowable::from_str(e, network))
             .collect::<Result<_, _>>()
             .expect("allowable parse error");
         let tracker = persister.get_tracker(node_id).expect("tracker");
         // FIXME persist node state
-        let state = NodeState::new();
+        let mut state = NodeState::new();
+        for entry in persister.get_payments(node_id).expect("payments") {
+            state.ledger.insert(entry.payment_hash, entry);
+        }
 
         let node = Arc::new(Node::new_from_persistence(
             config,
@@ -920,9 +1716,12 @@ impl Node {
             validator_factory,
             state,
         ));
+        // the keys manager has its own copy of the seed
+        node_entry.seed.zeroize();
         assert_eq!(&node.get_id(), node_id);
         info!("Restore node {}", node_id);
-        for (channel_id0, channel_entry) in persister.get_node_channels(node_id) {
+        let channels = persister.get_node_channels(node_id).expect("channels");
+        for (channel_id0, channel_entry) in channels {
             info!("  Restore channel {}", channel_id0);
             node.restore_channel(
                 channel_id0,
@@ -935,6 +1734,7 @@ impl Node {
             )
             .expect("restore channel");
         }
+        node.check_tracker_watches();
         node
     }
 
@@ -943,10 +1743,10 @@ impl Node {
     /// The channels of each node are also restored.
     pub fn restore_nodes(
         persister: Arc<dyn Persist>,
-        validator_factory: Arc<dyn ValidatorFactory>,
+        validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
     ) -> Map<PublicKey, Arc<Node>> {
         let mut nodes = Map::new();
-        for (node_id, node_entry) in persister.get_nodes() {
+        for (node_id, node_entry) in persister.get_nodes().expect("nodes") {
             let node = Node::restore_node(
                 &node_id,
                 node_entry,
@@ -958,6 +1758,97 @@ impl Node {
         nodes
     }
 
+    /// Register the expected funding outpoint and channel value of a channel
+    /// that is not ready yet, for example right after accept_channel.
+    ///
+    /// The chain tracker watches for the funding transaction, and
+    /// [Node::ready_channel] then requires that it was confirmed with an
+    /// output of the registered value.
+    pub fn register_funding(
+        &self,
+        channel_id0: &ChannelId,
+        funding_outpoint: OutPoint,
+        channel_value_sat: u64,
+    ) -> Result<(), Status> {
+        let mut tracker = self.tracker.lock().unwrap();
+        {
+            let channels = self.channels.lock().unwrap();
+            let arcobj = channels.get(channel_id0).ok_or_else(|| {
+                invalid_argument(format!("channel does not exist: {}", channel_id0))
+            })?;
+            let slot = arcobj.lock().unwrap();
+            match &*slot {
+                ChannelSlot::Stub(_) => {}
+                ChannelSlot::Ready(_) | ChannelSlot::Closing(_) | ChannelSlot::Closed(_) => {
+                    return Err(invalid_argument(format!("channel already ready: {}", channel_id0)))
+                }
+            }
+        }
+        if tracker.listeners.keys().any(|m| m.funding_outpoint == funding_outpoint) {
+            return Err(invalid_argument(format!(
+                "funding outpoint already watched: {}",
+                funding_outpoint
+            )));
+        }
+
+        let monitor = ChainMonitor::new(funding_outpoint, tracker.height());
+        monitor.add_funding_outpoint(&funding_outpoint);
+        monitor.expect_funding_value(channel_value_sat);
+        tracker.add_listener(monitor, OrderedSet::from_iter(vec![funding_outpoint.txid]));
+        info!(
+            "{}: registered funding {} of {} sat for channel {}",
+            self.log_prefix(),
+            funding_outpoint,
+            channel_value_sat,
+            channel_id0
+        );
+        self.persister
+            .update_tracker(&self.get_id(), &tracker)
+            .map_err(|e| persist_error("tracker persist failed", e))
+    }
+
+    // The monitor of a funding outpoint registered with register_funding,
+    // after checking that the funding was observed on-chain with the
+    // registered value
+    fn registered_funding_monitor(
+        tracker: &ChainTracker<ChainMonitor>,
+        setup: &ChannelSetup,
+    ) -> Result<Option<ChainMonitor>, ValidationError> {
+        let monitor =
+            match tracker.listeners.keys().find(|m| m.funding_outpoint == setup.funding_outpoint) {
+                Some(monitor) => monitor.clone(),
+                None => return Ok(None),
+            };
+        let state = monitor.get_state();
+        let expected_value_sat = match state.expected_funding_value_sat {
+            Some(value_sat) => value_sat,
+            None => return Ok(None),
+        };
+        if setup.channel_value_sat != expected_value_sat {
+            return Err(policy_error(format!(
+                "channel value {} does not match registered value {}",
+                setup.channel_value_sat, expected_value_sat
+            )));
+        }
+        match state.funding_value_sat {
+            None => {
+                return Err(policy_error(format!(
+                    "registered funding {} was not observed on-chain",
+                    setup.funding_outpoint
+                )))
+            }
+            Some(value_sat) if value_sat != expected_value_sat => {
+                return Err(policy_error(format!(
+                    "funding output value {} does not match registered value {}",
+                    value_sat, expected_value_sat
+                )))
+            }
+            Some(_) => {}
+        }
+        drop(state);
+        Ok(Some(monitor))
+    }
+
     /// Ready a new channel, making it available for use.
     ///
     /// This populates fields that are known later in the channel creation flow,
@@ -968,6 +1859,10 @@ impl Node {
     ///
     /// The channel is promoted from a [ChannelStub] to a [Channel].
     /// After this call, the channel may be referred to by either ID.
+    ///
+    /// If the funding was registered with [Node::register_funding], the
+    /// funding transaction must have been observed on-chain, matching the
+    /// registration.
     pub fn ready_channel(
         &self,
         channel_id0: ChannelId,
@@ -982,6 +1877,15 @@ impl Node {
             Some(channel_id0),
         );
 
+        let registered_monitor = Node::registered_funding_monitor(&tracker, &setup)?;
+        if registered_monitor.is_none() && validator.require_funding_registration() {
+            return Err(policy_error(format!(
+                "funding {} was not registered",
+                setup.funding_outpoint
+            ))
+            .into());
+        }
+
         let chan = {
             let channels = self.channels.lock().unwrap();
             let arcobj = channels.get(&channel_id0).ok_or_else(|| {
@@ -990,7 +1894,7 @@ impl Node {
             let slot = arcobj.lock().unwrap();
             let stub = match &*slot {
                 ChannelSlot::Stub(stub) => Ok(stub),
-                ChannelSlot::Ready(_) =>
+                ChannelSlot::Ready(_) | ChannelSlot::Closing(_) | ChannelSlot::Closed(_) =>
                     Err(invalid_argument(format!("channel already ready: {}", channel_id0))),
             }?;
             let mut keys = stub.channel_keys_with_channel_value(setup.channel_value_sat);
@@ -999,8 +1903,11 @@ impl Node {
                 Node::channel_setup_to_channel_transaction_parameters(&setup, holder_pubkeys);
             keys.ready_channel(&channel_transaction_parameters);
             let funding_outpoint = setup.funding_outpoint;
-            let monitor = ChainMonitor::new(funding_outpoint, tracker.height());
-            monitor.add_funding_outpoint(&funding_outpoint);
+            let monitor = registered_monitor.clone().unwrap_or_else(|| {
+                let monitor = ChainMonitor::new(funding_outpoint, tracker.height());
+                monitor.add_funding_outpoint(&funding_outpoint);
+                monitor
+            });
             let to_holder_msat = if setup.is_outbound {
                 // This is also checked in the validator, but we have to check
                 // here because we need it to create the validator
@@ -1017,7 +1924,9 @@ impl Node {
                 setup.push_value_msat
             };
             let initial_holder_value_sat = validator.minimum_initial_balance(to_holder_msat);
-            let enforcement_state = EnforcementState::new(initial_holder_value_sat);
+            let mut enforcement_state = EnforcementState::new(initial_holder_value_sat);
+            enforcement_state.review_height = tracker.height();
+            enforcement_state.last_activity_height = tracker.height();
             Channel {
                 node: Weak::clone(&stub.node),
                 nonce: stub.nonce.clone(),
@@ -1058,37 +1967,56 @@ impl Node {
         // inputs that are ours.
         // Note that the functional tests also have no inputs for the funder's tx
         // which might be a problem in the future with more validation.
-        tracker.add_listener(
-            chan.monitor.clone(),
-            OrderedSet::from_iter(vec![setup.funding_outpoint.txid]),
-        );
+        // A registered funding is already watched.
+        if registered_monitor.is_none() {
+            tracker.add_listener(
+                chan.monitor.clone(),
+                OrderedSet::from_iter(vec![setup.funding_outpoint.txid]),
+            );
+        }
 
         debug_vals!(&chan.setup);
         trace_enforcement_state!(&chan.enforcement_state);
+        let batch = PersistBatch { tracker: Some(&*tracker), channels: vec![&chan] };
         self.persister
-            .update_tracker(&self.get_id(), &tracker)
-            .map_err(|_| internal_error("tracker persist failed"))?;
-        self.persister
-            .update_channel(&self.get_id(), &chan)
-            .map_err(|_| internal_error("persist failed"))?;
+            .update_batch(&self.get_id(), &batch)
+            .map_err(|e| persist_error("persist failed", e))?;
 
         Ok(chan)
     }
 
+    // The key and the witness stack suffix for sweeping an output of a
+    // unilateral close
+    fn unilateral_close_key(
+        channels: &OrderedMap<ChannelId, Arc<Mutex<ChannelSlot>>>,
+        info: &UnilateralCloseInfo,
+    ) -> Result<(SecretKey, Vec<Vec<u8>>), Status> {
+        let slot_arc =
+            channels.get(&info.channel_id).ok_or_else(|| invalid_argument("no such channel"))?;
+        let slot = slot_arc.lock().unwrap();
+        match &*slot {
+            ChannelSlot::Ready(chan) | ChannelSlot::Closing(chan) => {
+                chan.unilateral_close_key(&info.commitment_point, &info.revocation_pubkey)
+            }
+            _ => Err(invalid_argument(format!("channel not ready: {}", &info.channel_id))),
+        }
+    }
+
     /// Sign an onchain transaction (funding tx or simple sweeps).
     ///
     /// The transaction may fund multiple channels at once.
+    /// It may only spend inputs reserved for fee bumping if all of its
+    /// outputs are to our wallet, as the child of an anchor spend is.
     /// Returns a witness stack for each input.  Inputs that are marked
     /// as [SpendType::Invalid] are not signed and get an empty witness stack.
     /// * `ipaths` - derivation path for the wallet key per input
     /// * `values_sat` - the amount in satoshi per input
     /// * `spendtypes` - spend type per input, or `Invalid` if this input is
     ///   to be signed by someone else.
-    /// * `uniclosekeys` - an optional unilateral close key to use instead of the
-    ///   wallet key.  Takes precedence over the `ipaths` entry.  This is used when
-    ///   we are sweeping a unilateral close and funding a channel in a single tx.
-    ///   The second item in the tuple is the witness stack suffix - zero or more
-    ///   script parameters and the redeemscript.
+    /// * `uniclosekeys` - an optional unilateral close output, to be signed with
+    ///   the channel key instead of the wallet key.  Takes precedence over the
+    ///   `ipaths` entry.  This is used when we are sweeping a unilateral close
+    ///   and funding a channel in a single tx.
     /// * `opaths` - derivation path for change, one per output.  Empty for
     ///   non-change outputs.
     pub fn sign_onchain_tx(
@@ -1097,7 +2025,7 @@ impl Node {
         ipaths: &Vec<Vec<u32>>,
         values_sat: &Vec<u64>,
         spendtypes: &Vec<SpendType>,
-        uniclosekeys: Vec<Option<(SecretKey, Vec<Vec<u8>>)>>,
+        uniclosekeys: Vec<Option<UnilateralCloseInfo>>,
         opaths: &Vec<Vec<u32>>,
     ) -> Result<Vec<Vec<Vec<u8>>>, Status> {
         let channels_lock = self.channels.lock().unwrap();
@@ -1121,7 +2049,17 @@ impl Node {
             })
             .collect();
 
-        validator.validate_onchain_tx(self, channels.clone(), tx, values_sat, opaths)?;
+        let audit = AuditRecord::for_tx(None, "sign_onchain_tx", None, tx);
+        validator
+            .validate_onchain_tx(self, channels.clone(), tx, values_sat, opaths)
+            .map_err(|ve| self.audit_failure(&audit, ve))?;
+        self.validate_fee_reserve_spend(tx, opaths).map_err(|ve| self.audit_failure(&audit, ve))?;
+
+        let taproot_prevouts = if spendtypes.contains(&SpendType::P2tr) {
+            Some(self.wallet_prevouts(tx, ipaths, values_sat, spendtypes, &uniclosekeys)?)
+        } else {
+            None
+        };
 
         let mut witvec: Vec<Vec<Vec<u8>>> = Vec::new();
         for (idx, uck) in uniclosekeys.into_iter().enumerate() {
@@ -1130,13 +2068,27 @@ impl Node {
                 // marked as SpendType::Invalid (we skip these), push
                 // an empty witness element instead.
                 witvec.push(vec![]);
+            } else if spendtypes[idx] == SpendType::P2tr {
+                // A BIP-86 key path spend, with the signature as the witness
+                let key = self.get_wallet_privkey(&secp_ctx, &ipaths[idx])?;
+                let sig = Self::sign_taproot_key_spend(
+                    &Secp256k1::new(),
+                    tx,
+                    idx,
+                    taproot_prevouts.as_ref().expect("taproot prevouts"),
+                    &key.key,
+                    validator.verify_signatures(),
+                )?;
+                witvec.push(vec![sig[..].to_vec()]);
             } else {
                 let value_sat = values_sat[idx];
                 let (privkey, mut witness) = match uck {
                     // There was a unilateral_close_key.
                     // TODO we don't care about the network here
-                    Some((key, stack)) =>
-                        (bitcoin::PrivateKey::new(key.clone(), Network::Testnet), stack),
+                    Some(info) => {
+                        let (key, stack) = Self::unilateral_close_key(&channels_lock, &info)?;
+                        (bitcoin::PrivateKey::new(key, Network::Testnet), stack)
+                    }
                     // Derive the HD key.
                     None => {
                         let key = self.get_wallet_privkey(&secp_ctx, &ipaths[idx])?;
@@ -1177,7 +2129,13 @@ impl Node {
                 let message = Message::from_slice(&sighash).map_err(|err| {
                     internal_error(format!("sighash {:?} failed: {}", spendtypes[idx], err))
                 })?;
-                let sig = secp_ctx.sign(&message, &privkey.key);
+                let sig = sign_ecdsa_verified(
+                    &secp_ctx,
+                    &message,
+                    &privkey.key,
+                    validator.grind_low_r(),
+                    validator.verify_signatures(),
+                )?;
                 let sigvec = signature_to_bitcoin_vec(sig);
                 witness.insert(0, sigvec);
 
@@ -1185,23 +2143,109 @@ impl Node {
             }
         }
 
-        // The tracker may be updated for multiple channels
-        let mut tracker = self.tracker.lock().unwrap();
+        self.funding_signed_channels(tx, &channels)?;
+        self.append_audit_record(audit)?;
 
-        // This locks channels in a random order, so we have to keep a global
-        // lock to ensure no deadlock.  We grab the self.channels mutex above
-        // for this purpose.
-        // TODO(devrandom) consider sorting instead
-        for (vout, slot_opt) in channels.iter().enumerate() {
-            if let Some(slot_mutex) = slot_opt {
-                let slot = slot_mutex.lock().unwrap();
-                match &*slot {
-                    ChannelSlot::Stub(_) => panic!("this can't happen"),
-                    ChannelSlot::Ready(chan) => {
-                        let inputs =
-                            OrderedSet::from_iter(tx.input.iter().map(|i| i.previous_output));
-                        tracker.add_listener_watches(chan.monitor.clone(), inputs);
-                        chan.funding_signed(tx, vout as u32)
+        // TODO(devrandom) self.persist_channel(node_id, chan);
+        Ok(witvec)
+    }
+
+    /// Sign a funding transaction of one or more channels that we open.
+    ///
+    /// Unlike [Node::sign_onchain_tx], all the inputs must be native segwit
+    /// outputs of our wallet, and all the outputs must fund a ready channel
+    /// or return change to our wallet, so that a compromised node can't
+    /// divert the funds.  Each funding output must match the setup of its
+    /// channel.  Returns a witness stack for each input.
+    /// * `inputs` - the wallet outputs spent, in the order of the inputs
+    /// * `opaths` - derivation path for change, one per output.  Empty for
+    ///   funding outputs.
+    pub fn sign_funding_tx(
+        &self,
+        tx: &bitcoin::Transaction,
+        inputs: &Vec<InteractiveInput>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<Vec<Vec<Vec<u8>>>, Status> {
+        if opaths.len() != tx.output.len() {
+            return Err(invalid_argument(format!(
+                "{} output paths for {} outputs",
+                opaths.len(),
+                tx.output.len()
+            )));
+        }
+        let channels_lock = self.channels.lock().unwrap();
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            None,
+        );
+
+        let txid = tx.txid();
+        let channels: Vec<Option<Arc<Mutex<ChannelSlot>>>> = (0..tx.output.len())
+            .map(|ndx| {
+                let outpoint = OutPoint { txid, vout: ndx as u32 };
+                find_channel_with_funding_outpoint(&channels_lock, &outpoint)
+            })
+            .collect();
+
+        let audit = AuditRecord::for_tx(None, "sign_funding_tx", None, tx);
+        validator
+            .validate_funding_tx(self, &channels, tx, inputs, opaths)
+            .map_err(|ve| self.audit_failure(&audit, ve))?;
+        let values_sat = inputs.iter().map(|input| input.prev_output.value).collect();
+        validator
+            .validate_onchain_tx(self, channels.clone(), tx, &values_sat, opaths)
+            .map_err(|ve| self.audit_failure(&audit, ve))?;
+        self.validate_fee_reserve_spend(tx, opaths).map_err(|ve| self.audit_failure(&audit, ve))?;
+
+        for input in inputs {
+            if input.prev_output.script_pubkey
+                != self.get_native_address(&input.ipath)?.script_pubkey()
+            {
+                return Err(invalid_argument(format!(
+                    "input {}: only native segwit wallet outputs can be spent",
+                    input.outpoint
+                )));
+            }
+        }
+        let witvec = self.sign_wallet_inputs(
+            tx,
+            inputs,
+            validator.grind_low_r(),
+            validator.verify_signatures(),
+        )?;
+
+        self.funding_signed_channels(tx, &channels)?;
+        self.append_audit_record(audit)?;
+        Ok(witvec)
+    }
+
+    // Watch the inputs of a transaction for the channels that it funds, and
+    // hand them the transaction.  The caller holds the channels lock.
+    fn funding_signed_channels(
+        &self,
+        tx: &bitcoin::Transaction,
+        channels: &Vec<Option<Arc<Mutex<ChannelSlot>>>>,
+    ) -> Result<(), Status> {
+        // The tracker may be updated for multiple channels
+        let mut tracker = self.tracker.lock().unwrap();
+
+        // This locks channels in a random order, so we have to keep a global
+        // lock to ensure no deadlock.  The caller grabs the self.channels
+        // mutex for this purpose.
+        // TODO(devrandom) consider sorting instead
+        for (vout, slot_opt) in channels.iter().enumerate() {
+            if let Some(slot_mutex) = slot_opt {
+                let slot = slot_mutex.lock().unwrap();
+                match &*slot {
+                    ChannelSlot::Stub(_) => panic!("this can't happen"),
+                    ChannelSlot::Ready(chan)
+                    | ChannelSlot::Closing(chan)
+                    | ChannelSlot::Closed(chan) => {
+                        let inputs =
+                            OrderedSet::from_iter(tx.input.iter().map(|i| i.previous_output));
+                        tracker.add_listener_watches(chan.monitor.clone(), inputs);
+                        chan.funding_signed(tx, vout as u32)
                     }
                 }
             }
@@ -1210,13 +2254,487 @@ impl Node {
         // the channels added some watches - persist
         self.persister
             .update_tracker(&self.get_id(), &tracker)
-            .map_err(|_| internal_error("tracker persist failed"))?;
+            .map_err(|e| persist_error("tracker persist failed", e))
+    }
 
-        // TODO(devrandom) self.persist_channel(node_id, chan);
+    /// Sign the wallet inputs of a PSBT.
+    ///
+    /// An input is ours if its BIP-32 derivations include a key with the
+    /// fingerprint of the layer-1 account xpub, at a path relative to that
+    /// xpub.  The key must match the path, and the output that the input
+    /// spends, given as its witness or non-witness UTXO, must be a native or
+    /// wrapped p2wpkh or a BIP-86 taproot output of the key.  Other inputs
+    /// are left alone.  Outputs with such a derivation are change, and must
+    /// be spendable by our wallet.
+    ///
+    /// The transaction is validated as in [Node::sign_onchain_tx], and the
+    /// value it sends from the wallet counts against the velocity policy.
+    /// Segwit v0 signatures are added as partial signatures, with the
+    /// redeem script of wrapped inputs.  Taproot inputs, which this PSBT
+    /// version has no fields for, are finalized with the key path signature.
+    pub fn sign_psbt(
+        &self,
+        psbt: &PartiallySignedTransaction,
+    ) -> Result<PartiallySignedTransaction, Status> {
+        let secp_ctx = Secp256k1::new();
+        let signing_ctx = Secp256k1::signing_only();
+        let tx = &psbt.global.unsigned_tx;
+        if psbt.inputs.len() != tx.input.len() || psbt.outputs.len() != tx.output.len() {
+            return Err(invalid_argument("psbt inputs or outputs don't match its transaction"));
+        }
+        let fingerprint = self.get_account_extended_key().fingerprint(&secp_ctx);
+
+        let mut prevouts = Vec::new();
+        let mut wallet_inputs = Vec::new();
+        for (idx, input) in psbt.inputs.iter().enumerate() {
+            let prevout = Self::psbt_prevout(idx, input, &tx.input[idx].previous_output)?;
+            if let Some(path) =
+                self.psbt_wallet_path(&signing_ctx, fingerprint, &input.bip32_derivation)?
+            {
+                let script_pubkey = &prevout
+                    .as_ref()
+                    .ok_or_else(|| invalid_argument(format!("input {}: missing utxo", idx)))?
+                    .script_pubkey;
+                let kind = if *script_pubkey == self.get_native_address(&path)?.script_pubkey() {
+                    PsbtInputKind::P2wpkh
+                } else if *script_pubkey == self.get_wrapped_address(&path)?.script_pubkey() {
+                    PsbtInputKind::P2shP2wpkh
+                } else if *script_pubkey == self.get_taproot_script_pubkey(&path)? {
+                    PsbtInputKind::P2tr
+                } else {
+                    return Err(invalid_argument(format!("input {}: wallet cannot spend", idx)));
+                };
+                wallet_inputs.push((idx, path, kind));
+            }
+            prevouts.push(prevout);
+        }
+        // A taproot signature commits to all the outputs being spent
+        let all_prevouts: Option<Vec<TxOut>> = prevouts.iter().cloned().collect();
+        if all_prevouts.is_none() && wallet_inputs.iter().any(|(_, _, k)| *k == PsbtInputKind::P2tr)
+        {
+            return Err(invalid_argument("taproot inputs need the utxos of all the inputs"));
+        }
+
+        let opaths = psbt
+            .outputs
+            .iter()
+            .map(|output| {
+                self.psbt_wallet_path(&signing_ctx, fingerprint, &output.bip32_derivation)
+                    .map(|path| path.unwrap_or_default())
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        let values_sat = wallet_inputs
+            .iter()
+            .map(|(idx, _, _)| prevouts[*idx].as_ref().expect("wallet input utxo").value)
+            .collect();
+
+        let channels_lock = self.channels.lock().unwrap();
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            None,
+        );
+
+        let txid = tx.txid();
+        let channels: Vec<Option<Arc<Mutex<ChannelSlot>>>> = (0..tx.output.len())
+            .map(|ndx| {
+                let outpoint = OutPoint { txid, vout: ndx as u32 };
+                find_channel_with_funding_outpoint(&channels_lock, &outpoint)
+            })
+            .collect();
+
+        let audit = AuditRecord::for_tx(None, "sign_psbt", None, tx);
+        validator
+            .validate_onchain_tx(self, channels.clone(), tx, &values_sat, &opaths)
+            .map_err(|ve| self.audit_failure(&audit, ve))?;
+        self.validate_fee_reserve_spend(tx, &opaths)
+            .map_err(|ve| self.audit_failure(&audit, ve))?;
+
+        // The value sent from the wallet, other than to change and channels
+        let spent_sat = tx
+            .output
+            .iter()
+            .zip(opaths.iter().zip(channels.iter()))
+            .filter(|(_, (opath, channel))| opath.is_empty() && channel.is_none())
+            .fold(0u64, |sum, (output, _)| sum.saturating_add(output.value));
+        let current_height = self.get_tracker().height();
+        let cstate = ChainState {
+            current_height,
+            funding_depth: 0,
+            funding_watched: false,
+            funding_double_spent_depth: 0,
+            closing_depth: 0,
+            clock_skew_secs: self.fresh_clock_skew_secs(current_height),
+        };
+
+        let mut signed = psbt.clone();
+        {
+            let mut state = self.get_state();
+            validator
+                .validate_onchain_velocity(&state.onchain_spends, spent_sat, &cstate)
+                .map_err(|ve| self.audit_failure(&audit, ve))?;
+
+            for (idx, path, kind) in wallet_inputs {
+                let privkey = self.get_wallet_privkey(&signing_ctx, &path)?;
+                let input = &mut signed.inputs[idx];
+                if kind == PsbtInputKind::P2tr {
+                    let all_prevouts = all_prevouts.as_ref().expect("checked above");
+                    let sig = Self::sign_taproot_key_spend(
+                        &secp_ctx,
+                        tx,
+                        idx,
+                        all_prevouts,
+                        &privkey.key,
+                        validator.verify_signatures(),
+                    )?;
+                    input.final_script_witness = Some(vec![sig[..].to_vec()]);
+                } else {
+                    let pubkey = privkey.public_key(&signing_ctx);
+                    let script_code = Address::p2pkh(&pubkey, privkey.network).script_pubkey();
+                    let value_sat = prevouts[idx].as_ref().expect("wallet input utxo").value;
+                    let sighash = SigHashCache::new(tx).signature_hash(
+                        idx,
+                        &script_code,
+                        value_sat,
+                        SigHashType::All,
+                    );
+                    let message = Message::from_slice(&sighash).expect("sighash is 32 bytes");
+                    let sig = sign_ecdsa_verified(
+                        &signing_ctx,
+                        &message,
+                        &privkey.key,
+                        validator.grind_low_r(),
+                        validator.verify_signatures(),
+                    )?;
+                    input.partial_sigs.insert(pubkey, signature_to_bitcoin_vec(sig));
+                    if kind == PsbtInputKind::P2shP2wpkh {
+                        let redeem_script =
+                            Address::p2wpkh(&pubkey, self.network()).expect("p2wpkh failed");
+                        input.redeem_script = Some(redeem_script.script_pubkey());
+                    }
+                }
+            }
+            state.record_onchain_spend(cstate.current_height, spent_sat);
+        }
+
+        self.funding_signed_channels(tx, &channels)?;
+        self.append_audit_record(audit)?;
+        Ok(signed)
+    }
+
+    // The output spent by a PSBT input, if the PSBT has it
+    // Sign a BIP-86 key path spend of the input with the tweaked key
+    fn sign_taproot_key_spend(
+        secp_ctx: &Secp256k1<All>,
+        tx: &Transaction,
+        idx: usize,
+        prevouts: &[TxOut],
+        key: &SecretKey,
+        verify: bool,
+    ) -> Result<schnorrsig::Signature, Status> {
+        let sighash = taproot_key_spend_sighash(tx, idx, prevouts)
+            .map_err(|_| internal_error("taproot sighash failed"))?;
+        let message = Message::from_slice(&sighash).expect("sighash is 32 bytes");
+        let keypair = bip86_tweaked_keypair(secp_ctx, key);
+        let sig = secp_ctx.schnorrsig_sign_no_aux_rand(&message, &keypair);
+        if verify {
+            let output_key = schnorrsig::PublicKey::from_slice(
+                &XOnlyPublicKey::from_keypair(&keypair).serialize(),
+            )
+            .map_err(|_| internal_error("bad taproot output key"))?;
+            verify_schnorr(&message, &sig, &output_key)?;
+        }
+        Ok(sig)
+    }
+
+    // The outputs spent by a transaction, which a taproot signature commits
+    // to.  They can only be derived if all the inputs are from our wallet.
+    fn wallet_prevouts(
+        &self,
+        tx: &Transaction,
+        ipaths: &Vec<Vec<u32>>,
+        values_sat: &Vec<u64>,
+        spendtypes: &Vec<SpendType>,
+        uniclosekeys: &Vec<Option<UnilateralCloseInfo>>,
+    ) -> Result<Vec<TxOut>, Status> {
+        if spendtypes.len() != tx.input.len() {
+            return Err(invalid_argument(format!(
+                "{} spend types for {} inputs",
+                spendtypes.len(),
+                tx.input.len()
+            )));
+        }
+        let mut prevouts = Vec::new();
+        for (idx, spendtype) in spendtypes.iter().enumerate() {
+            let path = &ipaths[idx];
+            let script_pubkey = match spendtype {
+                _ if uniclosekeys[idx].is_some() => {
+                    return Err(invalid_argument(format!(
+                        "input {}: a taproot input can't be signed with a unilateral close input",
+                        idx
+                    )))
+                }
+                SpendType::P2wpkh => self.get_native_address(path)?.script_pubkey(),
+                SpendType::P2shP2wpkh => self.get_wrapped_address(path)?.script_pubkey(),
+                SpendType::P2tr => self.get_taproot_script_pubkey(path)?,
+                st => return Err(invalid_argument(format!(
+                    "input {}: a taproot input can't be signed with an input of spend type {:?}",
+                    idx, st
+                ))),
+            };
+            prevouts.push(TxOut { value: values_sat[idx], script_pubkey });
+        }
+        Ok(prevouts)
+    }
+
+    fn psbt_prevout(
+        idx: usize,
+        input: &psbt::Input,
+        outpoint: &OutPoint,
+    ) -> Result<Option<TxOut>, Status> {
+        if let Some(utxo) = &input.witness_utxo {
+            return Ok(Some(utxo.clone()));
+        }
+        match &input.non_witness_utxo {
+            Some(prev_tx) => {
+                if prev_tx.txid() != outpoint.txid {
+                    return Err(invalid_argument(format!(
+                        "input {}: non-witness utxo doesn't match the outpoint",
+                        idx
+                    )));
+                }
+                let output = prev_tx.output.get(outpoint.vout as usize).ok_or_else(|| {
+                    invalid_argument(format!("input {}: non-witness utxo has no such output", idx))
+                })?;
+                Ok(Some(output.clone()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // The wallet path of a PSBT input or output, from its BIP-32 derivations
+    fn psbt_wallet_path(
+        &self,
+        secp_ctx: &Secp256k1<secp256k1::SignOnly>,
+        fingerprint: Fingerprint,
+        derivations: &OrderedMap<bitcoin::PublicKey, KeySource>,
+    ) -> Result<Option<Vec<u32>>, Status> {
+        for (pubkey, (key_fingerprint, path)) in derivations {
+            if *key_fingerprint != fingerprint {
+                continue;
+            }
+            let child_path = path
+                .as_ref()
+                .iter()
+                .map(|child| match child {
+                    ChildNumber::Normal { index } => Ok(*index),
+                    ChildNumber::Hardened { .. } => {
+                        Err(invalid_argument(format!("hardened wallet path {}", path)))
+                    }
+                })
+                .collect::<Result<Vec<u32>, Status>>()?;
+            if self.get_wallet_pubkey(secp_ctx, &child_path)? != *pubkey {
+                return Err(invalid_argument(format!(
+                    "wallet path {} doesn't match key {}",
+                    path, pubkey
+                )));
+            }
+            return Ok(Some(child_path));
+        }
+        Ok(None)
+    }
+
+    /// Begin the interactive construction of a dual-funded channel's
+    /// funding transaction (BOLT-2 interactive-tx).
+    ///
+    /// A construction already in progress for the channel is replaced, as
+    /// when the peers abort and restart.  Constructions are not persisted.
+    /// * `holder_contribution_sat` - our contribution to the channel value
+    pub fn begin_interactive_funding(
+        &self,
+        channel_id: &ChannelId,
+        holder_contribution_sat: u64,
+    ) -> Result<(), Status> {
+        match &*self.get_channel(channel_id)?.lock().unwrap() {
+            ChannelSlot::Stub(_) | ChannelSlot::Ready(_) => {}
+            ChannelSlot::Closing(_) | ChannelSlot::Closed(_) =>
+                return Err(invalid_argument(format!("channel is closing: {}", channel_id))),
+        }
+        let mut fundings = self.interactive_fundings.lock().unwrap();
+        let funding = InteractiveFunding::new(holder_contribution_sat);
+        if fundings.insert(*channel_id, funding).is_some() {
+            info!("restarting interactive funding for channel {}", channel_id);
+        }
+        Ok(())
+    }
+
+    /// Add one of our wallet inputs to an interactive funding construction.
+    ///
+    /// * `prev_output` - the output being spent, which our wallet must own
+    /// * `ipath` - derivation path for the wallet key
+    pub fn add_interactive_funding_input(
+        &self,
+        channel_id: &ChannelId,
+        outpoint: OutPoint,
+        prev_output: TxOut,
+        ipath: Vec<u32>,
+    ) -> Result<(), Status> {
+        let mut fundings = self.interactive_fundings.lock().unwrap();
+        let funding = fundings.get_mut(channel_id).ok_or_else(|| {
+            invalid_argument(format!("no interactive funding for channel {}", channel_id))
+        })?;
+        if funding.inputs.iter().any(|i| i.outpoint == outpoint) {
+            return Err(invalid_argument(format!("duplicate input {}", outpoint)));
+        }
+        let input = InteractiveInput { outpoint, prev_output, ipath };
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            Some(*channel_id),
+        );
+        validator.validate_interactive_funding_input(self, &input)?;
+        funding.inputs.push(input);
+        Ok(())
+    }
+
+    /// Add one of our outputs to an interactive funding construction.
+    ///
+    /// * `opath` - derivation path for change to our wallet, or empty if the
+    ///   output is to an allowlisted address
+    pub fn add_interactive_funding_output(
+        &self,
+        channel_id: &ChannelId,
+        output: TxOut,
+        opath: Vec<u32>,
+    ) -> Result<(), Status> {
+        let mut fundings = self.interactive_fundings.lock().unwrap();
+        let funding = fundings.get_mut(channel_id).ok_or_else(|| {
+            invalid_argument(format!("no interactive funding for channel {}", channel_id))
+        })?;
+        let output = InteractiveOutput { output, opath };
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            Some(*channel_id),
+        );
+        validator.validate_interactive_funding_output(self, &output)?;
+        funding.outputs.push(output);
+        Ok(())
+    }
+
+    /// Sign our inputs of a completed interactive funding transaction, ending
+    /// the construction.
+    ///
+    /// The channel must be ready, with its funding outpoint in the transaction
+    /// and its initial holder commitment validated.
+    /// Returns a witness stack for each input.  The counterparty's inputs get
+    /// an empty witness stack.  Wrapped segwit inputs also need a script_sig,
+    /// which is left to the caller.
+    pub fn sign_interactive_funding_tx(
+        &self,
+        channel_id: &ChannelId,
+        tx: &Transaction,
+    ) -> Result<Vec<Vec<Vec<u8>>>, Status> {
+        let mut fundings = self.interactive_fundings.lock().unwrap();
+        let funding = fundings.get(channel_id).ok_or_else(|| {
+            invalid_argument(format!("no interactive funding for channel {}", channel_id))
+        })?;
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            Some(*channel_id),
+        );
+
+        let mut tracker = self.tracker.lock().unwrap();
+        let slot_arc = self.get_channel(channel_id)?;
+        let slot = slot_arc.lock().unwrap();
+        let chan = match &*slot {
+            ChannelSlot::Ready(chan) => chan,
+            _ => return Err(invalid_argument(format!("channel not ready: {}", channel_id))),
+        };
+        validator.validate_interactive_funding_tx(chan, tx, funding)?;
+
+        let witvec = self.sign_wallet_inputs(
+            tx,
+            &funding.inputs,
+            validator.grind_low_r(),
+            validator.verify_signatures(),
+        )?;
+
+        let inputs = OrderedSet::from_iter(tx.input.iter().map(|i| i.previous_output));
+        tracker.add_listener_watches(chan.monitor.clone(), inputs);
+        chan.funding_signed(tx, chan.setup.funding_outpoint.vout);
+        self.persister
+            .update_tracker(&self.get_id(), &tracker)
+            .map_err(|e| persist_error("tracker persist failed", e))?;
+
+        fundings.remove(channel_id);
         Ok(witvec)
     }
 
-    fn channel_setup_to_channel_transaction_parameters(
+    /// Begin a splice of a ready channel's funding.
+    ///
+    /// See [Channel::begin_splice].  The channel and the tracker are
+    /// persisted together, since the channel monitor starts watching for
+    /// the splice transaction.
+    pub fn begin_splice(
+        &self,
+        channel_id: &ChannelId,
+        tx: &Transaction,
+        vout: u32,
+        inputs: &Vec<InteractiveInput>,
+        outputs: &Vec<InteractiveOutput>,
+    ) -> Result<(Signature, Vec<Vec<Vec<u8>>>), Status> {
+        let tracker = self.tracker.lock().unwrap();
+        self.with_ready_channel(channel_id, |chan| {
+            let result = chan.begin_splice(tx, vout, inputs, outputs)?;
+            chan.persist_with_tracker(Some(&*tracker))?;
+            Ok(result)
+        })
+    }
+
+    // Sign our p2wpkh wallet inputs to a transaction.  The witness for
+    // other inputs is left empty.
+    pub(crate) fn sign_wallet_inputs(
+        &self,
+        tx: &Transaction,
+        inputs: &Vec<InteractiveInput>,
+        grind_low_r: bool,
+        verify_signatures: bool,
+    ) -> Result<Vec<Vec<Vec<u8>>>, Status> {
+        let secp_ctx = Secp256k1::signing_only();
+        let mut sighash_cache = SigHashCache::new(tx);
+        let mut witvec: Vec<Vec<Vec<u8>>> = vec![vec![]; tx.input.len()];
+        for input in inputs {
+            let ndx = tx
+                .input
+                .iter()
+                .position(|txin| txin.previous_output == input.outpoint)
+                .ok_or_else(|| invalid_argument(format!("missing input {}", input.outpoint)))?;
+            let privkey = self.get_wallet_privkey(&secp_ctx, &input.ipath)?;
+            let pubkey = privkey.public_key(&secp_ctx);
+            let script_code = Address::p2pkh(&pubkey, privkey.network).script_pubkey();
+            let sighash = sighash_cache.signature_hash(
+                ndx,
+                &script_code,
+                input.prev_output.value,
+                SigHashType::All,
+            );
+            let message = Message::from_slice(&sighash)
+                .map_err(|err| internal_error(format!("sighash failed: {}", err)))?;
+            let sig = sign_ecdsa_verified(
+                &secp_ctx,
+                &message,
+                &privkey.key,
+                grind_low_r,
+                verify_signatures,
+            )?;
+            witvec[ndx] = vec![signature_to_bitcoin_vec(sig), pubkey.to_bytes()];
+        }
+        Ok(witvec)
+    }
+
+    pub(crate) fn channel_setup_to_channel_transaction_parameters(
         setup: &ChannelSetup,
         holder_pubkeys: &ChannelPublicKeys,
     ) -> ChannelTransactionParameters {
@@ -1295,27 +2813,163 @@ impl Node {
         ExtendedPubKey::from_private(&secp_ctx, &self.get_account_extended_key())
     }
 
+    /// Get the output descriptors of the layer-1 wallet, with checksums,
+    /// so that a watch-only wallet can track the funds of the signer.
+    ///
+    /// There is a native segwit, a wrapped segwit and a taproot descriptor
+    /// for each BIP32 chain of the key derivation style, external first.
+    /// The key origin is the fingerprint of the layer-1 xpub, as expected
+    /// in the PSBTs given to [Node::sign_psbt].
+    pub fn get_wallet_descriptor(&self) -> Vec<String> {
+        let xpub = self.get_account_extended_pubkey();
+        let chains: &[&str] = match self.node_config.key_derivation_style.get_key_path_len() {
+            1 => &[""],
+            _ => &["/0", "/1"],
+        };
+        let mut descriptors = Vec::new();
+        for chain in chains {
+            let key = format!("[{}]{}{}/*", xpub.fingerprint(), xpub, chain);
+            for desc in
+                &[format!("wpkh({})", key), format!("sh(wpkh({}))", key), format!("tr({})", key)]
+            {
+                descriptors.push(add_descriptor_checksum(desc).expect("descriptor charset"));
+            }
+        }
+        descriptors
+    }
+
+    /// Get the layer-1 wallet address at index of the external chain
+    ///
+    /// Only [SpendType::P2wpkh], [SpendType::P2shP2wpkh] and
+    /// [SpendType::P2tr] addresses can be derived.
+    pub fn get_address(&self, index: u32, spend_type: SpendType) -> Result<Address, Status> {
+        if index >= 1 << 31 {
+            return Err(invalid_argument(format!("hardened address index {}", index)));
+        }
+        let mut child_path = vec![0; self.node_config.key_derivation_style.get_key_path_len()];
+        *child_path.last_mut().unwrap() = index;
+        match spend_type {
+            SpendType::P2wpkh => self.get_native_address(&child_path),
+            SpendType::P2shP2wpkh => self.get_wrapped_address(&child_path),
+            SpendType::P2tr => {
+                let script_pubkey = self.get_taproot_script_pubkey(&child_path)?;
+                Ok(Address::from_script(&script_pubkey, self.network()).expect("p2tr script"))
+            }
+            _ => Err(invalid_argument(format!("unsupported spend type {:?}", spend_type))),
+        }
+    }
+
     /// Sign a node announcement using the node key
+    ///
+    /// `na` is the message after the signature.  The announcement must be
+    /// for this node.
     pub fn sign_node_announcement(&self, na: &Vec<u8>) -> Result<Signature, Status> {
-        let secp_ctx = Secp256k1::signing_only();
-        let na_hash = Sha256dHash::hash(na);
-        let encmsg = secp256k1::Message::from_slice(&na_hash[..])
-            .map_err(|err| internal_error(format!("encmsg failed: {}", err)))?;
-        let sig = secp_ctx.sign(&encmsg, &self.get_node_secret());
-        Ok(sig)
+        // the node ID follows the features and the timestamp
+        let flen = na
+            .get(0..2)
+            .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+            .ok_or_else(|| invalid_argument("node announcement too short"))?;
+        let node_id = na
+            .get(2 + flen + 4..2 + flen + 4 + 33)
+            .ok_or_else(|| invalid_argument("node announcement too short"))?;
+        let node_id = PublicKey::from_slice(node_id)
+            .map_err(|_| invalid_argument("node announcement with a bad node ID"))?;
+        if node_id != self.get_id() {
+            return Err(
+                policy_error(format!("node announcement for {}, not this node", node_id)).into()
+            );
+        }
+        self.sign_gossip_hash(na)
     }
 
     /// Sign a channel update using the node key
+    ///
+    /// `cu` is the message after the signature.  The update must be for the
+    /// chain of this node, and for one of its channels.
     pub fn sign_channel_update(&self, cu: &Vec<u8>) -> Result<Signature, Status> {
+        if cu.len() < 32 + 8 {
+            return Err(invalid_argument("channel update too short"));
+        }
+        let chain_hash = self.node_config.chain_params().genesis.block_hash();
+        if cu[0..32] != chain_hash[..] {
+            return Err(policy_error(format!(
+                "channel update for chain {}, not {}",
+                cu[0..32].to_hex(),
+                chain_hash[..].to_hex()
+            ))
+            .into());
+        }
+        let mut short_channel_id = [0u8; 8];
+        short_channel_id.copy_from_slice(&cu[32..40]);
+        let short_channel_id = u64::from_be_bytes(short_channel_id);
+        if !self.has_short_channel_id(short_channel_id) {
+            return Err(policy_error(format!(
+                "channel update for unknown channel {}x{}x{}",
+                short_channel_id >> 40,
+                (short_channel_id >> 16) & 0xFFFFFF,
+                short_channel_id & 0xFFFF
+            ))
+            .into());
+        }
+        self.sign_gossip_hash(cu)
+    }
+
+    /// Sign a gossip message using the node key
+    ///
+    /// `msg` is the message as it will be sent, starting with its type, with
+    /// the signature not yet set.  Node announcements and channel updates
+    /// are checked as by [Node::sign_node_announcement] and
+    /// [Node::sign_channel_update].  Channel announcements are signed by
+    /// the channel, with [Channel::sign_channel_announcement].
+    pub fn sign_gossip_message(&self, msg: &[u8]) -> Result<Signature, Status> {
+        if msg.len() < 2 + 64 {
+            return Err(invalid_argument("gossip message too short"));
+        }
+        let msg_type = u16::from_be_bytes([msg[0], msg[1]]);
+        // skip the type and the signature
+        let contents = msg[2 + 64..].to_vec();
+        match msg_type {
+            NODE_ANNOUNCEMENT_TYPE => self.sign_node_announcement(&contents),
+            CHANNEL_UPDATE_TYPE => self.sign_channel_update(&contents),
+            _ => Err(invalid_argument(format!("can't sign gossip message type {}", msg_type))),
+        }
+    }
+
+    // Whether a short channel ID may be for one of our channels.  The block
+    // is only known once the funding is confirmed, and the position in the
+    // block isn't tracked.
+    fn has_short_channel_id(&self, short_channel_id: u64) -> bool {
+        let height = (short_channel_id >> 40) as u32;
+        let vout = (short_channel_id & 0xFFFF) as u32;
+        let channels = self.channels.lock().unwrap();
+        channels.values().any(|slot_arc| match &*slot_arc.lock().unwrap() {
+            ChannelSlot::Ready(chan) | ChannelSlot::Closing(chan) => {
+                chan.setup.funding_outpoint.vout == vout
+                    && chan.monitor.funding_height().map(|h| h == height).unwrap_or(true)
+            }
+            _ => false,
+        })
+    }
+
+    // Sign the double SHA256 of a gossip message using the node key
+    fn sign_gossip_hash(&self, contents: &[u8]) -> Result<Signature, Status> {
         let secp_ctx = Secp256k1::signing_only();
-        let cu_hash = Sha256dHash::hash(cu);
-        let encmsg = secp256k1::Message::from_slice(&cu_hash[..])
+        let hash = Sha256dHash::hash(contents);
+        let encmsg = secp256k1::Message::from_slice(&hash[..])
             .map_err(|err| internal_error(format!("encmsg failed: {}", err)))?;
-        let sig = secp_ctx.sign(&encmsg, &self.get_node_secret());
-        Ok(sig)
+        sign_ecdsa_verified(
+            &secp_ctx,
+            &encmsg,
+            &self.get_node_secret(),
+            false,
+            self.verify_signatures(),
+        )
     }
 
     /// Sign an invoice and start tracking incoming payment for its payment hash
+    ///
+    /// The invoice must not name another node as the payee, and its amount
+    /// and expiry are checked with [Validator::validate_invoice].
     pub fn sign_invoice(
         &self,
         hrp_bytes: &[u8],
@@ -1326,6 +2980,13 @@ impl Node {
         let sig = signed_raw_invoice.signature().0;
         let (hash, invoice_state, invoice_hash) =
             Self::invoice_state_from_invoice(signed_raw_invoice)?;
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            None,
+        );
+        validator
+            .validate_invoice(invoice_state.amount_msat, invoice_state.expiry_duration.as_secs())?;
         info!(
             "{} signing an invoice {} -> {}",
             self.log_prefix(),
@@ -1343,7 +3004,17 @@ impl Node {
                 ))
             };
         }
+        state.record_payment(PaymentLedgerEntry {
+            payment_hash: hash,
+            outgoing: false,
+            amount_msat: invoice_state.amount_msat,
+            invoice_hash,
+            expiry_time: (invoice_state.duration_since_epoch + invoice_state.expiry_duration)
+                .as_secs(),
+            resolution: PaymentResolution::Pending,
+        });
         state.issued_invoices.insert(hash, invoice_state);
+        self.persist_ledger(&mut state)?;
 
         Ok(sig)
     }
@@ -1360,6 +3031,13 @@ impl Node {
         let data = RawDataPart::from_base32(invoice_data)
             .map_err(|e| invalid_argument(format!("parse error: {}", e)))?;
         let raw_invoice = RawInvoice { hrp, data };
+        if let Some(payee) = raw_invoice.payee_pub_key() {
+            if payee.0 != self.get_id() {
+                return Err(
+                    policy_error(format!("invoice for payee {}, not this node", payee.0)).into()
+                );
+            }
+        }
 
         let invoice_preimage = construct_invoice_preimage(&hrp_bytes, &invoice_data);
         let secp_ctx = Secp256k1::signing_only();
@@ -1394,31 +3072,430 @@ impl Node {
     }
 
     /// Perform an ECDH operation between the node key and a public key
-    /// This can be used for onion packet decoding
+    /// This can be used for onion packet decoding, so that the node software
+    /// doesn't need the node secret.  The shared secret is not logged.
     pub fn ecdh(&self, other_key: &PublicKey) -> Vec<u8> {
+        trace!("{}: ecdh with {}", self.log_prefix(), other_key);
         let our_key = self.keys_manager.get_node_secret(Recipient::Node).unwrap();
         let ss = SharedSecret::new(&other_key, &our_key);
         ss[..].to_vec()
     }
 
-    /// See [`MyKeysManager::spend_spendable_outputs`].
-    ///
-    /// For LDK compatibility.
-    pub fn spend_spendable_outputs(
+    /// See [`MyKeysManager::spend_spendable_outputs`].
+    ///
+    /// For LDK compatibility.
+    pub fn spend_spendable_outputs(
+        &self,
+        descriptors: &[&SpendableOutputDescriptor],
+        outputs: Vec<TxOut>,
+        change_destination_script: Script,
+        feerate_sat_per_1000_weight: u32,
+        secp_ctx: &Secp256k1<All>,
+    ) -> Result<Transaction, ()> {
+        self.keys_manager.spend_spendable_outputs(
+            descriptors,
+            outputs,
+            change_destination_script,
+            feerate_sat_per_1000_weight,
+            secp_ctx,
+        )
+    }
+
+    /// Add an approval token for a signing operation that moves more than
+    /// the policy's max_unapproved_value_sat to destinations outside our
+    /// wallet.
+    ///
+    /// The token is produced out-of-band by the holder of the policy's
+    /// value_approval_key, see
+    /// [crate::policy::simple_validator::value_approval_token].  It is
+    /// verified by the validator when the operation is signed.
+    pub fn add_value_approval(&self, token: [u8; 32]) {
+        let mut approvals = self.value_approvals.lock().unwrap();
+        if approvals.contains(&token) {
+            return;
+        }
+        if approvals.len() >= MAX_VALUE_APPROVALS {
+            approvals.remove(0);
+        }
+        approvals.push(token);
+    }
+
+    /// Plan the mutual close of a set of channels, for an orderly wind-down
+    /// of the node.
+    ///
+    /// Each close is validated as in [Channel::sign_mutual_close_tx_phase2],
+    /// and the plan as a whole is validated against the policy limits on the
+    /// sum of the closing fees and on the value sent outside our wallet.
+    /// The plan is persisted, and is signed with [Node::sign_close_plan]
+    /// once the operator approves it with [Node::approve_close_plan].  Only
+    /// one plan can be in progress.
+    pub fn plan_mutual_closes(&self, closes: Vec<PlannedClose>) -> Result<ClosePlan, Status> {
+        let mut current = self.close_plan.lock().unwrap();
+        if let Some(plan) = &*current {
+            return Err(failed_precondition(format!(
+                "close plan {} is in progress",
+                plan.id.to_hex()
+            )));
+        }
+        if closes.is_empty() {
+            return Err(invalid_argument("empty close plan"));
+        }
+
+        let mut id0s = OrderedSet::new();
+        let mut fee_sat = 0u64;
+        let mut non_wallet_sat = 0u64;
+        for close in &closes {
+            if close.signed {
+                return Err(invalid_argument("planned close is already signed"));
+            }
+            let (id0, close_fee_sat, close_non_wallet_sat) = self.validate_planned_close(close)?;
+            if !id0s.insert(id0) {
+                return Err(invalid_argument(format!(
+                    "channel {} is closed more than once",
+                    close.channel_id
+                )));
+            }
+            fee_sat = fee_sat.saturating_add(close_fee_sat);
+            non_wallet_sat = non_wallet_sat.saturating_add(close_non_wallet_sat);
+        }
+        self.validate_close_plan_totals(fee_sat, non_wallet_sat)?;
+
+        let plan = ClosePlan::new(closes, fee_sat, non_wallet_sat);
+        self.persist_close_plan(Some(&plan))?;
+        info!(
+            "{}: planned close {} of {} channels, fee {} non-wallet {}",
+            self.log_prefix(),
+            plan.id.to_hex(),
+            plan.closes.len(),
+            fee_sat,
+            non_wallet_sat
+        );
+        *current = Some(plan.clone());
+        Ok(plan)
+    }
+
+    /// The close plan in progress, if any
+    pub fn get_close_plan(&self) -> Option<ClosePlan> {
+        self.close_plan.lock().unwrap().clone()
+    }
+
+    /// Approve the close plan in progress.  The operator supplies the plan
+    /// ID, so that a plan replaced in the meantime is not approved.
+    pub fn approve_close_plan(&self, id: &[u8; 32]) -> Result<(), Status> {
+        let mut current = self.close_plan.lock().unwrap();
+        let mut plan =
+            current.clone().ok_or_else(|| failed_precondition("no close plan in progress"))?;
+        if plan.id != *id {
+            return Err(invalid_argument(format!("close plan {} is not in progress", id.to_hex())));
+        }
+        if plan.approved {
+            return Ok(());
+        }
+        plan.approved = true;
+        self.persist_close_plan(Some(&plan))?;
+        info!("{}: approved close plan {}", self.log_prefix(), plan.id.to_hex());
+        *current = Some(plan);
+        Ok(())
+    }
+
+    /// Sign the approved close plan, returning the closing transaction
+    /// signature of each channel.
+    ///
+    /// All the closes are validated again before any is signed, so that a
+    /// plan that no longer passes policy is not partially signed.  Signing
+    /// is deterministic, so after a restart the plan can be signed again to
+    /// resume it.  Closes that were signed and whose channel is no longer
+    /// ready are skipped.
+    pub fn sign_close_plan(&self) -> Result<Vec<(ChannelId, Signature)>, Status> {
+        let mut current = self.close_plan.lock().unwrap();
+        let mut plan =
+            current.clone().ok_or_else(|| failed_precondition("no close plan in progress"))?;
+        if !plan.approved {
+            return Err(failed_precondition(format!(
+                "close plan {} is not approved",
+                plan.id.to_hex()
+            )));
+        }
+
+        let mut pending = Vec::new();
+        for (i, close) in plan.closes.iter().enumerate() {
+            if close.signed && !self.is_channel_ready(&close.channel_id)? {
+                continue;
+            }
+            self.validate_planned_close(close)?;
+            pending.push(i);
+        }
+        self.validate_close_plan_totals(plan.fee_sat, plan.non_wallet_sat)?;
+
+        let mut sigs = Vec::with_capacity(pending.len());
+        for i in pending {
+            let close = plan.closes[i].clone();
+            let sig = self.with_ready_channel(&close.channel_id, |chan| {
+                chan.sign_mutual_close_tx_phase2(
+                    close.to_holder_value_sat,
+                    close.to_counterparty_value_sat,
+                    &close.holder_script,
+                    &close.counterparty_script,
+                    &close.holder_wallet_path_hint,
+                )
+            })?;
+            if !close.signed {
+                plan.closes[i].signed = true;
+                self.persist_close_plan(Some(&plan))?;
+                *current = Some(plan.clone());
+            }
+            sigs.push((close.channel_id, sig));
+        }
+        info!(
+            "{}: signed {} closes of close plan {}",
+            self.log_prefix(),
+            sigs.len(),
+            plan.id.to_hex()
+        );
+        Ok(sigs)
+    }
+
+    /// Remove the close plan in progress, once its closing transactions
+    /// were broadcast or to abandon it
+    pub fn cancel_close_plan(&self) -> Result<(), Status> {
+        let mut current = self.close_plan.lock().unwrap();
+        if let Some(plan) = &*current {
+            self.persist_close_plan(None)?;
+            info!("{}: removed close plan {}", self.log_prefix(), plan.id.to_hex());
+        }
+        *current = None;
+        Ok(())
+    }
+
+    /// Plan the move of this node to a new seed, after a suspected
+    /// compromise of its seed.
+    ///
+    /// `new_node` is the node with the new seed, on the same network.  The
+    /// wallet outputs in `inputs` are swept to the first address of the new
+    /// wallet, less `fee_sat`, and the sweep is signed with our wallet keys.
+    /// The plan is signed with our node key, see [MigrationPlan::digest].
+    /// Our channels are not affected - we keep signing for them with the
+    /// existing channel keys until they close.
+    pub fn plan_seed_migration(
+        &self,
+        new_node: &Node,
+        inputs: &Vec<InteractiveInput>,
+        fee_sat: u64,
+    ) -> Result<MigrationPlan, Status> {
+        let new_node_id = new_node.get_id();
+        if new_node_id == self.get_id() {
+            return Err(invalid_argument("new node has the same seed"));
+        }
+        if new_node.network() != self.network() {
+            return Err(invalid_argument(format!(
+                "network mismatch {} vs {}",
+                new_node.network(),
+                self.network()
+            )));
+        }
+        if inputs.is_empty() {
+            return Err(invalid_argument("no wallet outputs to sweep"));
+        }
+        let input_sat = inputs
+            .iter()
+            .try_fold(0u64, |sum, input| sum.checked_add(input.prev_output.value))
+            .ok_or_else(|| invalid_argument("input value overflow"))?;
+        let value_sat = input_sat
+            .checked_sub(fee_sat)
+            .filter(|value_sat| *value_sat >= MIN_DUST_LIMIT_SATOSHIS)
+            .ok_or_else(|| {
+                invalid_argument(format!("fee {} too large for inputs {}", fee_sat, input_sat))
+            })?;
+
+        let secp_ctx = Secp256k1::signing_only();
+        let new_wallet_path = vec![0; new_node.node_config.key_derivation_style.get_key_path_len()];
+        let new_wallet_pubkey = new_node.get_wallet_pubkey(&secp_ctx, &new_wallet_path)?;
+        let destination = Address::p2wpkh(&new_wallet_pubkey, self.network())
+            .map_err(|err| internal_error(format!("new wallet address: {}", err)))?;
+        let mut sweep_tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: inputs
+                .iter()
+                .map(|input| TxIn {
+                    previous_output: input.outpoint,
+                    script_sig: Script::new(),
+                    sequence: 0xffff_ffff,
+                    witness: vec![],
+                })
+                .collect(),
+            output: vec![TxOut { value: value_sat, script_pubkey: destination.script_pubkey() }],
+        };
+
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            None,
+        );
+        let witvec = self.sign_wallet_inputs(
+            &sweep_tx,
+            inputs,
+            validator.grind_low_r(),
+            validator.verify_signatures(),
+        )?;
+        for (txin, witness) in sweep_tx.input.iter_mut().zip(witvec) {
+            txin.witness = witness;
+        }
+
+        let new_account_xpub = new_node.get_account_extended_pubkey();
+        let digest = MigrationPlan::digest(
+            &self.get_id(),
+            &new_node_id,
+            &new_account_xpub,
+            &sweep_tx.txid(),
+        );
+        let message = Message::from_slice(&digest)
+            .map_err(|err| internal_error(format!("digest failed: {}", err)))?;
+        let signature = sign_ecdsa_verified(
+            &secp_ctx,
+            &message,
+            &self.get_node_secret(),
+            validator.grind_low_r(),
+            validator.verify_signatures(),
+        )?;
+        info!(
+            "{}: planned migration to {}, sweeping {} sat in {}",
+            self.log_prefix(),
+            new_node_id,
+            value_sat,
+            sweep_tx.txid()
+        );
+        Ok(MigrationPlan {
+            old_node_id: self.get_id(),
+            new_node_id,
+            new_account_xpub,
+            sweep_tx,
+            fee_sat,
+            signature,
+        })
+    }
+
+    // Validate a planned close, and return the initial channel ID, the
+    // closing fee and the value sent outside our wallet
+    fn validate_planned_close(
+        &self,
+        close: &PlannedClose,
+    ) -> Result<(ChannelId, u64, u64), Status> {
+        let (id0, channel_value_sat) = self.with_ready_channel(&close.channel_id, |chan| {
+            chan.validator().validate_mutual_close_tx(
+                self,
+                &chan.setup,
+                &chan.enforcement_state,
+                close.to_holder_value_sat,
+                close.to_counterparty_value_sat,
+                &close.holder_script,
+                &close.counterparty_script,
+                &close.holder_wallet_path_hint,
+            )?;
+            Ok((chan.id0, chan.setup.channel_value_sat))
+        })?;
+        let fee_sat = close
+            .to_holder_value_sat
+            .checked_add(close.to_counterparty_value_sat)
+            .and_then(|outputs_sat| channel_value_sat.checked_sub(outputs_sat))
+            .ok_or_else(|| {
+                invalid_argument(format!("close outputs exceed the value of {}", close.channel_id))
+            })?;
+        let non_wallet_sat = match &close.holder_script {
+            Some(script)
+                if close.to_holder_value_sat > 0
+                    && !self.can_spend(&close.holder_wallet_path_hint, script)? =>
+                close.to_holder_value_sat,
+            _ => 0,
+        };
+        Ok((id0, fee_sat, non_wallet_sat))
+    }
+
+    fn validate_close_plan_totals(&self, fee_sat: u64, non_wallet_sat: u64) -> Result<(), Status> {
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            None,
+        );
+        Ok(validator.validate_close_plan(fee_sat, non_wallet_sat)?)
+    }
+
+    fn is_channel_ready(&self, channel_id: &ChannelId) -> Result<bool, Status> {
+        let slot_arc = self.get_channel(channel_id)?;
+        let mut slot = slot_arc.lock().unwrap();
+        Self::update_channel_state(&mut slot)?;
+        Ok(matches!(*slot, ChannelSlot::Ready(_)))
+    }
+
+    fn persist_close_plan(&self, plan: Option<&ClosePlan>) -> Result<(), Status> {
+        self.persister.update_close_plan(&self.get_id(), plan).map_err(|e| {
+            error!("{}: close plan persist failed", self.log_prefix());
+            persist_error("close plan persist failed", e)
+        })
+    }
+
+    /// Returns the wallet UTXOs reserved for fee bumping.
+    pub fn fee_reserve(&self) -> Vec<OutPoint> {
+        self.fee_reserve.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Reserves wallet UTXOs for fee bumping a force close (CPFP).
+    ///
+    /// Reserved UTXOs are not spent by ordinary sends - see
+    /// [Node::sign_onchain_tx] - so that the node can always bump the fee of
+    /// its commitment transaction through an anchor.
+    pub fn add_fee_reserve(&self, outpoints: &Vec<OutPoint>) -> Result<(), Status> {
+        let mut reserve = self.fee_reserve.lock().unwrap();
+        reserve.extend(outpoints.iter().cloned());
+        self.update_fee_reserve(&reserve)
+    }
+
+    /// Releases wallet UTXOs from the fee bumping reserve.
+    pub fn remove_fee_reserve(&self, outpoints: &Vec<OutPoint>) -> Result<(), Status> {
+        let mut reserve = self.fee_reserve.lock().unwrap();
+        for outpoint in outpoints {
+            reserve.remove(outpoint);
+        }
+        self.update_fee_reserve(&reserve)
+    }
+
+    fn update_fee_reserve(&self, reserve: &MutexGuard<OrderedSet<OutPoint>>) -> Result<(), Status> {
+        self.persister
+            .update_fee_reserve(&self.get_id(), reserve.iter().cloned().collect())
+            .map_err(|e| persist_error("fee reserve persist failed", e))
+    }
+
+    /// Whether the transaction spends a UTXO reserved for fee bumping
+    pub(crate) fn spends_fee_reserve(&self, tx: &bitcoin::Transaction) -> bool {
+        let reserve = self.fee_reserve.lock().unwrap();
+        tx.input.iter().any(|input| reserve.contains(&input.previous_output))
+    }
+
+    // A transaction spending reserved UTXOs must be a fee bump, paying
+    // everything but the fee back to our wallet
+    fn validate_fee_reserve_spend(
         &self,
-        descriptors: &[&SpendableOutputDescriptor],
-        outputs: Vec<TxOut>,
-        change_destination_script: Script,
-        feerate_sat_per_1000_weight: u32,
-        secp_ctx: &Secp256k1<All>,
-    ) -> Result<Transaction, ()> {
-        self.keys_manager.spend_spendable_outputs(
-            descriptors,
-            outputs,
-            change_destination_script,
-            feerate_sat_per_1000_weight,
-            secp_ctx,
-        )
+        tx: &bitcoin::Transaction,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<(), ValidationError> {
+        if !self.spends_fee_reserve(tx) {
+            return Ok(());
+        }
+        for (idx, output) in tx.output.iter().enumerate() {
+            let to_wallet = opaths
+                .get(idx)
+                .map(|path| self.can_spend(path, &output.script_pubkey).unwrap_or(false))
+                .unwrap_or(false);
+            // policy-onchain-fee-reserve
+            if !to_wallet {
+                return Err(policy_error(format!(
+                    "sign_onchain_tx: output {} is not to our wallet, \
+                     but the tx spends inputs reserved for fee bumping",
+                    idx
+                )));
+            }
+        }
+        Ok(())
     }
 
     /// Returns the node's current allowlist.
@@ -1430,7 +3507,16 @@ impl Node {
             .collect::<Result<Vec<String>, Status>>()
     }
 
-    /// Adds addresses to the node's current allowlist.
+    /// Adds entries to the node's current allowlist.
+    ///
+    /// An entry is an address, a `payee:<node_id>`, or a range of native
+    /// segwit addresses derived from an xpub - either
+    /// `xpub:<xpub>[/<index>]*[:<gap_limit>]` or the output descriptor
+    /// `wpkh(<xpub>[/<index>]*/*)`.  Without an explicit gap limit
+    /// [DEFAULT_ALLOWLIST_GAP_LIMIT] addresses are allowed.
+    ///
+    /// An address or xpub entry with a `cold:<threshold_sat>:` prefix is a
+    /// cold storage destination, see [Allowable::ColdStorage].
     pub fn add_allowlist(&self, addlist: &Vec<String>) -> Result<(), Status> {
         let allowables = addlist
             .iter()
@@ -1449,7 +3535,7 @@ impl Node {
         let wlvec = (*alset).iter().map(|a| a.to_string(self.network())).collect();
         self.persister
             .update_node_allowlist(&self.get_id(), wlvec)
-            .map_err(|_| internal_error("persist failed"))
+            .map_err(|e| persist_error("allowlist persist failed", e))
     }
 
     /// Removes addresses from the node's current allowlist.
@@ -1467,11 +3553,376 @@ impl Node {
         Ok(())
     }
 
+    /// Get the operator-defined metadata of the node, or of a channel if
+    /// `channel_id` is supplied.
+    pub fn get_metadata(
+        &self,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<(String, String)>, Status> {
+        if let Some(channel_id) = channel_id {
+            self.get_channel(channel_id)?;
+        }
+        self.persister
+            .get_metadata(&self.get_id(), channel_id)
+            .map_err(|e| persist_error("metadata read failed", e))
+    }
+
+    /// Get the audit log of signing operations on a channel, or on the
+    /// whole node if `channel_id` is None, oldest first.
+    ///
+    /// The number of records kept is subject to the retention configuration
+    /// of the persister.
+    pub fn get_audit_log(
+        &self,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<AuditRecord>, Status> {
+        let id0 = match channel_id {
+            Some(channel_id) => Some(self.get_channel(channel_id)?.lock().unwrap().id()),
+            None => None,
+        };
+        self.persister
+            .get_audit_log(&self.get_id(), id0.as_ref())
+            .map_err(|e| persist_error("audit log read failed", e))
+    }
+
+    /// The public key of the audit log signatures, for
+    /// [crate::persist::model::verify_audit_log]
+    pub fn get_audit_pubkey(&self) -> PublicKey {
+        let secp_ctx = Secp256k1::signing_only();
+        PublicKey::from_secret_key(&secp_ctx, self.keys_manager.get_audit_secret())
+    }
+
+    /// Record a signing operation in the audit log, chained to the previous
+    /// record of the channel and signed by the audit key
+    pub(crate) fn append_audit_record(&self, mut record: AuditRecord) -> Result<(), Status> {
+        if record.error.is_some() {
+            POLICY_VIOLATIONS.fetch_add(1, Ordering::Relaxed);
+        } else {
+            SIGN_OPERATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        let mut heads = self.audit_heads.lock().unwrap();
+        let prev_hash = match heads.get(&record.channel_id) {
+            Some(hash) => *hash,
+            None => self
+                .persister
+                .get_audit_log(&self.get_id(), record.channel_id.as_ref())
+                .map_err(|e| persist_error("audit log read failed", e))?
+                .iter()
+                .filter(|r| r.channel_id == record.channel_id)
+                .last()
+                .map(|r| r.hash())
+                .unwrap_or([0; 32]),
+        };
+        record.prev_hash = prev_hash;
+        let hash = record.hash();
+        let secp_ctx = Secp256k1::signing_only();
+        let message = Message::from_slice(&hash).expect("sha256");
+        let sig = sign_ecdsa_verified(
+            &secp_ctx,
+            &message,
+            self.keys_manager.get_audit_secret(),
+            false,
+            self.verify_signatures(),
+        )?;
+        record.signature = sig.serialize_der().to_vec();
+        self.persister.append_audit_record(&self.get_id(), &record).map_err(|e| {
+            error!("{}: audit log persist failed: {:?}", self.log_prefix(), record);
+            persist_error("audit log persist failed", e)
+        })?;
+        heads.insert(record.channel_id, hash);
+        Ok(())
+    }
+
+    // Record a failed validation in the audit log, and return the failure.
+    // The failure is returned even if it can't be recorded.
+    pub(crate) fn audit_failure(
+        &self,
+        record: &AuditRecord,
+        ve: ValidationError,
+    ) -> ValidationError {
+        let mut record = record.clone();
+        record.error = Some(ve.clone().into());
+        let _ = self.append_audit_record(record);
+        ve
+    }
+
+    /// Set operator-defined metadata on the node, or on a channel if
+    /// `channel_id` is supplied.
+    ///
+    /// The entries are merged into the existing metadata, and entries with
+    /// an empty value are removed.  The signer does not interpret the
+    /// metadata.
+    pub fn set_metadata(
+        &self,
+        channel_id: Option<&ChannelId>,
+        entries: &Vec<(String, String)>,
+    ) -> Result<(), Status> {
+        // Holding the channels lock serializes metadata updates
+        let channels = self.channels.lock().unwrap();
+        if let Some(channel_id) = channel_id {
+            channels.get(channel_id).ok_or_else(|| invalid_argument("no such channel"))?;
+        }
+        let metadata = self
+            .persister
+            .get_metadata(&self.get_id(), channel_id)
+            .map_err(|e| persist_error("metadata read failed", e))?;
+        let mut metadata = OrderedMap::from_iter(metadata);
+        for (key, value) in entries {
+            if key.is_empty() || key.len() > MAX_METADATA_KEY_LEN {
+                return Err(invalid_argument(format!(
+                    "metadata key length {} not in 1..{}",
+                    key.len(),
+                    MAX_METADATA_KEY_LEN
+                )));
+            }
+            if value.len() > MAX_METADATA_VALUE_LEN {
+                return Err(invalid_argument(format!(
+                    "metadata value for {} too long: {} > {}",
+                    key,
+                    value.len(),
+                    MAX_METADATA_VALUE_LEN
+                )));
+            }
+            if value.is_empty() {
+                metadata.remove(key);
+            } else {
+                metadata.insert(key.clone(), value.clone());
+            }
+        }
+        if metadata.len() > MAX_METADATA_ENTRIES {
+            return Err(invalid_argument(format!(
+                "too many metadata entries: {} > {}",
+                metadata.len(),
+                MAX_METADATA_ENTRIES
+            )));
+        }
+        self.persister
+            .update_metadata(&self.get_id(), channel_id, metadata.into_iter().collect())
+            .map_err(|e| persist_error("metadata persist failed", e))
+    }
+
+    /// Register a listener for node events
+    pub fn add_event_listener(&self, listener: Arc<dyn NodeEventListener>) {
+        self.event_listeners.lock().unwrap().push(listener);
+    }
+
+    pub(crate) fn notify_event(&self, event: NodeEvent) {
+        match event {
+            NodeEvent::Breach { .. }
+            | NodeEvent::ChainSuspect { .. }
+            | NodeEvent::ClockSkew { .. } => {
+                warn!("{}: {:?}", self.log_prefix(), event)
+            }
+            _ => debug!("{}: {:?}", self.log_prefix(), event),
+        }
+        for listener in self.event_listeners.lock().unwrap().iter() {
+            listener.on_event(&event);
+        }
+    }
+
+    /// Export a watchtower blob on each counterparty revocation, or stop
+    /// exporting if `None`
+    pub fn set_watchtower_export(&self, export: Option<WatchtowerExport>) {
+        *self.watchtower_export.lock().unwrap() = export;
+    }
+
+    /// How watchtower blobs are exported, if they are
+    pub fn watchtower_export(&self) -> Option<WatchtowerExport> {
+        self.watchtower_export.lock().unwrap().clone()
+    }
+
+    pub(crate) fn queue_watchtower_blob(&self, blob: WatchtowerBlob) {
+        let sequence = self.watchtower_blobs.lock().unwrap().push(blob);
+        debug!("{}: queued watchtower blob {}", self.log_prefix(), sequence);
+    }
+
+    /// The watchtower blobs exported after the one numbered `sequence`,
+    /// oldest first, with their sequence numbers.  Use zero to get all the
+    /// blobs still held, up to [crate::watchtower::MAX_WATCHTOWER_BLOBS].
+    pub fn watchtower_blobs_since(
+        &self,
+        sequence: u64,
+    ) -> impl Iterator<Item = (u64, WatchtowerBlob)> {
+        self.watchtower_blobs.lock().unwrap().since(sequence).into_iter()
+    }
+
     /// Chain tracker with lock
     pub fn get_tracker(&self) -> MutexGuard<'_, ChainTracker<ChainMonitor>> {
         self.tracker.lock().unwrap()
     }
 
+    /// Remove the block at the chain tip, in a reorg.
+    ///
+    /// If the chain tracker refuses a reorg that is too deep, the node is
+    /// marked as chain-suspect and a [NodeEvent::ChainSuspect] is raised.
+    /// Reorgs are refused from then on, until [Node::clear_chain_suspect].
+    pub fn remove_block(
+        &self,
+        txs: Vec<Transaction>,
+        txs_proof: Option<PartialMerkleTree>,
+    ) -> Result<BlockHeader, Status> {
+        let mut tracker = self.tracker.lock().unwrap();
+        let was_suspect = tracker.suspect;
+        let result = tracker.remove_block(txs, txs_proof);
+        if tracker.suspect && !was_suspect {
+            if let Err(e) = self.persister.update_tracker(&self.get_id(), &tracker) {
+                error!("{}: tracker persist failed: {}", self.log_prefix(), e);
+            }
+            self.notify_event(NodeEvent::ChainSuspect {
+                height: tracker.height(),
+                block_hash: tracker.tip().block_hash(),
+            });
+        }
+        result.map_err(|e| {
+            failed_precondition(format!(
+                "remove block at height {} failed: {:?}",
+                tracker.height(),
+                e
+            ))
+        })
+    }
+
+    /// Whether the node is chain-suspect, see [Node::remove_block]
+    pub fn is_chain_suspect(&self) -> bool {
+        self.tracker.lock().unwrap().suspect
+    }
+
+    /// Follow reorgs again, after the operator investigated a refused
+    /// deep reorg
+    pub fn clear_chain_suspect(&self) -> Result<(), Status> {
+        let mut tracker = self.tracker.lock().unwrap();
+        tracker.clear_suspect();
+        self.persister
+            .update_tracker(&self.get_id(), &tracker)
+            .map_err(|e| persist_error("tracker persist failed", e))
+    }
+
+    /// Cross-check the open and closing channels against the chain tracker
+    /// watches.
+    ///
+    /// A channel that is missing from the tracker is watched from the current
+    /// height on.  Watches without a channel are only reported.
+    /// This is done when the node is restored, so that channels without
+    /// chain monitoring are caught early rather than at force-close.
+    pub fn check_tracker_watches(&self) -> Vec<TrackerDiscrepancy> {
+        let mut tracker = self.tracker.lock().unwrap();
+        let mut discrepancies = Vec::new();
+        let mut monitors = Vec::new();
+        for (channel_id, slot_arc) in self.channels.lock().unwrap().iter() {
+            let slot = slot_arc.lock().unwrap();
+            let chan = match &*slot {
+                ChannelSlot::Ready(chan) | ChannelSlot::Closing(chan) => chan,
+                ChannelSlot::Stub(_) | ChannelSlot::Closed(_) => continue,
+            };
+            // Channels are listed under both of their IDs
+            if monitors.iter().any(|m: &ChainMonitor| Arc::ptr_eq(&m.state, &chan.monitor.state)) {
+                continue;
+            }
+            monitors.push(chan.monitor.clone());
+            let watched =
+                tracker.listeners.keys().any(|m| Arc::ptr_eq(&m.state, &chan.monitor.state));
+            if !watched {
+                let funding_outpoint = chan.setup.funding_outpoint;
+                warn!(
+                    "{}: channel {} with funding {} is not watched",
+                    self.log_prefix(),
+                    channel_id,
+                    funding_outpoint
+                );
+                let needs_funding = {
+                    let mut state = chan.monitor.get_state();
+                    state.height = tracker.height();
+                    state.funding_txids.is_empty()
+                };
+                if needs_funding {
+                    chan.monitor.add_funding_outpoint(&funding_outpoint);
+                }
+                tracker.add_listener(
+                    chan.monitor.clone(),
+                    OrderedSet::from_iter(vec![funding_outpoint.txid]),
+                );
+                discrepancies.push(TrackerDiscrepancy::UnwatchedChannel {
+                    channel_id: *channel_id,
+                    funding_outpoint,
+                });
+            }
+        }
+        for listener in tracker.listeners.keys() {
+            // A registered funding is watched before its channel is ready
+            let registered = listener.get_state().expected_funding_value_sat.is_some();
+            if !registered && !monitors.iter().any(|m| Arc::ptr_eq(&m.state, &listener.state)) {
+                warn!(
+                    "{}: watch for funding {} has no channel",
+                    self.log_prefix(),
+                    listener.funding_outpoint
+                );
+                discrepancies.push(TrackerDiscrepancy::OrphanedWatch {
+                    funding_outpoint: listener.funding_outpoint,
+                });
+            }
+        }
+        let repaired =
+            discrepancies.iter().any(|d| matches!(d, TrackerDiscrepancy::UnwatchedChannel { .. }));
+        if repaired {
+            if let Err(e) = self.persister.update_tracker(&self.get_id(), &tracker) {
+                error!("{}: tracker persist failed: {}", self.log_prefix(), e);
+            }
+        }
+        discrepancies
+    }
+
+    /// Cross-check the host clock against the timestamp of the chain tip.
+    ///
+    /// `now` is the host clock as a duration since the UNIX epoch.
+    /// The resulting skew is remembered and made available to the
+    /// validator for time-delay dependent operations, until the chain
+    /// moves [MAX_CLOCK_SKEW_SAMPLE_AGE_BLOCKS] past the current tip.
+    /// Hosts should call this periodically.
+    ///
+    /// Returns the skew, or a failed precondition if it is outside the
+    /// policy range.  A [NodeEvent::ClockSkew] is raised when the skew goes
+    /// out of range.
+    pub fn heartbeat(&self, now: Duration) -> Result<i64, Status> {
+        let (tip_time, height) = {
+            let tracker = self.get_tracker();
+            (tracker.tip().time, tracker.height())
+        };
+        let skew = now.as_secs() as i64 - tip_time as i64;
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            None,
+        );
+        let result = validator.validate_clock_skew(skew);
+        let sample = ClockSkewSample {
+            skew_secs: skew,
+            sampled_at: now.as_secs(),
+            height,
+            within_policy: result.is_ok(),
+        };
+        let previous = self.clock_skew.lock().unwrap().replace(sample);
+        if let Err(ve) = result {
+            warn!("{}: clock skew check failed: {}", self.log_prefix(), ve);
+            // Only raise an event when the skew goes out of range
+            if previous.map(|p| p.within_policy).unwrap_or(true) {
+                self.notify_event(NodeEvent::ClockSkew { clock_skew_secs: skew, height });
+            }
+            return Err(ve.into());
+        }
+        Ok(skew)
+    }
+
+    /// The last heartbeat cross-check of the host clock, even if stale
+    pub fn clock_skew(&self) -> Option<ClockSkewSample> {
+        *self.clock_skew.lock().unwrap()
+    }
+
+    /// The host clock minus the chain tip timestamp, as of the last
+    /// heartbeat, unless the sample is stale at chain height `height`
+    pub fn fresh_clock_skew_secs(&self, height: u32) -> Option<i64> {
+        self.clock_skew().filter(|s| s.is_fresh(height)).map(|s| s.skew_secs)
+    }
+
     // Process payment preimages for offered HTLCs.
     // Any invoice with a payment hash that matches a preimage is marked
     // as paid, so that the offered HTLC can be removed and our balance
@@ -1480,12 +3931,15 @@ impl Node {
         &self,
         channel_id: &ChannelId,
         preimages: Vec<PaymentPreimage>,
-        validator: Arc<dyn Validator>,
+        validator: Arc<dyn Validator<CoreTypes>>,
     ) {
         let mut state = self.state.lock().unwrap();
         for preimage in preimages.into_iter() {
             state.htlc_fulfilled(channel_id, preimage, Arc::clone(&validator));
         }
+        if let Err(e) = self.persist_ledger(&mut state) {
+            error!("{}: {}", self.log_prefix(), e);
+        }
     }
 
     /// Add an invoice.
@@ -1510,11 +3964,53 @@ impl Node {
                 ))
             };
         }
+        // The ledger outlives the invoice tracking state, so an invoice that
+        // was paid before a restart is caught here
+        if let Some(entry) = state.ledger.get(&hash) {
+            let validator = self.validator_factory.lock().unwrap().make_validator(
+                self.network(),
+                self.get_id(),
+                None,
+            );
+            validator.validate_payment_duplicate(entry)?;
+        }
+        state.record_payment(PaymentLedgerEntry {
+            payment_hash: hash,
+            outgoing: true,
+            amount_msat: invoice_state.amount_msat,
+            invoice_hash,
+            expiry_time: (invoice_state.duration_since_epoch + invoice_state.expiry_duration)
+                .as_secs(),
+            resolution: PaymentResolution::Pending,
+        });
         state.invoices.insert(hash, invoice_state);
         state.payments.insert(hash, RoutedPayment::new());
+        self.persist_ledger(&mut state)
+    }
+
+    /// Persist the payment ledger entries changed since the last call
+    pub(crate) fn persist_ledger(&self, state: &mut NodeState) -> Result<(), Status> {
+        let node_id = self.get_id();
+        for entry in state.take_ledger_updates() {
+            self.persister
+                .update_payment(&node_id, &entry)
+                .map_err(|e| persist_error("payment ledger persist failed", e))?;
+        }
         Ok(())
     }
 
+    /// The payment ledger, for balance reconciliation.
+    ///
+    /// Pending payments whose invoice expired before the chain tip are
+    /// marked as expired first.
+    pub fn payment_ledger(&self) -> Result<Vec<PaymentLedgerEntry>, Status> {
+        let tip_time = self.get_tracker().tip().time as u64;
+        let mut state = self.state.lock().unwrap();
+        state.expire_payments(tip_time);
+        self.persist_ledger(&mut state)?;
+        Ok(state.ledger.values().cloned().collect())
+    }
+
     // Validate the invoice and create a tracking state for it
     fn invoice_state_from_invoice(
         raw_invoice: SignedRawInvoice,
@@ -1550,7 +4046,7 @@ fn find_channel_with_funding_outpoint(
     for (_, slot_arc) in channels_lock.iter() {
         let slot = slot_arc.lock().unwrap();
         match &*slot {
-            ChannelSlot::Ready(chan) =>
+            ChannelSlot::Ready(chan) | ChannelSlot::Closing(chan) | ChannelSlot::Closed(chan) =>
                 if chan.setup.funding_outpoint == *outpoint {
                     return Some(Arc::clone(slot_arc));
                 },
@@ -1582,6 +4078,8 @@ pub enum SpendType {
     P2shP2wpkh = 4,
     /// Pay to witness script hash
     P2wsh = 5,
+    /// Pay to taproot, a BIP-86 key path spend
+    P2tr = 6,
 }
 
 impl TryFrom<i32> for SpendType {
@@ -1594,12 +4092,34 @@ impl TryFrom<i32> for SpendType {
             x if x == SpendType::P2wpkh as i32 => SpendType::P2wpkh,
             x if x == SpendType::P2shP2wpkh as i32 => SpendType::P2shP2wpkh,
             x if x == SpendType::P2wsh as i32 => SpendType::P2wsh,
+            x if x == SpendType::P2tr as i32 => SpendType::P2tr,
             _ => return Err(()),
         };
         Ok(res)
     }
 }
 
+// How a wallet input of a PSBT is signed
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum PsbtInputKind {
+    P2wpkh,
+    P2shP2wpkh,
+    P2tr,
+}
+
+/// An output of a unilateral close of one of our channels, to be swept
+/// with a channel key by [Node::sign_onchain_tx]
+#[derive(Clone, Debug)]
+pub struct UnilateralCloseInfo {
+    /// The channel that was closed
+    pub channel_id: ChannelId,
+    /// The per-commitment point of the counterparty's commitment, or None
+    /// with `option_static_remotekey`
+    pub commitment_point: Option<PublicKey>,
+    /// The revocation pubkey, if sweeping our delayed output
+    pub revocation_pubkey: Option<PublicKey>,
+}
+
 /// Marker trait for LDK compatible logger
 pub trait SyncLogger: Logger + SendSync {}
 
@@ -1607,6 +4127,7 @@ pub trait SyncLogger: Logger + SendSync {}
 mod tests {
     use bitcoin;
     use bitcoin::bech32::{CheckBase32, ToBase32};
+    use bitcoin::blockdata::script::Builder;
     use bitcoin::consensus::deserialize;
     use bitcoin::hashes::sha256d::Hash as Sha256dHash;
     use bitcoin::hashes::Hash;
@@ -1614,15 +4135,23 @@ mod tests {
     use bitcoin::secp256k1::recovery::{RecoverableSignature, RecoveryId};
     use bitcoin::secp256k1::SecretKey;
     use bitcoin::util::bip143::SigHashCache;
-    use bitcoin::{Address, OutPoint, SigHashType};
+    use bitcoin::util::bip32::DerivationPath;
+    use bitcoin::{Address, OutPoint, SigHashType, TxIn};
     use lightning::ln::chan_utils::derive_private_key;
     use lightning::ln::{chan_utils, PaymentSecret};
     use lightning_invoice::{Currency, InvoiceBuilder};
     use test_log::test;
 
-    use crate::channel::ChannelBase;
+    use crate::chain::tracker::ChainListener;
+    use crate::channel::{ChannelBase, CommitmentBalance, CommitmentType, CLOSED_DEPTH};
+    use crate::persist::model::PaymentLedgerTotals;
+    use crate::persist::DummyPersister;
     use crate::policy::simple_validator::{make_simple_policy, SimpleValidatorFactory};
-    use crate::util::status::{internal_error, invalid_argument, Code, Status};
+    use crate::policy::validator::SigningIntent;
+    use crate::tx::tx::HTLCInfo2;
+    use crate::util::crypto_utils::verify_ecdsa;
+    use crate::util::key_utils::make_test_pubkey;
+    use crate::util::status::{internal_error, invalid_argument, persist_error, Code, Status};
     use crate::util::test_utils::*;
 
     use super::*;
@@ -1637,6 +4166,209 @@ mod tests {
         });
     }
 
+    #[test]
+    fn check_tracker_watches_test() {
+        let setup = make_test_channel_setup();
+        let funding_outpoint = setup.funding_outpoint;
+        let (node, channel_id) = init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup);
+        assert_eq!(node.check_tracker_watches(), vec![]);
+
+        // The channel watch is lost, and is added back
+        let monitor =
+            node.with_ready_channel(&channel_id, |chan| Ok(chan.monitor.clone())).unwrap();
+        node.get_tracker().remove_listener(&monitor);
+        assert_eq!(
+            node.check_tracker_watches(),
+            vec![TrackerDiscrepancy::UnwatchedChannel { channel_id, funding_outpoint }]
+        );
+        assert!(node.get_tracker().listeners.contains_key(&monitor));
+        assert_eq!(node.check_tracker_watches(), vec![]);
+
+        // A watch without a channel is reported
+        let orphan_outpoint = OutPoint { txid: Txid::default(), vout: 7 };
+        {
+            let mut tracker = node.get_tracker();
+            let height = tracker.height();
+            tracker.add_listener(ChainMonitor::new(orphan_outpoint, height), OrderedSet::new());
+        }
+        assert_eq!(
+            node.check_tracker_watches(),
+            vec![TrackerDiscrepancy::OrphanedWatch { funding_outpoint: orphan_outpoint }]
+        );
+    }
+
+    #[test]
+    fn new_channel_with_dbid_test() {
+        let peer_id = make_test_pubkey(9);
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let (channel_id, stub) = node.new_channel_with_dbid(&peer_id, 42, &node).unwrap();
+        let stub = stub.unwrap();
+        let mut nonce = peer_id.serialize().to_vec();
+        nonce.extend_from_slice(&[42, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(stub.nonce, nonce);
+        assert_eq!(channel_id, channel_nonce_to_id(&nonce));
+
+        // The keys only depend on the seed and the channel metadata
+        let restored_node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let (_, restored_stub) =
+            restored_node.new_channel_with_dbid(&peer_id, 42, &restored_node).unwrap();
+        assert!(restored_stub.unwrap().get_channel_basepoints() == stub.get_channel_basepoints());
+        let (_, other_stub) = node.new_channel_with_dbid(&peer_id, 43, &node).unwrap();
+        assert!(other_stub.unwrap().get_channel_basepoints() != stub.get_channel_basepoints());
+
+        let mut lnd_config = TEST_NODE_CONFIG;
+        lnd_config.key_derivation_style = KeyDerivationStyle::Lnd;
+        let lnd_node = init_node(lnd_config, TEST_SEED[1]);
+        assert_invalid_argument_err!(
+            lnd_node.new_channel_with_dbid(&peer_id, 42, &lnd_node),
+            "channel keys can't be derived from the dbid with lnd"
+        );
+    }
+
+    #[test]
+    fn register_funding_test() {
+        let node = init_node(REGTEST_NODE_CONFIG, TEST_SEED[1]);
+        let channel_nonce = "nonce1".as_bytes().to_vec();
+        let channel_id = channel_nonce_to_id(&channel_nonce);
+        node.new_channel(Some(channel_id), Some(channel_nonce), &node).expect("new_channel");
+
+        let mut setup = make_test_channel_setup();
+        let mut tx = make_tx(vec![make_txin(1)]);
+        tx.output[0].value = setup.channel_value_sat;
+        setup.funding_outpoint = OutPoint::new(tx.txid(), 0);
+
+        let unknown_id = channel_nonce_to_id(&"nonce2".as_bytes().to_vec());
+        assert_invalid_argument_err!(
+            node.register_funding(&unknown_id, setup.funding_outpoint, setup.channel_value_sat),
+            format!("channel does not exist: {}", unknown_id)
+        );
+        node.register_funding(&channel_id, setup.funding_outpoint, setup.channel_value_sat)
+            .expect("register_funding");
+        assert_invalid_argument_err!(
+            node.register_funding(&channel_id, setup.funding_outpoint, setup.channel_value_sat),
+            format!("funding outpoint already watched: {}", setup.funding_outpoint)
+        );
+        // a registered watch is not an orphan
+        assert_eq!(node.check_tracker_watches(), vec![]);
+
+        // the channel value must match the registration
+        let mut bad_setup = setup.clone();
+        bad_setup.channel_value_sat -= 1;
+        assert_failed_precondition_err!(
+            node.ready_channel(channel_id, None, bad_setup, &vec![]),
+            format!(
+                "policy failure: channel value {} does not match registered value {}",
+                setup.channel_value_sat - 1,
+                setup.channel_value_sat
+            )
+        );
+
+        // the funding must be confirmed
+        assert_failed_precondition_err!(
+            node.ready_channel(channel_id, None, setup.clone(), &vec![]),
+            format!(
+                "policy failure: registered funding {} was not observed on-chain",
+                setup.funding_outpoint
+            )
+        );
+
+        let block = make_block(node.get_tracker().tip(), vec![tx]);
+        let proof = proof_for_block(&block);
+        node.get_tracker().add_block(block.header, block.txdata.clone(), proof).unwrap();
+
+        node.ready_channel(channel_id, None, setup, &vec![]).expect("ready_channel");
+        let depth =
+            node.with_ready_channel(&channel_id, |chan| Ok(chan.monitor.funding_depth())).unwrap();
+        assert_eq!(depth, 1);
+        assert_eq!(node.check_tracker_watches(), vec![]);
+    }
+
+    #[test]
+    fn register_funding_value_mismatch_test() {
+        let node = init_node(REGTEST_NODE_CONFIG, TEST_SEED[1]);
+        let channel_nonce = "nonce1".as_bytes().to_vec();
+        let channel_id = channel_nonce_to_id(&channel_nonce);
+        node.new_channel(Some(channel_id), Some(channel_nonce), &node).expect("new_channel");
+
+        // the funding transaction pays less than the registered value
+        let mut setup = make_test_channel_setup();
+        let mut tx = make_tx(vec![make_txin(1)]);
+        tx.output[0].value = setup.channel_value_sat - 1;
+        setup.funding_outpoint = OutPoint::new(tx.txid(), 0);
+        node.register_funding(&channel_id, setup.funding_outpoint, setup.channel_value_sat)
+            .expect("register_funding");
+
+        let block = make_block(node.get_tracker().tip(), vec![tx]);
+        let proof = proof_for_block(&block);
+        node.get_tracker().add_block(block.header, block.txdata.clone(), proof).unwrap();
+
+        assert_failed_precondition_err!(
+            node.ready_channel(channel_id, None, setup.clone(), &vec![]),
+            format!(
+                "policy failure: funding output value {} does not match registered value {}",
+                setup.channel_value_sat - 1,
+                setup.channel_value_sat
+            )
+        );
+    }
+
+    #[test]
+    fn require_funding_registration_test() {
+        let node = init_node(REGTEST_NODE_CONFIG, TEST_SEED[1]);
+        let mut policy = make_simple_policy(Network::Regtest);
+        policy.require_funding_registration = true;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        let channel_nonce = "nonce1".as_bytes().to_vec();
+        let channel_id = channel_nonce_to_id(&channel_nonce);
+        node.new_channel(Some(channel_id), Some(channel_nonce), &node).expect("new_channel");
+
+        let setup = make_test_channel_setup();
+        assert_failed_precondition_err!(
+            node.ready_channel(channel_id, None, setup.clone(), &vec![]),
+            format!("policy failure: funding {} was not registered", setup.funding_outpoint)
+        );
+    }
+
+    #[test]
+    fn chain_params_test() {
+        let signet = ChainParams::for_network(Network::Signet);
+        assert_eq!(signet.genesis_hash(), genesis_block(Network::Signet).block_hash());
+        assert!(signet.signet_challenge.is_some());
+        assert_eq!(TEST_NODE_CONFIG.chain_params(), ChainParams::for_network(Network::Testnet));
+
+        // A regtest chain with a non-standard genesis block
+        let mut genesis = genesis_block(Network::Regtest).header;
+        genesis.time += 1;
+        while genesis.validate_pow(&genesis.target()).is_err() {
+            genesis.nonce += 1;
+        }
+        let mut config = REGTEST_NODE_CONFIG;
+        config.chain_params = Some(ChainParams { genesis, signet_challenge: None });
+        let node = init_node(config, TEST_SEED[1]);
+        assert_eq!(node.get_tracker().tip(), genesis);
+    }
+
+    #[test]
+    fn checkpoint_test() {
+        let genesis = Checkpoint::genesis(Network::Regtest);
+        let header = make_header(genesis.header, Default::default());
+        let chainwork = genesis.chainwork + header.work();
+        let mut config = REGTEST_NODE_CONFIG;
+        config.checkpoint = Some(Checkpoint { height: 1, header, chainwork });
+        let node = init_node(config.clone(), TEST_SEED[1]);
+        let tracker = node.get_tracker();
+        assert_eq!(tracker.height(), 1);
+        assert_eq!(tracker.tip(), header);
+        assert_eq!(tracker.chainwork, chainwork);
+
+        // a checkpoint with less than the minimum work is an error
+        config.checkpoint = Some(Checkpoint { height: 1, header, chainwork: header.work() });
+        let persister: Arc<dyn Persist> = Arc::new(DummyPersister {});
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let err = Node::new(config, &[0; 32], &persister, vec![], validator_factory).unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+
     #[test]
     fn node_debug_test() {
         let (node, _channel_id) =
@@ -1658,6 +4390,16 @@ mod tests {
         assert_eq!(err.message(), "testing internal_error");
     }
 
+    #[test]
+    fn node_persist_error_test() {
+        let err = persist_error("testing", crate::persist::Error::Unavailable("disk".to_string()));
+        assert_eq!(err.code(), Code::Internal);
+        assert_eq!(err.message(), "testing: unavailable: disk");
+        assert!(err.is_retryable());
+        let err = persist_error("testing", crate::persist::Error::NotFound("channel".to_string()));
+        assert!(!err.is_retryable());
+    }
+
     #[test]
     fn new_channel_test() {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
@@ -1752,6 +4494,61 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn payment_retry_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let mut state = node.state.lock().unwrap();
+        let channel_id = ChannelId([1; 32]);
+        let hash = PaymentHash([2; 32]);
+
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.require_invoices = false;
+        policy.max_payment_retries = 1;
+        let validator = SimpleValidatorFactory::new_with_policy(policy).make_validator(
+            Network::Testnet,
+            node.get_id(),
+            None,
+        );
+
+        let mut cstate = make_test_chain_state();
+        let update = |state: &mut NodeState, cstate: &ChainState, amount: u64| {
+            let outgoing = vec![(hash, amount)].into_iter().collect();
+            let delta = Default::default();
+            state.validate_payments(
+                &channel_id,
+                &Map::new(),
+                &outgoing,
+                &delta,
+                cstate,
+                validator.clone(),
+            )?;
+            state.apply_payments(
+                &channel_id,
+                &Map::new(),
+                &outgoing,
+                &delta,
+                cstate,
+                validator.clone(),
+            );
+            Ok::<_, ValidationError>(())
+        };
+
+        update(&mut state, &cstate, 5).expect("first attempt");
+        // the same HTLC in a later commitment is not a new attempt
+        update(&mut state, &cstate, 5).expect("same attempt");
+        update(&mut state, &cstate, 0).expect("fail");
+        cstate.current_height += 1;
+        update(&mut state, &cstate, 5).expect("retry");
+        update(&mut state, &cstate, 0).expect("fail");
+        cstate.current_height += 1;
+        assert!(update(&mut state, &cstate, 5).is_err());
+        assert_eq!(state.payments.get(&hash).unwrap().attempt_heights, vec![1000, 1001]);
+
+        // the failed attempts age out of the retry window
+        cstate.current_height = 1145;
+        update(&mut state, &cstate, 5).expect("retry after the window");
+    }
+
     fn make_test_invoice(
         payee_node: &Arc<Node>,
         description: &str,
@@ -1786,8 +4583,48 @@ mod tests {
         let hash = PaymentHash(Sha256Hash::hash(&preimage.0).into_inner());
 
         let invoice = make_test_invoice(&payee_node, "invoice", hash);
-
-        node.add_invoice(invoice).expect("add invoice");
+
+        node.add_invoice(invoice).expect("add invoice");
+
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.require_invoices = true;
+        policy.enforce_balance = true;
+        let factory = SimpleValidatorFactory::new_with_policy(policy);
+        let invoice_validator = factory.make_validator(Network::Testnet, node.get_id(), None);
+        node.set_validator_factory(Arc::new(factory));
+
+        {
+            let mut state = node.state.lock().unwrap();
+            assert!(state
+                .validate_and_apply_payments(
+                    &channel_id,
+                    &Map::new(),
+                    &vec![(hash, 110)].into_iter().collect(),
+                    &Default::default(),
+                    invoice_validator.clone()
+                )
+                .is_ok());
+        }
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.htlcs_fulfilled(vec![preimage]);
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn payment_ledger_test() {
+        let payee_node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let preimage = PaymentPreimage([0; 32]);
+        let hash = PaymentHash(Sha256Hash::hash(&preimage.0).into_inner());
+        let unpaid_hash = PaymentHash([7; 32]);
+
+        let invoice = make_test_invoice(&payee_node, "invoice", hash);
+        node.add_invoice(invoice.clone()).expect("add invoice");
+        let unpaid_invoice = make_test_invoice(&payee_node, "unpaid", unpaid_hash);
+        node.add_invoice(unpaid_invoice).expect("add invoice");
 
         let mut policy = make_simple_policy(Network::Testnet);
         policy.require_invoices = true;
@@ -1798,21 +4635,41 @@ mod tests {
 
         {
             let mut state = node.state.lock().unwrap();
-            assert!(state
+            let entry = state.ledger.get(&hash).unwrap();
+            assert!(entry.outgoing);
+            assert_eq!(entry.amount_msat, 100_000);
+            assert_eq!(entry.resolution, PaymentResolution::Pending);
+            state
                 .validate_and_apply_payments(
                     &channel_id,
                     &Map::new(),
                     &vec![(hash, 110)].into_iter().collect(),
                     &Default::default(),
-                    invoice_validator.clone()
+                    invoice_validator.clone(),
                 )
-                .is_ok());
+                .expect("pay");
         }
         node.with_ready_channel(&channel_id, |chan| {
             chan.htlcs_fulfilled(vec![preimage]);
             Ok(())
         })
         .unwrap();
+
+        // the unpaid invoice expired long before the chain tip
+        let ledger = node.payment_ledger().unwrap();
+        let resolutions: Vec<_> = ledger.iter().map(|e| (e.payment_hash, e.resolution)).collect();
+        assert_eq!(
+            resolutions,
+            vec![(unpaid_hash, PaymentResolution::Expired), (hash, PaymentResolution::Fulfilled)]
+        );
+        let totals = PaymentLedgerTotals::new(&ledger);
+        assert_eq!(totals.paid_msat, 100_000);
+        assert_eq!(totals.pending_outgoing_msat, 0);
+
+        // forget the invoice, as after a restart - the ledger still
+        // prevents paying it again
+        node.state.lock().unwrap().invoices.clear();
+        assert!(node.add_invoice(invoice).is_err());
     }
 
     #[test]
@@ -2018,7 +4875,7 @@ mod tests {
 
         let ann = hex_decode("0123456789abcdef").unwrap();
         let (nsig, bsig) = node
-            .with_ready_channel(&channel_id, |chan| Ok(chan.sign_channel_announcement(&ann)))
+            .with_ready_channel(&channel_id, |chan| chan.sign_channel_announcement(&ann))
             .unwrap();
 
         let ca_hash = Sha256dHash::hash(&ann);
@@ -2034,21 +4891,82 @@ mod tests {
     #[test]
     fn sign_node_announcement_test() -> Result<(), ()> {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
-        let ann = hex_decode("000302aaa25e445fef0265b6ab5ec860cd257865d61ef0bbf5b3339c36cbda8b26b74e7f1dca490b65180265b64c4f554450484f544f2d2e302d3139392d67613237336639642d6d6f646465640000").unwrap();
+        let ann = hex_decode("000302aaa25e445fef022d223620a359a47ff7f7ac447c85c46c923da53389221a0054c11c1e3ca31d590265b64c4f554450484f544f2d2e302d3139392d67613237336639642d6d6f646465640000").unwrap();
         let sigvec = node.sign_node_announcement(&ann).unwrap().serialize_der().to_vec();
-        assert_eq!(sigvec, hex_decode("30450221008ef1109b95f127a7deec63b190b72180f0c2692984eaf501c44b6bfc5c4e915502207a6fa2f250c5327694967be95ff42a94a9c3d00b7fa0fbf7daa854ceb872e439").unwrap());
+        assert_eq!(sigvec, hex_decode("3044022056c4c0e8b46710c042b910bcf3e285af10f439121005164e956a4f5a30daec5a022056342358703bb6bdfe5662761d90a7ec6192e725b8eee2e7dfa75040a307e64a").unwrap());
+
+        // the announcement of another node
+        let other = hex_decode("000302aaa25e445fef0265b6ab5ec860cd257865d61ef0bbf5b3339c36cbda8b26b74e7f1dca490b65180265b64c4f554450484f544f2d2e302d3139392d67613237336639642d6d6f646465640000").unwrap();
+        assert_failed_precondition_err!(
+            node.sign_node_announcement(&other),
+            "policy failure: node announcement for 0265b6ab5ec860cd257865d61ef0bbf5b3339c36cbda8b26b74e7f1dca490b6518, not this node"
+        );
+        assert_invalid_argument_err!(
+            node.sign_node_announcement(&ann[..40].to_vec()),
+            "node announcement too short"
+        );
         Ok(())
     }
 
     #[test]
-    fn sign_channel_update_test() -> Result<(), ()> {
+    fn sign_verified_test() {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
-        let cu = hex_decode("06226e46111a0b59caaf126043eb5bbf28c34f3a5e332a1fc7b2b73cf188910f00006700000100015e42ddc6010000060000000000000000000000010000000a000000003b023380").unwrap();
+        let ann = hex_decode("000302aaa25e445fef022d223620a359a47ff7f7ac447c85c46c923da53389221a0054c11c1e3ca31d590265b64c4f554450484f544f2d2e302d3139392d67613237336639642d6d6f646465640000").unwrap();
+        let unverified = node.sign_node_announcement(&ann).unwrap();
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.verify_signatures = true;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        assert_eq!(node.sign_node_announcement(&ann).unwrap(), unverified);
+        node.sign_bolt12("name".as_bytes(), "field".as_bytes(), &[1u8; 32], None).unwrap();
+    }
+
+    #[test]
+    fn sign_channel_update_test() -> Result<(), ()> {
+        let (node, _) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        // the channel funding is output 0
+        let cu = hex_decode("43497fd7f826957108f4a30fd9cec3aeba79972084e90ead01ea33090000000000006700000100005e42ddc6010000060000000000000000000000010000000a000000003b023380").unwrap();
         let sigvec = node.sign_channel_update(&cu).unwrap().serialize_der().to_vec();
-        assert_eq!(sigvec, hex_decode("3045022100be9840696c868b161aaa997f9fa91a899e921ea06c8083b2e1ea32b8b511948d0220352eec7a74554f97c2aed26950b8538ca7d7d7568b42fd8c6f195bd749763fa5").unwrap());
+        assert_eq!(sigvec, hex_decode("3045022100bf3b664998d595c1da874392de98383b53fc14ac382d02e7121f1a6aced4ddfb0220658da4c42aecf47ff70006c5e01c833316a1cc0e71f3a39fc842952291f138c7").unwrap());
+
+        // an update on regtest
+        let regtest_cu = hex_decode("06226e46111a0b59caaf126043eb5bbf28c34f3a5e332a1fc7b2b73cf188910f00006700000100005e42ddc6010000060000000000000000000000010000000a000000003b023380").unwrap();
+        assert_failed_precondition_err!(
+            node.sign_channel_update(&regtest_cu),
+            "policy failure: channel update for chain 06226e46111a0b59caaf126043eb5bbf28c34f3a5e332a1fc7b2b73cf188910f, not 43497fd7f826957108f4a30fd9cec3aeba79972084e90ead01ea330900000000"
+        );
+        // an update for output 1
+        let mut unknown_cu = cu.clone();
+        unknown_cu[39] = 1;
+        assert_failed_precondition_err!(
+            node.sign_channel_update(&unknown_cu),
+            "policy failure: channel update for unknown channel 103x1x1"
+        );
         Ok(())
     }
 
+    #[test]
+    fn sign_gossip_message_test() {
+        let (node, _) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let cu = hex_decode("43497fd7f826957108f4a30fd9cec3aeba79972084e90ead01ea33090000000000006700000100005e42ddc6010000060000000000000000000000010000000a000000003b023380").unwrap();
+        let mut msg = vec![0x01, 0x02];
+        msg.extend_from_slice(&[0u8; 64]);
+        msg.extend_from_slice(&cu);
+        assert_eq!(node.sign_gossip_message(&msg).unwrap(), node.sign_channel_update(&cu).unwrap());
+
+        // channel announcements are signed by the channel
+        msg[1] = 0x00;
+        assert_invalid_argument_err!(
+            node.sign_gossip_message(&msg),
+            "can't sign gossip message type 256"
+        );
+        assert_invalid_argument_err!(
+            node.sign_gossip_message(&msg[..10]),
+            "gossip message too short"
+        );
+    }
+
     #[test]
     fn sign_invoice_test() -> Result<(), ()> {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
@@ -2063,6 +4981,22 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_invoice_policy_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.max_invoice_amount_msat = 100_000;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        let human_readable_part = String::from("lnbcrt1230n");
+        let data_part = hex_decode("010f0418090a010101141917110f01040e050f06100003021e1b0e13161c150301011415060204130c0018190d07070a18070a1c1101111e111f130306000d00120c11121706181b120d051807081a0b0f0d18060004120e140018000105100114000b130b01110c001a05041a181716020007130c091d11170d10100d0b1a1b00030e05190208171e16080d00121a00110719021005000405001000").unwrap().check_base32().unwrap();
+        assert_failed_precondition_err!(
+            node.sign_invoice(human_readable_part.as_bytes(), &data_part),
+            "policy failure: validate_invoice: invoice amount 123000 msat exceeds the maximum 100000"
+        );
+        // the refused invoice is not tracked
+        assert!(node.state.lock().unwrap().issued_invoices.is_empty());
+    }
+
     #[test]
     fn sign_invoice_with_overhang_test() -> Result<(), ()> {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
@@ -2177,6 +5111,231 @@ mod tests {
         );
     }
 
+    #[test]
+    fn plan_seed_migration_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let new_node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let spent: Vec<TxOut> = [vec![1], vec![2]]
+            .iter()
+            .map(|path| TxOut {
+                value: 50_000,
+                script_pubkey: node.get_native_address(path).unwrap().script_pubkey(),
+            })
+            .collect();
+        let inputs: Vec<InteractiveInput> = spent
+            .iter()
+            .enumerate()
+            .map(|(i, txout)| InteractiveInput {
+                outpoint: OutPoint { txid: Txid::from_slice(&[2u8; 32]).unwrap(), vout: i as u32 },
+                prev_output: txout.clone(),
+                ipath: vec![i as u32 + 1],
+            })
+            .collect();
+
+        let plan = node.plan_seed_migration(&new_node, &inputs, 1000).unwrap();
+        assert_eq!(plan.new_node_id, new_node.get_id());
+        assert_eq!(plan.new_account_xpub, new_node.get_account_extended_pubkey());
+        assert_eq!(plan.sweep_tx.output.len(), 1);
+        assert_eq!(plan.sweep_tx.output[0].value, 99_000);
+        assert_eq!(
+            plan.sweep_tx.output[0].script_pubkey,
+            new_node.get_native_address(&vec![0]).unwrap().script_pubkey()
+        );
+        assert!(plan
+            .sweep_tx
+            .verify(|outpoint| Some(spent[outpoint.vout as usize].clone()))
+            .is_ok());
+        let digest = MigrationPlan::digest(
+            &node.get_id(),
+            &plan.new_node_id,
+            &plan.new_account_xpub,
+            &plan.sweep_tx.txid(),
+        );
+        assert!(verify_ecdsa(
+            &Message::from_slice(&digest).unwrap(),
+            &plan.signature,
+            &node.get_id()
+        )
+        .is_ok());
+
+        assert_invalid_argument_err!(
+            node.plan_seed_migration(&node, &inputs, 1000),
+            "new node has the same seed"
+        );
+        assert_invalid_argument_err!(
+            node.plan_seed_migration(&new_node, &inputs, 99_800),
+            "fee 99800 too large for inputs 100000"
+        );
+        assert_invalid_argument_err!(
+            node.plan_seed_migration(&new_node, &vec![], 1000),
+            "no wallet outputs to sweep"
+        );
+    }
+
+    #[test]
+    fn sign_psbt_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let secp_ctx = Secp256k1::new();
+        let fingerprint = node.get_account_extended_key().fingerprint(&secp_ctx);
+        let key_source = |i: u32| {
+            let pubkey = node.get_wallet_pubkey(&Secp256k1::signing_only(), &vec![i]).unwrap();
+            (pubkey, (fingerprint, DerivationPath::from(vec![ChildNumber::from(i)])))
+        };
+        let destination =
+            Address::p2wpkh(&bitcoin::PublicKey::new(make_test_pubkey(5)), node.network()).unwrap();
+        node.add_allowlist(&vec![destination.to_string()]).unwrap();
+
+        // a native, a wrapped and a taproot wallet input, and someone else's
+        let spent = vec![
+            TxOut {
+                value: 100_000,
+                script_pubkey: node.get_native_address(&vec![1]).unwrap().script_pubkey(),
+            },
+            TxOut {
+                value: 100_000,
+                script_pubkey: node.get_wrapped_address(&vec![2]).unwrap().script_pubkey(),
+            },
+            TxOut {
+                value: 100_000,
+                script_pubkey: node.get_taproot_script_pubkey(&vec![3]).unwrap(),
+            },
+            TxOut { value: 50_000, script_pubkey: destination.script_pubkey() },
+        ];
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: (0..spent.len())
+                .map(|i| TxIn {
+                    previous_output: OutPoint {
+                        txid: Txid::from_slice(&[2u8; 32]).unwrap(),
+                        vout: i as u32,
+                    },
+                    script_sig: Script::new(),
+                    sequence: 0xffffffff,
+                    witness: vec![],
+                })
+                .collect(),
+            output: vec![
+                TxOut {
+                    value: 150_000,
+                    script_pubkey: node.get_taproot_script_pubkey(&vec![4]).unwrap(),
+                },
+                TxOut { value: 149_000, script_pubkey: destination.script_pubkey() },
+            ],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        for (i, txout) in spent.iter().enumerate() {
+            psbt.inputs[i].witness_utxo = Some(txout.clone());
+            if i < 3 {
+                let (pubkey, source) = key_source(i as u32 + 1);
+                psbt.inputs[i].bip32_derivation.insert(pubkey, source);
+            }
+        }
+        let (pubkey, source) = key_source(4);
+        psbt.outputs[0].bip32_derivation.insert(pubkey, source);
+
+        let signed = node.sign_psbt(&psbt).unwrap();
+        let height = node.get_tracker().height();
+        assert_eq!(node.get_state().onchain_spends, vec![(height, 149_000)]);
+        assert!(signed.inputs[3].partial_sigs.is_empty());
+        assert!(signed.inputs[3].final_script_witness.is_none());
+
+        // finalize the segwit v0 inputs, and check the signatures
+        let mut tx = signed.global.unsigned_tx.clone();
+        for i in 0..2 {
+            let (pubkey, sig) = signed.inputs[i].partial_sigs.iter().next().unwrap();
+            tx.input[i].witness = vec![sig.clone(), pubkey.to_bytes()];
+        }
+        let redeem_script = signed.inputs[1].redeem_script.as_ref().unwrap();
+        tx.input[1].script_sig = Builder::new().push_slice(redeem_script.as_bytes()).into_script();
+        tx.input[2].witness = signed.inputs[2].final_script_witness.clone().unwrap();
+        assert!(tx.verify(|outpoint| Some(spent[outpoint.vout as usize].clone())).is_ok());
+
+        // the taproot signature is by the tweaked key of the output
+        let sighash = taproot_key_spend_sighash(&tx, 2, &spent).unwrap();
+        let output_key =
+            schnorrsig::PublicKey::from_slice(&spent[2].script_pubkey.as_bytes()[2..]).unwrap();
+        let sig = schnorrsig::Signature::from_slice(&tx.input[2].witness[0]).unwrap();
+        assert!(verify_schnorr(&Message::from_slice(&sighash).unwrap(), &sig, &output_key).is_ok());
+
+        // the key must match the path
+        let mut bad_psbt = psbt.clone();
+        let (pubkey, _) = key_source(1);
+        bad_psbt.inputs[0].bip32_derivation.insert(pubkey, key_source(5).1);
+        assert_invalid_argument_err!(
+            node.sign_psbt(&bad_psbt),
+            format!("wallet path m/5 doesn't match key {}", pubkey)
+        );
+
+        // change must be to our wallet
+        let mut bad_psbt = psbt.clone();
+        bad_psbt.outputs[0].bip32_derivation.clear();
+        let (pubkey, source) = key_source(5);
+        bad_psbt.outputs[0].bip32_derivation.insert(pubkey, source);
+        assert_failed_precondition_err!(
+            node.sign_psbt(&bad_psbt),
+            "policy failure: validate_onchain_tx: wallet cannot spend output[0]"
+        );
+    }
+
+    #[test]
+    fn sign_counterparty_payment_sweep_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let amount_sat = 100_000;
+        let wallet_path = vec![1];
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: Txid::from_slice(&[2u8; 32]).unwrap(), vout: 0 },
+                script_sig: Script::new(),
+                sequence: 0,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                value: amount_sat - 1000,
+                script_pubkey: node.get_native_address(&wallet_path).unwrap().script_pubkey(),
+            }],
+        };
+        let sign = |tx: &Transaction| {
+            node.with_ready_channel(&channel_id, |chan| {
+                let sig =
+                    chan.sign_counterparty_payment_sweep(tx, 0, &None, amount_sat, &wallet_path)?;
+                Ok((sig, chan.keys.pubkeys().payment_point))
+            })
+        };
+
+        // option_static_remotekey, the output pays to our payment point
+        let (sig, payment_point) = sign(&tx).unwrap();
+        let pubkey = bitcoin::PublicKey { compressed: true, key: payment_point };
+        let spent = TxOut {
+            value: amount_sat,
+            script_pubkey: Address::p2wpkh(&pubkey, Network::Testnet).unwrap().script_pubkey(),
+        };
+        let mut signed_tx = tx.clone();
+        signed_tx.input[0].witness = vec![signature_to_bitcoin_vec(sig), pubkey.to_bytes()];
+        assert!(signed_tx.verify(|_| Some(spent.clone())).is_ok());
+
+        let mut bad_tx = tx.clone();
+        bad_tx.output[0].script_pubkey = spent.script_pubkey.clone();
+        assert_failed_precondition_err!(
+            sign(&bad_tx),
+            "policy failure: validate_counterparty_payment_sweep: validate_sweep: \
+             destination is not in wallet or allowlist"
+        );
+        assert_invalid_argument_err!(
+            node.with_ready_channel(&channel_id, |chan| chan.sign_counterparty_payment_sweep(
+                &tx,
+                1,
+                &None,
+                amount_sat,
+                &wallet_path
+            )),
+            "sign_counterparty_payment_sweep: bad input index: 1 >= 1"
+        );
+    }
+
     #[test]
     fn get_account_ext_pub_key_test() {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
@@ -2184,6 +5343,60 @@ mod tests {
         assert_eq!(format!("{}", xpub), "tpubDAu312RD7nE6R9qyB4xJk9QAMyi3ppq3UJ4MMUGpB9frr6eNDd8FJVPw27zTVvWAfYFVUtJamgfh5ZLwT23EcymYgLx7MHsU8zZxc9L3GKk");
     }
 
+    #[test]
+    fn get_wallet_descriptor_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let xpub = node.get_account_extended_pubkey();
+        let descriptors = node.get_wallet_descriptor();
+        assert_eq!(descriptors.len(), 3);
+        let key = format!("[{}]{}/*", xpub.fingerprint(), xpub);
+        assert!(descriptors[0].starts_with(&format!("wpkh({})#", key)));
+        assert!(descriptors[1].starts_with(&format!("sh(wpkh({}))#", key)));
+        assert!(descriptors[2].starts_with(&format!("tr({})#", key)));
+
+        let mut lnd_config = TEST_NODE_CONFIG;
+        lnd_config.key_derivation_style = KeyDerivationStyle::Lnd;
+        let lnd_node = init_node(lnd_config, TEST_SEED[1]);
+        let descriptors = lnd_node.get_wallet_descriptor();
+        assert_eq!(descriptors.len(), 6);
+        assert!(descriptors[0].contains("/0/*)#"));
+        assert!(descriptors[3].contains("/1/*)#"));
+    }
+
+    #[test]
+    fn get_address_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        assert_eq!(
+            node.get_address(3, SpendType::P2wpkh).unwrap(),
+            node.get_native_address(&vec![3]).unwrap()
+        );
+        assert_eq!(
+            node.get_address(3, SpendType::P2shP2wpkh).unwrap(),
+            node.get_wrapped_address(&vec![3]).unwrap()
+        );
+        assert_eq!(
+            node.get_address(3, SpendType::P2tr).unwrap().script_pubkey(),
+            node.get_taproot_script_pubkey(&vec![3]).unwrap()
+        );
+        assert!(node.get_address(3, SpendType::P2tr).unwrap().to_string().starts_with("tb1p"));
+        assert_invalid_argument_err!(
+            node.get_address(3, SpendType::P2wsh),
+            "unsupported spend type P2wsh"
+        );
+        assert_invalid_argument_err!(
+            node.get_address(1 << 31, SpendType::P2wpkh),
+            "hardened address index 2147483648"
+        );
+
+        let mut lnd_config = TEST_NODE_CONFIG;
+        lnd_config.key_derivation_style = KeyDerivationStyle::Lnd;
+        let lnd_node = init_node(lnd_config, TEST_SEED[1]);
+        assert_eq!(
+            lnd_node.get_address(3, SpendType::P2wpkh).unwrap(),
+            lnd_node.get_native_address(&vec![0, 3]).unwrap()
+        );
+    }
+
     #[test]
     fn sign_message_test() {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
@@ -2279,6 +5492,420 @@ mod tests {
         matching == a.len() && matching == b.len()
     }
 
+    #[test]
+    fn node_metadata_limits_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let entry = |k: &str, v: &str| (k.to_string(), v.to_string());
+
+        assert_status_ok!(node.set_metadata(None, &vec![entry("customer", "c1")]));
+        assert_status_ok!(node.set_metadata(Some(&channel_id), &vec![entry("cohort", "a")]));
+        let unknown_id = ChannelId([9; 32]);
+        assert_invalid_argument_err!(
+            node.set_metadata(Some(&unknown_id), &vec![entry("cohort", "a")]),
+            "no such channel"
+        );
+        assert_invalid_argument_err!(node.get_metadata(Some(&unknown_id)), "no such channel");
+
+        let long_key = "k".repeat(MAX_METADATA_KEY_LEN + 1);
+        assert_invalid_argument_err!(
+            node.set_metadata(None, &vec![entry(&long_key, "v")]),
+            "metadata key length 65 not in 1..64"
+        );
+        assert_invalid_argument_err!(
+            node.set_metadata(None, &vec![entry("", "v")]),
+            "metadata key length 0 not in 1..64"
+        );
+        let long_value = "v".repeat(MAX_METADATA_VALUE_LEN + 1);
+        assert_invalid_argument_err!(
+            node.set_metadata(None, &vec![entry("k", &long_value)]),
+            "metadata value for k too long: 257 > 256"
+        );
+        let too_many: Vec<(String, String)> =
+            (0..=MAX_METADATA_ENTRIES).map(|i| entry(&format!("k{}", i), "v")).collect();
+        assert_invalid_argument_err!(
+            node.set_metadata(None, &too_many),
+            "too many metadata entries: 33 > 32"
+        );
+    }
+
+    struct TestEventListener {
+        events: Mutex<Vec<NodeEvent>>,
+    }
+
+    impl SendSync for TestEventListener {}
+
+    impl NodeEventListener for TestEventListener {
+        fn on_event(&self, event: &NodeEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn node_heartbeat_test() {
+        let node = init_node(REGTEST_NODE_CONFIG, TEST_SEED[1]);
+        let listener = Arc::new(TestEventListener { events: Mutex::new(vec![]) });
+        node.add_event_listener(listener.clone());
+        assert_eq!(node.clock_skew(), None);
+        assert_eq!(node.fresh_clock_skew_secs(0), None);
+        let tip_time = node.get_tracker().tip().time as u64;
+
+        assert_eq!(node.heartbeat(Duration::from_secs(tip_time + 100)).unwrap(), 100);
+        assert_eq!(
+            node.clock_skew(),
+            Some(ClockSkewSample {
+                skew_secs: 100,
+                sampled_at: tip_time + 100,
+                height: 0,
+                within_policy: true
+            })
+        );
+        assert_eq!(node.fresh_clock_skew_secs(0), Some(100));
+
+        assert_eq!(node.heartbeat(Duration::from_secs(tip_time - 100)).unwrap(), -100);
+        assert_eq!(node.fresh_clock_skew_secs(0), Some(-100));
+
+        // The sample expires as the chain moves on
+        let age = MAX_CLOCK_SKEW_SAMPLE_AGE_BLOCKS;
+        assert_eq!(node.fresh_clock_skew_secs(age), Some(-100));
+        assert_eq!(node.fresh_clock_skew_secs(age + 1), None);
+        assert!(node.clock_skew().is_some());
+        assert!(listener.events.lock().unwrap().is_empty());
+
+        // test networks allow a day of skew, the skew is still recorded on failure
+        let skew = 24 * 3600 + 1;
+        let res = node.heartbeat(Duration::from_secs(tip_time + skew as u64));
+        assert_eq!(res.unwrap_err().code(), Code::FailedPrecondition);
+        assert_eq!(node.fresh_clock_skew_secs(0), Some(skew));
+        assert!(!node.clock_skew().unwrap().within_policy);
+        assert_eq!(
+            *listener.events.lock().unwrap(),
+            vec![NodeEvent::ClockSkew { clock_skew_secs: skew, height: 0 }]
+        );
+
+        // Only raised when the skew goes out of range
+        assert!(node.heartbeat(Duration::from_secs(tip_time + skew as u64)).is_err());
+        assert_eq!(listener.events.lock().unwrap().len(), 1);
+        node.heartbeat(Duration::from_secs(tip_time)).unwrap();
+        assert!(node.heartbeat(Duration::from_secs(tip_time + skew as u64)).is_err());
+        assert_eq!(listener.events.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn node_chain_suspect_test() {
+        let node = init_node(REGTEST_NODE_CONFIG, TEST_SEED[1]);
+        let listener = Arc::new(TestEventListener { events: Mutex::new(vec![]) });
+        node.add_event_listener(listener.clone());
+        let header = make_header(node.get_tracker().tip(), Default::default());
+        node.get_tracker().add_block(header, vec![], None).unwrap();
+        node.get_tracker().set_max_reorg_depth(0);
+
+        let result = node.remove_block(vec![], None);
+        assert_failed_precondition_err!(result, "remove block at height 1 failed: ReorgTooDeep");
+        assert!(node.is_chain_suspect());
+        assert_eq!(
+            *listener.events.lock().unwrap(),
+            vec![NodeEvent::ChainSuspect { height: 1, block_hash: header.block_hash() }]
+        );
+
+        // Only raised once
+        assert!(node.remove_block(vec![], None).is_err());
+        assert_eq!(listener.events.lock().unwrap().len(), 1);
+
+        node.clear_chain_suspect().unwrap();
+        assert!(!node.is_chain_suspect());
+    }
+
+    #[test]
+    fn node_breach_event_test() {
+        let (node, setup, channel_id, offered_htlcs, received_htlcs) =
+            sign_commitment_tx_with_mutators_setup(CommitmentType::StaticRemoteKey);
+        let listener = Arc::new(TestEventListener { events: Mutex::new(vec![]) });
+        node.add_event_listener(listener.clone());
+
+        let (chan_id, current_tx, revoked_tx, monitor) = node
+            .with_ready_channel(&channel_id, |chan| {
+                let commit_num = 23;
+                let point = make_test_pubkey(10);
+                chan.enforcement_state.set_next_counterparty_commit_num_for_testing(
+                    commit_num,
+                    make_test_pubkey(0x10),
+                );
+                chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+                chan.sign_counterparty_commitment_tx_phase2(
+                    &point,
+                    commit_num,
+                    0,
+                    1_000_000,
+                    1_979_997,
+                    offered_htlcs.clone(),
+                    received_htlcs.clone(),
+                )?;
+                let htlcs =
+                    Channel::htlcs_info2_to_oic(offered_htlcs.clone(), received_htlcs.clone());
+                let current_tx = chan
+                    .make_counterparty_commitment_tx(
+                        &point,
+                        commit_num,
+                        0,
+                        1_000_000,
+                        1_979_997,
+                        htlcs.clone(),
+                    )
+                    .trust()
+                    .built_transaction()
+                    .transaction
+                    .clone();
+                let revoked_tx = chan
+                    .make_counterparty_commitment_tx(
+                        &make_test_pubkey(11),
+                        commit_num - 2,
+                        0,
+                        1_000_000,
+                        1_979_997,
+                        htlcs,
+                    )
+                    .trust()
+                    .built_transaction()
+                    .transaction
+                    .clone();
+                Ok((chan.id(), current_tx, revoked_tx, chan.monitor.clone()))
+            })
+            .unwrap();
+        monitor.get_state().funding_outpoint = Some(setup.funding_outpoint);
+
+        // the current commitment is not a breach
+        monitor.on_add_block(vec![&current_tx]);
+        assert!(listener.events.lock().unwrap().is_empty());
+        monitor.on_remove_block(vec![&current_tx]);
+
+        monitor.on_add_block(vec![&revoked_tx]);
+        let height = monitor.get_state().height;
+        assert_eq!(
+            *listener.events.lock().unwrap(),
+            vec![NodeEvent::Breach {
+                channel_id: chan_id,
+                txid: revoked_tx.txid(),
+                commit_num: 21,
+                height,
+            }]
+        );
+    }
+
+    // Confirm a counterparty commitment transaction, closing the channel
+    fn confirm_commitment_tx(
+        node: &Node,
+        setup: &ChannelSetup,
+        channel_id: &ChannelId,
+        offered_htlcs: Vec<HTLCInfo2>,
+        received_htlcs: Vec<HTLCInfo2>,
+    ) -> (Transaction, ChainMonitor) {
+        let (commitment_tx, monitor) = node
+            .with_ready_channel(channel_id, |chan| {
+                let htlcs =
+                    Channel::htlcs_info2_to_oic(offered_htlcs.clone(), received_htlcs.clone());
+                let tx = chan
+                    .make_counterparty_commitment_tx(
+                        &make_test_pubkey(10),
+                        23,
+                        0,
+                        1_000_000,
+                        1_979_997,
+                        htlcs,
+                    )
+                    .trust()
+                    .built_transaction()
+                    .transaction
+                    .clone();
+                Ok((tx, chan.monitor.clone()))
+            })
+            .unwrap();
+        monitor.get_state().funding_outpoint = Some(setup.funding_outpoint);
+        monitor.on_add_block(vec![&commitment_tx]);
+        (commitment_tx, monitor)
+    }
+
+    #[test]
+    fn node_channel_closing_test() {
+        let (node, setup, channel_id, offered_htlcs, received_htlcs) =
+            sign_commitment_tx_with_mutators_setup(CommitmentType::StaticRemoteKey);
+        let (commitment_tx, monitor) =
+            confirm_commitment_tx(&node, &setup, &channel_id, offered_htlcs, received_htlcs);
+        let height = monitor.get_state().height;
+
+        // no further commitments may be signed
+        let status = node.with_ready_channel(&channel_id, |_| Ok(())).unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(status.message(), format!("channel is closing: {}", channel_id));
+
+        // but sweeps are still allowed
+        let (closing_txid, closing_height) = node
+            .with_ready_or_closing_channel(&channel_id, |chan| {
+                Ok((chan.enforcement_state.closing_txid, chan.enforcement_state.closing_height))
+            })
+            .unwrap();
+        assert_eq!(closing_txid, Some(commitment_tx.txid()));
+        assert_eq!(closing_height, Some(height));
+        let slot = node.get_channel(&channel_id).unwrap();
+        assert!(matches!(&*slot.lock().unwrap(), ChannelSlot::Closing(_)));
+    }
+
+    #[test]
+    fn node_prune_closed_channels_test() {
+        let (node, setup, channel_id, offered_htlcs, received_htlcs) =
+            sign_commitment_tx_with_mutators_setup(CommitmentType::StaticRemoteKey);
+        let (_, monitor) =
+            confirm_commitment_tx(&node, &setup, &channel_id, offered_htlcs, received_htlcs);
+        assert!(node.get_tracker().listeners.contains_key(&monitor));
+
+        // not deep enough to be closed
+        assert!(node.prune_closed_channels(0).unwrap().is_empty());
+        let closed_depth = setup.counterparty_selected_contest_delay as u32 + CLOSED_DEPTH;
+        for _ in 1..closed_depth {
+            monitor.on_add_block(vec![]);
+        }
+        assert_eq!(monitor.closing_depth(), closed_depth);
+
+        // closed, but not deep enough to be pruned
+        assert!(node.prune_closed_channels(closed_depth + 1).unwrap().is_empty());
+        let status = node.with_ready_or_closing_channel(&channel_id, |_| Ok(())).unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(status.message(), format!("channel is closed: {}", channel_id));
+
+        assert_eq!(node.prune_closed_channels(closed_depth).unwrap(), vec![channel_id]);
+        assert!(node.get_channel(&channel_id).is_err());
+        assert!(!node.get_tracker().listeners.contains_key(&monitor));
+    }
+
+    #[test]
+    fn node_channel_review_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.max_channel_age_blocks = 10;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        let monitor =
+            node.with_ready_channel(&channel_id, |chan| Ok(chan.monitor.clone())).unwrap();
+        monitor.on_add_block(vec![]);
+        assert_status_ok!(node.acknowledge_channel_review(&channel_id));
+        assert!(node.channels_due_for_review().is_empty());
+
+        for _ in 0..10 {
+            monitor.on_add_block(vec![]);
+        }
+        assert_eq!(
+            node.channels_due_for_review(),
+            vec![(channel_id, "10 blocks since review >= 10".to_string())]
+        );
+
+        assert_status_ok!(node.acknowledge_channel_review(&channel_id));
+        assert!(node.channels_due_for_review().is_empty());
+        assert_invalid_argument_err!(
+            node.acknowledge_channel_review(&ChannelId([9; 32])),
+            "no such channel"
+        );
+    }
+
+    #[test]
+    fn node_incomplete_signing_test() {
+        let (node, _setup, channel_id, offered_htlcs, received_htlcs) =
+            sign_commitment_tx_with_mutators_setup(CommitmentType::StaticRemoteKey);
+        let commit_num = 23;
+        let sign = || {
+            node.with_ready_channel(&channel_id, |chan| {
+                chan.enforcement_state.set_next_counterparty_commit_num_for_testing(
+                    commit_num,
+                    make_test_pubkey(0x10),
+                );
+                chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+                chan.sign_counterparty_commitment_tx_phase2(
+                    &make_test_pubkey(10),
+                    commit_num,
+                    0,
+                    1_000_000,
+                    1_979_997,
+                    offered_htlcs.clone(),
+                    received_htlcs.clone(),
+                )
+            })
+        };
+
+        // a crash between persisting the intent and the resulting state
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.signing_intent = Some(SigningIntent {
+                operation: "sign_counterparty_commitment_tx".to_string(),
+                commit_num,
+            });
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            node.channels_due_for_review(),
+            vec![(
+                channel_id,
+                "incomplete sign_counterparty_commitment_tx of commitment 23".to_string()
+            )]
+        );
+        let status = sign().unwrap_err();
+        assert!(status.message().contains(
+            "incomplete sign_counterparty_commitment_tx of commitment 23, \
+             operator acknowledgement required"
+        ));
+
+        assert_status_ok!(node.acknowledge_channel_review(&channel_id));
+        assert!(node.channels_due_for_review().is_empty());
+        assert_status_ok!(sign());
+        let intent = node
+            .with_ready_channel(&channel_id, |chan| {
+                Ok(chan.enforcement_state.signing_intent.clone())
+            })
+            .unwrap();
+        assert_eq!(intent, None);
+    }
+
+    #[test]
+    fn node_status_test() {
+        let (node, _setup, channel_id, offered_htlcs, received_htlcs) =
+            sign_commitment_tx_with_mutators_setup(CommitmentType::StaticRemoteKey);
+        node.new_channel(None, Some([7; 32].to_vec()), &node).expect("new_channel");
+        let status = node.status();
+        assert_eq!(status.height, node.get_tracker().height());
+        assert_eq!(status.stub_channels, 1);
+        assert_eq!(status.ready_channels, 1);
+        assert_eq!(status.closing_channels + status.closed_channels, 0);
+
+        let status = node
+            .with_ready_channel(&channel_id, |chan| {
+                chan.enforcement_state
+                    .set_next_counterparty_commit_num_for_testing(23, make_test_pubkey(0x10));
+                chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(22);
+                chan.sign_counterparty_commitment_tx_phase2(
+                    &make_test_pubkey(10),
+                    23,
+                    0,
+                    1_000_000,
+                    1_979_997,
+                    offered_htlcs.clone(),
+                    received_htlcs.clone(),
+                )?;
+                Ok(chan.status())
+            })
+            .unwrap();
+        assert_eq!(status.channel_id, channel_id);
+        assert_eq!(status.next_counterparty_commit_num, 24);
+        assert_eq!(status.funding_depth, 0);
+        assert!(status.holder_commitment.is_none());
+        assert_eq!(
+            status.counterparty_commitment,
+            Some(CommitmentBalance {
+                to_holder_sat: 1_000_000,
+                to_counterparty_sat: 1_979_997,
+                offered_htlc_sat: 15_003,
+                received_htlc_sat: 4_000,
+            })
+        );
+    }
+
     #[test]
     fn node_allowlist_test() {
         fn prefix(a: &String) -> String {
@@ -2296,6 +5923,7 @@ mod tests {
             "2N6i2gfgTonx88yvYm32PRhnHxqxtEfocbt",
             "tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z",
             "tb1qycu764qwuvhn7u0enpg0x8gwumyuw565f3mspnn58rsgar5hkjmqtjegrh",
+            "tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c",
         ]
         .iter()
         .map(|s| s.to_string())
@@ -2315,7 +5943,7 @@ mod tests {
         assert_status_ok!(node.remove_allowlist(&removes0));
         assert!(vecs_match(
             node.allowlist().expect("allowlist").clone(),
-            vec![prefix(&adds0[1]), prefix(&adds0[2])]
+            vec![prefix(&adds0[1]), prefix(&adds0[2]), prefix(&adds0[4])]
         ));
 
         // can't add bogus addresses
@@ -2336,4 +5964,118 @@ mod tests {
             "could not parse 1287uUybCYgf7Tb76qnfPf8E1ohCgSZATp: expected network testnet"
         );
     }
+
+    #[test]
+    fn node_allowlist_xpub_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let secp_ctx = Secp256k1::new();
+        let xprv = ExtendedPrivKey::new_master(Network::Testnet, &[3u8; 32]).unwrap();
+        let xpub = ExtendedPubKey::from_private(&secp_ctx, &xprv);
+        let address_at = |index: u32| {
+            let child = xpub
+                .ckd_pub(&secp_ctx, ChildNumber::Normal { index: 1 })
+                .unwrap()
+                .ckd_pub(&secp_ctx, ChildNumber::Normal { index })
+                .unwrap();
+            Address::p2wpkh(&child.public_key, Network::Testnet).unwrap().script_pubkey()
+        };
+
+        assert!(!node.allowlist_contains(&address_at(0)));
+
+        // an output descriptor allows the default number of addresses
+        assert_status_ok!(node.add_allowlist(&vec![format!("wpkh({}/1/*)", xpub)]));
+        assert_eq!(node.allowlist().unwrap(), vec![format!("xpub:{}/1:20", xpub)]);
+        assert!(node.allowlist_contains(&address_at(0)));
+        assert!(node.allowlist_contains(&address_at(DEFAULT_ALLOWLIST_GAP_LIMIT - 1)));
+        assert!(!node.allowlist_contains(&address_at(DEFAULT_ALLOWLIST_GAP_LIMIT)));
+
+        // the descriptor is the same entry as the canonical form
+        assert_status_ok!(node.remove_allowlist(&vec![format!("xpub:{}/1", xpub)]));
+        assert!(node.allowlist().unwrap().is_empty());
+
+        // an explicit gap limit
+        assert_status_ok!(node.add_allowlist(&vec![format!("xpub:{}/1:100", xpub)]));
+        assert!(node.allowlist_contains(&address_at(99)));
+        assert!(!node.allowlist_contains(&address_at(100)));
+        // not our wallet, and not derived from the xpub
+        assert!(
+            !node.allowlist_contains(&node.get_native_address(&vec![1]).unwrap().script_pubkey())
+        );
+
+        // the persisted form round-trips
+        let persisted = node.allowlist().unwrap();
+        assert!(
+            Allowable::from_str(&persisted[0], Network::Testnet).unwrap()
+                == Allowable::XPub { xpub, path: vec![1], gap_limit: 100 }
+        );
+
+        // hardened derivation is not possible from an xpub
+        let bad = format!("xpub:{}/1'", xpub);
+        assert_invalid_argument_err!(
+            node.add_allowlist(&vec![bad.clone()]),
+            format!("could not parse {}: bad derivation index 1'", bad)
+        );
+
+        // can't add w/ wrong network
+        let mainnet_xprv = ExtendedPrivKey::new_master(Network::Bitcoin, &[3u8; 32]).unwrap();
+        let mainnet_xpub = ExtendedPubKey::from_private(&secp_ctx, &mainnet_xprv);
+        let bad = format!("wpkh({}/1/*)", mainnet_xpub);
+        assert_invalid_argument_err!(
+            node.add_allowlist(&vec![bad.clone()]),
+            format!("could not parse {}: expected network testnet", bad)
+        );
+    }
+    #[test]
+    fn node_allowlist_cold_storage_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let secp_ctx = Secp256k1::new();
+        let xprv = ExtendedPrivKey::new_master(Network::Testnet, &[3u8; 32]).unwrap();
+        let xpub = ExtendedPubKey::from_private(&secp_ctx, &xprv);
+        let cold_address = |index: u32| {
+            let child = xpub.ckd_pub(&secp_ctx, ChildNumber::Normal { index }).unwrap();
+            Address::p2wpkh(&child.public_key, Network::Testnet).unwrap().script_pubkey()
+        };
+        let hot_address = Address::from_str("tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z")
+            .unwrap()
+            .script_pubkey();
+
+        assert_eq!(node.cold_storage_threshold(), None);
+        assert_status_ok!(node.add_allowlist(&vec![
+            "tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z".to_string(),
+            format!("cold:500000:wpkh({}/*)", xpub),
+            format!("cold:100000:xpub:{}:5", xpub),
+        ]));
+        assert_eq!(node.cold_storage_threshold(), Some(100000));
+
+        // cold storage destinations are also allowlisted
+        assert!(node.allowlist_contains(&cold_address(3)));
+        assert!(node.cold_storage_contains(&cold_address(3)));
+        assert!(node.allowlist_contains(&hot_address));
+        assert!(!node.cold_storage_contains(&hot_address));
+
+        // the persisted form round-trips
+        let mut persisted = node.allowlist().unwrap();
+        persisted.sort();
+        assert_eq!(persisted[0], format!("cold:100000:xpub:{}:5", xpub));
+        assert!(
+            Allowable::from_str(&persisted[0], Network::Testnet).unwrap()
+                == Allowable::ColdStorage {
+                    threshold_sat: 100000,
+                    destination: Box::new(Allowable::XPub { xpub, path: vec![], gap_limit: 5 })
+                }
+        );
+
+        // cold storage is a layer-1 destination
+        let bad = format!("cold:1000:payee:{}", node.get_id().to_hex());
+        assert_invalid_argument_err!(
+            node.add_allowlist(&vec![bad.clone()]),
+            format!("could not parse {}: cold storage must be a layer-1 destination", bad)
+        );
+        assert_invalid_argument_err!(
+            node.add_allowlist(&vec![
+                "cold:x:tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z".to_string()
+            ]),
+            "could not parse cold:x:tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z"
+        );
+    }
 }
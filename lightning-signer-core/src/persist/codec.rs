@@ -0,0 +1,565 @@
+//! A compact binary encoding of the persisted records, for persisters
+//! that don't have serde, such as [super::memory::MemoryPersister].
+//!
+//! Integers are big-endian, sequences have a u32 length prefix, and
+//! bitcoin types use their consensus encoding.
+
+use alloc::collections::VecDeque;
+use core::convert::TryFrom;
+use core::mem::size_of;
+
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::util::uint::Uint256;
+use bitcoin::{BlockHeader, Network, OutPoint, Script, Txid};
+use lightning::ln::chan_utils::ChannelPublicKeys;
+use lightning::ln::PaymentHash;
+
+use super::model::{AuditRecord, ChannelEntry, NodeEntry, PaymentLedgerEntry, PaymentResolution};
+use super::Error;
+use crate::chain::tracker::{ChainTracker, ListenSlot};
+use crate::channel::{ChannelId, ChannelSetup, CommitmentType};
+use crate::close_plan::{ClosePlan, PlannedClose};
+use crate::monitor::{ChainMonitor, State as ChainMonitorState};
+use crate::node::ChainParams;
+use crate::policy::validator::{EnforcementState, SigningIntent, SpliceState};
+use crate::prelude::*;
+use crate::tx::tx::{CommitmentInfo2, HTLCInfo2};
+use crate::util::shachain::CounterpartyRevocationSecrets;
+
+/// A value that can be encoded
+pub(crate) trait Encode {
+    /// Append the encoding to the buffer
+    fn encode(&self, w: &mut Vec<u8>);
+}
+
+/// A value that can be decoded
+pub(crate) trait Decode: Sized {
+    /// Decode a value from the front of the reader
+    fn decode(r: &mut Reader) -> Result<Self, Error>;
+}
+
+/// Reads encoded values from a buffer
+pub(crate) struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Read from the start of the buffer
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Reader(buf)
+    }
+
+    /// Whether the whole buffer was read
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(Error::Corrupt("truncated record".to_string()));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+}
+
+/// Encode a value into a new buffer
+pub(crate) fn encode<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+    let mut w = Vec::new();
+    value.encode(&mut w);
+    w
+}
+
+/// Decode a value that fills the whole buffer
+pub(crate) fn decode<T: Decode>(buf: &[u8]) -> Result<T, Error> {
+    let mut r = Reader::new(buf);
+    let value = T::decode(&mut r)?;
+    if !r.is_empty() {
+        return Err(Error::Corrupt("trailing bytes after record".to_string()));
+    }
+    Ok(value)
+}
+
+macro_rules! impl_int {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, w: &mut Vec<u8>) {
+                    w.extend_from_slice(&self.to_be_bytes());
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(r: &mut Reader) -> Result<Self, Error> {
+                    let mut bytes = [0; size_of::<$t>()];
+                    bytes.copy_from_slice(r.take(size_of::<$t>())?);
+                    Ok(<$t>::from_be_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_int!(u8, u16, u32, u64);
+
+impl Encode for bool {
+    fn encode(&self, w: &mut Vec<u8>) {
+        (*self as u8).encode(w)
+    }
+}
+
+impl Decode for bool {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        match u8::decode(r)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(Error::Corrupt(format!("bad bool {}", b))),
+        }
+    }
+}
+
+impl Encode for [u8; 32] {
+    fn encode(&self, w: &mut Vec<u8>) {
+        w.extend_from_slice(self)
+    }
+}
+
+impl Decode for [u8; 32] {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let mut bytes = [0; 32];
+        bytes.copy_from_slice(r.take(32)?);
+        Ok(bytes)
+    }
+}
+
+fn encode_len(len: usize, w: &mut Vec<u8>) {
+    u32::try_from(len).expect("sequence too long").encode(w)
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, w: &mut Vec<u8>) {
+        encode_len(self.len(), w);
+        for item in self {
+            item.encode(w);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, w: &mut Vec<u8>) {
+        self.as_slice().encode(w)
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let len = u32::decode(r)?;
+        // don't preallocate, the length is not trusted
+        let mut res = Vec::new();
+        for _ in 0..len {
+            res.push(T::decode(r)?);
+        }
+        Ok(res)
+    }
+}
+
+impl<T: Encode> Encode for OrderedSet<T> {
+    fn encode(&self, w: &mut Vec<u8>) {
+        encode_len(self.len(), w);
+        for item in self {
+            item.encode(w);
+        }
+    }
+}
+
+impl<T: Decode + Ord> Decode for OrderedSet<T> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok(Vec::<T>::decode(r)?.into_iter().collect())
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, w: &mut Vec<u8>) {
+        self.as_bytes().encode(w)
+    }
+}
+
+impl Decode for String {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        String::from_utf8(Vec::decode(r)?).map_err(|_| Error::Corrupt("bad string".to_string()))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, w: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                true.encode(w);
+                value.encode(w);
+            }
+            None => false.encode(w),
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        if bool::decode(r)? {
+            Ok(Some(T::decode(r)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, w: &mut Vec<u8>) {
+        self.0.encode(w);
+        self.1.encode(w);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        Ok((A::decode(r)?, B::decode(r)?))
+    }
+}
+
+impl Encode for PublicKey {
+    fn encode(&self, w: &mut Vec<u8>) {
+        w.extend_from_slice(&self.serialize())
+    }
+}
+
+impl Decode for PublicKey {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        PublicKey::from_slice(r.take(33)?).map_err(|e| Error::Corrupt(format!("pubkey: {}", e)))
+    }
+}
+
+impl Encode for Network {
+    fn encode(&self, w: &mut Vec<u8>) {
+        self.magic().encode(w)
+    }
+}
+
+impl Decode for Network {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let magic = u32::decode(r)?;
+        Network::from_magic(magic).ok_or_else(|| Error::Corrupt(format!("network {:x}", magic)))
+    }
+}
+
+// Bitcoin types are encoded as their length prefixed consensus encoding
+macro_rules! impl_consensus {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, w: &mut Vec<u8>) {
+                    serialize(self).encode(w)
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(r: &mut Reader) -> Result<Self, Error> {
+                    let bytes: Vec<u8> = Decode::decode(r)?;
+                    deserialize(&bytes)
+                        .map_err(|e| Error::Corrupt(format!("{}: {}", stringify!($t), e)))
+                }
+            }
+        )*
+    };
+}
+
+impl_consensus!(Txid, OutPoint, Script, BlockHeader, Uint256);
+
+// Newtypes of a 32 byte array
+macro_rules! impl_bytes_newtype {
+    ($($t:ident),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, w: &mut Vec<u8>) {
+                    self.0.encode(w)
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(r: &mut Reader) -> Result<Self, Error> {
+                    Ok($t(Decode::decode(r)?))
+                }
+            }
+        )*
+    };
+}
+
+impl_bytes_newtype!(ChannelId, PaymentHash);
+
+// Structs are encoded field by field, in the order listed
+macro_rules! impl_struct {
+    ($t:ident { $($field:ident),* $(,)? }) => {
+        impl Encode for $t {
+            fn encode(&self, w: &mut Vec<u8>) {
+                $(self.$field.encode(w);)*
+            }
+        }
+
+        impl Decode for $t {
+            fn decode(r: &mut Reader) -> Result<Self, Error> {
+                Ok($t { $($field: Decode::decode(r)?),* })
+            }
+        }
+    };
+}
+
+impl Encode for CommitmentType {
+    fn encode(&self, w: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            CommitmentType::Legacy => 0,
+            CommitmentType::StaticRemoteKey => 1,
+            CommitmentType::Anchors => 2,
+            CommitmentType::AnchorsZeroFeeHtlc => 3,
+            CommitmentType::Taproot => 4,
+        };
+        tag.encode(w)
+    }
+}
+
+impl Decode for CommitmentType {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        match u8::decode(r)? {
+            0 => Ok(CommitmentType::Legacy),
+            1 => Ok(CommitmentType::StaticRemoteKey),
+            2 => Ok(CommitmentType::Anchors),
+            3 => Ok(CommitmentType::AnchorsZeroFeeHtlc),
+            4 => Ok(CommitmentType::Taproot),
+            t => Err(Error::Corrupt(format!("commitment type {}", t))),
+        }
+    }
+}
+
+impl_struct!(ChannelPublicKeys {
+    funding_pubkey,
+    revocation_basepoint,
+    payment_point,
+    delayed_payment_basepoint,
+    htlc_basepoint,
+});
+
+impl_struct!(ChannelSetup {
+    is_outbound,
+    channel_value_sat,
+    push_value_msat,
+    funding_outpoint,
+    holder_selected_contest_delay,
+    holder_shutdown_script,
+    counterparty_points,
+    counterparty_selected_contest_delay,
+    counterparty_shutdown_script,
+    commitment_type,
+});
+
+impl_struct!(HTLCInfo2 { value_sat, payment_hash, cltv_expiry });
+
+impl_struct!(CommitmentInfo2 {
+    is_counterparty_broadcaster,
+    to_countersigner_pubkey,
+    to_countersigner_value_sat,
+    revocation_pubkey,
+    to_broadcaster_delayed_pubkey,
+    to_broadcaster_value_sat,
+    to_self_delay,
+    offered_htlcs,
+    received_htlcs,
+    feerate_per_kw,
+});
+
+impl_struct!(CounterpartyRevocationSecrets { old_secrets });
+
+impl_struct!(SpliceState {
+    previous_outpoint,
+    previous_value_sat,
+    first_holder_commit_num,
+    first_counterparty_commit_num,
+});
+
+impl_struct!(SigningIntent { operation, commit_num });
+
+impl_struct!(EnforcementState {
+    next_holder_commit_num,
+    next_holder_revoke_num,
+    next_counterparty_commit_num,
+    next_counterparty_revoke_num,
+    current_counterparty_point,
+    previous_counterparty_point,
+    current_holder_commit_info,
+    current_counterparty_commit_info,
+    previous_counterparty_commit_info,
+    mutual_close_signed,
+    initial_holder_value,
+    counterparty_secrets,
+    closing_txid,
+    closing_height,
+    next_musig_nonce_index,
+    pending_musig_nonce_index,
+    splice,
+    review_height,
+    last_activity_height,
+    signing_intent,
+});
+
+impl_struct!(ChainParams { genesis, signet_challenge });
+
+impl_struct!(NodeEntry { seed, key_derivation_style, network, chain_params });
+
+impl_struct!(ChannelEntry { nonce, channel_value_satoshis, channel_setup, id, enforcement_state });
+
+impl_struct!(ListenSlot { txid_watches, watches, seen });
+
+impl_struct!(ChainMonitorState {
+    height,
+    funding_txids,
+    funding_vouts,
+    funding_inputs,
+    funding_height,
+    funding_outpoint,
+    funding_double_spent_height,
+    closing_height,
+    closing_commitment_txid,
+    splice_outpoint,
+    splice_height,
+    previous_funding_outpoint,
+    splice_reorged,
+    expected_funding_value_sat,
+    funding_value_sat,
+});
+
+impl_struct!(AuditRecord {
+    sequence,
+    timestamp,
+    channel_id,
+    operation,
+    commit_num,
+    values_sat,
+    destinations,
+    error,
+    prev_hash,
+    signature,
+});
+
+impl Encode for PaymentResolution {
+    fn encode(&self, w: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            PaymentResolution::Pending => 0,
+            PaymentResolution::Fulfilled => 1,
+            PaymentResolution::Failed => 2,
+            PaymentResolution::Expired => 3,
+        };
+        tag.encode(w)
+    }
+}
+
+impl Decode for PaymentResolution {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        match u8::decode(r)? {
+            0 => Ok(PaymentResolution::Pending),
+            1 => Ok(PaymentResolution::Fulfilled),
+            2 => Ok(PaymentResolution::Failed),
+            3 => Ok(PaymentResolution::Expired),
+            t => Err(Error::Corrupt(format!("payment resolution {}", t))),
+        }
+    }
+}
+
+impl_struct!(PaymentLedgerEntry {
+    payment_hash,
+    outgoing,
+    amount_msat,
+    invoice_hash,
+    expiry_time,
+    resolution,
+});
+
+impl_struct!(PlannedClose {
+    channel_id,
+    to_holder_value_sat,
+    to_counterparty_value_sat,
+    holder_script,
+    counterparty_script,
+    holder_wallet_path_hint,
+    signed,
+});
+
+impl_struct!(ClosePlan { id, closes, fee_sat, non_wallet_sat, approved });
+
+// The listeners are restored from their funding outpoint and state, as in
+// the other persisters
+impl Encode for ChainTracker<ChainMonitor> {
+    fn encode(&self, w: &mut Vec<u8>) {
+        encode_len(self.headers.len(), w);
+        for header in self.headers.iter() {
+            header.encode(w);
+        }
+        self.tip.encode(w);
+        self.height.encode(w);
+        self.chainwork.encode(w);
+        self.network.encode(w);
+        encode_len(self.listeners.len(), w);
+        for (listener, slot) in self.listeners.iter() {
+            listener.funding_outpoint.encode(w);
+            listener.get_state().encode(w);
+            slot.encode(w);
+        }
+        self.max_reorg_depth.encode(w);
+        self.suspect.encode(w);
+        self.period_start_time.encode(w);
+    }
+}
+
+impl Decode for ChainTracker<ChainMonitor> {
+    fn decode(r: &mut Reader) -> Result<Self, Error> {
+        let headers: VecDeque<BlockHeader> = Vec::decode(r)?.into_iter().collect();
+        let tip = Decode::decode(r)?;
+        let height = Decode::decode(r)?;
+        let chainwork = Decode::decode(r)?;
+        let network = Decode::decode(r)?;
+        let mut listeners = OrderedMap::new();
+        for _ in 0..u32::decode(r)? {
+            let outpoint = Decode::decode(r)?;
+            let state = Decode::decode(r)?;
+            let slot = Decode::decode(r)?;
+            listeners.insert(ChainMonitor::new_from_persistence(outpoint, state), slot);
+        }
+        let max_reorg_depth = Decode::decode(r)?;
+        let suspect = Decode::decode(r)?;
+        let period_start_time = Decode::decode(r)?;
+        Ok(ChainTracker {
+            headers,
+            tip,
+            height,
+            chainwork,
+            network,
+            listeners,
+            max_reorg_depth,
+            suspect,
+            period_start_time,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_test() {
+        let value = (Some(12345u64), vec!["a".to_string(), "bc".to_string()]);
+        let buf = encode(&value);
+        assert_eq!(decode::<(Option<u64>, Vec<String>)>(&buf), Ok(value));
+        for len in 0..buf.len() {
+            assert!(decode::<(Option<u64>, Vec<String>)>(&buf[..len]).is_err());
+        }
+        let mut longer = buf.clone();
+        longer.push(0);
+        assert!(decode::<(Option<u64>, Vec<String>)>(&longer).is_err());
+    }
+}
@@ -0,0 +1,640 @@
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
+use lightning::ln::PaymentHash;
+
+use super::codec::{decode, encode, Decode, Encode, Reader};
+use super::model::{
+    AuditRecord, ChangeRecord, ChannelChange, ChannelEntry, NodeEntry, PaymentLedgerEntry,
+};
+use super::{Error, Persist, PersistBatch};
+use crate::chain::tracker::ChainTracker;
+use crate::channel::{Channel, ChannelId, ChannelStub};
+use crate::close_plan::ClosePlan;
+use crate::monitor::ChainMonitor;
+use crate::node::NodeConfig;
+use crate::policy::validator::EnforcementState;
+use crate::prelude::*;
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"VLSM";
+// Bumped whenever the encoding of a stored record changes
+const SNAPSHOT_VERSION: u8 = 4;
+
+type Table = OrderedMap<Vec<u8>, Vec<u8>>;
+
+/// A persister that keeps its records in memory, for embedded and HSM
+/// deployments without a filesystem or database.
+///
+/// [MemoryPersister::snapshot] serializes the full contents to a byte
+/// buffer and [MemoryPersister::restore] restores them, so the platform can
+/// keep the snapshot in its own storage, such as flash.  The platform
+/// should write a new snapshot after each signing operation, before
+/// returning the signature.
+///
+/// Audit records are kept without a retention limit, and have a zero
+/// timestamp, since there is no clock.
+///
+/// The fencing token is not part of the snapshot, it only fences the
+/// signers sharing this persister in the same process.
+pub struct MemoryPersister {
+    state: Mutex<State>,
+}
+
+// The records are kept encoded, keyed by the node ID followed by the
+// channel ID or sequence number where there are several per node.  A
+// deleted channel is kept as a tombstone, for export_since.
+#[derive(Default)]
+struct State {
+    last_sequence: u64,
+    last_audit_sequence: u64,
+    // not in snapshots
+    fence: u64,
+    nodes: Table,
+    trackers: Table,
+    // (sequence, Option<ChannelEntry>)
+    channels: Table,
+    allowlists: Table,
+    // keyed by node ID and Option<ChannelId>
+    metadata: Table,
+    audit_log: Table,
+    close_plans: Table,
+    fee_reserves: Table,
+    // keyed by node ID and payment hash
+    payments: Table,
+}
+
+fn node_key(node_id: &PublicKey) -> Vec<u8> {
+    encode(node_id)
+}
+
+fn channel_key(node_id: &PublicKey, channel_id: &ChannelId) -> Vec<u8> {
+    encode(&(*node_id, *channel_id))
+}
+
+fn metadata_key(node_id: &PublicKey, channel_id: Option<&ChannelId>) -> Vec<u8> {
+    encode(&(*node_id, channel_id.cloned()))
+}
+
+fn node_records<'a>(
+    table: &'a Table,
+    node_id: &PublicKey,
+) -> impl Iterator<Item = (&'a Vec<u8>, &'a Vec<u8>)> {
+    let prefix = node_key(node_id);
+    table.range(prefix.clone()..).take_while(move |(k, _)| k.starts_with(&prefix))
+}
+
+fn remove_node_records(table: &mut Table, node_id: &PublicKey) {
+    let prefix = node_key(node_id);
+    table.retain(|k, _| !k.starts_with(&prefix));
+}
+
+fn new_channel_entry(stub: &ChannelStub) -> ChannelEntry {
+    ChannelEntry {
+        nonce: stub.nonce.clone(),
+        channel_value_satoshis: 0, // TODO not known yet
+        channel_setup: None,
+        id: None,
+        enforcement_state: EnforcementState::new(0),
+    }
+}
+
+fn channel_entry(channel: &Channel) -> ChannelEntry {
+    ChannelEntry {
+        nonce: channel.nonce.clone(),
+        channel_value_satoshis: channel.setup.channel_value_sat,
+        channel_setup: Some(channel.setup.clone()),
+        id: channel.id,
+        enforcement_state: channel.enforcement_state.clone(),
+    }
+}
+
+impl State {
+    fn get_channel(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+    ) -> Result<ChannelEntry, Error> {
+        let value = self.channels.get(&channel_key(node_id, channel_id));
+        let entry = match value {
+            Some(value) => decode::<(u64, Option<ChannelEntry>)>(value)?.1,
+            None => None,
+        };
+        entry.ok_or_else(|| Error::NotFound(format!("channel {}", channel_id)))
+    }
+
+    fn set_channel(&mut self, key: Vec<u8>, entry: Option<ChannelEntry>) {
+        self.last_sequence += 1;
+        self.channels.insert(key, encode(&(self.last_sequence, entry)));
+    }
+
+    fn update_channel(&mut self, node_id: &PublicKey, channel: &Channel) -> Result<(), Error> {
+        self.get_channel(node_id, &channel.id0)?;
+        self.set_channel(channel_key(node_id, &channel.id0), Some(channel_entry(channel)));
+        Ok(())
+    }
+
+    fn update_tracker(&mut self, node_id: &PublicKey, tracker: &ChainTracker<ChainMonitor>) {
+        self.trackers.insert(node_key(node_id), encode(tracker));
+    }
+
+    fn write_snapshot(&self) -> Vec<u8> {
+        let mut w = SNAPSHOT_MAGIC.to_vec();
+        SNAPSHOT_VERSION.encode(&mut w);
+        self.last_sequence.encode(&mut w);
+        self.last_audit_sequence.encode(&mut w);
+        for table in self.tables() {
+            let records: Vec<(&Vec<u8>, &Vec<u8>)> = table.iter().collect();
+            encode_records(&records, &mut w);
+        }
+        w
+    }
+
+    fn read_snapshot(buf: &[u8]) -> Result<Self, Error> {
+        if buf.len() < SNAPSHOT_MAGIC.len() || &buf[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+            return Err(Error::Corrupt("not a snapshot".to_string()));
+        }
+        let mut r = Reader::new(&buf[SNAPSHOT_MAGIC.len()..]);
+        let version = u8::decode(&mut r)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(Error::Corrupt(format!("unknown snapshot version {}", version)));
+        }
+        let mut state = State {
+            last_sequence: Decode::decode(&mut r)?,
+            last_audit_sequence: Decode::decode(&mut r)?,
+            ..Default::default()
+        };
+        for table in state.tables_mut() {
+            let records: Vec<(Vec<u8>, Vec<u8>)> = Decode::decode(&mut r)?;
+            table.extend(records);
+        }
+        if !r.is_empty() {
+            return Err(Error::Corrupt("trailing bytes after snapshot".to_string()));
+        }
+        state.check()?;
+        Ok(state)
+    }
+
+    fn tables(&self) -> [&Table; 9] {
+        [
+            &self.nodes,
+            &self.trackers,
+            &self.channels,
+            &self.allowlists,
+            &self.metadata,
+            &self.audit_log,
+            &self.close_plans,
+            &self.fee_reserves,
+            &self.payments,
+        ]
+    }
+
+    fn tables_mut(&mut self) -> [&mut Table; 9] {
+        [
+            &mut self.nodes,
+            &mut self.trackers,
+            &mut self.channels,
+            &mut self.allowlists,
+            &mut self.metadata,
+            &mut self.audit_log,
+            &mut self.close_plans,
+            &mut self.fee_reserves,
+            &mut self.payments,
+        ]
+    }
+
+    // Decode every record, so that a corrupt snapshot is rejected on
+    // restore rather than on use
+    fn check(&self) -> Result<(), Error> {
+        fn check_table<K: Decode, V: Decode>(table: &Table) -> Result<(), Error> {
+            for (key, value) in table.iter() {
+                decode::<K>(key)?;
+                decode::<V>(value)?;
+            }
+            Ok(())
+        }
+        check_table::<PublicKey, NodeEntry>(&self.nodes)?;
+        check_table::<PublicKey, ChainTracker<ChainMonitor>>(&self.trackers)?;
+        check_table::<(PublicKey, ChannelId), (u64, Option<ChannelEntry>)>(&self.channels)?;
+        check_table::<PublicKey, Vec<String>>(&self.allowlists)?;
+        check_table::<(PublicKey, Option<ChannelId>), Vec<(String, String)>>(&self.metadata)?;
+        check_table::<(PublicKey, u64), AuditRecord>(&self.audit_log)?;
+        check_table::<PublicKey, ClosePlan>(&self.close_plans)?;
+        check_table::<PublicKey, Vec<OutPoint>>(&self.fee_reserves)?;
+        check_table::<(PublicKey, PaymentHash), PaymentLedgerEntry>(&self.payments)?;
+        Ok(())
+    }
+}
+
+fn encode_records(records: &[(&Vec<u8>, &Vec<u8>)], w: &mut Vec<u8>) {
+    (records.len() as u32).encode(w);
+    for (key, value) in records {
+        key.encode(w);
+        value.encode(w);
+    }
+}
+
+impl MemoryPersister {
+    /// Create an empty persister
+    pub fn new() -> Self {
+        MemoryPersister { state: Mutex::new(State::default()) }
+    }
+
+    /// Serialize the full contents, for [MemoryPersister::restore]
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.state.lock().unwrap().write_snapshot()
+    }
+
+    /// Create a persister with the contents of a snapshot.  Fails with
+    /// [Error::Corrupt] if the snapshot is truncated or damaged.
+    pub fn restore(snapshot: &[u8]) -> Result<Self, Error> {
+        Ok(MemoryPersister { state: Mutex::new(State::read_snapshot(snapshot)?) })
+    }
+}
+
+impl Persist for MemoryPersister {
+    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let key = node_key(node_id);
+        if state.nodes.contains_key(&key) {
+            return Err(Error::AlreadyExists(format!("node {}", node_id)));
+        }
+        let entry = NodeEntry {
+            seed: seed.to_vec(),
+            key_derivation_style: config.key_derivation_style as u8,
+            network: config.network.to_string(),
+            chain_params: config.chain_params.clone(),
+        };
+        state.nodes.insert(key, encode(&entry));
+        Ok(())
+    }
+
+    fn delete_node(&self, node_id: &PublicKey) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let mut keys = Vec::new();
+        for (k, v) in node_records(&state.channels, node_id) {
+            if decode::<(u64, Option<ChannelEntry>)>(v)?.1.is_some() {
+                keys.push(k.clone());
+            }
+        }
+        for key in keys {
+            state.set_channel(key, None);
+        }
+        remove_node_records(&mut state.nodes, node_id);
+        remove_node_records(&mut state.trackers, node_id);
+        remove_node_records(&mut state.allowlists, node_id);
+        remove_node_records(&mut state.metadata, node_id);
+        remove_node_records(&mut state.audit_log, node_id);
+        remove_node_records(&mut state.close_plans, node_id);
+        remove_node_records(&mut state.fee_reserves, node_id);
+        remove_node_records(&mut state.payments, node_id);
+        Ok(())
+    }
+
+    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        // A channel ID can be reused after the channel is deleted
+        if state.get_channel(node_id, &stub.id0).is_ok() {
+            return Err(Error::AlreadyExists(format!("channel {}", stub.id0)));
+        }
+        state.set_channel(channel_key(node_id, &stub.id0), Some(new_channel_entry(stub)));
+        Ok(())
+    }
+
+    fn new_chain_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.trackers.contains_key(&node_key(node_id)) {
+            return Err(Error::AlreadyExists(format!("tracker of node {}", node_id)));
+        }
+        state.update_tracker(node_id, tracker);
+        Ok(())
+    }
+
+    fn update_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        self.state.lock().unwrap().update_tracker(node_id, tracker);
+        Ok(())
+    }
+
+    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, Error> {
+        let state = self.state.lock().unwrap();
+        let value = state
+            .trackers
+            .get(&node_key(node_id))
+            .ok_or_else(|| Error::NotFound(format!("tracker of node {}", node_id)))?;
+        decode(value)
+    }
+
+    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), Error> {
+        self.state.lock().unwrap().update_channel(node_id, channel)
+    }
+
+    fn update_batch(&self, node_id: &PublicKey, batch: &PersistBatch) -> Result<(), Error> {
+        // Check the channels first, so that the batch is all or nothing
+        let mut state = self.state.lock().unwrap();
+        for channel in batch.channels.iter() {
+            state.get_channel(node_id, &channel.id0)?;
+        }
+        if let Some(tracker) = batch.tracker {
+            state.update_tracker(node_id, tracker);
+        }
+        for channel in batch.channels.iter() {
+            state.update_channel(node_id, channel)?;
+        }
+        Ok(())
+    }
+
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let entry = state.get_channel(node_id, id0)?;
+        // Metadata may have been set under either channel ID
+        state.metadata.remove(&metadata_key(node_id, Some(id0)));
+        if let Some(id) = entry.id {
+            state.metadata.remove(&metadata_key(node_id, Some(&id)));
+        }
+        state.set_channel(channel_key(node_id, id0), None);
+        Ok(())
+    }
+
+    fn get_channel(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+    ) -> Result<ChannelEntry, Error> {
+        self.state.lock().unwrap().get_channel(node_id, channel_id)
+    }
+
+    fn get_node_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Result<Vec<(ChannelId, ChannelEntry)>, Error> {
+        let state = self.state.lock().unwrap();
+        let mut res = Vec::new();
+        for (k, v) in node_records(&state.channels, node_id) {
+            let (_, id0) = decode::<(PublicKey, ChannelId)>(k)?;
+            if let (_, Some(entry)) = decode::<(u64, Option<ChannelEntry>)>(v)? {
+                res.push((id0, entry));
+            }
+        }
+        Ok(res)
+    }
+
+    fn update_node_allowlist(
+        &self,
+        node_id: &PublicKey,
+        allowlist: Vec<String>,
+    ) -> Result<(), Error> {
+        self.state.lock().unwrap().allowlists.insert(node_key(node_id), encode(&allowlist));
+        Ok(())
+    }
+
+    fn get_node_allowlist(&self, node_id: &PublicKey) -> Result<Vec<String>, Error> {
+        let state = self.state.lock().unwrap();
+        match state.allowlists.get(&node_key(node_id)) {
+            Some(value) => decode(value),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn update_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+        metadata: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        let key = metadata_key(node_id, channel_id);
+        self.state.lock().unwrap().metadata.insert(key, encode(&metadata));
+        Ok(())
+    }
+
+    fn get_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        let state = self.state.lock().unwrap();
+        match state.metadata.get(&metadata_key(node_id, channel_id)) {
+            Some(value) => decode(value),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn get_nodes(&self) -> Result<Vec<(PublicKey, NodeEntry)>, Error> {
+        let state = self.state.lock().unwrap();
+        let mut res = Vec::new();
+        for (k, v) in state.nodes.iter() {
+            res.push((decode(k)?, decode(v)?));
+        }
+        Ok(res)
+    }
+
+    fn export_since(&self, sequence: u64) -> Result<Vec<ChangeRecord>, Error> {
+        let state = self.state.lock().unwrap();
+        let mut res = Vec::new();
+        for (k, v) in state.channels.iter() {
+            let (node_id, id0) = decode::<(PublicKey, ChannelId)>(k)?;
+            let (seq, entry) = decode::<(u64, Option<ChannelEntry>)>(v)?;
+            if seq <= sequence {
+                continue;
+            }
+            let change = match entry {
+                Some(entry) => ChannelChange::Update(entry),
+                None => ChannelChange::Delete,
+            };
+            res.push(ChangeRecord { sequence: seq, node_id, id0, change });
+        }
+        res.sort_by_key(|r| r.sequence);
+        Ok(res)
+    }
+
+    fn append_audit_record(&self, node_id: &PublicKey, record: &AuditRecord) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        state.last_audit_sequence += 1;
+        let mut record = record.clone();
+        record.sequence = state.last_audit_sequence;
+        record.timestamp = 0;
+        let key = encode(&(*node_id, record.sequence));
+        state.audit_log.insert(key, encode(&record));
+        Ok(())
+    }
+
+    fn get_audit_log(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<AuditRecord>, Error> {
+        let state = self.state.lock().unwrap();
+        let mut res = Vec::new();
+        for (_, v) in node_records(&state.audit_log, node_id) {
+            let record = decode::<AuditRecord>(v)?;
+            if channel_id.is_none() || record.channel_id.as_ref() == channel_id {
+                res.push(record);
+            }
+        }
+        Ok(res)
+    }
+
+    fn update_close_plan(
+        &self,
+        node_id: &PublicKey,
+        plan: Option<&ClosePlan>,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        match plan {
+            Some(plan) => state.close_plans.insert(node_key(node_id), encode(plan)),
+            None => state.close_plans.remove(&node_key(node_id)),
+        };
+        Ok(())
+    }
+
+    fn get_close_plan(&self, node_id: &PublicKey) -> Result<Option<ClosePlan>, Error> {
+        let state = self.state.lock().unwrap();
+        state.close_plans.get(&node_key(node_id)).map(|v| decode(v)).transpose()
+    }
+
+    fn update_fee_reserve(&self, node_id: &PublicKey, reserve: Vec<OutPoint>) -> Result<(), Error> {
+        self.state.lock().unwrap().fee_reserves.insert(node_key(node_id), encode(&reserve));
+        Ok(())
+    }
+
+    fn get_fee_reserve(&self, node_id: &PublicKey) -> Result<Vec<OutPoint>, Error> {
+        let state = self.state.lock().unwrap();
+        match state.fee_reserves.get(&node_key(node_id)) {
+            Some(value) => decode(value),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn update_payment(&self, node_id: &PublicKey, entry: &PaymentLedgerEntry) -> Result<(), Error> {
+        let key = encode(&(*node_id, entry.payment_hash));
+        self.state.lock().unwrap().payments.insert(key, encode(entry));
+        Ok(())
+    }
+
+    fn get_payments(&self, node_id: &PublicKey) -> Result<Vec<PaymentLedgerEntry>, Error> {
+        let state = self.state.lock().unwrap();
+        node_records(&state.payments, node_id).map(|(_, v)| decode(v)).collect()
+    }
+
+    fn get_fence(&self) -> Result<u64, Error> {
+        Ok(self.state.lock().unwrap().fence)
+    }
+
+    fn advance_fence(&self, current: u64) -> Result<u64, Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.fence != current {
+            return Err(Error::Refused(format!("fence was advanced to {}", state.fence)));
+        }
+        state.fence += 1;
+        Ok(state.fence)
+    }
+
+    fn clear_database(&self) -> Result<(), Error> {
+        *self.state.lock().unwrap() = State::default();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::channel::channel_nonce_to_id;
+    use crate::persist::model::PaymentResolution;
+    use crate::util::test_utils::*;
+
+    use super::*;
+
+    #[test]
+    fn snapshot_restore_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let (node_id, node, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let persister = MemoryPersister::new();
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed).unwrap();
+        persister.new_chain_tracker(&node_id, &node.get_tracker()).unwrap();
+        persister.new_channel(&node_id, &stub).unwrap();
+        let setup = create_test_channel_setup(make_dummy_pubkey(0x12));
+        let channel = node.ready_channel(channel_id0, None, setup, &vec![]).unwrap();
+        persister.update_channel(&node_id, &channel).unwrap();
+        let metadata = vec![("cohort".to_string(), "a".to_string())];
+        persister.update_metadata(&node_id, Some(&channel_id0), metadata.clone()).unwrap();
+        persister.update_node_allowlist(&node_id, vec!["addr".to_string()]).unwrap();
+        let record = AuditRecord::new(
+            Some(channel_id0),
+            "sign_holder_commitment_tx",
+            Some(0),
+            vec![1000],
+            vec![],
+        );
+        persister.append_audit_record(&node_id, &record).unwrap();
+        let payment = PaymentLedgerEntry {
+            payment_hash: PaymentHash([1; 32]),
+            outgoing: true,
+            amount_msat: 100_000,
+            invoice_hash: [2; 32],
+            expiry_time: 123_456_789,
+            resolution: PaymentResolution::Fulfilled,
+        };
+        persister.update_payment(&node_id, &payment).unwrap();
+
+        let snapshot = persister.snapshot();
+        let restored = MemoryPersister::restore(&snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+
+        let nodes = restored.get_nodes().unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].0, node_id);
+        assert_eq!(nodes[0].1.seed, seed.to_vec());
+        let entry = restored.get_channel(&node_id, &channel_id0).unwrap();
+        assert_eq!(entry.channel_value_satoshis, channel.setup.channel_value_sat);
+        assert_eq!(
+            entry.channel_setup.unwrap().counterparty_points.funding_pubkey,
+            channel.setup.counterparty_points.funding_pubkey
+        );
+        assert_eq!(restored.get_tracker(&node_id).unwrap().height(), node.get_tracker().height());
+        assert_eq!(restored.get_metadata(&node_id, Some(&channel_id0)).unwrap(), metadata);
+        assert_eq!(restored.get_node_allowlist(&node_id).unwrap(), vec!["addr".to_string()]);
+        let log = restored.get_audit_log(&node_id, Some(&channel_id0)).unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].hash(), record.hash());
+        assert_eq!(restored.get_payments(&node_id).unwrap(), vec![payment]);
+
+        // sequence numbers continue after a restore
+        restored.delete_channel(&node_id, &channel_id0).unwrap();
+        let changes = restored.export_since(2).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].sequence, 3);
+        assert!(matches!(changes[0].change, ChannelChange::Delete));
+    }
+
+    #[test]
+    fn restore_corrupt_test() {
+        let persister = MemoryPersister::new();
+        persister
+            .update_node_allowlist(&make_dummy_pubkey(0x12), vec!["addr".to_string()])
+            .unwrap();
+        let snapshot = persister.snapshot();
+        assert!(MemoryPersister::restore(&snapshot).is_ok());
+        for len in 0..snapshot.len() {
+            assert!(matches!(MemoryPersister::restore(&snapshot[..len]), Err(Error::Corrupt(_))));
+        }
+        let mut bad_version = snapshot.clone();
+        bad_version[4] = SNAPSHOT_VERSION + 1;
+        assert!(MemoryPersister::restore(&bad_version).is_err());
+    }
+
+    #[test]
+    fn fence_test() {
+        let persister = MemoryPersister::new();
+        assert_eq!(persister.get_fence().unwrap(), 0);
+        assert_eq!(persister.advance_fence(0).unwrap(), 1);
+        // a signer that read the old token lost the race
+        assert!(matches!(persister.advance_fence(0), Err(Error::Refused(_))));
+        assert_eq!(persister.advance_fence(1).unwrap(), 2);
+        assert_eq!(persister.get_fence().unwrap(), 2);
+    }
+}
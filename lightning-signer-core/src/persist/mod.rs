@@ -1,58 +1,247 @@
+use core::fmt;
+
 use crate::chain::tracker::ChainTracker;
 use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
 
 use crate::channel::{Channel, ChannelId, ChannelStub};
+use crate::close_plan::ClosePlan;
 use crate::monitor::ChainMonitor;
 use crate::node::NodeConfig;
 use crate::prelude::*;
 
+mod codec;
+/// An in-memory persister with snapshot and restore, for embedded use
+pub mod memory;
 /// Models for persistence
 pub mod model;
 
+/// Persistence errors
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The record was not found
+    NotFound(String),
+    /// The record already exists
+    AlreadyExists(String),
+    /// The store could not be reached or written to.  This may be
+    /// transient, so the operation can be retried.
+    Unavailable(String),
+    /// The store refused the write, for example because another replica
+    /// updated the record concurrently
+    Refused(String),
+    /// A stored record could not be decoded
+    Corrupt(String),
+    /// The store failed in a way that is not expected to clear up
+    Internal(String),
+}
+
+impl Error {
+    /// Whether the failed operation may succeed if retried
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Unavailable(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(s) => write!(f, "not found: {}", s),
+            Error::AlreadyExists(s) => write!(f, "already exists: {}", s),
+            Error::Unavailable(s) => write!(f, "unavailable: {}", s),
+            Error::Refused(s) => write!(f, "refused: {}", s),
+            Error::Corrupt(s) => write!(f, "corrupt: {}", s),
+            Error::Internal(s) => write!(f, "internal: {}", s),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Related records that are persisted together by [Persist::update_batch]
+#[derive(Default)]
+pub struct PersistBatch<'a> {
+    /// The chain tracker, if it changed
+    pub tracker: Option<&'a ChainTracker<ChainMonitor>>,
+    /// The channels that changed
+    pub channels: Vec<&'a Channel>,
+}
+
 /// Persister of nodes and channels
 ///
 /// A Node will call the relevant methods here as needed.
 /// The persister should durably persist before returning, for safety.
 pub trait Persist: Sync + Send {
     /// Create a new node
-    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]);
+    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]) -> Result<(), Error>;
     /// Delete a node and all of its channels.  Used in test mode.
-    fn delete_node(&self, node_id: &PublicKey);
+    fn delete_node(&self, node_id: &PublicKey) -> Result<(), Error>;
     /// Will error if exists
-    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), ()>;
+    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), Error>;
 
     /// Create a new tracker
-    fn new_chain_tracker(&self, node_id: &PublicKey, tracker: &ChainTracker<ChainMonitor>);
+    fn new_chain_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error>;
     /// Update the tracker
     fn update_tracker(
         &self,
         node_id: &PublicKey,
         tracker: &ChainTracker<ChainMonitor>,
-    ) -> Result<(), ()>;
+    ) -> Result<(), Error>;
     /// Get the tracker
-    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, ()>;
+    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, Error>;
 
     /// Will error if doesn't exist.
     ///
     /// * `id0` original channel ID supplied to [`Persist::new_channel()`]
     /// * `id` an optional additional permanent channel ID
-    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), ()>;
+    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), Error>;
+    /// Persist related records atomically, so that a crash can't leave
+    /// some of them updated and others not.  Will error if a channel
+    /// doesn't exist.
+    ///
+    /// The default implementation persists the records one at a time.
+    /// Persisters that support transactions should override it.
+    fn update_batch(&self, node_id: &PublicKey, batch: &PersistBatch) -> Result<(), Error> {
+        if let Some(tracker) = batch.tracker {
+            self.update_tracker(node_id, tracker)?;
+        }
+        for channel in batch.channels.iter() {
+            self.update_channel(node_id, channel)?;
+        }
+        Ok(())
+    }
+    /// Delete a channel and its metadata, such as a pruned closed channel.
+    ///
+    /// * `id0` original channel ID supplied to [`Persist::new_channel()`]
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), Error>;
     /// Get a channel from store
     fn get_channel(
         &self,
         node_id: &PublicKey,
         channel_id: &ChannelId,
-    ) -> Result<model::ChannelEntry, ()>;
+    ) -> Result<model::ChannelEntry, Error>;
     /// Get all channels for a node from store
-    fn get_node_channels(&self, node_id: &PublicKey) -> Vec<(ChannelId, model::ChannelEntry)>;
+    fn get_node_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Result<Vec<(ChannelId, model::ChannelEntry)>, Error>;
     /// Persist the allowlist to the store.
-    fn update_node_allowlist(&self, node_id: &PublicKey, allowlist: Vec<String>) -> Result<(), ()>;
+    fn update_node_allowlist(
+        &self,
+        node_id: &PublicKey,
+        allowlist: Vec<String>,
+    ) -> Result<(), Error>;
     /// Get the allowlist from the store.
-    fn get_node_allowlist(&self, node_id: &PublicKey) -> Vec<String>;
+    fn get_node_allowlist(&self, node_id: &PublicKey) -> Result<Vec<String>, Error>;
+    /// Persist the operator-defined metadata of a node, or of one of its
+    /// channels if `channel_id` is supplied.
+    fn update_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+        metadata: Vec<(String, String)>,
+    ) -> Result<(), Error>;
+    /// Get the operator-defined metadata of a node or channel from the store.
+    fn get_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<(String, String)>, Error>;
     /// Get all nodes from store
-    fn get_nodes(&self) -> Vec<(PublicKey, model::NodeEntry)>;
+    fn get_nodes(&self) -> Result<Vec<(PublicKey, model::NodeEntry)>, Error>;
+    /// Get the channel changes with a sequence number greater than
+    /// `sequence`, in sequence order.
+    ///
+    /// Each channel appears at most once, with its latest entry or as
+    /// deleted, so a replica that applies the changes and remembers the
+    /// last sequence number stays in sync without copying every channel.
+    /// Nodes, trackers and allowlists are not included.
+    fn export_since(&self, sequence: u64) -> Result<Vec<model::ChangeRecord>, Error>;
+    /// Append a signing operation to the audit log.  The log is append-only,
+    /// but the persister may drop the oldest records of a channel according
+    /// to its retention configuration.
+    fn append_audit_record(
+        &self,
+        node_id: &PublicKey,
+        record: &model::AuditRecord,
+    ) -> Result<(), Error>;
+    /// Get the audit log of a channel, or of the whole node if `channel_id`
+    /// is None, oldest first.
+    ///
+    /// * `channel_id` the initial channel ID
+    fn get_audit_log(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<model::AuditRecord>, Error>;
+    /// Update the multi-channel close plan of a node, or delete it if
+    /// `plan` is None
+    fn update_close_plan(&self, node_id: &PublicKey, plan: Option<&ClosePlan>)
+        -> Result<(), Error>;
+    /// Get the multi-channel close plan of a node, if any
+    fn get_close_plan(&self, node_id: &PublicKey) -> Result<Option<ClosePlan>, Error>;
+    /// Persist the wallet UTXOs of a node reserved for fee bumping
+    fn update_fee_reserve(&self, node_id: &PublicKey, reserve: Vec<OutPoint>) -> Result<(), Error>;
+    /// Get the wallet UTXOs of a node reserved for fee bumping from the store
+    fn get_fee_reserve(&self, node_id: &PublicKey) -> Result<Vec<OutPoint>, Error>;
+    /// Persist an entry of the payment ledger of a node, replacing the
+    /// entry with the same payment hash, if any
+    fn update_payment(
+        &self,
+        node_id: &PublicKey,
+        entry: &model::PaymentLedgerEntry,
+    ) -> Result<(), Error>;
+    /// Get the payment ledger of a node from the store
+    fn get_payments(&self, node_id: &PublicKey) -> Result<Vec<model::PaymentLedgerEntry>, Error>;
+    /// Export all nodes, with their channels, allowlists and chain
+    /// trackers, as a versioned archive encrypted with `passphrase`, for
+    /// cold backups or to move the signer to another host.
+    ///
+    /// The default implementation fails, for persisters that keep no state.
+    fn export_all(&self, _passphrase: &str) -> Result<Vec<u8>, Error> {
+        Err(Error::Internal("export not supported".to_string()))
+    }
+    /// Import an archive created by [Persist::export_all], possibly by
+    /// another kind of persister, and return the IDs of the imported nodes.
+    ///
+    /// Nothing is imported if the archive can't be decrypted or if one of
+    /// its nodes already exists.
+    fn import_all(&self, _passphrase: &str, _archive: &[u8]) -> Result<Vec<PublicKey>, Error> {
+        Err(Error::Internal("import not supported".to_string()))
+    }
+    /// Get the fencing token of the store, zero if it was never advanced.
+    ///
+    /// Signers sharing a store, such as a primary and its standby, take
+    /// the next token with [Persist::advance_fence] before they sign, and
+    /// stop signing once the token in the store is not theirs anymore.
+    ///
+    /// The default implementation fails, for persisters that are not
+    /// shared.
+    fn get_fence(&self) -> Result<u64, Error> {
+        Err(Error::Internal("fencing not supported".to_string()))
+    }
+    /// Advance the fencing token from `current` and return the new token.
+    /// Fails with [Error::Refused] if the token in the store is not
+    /// `current`, because another signer advanced it.
+    fn advance_fence(&self, _current: u64) -> Result<u64, Error> {
+        Err(Error::Internal("fencing not supported".to_string()))
+    }
+    /// Make any buffered writes durable, before the process exits.
+    ///
+    /// The default implementation does nothing, for persisters that are
+    /// durable when each write returns.
+    fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
     /// Clears the database.  Not for production use.
-    fn clear_database(&self);
+    fn clear_database(&self) -> Result<(), Error>;
 }
 
 /// A null persister for testing
@@ -60,29 +249,43 @@ pub struct DummyPersister;
 
 #[allow(unused_variables)]
 impl Persist for DummyPersister {
-    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]) {}
+    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]) -> Result<(), Error> {
+        Ok(())
+    }
 
-    fn delete_node(&self, node_id: &PublicKey) {}
+    fn delete_node(&self, node_id: &PublicKey) -> Result<(), Error> {
+        Ok(())
+    }
 
-    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), ()> {
+    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), Error> {
         Ok(())
     }
 
-    fn new_chain_tracker(&self, node_id: &PublicKey, tracker: &ChainTracker<ChainMonitor>) {}
+    fn new_chain_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
 
     fn update_tracker(
         &self,
         node_id: &PublicKey,
         tracker: &ChainTracker<ChainMonitor>,
-    ) -> Result<(), ()> {
+    ) -> Result<(), Error> {
         Ok(())
     }
 
-    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, ()> {
-        Err(())
+    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, Error> {
+        Err(Error::NotFound("tracker".to_string()))
+    }
+
+    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), Error> {
+        Ok(())
     }
 
-    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), ()> {
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), Error> {
         Ok(())
     }
 
@@ -90,25 +293,103 @@ impl Persist for DummyPersister {
         &self,
         node_id: &PublicKey,
         channel_id: &ChannelId,
-    ) -> Result<model::ChannelEntry, ()> {
-        Err(())
+    ) -> Result<model::ChannelEntry, Error> {
+        Err(Error::NotFound("channel".to_string()))
+    }
+
+    fn get_node_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Result<Vec<(ChannelId, model::ChannelEntry)>, Error> {
+        Ok(Vec::new())
+    }
+
+    fn update_node_allowlist(
+        &self,
+        node_id: &PublicKey,
+        allowlist: Vec<String>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_node_allowlist(&self, node_id: &PublicKey) -> Result<Vec<String>, Error> {
+        Ok(Vec::new())
+    }
+
+    fn update_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+        metadata: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        Ok(Vec::new())
+    }
+
+    fn get_nodes(&self) -> Result<Vec<(PublicKey, model::NodeEntry)>, Error> {
+        Ok(Vec::new())
+    }
+
+    fn export_since(&self, sequence: u64) -> Result<Vec<model::ChangeRecord>, Error> {
+        Ok(Vec::new())
+    }
+
+    fn append_audit_record(
+        &self,
+        node_id: &PublicKey,
+        record: &model::AuditRecord,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_audit_log(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<model::AuditRecord>, Error> {
+        Ok(Vec::new())
     }
 
-    fn get_node_channels(&self, node_id: &PublicKey) -> Vec<(ChannelId, model::ChannelEntry)> {
-        Vec::new()
+    fn update_close_plan(
+        &self,
+        node_id: &PublicKey,
+        plan: Option<&ClosePlan>,
+    ) -> Result<(), Error> {
+        Ok(())
     }
 
-    fn update_node_allowlist(&self, node_id: &PublicKey, allowlist: Vec<String>) -> Result<(), ()> {
+    fn get_close_plan(&self, node_id: &PublicKey) -> Result<Option<ClosePlan>, Error> {
+        Ok(None)
+    }
+
+    fn update_fee_reserve(&self, node_id: &PublicKey, reserve: Vec<OutPoint>) -> Result<(), Error> {
         Ok(())
     }
 
-    fn get_node_allowlist(&self, node_id: &PublicKey) -> Vec<String> {
-        Vec::new()
+    fn get_fee_reserve(&self, node_id: &PublicKey) -> Result<Vec<OutPoint>, Error> {
+        Ok(Vec::new())
     }
 
-    fn get_nodes(&self) -> Vec<(PublicKey, model::NodeEntry)> {
-        Vec::new()
+    fn update_payment(
+        &self,
+        node_id: &PublicKey,
+        entry: &model::PaymentLedgerEntry,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_payments(&self, node_id: &PublicKey) -> Result<Vec<model::PaymentLedgerEntry>, Error> {
+        Ok(Vec::new())
     }
 
-    fn clear_database(&self) {}
+    fn clear_database(&self) -> Result<(), Error> {
+        Ok(())
+    }
 }
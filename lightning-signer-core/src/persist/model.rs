@@ -1,5 +1,12 @@
+use bitcoin::hashes::sha256::{self, Hash as Sha256Hash};
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, Signature};
+use bitcoin::{Script, Transaction};
+use lightning::ln::PaymentHash;
+
 use crate::channel::ChannelId;
 use crate::channel::ChannelSetup;
+use crate::node::ChainParams;
 use crate::policy::validator::EnforcementState;
 use crate::prelude::*;
 
@@ -9,6 +16,7 @@ pub struct NodeEntry {
     pub seed: Vec<u8>,
     pub key_derivation_style: u8,
     pub network: String,
+    pub chain_params: Option<ChainParams>,
 }
 
 /// A persistence layer entry for a channel
@@ -22,3 +30,330 @@ pub struct ChannelEntry {
     pub id: Option<ChannelId>,
     pub enforcement_state: EnforcementState,
 }
+
+/// A change to the persisted channels, for incremental replication
+#[derive(Debug)]
+pub enum ChannelChange {
+    /// A channel was created or updated
+    Update(ChannelEntry),
+    /// A channel was deleted
+    Delete,
+}
+
+/// A channel change with its sequence number, as returned by
+/// [`crate::persist::Persist::export_since`]
+#[allow(missing_docs)]
+#[derive(Debug)]
+pub struct ChangeRecord {
+    pub sequence: u64,
+    pub node_id: PublicKey,
+    pub id0: ChannelId,
+    pub change: ChannelChange,
+}
+
+/// How a payment in the ledger was resolved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaymentResolution {
+    /// Not resolved yet, or being attempted again after a failure
+    Pending,
+    /// The preimage is known, so the payment succeeded
+    Fulfilled,
+    /// Every HTLC of the last attempt at an outgoing payment failed
+    Failed,
+    /// The invoice expired before it was paid
+    Expired,
+}
+
+/// A payment for an invoice, as recorded in the node's payment ledger.
+///
+/// The ledger survives restarts, so that an invoice that was already paid
+/// is not paid again, and the payments can be reconciled with the balance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaymentLedgerEntry {
+    /// The payment hash of the invoice
+    pub payment_hash: PaymentHash,
+    /// Whether the node pays the invoice, rather than having issued it
+    pub outgoing: bool,
+    /// The invoiced amount, zero if the invoice has no amount
+    pub amount_msat: u64,
+    /// The hash of the invoice
+    pub invoice_hash: [u8; 32],
+    /// When the invoice expires, in seconds since the UNIX epoch
+    pub expiry_time: u64,
+    /// How the payment was resolved
+    pub resolution: PaymentResolution,
+}
+
+/// The totals of a payment ledger, for balance reconciliation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PaymentLedgerTotals {
+    /// Fulfilled outgoing payments
+    pub paid_msat: u64,
+    /// Fulfilled incoming payments
+    pub received_msat: u64,
+    /// Outgoing payments that are not resolved yet
+    pub pending_outgoing_msat: u64,
+    /// Incoming payments that are not resolved yet
+    pub pending_incoming_msat: u64,
+}
+
+impl PaymentLedgerTotals {
+    /// Sum up the invoiced amounts of ledger entries
+    pub fn new(entries: &[PaymentLedgerEntry]) -> Self {
+        let mut totals = PaymentLedgerTotals::default();
+        for entry in entries {
+            let total = match (entry.resolution, entry.outgoing) {
+                (PaymentResolution::Fulfilled, true) => &mut totals.paid_msat,
+                (PaymentResolution::Fulfilled, false) => &mut totals.received_msat,
+                (PaymentResolution::Pending, true) => &mut totals.pending_outgoing_msat,
+                (PaymentResolution::Pending, false) => &mut totals.pending_incoming_msat,
+                (PaymentResolution::Failed, _) | (PaymentResolution::Expired, _) => continue,
+            };
+            *total += entry.amount_msat;
+        }
+        totals
+    }
+}
+
+/// A signing operation, as recorded in the audit log.
+///
+/// The sequence number and timestamp are assigned by the persister.
+///
+/// The records of a channel, and the node-level records of a node, form
+/// a hash chain.  Each record has the hash of the previous record in its
+/// chain, and is signed by the node's audit key, so that removed or
+/// altered records are detected by [verify_audit_log].
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditRecord {
+    /// Increases in the order the operations were recorded
+    pub sequence: u64,
+    /// Seconds since the UNIX epoch, or zero if the persister has no clock
+    pub timestamp: u64,
+    /// The initial channel ID, or None for a node-level operation
+    pub channel_id: Option<ChannelId>,
+    /// The operation, such as "sign_mutual_close_tx"
+    pub operation: String,
+    /// The commitment number, for operations on a commitment
+    pub commit_num: Option<u64>,
+    /// The amounts of the signed transaction, in satoshi
+    pub values_sat: Vec<u64>,
+    /// The destination scripts of the signed transaction
+    pub destinations: Vec<Script>,
+    /// The validation failure, or None if the operation was signed
+    pub error: Option<String>,
+    /// The hash of the previous record in the chain, or zero for the first
+    /// record
+    pub prev_hash: [u8; 32],
+    /// The DER encoded signature of [AuditRecord::hash] by the audit key,
+    /// empty for records written before the log was signed
+    pub signature: Vec<u8>,
+}
+
+impl AuditRecord {
+    /// Create a record of a signed operation
+    pub fn new(
+        channel_id: Option<ChannelId>,
+        operation: &str,
+        commit_num: Option<u64>,
+        values_sat: Vec<u64>,
+        destinations: Vec<Script>,
+    ) -> Self {
+        AuditRecord {
+            sequence: 0,
+            timestamp: 0,
+            channel_id,
+            operation: operation.to_string(),
+            commit_num,
+            values_sat,
+            destinations,
+            error: None,
+            prev_hash: [0; 32],
+            signature: vec![],
+        }
+    }
+
+    /// Create a record of a signed transaction, with its output values and
+    /// scripts
+    pub fn for_tx(
+        channel_id: Option<ChannelId>,
+        operation: &str,
+        commit_num: Option<u64>,
+        tx: &Transaction,
+    ) -> Self {
+        Self::new(
+            channel_id,
+            operation,
+            commit_num,
+            tx.output.iter().map(|o| o.value).collect(),
+            tx.output.iter().map(|o| o.script_pubkey.clone()).collect(),
+        )
+    }
+
+    /// The hash of the record, which is signed and chained.  The sequence
+    /// number and timestamp are assigned by the persister, and are not
+    /// covered.
+    pub fn hash(&self) -> [u8; 32] {
+        fn input_bytes(engine: &mut sha256::HashEngine, bytes: &[u8]) {
+            engine.input(&(bytes.len() as u32).to_be_bytes());
+            engine.input(bytes);
+        }
+
+        let mut engine = Sha256Hash::engine();
+        engine.input(&self.prev_hash);
+        match &self.channel_id {
+            Some(channel_id) => {
+                engine.input(&[1]);
+                engine.input(&channel_id.0);
+            }
+            None => engine.input(&[0]),
+        }
+        input_bytes(&mut engine, self.operation.as_bytes());
+        match self.commit_num {
+            Some(commit_num) => {
+                engine.input(&[1]);
+                engine.input(&commit_num.to_be_bytes());
+            }
+            None => engine.input(&[0]),
+        }
+        engine.input(&(self.values_sat.len() as u32).to_be_bytes());
+        for value_sat in &self.values_sat {
+            engine.input(&value_sat.to_be_bytes());
+        }
+        engine.input(&(self.destinations.len() as u32).to_be_bytes());
+        for destination in &self.destinations {
+            input_bytes(&mut engine, destination.as_bytes());
+        }
+        match &self.error {
+            Some(error) => {
+                engine.input(&[1]);
+                input_bytes(&mut engine, error.as_bytes());
+            }
+            None => engine.input(&[0]),
+        }
+        Sha256Hash::from_engine(engine).into_inner()
+    }
+}
+
+/// A failure to verify an audit log, see [verify_audit_log]
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuditLogError {
+    /// The record with this sequence number doesn't have the hash of the
+    /// previous record in its chain, so a record was removed or altered
+    BrokenChain(u64),
+    /// The record with this sequence number has a bad signature
+    BadSignature(u64),
+    /// The record with this sequence number is unsigned, but follows a
+    /// signed record
+    Unsigned(u64),
+}
+
+/// Verify an audit log, as returned by
+/// [crate::persist::Persist::get_audit_log], against the node's audit
+/// public key.
+///
+/// Each record must be signed and must have the hash of the previous
+/// record of its chain.  The first record of a chain in the log has a zero
+/// previous hash, unless older records were dropped according to the
+/// retention configuration of the persister.  Unsigned records, written
+/// before the log was signed, are only accepted at the start of a chain.
+pub fn verify_audit_log(
+    records: &[AuditRecord],
+    audit_pubkey: &PublicKey,
+) -> Result<(), AuditLogError> {
+    let secp_ctx = Secp256k1::verification_only();
+    // the hash of the last record of each chain, and whether it was signed
+    let mut heads: OrderedMap<Option<ChannelId>, ([u8; 32], bool)> = OrderedMap::new();
+    for record in records {
+        let hash = record.hash();
+        let head = heads.get(&record.channel_id);
+        if let Some((head_hash, _)) = head {
+            if record.prev_hash != *head_hash {
+                return Err(AuditLogError::BrokenChain(record.sequence));
+            }
+        }
+        let signed = !record.signature.is_empty();
+        if signed {
+            let sig = Signature::from_der(&record.signature)
+                .map_err(|_| AuditLogError::BadSignature(record.sequence))?;
+            let message = Message::from_slice(&hash).expect("sha256");
+            secp_ctx
+                .verify(&message, &sig, audit_pubkey)
+                .map_err(|_| AuditLogError::BadSignature(record.sequence))?;
+        } else if head.map(|(_, head_signed)| *head_signed).unwrap_or(false) {
+            return Err(AuditLogError::Unsigned(record.sequence));
+        }
+        heads.insert(record.channel_id, (hash, signed));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::SecretKey;
+
+    use super::*;
+
+    // Append a record to a log, chained and signed as by the node
+    fn append(log: &mut Vec<AuditRecord>, key: &SecretKey, mut record: AuditRecord) {
+        let secp_ctx = Secp256k1::signing_only();
+        record.sequence = log.len() as u64 + 1;
+        record.prev_hash = log
+            .iter()
+            .filter(|r| r.channel_id == record.channel_id)
+            .last()
+            .map(|r| r.hash())
+            .unwrap_or([0; 32]);
+        let message = Message::from_slice(&record.hash()).unwrap();
+        record.signature = secp_ctx.sign(&message, key).serialize_der().to_vec();
+        log.push(record);
+    }
+
+    #[test]
+    fn verify_audit_log_test() {
+        let key = SecretKey::from_slice(&[3; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::signing_only(), &key);
+        let channel_id = ChannelId([1; 32]);
+        let commitment = |commit_num| {
+            AuditRecord::new(
+                Some(channel_id),
+                "sign_holder_commitment_tx",
+                Some(commit_num),
+                vec![1000],
+                vec![],
+            )
+        };
+
+        let mut log = vec![];
+        append(&mut log, &key, commitment(0));
+        append(&mut log, &key, AuditRecord::new(None, "sign_onchain_tx", None, vec![500], vec![]));
+        append(&mut log, &key, commitment(1));
+        append(&mut log, &key, commitment(2));
+        assert_eq!(verify_audit_log(&log, &pubkey), Ok(()));
+        assert_eq!(log[2].prev_hash, log[0].hash());
+        assert_eq!(log[1].prev_hash, [0; 32]);
+
+        // the oldest records may be dropped by retention
+        assert_eq!(verify_audit_log(&log[1..], &pubkey), Ok(()));
+
+        let mut removed = log.clone();
+        removed.remove(2);
+        assert_eq!(verify_audit_log(&removed, &pubkey), Err(AuditLogError::BrokenChain(4)));
+
+        let mut altered = log.clone();
+        altered[2].values_sat = vec![2000];
+        assert_eq!(verify_audit_log(&altered, &pubkey), Err(AuditLogError::BadSignature(3)));
+
+        let other_pubkey = PublicKey::from_secret_key(
+            &Secp256k1::signing_only(),
+            &SecretKey::from_slice(&[4; 32]).unwrap(),
+        );
+        assert_eq!(verify_audit_log(&log, &other_pubkey), Err(AuditLogError::BadSignature(1)));
+
+        // unsigned records are only accepted before the log was signed
+        let mut unsigned = log.clone();
+        unsigned[2].signature = vec![];
+        assert_eq!(verify_audit_log(&unsigned, &pubkey), Err(AuditLogError::Unsigned(3)));
+        unsigned[0].signature = vec![];
+        assert_eq!(verify_audit_log(&unsigned, &pubkey), Ok(()));
+    }
+}
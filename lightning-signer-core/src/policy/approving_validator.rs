@@ -0,0 +1,458 @@
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use bitcoin::{self, Network, Script, SigHash, SigHashType, Transaction};
+use lightning::chain::keysinterface::InMemorySigner;
+use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
+use log::warn;
+
+use crate::channel::{Channel, ChannelId, ChannelSetup, ChannelSlot};
+use crate::persist::model::PaymentLedgerEntry;
+use crate::policy::error::ValidationErrorKind;
+use crate::policy::validator::EnforcementState;
+use crate::policy::validator::{ChainState, CoreTypes, Validator, ValidatorFactory};
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::tx::interactive::{InteractiveFunding, InteractiveInput, InteractiveOutput};
+use crate::tx::tx::{CommitmentInfo, CommitmentInfo2};
+use crate::wallet::Wallet;
+
+use super::error::ValidationError;
+
+/// Decides whether an operator approved a policy failure
+pub trait Approver: Sync + Send {
+    /// Whether the policy failure with `message` is approved for `node_id`.
+    ///
+    /// The message is formatted as in the status returned to the caller,
+    /// for example "policy failure: ...".
+    fn is_approved(&self, node_id: &PublicKey, message: &str) -> bool;
+}
+
+/// A factory for ApprovingValidator
+pub struct ApprovingValidatorFactory {
+    inner_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
+    approver: Arc<dyn Approver>,
+}
+
+impl ApprovingValidatorFactory {
+    /// Wrap the validators of `inner_factory`
+    pub fn new(
+        inner_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
+        approver: Arc<dyn Approver>,
+    ) -> Self {
+        Self { inner_factory, approver }
+    }
+}
+
+impl ValidatorFactory<CoreTypes> for ApprovingValidatorFactory {
+    fn make_validator(
+        &self,
+        network: Network,
+        node_id: PublicKey,
+        channel_id: Option<ChannelId>,
+    ) -> Arc<dyn Validator<CoreTypes>> {
+        let validator = ApprovingValidator {
+            inner: self.inner_factory.make_validator(network, node_id, channel_id),
+            node_id,
+            approver: Arc::clone(&self.approver),
+        };
+        Arc::new(validator)
+    }
+}
+
+/// A validator that accepts the policy failures an operator approved.
+///
+/// Only the validation of on-chain and mutual close transactions, which
+/// spend funds to destinations, can be approved.  All other validation,
+/// and any failure that is not a policy failure, is passed through.
+pub struct ApprovingValidator {
+    inner: Arc<dyn Validator<CoreTypes>>,
+    node_id: PublicKey,
+    approver: Arc<dyn Approver>,
+}
+
+impl ApprovingValidator {
+    fn approve(&self, res: Result<(), ValidationError>) -> Result<(), ValidationError> {
+        match res {
+            Err(ve) if matches!(ve.kind, ValidationErrorKind::Policy(_)) => {
+                let message: String = ve.clone().into();
+                if self.approver.is_approved(&self.node_id, &message) {
+                    warn!("{}: accepting approved {}", self.node_id, message);
+                    Ok(())
+                } else {
+                    Err(ve)
+                }
+            }
+            res => res,
+        }
+    }
+}
+
+impl Validator<CoreTypes> for ApprovingValidator {
+    fn validate_ready_channel(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        holder_shutdown_key_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_ready_channel(wallet, setup, holder_shutdown_key_path)
+    }
+
+    fn validate_channel_value(&self, setup: &ChannelSetup) -> Result<(), ValidationError> {
+        self.inner.validate_channel_value(setup)
+    }
+
+    fn validate_onchain_tx(
+        &self,
+        wallet: &Wallet,
+        channels: Vec<Option<Arc<Mutex<ChannelSlot>>>>,
+        tx: &Transaction,
+        values_sat: &Vec<u64>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<(), ValidationError> {
+        self.approve(self.inner.validate_onchain_tx(wallet, channels, tx, values_sat, opaths))
+    }
+
+    fn validate_funding_tx(
+        &self,
+        wallet: &Wallet,
+        channels: &Vec<Option<Arc<Mutex<ChannelSlot>>>>,
+        tx: &Transaction,
+        inputs: &Vec<InteractiveInput>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<(), ValidationError> {
+        self.approve(self.inner.validate_funding_tx(wallet, channels, tx, inputs, opaths))
+    }
+
+    fn validate_interactive_funding_input(
+        &self,
+        wallet: &Wallet,
+        input: &InteractiveInput,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_interactive_funding_input(wallet, input)
+    }
+
+    fn validate_interactive_funding_output(
+        &self,
+        wallet: &Wallet,
+        output: &InteractiveOutput,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_interactive_funding_output(wallet, output)
+    }
+
+    fn validate_interactive_funding_tx(
+        &self,
+        chan: &Channel,
+        tx: &Transaction,
+        funding: &InteractiveFunding,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_interactive_funding_tx(chan, tx, funding)
+    }
+
+    fn validate_splice_tx(
+        &self,
+        wallet: &Wallet,
+        chan: &Channel,
+        tx: &Transaction,
+        vout: u32,
+        inputs: &Vec<InteractiveInput>,
+        outputs: &Vec<InteractiveOutput>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_splice_tx(wallet, chan, tx, vout, inputs, outputs)
+    }
+
+    fn decode_commitment_tx(
+        &self,
+        keys: &InMemorySigner,
+        setup: &ChannelSetup,
+        is_counterparty: bool,
+        tx: &bitcoin::Transaction,
+        output_witscripts: &Vec<Vec<u8>>,
+    ) -> Result<CommitmentInfo, ValidationError> {
+        self.inner.decode_commitment_tx(keys, setup, is_counterparty, tx, output_witscripts)
+    }
+
+    fn validate_counterparty_commitment_tx(
+        &self,
+        estate: &EnforcementState,
+        commit_num: u64,
+        commitment_point: &PublicKey,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        info2: &CommitmentInfo2,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_counterparty_commitment_tx(
+            estate,
+            commit_num,
+            commitment_point,
+            setup,
+            cstate,
+            info2,
+        )
+    }
+
+    fn validate_holder_commitment_tx(
+        &self,
+        estate: &EnforcementState,
+        commit_num: u64,
+        commitment_point: &PublicKey,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        info2: &CommitmentInfo2,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_holder_commitment_tx(
+            estate,
+            commit_num,
+            commitment_point,
+            setup,
+            cstate,
+            info2,
+        )
+    }
+
+    fn validate_counterparty_revocation(
+        &self,
+        state: &EnforcementState,
+        revoke_num: u64,
+        commitment_secret: &SecretKey,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_counterparty_revocation(state, revoke_num, commitment_secret)
+    }
+
+    // Phase 1
+    // setup and txkeys must come from a trusted source
+    fn decode_and_validate_htlc_tx(
+        &self,
+        is_counterparty: bool,
+        setup: &ChannelSetup,
+        txkeys: &TxCreationKeys,
+        tx: &Transaction,
+        redeemscript: &Script,
+        htlc_amount_sat: u64,
+        output_witscript: &Script,
+    ) -> Result<(u32, HTLCOutputInCommitment, SigHash, SigHashType), ValidationError> {
+        self.inner.decode_and_validate_htlc_tx(
+            is_counterparty,
+            setup,
+            txkeys,
+            tx,
+            redeemscript,
+            htlc_amount_sat,
+            output_witscript,
+        )
+    }
+
+    fn validate_htlc_tx(
+        &self,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        is_counterparty: bool,
+        htlc: &HTLCOutputInCommitment,
+        feerate_per_kw: u32,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_htlc_tx(setup, cstate, is_counterparty, htlc, feerate_per_kw)
+    }
+
+    fn decode_and_validate_mutual_close_tx(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        tx: &Transaction,
+        wallet_paths: &Vec<Vec<u32>>,
+    ) -> Result<ClosingTransaction, ValidationError> {
+        self.inner.decode_and_validate_mutual_close_tx(wallet, setup, estate, tx, wallet_paths)
+    }
+
+    fn validate_mutual_close_tx(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        state: &EnforcementState,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.approve(self.inner.validate_mutual_close_tx(
+            wallet,
+            setup,
+            state,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+            holder_wallet_path_hint,
+        ))
+    }
+
+    #[cfg(feature = "simple_close")]
+    fn validate_simple_close_tx(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        state: &EnforcementState,
+        holder_is_closer: bool,
+        locktime: u32,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.approve(self.inner.validate_simple_close_tx(
+            wallet,
+            setup,
+            state,
+            holder_is_closer,
+            locktime,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+            holder_wallet_path_hint,
+        ))
+    }
+
+    fn validate_close_plan(
+        &self,
+        fee_sat: u64,
+        non_wallet_sat: u64,
+    ) -> Result<(), ValidationError> {
+        self.approve(self.inner.validate_close_plan(fee_sat, non_wallet_sat))
+    }
+
+    fn validate_delayed_sweep(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        tx: &Transaction,
+        input: usize,
+        amount_sat: u64,
+        wallet_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_delayed_sweep(wallet, setup, cstate, tx, input, amount_sat, wallet_path)
+    }
+
+    fn validate_counterparty_payment_sweep(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        tx: &Transaction,
+        input: usize,
+        amount_sat: u64,
+        wallet_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_counterparty_payment_sweep(
+            wallet,
+            setup,
+            cstate,
+            tx,
+            input,
+            amount_sat,
+            wallet_path,
+        )
+    }
+
+    fn validate_counterparty_htlc_sweep(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        tx: &Transaction,
+        redeemscript: &Script,
+        input: usize,
+        amount_sat: u64,
+        wallet_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_counterparty_htlc_sweep(
+            wallet,
+            setup,
+            cstate,
+            tx,
+            redeemscript,
+            input,
+            amount_sat,
+            wallet_path,
+        )
+    }
+
+    fn validate_justice_sweep(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        tx: &Transaction,
+        input: usize,
+        amount_sat: u64,
+        wallet_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_justice_sweep(wallet, setup, cstate, tx, input, amount_sat, wallet_path)
+    }
+
+    fn validate_payment_balance(
+        &self,
+        incoming: u64,
+        outgoing: u64,
+        invoiced_amount: Option<u64>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_payment_balance(incoming, outgoing, invoiced_amount)
+    }
+
+    fn enforce_balance(&self) -> bool {
+        self.inner.enforce_balance()
+    }
+
+    fn require_funding_registration(&self) -> bool {
+        self.inner.require_funding_registration()
+    }
+
+    fn grind_low_r(&self) -> bool {
+        self.inner.grind_low_r()
+    }
+
+    fn verify_signatures(&self) -> bool {
+        self.inner.verify_signatures()
+    }
+
+    fn channel_review_due(&self, estate: &EnforcementState, cstate: &ChainState) -> Option<String> {
+        self.inner.channel_review_due(estate, cstate)
+    }
+
+    fn validate_payment_retry(
+        &self,
+        attempt_heights: &[u32],
+        cstate: &ChainState,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_payment_retry(attempt_heights, cstate)
+    }
+
+    fn validate_onchain_velocity(
+        &self,
+        spends: &[(u32, u64)],
+        value_sat: u64,
+        cstate: &ChainState,
+    ) -> Result<(), ValidationError> {
+        self.approve(self.inner.validate_onchain_velocity(spends, value_sat, cstate))
+    }
+
+    fn validate_payment_duplicate(
+        &self,
+        entry: &PaymentLedgerEntry,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_payment_duplicate(entry)
+    }
+
+    fn validate_invoice(&self, amount_msat: u64, expiry_secs: u64) -> Result<(), ValidationError> {
+        self.inner.validate_invoice(amount_msat, expiry_secs)
+    }
+
+    fn minimum_initial_balance(&self, holder_value_msat: u64) -> u64 {
+        self.inner.minimum_initial_balance(holder_value_msat)
+    }
+
+    fn validate_clock_skew(&self, clock_skew_secs: i64) -> Result<(), ValidationError> {
+        self.inner.validate_clock_skew(clock_skew_secs)
+    }
+}
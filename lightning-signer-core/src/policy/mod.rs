@@ -1,6 +1,8 @@
-/// Policy errors
+/// Policy errors, defined in the `vls-policy` crate
 #[macro_use]
 pub mod error;
+/// Operator approval of policy failures
+pub mod approving_validator;
 /// Null policy enforcement
 #[cfg(feature = "test_utils")]
 pub mod null_validator;
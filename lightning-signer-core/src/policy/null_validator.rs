@@ -3,12 +3,13 @@ use bitcoin::{self, Network, Script, SigHash, SigHashType, Transaction};
 use lightning::chain::keysinterface::InMemorySigner;
 use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
 
-use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
+use crate::channel::{Channel, ChannelId, ChannelSetup, ChannelSlot};
 use crate::policy::simple_validator::SimpleValidatorFactory;
 use crate::policy::validator::EnforcementState;
-use crate::policy::validator::{ChainState, Validator, ValidatorFactory};
+use crate::policy::validator::{ChainState, CoreTypes, Validator, ValidatorFactory};
 use crate::prelude::*;
 use crate::sync::Arc;
+use crate::tx::interactive::{InteractiveFunding, InteractiveInput, InteractiveOutput};
 use crate::tx::tx::{CommitmentInfo, CommitmentInfo2};
 use crate::wallet::Wallet;
 
@@ -30,21 +31,21 @@ fn null_validator() -> NullValidator {
     }
 }
 
-impl ValidatorFactory for NullValidatorFactory {
+impl ValidatorFactory<CoreTypes> for NullValidatorFactory {
     fn make_validator(
         &self,
         _network: Network,
         _node_id: PublicKey,
         _channel_id: Option<ChannelId>,
-    ) -> Arc<dyn Validator> {
+    ) -> Arc<dyn Validator<CoreTypes>> {
         Arc::new(null_validator())
     }
 }
 
 /// A null validator
-pub struct NullValidator(Arc<dyn Validator>); // So we can DRY by borrowing its decode methods ...
+pub struct NullValidator(Arc<dyn Validator<CoreTypes>>); // So we can DRY by borrowing its decode methods ...
 
-impl Validator for NullValidator {
+impl Validator<CoreTypes> for NullValidator {
     fn validate_ready_channel(
         &self,
         _wallet: &Wallet,
@@ -69,6 +70,54 @@ impl Validator for NullValidator {
         Ok(())
     }
 
+    fn validate_funding_tx(
+        &self,
+        _wallet: &Wallet,
+        _channels: &Vec<Option<Arc<Mutex<ChannelSlot>>>>,
+        _tx: &Transaction,
+        _inputs: &Vec<InteractiveInput>,
+        _opaths: &Vec<Vec<u32>>,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn validate_interactive_funding_input(
+        &self,
+        _wallet: &Wallet,
+        _input: &InteractiveInput,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn validate_interactive_funding_output(
+        &self,
+        _wallet: &Wallet,
+        _output: &InteractiveOutput,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn validate_interactive_funding_tx(
+        &self,
+        _chan: &Channel,
+        _tx: &Transaction,
+        _funding: &InteractiveFunding,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn validate_splice_tx(
+        &self,
+        _wallet: &Wallet,
+        _chan: &Channel,
+        _tx: &Transaction,
+        _vout: u32,
+        _inputs: &Vec<InteractiveInput>,
+        _outputs: &Vec<InteractiveOutput>,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
     fn decode_commitment_tx(
         &self,
         keys: &InMemorySigner,
@@ -175,6 +224,31 @@ impl Validator for NullValidator {
         Ok(())
     }
 
+    #[cfg(feature = "simple_close")]
+    fn validate_simple_close_tx(
+        &self,
+        _wallet: &Wallet,
+        _setup: &ChannelSetup,
+        _estate: &EnforcementState,
+        _holder_is_closer: bool,
+        _locktime: u32,
+        _to_holder_value_sat: u64,
+        _to_counterparty_value_sat: u64,
+        _holder_script: &Option<Script>,
+        _counterparty_script: &Option<Script>,
+        _holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn validate_close_plan(
+        &self,
+        _fee_sat: u64,
+        _non_wallet_sat: u64,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
     fn validate_delayed_sweep(
         &self,
         _wallet: &Wallet,
@@ -188,6 +262,19 @@ impl Validator for NullValidator {
         Ok(())
     }
 
+    fn validate_counterparty_payment_sweep(
+        &self,
+        _wallet: &Wallet,
+        _setup: &ChannelSetup,
+        _cstate: &ChainState,
+        _tx: &Transaction,
+        _input: usize,
+        _amount_sat: u64,
+        _wallet_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
     fn validate_counterparty_htlc_sweep(
         &self,
         _wallet: &Wallet,
@@ -224,6 +311,10 @@ impl Validator for NullValidator {
         Ok(())
     }
 
+    fn validate_clock_skew(&self, _clock_skew_secs: i64) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
     fn minimum_initial_balance(&self, _holder_value_msat: u64) -> u64 {
         0
     }
@@ -3,13 +3,15 @@ use bitcoin::{self, Network, Script, SigHash, SigHashType, Transaction};
 use lightning::chain::keysinterface::InMemorySigner;
 use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
 
-use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
+use crate::channel::{Channel, ChannelId, ChannelSetup, ChannelSlot};
+use crate::persist::model::PaymentLedgerEntry;
 use crate::policy::error::policy_error;
 use crate::policy::simple_validator::SimpleValidatorFactory;
 use crate::policy::validator::EnforcementState;
-use crate::policy::validator::{ChainState, Validator, ValidatorFactory};
+use crate::policy::validator::{ChainState, CoreTypes, Validator, ValidatorFactory};
 use crate::prelude::*;
 use crate::sync::Arc;
+use crate::tx::interactive::{InteractiveFunding, InteractiveInput, InteractiveOutput};
 use crate::tx::tx::{CommitmentInfo, CommitmentInfo2};
 use crate::wallet::Wallet;
 
@@ -29,13 +31,13 @@ impl OnchainValidatorFactory {
     }
 }
 
-impl ValidatorFactory for OnchainValidatorFactory {
+impl ValidatorFactory<CoreTypes> for OnchainValidatorFactory {
     fn make_validator(
         &self,
         network: Network,
         node_id: PublicKey,
         channel_id: Option<ChannelId>,
-    ) -> Arc<dyn Validator> {
+    ) -> Arc<dyn Validator<CoreTypes>> {
         let validator = OnchainValidator {
             inner: self.inner_factory.make_validator(network, node_id, channel_id),
             policy: make_onchain_policy(network),
@@ -46,7 +48,7 @@ impl ValidatorFactory for OnchainValidatorFactory {
 
 /// An on-chain validator, subsumes the policy checks of SimpleValidator
 pub struct OnchainValidator {
-    inner: Arc<dyn Validator>,
+    inner: Arc<dyn Validator<CoreTypes>>,
     policy: OnchainPolicy,
 }
 
@@ -59,7 +61,7 @@ fn make_onchain_policy(_network: Network) -> OnchainPolicy {
     OnchainPolicy { min_funding_depth: 6 }
 }
 
-impl Validator for OnchainValidator {
+impl Validator<CoreTypes> for OnchainValidator {
     fn validate_ready_channel(
         &self,
         wallet: &Wallet,
@@ -84,6 +86,54 @@ impl Validator for OnchainValidator {
         self.inner.validate_onchain_tx(wallet, channels, tx, values_sat, opaths)
     }
 
+    fn validate_funding_tx(
+        &self,
+        wallet: &Wallet,
+        channels: &Vec<Option<Arc<Mutex<ChannelSlot>>>>,
+        tx: &Transaction,
+        inputs: &Vec<InteractiveInput>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_funding_tx(wallet, channels, tx, inputs, opaths)
+    }
+
+    fn validate_interactive_funding_input(
+        &self,
+        wallet: &Wallet,
+        input: &InteractiveInput,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_interactive_funding_input(wallet, input)
+    }
+
+    fn validate_interactive_funding_output(
+        &self,
+        wallet: &Wallet,
+        output: &InteractiveOutput,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_interactive_funding_output(wallet, output)
+    }
+
+    fn validate_interactive_funding_tx(
+        &self,
+        chan: &Channel,
+        tx: &Transaction,
+        funding: &InteractiveFunding,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_interactive_funding_tx(chan, tx, funding)
+    }
+
+    fn validate_splice_tx(
+        &self,
+        wallet: &Wallet,
+        chan: &Channel,
+        tx: &Transaction,
+        vout: u32,
+        inputs: &Vec<InteractiveInput>,
+        outputs: &Vec<InteractiveOutput>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_splice_tx(wallet, chan, tx, vout, inputs, outputs)
+    }
+
     fn decode_commitment_tx(
         &self,
         keys: &InMemorySigner,
@@ -219,6 +269,42 @@ impl Validator for OnchainValidator {
         )
     }
 
+    #[cfg(feature = "simple_close")]
+    fn validate_simple_close_tx(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        state: &EnforcementState,
+        holder_is_closer: bool,
+        locktime: u32,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_simple_close_tx(
+            wallet,
+            setup,
+            state,
+            holder_is_closer,
+            locktime,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+            holder_wallet_path_hint,
+        )
+    }
+
+    fn validate_close_plan(
+        &self,
+        fee_sat: u64,
+        non_wallet_sat: u64,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_close_plan(fee_sat, non_wallet_sat)
+    }
+
     fn validate_delayed_sweep(
         &self,
         wallet: &Wallet,
@@ -232,6 +318,27 @@ impl Validator for OnchainValidator {
         self.inner.validate_delayed_sweep(wallet, setup, cstate, tx, input, amount_sat, wallet_path)
     }
 
+    fn validate_counterparty_payment_sweep(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        tx: &Transaction,
+        input: usize,
+        amount_sat: u64,
+        wallet_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_counterparty_payment_sweep(
+            wallet,
+            setup,
+            cstate,
+            tx,
+            input,
+            amount_sat,
+            wallet_path,
+        )
+    }
+
     fn validate_counterparty_htlc_sweep(
         &self,
         wallet: &Wallet,
@@ -277,9 +384,53 @@ impl Validator for OnchainValidator {
         self.inner.validate_payment_balance(incoming, outgoing, invoiced_amount)
     }
 
+    fn require_funding_registration(&self) -> bool {
+        self.inner.require_funding_registration()
+    }
+
+    fn verify_signatures(&self) -> bool {
+        self.inner.verify_signatures()
+    }
+
+    fn channel_review_due(&self, estate: &EnforcementState, cstate: &ChainState) -> Option<String> {
+        self.inner.channel_review_due(estate, cstate)
+    }
+
+    fn validate_payment_retry(
+        &self,
+        attempt_heights: &[u32],
+        cstate: &ChainState,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_payment_retry(attempt_heights, cstate)
+    }
+
+    fn validate_onchain_velocity(
+        &self,
+        spends: &[(u32, u64)],
+        value_sat: u64,
+        cstate: &ChainState,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_onchain_velocity(spends, value_sat, cstate)
+    }
+
+    fn validate_payment_duplicate(
+        &self,
+        entry: &PaymentLedgerEntry,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_payment_duplicate(entry)
+    }
+
+    fn validate_invoice(&self, amount_msat: u64, expiry_secs: u64) -> Result<(), ValidationError> {
+        self.inner.validate_invoice(amount_msat, expiry_secs)
+    }
+
     fn minimum_initial_balance(&self, holder_value_msat: u64) -> u64 {
         self.inner.minimum_initial_balance(holder_value_msat)
     }
+
+    fn validate_clock_skew(&self, clock_skew_secs: i64) -> Result<(), ValidationError> {
+        self.inner.validate_clock_skew(clock_skew_secs)
+    }
 }
 
 impl OnchainValidator {
@@ -1,24 +1,34 @@
+use core::cmp;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::{Hash, HashEngine};
 use bitcoin::policy::DUST_RELAY_TX_FEE;
 use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
 use bitcoin::util::bip143::SigHashCache;
-use bitcoin::{self, Network, Script, SigHash, SigHashType, Transaction};
+use bitcoin::{self, Network, Script, SigHash, SigHashType, Transaction, Txid};
 use lightning::chain::keysinterface::{BaseSign, InMemorySigner};
 use lightning::ln::chan_utils::{
     build_htlc_transaction, htlc_success_tx_weight, htlc_timeout_tx_weight,
     make_funding_redeemscript, ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys,
 };
 use lightning::ln::PaymentHash;
-use log::{debug, info};
+use log::{debug, info, warn};
 
-use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
+use crate::channel::{Channel, ChannelId, ChannelSetup, ChannelSlot};
+use crate::persist::model::{PaymentLedgerEntry, PaymentResolution};
 use crate::policy::validator::EnforcementState;
-use crate::policy::validator::{ChainState, Validator, ValidatorFactory};
+use crate::policy::validator::{ChainState, CoreTypes, Validator, ValidatorFactory};
 use crate::prelude::*;
 use crate::sync::Arc;
+use crate::tx::interactive::{InteractiveFunding, InteractiveInput, InteractiveOutput};
+#[cfg(feature = "simple_close")]
+use crate::tx::simple_close::build_simple_close_tx;
 use crate::tx::tx::{
     parse_offered_htlc_script, parse_received_htlc_script, parse_revokeable_redeemscript,
-    CommitmentInfo, CommitmentInfo2,
+    recompose_htlc_redeemscript, CommitmentInfo, CommitmentInfo2,
 };
 use crate::util::crypto_utils::payload_for_p2wsh;
 use crate::util::debug_utils::{
@@ -30,7 +40,48 @@ use crate::wallet::Wallet;
 
 extern crate scopeguard;
 
-use super::error::{policy_error, transaction_format_error, ValidationError};
+use super::error::{policy_error, transaction_format_error, PolicyTag, ValidationError};
+
+use vls_policy::rules::{RuleAction, RuleFacts};
+pub use vls_policy::simple::{make_simple_policy, EnforcementLevel, SimplePolicy};
+
+/// The approval token for signing a transaction that moves more than
+/// [SimplePolicy::max_unapproved_value_sat] to destinations outside our
+/// wallet - the HMAC-SHA256 of the txid with the policy's
+/// value_approval_key
+pub fn value_approval_token(key: &[u8; 32], txid: &Txid) -> [u8; 32] {
+    let mut engine = HmacEngine::<Sha256Hash>::new(key);
+    engine.input(&txid[..]);
+    Hmac::<Sha256Hash>::from_engine(engine).into_inner()
+}
+
+// The number of violations let through at the warn level, by tag, indexed
+// as in PolicyTag::ALL
+static POLICY_WARNINGS: [AtomicUsize; PolicyTag::ALL.len()] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+/// The number of policy violations let through at the
+/// [EnforcementLevel::Warn] level since startup, by tag
+pub fn policy_warnings() -> Vec<(PolicyTag, usize)> {
+    PolicyTag::ALL
+        .iter()
+        .zip(POLICY_WARNINGS.iter())
+        .map(|(tag, count)| (*tag, count.load(Ordering::Relaxed)))
+        .collect()
+}
 
 /// A factory for SimpleValidator
 pub struct SimpleValidatorFactory {
@@ -49,13 +100,13 @@ impl SimpleValidatorFactory {
     }
 }
 
-impl ValidatorFactory for SimpleValidatorFactory {
+impl ValidatorFactory<CoreTypes> for SimpleValidatorFactory {
     fn make_validator(
         &self,
         network: Network,
         node_id: PublicKey,
         channel_id: Option<ChannelId>,
-    ) -> Arc<dyn Validator> {
+    ) -> Arc<dyn Validator<CoreTypes>> {
         let validator = SimpleValidator {
             policy: self.policy.clone().unwrap_or_else(|| make_simple_policy(network)),
             node_id,
@@ -66,42 +117,6 @@ impl ValidatorFactory for SimpleValidatorFactory {
     }
 }
 
-/// A simple policy to configure a SimpleValidator
-#[derive(Clone)]
-pub struct SimplePolicy {
-    /// Minimum delay in blocks
-    pub min_delay: u16,
-    /// Maximum delay in blocks
-    pub max_delay: u16,
-    /// Maximum channel value in satoshi
-    pub max_channel_size_sat: u64,
-    /// amounts below this number of satoshi are not considered important
-    pub epsilon_sat: u64,
-    /// Maximum number of in-flight HTLCs
-    pub max_htlcs: usize,
-    /// Maximum value of in-flight HTLCs
-    pub max_htlc_value_sat: u64,
-    /// Whether to use knowledge of chain state (e.g. current_height)
-    pub use_chain_state: bool,
-    /// Minimum feerate
-    pub min_feerate_per_kw: u32,
-    /// Maximum feerate
-    pub max_feerate_per_kw: u32,
-    /// Minimum fee in satoshi
-    pub min_fee: u64,
-    /// Maximum fee in satoshi
-    pub max_fee: u64,
-    /// Require invoices for payments, and disallow keysend
-    // TODO secure keysend
-    pub require_invoices: bool,
-    /// Enforce holder balance
-    // TODO incoming payments
-    // TODO routing
-    pub enforce_balance: bool,
-    /// Maximum layer-2 fee
-    pub max_routing_fee_msat: u64,
-}
-
 /// A simple validator.
 /// See [`SimpleValidatorFactory`] for construction
 pub struct SimpleValidator {
@@ -114,6 +129,31 @@ impl SimpleValidator {
     const ANCHOR_SEQS: [u32; 1] = [0x_0000_0001];
     const NON_ANCHOR_SEQS: [u32; 3] = [0x_0000_0000_u32, 0x_ffff_fffd_u32, 0x_ffff_ffff_u32];
 
+    // The channel ID reported with a policy violation
+    fn channel_hex(&self) -> Option<String> {
+        self.channel_id.as_ref().map(|c| c.to_string())
+    }
+
+    // Apply the enforcement level of the violated rule.  At the warn level
+    // the violation is logged and counted, and the operation proceeds.
+    fn enforce(&self, res: Result<(), ValidationError>) -> Result<(), ValidationError> {
+        let err = match res {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+        let tag = err.policy.as_ref().map(|p| p.tag).unwrap_or(PolicyTag::Unclassified);
+        match self.policy.enforcement_level(tag) {
+            EnforcementLevel::Enforce => Err(err),
+            EnforcementLevel::Warn => {
+                let index = PolicyTag::ALL.iter().position(|t| *t == tag).expect("tag");
+                POLICY_WARNINGS[index].fetch_add(1, Ordering::Relaxed);
+                warn!("{} policy violation not enforced: {}", self.log_prefix(), err);
+                Ok(())
+            }
+            EnforcementLevel::Off => Ok(()),
+        }
+    }
+
     fn log_prefix(&self) -> String {
         let short_node_id = &self.node_id.to_hex()[0..4];
         let short_channel_id =
@@ -121,14 +161,35 @@ impl SimpleValidator {
         format!("{}/{}", short_node_id, short_channel_id)
     }
 
-    fn validate_delay(&self, name: &str, delay: u32) -> Result<(), ValidationError> {
+    fn validate_delay(
+        &self,
+        name: &str,
+        delay: u32,
+        max_delay: u16,
+    ) -> Result<(), ValidationError> {
         let policy = &self.policy;
 
         if delay < policy.min_delay as u32 {
-            return policy_err!("{} too small: {} < {}", name, delay, policy.min_delay);
+            self.enforce(tagged_policy_err!(
+                PolicyTag::DelayRange,
+                self.channel_hex(),
+                [(name, delay), ("min_delay", policy.min_delay)],
+                "{} too small: {} < {}",
+                name,
+                delay,
+                policy.min_delay
+            ))?;
         }
-        if delay > policy.max_delay as u32 {
-            return policy_err!("{} too large: {} > {}", name, delay, policy.max_delay);
+        if delay > max_delay as u32 {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::DelayRange,
+                self.channel_hex(),
+                [(name, delay), ("max_delay", max_delay)],
+                "{} too large: {} > {}",
+                name,
+                delay,
+                max_delay
+            ))?;
         }
 
         Ok(())
@@ -144,35 +205,128 @@ impl SimpleValidator {
 
         if policy.use_chain_state {
             if expiry < current_height + policy.min_delay as u32 {
-                return policy_err!(
+                self.enforce(tagged_policy_err!(
+                    PolicyTag::DelayRange,
+                    self.channel_hex(),
+                    [(name, expiry), ("current_height", current_height)],
                     "{} expiry too early: {} < {}",
                     name,
                     expiry,
                     current_height + policy.min_delay as u32
-                );
+                ))?;
             }
             if expiry > current_height + policy.max_delay as u32 {
-                return policy_err!(
+                self.enforce(tagged_policy_err!(
+                    PolicyTag::DelayRange,
+                    self.channel_hex(),
+                    [(name, expiry), ("current_height", current_height)],
                     "{} expiry too late: {} > {}",
                     name,
                     expiry,
                     current_height + policy.max_delay as u32
-                );
+                ))?;
             }
         }
 
         Ok(())
     }
 
+    // policy-onchain-value-approval
+    fn validate_value_approval(
+        &self,
+        wallet: &Wallet,
+        txid: &Txid,
+        non_wallet_sat: u64,
+    ) -> Result<(), ValidationError> {
+        let key = match &self.policy.value_approval_key {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        if non_wallet_sat <= self.policy.max_unapproved_value_sat {
+            return Ok(());
+        }
+        if !wallet.has_value_approval(&value_approval_token(key, txid)) {
+            return self.enforce(tagged_policy_err!(
+                PolicyTag::ApprovalRequired,
+                self.channel_hex(),
+                [
+                    ("non_wallet_sat", non_wallet_sat),
+                    ("max_unapproved_value_sat", self.policy.max_unapproved_value_sat),
+                    ("txid", txid),
+                ],
+                "value to non-wallet destinations requires approval: {} > {}",
+                non_wallet_sat,
+                self.policy.max_unapproved_value_sat
+            ));
+        }
+        info!("{} approved value {} for {}", self.log_prefix(), non_wallet_sat, txid);
+        Ok(())
+    }
+
+    // policy-custom-rules
+    // A require_approval rule is satisfied by the approval token of the
+    // transaction, if it is known.
+    fn validate_rules(
+        &self,
+        wallet: &Wallet,
+        txid: Option<&Txid>,
+        facts: &RuleFacts,
+    ) -> Result<(), ValidationError> {
+        for rule in self.policy.rules.iter().filter(|r| r.matches(facts)) {
+            match rule.action {
+                RuleAction::Warn => warn!("{} custom rule matched: {}", self.log_prefix(), rule),
+                RuleAction::Deny => self.enforce(tagged_policy_err!(
+                    PolicyTag::Unclassified,
+                    self.channel_hex(),
+                    [("rule", rule)],
+                    "denied by custom rule: {}",
+                    rule
+                ))?,
+                RuleAction::RequireApproval => {
+                    let approved = match (&self.policy.value_approval_key, txid) {
+                        (Some(key), Some(txid)) => {
+                            wallet.has_value_approval(&value_approval_token(key, txid))
+                        }
+                        _ => false,
+                    };
+                    if !approved {
+                        self.enforce(tagged_policy_err!(
+                            PolicyTag::ApprovalRequired,
+                            self.channel_hex(),
+                            [("rule", rule)],
+                            "custom rule requires approval: {}",
+                            rule
+                        ))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn validate_fee(&self, sum_inputs: u64, sum_outputs: u64) -> Result<(), ValidationError> {
         let fee = sum_inputs.checked_sub(sum_outputs).ok_or_else(|| {
             policy_error(format!("fee underflow: {} - {}", sum_inputs, sum_outputs))
         })?;
         if fee < self.policy.min_fee {
-            return policy_err!("fee below minimum: {} < {}", fee, self.policy.min_fee);
+            self.enforce(tagged_policy_err!(
+                PolicyTag::FeeRange,
+                self.channel_hex(),
+                [("fee", fee), ("min_fee", self.policy.min_fee)],
+                "fee below minimum: {} < {}",
+                fee,
+                self.policy.min_fee
+            ))?;
         }
         if fee > self.policy.max_fee {
-            return policy_err!("fee above maximum: {} > {}", fee, self.policy.max_fee);
+            self.enforce(tagged_policy_err!(
+                PolicyTag::FeeRange,
+                self.channel_hex(),
+                [("fee", fee), ("max_fee", self.policy.max_fee)],
+                "fee above maximum: {} > {}",
+                fee,
+                self.policy.max_fee
+            ))?;
         }
         Ok(())
     }
@@ -189,11 +343,82 @@ impl SimpleValidator {
             ))
         })?;
         if non_beneficial > self.policy.max_fee {
-            return policy_err!(
+            self.enforce(tagged_policy_err!(
+                PolicyTag::FeeRange,
+                self.channel_hex(),
+                [("non_beneficial", non_beneficial), ("max_fee", self.policy.max_fee)],
                 "non-beneficial value above maximum: {} > {}",
                 non_beneficial,
                 self.policy.max_fee
-            );
+            ))?;
+        }
+        Ok(())
+    }
+
+    // Enforce the clock check on operations that depend on the chain state
+    // for their time-delays.
+    fn validate_chain_clock(&self, cstate: &ChainState) -> Result<(), ValidationError> {
+        if self.policy.enforce_clock_skew {
+            // policy-chain-clock-skew
+            let skew = cstate
+                .clock_skew_secs
+                .ok_or_else(|| policy_error("clock skew unknown or stale, heartbeat needed"))?;
+            self.validate_clock_skew(skew)?;
+        }
+        Ok(())
+    }
+
+    // Limit what we stand to lose if the funding of a zero-conf channel is
+    // double-spent - the value we received on top of our initial balance.
+    fn validate_zero_conf(
+        &self,
+        estate: &EnforcementState,
+        commit_num: u64,
+        cstate: &ChainState,
+        info: &CommitmentInfo2,
+    ) -> Result<(), ValidationError> {
+        let policy = &self.policy;
+
+        // policy-funding-watched
+        if policy.require_funding_watch && !cstate.funding_watched {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::ChainState,
+                self.channel_hex(),
+                [],
+                "funding outpoint is not watched"
+            ))?;
+        }
+
+        // policy-zero-conf-exposure
+        if policy.use_chain_state
+            && commit_num > 0
+            && cstate.funding_depth < policy.min_funding_depth as u32
+        {
+            let (holder_value_sat, _) = info.value_to_parties();
+            let incoming_htlcs = if info.is_counterparty_broadcaster {
+                &info.offered_htlcs
+            } else {
+                &info.received_htlcs
+            };
+            let exposure = incoming_htlcs
+                .iter()
+                .fold(holder_value_sat, |sum, htlc| sum.saturating_add(htlc.value_sat))
+                .saturating_sub(estate.initial_holder_value);
+            if exposure > policy.max_zero_conf_exposure_sat {
+                self.enforce(tagged_policy_err!(
+                    PolicyTag::ChainState,
+                    self.channel_hex(),
+                    [
+                        ("funding_depth", cstate.funding_depth),
+                        ("exposure", exposure),
+                        ("max_zero_conf_exposure_sat", policy.max_zero_conf_exposure_sat),
+                    ],
+                    "zero-conf exposure above maximum at funding depth {}: {} > {}",
+                    cstate.funding_depth,
+                    exposure,
+                    policy.max_zero_conf_exposure_sat
+                ))?;
+            }
         }
         Ok(())
     }
@@ -212,7 +437,7 @@ impl SimpleValidator {
         wallet: &Wallet,
         tx: &Transaction,
         _input: usize,
-        _amount_sat: u64,
+        amount_sat: u64,
         wallet_path: &Vec<u32>,
     ) -> Result<(), ValidationError> {
         // policy-sweep-version
@@ -240,7 +465,31 @@ impl SimpleValidator {
                     wallet_path,
                     script_debug(dest_script, wallet.network())
                 );
-                return policy_err!("destination is not in wallet or allowlist");
+                self.enforce(tagged_policy_err!(
+                    PolicyTag::Destination,
+                    self.channel_hex(),
+                    [("script_pubkey", dest_script)],
+                    "destination is not in wallet or allowlist"
+                ))?;
+            }
+        }
+
+        // policy-sweep-destination-cold-storage
+        if let Some(threshold_sat) = wallet.cold_storage_threshold() {
+            if amount_sat > threshold_sat {
+                for out in tx.output.iter() {
+                    if !wallet.cold_storage_contains(&out.script_pubkey) {
+                        self.enforce(tagged_policy_err!(
+                            PolicyTag::Destination,
+                            self.channel_hex(),
+                            [("script_pubkey", &out.script_pubkey), ("amount_sat", amount_sat)],
+                            "sweep of {} sat is above the cold storage threshold {} \
+                             but is not to cold storage",
+                            amount_sat,
+                            threshold_sat
+                        ))?;
+                    }
+                }
             }
         }
 
@@ -278,7 +527,7 @@ impl SimpleValidator {
 // TODO - policy-velocity-transferred
 // TODO - policy-merchant-no-sends
 
-impl Validator for SimpleValidator {
+impl Validator<CoreTypes> for SimpleValidator {
     fn validate_ready_channel(
         &self,
         wallet: &Wallet,
@@ -287,13 +536,17 @@ impl Validator for SimpleValidator {
     ) -> Result<(), ValidationError> {
         let mut debug_on_return = scoped_debug_return!(setup, holder_shutdown_key_path);
 
-        // NOTE - setup.channel_value_sat is not valid, set later on.
+        // Taproot commitment transactions are not validated yet
+        if setup.option_taproot() {
+            return policy_err!("taproot channels are not supported yet");
+        }
 
         // policy-channel-counterparty-contest-delay-range
         // policy-commitment-to-self-delay-range relies on this value
         self.validate_delay(
             "counterparty_selected_contest_delay",
             setup.counterparty_selected_contest_delay as u32,
+            cmp::min(self.policy.max_delay, self.policy.max_counterparty_contest_delay),
         )?;
 
         // policy-channel-holder-contest-delay-range
@@ -301,8 +554,31 @@ impl Validator for SimpleValidator {
         self.validate_delay(
             "holder_selected_contest_delay",
             setup.holder_selected_contest_delay as u32,
+            self.policy.max_delay,
         )?;
 
+        // policy-channel-push-value-range
+        if setup.is_outbound {
+            if setup.push_value_msat > self.policy.max_push_value_msat {
+                return policy_err!(
+                    "push_value_msat too large: {} > {}",
+                    setup.push_value_msat,
+                    self.policy.max_push_value_msat
+                );
+            }
+            // msat * percentage / 100
+            let max_push_msat =
+                setup.channel_value_sat * 10 * self.policy.max_push_percentage as u64;
+            if setup.push_value_msat > max_push_msat {
+                return policy_err!(
+                    "push_value_msat {} too large for channel value {}: more than {}%",
+                    setup.push_value_msat,
+                    setup.channel_value_sat,
+                    self.policy.max_push_percentage
+                );
+            }
+        }
+
         // policy-mutual-destination-allowlisted
         if let Some(holder_shutdown_script) = &setup.holder_shutdown_script {
             if !wallet
@@ -323,6 +599,7 @@ impl Validator for SimpleValidator {
     }
 
     fn validate_channel_value(&self, setup: &ChannelSetup) -> Result<(), ValidationError> {
+        // policy-channel-value-range
         if setup.channel_value_sat > self.policy.max_channel_size_sat {
             return policy_err!("channel value {} too large", setup.channel_value_sat);
         }
@@ -345,6 +622,7 @@ impl Validator for SimpleValidator {
         }
 
         let mut beneficial_sum = 0u64;
+        let mut facts = RuleFacts { onchain_tx: true, ..Default::default() };
         for outndx in 0..tx.output.len() {
             let output = &tx.output[outndx];
             let opath = &opaths[outndx];
@@ -431,13 +709,19 @@ impl Validator for SimpleValidator {
                             // push_val_sat
                         };
                         debug!("output {} ({}) funds channel {}", outndx, output.value, chan.id());
+                        facts.channel_value_sat =
+                            facts.channel_value_sat.saturating_add(output.value);
                         beneficial_sum =
                             add_beneficial_output!(beneficial_sum, our_value, "channel value")?;
                     }
+                    ChannelSlot::Closing(chan) | ChannelSlot::Closed(chan) => {
+                        return policy_err!("funding output for closing channel {}", chan.id());
+                    }
                     _ => panic!("this can't happen"),
                 };
             } else {
                 debug!("output {} ({}) is unknown", outndx, output.value);
+                facts.dest_not_allowlisted = true;
             }
         }
 
@@ -452,6 +736,350 @@ impl Validator for SimpleValidator {
         self.validate_beneficial_value(sum_inputs, beneficial_sum)
             .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
 
+        // Outputs with a path were checked to be spendable by our wallet above
+        let non_wallet_sum = tx
+            .output
+            .iter()
+            .zip(opaths)
+            .filter(|(_, opath)| opath.is_empty())
+            .fold(0u64, |sum, (output, _)| sum.saturating_add(output.value));
+        self.validate_value_approval(wallet, &tx.txid(), non_wallet_sum)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
+        let sum_outputs = tx.output.iter().fold(0u64, |sum, o| sum.saturating_add(o.value));
+        facts.value_sat = non_wallet_sum;
+        facts.fee_sat = sum_inputs.saturating_sub(sum_outputs);
+        self.validate_rules(wallet, Some(&tx.txid()), &facts)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
+        *debug_on_return = false;
+        Ok(())
+    }
+
+    fn validate_funding_tx(
+        &self,
+        wallet: &Wallet,
+        channels: &Vec<Option<Arc<Mutex<ChannelSlot>>>>,
+        tx: &Transaction,
+        inputs: &Vec<InteractiveInput>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<(), ValidationError> {
+        let mut debug_on_return = scoped_debug_return!(tx, inputs, opaths);
+
+        // policy-funding-inputs-wallet
+        if inputs.len() != tx.input.len() {
+            return policy_err!(
+                "only {} of the {} inputs are from our wallet",
+                inputs.len(),
+                tx.input.len()
+            );
+        }
+        for (txin, input) in tx.input.iter().zip(inputs) {
+            if txin.previous_output != input.outpoint {
+                return policy_err!(
+                    "input {} is not our wallet input {}",
+                    txin.previous_output,
+                    input.outpoint
+                );
+            }
+            self.validate_interactive_funding_input(wallet, input)?;
+        }
+
+        // policy-funding-outputs-channel-or-change
+        let mut funded = 0;
+        for (outndx, output) in tx.output.iter().enumerate() {
+            if channels[outndx].is_some() {
+                funded += 1;
+            } else if opaths[outndx].is_empty() {
+                self.enforce(tagged_policy_err!(
+                    PolicyTag::Destination,
+                    self.channel_hex(),
+                    [("output", outndx), ("value_sat", output.value)],
+                    "output[{}] neither funds a channel nor returns change to our wallet",
+                    outndx
+                ))?;
+            }
+        }
+        if funded == 0 {
+            return policy_err!("transaction does not fund a channel");
+        }
+
+        // policy-onchain-fee-range
+        let sum_inputs = inputs
+            .iter()
+            .try_fold(0u64, |sum, input| sum.checked_add(input.prev_output.value))
+            .ok_or_else(|| policy_error(format!("funding sum inputs overflow")))?;
+        let sum_outputs = tx
+            .output
+            .iter()
+            .try_fold(0u64, |sum, output| sum.checked_add(output.value))
+            .ok_or_else(|| policy_error(format!("funding sum outputs overflow")))?;
+        self.validate_fee(sum_inputs, sum_outputs)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
+        *debug_on_return = false;
+        Ok(())
+    }
+
+    fn validate_interactive_funding_input(
+        &self,
+        wallet: &Wallet,
+        input: &InteractiveInput,
+    ) -> Result<(), ValidationError> {
+        // policy-interactive-input-wallet
+        let spendable =
+            wallet.can_spend(&input.ipath, &input.prev_output.script_pubkey).map_err(|err| {
+                policy_error(format!("input {}: wallet_can_spend error: {}", input.outpoint, err))
+            })?;
+        if !spendable {
+            return policy_err!("wallet cannot spend input {}", input.outpoint);
+        }
+        Ok(())
+    }
+
+    fn validate_interactive_funding_output(
+        &self,
+        wallet: &Wallet,
+        output: &InteractiveOutput,
+    ) -> Result<(), ValidationError> {
+        let script_pubkey = &output.output.script_pubkey;
+        // policy-interactive-output-wallet
+        if !output.opath.is_empty() {
+            let spendable = wallet.can_spend(&output.opath, script_pubkey).map_err(|err| {
+                policy_error(format!("output {}: wallet_can_spend error: {}", script_pubkey, err))
+            })?;
+            if !spendable {
+                return policy_err!("wallet cannot spend output {}", script_pubkey);
+            }
+        } else if !wallet.allowlist_contains(script_pubkey) {
+            return policy_err!("output {} is not to our wallet or allowlisted", script_pubkey);
+        }
+        Ok(())
+    }
+
+    fn validate_interactive_funding_tx(
+        &self,
+        chan: &Channel,
+        tx: &Transaction,
+        funding: &InteractiveFunding,
+    ) -> Result<(), ValidationError> {
+        let mut debug_on_return = scoped_debug_return!(tx, funding);
+        let setup = &chan.setup;
+
+        // policy-onchain-format-standard
+        if tx.version != 2 {
+            return policy_err!("invalid version: {}", tx.version);
+        }
+
+        // policy-onchain-output-match-commitment
+        let vout = setup.funding_outpoint.vout as usize;
+        if tx.txid() != setup.funding_outpoint.txid || vout >= tx.output.len() {
+            return policy_err!("transaction does not fund channel {}", chan.id());
+        }
+        let output = &tx.output[vout];
+        if output.value != setup.channel_value_sat {
+            return policy_err!(
+                "funding output amount mismatch w/ channel: {} != {}",
+                output.value,
+                setup.channel_value_sat
+            );
+        }
+
+        // policy-onchain-output-scriptpubkey
+        let funding_redeemscript = make_funding_redeemscript(
+            &chan.keys.pubkeys().funding_pubkey,
+            &chan.keys.counterparty_pubkeys().funding_pubkey,
+        );
+        let script_pubkey = payload_for_p2wsh(&funding_redeemscript).script_pubkey();
+        if output.script_pubkey != script_pubkey {
+            return policy_err!(
+                "funding script_pubkey mismatch w/ channel: {} != {}",
+                output.script_pubkey,
+                script_pubkey
+            );
+        }
+
+        // policy-onchain-initial-commitment-countersigned
+        let holder_info = match &chan.enforcement_state.current_holder_commit_info {
+            Some(info) if chan.enforcement_state.next_holder_commit_num == 1 => info,
+            _ => return policy_err!("initial holder commitment not validated"),
+        };
+
+        // policy-interactive-contribution-credited
+        // The opener pays the commitment fee out of its contribution
+        if funding.holder_contribution_sat > setup.channel_value_sat {
+            return policy_err!(
+                "holder contribution exceeds channel value: {} > {}",
+                funding.holder_contribution_sat,
+                setup.channel_value_sat
+            );
+        }
+        let holder_value = holder_info.to_broadcaster_value_sat;
+        let shortfall = funding.holder_contribution_sat.saturating_sub(holder_value);
+        if shortfall > 0 && (!setup.is_outbound || shortfall > self.policy.max_fee) {
+            return policy_err!(
+                "initial commitment does not credit holder contribution: {} < {}",
+                holder_value,
+                funding.holder_contribution_sat
+            );
+        }
+
+        // policy-interactive-inputs-accounted
+        for (input, ndx) in funding.inputs.iter().zip(funding.input_indices(tx)) {
+            if ndx.is_none() {
+                return policy_err!("input {} missing from transaction", input.outpoint);
+            }
+        }
+        for (output, ndx) in funding.outputs.iter().zip(funding.output_indices(tx)) {
+            if ndx.is_none() {
+                return policy_err!(
+                    "output {} missing from transaction",
+                    output.output.script_pubkey
+                );
+            }
+        }
+        let sum_inputs =
+            funding.inputs_sat().ok_or_else(|| policy_error("sum of our inputs overflow"))?;
+        let beneficial_sum = funding
+            .outputs
+            .iter()
+            .try_fold(funding.holder_contribution_sat, |sum, o| sum.checked_add(o.output.value))
+            .ok_or_else(|| policy_error("beneficial outputs overflow"))?;
+
+        // policy-onchain-beneficial-value
+        // policy-onchain-fee-range
+        self.validate_beneficial_value(sum_inputs, beneficial_sum)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
+        *debug_on_return = false;
+        Ok(())
+    }
+
+    fn validate_splice_tx(
+        &self,
+        wallet: &Wallet,
+        chan: &Channel,
+        tx: &Transaction,
+        vout: u32,
+        inputs: &Vec<InteractiveInput>,
+        outputs: &Vec<InteractiveOutput>,
+    ) -> Result<(), ValidationError> {
+        let mut debug_on_return = scoped_debug_return!(tx, vout, inputs, outputs);
+        let setup = &chan.setup;
+        let estate = &chan.enforcement_state;
+
+        // policy-onchain-format-standard
+        if tx.version != 2 {
+            return policy_err!("invalid version: {}", tx.version);
+        }
+
+        // policy-splice-single
+        if estate.splice.is_some() {
+            return policy_err!("splice already in progress");
+        }
+
+        // policy-splice-quiescent
+        // Both sides must have revoked all but the current commitment, and
+        // the current commitments must not have HTLCs
+        let holder_info = estate.current_holder_commit_info.as_ref();
+        let cp_info = estate.current_counterparty_commit_info.as_ref();
+        let (holder_info, cp_info) = match (holder_info, cp_info) {
+            (Some(holder_info), Some(cp_info))
+                if estate.next_holder_revoke_num + 1 == estate.next_holder_commit_num
+                    && estate.next_counterparty_revoke_num + 1
+                        == estate.next_counterparty_commit_num =>
+            {
+                (holder_info, cp_info)
+            }
+            _ => return policy_err!("channel is not quiescent"),
+        };
+        if !holder_info.htlcs_is_empty() || !cp_info.htlcs_is_empty() {
+            return policy_err!("channel has pending HTLCs");
+        }
+
+        // policy-splice-inputs
+        let funding = InteractiveFunding {
+            holder_contribution_sat: 0,
+            inputs: inputs.clone(),
+            outputs: outputs.clone(),
+        };
+        let input_indices = funding.input_indices(tx);
+        let mut funding_spent = false;
+        for (ndx, txin) in tx.input.iter().enumerate() {
+            if txin.previous_output == setup.funding_outpoint {
+                funding_spent = true;
+            } else if !input_indices.contains(&Some(ndx)) {
+                return policy_err!("unknown input {}", txin.previous_output);
+            }
+        }
+        if !funding_spent {
+            return policy_err!("transaction does not spend the funding of channel {}", chan.id());
+        }
+        for (input, ndx) in inputs.iter().zip(input_indices) {
+            if ndx.is_none() {
+                return policy_err!("input {} missing from transaction", input.outpoint);
+            }
+            self.validate_interactive_funding_input(wallet, input)
+                .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+        }
+
+        // policy-onchain-output-scriptpubkey
+        let vout = vout as usize;
+        if vout >= tx.output.len() {
+            return policy_err!("funding output index out of range: {}", vout);
+        }
+        let funding_redeemscript = make_funding_redeemscript(
+            &chan.keys.pubkeys().funding_pubkey,
+            &chan.keys.counterparty_pubkeys().funding_pubkey,
+        );
+        let script_pubkey = payload_for_p2wsh(&funding_redeemscript).script_pubkey();
+        if tx.output[vout].script_pubkey != script_pubkey {
+            return policy_err!(
+                "funding script_pubkey mismatch w/ channel: {} != {}",
+                tx.output[vout].script_pubkey,
+                script_pubkey
+            );
+        }
+
+        // policy-splice-outputs
+        for (output, ndx) in outputs.iter().zip(funding.output_indices(tx)) {
+            if ndx.is_none() || ndx == Some(vout) {
+                return policy_err!(
+                    "output {} missing from transaction",
+                    output.output.script_pubkey
+                );
+            }
+            self.validate_interactive_funding_output(wallet, output)
+                .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+        }
+
+        // policy-splice-value-conserved
+        let old_value = setup.channel_value_sat;
+        let new_value = tx.output[vout].value;
+        let sum_inputs = funding
+            .inputs_sat()
+            .and_then(|sum| sum.checked_add(old_value))
+            .ok_or_else(|| policy_error("sum of our inputs overflow"))?;
+        let sum_outputs = outputs
+            .iter()
+            .try_fold(new_value, |sum, o| sum.checked_add(o.output.value))
+            .ok_or_else(|| policy_error("beneficial outputs overflow"))?;
+        self.validate_beneficial_value(sum_inputs, sum_outputs)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
+        // policy-splice-out-holder-balance
+        // Value spliced out of the channel comes out of our balance
+        let spliced_out = old_value.saturating_sub(new_value);
+        let holder_balance =
+            cmp::min(holder_info.to_broadcaster_value_sat, cp_info.to_countersigner_value_sat);
+        if spliced_out > holder_balance {
+            return policy_err!(
+                "splice-out exceeds holder balance: {} > {}",
+                spliced_out,
+                holder_balance
+            );
+        }
+
         *debug_on_return = false;
         Ok(())
     }
@@ -528,6 +1156,48 @@ impl Validator for SimpleValidator {
             return Err(policy_error("holder_selected_contest_delay mismatch".to_string()));
         }
 
+        // policy-commitment-funding-depth
+        if self.policy.enforce_funding_depth
+            && commit_num > 0
+            && cstate.funding_depth < self.policy.min_funding_depth as u32
+        {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::ChainState,
+                self.channel_hex(),
+                [
+                    ("commit_num", commit_num),
+                    ("funding_depth", cstate.funding_depth),
+                    ("min_funding_depth", self.policy.min_funding_depth),
+                ],
+                "funding depth too low for commit_num {}: {} < {}",
+                commit_num,
+                cstate.funding_depth,
+                self.policy.min_funding_depth
+            ))?;
+        }
+
+        // policy-commitment-channel-review
+        if self.policy.block_htlcs_pending_review {
+            if let Some(reason) = self.channel_review_due(estate, cstate) {
+                let adds_htlcs = match &estate.current_counterparty_commit_info {
+                    Some(current) => {
+                        current.delta_offered_htlcs(info2).0.next().is_some()
+                            || current.delta_received_htlcs(info2).0.next().is_some()
+                    }
+                    None => !info2.offered_htlcs.is_empty() || !info2.received_htlcs.is_empty(),
+                };
+                if adds_htlcs {
+                    self.enforce(tagged_policy_err!(
+                        PolicyTag::ChannelReview,
+                        self.channel_hex(),
+                        [("commit_num", commit_num), ("current_height", cstate.current_height)],
+                        "cannot add HTLCs to a channel pending review: {}",
+                        reason
+                    ))?;
+                }
+            }
+        }
+
         // policy-commitment-previous-revoked
         // if next_counterparty_revoke_num is 20:
         // - commit_num 19 has been revoked
@@ -537,7 +1207,13 @@ impl Validator for SimpleValidator {
         // This check overlaps the check in set_next_counterparty_commit_num
         // but gives better diagnostic.
         if commit_num > estate.next_counterparty_revoke_num + 1 {
-            return policy_err!(
+            return tagged_policy_err!(
+                PolicyTag::RevocationOrder,
+                self.channel_hex(),
+                [
+                    ("commit_num", commit_num),
+                    ("next_counterparty_revoke_num", estate.next_counterparty_revoke_num),
+                ],
                 "invalid attempt to sign counterparty commit_num {} \
                          with next_counterparty_revoke_num {}",
                 commit_num,
@@ -632,7 +1308,13 @@ impl Validator for SimpleValidator {
         // better diagnostic.
         if commit_num + 2 <= estate.next_holder_commit_num {
             debug_failed_vals!(estate, commit_num);
-            return policy_err!(
+            return tagged_policy_err!(
+                PolicyTag::RevocationOrder,
+                self.channel_hex(),
+                [
+                    ("commit_num", commit_num),
+                    ("next_holder_commit_num", estate.next_holder_commit_num)
+                ],
                 "can't sign revoked commitment_number {}, \
                  next_holder_commit_num is {}",
                 commit_num,
@@ -665,14 +1347,20 @@ impl Validator for SimpleValidator {
             && revoke_num + 1 != state.next_counterparty_revoke_num
         {
             debug_failed_vals!(state, revoke_num, commitment_secret);
-            return policy_err!(
+            return tagged_policy_err!(
+                PolicyTag::RevocationOrder,
+                self.channel_hex(),
+                [
+                    ("revoke_num", revoke_num),
+                    ("next_counterparty_revoke_num", state.next_counterparty_revoke_num),
+                ],
                 "invalid counterparty revoke_num {} with next_counterparty_revoke_num {}",
                 revoke_num,
                 state.next_counterparty_revoke_num
             );
         }
 
-        // policy-commitment-previous-revoked (partial: secret validated, but not stored here)
+        // policy-commitment-previous-revoked (secret stored by the caller)
         let supplied_commit_point = PublicKey::from_secret_key(&secp_ctx, &commitment_secret);
         let prev_commit_point = state.get_previous_counterparty_point(revoke_num)?;
         if supplied_commit_point != prev_commit_point {
@@ -710,8 +1398,21 @@ impl Validator for SimpleValidator {
         } else {
             SigHashType::All
         };
-        let original_tx_sighash =
-            SigHashCache::new(tx).signature_hash(0, &redeemscript, htlc_amount_sat, sighash_type);
+        // With anchors, the counterparty signs our HTLC transactions with
+        // SIGHASH_SINGLE|SIGHASH_ANYONECANPAY, so we may add inputs and
+        // outputs to bump the fee.  Only the HTLC input and output are
+        // compared against the recomposed transaction in that case.
+        let compare_sighash_type = if setup.option_anchor_outputs() {
+            SigHashType::SinglePlusAnyoneCanPay
+        } else {
+            SigHashType::All
+        };
+        let original_tx_sighash = SigHashCache::new(tx).signature_hash(
+            0,
+            &redeemscript,
+            htlc_amount_sat,
+            compare_sighash_type,
+        );
 
         let offered = if parse_offered_htlc_script(redeemscript, setup.option_anchor_outputs())
             .is_ok()
@@ -771,7 +1472,7 @@ impl Validator for SimpleValidator {
             0,
             &redeemscript,
             htlc_amount_sat,
-            sighash_type,
+            compare_sighash_type,
         );
 
         if recomposed_tx_sighash != original_tx_sighash {
@@ -823,13 +1524,39 @@ impl Validator for SimpleValidator {
         // - policy-htlc-revocation-pubkey
         // - policy-htlc-delayed-pubkey
 
-        Ok((feerate_per_kw, htlc, recomposed_tx_sighash, sighash_type))
+        // policy-htlc-redeemscript
+        // The redeemscript must be the one reconstructed from the commitment
+        // keys, so that we only sign for our own HTLC output
+        let expected_redeemscript =
+            recompose_htlc_redeemscript(redeemscript, setup.option_anchor_outputs(), txkeys);
+        if expected_redeemscript.as_ref() != Some(redeemscript) {
+            debug_failed_vals!(
+                is_counterparty,
+                setup,
+                DebugTxCreationKeys(txkeys),
+                tx,
+                redeemscript,
+                htlc_amount_sat,
+                output_witscript
+            );
+            return Err(policy_error("redeemscript mismatch".to_string()));
+        }
+
+        // Our signature on our own HTLC transaction also covers any fee
+        // bumping inputs and outputs
+        let sighash = if sighash_type == compare_sighash_type {
+            recomposed_tx_sighash
+        } else {
+            SigHashCache::new(tx).signature_hash(0, &redeemscript, htlc_amount_sat, sighash_type)
+        };
+
+        Ok((feerate_per_kw, htlc, sighash, sighash_type))
     }
 
     fn validate_htlc_tx(
         &self,
-        _setup: &ChannelSetup,
-        _cstate: &ChainState,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
         _is_counterparty: bool,
         htlc: &HTLCOutputInCommitment,
         feerate_per_kw: u32,
@@ -837,7 +1564,10 @@ impl Validator for SimpleValidator {
         let mut debug_on_return =
             scoped_debug_return!(DebugHTLCOutputInCommitment(htlc), feerate_per_kw);
 
-        // This must be further checked with policy-htlc-cltv-range.
+        self.validate_chain_clock(cstate)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
+        // This must be further checked with policy-htlc-cltv-range.
         // Note that we can't check cltv_expiry for non-offered 2nd level
         // HTLC txs in phase 1, because they don't mention the cltv_expiry
         // there, only in the commitment tx output.
@@ -847,19 +1577,42 @@ impl Validator for SimpleValidator {
         }
 
         // policy-htlc-fee-range
-        if feerate_per_kw < self.policy.min_feerate_per_kw {
-            return policy_err!(
+        if setup.option_anchors_zero_fee_htlc() {
+            // fees are added by the broadcaster
+            if feerate_per_kw != 0 {
+                self.enforce(tagged_policy_err!(
+                    PolicyTag::FeeRange,
+                    self.channel_hex(),
+                    [("feerate_per_kw", feerate_per_kw)],
+                    "feerate_per_kw of {} is not zero for a zero-fee HTLC tx",
+                    feerate_per_kw
+                ))?;
+            }
+        } else if feerate_per_kw < self.policy.min_feerate_per_kw {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::FeeRange,
+                self.channel_hex(),
+                [
+                    ("feerate_per_kw", feerate_per_kw),
+                    ("min_feerate_per_kw", self.policy.min_feerate_per_kw),
+                ],
                 "feerate_per_kw of {} is smaller than the minimum of {}",
                 feerate_per_kw,
                 self.policy.min_feerate_per_kw
-            );
+            ))?;
         }
         if feerate_per_kw > self.policy.max_feerate_per_kw {
-            return policy_err!(
+            self.enforce(tagged_policy_err!(
+                PolicyTag::FeeRange,
+                self.channel_hex(),
+                [
+                    ("feerate_per_kw", feerate_per_kw),
+                    ("max_feerate_per_kw", self.policy.max_feerate_per_kw),
+                ],
                 "feerate_per_kw of {} is larger than the maximum of {}",
                 feerate_per_kw,
                 self.policy.max_feerate_per_kw
-            );
+            ))?;
         }
 
         *debug_on_return = false;
@@ -1092,6 +1845,7 @@ impl Validator for SimpleValidator {
             );
         }
 
+        // policy-mutual-upfront-shutdown-script
         // If the upfront holder_shutdown_script was in effect, make sure the
         // holder script matches.
         if setup.holder_shutdown_script.is_some() && to_holder_value_sat > 0 {
@@ -1100,6 +1854,16 @@ impl Validator for SimpleValidator {
             }
         }
 
+        // Likewise, the counterparty committed to their upfront
+        // counterparty_shutdown_script.
+        if setup.counterparty_shutdown_script.is_some() && to_counterparty_value_sat > 0 {
+            if *counterparty_script != setup.counterparty_shutdown_script {
+                return policy_err!(
+                    "counterparty_script doesn't match upfront counterparty_shutdown_script"
+                );
+            }
+        }
+
         // policy-mutual-no-pending-htlcs
         if !holder_info.htlcs_is_empty() || !counterparty_info.htlcs_is_empty() {
             return policy_err!("cannot close with pending htlcs");
@@ -1168,21 +1932,268 @@ impl Validator for SimpleValidator {
             }
         }
 
+        let mut facts = RuleFacts {
+            mutual_close: true,
+            channel_value_sat: setup.channel_value_sat,
+            fee_sat: setup.channel_value_sat.saturating_sub(sum_outputs),
+            ..Default::default()
+        };
+        let mut closing_txid = None;
+
         // policy-mutual-destination-allowlisted
         if let Some(script) = &holder_script {
-            if !wallet
+            let to_wallet = wallet
                 .can_spend(holder_wallet_path_hint, script)
-                .map_err(|err| policy_error(format!("wallet can_spend error: {}", err)))?
-                && !wallet.allowlist_contains(script)
-            {
-                return policy_err!("holder output not to wallet or in allowlist");
+                .map_err(|err| policy_error(format!("wallet can_spend error: {}", err)))?;
+            if !to_wallet && !wallet.allowlist_contains(script) {
+                self.enforce(tagged_policy_err!(
+                    PolicyTag::Destination,
+                    self.channel_hex(),
+                    [("script_pubkey", script)],
+                    "holder output not to wallet or in allowlist"
+                ))?;
+            }
+
+            if !to_wallet {
+                let closing_tx = ClosingTransaction::new(
+                    to_holder_value_sat,
+                    to_counterparty_value_sat,
+                    script.clone(),
+                    counterparty_script.clone().unwrap_or_else(|| Script::new()),
+                    setup.funding_outpoint,
+                );
+                let txid = closing_tx.trust().built_transaction().txid();
+                self.validate_value_approval(wallet, &txid, to_holder_value_sat)
+                    .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+                facts.value_sat = to_holder_value_sat;
+                facts.dest_not_allowlisted = !wallet.allowlist_contains(script);
+                closing_txid = Some(txid);
             }
         }
+        self.validate_rules(wallet, closing_txid.as_ref(), &facts)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
 
         *debug_on_return = false; // don't debug when we succeed
         Ok(())
     }
 
+    #[cfg(feature = "simple_close")]
+    fn validate_simple_close_tx(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        holder_is_closer: bool,
+        locktime: u32,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        let mut debug_on_return = scoped_debug_return!(
+            setup,
+            estate,
+            holder_is_closer,
+            locktime,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script
+        );
+
+        let holder_info = estate
+            .current_holder_commit_info
+            .as_ref()
+            .ok_or_else(|| policy_error("current_holder_commit_info missing"))?;
+
+        let counterparty_info = estate
+            .current_counterparty_commit_info
+            .as_ref()
+            .ok_or_else(|| policy_error("current_counterparty_commit_info missing"))?;
+
+        if to_holder_value_sat > 0 && holder_script.is_none() {
+            return policy_err!(
+                "missing holder_script with {} to_holder_value_sat",
+                to_holder_value_sat
+            );
+        }
+
+        if to_counterparty_value_sat > 0 && counterparty_script.is_none() {
+            return policy_err!(
+                "missing counterparty_script with {} to_counterparty_value_sat",
+                to_counterparty_value_sat
+            );
+        }
+
+        // policy-mutual-upfront-shutdown-script
+        if setup.holder_shutdown_script.is_some() && to_holder_value_sat > 0 {
+            if *holder_script != setup.holder_shutdown_script {
+                return policy_err!("holder_script doesn't match upfront holder_shutdown_script");
+            }
+        }
+
+        if setup.counterparty_shutdown_script.is_some() && to_counterparty_value_sat > 0 {
+            if *counterparty_script != setup.counterparty_shutdown_script {
+                return policy_err!(
+                    "counterparty_script doesn't match upfront counterparty_shutdown_script"
+                );
+            }
+        }
+
+        // policy-mutual-no-pending-htlcs
+        if !holder_info.htlcs_is_empty() || !counterparty_info.htlcs_is_empty() {
+            return policy_err!("cannot close with pending htlcs");
+        }
+
+        // The balances before fees.  The non-funder's balance is its
+        // commitment output, and the commitment fee and anchors go back to
+        // the funder.
+        let (holder_balance_sat, counterparty_balance_sat) = if setup.is_outbound {
+            let counterparty_balance_sat = holder_info.to_countersigner_value_sat;
+            let holder_balance_sat = setup
+                .channel_value_sat
+                .checked_sub(counterparty_balance_sat)
+                .ok_or_else(|| policy_error("counterparty balance overflow"))?;
+            (holder_balance_sat, counterparty_balance_sat)
+        } else {
+            let holder_balance_sat = holder_info.to_broadcaster_value_sat;
+            let counterparty_balance_sat = setup
+                .channel_value_sat
+                .checked_sub(holder_balance_sat)
+                .ok_or_else(|| policy_error("holder balance overflow"))?;
+            (holder_balance_sat, counterparty_balance_sat)
+        };
+
+        // policy-simple-close-closer-pays-fee
+        // The closer pays the whole fee from its own output, so the
+        // closee's output is its whole balance.
+        let (closee, closee_value_sat, closee_balance_sat) = if holder_is_closer {
+            ("counterparty", to_counterparty_value_sat, counterparty_balance_sat)
+        } else {
+            ("holder", to_holder_value_sat, holder_balance_sat)
+        };
+        if closee_value_sat == 0 {
+            // policy-simple-close-closee-dust
+            // The closee's output can only be omitted if it is dust.
+            if closee_balance_sat >= MIN_DUST_LIMIT_SATOSHIS {
+                return policy_err!(
+                    "closee {} output omitted with balance {}",
+                    closee,
+                    closee_balance_sat
+                );
+            }
+        } else if let (true, descr) =
+            self.outside_epsilon_range(closee_value_sat, closee_balance_sat)
+        {
+            return policy_err!(
+                "closee {} output {} is {} than its balance {}",
+                closee,
+                closee_value_sat,
+                descr,
+                closee_balance_sat
+            );
+        }
+
+        let sum_outputs = to_holder_value_sat
+            .checked_add(to_counterparty_value_sat)
+            .ok_or_else(|| policy_error("consumed overflow".to_string()))?;
+        if holder_is_closer {
+            // policy-mutual-fee-range
+            // The fee is ours, an omitted holder output adds to it.
+            self.validate_fee(setup.channel_value_sat, sum_outputs)
+                .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+        } else if sum_outputs > setup.channel_value_sat {
+            return policy_err!(
+                "sum of outputs {} larger than channel value {}",
+                sum_outputs,
+                setup.channel_value_sat
+            );
+        }
+
+        let mut facts = RuleFacts {
+            mutual_close: true,
+            channel_value_sat: setup.channel_value_sat,
+            fee_sat: setup.channel_value_sat.saturating_sub(sum_outputs),
+            ..Default::default()
+        };
+        let mut closing_txid = None;
+
+        // policy-mutual-destination-allowlisted
+        if let (true, Some(script)) = (to_holder_value_sat > 0, holder_script) {
+            let to_wallet = wallet
+                .can_spend(holder_wallet_path_hint, script)
+                .map_err(|err| policy_error(format!("wallet can_spend error: {}", err)))?;
+            if !to_wallet && !wallet.allowlist_contains(script) {
+                self.enforce(tagged_policy_err!(
+                    PolicyTag::Destination,
+                    self.channel_hex(),
+                    [("script_pubkey", script)],
+                    "holder output not to wallet or in allowlist"
+                ))?;
+            }
+
+            if !to_wallet {
+                let txid = build_simple_close_tx(
+                    setup.funding_outpoint,
+                    locktime,
+                    to_holder_value_sat,
+                    to_counterparty_value_sat,
+                    holder_script,
+                    counterparty_script,
+                )
+                .txid();
+                self.validate_value_approval(wallet, &txid, to_holder_value_sat)
+                    .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+                facts.value_sat = to_holder_value_sat;
+                facts.dest_not_allowlisted = !wallet.allowlist_contains(script);
+                closing_txid = Some(txid);
+            }
+        }
+        self.validate_rules(wallet, closing_txid.as_ref(), &facts)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
+        *debug_on_return = false; // don't debug when we succeed
+        Ok(())
+    }
+
+    fn validate_close_plan(
+        &self,
+        fee_sat: u64,
+        non_wallet_sat: u64,
+    ) -> Result<(), ValidationError> {
+        // policy-close-plan-fee-range
+        if fee_sat > self.policy.max_close_plan_fee_sat {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::FeeRange,
+                None,
+                [
+                    ("fee_sat", fee_sat),
+                    ("max_close_plan_fee_sat", self.policy.max_close_plan_fee_sat)
+                ],
+                "close plan fees above maximum: {} > {}",
+                fee_sat,
+                self.policy.max_close_plan_fee_sat
+            ))?;
+        }
+
+        // policy-close-plan-velocity
+        if non_wallet_sat > self.policy.max_close_plan_non_wallet_sat {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::Destination,
+                None,
+                [
+                    ("non_wallet_sat", non_wallet_sat),
+                    ("max_close_plan_non_wallet_sat", self.policy.max_close_plan_non_wallet_sat)
+                ],
+                "close plan value to non-wallet destinations above maximum: {} > {}",
+                non_wallet_sat,
+                self.policy.max_close_plan_non_wallet_sat
+            ))?;
+        }
+        Ok(())
+    }
+
     fn validate_delayed_sweep(
         &self,
         wallet: &Wallet,
@@ -1200,6 +2211,9 @@ impl Validator for SimpleValidator {
         self.validate_sweep(wallet, tx, input, amount_sat, wallet_path)
             .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
 
+        self.validate_chain_clock(cstate)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
         // policy-sweep-locktime
         if tx.lock_time > cstate.current_height {
             return transaction_format_err!(
@@ -1223,6 +2237,39 @@ impl Validator for SimpleValidator {
         Ok(())
     }
 
+    fn validate_counterparty_payment_sweep(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        tx: &Transaction,
+        input: usize,
+        amount_sat: u64,
+        wallet_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        let mut debug_on_return =
+            scoped_debug_return!(setup, cstate, tx, input, amount_sat, wallet_path);
+
+        // Common sweep validation
+        self.validate_sweep(wallet, tx, input, amount_sat, wallet_path)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
+        self.validate_chain_clock(cstate)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
+        // policy-sweep-locktime
+        if tx.lock_time > cstate.current_height {
+            return transaction_format_err!(
+                "bad locktime: {} > {}",
+                tx.lock_time,
+                cstate.current_height
+            );
+        }
+
+        *debug_on_return = false;
+        Ok(())
+    }
+
     fn validate_counterparty_htlc_sweep(
         &self,
         wallet: &Wallet,
@@ -1241,6 +2288,9 @@ impl Validator for SimpleValidator {
         self.validate_sweep(wallet, tx, input, amount_sat, wallet_path)
             .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
 
+        self.validate_chain_clock(cstate)
+            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+
         // Parse the redeemscript to determine the cltv_expiry
         if let Ok((
             _revocation_hash,
@@ -1356,10 +2406,172 @@ impl Validator for SimpleValidator {
         }
     }
 
+    fn validate_clock_skew(&self, clock_skew_secs: i64) -> Result<(), ValidationError> {
+        let max = self.policy.max_clock_skew_secs as i64;
+        // Block timestamps may be up to two hours in the future, but can't
+        // be that far ahead of a correct clock.
+        // policy-chain-clock-skew
+        if clock_skew_secs < -max {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::ChainState,
+                self.channel_hex(),
+                [("clock_skew_secs", clock_skew_secs), ("max_clock_skew_secs", max)],
+                "host clock is {}s behind the chain tip, max {}",
+                -clock_skew_secs,
+                max
+            ))?;
+        }
+        if clock_skew_secs > max {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::ChainState,
+                self.channel_hex(),
+                [("clock_skew_secs", clock_skew_secs), ("max_clock_skew_secs", max)],
+                "host clock is {}s ahead of the chain tip, max {}, or the chain is stale",
+                clock_skew_secs,
+                max
+            ))?;
+        }
+        Ok(())
+    }
+
     fn enforce_balance(&self) -> bool {
         self.policy.enforce_balance
     }
 
+    fn require_funding_registration(&self) -> bool {
+        self.policy.require_funding_registration
+    }
+
+    fn grind_low_r(&self) -> bool {
+        self.policy.grind_low_r
+    }
+
+    fn verify_signatures(&self) -> bool {
+        self.policy.verify_signatures
+    }
+
+    fn channel_review_due(&self, estate: &EnforcementState, cstate: &ChainState) -> Option<String> {
+        let height = cstate.current_height;
+        let max_age = self.policy.max_channel_age_blocks;
+        if max_age > 0 && estate.review_height > 0 {
+            let age = height.saturating_sub(estate.review_height);
+            if age >= max_age {
+                return Some(format!("{} blocks since review >= {}", age, max_age));
+            }
+        }
+        let max_inactivity = self.policy.max_channel_inactivity_blocks;
+        if max_inactivity > 0 && estate.last_activity_height > 0 {
+            let inactivity = height.saturating_sub(estate.last_activity_height);
+            if inactivity >= max_inactivity {
+                return Some(format!("{} blocks inactive >= {}", inactivity, max_inactivity));
+            }
+        }
+        None
+    }
+
+    fn validate_payment_retry(
+        &self,
+        attempt_heights: &[u32],
+        cstate: &ChainState,
+    ) -> Result<(), ValidationError> {
+        let max = self.policy.max_payment_retries;
+        if max == 0 {
+            return Ok(());
+        }
+        let window = self.policy.payment_retry_window_blocks;
+        let height = cstate.current_height;
+        let failed = attempt_heights.iter().filter(|h| height.saturating_sub(**h) < window).count();
+        // policy-payment-retry-limit
+        if failed > max as usize {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::PaymentRetry,
+                self.channel_hex(),
+                [("failed_attempts", failed), ("max_payment_retries", max)],
+                "{} failed attempts at the payment in the last {} blocks, max retries {}",
+                failed,
+                window,
+                max
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn validate_payment_duplicate(
+        &self,
+        entry: &PaymentLedgerEntry,
+    ) -> Result<(), ValidationError> {
+        // policy-payment-duplicate
+        if entry.outgoing && entry.resolution == PaymentResolution::Fulfilled {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::DuplicatePayment,
+                self.channel_hex(),
+                [("payment_hash", entry.payment_hash.0.to_hex())],
+                "invoice {} was already paid",
+                entry.payment_hash.0.to_hex()
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn validate_invoice(&self, amount_msat: u64, expiry_secs: u64) -> Result<(), ValidationError> {
+        // policy-invoice-amount-range
+        let max_amount_msat = self.policy.max_invoice_amount_msat;
+        if max_amount_msat > 0 && amount_msat > max_amount_msat {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::InvoiceLimit,
+                self.channel_hex(),
+                [("amount_msat", amount_msat), ("max_invoice_amount_msat", max_amount_msat)],
+                "invoice amount {} msat exceeds the maximum {}",
+                amount_msat,
+                max_amount_msat
+            ))?;
+        }
+        // policy-invoice-expiry-range
+        let max_expiry_secs = self.policy.max_invoice_expiry_secs;
+        if max_expiry_secs > 0 && expiry_secs > max_expiry_secs {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::InvoiceLimit,
+                self.channel_hex(),
+                [("expiry_secs", expiry_secs), ("max_invoice_expiry_secs", max_expiry_secs)],
+                "invoice expiry {}s exceeds the maximum {}s",
+                expiry_secs,
+                max_expiry_secs
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn validate_onchain_velocity(
+        &self,
+        spends: &[(u32, u64)],
+        value_sat: u64,
+        cstate: &ChainState,
+    ) -> Result<(), ValidationError> {
+        let max = self.policy.max_onchain_velocity_sat;
+        if max == 0 {
+            return Ok(());
+        }
+        let window = self.policy.onchain_velocity_window_blocks;
+        let height = cstate.current_height;
+        let total = spends
+            .iter()
+            .filter(|(h, _)| height.saturating_sub(*h) < window)
+            .fold(value_sat, |sum, (_, value)| sum.saturating_add(*value));
+        // policy-onchain-velocity
+        if total > max {
+            self.enforce(tagged_policy_err!(
+                PolicyTag::Velocity,
+                self.channel_hex(),
+                [("value_sat", total), ("max_onchain_velocity_sat", max)],
+                "{} sat sent from the wallet in the last {} blocks, above the maximum {}",
+                total,
+                window,
+                max
+            ))?;
+        }
+        Ok(())
+    }
+
     fn minimum_initial_balance(&self, holder_value_msat: u64) -> u64 {
         holder_value_msat / 1000
     }
@@ -1403,14 +2615,25 @@ impl SimpleValidator {
 
         // policy-commitment-htlc-count-limit
         if info.offered_htlcs.len() + info.received_htlcs.len() > policy.max_htlcs {
-            return Err(policy_error("too many HTLCs".to_string()));
+            self.enforce(Err(policy_error("too many HTLCs".to_string()).with_policy(
+                PolicyTag::HtlcLimit,
+                vec![
+                    (
+                        "htlc_count".to_string(),
+                        (info.offered_htlcs.len() + info.received_htlcs.len()).to_string(),
+                    ),
+                    ("max_htlcs".to_string(), policy.max_htlcs.to_string()),
+                ],
+                self.channel_hex(),
+            )))?;
         }
 
         let mut htlc_value_sat: u64 = 0;
 
+        // Zero-fee HTLC transactions don't add to the dust limit
+        let htlc_tx_feerate = setup.htlc_feerate_per_kw(DUST_RELAY_TX_FEE) as u64;
         let offered_htlc_dust_limit = MIN_DUST_LIMIT_SATOSHIS
-            + (DUST_RELAY_TX_FEE as u64 * htlc_timeout_tx_weight(setup.option_anchor_outputs())
-                / 1000);
+            + (htlc_tx_feerate * htlc_timeout_tx_weight(setup.option_anchor_outputs()) / 1000);
         for htlc in &info.offered_htlcs {
             // TODO - this check should be converted into two checks, one the first time
             // the HTLC is introduced and the other every time it is encountered.
@@ -1433,8 +2656,7 @@ impl SimpleValidator {
         }
 
         let received_htlc_dust_limit = MIN_DUST_LIMIT_SATOSHIS
-            + (DUST_RELAY_TX_FEE as u64 * htlc_success_tx_weight(setup.option_anchor_outputs())
-                / 1000);
+            + (htlc_tx_feerate * htlc_success_tx_weight(setup.option_anchor_outputs()) / 1000);
         for htlc in &info.received_htlcs {
             // TODO - this check should be converted into two checks, one the first time
             // the HTLC is introduced and the other every time it is encountered.
@@ -1458,7 +2680,16 @@ impl SimpleValidator {
 
         // policy-commitment-htlc-inflight-limit
         if htlc_value_sat > policy.max_htlc_value_sat {
-            return policy_err!("sum of HTLC values {} too large", htlc_value_sat);
+            self.enforce(tagged_policy_err!(
+                PolicyTag::HtlcLimit,
+                self.channel_hex(),
+                [
+                    ("htlc_value_sat", htlc_value_sat),
+                    ("max_htlc_value_sat", policy.max_htlc_value_sat)
+                ],
+                "sum of HTLC values {} too large",
+                htlc_value_sat
+            ))?;
         }
 
         // policy-commitment-fee-range
@@ -1471,6 +2702,8 @@ impl SimpleValidator {
         self.validate_fee(setup.channel_value_sat, sum_outputs)
             .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
 
+        self.validate_zero_conf(estate, commit_num, cstate, info)?;
+
         let (_holder_value_sat, counterparty_value_sat) = info.value_to_parties();
 
         // Enforce additional requirements on initial commitments.
@@ -1503,46 +2736,6 @@ impl SimpleValidator {
     }
 }
 
-/// Construct a default simple policy
-pub fn make_simple_policy(network: Network) -> SimplePolicy {
-    if network == Network::Bitcoin {
-        SimplePolicy {
-            min_delay: 60,
-            max_delay: 2016, // Match LDK maximum and default
-            max_channel_size_sat: 1_000_000_001,
-            epsilon_sat: 1_600_000,
-            max_htlcs: 1000,
-            max_htlc_value_sat: 16_777_216,
-            use_chain_state: false,
-            min_feerate_per_kw: 1000,
-            max_feerate_per_kw: 1000 * 1000,
-            min_fee: 100,
-            max_fee: 1000,
-            require_invoices: false,
-            enforce_balance: false,
-            max_routing_fee_msat: 10000,
-        }
-    } else {
-        SimplePolicy {
-            min_delay: 4,
-            max_delay: 2016,                     // Match LDK maximum and default
-            max_channel_size_sat: 1_000_000_001, // lnd itest: wumbu default + 1
-            // lnd itest: async_bidirectional_payments (large amount of dust HTLCs) 1_600_000
-            epsilon_sat: 10_000, // c-lightning
-            max_htlcs: 1000,
-            max_htlc_value_sat: 16_777_216, // lnd itest: multi-hop_htlc_error_propagation
-            use_chain_state: false,
-            min_feerate_per_kw: 500,    // c-lightning integration
-            max_feerate_per_kw: 16_000, // c-lightning integration
-            min_fee: 100,
-            max_fee: 200_000, // c-lightning integration 124301
-            require_invoices: false,
-            enforce_balance: false,
-            max_routing_fee_msat: 10000,
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use lightning::ln::PaymentHash;
@@ -1550,6 +2743,7 @@ mod tests {
 
     use crate::tx::tx::HTLCInfo2;
     use crate::util::key_utils::*;
+    use crate::util::status::Status;
     use crate::util::test_utils::*;
 
     use super::*;
@@ -1558,7 +2752,10 @@ mod tests {
         let policy = SimplePolicy {
             min_delay: 5,
             max_delay: 1440,
+            max_counterparty_contest_delay: 1440,
             max_channel_size_sat: 100_000_000,
+            max_push_value_msat: 1_000_000_000,
+            max_push_percentage: 50,
             epsilon_sat: 100_000,
             max_htlcs: 1000,
             max_htlc_value_sat: 10_000_000,
@@ -1570,6 +2767,30 @@ mod tests {
             require_invoices: false,
             enforce_balance: false,
             max_routing_fee_msat: 10000,
+            max_clock_skew_secs: 3 * 3600,
+            enforce_clock_skew: false,
+            grind_low_r: false,
+            verify_signatures: false,
+            require_funding_watch: true,
+            min_funding_depth: 3,
+            enforce_funding_depth: false,
+            max_zero_conf_exposure_sat: 3_000_000,
+            require_funding_registration: false,
+            max_unapproved_value_sat: 1_000_000,
+            value_approval_key: None,
+            max_close_plan_fee_sat: 30_000,
+            max_close_plan_non_wallet_sat: 1_000_000,
+            max_channel_age_blocks: 0,
+            max_channel_inactivity_blocks: 0,
+            block_htlcs_pending_review: false,
+            max_payment_retries: 0,
+            payment_retry_window_blocks: 144,
+            max_invoice_amount_msat: 0,
+            max_invoice_expiry_secs: 30 * 24 * 3600,
+            max_onchain_velocity_sat: 0,
+            onchain_velocity_window_blocks: 144,
+            enforcement: vec![],
+            rules: vec![],
         };
 
         SimpleValidator {
@@ -1619,6 +2840,144 @@ mod tests {
         assert!(validator.validate_channel_value(&setup).is_err());
     }
 
+    // policy-channel-push-value-range
+    #[test]
+    fn validate_push_value_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let mut setup = make_test_channel_setup();
+        let validator = make_test_validator();
+        // The absolute limit is the lower one
+        setup.channel_value_sat = 3_000_000;
+        setup.push_value_msat = 1_000_000_000;
+        assert_validation_ok!(validator.validate_ready_channel(&*node, &setup, &vec![]));
+        setup.push_value_msat = 1_000_000_001;
+        assert_policy_err!(
+            validator.validate_ready_channel(&*node, &setup, &vec![]),
+            "validate_ready_channel: push_value_msat too large: 1000000001 > 1000000000"
+        );
+        // The percentage limit is the lower one
+        setup.channel_value_sat = 1_000_000;
+        setup.push_value_msat = 500_000_000;
+        assert_validation_ok!(validator.validate_ready_channel(&*node, &setup, &vec![]));
+        setup.push_value_msat = 500_000_001;
+        assert_policy_err!(
+            validator.validate_ready_channel(&*node, &setup, &vec![]),
+            "validate_ready_channel: push_value_msat 500000001 too large for channel value 1000000: more than 50%"
+        );
+        // Pushes to us are not limited
+        setup.is_outbound = false;
+        assert_validation_ok!(validator.validate_ready_channel(&*node, &setup, &vec![]));
+    }
+
+    // policy-channel-push-value-range
+    #[test]
+    fn validate_push_value_combined_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let mut setup = make_test_channel_setup();
+        let validator = make_test_validator();
+        // 50% of the channel value is exactly the absolute limit
+        setup.channel_value_sat = 2_000_000;
+        setup.push_value_msat = 1_000_000_000;
+        assert_validation_ok!(validator.validate_ready_channel(&*node, &setup, &vec![]));
+        setup.push_value_msat = 1_000_000_001;
+        assert_policy_err!(
+            validator.validate_ready_channel(&*node, &setup, &vec![]),
+            "validate_ready_channel: push_value_msat too large: 1000000001 > 1000000000"
+        );
+        // Just below, the percentage limit applies
+        setup.channel_value_sat = 1_999_999;
+        setup.push_value_msat = 999_999_500;
+        assert_validation_ok!(validator.validate_ready_channel(&*node, &setup, &vec![]));
+        setup.push_value_msat = 999_999_501;
+        assert_policy_err!(
+            validator.validate_ready_channel(&*node, &setup, &vec![]),
+            "validate_ready_channel: push_value_msat 999999501 too large for channel value 1999999: more than 50%"
+        );
+    }
+
+    // policy-chain-clock-skew
+    #[test]
+    fn validate_clock_skew_test() {
+        let validator = make_test_validator();
+        assert_validation_ok!(validator.validate_clock_skew(0));
+        assert_validation_ok!(validator.validate_clock_skew(10800));
+        assert_validation_ok!(validator.validate_clock_skew(-10800));
+        assert_policy_err!(
+            validator.validate_clock_skew(10801),
+            "validate_clock_skew: host clock is 10801s ahead of the chain tip, max 10800, or the chain is stale"
+        );
+        assert_policy_err!(
+            validator.validate_clock_skew(-10801),
+            "validate_clock_skew: host clock is 10801s behind the chain tip, max 10800"
+        );
+    }
+
+    #[test]
+    fn policy_violation_details_test() {
+        let mut validator = make_test_validator();
+        let policy = validator.validate_clock_skew(10801).unwrap_err().policy.unwrap();
+        assert_eq!(policy.tag, PolicyTag::ChainState);
+        assert_eq!(
+            policy.values,
+            vec![
+                ("clock_skew_secs".to_string(), "10801".to_string()),
+                ("max_clock_skew_secs".to_string(), "10800".to_string())
+            ]
+        );
+        assert_eq!(policy.channel_id, None);
+
+        let channel_id = ChannelId([3u8; 32]);
+        validator.channel_id = Some(channel_id);
+        let err = validator.validate_delay("self", 4, 1440).unwrap_err();
+        let status: Status = err.into();
+        let policy = status.policy_violation().unwrap();
+        assert_eq!(policy.tag, PolicyTag::DelayRange);
+        assert_eq!(policy.channel_id, Some(channel_id.to_string()));
+    }
+
+    fn fee_range_warnings() -> usize {
+        policy_warnings().iter().find(|(tag, _)| *tag == PolicyTag::FeeRange).unwrap().1
+    }
+
+    #[test]
+    fn enforcement_level_test() {
+        let mut validator = make_test_validator();
+        assert!(validator.validate_fee(20_000, 5_000).is_err());
+
+        let warnings = fee_range_warnings();
+        validator.policy.enforcement = vec![(PolicyTag::FeeRange, EnforcementLevel::Warn)];
+        assert_validation_ok!(validator.validate_fee(20_000, 5_000));
+        assert_eq!(fee_range_warnings(), warnings + 1);
+        // other rules are still enforced
+        assert!(validator.validate_delay("self", 4, 1440).is_err());
+
+        validator.policy.enforcement = vec![(PolicyTag::FeeRange, EnforcementLevel::Off)];
+        assert_validation_ok!(validator.validate_fee(20_000, 5_000));
+        assert_eq!(fee_range_warnings(), warnings + 1);
+
+        // revocation order can't be relaxed
+        validator.policy.enforcement = vec![(PolicyTag::RevocationOrder, EnforcementLevel::Off)];
+        assert_eq!(
+            validator.policy.enforcement_level(PolicyTag::RevocationOrder),
+            EnforcementLevel::Enforce
+        );
+    }
+
+    #[test]
+    fn validate_close_plan_test() {
+        let validator = make_test_validator();
+        assert_validation_ok!(validator.validate_close_plan(30_000, 1_000_000));
+        assert_policy_err!(
+            validator.validate_close_plan(30_001, 0),
+            "validate_close_plan: close plan fees above maximum: 30001 > 30000"
+        );
+        assert_policy_err!(
+            validator.validate_close_plan(0, 1_000_001),
+            "validate_close_plan: \
+             close plan value to non-wallet destinations above maximum: 1000001 > 1000000"
+        );
+    }
+
     fn make_counterparty_info(
         to_holder_value_sat: u64,
         to_counterparty_value_sat: u64,
@@ -1688,6 +3047,272 @@ mod tests {
         ));
     }
 
+    // policy-zero-conf-exposure
+    // policy-funding-watched
+    #[test]
+    fn validate_zero_conf_test() {
+        let mut validator = make_test_validator();
+        validator.policy.max_zero_conf_exposure_sat = 2_000_000;
+        let mut enforcement_state = EnforcementState::new(0);
+        let commit_num = 23;
+        enforcement_state
+            .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
+        enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+        let commit_point = make_test_pubkey(0x12);
+        let mut cstate = make_test_chain_state();
+        let setup = make_test_channel_setup();
+        let delay = setup.holder_selected_contest_delay;
+        let info_good = make_counterparty_info(2_000_000, 999_000, delay, vec![], vec![]);
+        assert_validation_ok!(validator.validate_commitment_tx(
+            &enforcement_state,
+            commit_num,
+            &commit_point,
+            &setup,
+            &cstate,
+            &info_good,
+        ));
+        // An HTLC offered to us adds to the exposure
+        let info_bad =
+            make_counterparty_info(2_000_000, 990_000, delay, vec![make_htlc_info2(1100)], vec![]);
+        assert_policy_err!(
+            validator.validate_commitment_tx(
+                &enforcement_state,
+                commit_num,
+                &commit_point,
+                &setup,
+                &cstate,
+                &info_bad,
+            ),
+            "validate_zero_conf: zero-conf exposure above maximum at funding depth 0: \
+             2005010 > 2000000"
+        );
+        // No limit once the funding is buried
+        cstate.funding_depth = 3;
+        assert_validation_ok!(validator.validate_commitment_tx(
+            &enforcement_state,
+            commit_num,
+            &commit_point,
+            &setup,
+            &cstate,
+            &info_bad,
+        ));
+        cstate.funding_watched = false;
+        assert_policy_err!(
+            validator.validate_commitment_tx(
+                &enforcement_state,
+                commit_num,
+                &commit_point,
+                &setup,
+                &cstate,
+                &info_bad,
+            ),
+            "validate_zero_conf: funding outpoint is not watched"
+        );
+    }
+
+    // policy-commitment-funding-depth
+    #[test]
+    fn validate_counterparty_commitment_funding_depth_test() {
+        let mut validator = make_test_validator();
+        validator.policy.enforce_funding_depth = true;
+        let mut enforcement_state = EnforcementState::new(0);
+        let commit_num = 23;
+        enforcement_state
+            .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
+        enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+        let commit_point = make_test_pubkey(0x12);
+        let mut cstate = make_test_chain_state();
+        cstate.funding_depth = 2;
+        let setup = make_test_channel_setup();
+        let delay = setup.holder_selected_contest_delay;
+        let info = make_counterparty_info(2_000_000, 999_000, delay, vec![], vec![]);
+        assert_policy_err!(
+            validator.validate_counterparty_commitment_tx(
+                &enforcement_state,
+                commit_num,
+                &commit_point,
+                &setup,
+                &cstate,
+                &info,
+            ),
+            "validate_counterparty_commitment_tx: funding depth too low for commit_num 23: 2 < 3"
+        );
+        cstate.funding_depth = 3;
+        assert_validation_ok!(validator.validate_counterparty_commitment_tx(
+            &enforcement_state,
+            commit_num,
+            &commit_point,
+            &setup,
+            &cstate,
+            &info,
+        ));
+    }
+
+    #[test]
+    fn channel_review_due_test() {
+        let mut validator = make_test_validator();
+        let mut enforcement_state = EnforcementState::new(0);
+        let cstate = make_test_chain_state();
+        // disabled by default, and unknown heights are not flagged
+        enforcement_state.review_height = 900;
+        enforcement_state.last_activity_height = 950;
+        assert_eq!(validator.channel_review_due(&enforcement_state, &cstate), None);
+        validator.policy.max_channel_age_blocks = 100;
+        assert_eq!(
+            validator.channel_review_due(&enforcement_state, &cstate),
+            Some("100 blocks since review >= 100".to_string())
+        );
+        enforcement_state.review_height = 901;
+        assert_eq!(validator.channel_review_due(&enforcement_state, &cstate), None);
+        validator.policy.max_channel_inactivity_blocks = 50;
+        assert_eq!(
+            validator.channel_review_due(&enforcement_state, &cstate),
+            Some("50 blocks inactive >= 50".to_string())
+        );
+        enforcement_state.last_activity_height = 0;
+        assert_eq!(validator.channel_review_due(&enforcement_state, &cstate), None);
+    }
+
+    // policy-commitment-channel-review
+    #[test]
+    fn validate_counterparty_commitment_channel_review_test() {
+        let mut validator = make_test_validator();
+        validator.policy.max_channel_age_blocks = 100;
+        let mut enforcement_state = EnforcementState::new(0);
+        enforcement_state.review_height = 900;
+        let commit_num = 23;
+        enforcement_state
+            .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
+        enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+        let commit_point = make_test_pubkey(0x12);
+        let mut cstate = make_test_chain_state();
+        cstate.funding_depth = 3;
+        let setup = make_test_channel_setup();
+        let delay = setup.holder_selected_contest_delay;
+        let info =
+            make_counterparty_info(2_000_000, 990_000, delay, vec![make_htlc_info2(1100)], vec![]);
+        // HTLCs are only blocked if the policy says so
+        assert_validation_ok!(validator.validate_counterparty_commitment_tx(
+            &enforcement_state,
+            commit_num,
+            &commit_point,
+            &setup,
+            &cstate,
+            &info,
+        ));
+        validator.policy.block_htlcs_pending_review = true;
+        assert_policy_err!(
+            validator.validate_counterparty_commitment_tx(
+                &enforcement_state,
+                commit_num,
+                &commit_point,
+                &setup,
+                &cstate,
+                &info,
+            ),
+            "validate_counterparty_commitment_tx: cannot add HTLCs to a channel pending review: \
+             100 blocks since review >= 100"
+        );
+        // a commitment without HTLCs can still be signed, so that the
+        // channel can be drained
+        let info = make_counterparty_info(2_000_000, 999_000, delay, vec![], vec![]);
+        assert_validation_ok!(validator.validate_counterparty_commitment_tx(
+            &enforcement_state,
+            commit_num,
+            &commit_point,
+            &setup,
+            &cstate,
+            &info,
+        ));
+    }
+
+    // policy-payment-retry-limit
+    #[test]
+    fn validate_payment_retry_test() {
+        let mut validator = make_test_validator();
+        let cstate = make_test_chain_state();
+        let attempts = vec![800, 900, 950, 990];
+        // disabled by default
+        assert_validation_ok!(validator.validate_payment_retry(&attempts, &cstate));
+        validator.policy.max_payment_retries = 2;
+        assert_validation_ok!(validator.validate_payment_retry(&attempts[..2], &cstate));
+        assert_policy_err!(
+            validator.validate_payment_retry(&attempts, &cstate),
+            "validate_payment_retry: 3 failed attempts at the payment in the last 144 blocks, \
+             max retries 2"
+        );
+        // older attempts age out of the window
+        validator.policy.payment_retry_window_blocks = 60;
+        assert_validation_ok!(validator.validate_payment_retry(&attempts, &cstate));
+    }
+
+    // policy-payment-duplicate
+    #[test]
+    fn validate_payment_duplicate_test() {
+        let validator = make_test_validator();
+        let mut entry = PaymentLedgerEntry {
+            payment_hash: PaymentHash([3; 32]),
+            outgoing: true,
+            amount_msat: 1_000_000,
+            invoice_hash: [4; 32],
+            expiry_time: 1_000,
+            resolution: PaymentResolution::Failed,
+        };
+        // a failed payment can be retried
+        assert_validation_ok!(validator.validate_payment_duplicate(&entry));
+        entry.resolution = PaymentResolution::Fulfilled;
+        assert_policy_err!(
+            validator.validate_payment_duplicate(&entry),
+            "validate_payment_duplicate: invoice \
+             0303030303030303030303030303030303030303030303030303030303030303 was already paid"
+        );
+        // an incoming payment is not ours to pay
+        entry.outgoing = false;
+        assert_validation_ok!(validator.validate_payment_duplicate(&entry));
+    }
+
+    // policy-invoice-amount-range
+    // policy-invoice-expiry-range
+    #[test]
+    fn validate_invoice_test() {
+        let mut validator = make_test_validator();
+        // no amount limit by default
+        assert_validation_ok!(validator.validate_invoice(u64::MAX, 3600));
+        validator.policy.max_invoice_amount_msat = 1_000_000;
+        assert_validation_ok!(validator.validate_invoice(1_000_000, 3600));
+        assert_validation_ok!(validator.validate_invoice(0, 3600));
+        assert_policy_err!(
+            validator.validate_invoice(1_000_001, 3600),
+            "validate_invoice: invoice amount 1000001 msat exceeds the maximum 1000000"
+        );
+        assert_policy_err!(
+            validator.validate_invoice(1_000, 31 * 24 * 3600),
+            "validate_invoice: invoice expiry 2678400s exceeds the maximum 2592000s"
+        );
+        validator.policy.max_invoice_expiry_secs = 0;
+        assert_validation_ok!(validator.validate_invoice(1_000, 31 * 24 * 3600));
+    }
+
+    // policy-onchain-velocity
+    #[test]
+    fn validate_onchain_velocity_test() {
+        let mut validator = make_test_validator();
+        let cstate = make_test_chain_state();
+        let spends = vec![(900, 300_000), (950, 200_000)];
+        // disabled by default
+        assert_validation_ok!(validator.validate_onchain_velocity(&spends, u64::MAX, &cstate));
+        validator.policy.max_onchain_velocity_sat = 1_000_000;
+        assert_validation_ok!(validator.validate_onchain_velocity(&spends, 500_000, &cstate));
+        assert_policy_err!(
+            validator.validate_onchain_velocity(&spends, 500_001, &cstate),
+            "validate_onchain_velocity: 1000001 sat sent from the wallet in the last 144 blocks, \
+             above the maximum 1000000"
+        );
+        // older spends age out of the window
+        validator.policy.onchain_velocity_window_blocks = 60;
+        assert_validation_ok!(validator.validate_onchain_velocity(&spends, 800_000, &cstate));
+    }
+
     // policy-channel-holder-contest-delay-range
     // policy-commitment-to-self-delay-range
     #[test]
@@ -1752,6 +3377,23 @@ mod tests {
         );
     }
 
+    // policy-channel-counterparty-contest-delay-range
+    #[test]
+    fn validate_to_counterparty_max_contest_delay_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let mut setup = make_test_channel_setup();
+        let mut validator = make_test_validator();
+        validator.policy.max_counterparty_contest_delay = 144;
+        setup.counterparty_selected_contest_delay = 144;
+        setup.holder_selected_contest_delay = 1440;
+        assert_validation_ok!(validator.validate_ready_channel(&*node, &setup, &vec![]));
+        setup.counterparty_selected_contest_delay = 145;
+        assert_policy_err!(
+            validator.validate_ready_channel(&*node, &setup, &vec![]),
+            "validate_delay: counterparty_selected_contest_delay too large: 145 > 144"
+        );
+    }
+
     // policy-commitment-fee-range
     #[test]
     fn validate_commitment_tx_shortage_test() {
@@ -1,205 +1,44 @@
 extern crate scopeguard;
 
 use core::cmp::{max, min};
+use core::fmt;
 
 use bitcoin::secp256k1::{PublicKey, SecretKey};
-use bitcoin::{self, Network, Script, SigHash, SigHashType, Transaction};
-use lightning::chain::keysinterface::InMemorySigner;
-use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
+use bitcoin::{OutPoint, Txid};
 use lightning::ln::PaymentHash;
 use log::debug;
 
-use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
+use crate::channel::{Channel, ChannelId, ChannelSetup, ChannelSlot};
+use crate::persist::model::PaymentLedgerEntry;
 use crate::prelude::*;
 use crate::sync::Arc;
+use crate::tx::interactive::{InteractiveFunding, InteractiveInput, InteractiveOutput};
 use crate::tx::tx::{CommitmentInfo, CommitmentInfo2, HTLCInfo2, PreimageMap};
+use crate::util::shachain::CounterpartyRevocationSecrets;
 use crate::wallet::Wallet;
 
-use super::error::{policy_error, ValidationError};
-
-/// A policy checker
-///
-/// Called by Node / Channel as needed.
-pub trait Validator {
-    /// Validate ready channel parameters.
-    /// The holder_shutdown_key_path should be an empty vector if the
-    /// setup.holder_shutdown_script is not set or the address is in
-    /// the allowlist.
-    fn validate_ready_channel(
-        &self,
-        wallet: &Wallet,
-        setup: &ChannelSetup,
-        holder_shutdown_key_path: &Vec<u32>,
-    ) -> Result<(), ValidationError>;
-
-    /// Validate channel value after it is late-filled
-    fn validate_channel_value(&self, setup: &ChannelSetup) -> Result<(), ValidationError>;
-
-    /// Validate an onchain transaction (funding tx, simple sweeps).
-    /// This transaction may fund multiple channels at the same time.
-    ///
-    /// * `channels` the funded channel for each funding output, or
-    ///   None for change outputs
-    /// * `values_sat` - the amount in satoshi per input
-    /// * `opaths` - derivation path for change, one per output,
-    ///   empty for non-change or allowlisted outputs
-    fn validate_onchain_tx(
-        &self,
-        wallet: &Wallet,
-        channels: Vec<Option<Arc<Mutex<ChannelSlot>>>>,
-        tx: &Transaction,
-        values_sat: &Vec<u64>,
-        opaths: &Vec<Vec<u32>>,
-    ) -> Result<(), ValidationError>;
-
-    /// Phase 1 CommitmentInfo
-    fn decode_commitment_tx(
-        &self,
-        keys: &InMemorySigner,
-        setup: &ChannelSetup,
-        is_counterparty: bool,
-        tx: &bitcoin::Transaction,
-        output_witscripts: &Vec<Vec<u8>>,
-    ) -> Result<CommitmentInfo, ValidationError>;
-
-    /// Validate a counterparty commitment
-    fn validate_counterparty_commitment_tx(
-        &self,
-        estate: &EnforcementState,
-        commit_num: u64,
-        commitment_point: &PublicKey,
-        setup: &ChannelSetup,
-        cstate: &ChainState,
-        info2: &CommitmentInfo2,
-    ) -> Result<(), ValidationError>;
-
-    /// Validate a holder commitment
-    fn validate_holder_commitment_tx(
-        &self,
-        estate: &EnforcementState,
-        commit_num: u64,
-        commitment_point: &PublicKey,
-        setup: &ChannelSetup,
-        cstate: &ChainState,
-        info2: &CommitmentInfo2,
-    ) -> Result<(), ValidationError>;
-
-    /// Check a counterparty's revocation of an old state.
-    /// This also makes a note that the counterparty has committed to their
-    /// current commitment transaction.
-    fn validate_counterparty_revocation(
-        &self,
-        state: &EnforcementState,
-        revoke_num: u64,
-        commitment_secret: &SecretKey,
-    ) -> Result<(), ValidationError>;
-
-    /// Phase 1 decoding of 2nd level HTLC tx and validation by recomposition
-    fn decode_and_validate_htlc_tx(
-        &self,
-        is_counterparty: bool,
-        setup: &ChannelSetup,
-        txkeys: &TxCreationKeys,
-        tx: &Transaction,
-        redeemscript: &Script,
-        htlc_amount_sat: u64,
-        output_witscript: &Script,
-    ) -> Result<(u32, HTLCOutputInCommitment, SigHash, SigHashType), ValidationError>;
-
-    /// Phase 2 validation of 2nd level HTLC tx
-    fn validate_htlc_tx(
-        &self,
-        setup: &ChannelSetup,
-        cstate: &ChainState,
-        is_counterparty: bool,
-        htlc: &HTLCOutputInCommitment,
-        feerate_per_kw: u32,
-    ) -> Result<(), ValidationError>;
-
-    /// Phase 1 decoding and recomposition of mutual_close
-    fn decode_and_validate_mutual_close_tx(
-        &self,
-        wallet: &Wallet,
-        setup: &ChannelSetup,
-        state: &EnforcementState,
-        tx: &Transaction,
-        opaths: &Vec<Vec<u32>>,
-    ) -> Result<ClosingTransaction, ValidationError>;
-
-    /// Phase 2 Validatation of mutual_close
-    fn validate_mutual_close_tx(
-        &self,
-        wallet: &Wallet,
-        setup: &ChannelSetup,
-        state: &EnforcementState,
-        to_holder_value_sat: u64,
-        to_counterparty_value_sat: u64,
-        holder_shutdown_script: &Option<Script>,
-        counterparty_shutdown_script: &Option<Script>,
-        holder_wallet_path_hint: &Vec<u32>,
-    ) -> Result<(), ValidationError>;
-
-    /// Validation of delayed sweep transaction
-    fn validate_delayed_sweep(
-        &self,
-        wallet: &Wallet,
-        setup: &ChannelSetup,
-        cstate: &ChainState,
-        tx: &Transaction,
-        input: usize,
-        amount_sat: u64,
-        key_path: &Vec<u32>,
-    ) -> Result<(), ValidationError>;
-
-    /// Validation of counterparty htlc sweep transaction (first level
-    /// commitment htlc outputs)
-    fn validate_counterparty_htlc_sweep(
-        &self,
-        wallet: &Wallet,
-        setup: &ChannelSetup,
-        cstate: &ChainState,
-        tx: &Transaction,
-        redeemscript: &Script,
-        input: usize,
-        amount_sat: u64,
-        key_path: &Vec<u32>,
-    ) -> Result<(), ValidationError>;
-
-    /// Validation of justice sweep transaction
-    fn validate_justice_sweep(
-        &self,
-        wallet: &Wallet,
-        setup: &ChannelSetup,
-        cstate: &ChainState,
-        tx: &Transaction,
-        input: usize,
-        amount_sat: u64,
-        key_path: &Vec<u32>,
-    ) -> Result<(), ValidationError>;
-
-    /// Validation of the payment state for a payment hash.
-    /// This could include a payment routed through us, or a payment we
-    /// are making, or both.  If we are not making a payment, then the incoming
-    /// must be greater or equal to the outgoing.  Otherwise, the incoming
-    /// minus outgoing should be enough to pay for the invoice and routing fees,
-    /// but no larger.
-    fn validate_payment_balance(
-        &self,
-        incoming: u64,
-        outgoing: u64,
-        invoiced_amount_msat: Option<u64>,
-    ) -> Result<(), ValidationError>;
-
-    /// Whether the policy specifies that holder balance should be tracked and
-    /// enforced.
-    fn enforce_balance(&self) -> bool {
-        false
-    }
-
-    /// The minimum initial commitment transaction balance to us, given
-    /// the funding amount.
-    /// The result is in satoshi.
-    fn minimum_initial_balance(&self, holder_value_msat: u64) -> u64;
+use super::error::{policy_error, PolicyTag, ValidationError};
+
+pub use vls_policy::validator::{SignerTypes, Validator, ValidatorFactory};
+
+/// The types of this signer, for the [Validator] and [ValidatorFactory]
+/// traits
+pub struct CoreTypes;
+
+impl SignerTypes for CoreTypes {
+    type Wallet = dyn Wallet;
+    type ChannelRef = Arc<Mutex<ChannelSlot>>;
+    type Channel = Channel;
+    type ChannelId = ChannelId;
+    type ChannelSetup = ChannelSetup;
+    type EnforcementState = EnforcementState;
+    type ChainState = ChainState;
+    type CommitmentInfo = CommitmentInfo;
+    type CommitmentInfo2 = CommitmentInfo2;
+    type InteractiveFunding = InteractiveFunding;
+    type InteractiveInput = InteractiveInput;
+    type InteractiveOutput = InteractiveOutput;
+    type PaymentLedgerEntry = PaymentLedgerEntry;
 }
 
 /// Blockchain state used by the validator
@@ -209,21 +48,14 @@ pub struct ChainState {
     pub current_height: u32,
     /// Zero or the number of confirmation of the funding tx
     pub funding_depth: u32,
+    /// Whether the funding outpoint is watched by the chain tracker
+    pub funding_watched: bool,
     /// Zero or the number of confirmation of a double-spend of the funding tx
     pub funding_double_spent_depth: u32,
     /// Zero or the number of confirmations of a closing tx
     pub closing_depth: u32,
-}
-
-/// A factory for validators
-pub trait ValidatorFactory: Send + Sync {
-    /// Construct a validator
-    fn make_validator(
-        &self,
-        network: Network,
-        node_id: PublicKey,
-        channel_id: Option<ChannelId>,
-    ) -> Arc<dyn Validator>;
+    /// The host clock minus the chain tip timestamp in seconds, if known
+    pub clock_skew_secs: Option<i64>,
 }
 
 /// Enforcement state for a channel
@@ -234,6 +66,7 @@ pub trait ValidatorFactory: Send + Sync {
 #[derive(Clone, Debug)]
 pub struct EnforcementState {
     pub next_holder_commit_num: u64,
+    pub next_holder_revoke_num: u64, // holder commitments below this are revoked
     pub next_counterparty_commit_num: u64,
     pub next_counterparty_revoke_num: u64,
     pub current_counterparty_point: Option<PublicKey>, // next_counterparty_commit_num - 1
@@ -243,6 +76,55 @@ pub struct EnforcementState {
     pub previous_counterparty_commit_info: Option<CommitmentInfo2>,
     pub mutual_close_signed: bool,
     pub initial_holder_value: u64,
+    pub counterparty_secrets: CounterpartyRevocationSecrets, // revealed by revocation
+    pub closing_txid: Option<Txid>, // confirmed commitment, after a force-close
+    pub closing_height: Option<u32>,
+    /// The index of the next MuSig2 nonce, never reused
+    pub next_musig_nonce_index: u64,
+    /// The index of the nonce given out for the next partial signature
+    pub pending_musig_nonce_index: Option<u64>,
+    /// The splice of the funding, until it is locked
+    pub splice: Option<SpliceState>,
+    /// The block height at which the channel was readied or its review was
+    /// last acknowledged, zero if unknown
+    pub review_height: u32,
+    /// The block height of the last counterparty commitment, zero if unknown
+    pub last_activity_height: u32,
+    /// The signature in progress, persisted before signing and cleared
+    /// with the resulting state.  If it is set when the channel is loaded,
+    /// the signer crashed in between and the channel requires operator
+    /// acknowledgement.
+    pub signing_intent: Option<SigningIntent>,
+}
+
+/// A signature that was started but whose resulting state may not have
+/// been persisted
+#[derive(Clone, Debug, PartialEq)]
+pub struct SigningIntent {
+    /// The signing operation
+    pub operation: String,
+    /// The commitment number being signed or validated
+    pub commit_num: u64,
+}
+
+impl fmt::Display for SigningIntent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of commitment {}", self.operation, self.commit_num)
+    }
+}
+
+/// A splice of the channel funding, kept until the splice is locked so
+/// that it can be rolled back on a reorg
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpliceState {
+    /// The funding outpoint before the splice
+    pub previous_outpoint: OutPoint,
+    /// The channel value before the splice
+    pub previous_value_sat: u64,
+    /// The first holder commitment number on the spliced funding
+    pub first_holder_commit_num: u64,
+    /// The first counterparty commitment number on the spliced funding
+    pub first_counterparty_commit_num: u64,
 }
 
 impl EnforcementState {
@@ -253,6 +135,7 @@ impl EnforcementState {
     pub fn new(initial_holder_value: u64) -> EnforcementState {
         EnforcementState {
             next_holder_commit_num: 0,
+            next_holder_revoke_num: 0,
             next_counterparty_commit_num: 0,
             next_counterparty_revoke_num: 0,
             current_counterparty_point: None,
@@ -262,6 +145,15 @@ impl EnforcementState {
             previous_counterparty_commit_info: None,
             mutual_close_signed: false,
             initial_holder_value,
+            counterparty_secrets: CounterpartyRevocationSecrets::new(),
+            closing_txid: None,
+            closing_height: None,
+            next_musig_nonce_index: 0,
+            pending_musig_nonce_index: None,
+            splice: None,
+            review_height: 0,
+            last_activity_height: 0,
+            signing_intent: None,
         }
     }
 
@@ -330,6 +222,80 @@ impl EnforcementState {
         Ok(())
     }
 
+    /// Set next holder revocation number, after the secret for the
+    /// holder commitment `num - 1` was released
+    pub fn set_next_holder_revoke_num(&mut self, num: u64) -> Result<(), ValidationError> {
+        let current = self.next_holder_revoke_num;
+        if num < current {
+            return policy_err!("invalid regression: {} to {}", current, num);
+        }
+        // The current holder commitment can never be revoked
+        if num >= self.next_holder_commit_num {
+            return policy_err!(
+                "{} too large relative to next_holder_commit_num {}",
+                num,
+                self.next_holder_commit_num
+            );
+        }
+        debug!("next_holder_revoke_num {} -> {}", current, num);
+        self.next_holder_revoke_num = num;
+        Ok(())
+    }
+
+    /// Store the secret revealed by the counterparty when revoking
+    /// commitment `num`
+    pub fn set_counterparty_revocation_secret(
+        &mut self,
+        num: u64,
+        secret: &SecretKey,
+    ) -> Result<(), ValidationError> {
+        if self.counterparty_secrets.provide_secret(num, secret).is_err() {
+            return policy_err!(
+                "revocation secret for commit_num {} inconsistent with previous secrets",
+                num
+            );
+        }
+        Ok(())
+    }
+
+    /// The secret revealed by the counterparty when revoking commitment `num`,
+    /// if any
+    pub fn get_counterparty_revocation_secret(&self, num: u64) -> Option<SecretKey> {
+        self.counterparty_secrets.get_secret(num)
+    }
+
+    /// Ensure that a holder commitment was not revoked, so that it is
+    /// safe to sign it for broadcast
+    pub fn check_holder_commit_not_revoked(&self, num: u64) -> Result<(), ValidationError> {
+        // policy-commitment-holder-not-revoked
+        if num < self.next_holder_revoke_num {
+            return tagged_policy_err!(
+                PolicyTag::RevocationOrder,
+                None,
+                [("commit_num", num), ("next_holder_revoke_num", self.next_holder_revoke_num)],
+                "holder commitment {} was revoked, next_holder_revoke_num is {}",
+                num,
+                self.next_holder_revoke_num
+            );
+        }
+        Ok(())
+    }
+
+    /// Ensure that no signature was left incomplete by a crash, since the
+    /// state may then be behind what the counterparty has seen
+    pub fn check_no_signing_intent(&self) -> Result<(), ValidationError> {
+        if let Some(intent) = &self.signing_intent {
+            return tagged_policy_err!(
+                PolicyTag::ChannelReview,
+                None,
+                [("operation", intent.operation), ("commit_num", intent.commit_num)],
+                "incomplete {}, operator acknowledgement required",
+                intent
+            );
+        }
+        Ok(())
+    }
+
     /// Get the current commitment info
     pub fn get_current_holder_commitment_info(
         &self,
@@ -604,6 +570,45 @@ impl EnforcementState {
         summary
     }
 
+    // Whether the current holder or counterparty commitment predates the
+    // splice in progress
+    fn is_pre_splice(&self, is_holder: bool) -> bool {
+        match &self.splice {
+            Some(splice) if is_holder =>
+                self.next_holder_commit_num <= splice.first_holder_commit_num,
+            Some(splice) =>
+                self.next_counterparty_commit_num <= splice.first_counterparty_commit_num,
+            None => false,
+        }
+    }
+
+    // Our claimable balance in a current commitment.  A commitment that
+    // predates the splice is valued against the previous channel value, and
+    // rebased by the change in value, which the splice credits to us.
+    fn current_claimable_balance<T: PreimageMap>(
+        &self,
+        tx: &CommitmentInfo2,
+        pre_splice: bool,
+        preimage_map: &T,
+        channel_setup: &ChannelSetup,
+    ) -> u64 {
+        match &self.splice {
+            Some(splice) if pre_splice => tx
+                .claimable_balance(
+                    preimage_map,
+                    channel_setup.is_outbound,
+                    splice.previous_value_sat,
+                )
+                .saturating_add(channel_setup.channel_value_sat)
+                .saturating_sub(splice.previous_value_sat),
+            _ => tx.claimable_balance(
+                preimage_map,
+                channel_setup.is_outbound,
+                channel_setup.channel_value_sat,
+            ),
+        }
+    }
+
     /// The claimable balance before and after a new commitment tx
     ///
     /// See [`CommitmentInfo2::claimable_balance`]
@@ -624,39 +629,44 @@ impl EnforcementState {
         );
         // Our balance in the holder commitment tx
         let cur_holder_bal = self.current_holder_commit_info.as_ref().map(|tx| {
-            tx.claimable_balance(
+            self.current_claimable_balance(
+                tx,
+                self.is_pre_splice(true),
                 preimage_map,
-                channel_setup.is_outbound,
-                channel_setup.channel_value_sat,
+                channel_setup,
             )
         });
         // Our balance in the counterparty commitment tx
         let cur_cp_bal = self.current_counterparty_commit_info.as_ref().map(|tx| {
-            tx.claimable_balance(
+            self.current_claimable_balance(
+                tx,
+                self.is_pre_splice(false),
                 preimage_map,
-                channel_setup.is_outbound,
-                channel_setup.channel_value_sat,
+                channel_setup,
             )
         });
         // Our overall balance is the lower of the two
         let cur_bal_opt = min_opt(cur_holder_bal, cur_cp_bal);
 
         // Perform balance calculations given the new transaction
-        let new_holder_bal = new_holder_tx.or(self.current_holder_commit_info.as_ref()).map(|tx| {
-            tx.claimable_balance(
-                preimage_map,
-                channel_setup.is_outbound,
-                channel_setup.channel_value_sat,
-            )
-        });
-        let new_cp_bal =
-            new_counterparty_tx.or(self.current_counterparty_commit_info.as_ref()).map(|tx| {
+        let new_holder_bal = new_holder_tx
+            .map(|tx| {
                 tx.claimable_balance(
                     preimage_map,
                     channel_setup.is_outbound,
                     channel_setup.channel_value_sat,
                 )
-            });
+            })
+            .or(cur_holder_bal);
+        let new_cp_bal = new_counterparty_tx
+            .map(|tx| {
+                tx.claimable_balance(
+                    preimage_map,
+                    channel_setup.is_outbound,
+                    channel_setup.channel_value_sat,
+                )
+            })
+            .or(cur_cp_bal);
         let new_bal =
             min_opt(new_holder_bal, new_cp_bal).expect("already checked that we have a new tx");
 
@@ -709,6 +719,37 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn enforcement_state_holder_revoke_num_test() {
+        let mut state = EnforcementState::new(0);
+        let commit_info = make_test_commitment_info();
+
+        // the current holder commitment can't be revoked
+        assert_policy_err!(
+            state.set_next_holder_revoke_num(0),
+            "set_next_holder_revoke_num: 0 too large relative to next_holder_commit_num 0"
+        );
+
+        assert_validation_ok!(state.set_next_holder_commit_num(1, commit_info.clone()));
+        assert_validation_ok!(state.set_next_holder_commit_num(2, commit_info.clone()));
+        assert_validation_ok!(state.check_holder_commit_not_revoked(0));
+        assert_validation_ok!(state.set_next_holder_revoke_num(1));
+        // retry is ok
+        assert_validation_ok!(state.set_next_holder_revoke_num(1));
+        assert_policy_err!(
+            state.check_holder_commit_not_revoked(0),
+            "check_holder_commit_not_revoked: \
+             holder commitment 0 was revoked, next_holder_revoke_num is 1"
+        );
+        assert_validation_ok!(state.check_holder_commit_not_revoked(1));
+
+        // can't go backwards
+        assert_policy_err!(
+            state.set_next_holder_revoke_num(0),
+            "set_next_holder_revoke_num: invalid regression: 1 to 0"
+        );
+    }
+
     #[test]
     fn enforcement_state_previous_counterparty_point_test() {
         let mut state = EnforcementState::new(0);
@@ -7,7 +7,7 @@ mod tests {
     use lightning::ln::chan_utils::ChannelPublicKeys;
     use test_log::test;
 
-    use crate::channel::channel_nonce_to_id;
+    use crate::channel::{channel_nonce_to_id, CommitmentType};
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
 
@@ -216,6 +216,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn ready_channel_taproot_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let channel_nonce = "nonce1".as_bytes().to_vec();
+        let channel_id = channel_nonce_to_id(&channel_nonce);
+        node.new_channel(Some(channel_id), Some(channel_nonce), &node).expect("new_channel");
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::Taproot;
+        assert_failed_precondition_err!(
+            node.ready_channel(channel_id, None, setup.clone(), &vec![]),
+            "policy failure: validate_ready_channel: taproot channels are not supported yet"
+        );
+    }
+
     #[test]
     fn ready_channel_holder_shutdown_script_in_allowlist() {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
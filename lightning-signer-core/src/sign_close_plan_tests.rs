@@ -0,0 +1,246 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::hex::{FromHex, ToHex};
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Secp256k1, Signature};
+    use bitcoin::{self, Address, Network, Script, Txid};
+    use lightning::ln::chan_utils::{make_funding_redeemscript, ClosingTransaction};
+
+    use test_log::test;
+
+    use crate::channel::{channel_nonce_to_id, ChannelId, TypedSignature};
+    use crate::close_plan::PlannedClose;
+    use crate::node::Node;
+    use crate::policy::simple_validator::{make_simple_policy, SimpleValidatorFactory};
+    use crate::sync::Arc;
+    use crate::tx::tx::CommitmentInfo2;
+    use crate::util::key_utils::*;
+    use crate::util::status::Code;
+    use crate::util::test_utils::*;
+
+    const HOLDER_BALANCE_SAT: u64 = 2_000_000;
+    const COMMITMENT_FEE_SAT: u64 = 2000;
+    const HOLDER_WALLET_PATH: [u32; 1] = [7];
+
+    // A node with two outbound channels after shutdown
+    fn setup_close_plan() -> (Arc<Node>, Vec<ChannelId>) {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+
+        let channel_nonce = "nonce2".as_bytes().to_vec();
+        let channel_id2 = channel_nonce_to_id(&channel_nonce);
+        let mut setup2 = setup.clone();
+        setup2.funding_outpoint.txid = Txid::from_slice(&[3u8; 32]).unwrap();
+        node.new_channel(Some(channel_id2), Some(channel_nonce), &node).expect("new_channel");
+        node.ready_channel(channel_id2, None, setup2, &vec![]).expect("ready channel");
+
+        let to_holder_value_sat = HOLDER_BALANCE_SAT - COMMITMENT_FEE_SAT;
+        let to_counterparty_value_sat = setup.channel_value_sat - HOLDER_BALANCE_SAT;
+        for channel_id in [channel_id, channel_id2].iter() {
+            node.with_ready_channel(channel_id, |chan| {
+                let estate = &mut chan.enforcement_state;
+                estate.current_holder_commit_info = Some(CommitmentInfo2 {
+                    is_counterparty_broadcaster: false,
+                    to_countersigner_pubkey: make_test_pubkey(100),
+                    to_countersigner_value_sat: to_counterparty_value_sat,
+                    revocation_pubkey: make_test_pubkey(101),
+                    to_broadcaster_delayed_pubkey: make_test_pubkey(102),
+                    to_broadcaster_value_sat: to_holder_value_sat,
+                    to_self_delay: setup.counterparty_selected_contest_delay,
+                    offered_htlcs: vec![],
+                    received_htlcs: vec![],
+                    feerate_per_kw: 7500,
+                });
+                estate.current_counterparty_commit_info = Some(CommitmentInfo2 {
+                    is_counterparty_broadcaster: true,
+                    to_countersigner_pubkey: make_test_pubkey(110),
+                    to_countersigner_value_sat: to_holder_value_sat,
+                    revocation_pubkey: make_test_pubkey(111),
+                    to_broadcaster_delayed_pubkey: make_test_pubkey(112),
+                    to_broadcaster_value_sat: to_counterparty_value_sat,
+                    to_self_delay: setup.holder_selected_contest_delay,
+                    offered_htlcs: vec![],
+                    received_htlcs: vec![],
+                    feerate_per_kw: 7500,
+                });
+                Ok(())
+            })
+            .expect("state setup");
+        }
+        (node, vec![channel_id, channel_id2])
+    }
+
+    fn holder_script(node: &Node) -> Script {
+        let secp_ctx = Secp256k1::signing_only();
+        Address::p2wpkh(
+            &node.get_wallet_pubkey(&secp_ctx, &HOLDER_WALLET_PATH.to_vec()).unwrap(),
+            Network::Testnet,
+        )
+        .expect("Address")
+        .script_pubkey()
+    }
+
+    fn counterparty_script() -> Script {
+        Script::from_hex("0014be56df7de366ad8ee9ccdad54e9a9993e99ef565").expect("script_pubkey")
+    }
+
+    // A close with the given closing fee, paid by the holder
+    fn planned_close(channel_id: ChannelId, holder_script: Script, fee_sat: u64) -> PlannedClose {
+        PlannedClose {
+            channel_id,
+            to_holder_value_sat: HOLDER_BALANCE_SAT - fee_sat,
+            to_counterparty_value_sat: make_test_channel_setup().channel_value_sat
+                - HOLDER_BALANCE_SAT,
+            holder_script: Some(holder_script),
+            counterparty_script: Some(counterparty_script()),
+            holder_wallet_path_hint: HOLDER_WALLET_PATH.to_vec(),
+            signed: false,
+        }
+    }
+
+    fn check_close_signature(node: &Node, close: &PlannedClose, sig: Signature) {
+        let setup = node.with_ready_channel(&close.channel_id, |chan| Ok(chan.setup.clone()));
+        let setup = setup.unwrap();
+        let closing_tx = ClosingTransaction::new(
+            close.to_holder_value_sat,
+            close.to_counterparty_value_sat,
+            close.holder_script.clone().unwrap(),
+            close.counterparty_script.clone().unwrap(),
+            setup.funding_outpoint,
+        );
+        let funding_pubkey = get_channel_funding_pubkey(node, &close.channel_id);
+        let redeemscript = make_funding_redeemscript(
+            &funding_pubkey,
+            &make_test_counterparty_points().funding_pubkey,
+        );
+        check_signature(
+            &closing_tx.trust().built_transaction(),
+            0,
+            TypedSignature::all(sig),
+            &funding_pubkey,
+            setup.channel_value_sat,
+            &redeemscript,
+        );
+    }
+
+    fn is_close_signed(node: &Node, channel_id: &ChannelId) -> bool {
+        node.with_ready_channel(channel_id, |chan| Ok(chan.enforcement_state.mutual_close_signed))
+            .unwrap()
+    }
+
+    #[test]
+    fn sign_close_plan_success() {
+        let (node, channel_ids) = setup_close_plan();
+        let closes: Vec<PlannedClose> =
+            channel_ids.iter().map(|id| planned_close(*id, holder_script(&node), 2000)).collect();
+        let plan = node.plan_mutual_closes(closes.clone()).unwrap();
+        assert_eq!(plan.fee_sat, 4000);
+        assert_eq!(plan.non_wallet_sat, 0);
+        assert_eq!(node.get_close_plan(), Some(plan.clone()));
+
+        // only one plan at a time
+        assert_failed_precondition_err!(
+            node.plan_mutual_closes(closes.clone()),
+            format!("close plan {} is in progress", plan.id.to_hex())
+        );
+
+        // the operator approves the plan before it is signed
+        assert_failed_precondition_err!(
+            node.sign_close_plan(),
+            format!("close plan {} is not approved", plan.id.to_hex())
+        );
+        assert_invalid_argument_err!(
+            node.approve_close_plan(&[0; 32]),
+            format!("close plan {} is not in progress", [0u8; 32].to_hex())
+        );
+        node.approve_close_plan(&plan.id).unwrap();
+
+        let sigs = node.sign_close_plan().unwrap();
+        assert_eq!(sigs.len(), 2);
+        for (close, (channel_id, sig)) in closes.iter().zip(sigs.iter()) {
+            assert_eq!(*channel_id, close.channel_id);
+            check_close_signature(&node, close, *sig);
+            assert!(is_close_signed(&node, channel_id));
+        }
+        assert!(node.get_close_plan().unwrap().is_complete());
+
+        // signing again, such as after a restart, gives the same signatures
+        assert_eq!(node.sign_close_plan().unwrap(), sigs);
+
+        node.cancel_close_plan().unwrap();
+        assert!(node.get_close_plan().is_none());
+    }
+
+    // policy-close-plan-fee-range
+    #[test]
+    fn sign_close_plan_fees_too_large() {
+        let (node, channel_ids) = setup_close_plan();
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.max_close_plan_fee_sat = 3000;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+
+        let closes: Vec<PlannedClose> =
+            channel_ids.iter().map(|id| planned_close(*id, holder_script(&node), 2000)).collect();
+        assert_failed_precondition_err!(
+            node.plan_mutual_closes(closes.clone()),
+            "policy failure: validate_close_plan: close plan fees above maximum: 4000 > 3000"
+        );
+        assert!(node.get_close_plan().is_none());
+        assert!(!is_close_signed(&node, &channel_ids[0]));
+    }
+
+    // policy-close-plan-velocity
+    #[test]
+    fn sign_close_plan_policy_tightened() {
+        let (node, channel_ids) = setup_close_plan();
+        node.add_allowlist(&vec![format!(
+            "{}",
+            Address::from_script(&counterparty_script(), Network::Testnet).unwrap()
+        )])
+        .unwrap();
+        let closes = vec![
+            planned_close(channel_ids[0], holder_script(&node), 2000),
+            planned_close(channel_ids[1], counterparty_script(), 2000),
+        ];
+        let plan = node.plan_mutual_closes(closes).unwrap();
+        assert_eq!(plan.non_wallet_sat, HOLDER_BALANCE_SAT - 2000);
+        node.approve_close_plan(&plan.id).unwrap();
+
+        // the plan is validated again before any close is signed
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.max_close_plan_non_wallet_sat = 1_000_000;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        assert_failed_precondition_err!(
+            node.sign_close_plan(),
+            "policy failure: validate_close_plan: \
+             close plan value to non-wallet destinations above maximum: 1998000 > 1000000"
+        );
+        assert!(!is_close_signed(&node, &channel_ids[0]));
+        assert!(!node.get_close_plan().unwrap().closes[0].signed);
+    }
+
+    // policy-mutual-destination-allowlisted
+    #[test]
+    fn sign_close_plan_close_invalid() {
+        let (node, channel_ids) = setup_close_plan();
+        let closes = vec![
+            planned_close(channel_ids[0], holder_script(&node), 2000),
+            planned_close(channel_ids[1], counterparty_script(), 2000),
+        ];
+        assert_failed_precondition_err!(
+            node.plan_mutual_closes(closes.clone()),
+            "policy failure: validate_mutual_close_tx: holder output not to wallet or in allowlist"
+        );
+
+        let closes = vec![
+            planned_close(channel_ids[0], holder_script(&node), 2000),
+            planned_close(channel_ids[0], holder_script(&node), 2000),
+        ];
+        assert_invalid_argument_err!(
+            node.plan_mutual_closes(closes.clone()),
+            format!("channel {} is closed more than once", channel_ids[0])
+        );
+        assert!(node.get_close_plan().is_none());
+    }
+}
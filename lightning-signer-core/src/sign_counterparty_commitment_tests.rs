@@ -3,23 +3,35 @@ mod tests {
     use bitcoin;
     use bitcoin::hashes::hex::ToHex;
     use bitcoin::hashes::Hash;
-    use bitcoin::secp256k1::PublicKey;
+    use bitcoin::secp256k1::{schnorrsig, Message, PublicKey, Secp256k1};
+    use bitcoin::util::bip143::SigHashCache;
     use bitcoin::util::psbt::serialize::Serialize;
+    use bitcoin::{Network, Script, SigHashType, Transaction, TxIn, TxOut};
     use lightning::chain::keysinterface::BaseSign;
     use lightning::ln::chan_utils::{
-        make_funding_redeemscript, BuiltCommitmentTransaction, TxCreationKeys,
+        build_htlc_transaction, get_htlc_redeemscript, make_funding_redeemscript,
+        BuiltCommitmentTransaction, TxCreationKeys,
     };
     use lightning::ln::PaymentHash;
     use test_log::test;
 
-    use crate::channel::{Channel, ChannelSetup, CommitmentType, TypedSignature};
+    use crate::channel::{
+        channel_nonce_to_id, Channel, ChannelSetup, CommitmentType, TypedSignature,
+    };
+    use crate::policy::null_validator::NullValidatorFactory;
+    use crate::policy::simple_validator::{make_simple_policy, SimpleValidatorFactory};
     use crate::policy::validator::{ChainState, EnforcementState};
     use crate::tx::script::get_to_countersignatory_with_anchors_redeemscript;
     use crate::tx::tx::HTLCInfo2;
-    use crate::util::crypto_utils::payload_for_p2wpkh;
+    use crate::util::crypto_utils::{derive_public_key, payload_for_p2wpkh};
     use crate::util::key_utils::*;
+    use crate::util::musig2::{
+        nonce_agg, nonce_gen, partial_sig_agg, partial_sig_verify, partial_sign, KeyAggContext,
+    };
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
+    use crate::util::transaction_utils::taproot_key_spend_sighash;
+    use crate::Arc;
 
     use paste::paste;
 
@@ -234,19 +246,211 @@ mod tests {
     #[test]
     fn sign_counterparty_commitment_tx_phase2_static_test() {
         let setup = make_test_channel_setup();
-        sign_counterparty_commitment_tx_phase2_test(&setup);
+        sign_counterparty_commitment_tx_phase2_test(&setup, false);
     }
 
     #[test]
     fn sign_counterparty_commitment_tx_phase2_legacy_test() {
         let mut setup = make_test_channel_setup();
         setup.commitment_type = CommitmentType::Legacy;
-        sign_counterparty_commitment_tx_phase2_test(&setup);
+        sign_counterparty_commitment_tx_phase2_test(&setup, false);
+    }
+
+    #[test]
+    fn sign_counterparty_commitment_tx_phase2_low_r_test() {
+        let setup = make_test_channel_setup();
+        sign_counterparty_commitment_tx_phase2_test(&setup, true);
+    }
+
+    #[test]
+    fn sign_counterparty_commitment_tx_musig_test() {
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::Taproot;
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        // taproot commitments are not validated yet, so the simple policy
+        // refuses taproot channels
+        node.set_validator_factory(Arc::new(NullValidatorFactory {}));
+        let channel_nonce = "nonce1".as_bytes().to_vec();
+        let channel_id = channel_nonce_to_id(&channel_nonce);
+        node.new_channel(Some(channel_id), Some(channel_nonce), &node).expect("new_channel");
+        node.ready_channel(channel_id, None, setup.clone(), &vec![]).expect("ready channel");
+
+        let secp_ctx = Secp256k1::new();
+        let counterparty_key = make_test_privkey(104);
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: setup.funding_outpoint,
+                script_sig: Script::new(),
+                sequence: 0x8000_0000,
+                witness: vec![],
+            }],
+            output: vec![TxOut { value: 2_999_000, script_pubkey: Script::new() }],
+        };
+
+        node.with_ready_channel(&channel_id, |chan| {
+            assert_invalid_argument_err!(
+                chan.sign_counterparty_commitment_tx_phase2(
+                    &make_test_pubkey(10),
+                    23,
+                    0,
+                    1_000_000,
+                    1_999_000,
+                    vec![],
+                    vec![],
+                ),
+                "taproot channels are signed with MuSig2"
+            );
+
+            let nonce = chan.get_musig_nonce()?;
+            // the pending nonce is returned until it is used
+            assert_eq!(chan.get_musig_nonce()?, nonce);
+            assert_eq!(chan.enforcement_state.next_musig_nonce_index, 1);
+
+            let funding_pubkey = chan.keys.pubkeys().funding_pubkey;
+            let mut pubkeys =
+                vec![funding_pubkey, PublicKey::from_secret_key(&secp_ctx, &counterparty_key)];
+            pubkeys.sort_by_key(|pk| pk.serialize());
+            let mut ctx = KeyAggContext::new(&secp_ctx, &pubkeys).unwrap();
+            ctx.apply_bip86_tweak(&secp_ctx).unwrap();
+            let funding_output = TxOut {
+                value: setup.channel_value_sat,
+                script_pubkey: chan.taproot_funding_script()?,
+            };
+            assert_eq!(&funding_output.script_pubkey.as_bytes()[2..], &ctx.xonly_pubkey()[..]);
+            let sighash = taproot_key_spend_sighash(&tx, 0, &[funding_output]).unwrap();
+
+            let (counterparty_secnonce, counterparty_nonce) = nonce_gen(
+                &secp_ctx,
+                &[7; 32],
+                &counterparty_key,
+                Some(&ctx.xonly_pubkey()),
+                None,
+                &[],
+            )
+            .unwrap();
+            let psig = chan.sign_counterparty_commitment_tx_musig(&tx, &counterparty_nonce)?;
+            let aggnonce = nonce_agg(&[nonce, counterparty_nonce]).unwrap();
+            assert!(partial_sig_verify(
+                &secp_ctx,
+                &psig,
+                &nonce,
+                &funding_pubkey,
+                &ctx,
+                &aggnonce,
+                &sighash
+            )
+            .unwrap());
+            let counterparty_psig = partial_sign(
+                &secp_ctx,
+                counterparty_secnonce,
+                &counterparty_key,
+                &ctx,
+                &aggnonce,
+                &sighash,
+            )
+            .unwrap();
+            let sig =
+                partial_sig_agg(&secp_ctx, &[psig, counterparty_psig], &ctx, &aggnonce, &sighash)
+                    .unwrap();
+            secp_ctx
+                .schnorrsig_verify(
+                    &schnorrsig::Signature::from_slice(&sig).unwrap(),
+                    &Message::from_slice(&sighash).unwrap(),
+                    &schnorrsig::PublicKey::from_slice(&ctx.xonly_pubkey()).unwrap(),
+                )
+                .unwrap();
+
+            // the nonce was consumed
+            assert_failed_precondition_err!(
+                chan.sign_counterparty_commitment_tx_musig(&tx, &counterparty_nonce),
+                "no pending musig2 nonce"
+            );
+            assert_ne!(chan.get_musig_nonce()?, nonce);
+            assert_eq!(chan.enforcement_state.next_musig_nonce_index, 2);
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn sign_counterparty_commitment_tx_zero_fee_htlc_test() {
+        let (node, setup, channel_id, offered_htlcs, received_htlcs) =
+            sign_commitment_tx_with_mutators_setup(CommitmentType::AnchorsZeroFeeHtlc);
+        let remote_percommitment_point = make_test_pubkey(10);
+        let commit_num = 23;
+        let feerate_per_kw = 1000;
+
+        assert_status_ok!(node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state
+                .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
+            chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+            let (_, htlc_sigs) = chan.sign_counterparty_commitment_tx_phase2(
+                &remote_percommitment_point,
+                commit_num,
+                feerate_per_kw,
+                1_000_000,
+                1_979_997,
+                offered_htlcs.clone(),
+                received_htlcs.clone(),
+            )?;
+
+            let htlcs = Channel::htlcs_info2_to_oic(offered_htlcs.clone(), received_htlcs.clone());
+            let commitment_tx = chan.make_counterparty_commitment_tx(
+                &remote_percommitment_point,
+                commit_num,
+                feerate_per_kw,
+                1_000_000,
+                1_979_997,
+                htlcs,
+            );
+            let trusted_tx = commitment_tx.trust();
+            let keys = trusted_tx.keys();
+            let secp_ctx = Secp256k1::new();
+            let htlc_pubkey = derive_public_key(
+                &secp_ctx,
+                &remote_percommitment_point,
+                &chan.keys.pubkeys().htlc_basepoint,
+            )
+            .unwrap();
+            assert_eq!(htlc_sigs.len(), commitment_tx.htlcs().len());
+            for (htlc, sig) in commitment_tx.htlcs().iter().zip(htlc_sigs.iter()) {
+                let htlc_redeemscript = get_htlc_redeemscript(htlc, true, keys);
+                let sighash = |htlc_feerate_per_kw| {
+                    let htlc_tx = build_htlc_transaction(
+                        &trusted_tx.txid(),
+                        htlc_feerate_per_kw,
+                        setup.holder_selected_contest_delay,
+                        htlc,
+                        true,
+                        &keys.broadcaster_delayed_payment_key,
+                        &keys.revocation_key,
+                    );
+                    Message::from_slice(
+                        &SigHashCache::new(&htlc_tx).signature_hash(
+                            0,
+                            &htlc_redeemscript,
+                            htlc.amount_msat / 1000,
+                            SigHashType::SinglePlusAnyoneCanPay,
+                        )[..],
+                    )
+                    .unwrap()
+                };
+                // the HTLC transaction pays no fee
+                assert!(secp_ctx.verify(&sighash(0), sig, &htlc_pubkey).is_ok());
+                assert!(secp_ctx.verify(&sighash(feerate_per_kw), sig, &htlc_pubkey).is_err());
+            }
+            Ok(())
+        }));
     }
 
-    fn sign_counterparty_commitment_tx_phase2_test(setup: &ChannelSetup) {
+    fn sign_counterparty_commitment_tx_phase2_test(setup: &ChannelSetup, grind_low_r: bool) {
         let (node, channel_id) =
             init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.grind_low_r = grind_low_r;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
 
         let remote_percommitment_point = make_test_pubkey(10);
         let funding_pubkey = get_channel_funding_pubkey(&node, &channel_id);
@@ -297,6 +501,9 @@ mod tests {
             .expect("sign");
         let channel_funding_redeemscript =
             make_funding_redeemscript(&funding_pubkey, &setup.counterparty_points.funding_pubkey);
+        if grind_low_r {
+            assert!(signature.serialize_der().len() <= 70);
+        }
 
         check_signature(
             &tx,
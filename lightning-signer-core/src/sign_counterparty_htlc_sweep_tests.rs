@@ -262,6 +262,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sign_counterparty_offered_htlc_sweep_with_wrong_commitment_point() {
+        assert_failed_precondition_err!(
+            sign_counterparty_htlc_sweep_with_mutators(
+                OfferedHTLC,
+                |node_ctx| { make_test_wallet_dest(node_ctx, 19, P2shP2wpkh) },
+                |_chan,
+                 _cstate,
+                 _tx,
+                 _input,
+                 remote_per_commitment_point,
+                 _redeemscript,
+                 _amount_sat| {
+                    *remote_per_commitment_point = make_test_pubkey(11);
+                },
+            ),
+            "policy failure: sign_counterparty_htlc_sweep: redeemscript mismatch"
+        );
+    }
+
     // policy-sweep-version
     #[test]
     fn sign_counterparty_offered_htlc_sweep_with_bad_version() {
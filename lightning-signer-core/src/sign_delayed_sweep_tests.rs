@@ -1,12 +1,13 @@
 #[cfg(test)]
 mod tests {
-    use bitcoin::{self, OutPoint, Script, Transaction, TxIn, TxOut, Txid};
+    use bitcoin::{self, Network, OutPoint, Script, Transaction, TxIn, TxOut, Txid};
     use lightning::ln::chan_utils::get_revokeable_redeemscript;
     use test_log::test;
 
     use crate::channel::{Channel, ChannelBase, TypedSignature};
-    use crate::node::SpendType::{P2shP2wpkh, P2wpkh};
+    use crate::node::SpendType::{P2shP2wpkh, P2tr, P2wpkh};
     use crate::policy::validator::ChainState;
+    use crate::util::key_utils::make_test_pubkey;
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
 
@@ -156,6 +157,15 @@ mod tests {
         ));
     }
 
+    // policy-sweep-destination-allowlisted
+    #[test]
+    fn sign_delayed_to_local_wallet_p2tr_success() {
+        assert_status_ok!(sign_delayed_sweep_with_mutators(
+            |node_ctx| { make_test_wallet_dest(node_ctx, 19, P2tr) },
+            |_chan, _cstate, _tx, _input, _commit_num, _redeemscript, _amount_sat| {},
+        ));
+    }
+
     // policy-sweep-destination-allowlisted
     #[test]
     fn sign_delayed_to_local_allowlist_p2wpkh_success() {
@@ -186,6 +196,78 @@ mod tests {
         ));
     }
 
+    // policy-sweep-destination-allowlisted
+    #[test]
+    fn sign_delayed_to_local_allowlist_p2tr_success() {
+        assert_status_ok!(sign_delayed_sweep_with_mutators(
+            |node_ctx| { make_test_nonwallet_dest(node_ctx, 3, P2tr) },
+            |chan, _cstate, _tx, _input, _commit_num, _redeemscript, _amount_sat| {
+                let address = make_test_taproot_address(3, Network::Testnet);
+                chan.node
+                    .upgrade()
+                    .unwrap()
+                    .add_allowlist(&vec![address.to_string()])
+                    .expect("add_allowlist");
+            },
+        ));
+    }
+
+    // policy-sweep-destination-cold-storage
+    #[test]
+    fn sign_delayed_to_local_cold_storage_success() {
+        assert_status_ok!(sign_delayed_sweep_with_mutators(
+            |node_ctx| { make_test_nonwallet_dest(node_ctx, 3, P2wpkh) },
+            |chan, _cstate, _tx, _input, _commit_num, _redeemscript, _amount_sat| {
+                chan.node
+                    .upgrade()
+                    .unwrap()
+                    .add_allowlist(&vec![
+                        "cold:1000000:tb1qg975h6gdx5mryeac72h6lj2nzygugxhyk6dnhr".to_string()
+                    ])
+                    .expect("add_allowlist");
+            },
+        ));
+    }
+
+    // policy-sweep-destination-cold-storage
+    #[test]
+    fn sign_delayed_to_local_wallet_below_cold_storage_threshold() {
+        assert_status_ok!(sign_delayed_sweep_with_mutators(
+            |node_ctx| { make_test_wallet_dest(node_ctx, 19, P2wpkh) },
+            |chan, _cstate, _tx, _input, _commit_num, _redeemscript, _amount_sat| {
+                chan.node
+                    .upgrade()
+                    .unwrap()
+                    .add_allowlist(&vec![
+                        "cold:2000000:tb1qg975h6gdx5mryeac72h6lj2nzygugxhyk6dnhr".to_string()
+                    ])
+                    .expect("add_allowlist");
+            },
+        ));
+    }
+
+    // policy-sweep-destination-cold-storage
+    #[test]
+    fn sign_delayed_to_local_wallet_above_cold_storage_threshold() {
+        assert_failed_precondition_err!(
+            sign_delayed_sweep_with_mutators(
+                |node_ctx| { make_test_wallet_dest(node_ctx, 19, P2wpkh) },
+                |chan, _cstate, _tx, _input, _commit_num, _redeemscript, _amount_sat| {
+                    chan.node
+                        .upgrade()
+                        .unwrap()
+                        .add_allowlist(&vec![
+                            "cold:1000000:tb1qg975h6gdx5mryeac72h6lj2nzygugxhyk6dnhr".to_string(),
+                        ])
+                        .expect("add_allowlist");
+                },
+            ),
+            "policy failure: validate_delayed_sweep: validate_sweep: \
+             sweep of 1979997 sat is above the cold storage threshold 1000000 \
+             but is not to cold storage"
+        );
+    }
+
     // policy-sweep-destination-allowlisted
     #[test]
     fn sign_delayed_to_local_with_unknown_dest() {
@@ -228,6 +310,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sign_delayed_sweep_with_bad_redeemscript() {
+        assert_failed_precondition_err!(
+            sign_delayed_sweep_with_mutators(
+                |node_ctx| { make_test_wallet_dest(node_ctx, 19, P2wpkh) },
+                |_chan, _cstate, _tx, _input, _commit_num, redeemscript, _amount_sat| {
+                    *redeemscript = get_revokeable_redeemscript(
+                        &make_test_pubkey(42),
+                        6,
+                        &make_test_pubkey(43),
+                    );
+                },
+            ),
+            "policy failure: sign_delayed_sweep: redeemscript mismatch"
+        );
+    }
+
     // policy-sweep-version
     #[test]
     fn sign_delayed_sweep_with_bad_version() {
@@ -1,9 +1,11 @@
 #[cfg(test)]
 mod tests {
     use bitcoin::hashes::hex::ToHex;
-    use bitcoin::{self, Script, Transaction};
+    use bitcoin::hashes::Hash;
+    use bitcoin::{self, OutPoint, Script, Transaction, TxIn, TxOut, Txid};
     use lightning::ln::chan_utils::{
-        build_htlc_transaction, get_htlc_redeemscript, make_funding_redeemscript,
+        build_htlc_transaction, get_anchor_redeemscript, get_htlc_redeemscript,
+        make_funding_redeemscript,
     };
 
     use test_log::test;
@@ -93,6 +95,115 @@ mod tests {
         );
     }
 
+    // policy-commitment-holder-not-revoked
+    #[test]
+    fn redundant_revoked_commitment_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+
+        let commit_num = 23;
+        let res = node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(commit_num + 2);
+            chan.enforcement_state.set_next_holder_revoke_num(commit_num + 1)?;
+            chan.sign_holder_commitment_tx_phase2_redundant(
+                commit_num,
+                0, // feerate not used
+                1_000_000,
+                1_999_000,
+                vec![],
+                vec![],
+            )
+        });
+        assert_failed_precondition_err!(
+            res,
+            "policy failure: check_holder_commit_not_revoked: \
+             holder commitment 23 was revoked, next_holder_revoke_num is 24"
+        );
+    }
+
+    #[test]
+    fn get_revocation_revokes_commitment_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+
+        let commit_num = 23;
+        let res = node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(commit_num + 2);
+            chan.get_revocation(commit_num)?;
+            assert_eq!(chan.enforcement_state.next_holder_revoke_num, commit_num + 1);
+            // an older secret doesn't move the revocation back
+            chan.get_revocation(commit_num - 1)?;
+            assert_eq!(chan.enforcement_state.next_holder_revoke_num, commit_num + 1);
+            chan.sign_holder_commitment_tx_phase2_redundant(
+                commit_num,
+                0, // feerate not used
+                1_000_000,
+                1_999_000,
+                vec![],
+                vec![],
+            )
+        });
+        assert_failed_precondition_err!(
+            res,
+            "policy failure: check_holder_commit_not_revoked: \
+             holder commitment 23 was revoked, next_holder_revoke_num is 24"
+        );
+    }
+
+    #[test]
+    fn sign_holder_anchor_input_test() {
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::Anchors;
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        let funding_pubkey = get_channel_funding_pubkey(&node, &channel_id);
+
+        // A child spending the anchor and a wallet input
+        let make_input = |b: u8| TxIn {
+            previous_output: OutPoint { txid: Txid::from_slice(&[b; 32]).unwrap(), vout: 0 },
+            script_sig: Script::new(),
+            sequence: 0xffff_ffff,
+            witness: vec![],
+        };
+        let anchor_tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![make_input(2), make_input(3)],
+            output: vec![TxOut { value: 10_000, script_pubkey: Script::new() }],
+        };
+
+        let sig = node
+            .with_ready_channel(&channel_id, |chan| chan.sign_holder_anchor_input(&anchor_tx, 0))
+            .expect("sign");
+        check_signature(
+            &anchor_tx,
+            0,
+            TypedSignature::all(sig),
+            &funding_pubkey,
+            330,
+            &get_anchor_redeemscript(&funding_pubkey),
+        );
+
+        let res = node
+            .with_ready_channel(&channel_id, |chan| chan.sign_holder_anchor_input(&anchor_tx, 2));
+        assert_invalid_argument_err!(res, "sign_holder_anchor_input: bad input index: 2 >= 2");
+
+        // Not possible without anchors
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let res = node
+            .with_ready_channel(&channel_id, |chan| chan.sign_holder_anchor_input(&anchor_tx, 0));
+        assert_failed_precondition_err!(
+            res,
+            format!(
+                "policy failure: sign_holder_anchor_input: channel {} does not have anchors",
+                channel_id
+            )
+        );
+    }
+
     const HOLD_COMMIT_NUM: u64 = 23;
 
     #[allow(dead_code)]
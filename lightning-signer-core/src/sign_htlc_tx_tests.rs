@@ -2,7 +2,7 @@
 mod tests {
     use bitcoin::hashes::hex::ToHex;
     use bitcoin::hashes::Hash;
-    use bitcoin::{self, Transaction};
+    use bitcoin::{self, OutPoint, Script, Transaction, TxIn, TxOut};
     use lightning::ln::chan_utils::{
         build_htlc_transaction, get_htlc_redeemscript, get_revokeable_redeemscript,
         ChannelTransactionParameters, HTLCOutputInCommitment, TxCreationKeys,
@@ -21,17 +21,99 @@ mod tests {
     #[test]
     fn sign_local_htlc_tx_static_test() {
         let setup = make_test_channel_setup();
-        sign_local_htlc_tx_test(&setup);
+        sign_local_htlc_tx_test(&setup, false);
     }
 
     #[test]
     fn sign_local_htlc_tx_legacy_test() {
         let mut setup = make_test_channel_setup();
         setup.commitment_type = CommitmentType::Legacy;
-        sign_local_htlc_tx_test(&setup);
+        sign_local_htlc_tx_test(&setup, false);
     }
 
-    fn sign_local_htlc_tx_test(setup: &ChannelSetup) {
+    #[test]
+    fn sign_local_htlc_tx_anchors_test() {
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::Anchors;
+        sign_local_htlc_tx_test(&setup, false);
+    }
+
+    #[test]
+    fn sign_local_htlc_tx_anchors_fee_bump_test() {
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::Anchors;
+        sign_local_htlc_tx_test(&setup, true);
+    }
+
+    #[test]
+    fn sign_local_htlc_tx_zero_fee_anchors_test() {
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::AnchorsZeroFeeHtlc;
+        sign_local_htlc_tx_test(&setup, false);
+    }
+
+    #[test]
+    fn sign_local_htlc_tx_zero_fee_anchors_fee_bump_test() {
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::AnchorsZeroFeeHtlc;
+        sign_local_htlc_tx_test(&setup, true);
+    }
+
+    // policy-htlc-fee-range
+    #[test]
+    fn sign_local_htlc_tx_zero_fee_anchors_with_fee_test() {
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::AnchorsZeroFeeHtlc;
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+
+        let htlc_amount_sat = 10 * 1000;
+        let htlc = HTLCOutputInCommitment {
+            offered: true,
+            amount_msat: htlc_amount_sat * 1000,
+            cltv_expiry: 2 << 16,
+            payment_hash: PaymentHash([1; 32]),
+            transaction_output_index: Some(0),
+        };
+        let commitment_txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
+
+        assert_failed_precondition_err!(
+            node.with_ready_channel(&channel_id, |chan| {
+                chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
+                let per_commitment_point = chan.get_per_commitment_point(1)?;
+                let txkeys = chan.make_holder_tx_keys(&per_commitment_point)?;
+                let to_self_delay = setup.counterparty_selected_contest_delay;
+                // the HTLC tx pays a fee out of the HTLC value
+                let htlc_tx = build_htlc_transaction(
+                    &commitment_txid,
+                    1000,
+                    to_self_delay,
+                    &htlc,
+                    true,
+                    &txkeys.broadcaster_delayed_payment_key,
+                    &txkeys.revocation_key,
+                );
+                let htlc_redeemscript = get_htlc_redeemscript(&htlc, true, &txkeys);
+                let output_witscript = get_revokeable_redeemscript(
+                    &txkeys.revocation_key,
+                    to_self_delay,
+                    &txkeys.broadcaster_delayed_payment_key,
+                );
+                chan.sign_holder_htlc_tx(
+                    &htlc_tx,
+                    1,
+                    None,
+                    &htlc_redeemscript,
+                    htlc_amount_sat,
+                    &output_witscript,
+                )
+            }),
+            "policy failure: validate_htlc_tx: \
+             feerate_per_kw of 1000 is not zero for a zero-fee HTLC tx"
+        );
+    }
+
+    fn sign_local_htlc_tx_test(setup: &ChannelSetup, bump_fee: bool) {
         let (node, channel_id) =
             init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
 
@@ -61,15 +143,28 @@ mod tests {
             })
             .expect("point");
 
-        let htlc_tx = build_htlc_transaction(
+        let mut htlc_tx = build_htlc_transaction(
             &commitment_txid,
-            feerate_per_kw,
+            setup.htlc_feerate_per_kw(feerate_per_kw),
             to_self_delay,
             &htlc,
             setup.option_anchor_outputs(),
             &txkeys.broadcaster_delayed_payment_key,
             &txkeys.revocation_key,
         );
+        if bump_fee {
+            // add a wallet input and a change output
+            htlc_tx.input.push(TxIn {
+                previous_output: OutPoint {
+                    txid: bitcoin::Txid::from_slice(&[3u8; 32]).unwrap(),
+                    vout: 0,
+                },
+                script_sig: Script::new(),
+                sequence: 0xffff_ffff,
+                witness: vec![],
+            });
+            htlc_tx.output.push(TxOut { value: 5_000, script_pubkey: Script::new() });
+        }
 
         let htlc_redeemscript =
             get_htlc_redeemscript(&htlc, setup.option_anchor_outputs(), &txkeys);
@@ -0,0 +1,266 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Message, PublicKey, Signature};
+    use bitcoin::util::bip143::SigHashCache;
+    use bitcoin::{self, Address, OutPoint, Script, SigHashType, Transaction, TxIn, TxOut, Txid};
+
+    use test_log::test;
+
+    use crate::util::status::{Code, Status};
+    use crate::util::test_utils::*;
+
+    #[allow(unused_imports)]
+    use log::debug;
+
+    const CHANNEL_VALUE: u64 = 3_000_000;
+    const CONTRIBUTION: u64 = 2_000_000;
+    const INPUT_VALUE: u64 = 2_500_000;
+
+    struct InteractiveFundingState {
+        node_ctx: TestNodeContext,
+        chan_ctx: TestChannelContext,
+        our_input: TxIn,
+        prev_output: TxOut,
+        change: TxOut,
+    }
+
+    // Set up a channel and add our wallet input and change output to an
+    // interactive funding construction
+    fn begin_interactive_funding(is_outbound: bool, change_sat: u64) -> InteractiveFundingState {
+        let node_ctx = test_node_ctx(1);
+        let mut chan_ctx = test_chan_ctx(&node_ctx, 1, CHANNEL_VALUE);
+        chan_ctx.setup.is_outbound = is_outbound;
+        let node = &node_ctx.node;
+
+        node.begin_interactive_funding(&chan_ctx.channel_id, CONTRIBUTION).expect("begin");
+
+        let our_input = make_test_funding_wallet_input();
+        let prev_output = TxOut {
+            value: INPUT_VALUE,
+            script_pubkey: make_test_funding_wallet_addr(&node_ctx.secp_ctx, node, 1, false)
+                .script_pubkey(),
+        };
+        node.add_interactive_funding_input(
+            &chan_ctx.channel_id,
+            our_input.previous_output,
+            prev_output.clone(),
+            vec![1],
+        )
+        .expect("add input");
+
+        let change =
+            make_test_funding_wallet_output(&node_ctx.secp_ctx, node, 2, change_sat, false);
+        node.add_interactive_funding_output(&chan_ctx.channel_id, change.clone(), vec![2])
+            .expect("add output");
+
+        InteractiveFundingState { node_ctx, chan_ctx, our_input, prev_output, change }
+    }
+
+    // Complete the construction with a counterparty input, make the channel
+    // ready and validate the initial holder commitment
+    fn complete_interactive_funding(
+        state: &mut InteractiveFundingState,
+        outputs: Vec<TxOut>,
+    ) -> Transaction {
+        let node_ctx = &state.node_ctx;
+        let their_input = TxIn {
+            previous_output: OutPoint { txid: Txid::from_slice(&[7u8; 32]).unwrap(), vout: 3 },
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: vec![],
+        };
+        let mut outputs = outputs;
+        let channel_outpoint = make_test_funding_channel_outpoint(
+            &node_ctx.node,
+            &state.chan_ctx.setup,
+            &state.chan_ctx.channel_id,
+            CHANNEL_VALUE,
+        );
+        outputs.push(channel_outpoint);
+        let vout = outputs.len() as u32 - 1;
+        let tx =
+            make_test_funding_tx_with_ins_outs(vec![their_input, state.our_input.clone()], outputs);
+
+        funding_tx_ready_channel(node_ctx, &mut state.chan_ctx, &tx, vout);
+        let mut commit_tx_ctx = channel_initial_holder_commitment(node_ctx, &state.chan_ctx);
+        let (csig, hsigs) =
+            counterparty_sign_holder_commitment(node_ctx, &state.chan_ctx, &mut commit_tx_ctx);
+        validate_holder_commitment(node_ctx, &state.chan_ctx, &commit_tx_ctx, &csig, &hsigs)
+            .expect("valid holder commitment");
+        tx
+    }
+
+    fn sign_interactive_funding(
+        state: &mut InteractiveFundingState,
+        outputs: Vec<TxOut>,
+    ) -> Result<Vec<Vec<Vec<u8>>>, Status> {
+        let tx = complete_interactive_funding(state, outputs);
+        state.node_ctx.node.sign_interactive_funding_tx(&state.chan_ctx.channel_id, &tx)
+    }
+
+    fn check_interactive_funding_success(is_outbound: bool) {
+        let fee = 1000;
+        let mut state = begin_interactive_funding(is_outbound, INPUT_VALUE - CONTRIBUTION - fee);
+        let outputs = vec![state.change.clone()];
+        let tx = complete_interactive_funding(&mut state, outputs);
+        let witvec = state
+            .node_ctx
+            .node
+            .sign_interactive_funding_tx(&state.chan_ctx.channel_id, &tx)
+            .expect("witvec");
+
+        // the counterparty input is not signed
+        assert_eq!(witvec.len(), 2);
+        assert!(witvec[0].is_empty());
+
+        let witness = &witvec[1];
+        assert_eq!(witness.len(), 2);
+        let pubkey = bitcoin::PublicKey::from_slice(&witness[1]).unwrap();
+        assert_eq!(
+            Address::p2wpkh(&pubkey, state.node_ctx.node.network()).unwrap().script_pubkey(),
+            state.prev_output.script_pubkey
+        );
+        let script_code = Address::p2pkh(&pubkey, state.node_ctx.node.network()).script_pubkey();
+        let sighash = SigHashCache::new(&tx).signature_hash(
+            1,
+            &script_code,
+            state.prev_output.value,
+            SigHashType::All,
+        );
+        let sigvec = &witness[0];
+        assert_eq!(sigvec[sigvec.len() - 1], SigHashType::All as u8);
+        let sig = Signature::from_der(&sigvec[..sigvec.len() - 1]).unwrap();
+        let secp_ctx = bitcoin::secp256k1::Secp256k1::verification_only();
+        let message = Message::from_slice(&sighash[..]).unwrap();
+        secp_ctx.verify(&message, &sig, &PublicKey::from_slice(&witness[1]).unwrap()).unwrap();
+
+        // the construction is finished
+        assert_invalid_argument_err!(
+            state.node_ctx.node.sign_interactive_funding_tx(&state.chan_ctx.channel_id, &tx),
+            format!("no interactive funding for channel {}", state.chan_ctx.channel_id)
+        );
+    }
+
+    #[test]
+    fn sign_interactive_funding_outbound_test() {
+        check_interactive_funding_success(true);
+    }
+
+    #[test]
+    fn sign_interactive_funding_inbound_test() {
+        check_interactive_funding_success(false);
+    }
+
+    #[test]
+    fn interactive_funding_not_begun_test() {
+        let node_ctx = test_node_ctx(1);
+        let chan_ctx = test_chan_ctx(&node_ctx, 1, CHANNEL_VALUE);
+        let output = TxOut { value: 1000, script_pubkey: Script::new() };
+        assert_invalid_argument_err!(
+            node_ctx.node.add_interactive_funding_output(&chan_ctx.channel_id, output, vec![2]),
+            format!("no interactive funding for channel {}", chan_ctx.channel_id)
+        );
+    }
+
+    #[test]
+    fn interactive_funding_input_not_in_wallet_test() {
+        let state = begin_interactive_funding(true, 499_000);
+        let outpoint = OutPoint { txid: Txid::from_slice(&[8u8; 32]).unwrap(), vout: 1 };
+        assert_failed_precondition_err!(
+            state.node_ctx.node.add_interactive_funding_input(
+                &state.chan_ctx.channel_id,
+                outpoint,
+                state.prev_output.clone(),
+                vec![3],
+            ),
+            format!(
+                "policy failure: validate_interactive_funding_input: \
+                 wallet cannot spend input {}",
+                outpoint
+            )
+        );
+    }
+
+    #[test]
+    fn interactive_funding_duplicate_input_test() {
+        let state = begin_interactive_funding(true, 499_000);
+        let outpoint = state.our_input.previous_output;
+        assert_invalid_argument_err!(
+            state.node_ctx.node.add_interactive_funding_input(
+                &state.chan_ctx.channel_id,
+                outpoint,
+                state.prev_output.clone(),
+                vec![1],
+            ),
+            format!("duplicate input {}", outpoint)
+        );
+    }
+
+    #[test]
+    fn interactive_funding_output_not_in_wallet_test() {
+        let state = begin_interactive_funding(true, 499_000);
+        let output = TxOut { value: 1000, script_pubkey: state.prev_output.script_pubkey.clone() };
+        assert_failed_precondition_err!(
+            state.node_ctx.node.add_interactive_funding_output(
+                &state.chan_ctx.channel_id,
+                output.clone(),
+                vec![3],
+            ),
+            format!(
+                "policy failure: validate_interactive_funding_output: \
+                 wallet cannot spend output {}",
+                output.script_pubkey
+            )
+        );
+        assert_failed_precondition_err!(
+            state.node_ctx.node.add_interactive_funding_output(
+                &state.chan_ctx.channel_id,
+                output.clone(),
+                vec![],
+            ),
+            format!(
+                "policy failure: validate_interactive_funding_output: \
+                 output {} is not to our wallet or allowlisted",
+                output.script_pubkey
+            )
+        );
+    }
+
+    #[test]
+    fn interactive_funding_missing_output_test() {
+        let mut state = begin_interactive_funding(true, 499_000);
+        let script_pubkey = state.change.script_pubkey.clone();
+        assert_failed_precondition_err!(
+            sign_interactive_funding(&mut state, vec![]),
+            format!(
+                "policy failure: validate_interactive_funding_tx: \
+                 output {} missing from transaction",
+                script_pubkey
+            )
+        );
+    }
+
+    #[test]
+    fn interactive_funding_fee_too_high_test() {
+        let mut state = begin_interactive_funding(true, 200_000);
+        let outputs = vec![state.change.clone()];
+        assert_failed_precondition_err!(
+            sign_interactive_funding(&mut state, outputs),
+            "policy failure: validate_interactive_funding_tx: \
+             validate_beneficial_value: non-beneficial value above maximum: 300000 > 200000"
+        );
+    }
+
+    #[test]
+    fn interactive_funding_overspent_test() {
+        let mut state = begin_interactive_funding(true, 600_000);
+        let outputs = vec![state.change.clone()];
+        assert_failed_precondition_err!(
+            sign_interactive_funding(&mut state, outputs),
+            "policy failure: validate_interactive_funding_tx: \
+             validate_beneficial_value: non-beneficial value underflow: \
+             sum of our inputs 2500000 < sum of our outputs 2600000"
+        );
+    }
+}
@@ -2,18 +2,18 @@
 mod tests {
     use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
     use bitcoin::{self, OutPoint, Script, Transaction, TxIn, TxOut, Txid};
-    use lightning::ln::chan_utils::get_revokeable_redeemscript;
+    use lightning::ln::chan_utils::{build_commitment_secret, get_revokeable_redeemscript};
     use test_log::test;
 
     use crate::channel::{Channel, ChannelBase, CommitmentType, TypedSignature};
     use crate::node::SpendType::{P2shP2wpkh, P2wpkh};
     use crate::policy::validator::ChainState;
-    use crate::util::crypto_utils::{
-        derive_private_revocation_key, derive_public_key, derive_revocation_pubkey,
-    };
+    use crate::tx::tx::{HTLCInfo2, JusticeOutput, JusticeOutputKind};
+    use crate::util::crypto_utils::derive_private_revocation_key;
     use crate::util::key_utils::{make_test_key, make_test_pubkey};
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
+    use crate::util::INITIAL_COMMITMENT_NUMBER;
 
     fn make_test_justice_sweep_tx(
         txid: Txid,
@@ -96,17 +96,9 @@ mod tests {
                 let mut amount_sat = built_commit_tx.output[to_local_outndx].value;
                 assert_eq!(amount_sat, 1_979_997);
 
-                let per_commitment_point = chan.get_per_commitment_point(commit_num)?;
                 let per_commitment_secret = chan.get_per_commitment_secret(commit_num)?;
 
-                let (revocation_base_point, revocation_base_secret) = make_test_key(42);
-
-                let revocation_pubkey = derive_revocation_pubkey(
-                    &secp_ctx,
-                    &per_commitment_point,
-                    &revocation_base_point,
-                )
-                .expect("revocation_pubkey");
+                let (_, revocation_base_secret) = make_test_key(42);
 
                 let mut revocation_secret = derive_private_revocation_key(
                     &secp_ctx,
@@ -126,14 +118,13 @@ mod tests {
                     amount_sat - fee,
                 );
 
-                let delayed_payment_base = make_test_pubkey(2);
-                let delayed_payment_pubkey =
-                    derive_public_key(&secp_ctx, &per_commitment_point, &delayed_payment_base)
-                        .expect("delayed_payment_pubkey");
+                // The revoked to-local output of the counterparty
+                let revocation_point = PublicKey::from_secret_key(&secp_ctx, &revocation_secret);
+                let revoked_keys = chan.make_counterparty_tx_keys(&revocation_point)?;
                 let mut redeemscript = get_revokeable_redeemscript(
-                    &revocation_pubkey,
+                    &revoked_keys.revocation_key,
                     setup.holder_selected_contest_delay,
-                    &delayed_payment_pubkey,
+                    &revoked_keys.broadcaster_delayed_payment_key,
                 );
                 let mut cstate = make_test_chain_state();
 
@@ -216,6 +207,46 @@ mod tests {
         ));
     }
 
+    // policy-sweep-destination-cold-storage
+    #[test]
+    fn sign_justice_to_local_cold_storage_success() {
+        assert_status_ok!(sign_justice_sweep_with_mutators(
+            |node_ctx| { make_test_nonwallet_dest(node_ctx, 3, P2wpkh) },
+            |chan, _cstate, _tx, _input, _commit_num, _redeemscript, _amount_sat| {
+                chan.node
+                    .upgrade()
+                    .unwrap()
+                    .add_allowlist(&vec![
+                        "cold:1000000:tb1qg975h6gdx5mryeac72h6lj2nzygugxhyk6dnhr".to_string()
+                    ])
+                    .expect("add_allowlist");
+            },
+        ));
+    }
+
+    // policy-sweep-destination-cold-storage
+    #[test]
+    fn sign_justice_to_local_allowlist_not_cold_storage() {
+        assert_failed_precondition_err!(
+            sign_justice_sweep_with_mutators(
+                |node_ctx| { make_test_nonwallet_dest(node_ctx, 3, P2shP2wpkh) },
+                |chan, _cstate, _tx, _input, _commit_num, _redeemscript, _amount_sat| {
+                    chan.node
+                        .upgrade()
+                        .unwrap()
+                        .add_allowlist(&vec![
+                            "2MspRgcQvaVN2RkpumN1X8GkzsE7BVTTb6y".to_string(),
+                            "cold:1000000:tb1qg975h6gdx5mryeac72h6lj2nzygugxhyk6dnhr".to_string(),
+                        ])
+                        .expect("add_allowlist");
+                },
+            ),
+            "policy failure: validate_justice_sweep: validate_sweep: \
+             sweep of 1979997 sat is above the cold storage threshold 1000000 \
+             but is not to cold storage"
+        );
+    }
+
     // policy-sweep-destination-allowlisted
     #[test]
     fn sign_justice_to_local_with_unknown_dest() {
@@ -258,6 +289,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sign_justice_sweep_with_bad_redeemscript() {
+        assert_failed_precondition_err!(
+            sign_justice_sweep_with_mutators(
+                |node_ctx| { make_test_wallet_dest(node_ctx, 19, P2wpkh) },
+                |_chan, _cstate, _tx, _input, _commit_num, redeemscript, _amount_sat| {
+                    *redeemscript = get_revokeable_redeemscript(
+                        &make_test_pubkey(42),
+                        6,
+                        &make_test_pubkey(43),
+                    );
+                },
+            ),
+            "policy failure: sign_justice_sweep: redeemscript mismatch"
+        );
+    }
+
     // policy-sweep-version
     #[test]
     fn sign_justice_sweep_with_bad_version() {
@@ -358,4 +406,82 @@ mod tests {
              fee above maximum: 1978997 > 200000"
         );
     }
+
+    #[test]
+    fn build_and_sign_justice_tx_test() {
+        let (node, setup, channel_id, offered_htlcs, received_htlcs) =
+            sign_commitment_tx_with_mutators_setup(CommitmentType::StaticRemoteKey);
+        let node_ctx = TestNodeContext { node, secp_ctx: Secp256k1::signing_only() };
+        let (_, wallet_path) = make_test_wallet_dest(&node_ctx, 19, P2wpkh);
+
+        assert_status_ok!(node_ctx.node.with_ready_channel(&channel_id, |chan| {
+            let commit_num = 23;
+            let secret = SecretKey::from_slice(&build_commitment_secret(
+                &[0x33; 32],
+                INITIAL_COMMITMENT_NUMBER - commit_num,
+            ))
+            .unwrap();
+            let point = PublicKey::from_secret_key(&Secp256k1::new(), &secret);
+
+            let keys = chan.make_counterparty_tx_keys(&point)?;
+            let htlcs = Channel::htlcs_info2_to_oic(offered_htlcs.clone(), received_htlcs.clone());
+            let commitment_tx = chan.make_counterparty_commitment_tx_with_keys(
+                keys, commit_num, 5_000, 1_000_000, 1_979_997, htlcs,
+            );
+            let built = commitment_tx.trust().built_transaction().clone();
+            let to_local_vout =
+                built.transaction.output.iter().position(|o| o.value == 1_979_997).unwrap();
+
+            let mut outputs = vec![JusticeOutput {
+                vout: to_local_vout as u32,
+                value_sat: 1_979_997,
+                kind: JusticeOutputKind::ToLocal,
+            }];
+            for htlc in commitment_tx.htlcs() {
+                let info = HTLCInfo2 {
+                    value_sat: htlc.amount_msat / 1000,
+                    payment_hash: htlc.payment_hash,
+                    cltv_expiry: htlc.cltv_expiry,
+                };
+                outputs.push(JusticeOutput {
+                    vout: htlc.transaction_output_index.unwrap(),
+                    value_sat: info.value_sat,
+                    kind: if htlc.offered {
+                        JusticeOutputKind::OfferedHtlc(info)
+                    } else {
+                        JusticeOutputKind::ReceivedHtlc(info)
+                    },
+                });
+            }
+
+            // the secret must have been revealed
+            assert_invalid_argument_err!(
+                chan.build_and_sign_justice_tx(
+                    &built.txid,
+                    commit_num,
+                    &outputs,
+                    1000,
+                    &wallet_path
+                ),
+                "build_and_sign_justice_tx: no revocation secret for commit_num 23"
+            );
+            chan.enforcement_state.set_counterparty_revocation_secret(commit_num, &secret)?;
+
+            let tx = chan.build_and_sign_justice_tx(
+                &built.txid,
+                commit_num,
+                &outputs,
+                1000,
+                &wallet_path,
+            )?;
+            assert_eq!(tx.input.len(), 4);
+            let total: u64 = outputs.iter().map(|o| o.value_sat).sum();
+            let fee = total - tx.output[0].value;
+            // within a few bytes of the estimate
+            assert!(fee >= tx.get_weight() as u64 && fee < tx.get_weight() as u64 + 10);
+            tx.verify(|outpoint| Some(built.transaction.output[outpoint.vout as usize].clone()))
+                .expect("verify");
+            Ok(())
+        }));
+    }
 }
@@ -685,6 +685,75 @@ mod tests {
         );
     }
 
+    // policy-mutual-upfront-shutdown-script
+    #[test]
+    fn sign_mutual_close_tx_phase2_counterparty_upfront_script_mismatch() {
+        assert_failed_precondition_err!(
+            sign_mutual_close_tx_phase2_with_mutators_outbound!(
+                |chan,
+                 _to_holder,
+                 _to_counterparty,
+                 _holder_script,
+                 counter_script,
+                 _outpoint,
+                 _wallet_path,
+                 _allowlist| {
+                    chan.setup.counterparty_shutdown_script =
+                        Some(hex_script!("0014b76dd61e41b5ef052af21cda3260888c070bb9af"));
+                    *counter_script =
+                        hex_script!("76a9149f9a7abd600c0caa03983a77c8c3df8e062cb2fa88ac");
+                },
+                |chan| {
+                    // Channel should not be marked closed
+                    assert_eq!(chan.enforcement_state.mutual_close_signed, false);
+                }
+            ),
+            "policy failure: validate_mutual_close_tx: \
+             counterparty_script doesn't match upfront counterparty_shutdown_script"
+        );
+    }
+
+    // policy-mutual-upfront-shutdown-script
+    #[test]
+    fn sign_mutual_close_tx_with_upfront_scripts_success() {
+        assert_status_ok!(sign_mutual_close_tx_with_mutators_outbound!(
+            |chan, _to_holder, _to_counterparty, holder_script, counter_script, _outpoint| {
+                // Both sides committed to the scripts they close to
+                chan.setup.holder_shutdown_script = Some(holder_script.clone());
+                chan.setup.counterparty_shutdown_script = Some(counter_script.clone());
+            },
+            |_tx, _wallet_paths, _allowlist| {
+                // don't need to mutate these
+            },
+            |chan| {
+                // Channel should be marked closed
+                assert_eq!(chan.enforcement_state.mutual_close_signed, true);
+            }
+        ));
+    }
+
+    // policy-mutual-upfront-shutdown-script
+    #[test]
+    fn sign_mutual_close_tx_with_counterparty_upfront_script_mismatch() {
+        assert_failed_precondition_err!(
+            sign_mutual_close_tx_with_mutators_outbound!(
+                |chan, _to_holder, _to_counterparty, _holder_script, counter_script, _outpoint| {
+                    chan.setup.counterparty_shutdown_script = Some(counter_script.clone());
+                    *counter_script = hex_script!("0014b76dd61e41b5ef052af21cda3260888c070bb9af");
+                },
+                |_tx, _wallet_paths, _allowlist| {
+                    // don't need to mutate these
+                },
+                |chan| {
+                    // Channel should not be marked closed
+                    assert_eq!(chan.enforcement_state.mutual_close_signed, false);
+                }
+            ),
+            "policy failure: validate_mutual_close_tx: \
+             counterparty_script doesn't match upfront counterparty_shutdown_script"
+        );
+    }
+
     // policy-mutual-fee-range
     #[test]
     fn sign_mutual_close_tx_phase2_with_fee_too_large() {
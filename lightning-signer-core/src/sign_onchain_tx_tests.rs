@@ -4,16 +4,24 @@ mod tests {
     use bitcoin::blockdata::script::Builder;
     use bitcoin::hashes::hash160::Hash as Hash160;
     use bitcoin::hashes::Hash;
-    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use bitcoin::secp256k1::{schnorrsig, Message, Secp256k1};
     use bitcoin::util::psbt::serialize::Serialize;
     use bitcoin::{self, Address, Network, OutPoint, Script, Transaction, TxIn, TxOut};
 
     use test_log::test;
+    use vls_policy::rules::Rule;
 
     use crate::channel::CommitmentType;
-    use crate::node::SpendType;
+    use crate::node::{SpendType, UnilateralCloseInfo};
+    use crate::policy::simple_validator::{
+        make_simple_policy, value_approval_token, SimpleValidatorFactory,
+    };
+    use crate::sync::Arc;
+    use crate::util::crypto_utils::verify_schnorr;
+    use crate::util::key_utils::make_test_bitcoin_pubkey;
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
+    use crate::util::transaction_utils::taproot_key_spend_sighash;
 
     #[allow(unused_imports)]
     use log::debug;
@@ -69,6 +77,71 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_funding_tx_p2tr_test() {
+        let secp_ctx = Secp256k1::signing_only();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let ipaths = vec![vec![0u32], vec![1u32]];
+        let values_sat = vec![100u64, 300u64];
+        let inputs = (0..2)
+            .map(|vout| TxIn {
+                previous_output: OutPoint { txid: Default::default(), vout },
+                script_sig: Script::new(),
+                sequence: 0,
+                witness: vec![],
+            })
+            .collect();
+        let (opath, mut tx) = make_test_funding_tx(&secp_ctx, &node, inputs, 300);
+        let spendtypes = vec![SpendType::P2wpkh, SpendType::P2tr];
+
+        let witvec = node
+            .sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &values_sat,
+                &spendtypes,
+                vec![None, None],
+                &vec![opath.clone()],
+            )
+            .expect("good sigs");
+        tx.input[0].witness = witvec[0].clone();
+        tx.input[1].witness = witvec[1].clone();
+
+        let spent = vec![
+            TxOut {
+                value: values_sat[0],
+                script_pubkey: node.get_native_address(&ipaths[0]).unwrap().script_pubkey(),
+            },
+            TxOut {
+                value: values_sat[1],
+                script_pubkey: node.get_taproot_script_pubkey(&ipaths[1]).unwrap(),
+            },
+        ];
+        assert!(tx.verify(|p| Some(spent[p.vout as usize].clone())).is_ok());
+
+        // a key path spend has the signature by the tweaked key as the witness
+        assert_eq!(tx.input[1].witness.len(), 1);
+        let sighash = taproot_key_spend_sighash(&tx, 1, &spent).unwrap();
+        let output_key =
+            schnorrsig::PublicKey::from_slice(&spent[1].script_pubkey.as_bytes()[2..]).unwrap();
+        let sig = schnorrsig::Signature::from_slice(&tx.input[1].witness[0]).unwrap();
+        assert!(verify_schnorr(&Message::from_slice(&sighash).unwrap(), &sig, &output_key).is_ok());
+
+        // all the spent outputs must be known to sign a taproot input
+        let spendtypes = vec![SpendType::Invalid, SpendType::P2tr];
+        assert_invalid_argument_err!(
+            node.sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &values_sat,
+                &spendtypes,
+                vec![None, None],
+                &vec![opath]
+            ),
+            "input 0: a taproot input can't be signed with an input of spend type Invalid"
+        );
+    }
+
     #[test]
     fn sign_funding_tx_p2wpkh_test1() -> Result<(), ()> {
         let secp_ctx = Secp256k1::signing_only();
@@ -152,7 +225,8 @@ mod tests {
     #[test]
     fn sign_funding_tx_unilateral_close_info_test() -> Result<(), ()> {
         let secp_ctx = Secp256k1::signing_only();
-        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[0], make_test_channel_setup());
         let txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
         let ival0 = 300u64;
         let chanamt = 200u64;
@@ -169,17 +243,16 @@ mod tests {
         let (opath, mut tx) = make_test_funding_tx(&secp_ctx, &node, vec![input1], chanamt);
         let spendtypes = vec![SpendType::P2wpkh];
 
-        let uniclosekey = SecretKey::from_slice(
-            hex_decode("4220531d6c8b15d66953c46b5c4d67c921943431452d5543d8805b9903c6b858")
-                .unwrap()
-                .as_slice(),
-        )
-        .unwrap();
-        let uniclosepubkey = bitcoin::PublicKey::from_slice(
-            &PublicKey::from_secret_key(&secp_ctx, &uniclosekey).serialize()[..],
-        )
-        .unwrap();
-        let uniclosekeys = vec![Some((uniclosekey, vec![uniclosepubkey.serialize()]))];
+        // option_static_remotekey, the output pays to our payment point
+        let payment_point = node
+            .with_ready_channel(&channel_id, |chan| Ok(chan.keys.pubkeys().payment_point))
+            .unwrap();
+        let uniclosepubkey = bitcoin::PublicKey { compressed: true, key: payment_point };
+        let uniclosekeys = vec![Some(UnilateralCloseInfo {
+            channel_id,
+            commitment_point: None,
+            revocation_pubkey: None,
+        })];
 
         let witvec = node
             .sign_onchain_tx(&tx, &ipaths, &values_sat, &spendtypes, uniclosekeys, &vec![opath])
@@ -546,6 +619,109 @@ mod tests {
         );
     }
 
+    fn sign_validated_funding_tx_with_mutator<FundingTxMutator>(
+        mutate_funding_tx: FundingTxMutator,
+    ) -> Result<(), Status>
+    where
+        FundingTxMutator: Fn(&mut FundingTxMutationState),
+    {
+        let is_p2sh = false;
+        let node_ctx = test_node_ctx(1);
+
+        let incoming0 = 5_000_000;
+        let incoming1 = 4_000_000;
+        let channel_amount = 3_000_000;
+        let fee = 1000;
+        let change = incoming0 + incoming1 - channel_amount - fee;
+
+        let mut chan_ctx = test_chan_ctx(&node_ctx, 1, channel_amount);
+        let mut tx_ctx = test_funding_tx_ctx();
+
+        funding_tx_add_wallet_input(&mut tx_ctx, is_p2sh, 1, incoming0);
+        funding_tx_add_wallet_input(&mut tx_ctx, is_p2sh, 2, incoming1);
+        funding_tx_add_wallet_output(&node_ctx, &mut tx_ctx, is_p2sh, 1, change);
+        let outpoint_ndx =
+            funding_tx_add_channel_outpoint(&node_ctx, &chan_ctx, &mut tx_ctx, channel_amount);
+
+        let mut tx = funding_tx_from_ctx(&tx_ctx);
+
+        mutate_funding_tx(&mut FundingTxMutationState {
+            chan_ctx: &mut chan_ctx,
+            tx_ctx: &mut tx_ctx,
+            tx: &mut tx,
+        });
+
+        let err_opt = funding_tx_ready_channel(&node_ctx, &mut chan_ctx, &tx, outpoint_ndx);
+        if let Some(err) = err_opt {
+            return Err(err);
+        }
+
+        let mut commit_tx_ctx = channel_initial_holder_commitment(&node_ctx, &chan_ctx);
+        let (csig, hsigs) =
+            counterparty_sign_holder_commitment(&node_ctx, &chan_ctx, &mut commit_tx_ctx);
+        validate_holder_commitment(&node_ctx, &chan_ctx, &commit_tx_ctx, &csig, &hsigs)
+            .expect("valid holder commitment");
+
+        let witvec = funding_tx_sign_validated(&node_ctx, &tx_ctx, &tx)?;
+        funding_tx_validate_sig(&node_ctx, &tx_ctx, &mut tx, &witvec);
+
+        Ok(())
+    }
+
+    #[test]
+    fn validated_funding_success() {
+        assert_status_ok!(sign_validated_funding_tx_with_mutator(|_| {}));
+    }
+
+    // policy-funding-inputs-wallet
+    #[test]
+    fn validated_funding_foreign_input() {
+        assert_failed_precondition_err!(
+            sign_validated_funding_tx_with_mutator(|fms| {
+                fms.tx_ctx.ispnds[1] = SpendType::Invalid;
+            }),
+            "policy failure: validate_funding_tx: only 1 of the 2 inputs are from our wallet"
+        );
+    }
+
+    // policy-funding-outputs-channel-or-change
+    #[test]
+    fn validated_funding_non_wallet_output() {
+        assert_failed_precondition_err!(
+            sign_validated_funding_tx_with_mutator(|fms| {
+                let value = 100_000;
+                fms.tx.output[0].value -= value;
+                fms.tx.output.push(TxOut { value, script_pubkey: Script::new() });
+                fms.tx_ctx.opaths.push(vec![]);
+            }),
+            "policy failure: validate_funding_tx: \
+             output[2] neither funds a channel nor returns change to our wallet"
+        );
+    }
+
+    // policy-onchain-output-match-commitment
+    #[test]
+    fn validated_funding_channel_value_mismatch() {
+        assert_failed_precondition_err!(
+            sign_validated_funding_tx_with_mutator(|fms| {
+                fms.tx.output[1].value -= 42;
+            }),
+            "policy failure: validate_onchain_tx: \
+             funding output amount mismatch w/ channel: 2999958 != 3000000"
+        );
+    }
+
+    #[test]
+    fn validated_funding_p2sh_input() {
+        assert_invalid_argument_err!(
+            sign_validated_funding_tx_with_mutator(|fms| {
+                fms.tx_ctx.ispnds[0] = SpendType::P2shP2wpkh;
+            }),
+            "input 0000000000000000000000000000000000000000000000000000000000000000:0: \
+             only native segwit wallet outputs can be spent"
+        );
+    }
+
     fn sign_funding_tx_with_output_and_change(is_p2sh: bool) {
         let node_ctx = test_node_ctx(1);
 
@@ -576,6 +752,107 @@ mod tests {
         funding_tx_validate_sig(&node_ctx, &tx_ctx, &mut tx, &witvec);
     }
 
+    #[test]
+    fn sign_funding_tx_with_value_approval() {
+        let is_p2sh = false;
+        let node_ctx = test_node_ctx(1);
+        let key = [7u8; 32];
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.max_unapproved_value_sat = 1_000_000;
+        policy.value_approval_key = Some(key);
+        node_ctx
+            .node
+            .set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+
+        let incoming = 5_000_000;
+        let channel_amount = 3_000_000;
+        let fee = 1000;
+        let change = incoming - channel_amount - fee;
+
+        let mut chan_ctx = test_chan_ctx(&node_ctx, 1, channel_amount);
+        let mut tx_ctx = test_funding_tx_ctx();
+
+        funding_tx_add_wallet_input(&mut tx_ctx, is_p2sh, 1, incoming);
+        funding_tx_add_wallet_output(&node_ctx, &mut tx_ctx, is_p2sh, 1, change);
+        let outpoint_ndx =
+            funding_tx_add_channel_outpoint(&node_ctx, &chan_ctx, &mut tx_ctx, channel_amount);
+
+        let mut tx = funding_tx_from_ctx(&tx_ctx);
+
+        funding_tx_ready_channel(&node_ctx, &mut chan_ctx, &tx, outpoint_ndx);
+
+        let mut commit_tx_ctx = channel_initial_holder_commitment(&node_ctx, &chan_ctx);
+        let (csig, hsigs) =
+            counterparty_sign_holder_commitment(&node_ctx, &chan_ctx, &mut commit_tx_ctx);
+        validate_holder_commitment(&node_ctx, &chan_ctx, &commit_tx_ctx, &csig, &hsigs)
+            .expect("valid holder commitment");
+
+        assert_failed_precondition_err!(
+            funding_tx_sign(&node_ctx, &tx_ctx, &tx),
+            "policy failure: validate_onchain_tx: validate_value_approval: \
+             value to non-wallet destinations requires approval: 3000000 > 1000000"
+        );
+
+        // a token made with another key is not accepted
+        node_ctx.node.add_value_approval(value_approval_token(&[8u8; 32], &tx.txid()));
+        assert!(funding_tx_sign(&node_ctx, &tx_ctx, &tx).is_err());
+
+        node_ctx.node.add_value_approval(value_approval_token(&key, &tx.txid()));
+        let witvec = funding_tx_sign(&node_ctx, &tx_ctx, &tx).expect("witvec");
+        funding_tx_validate_sig(&node_ctx, &tx_ctx, &mut tx, &witvec);
+    }
+
+    #[test]
+    fn sign_funding_tx_with_policy_rules() {
+        let is_p2sh = false;
+        let node_ctx = test_node_ctx(1);
+        let key = [7u8; 32];
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.value_approval_key = Some(key);
+        policy.rules.push(Rule::parse("fee_sat > 10_000 => deny").unwrap());
+        policy.rules.push(
+            Rule::parse("op.onchain_tx && channel_value_sat > 2_000_000 => require_approval")
+                .unwrap(),
+        );
+        policy.rules.push(Rule::parse("dest.not_allowlisted => deny").unwrap());
+        node_ctx
+            .node
+            .set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+
+        let incoming = 5_000_000;
+        let channel_amount = 3_000_000;
+        let fee = 1000;
+        let change = incoming - channel_amount - fee;
+
+        let mut chan_ctx = test_chan_ctx(&node_ctx, 1, channel_amount);
+        let mut tx_ctx = test_funding_tx_ctx();
+
+        funding_tx_add_wallet_input(&mut tx_ctx, is_p2sh, 1, incoming);
+        funding_tx_add_wallet_output(&node_ctx, &mut tx_ctx, is_p2sh, 1, change);
+        let outpoint_ndx =
+            funding_tx_add_channel_outpoint(&node_ctx, &chan_ctx, &mut tx_ctx, channel_amount);
+
+        let mut tx = funding_tx_from_ctx(&tx_ctx);
+
+        funding_tx_ready_channel(&node_ctx, &mut chan_ctx, &tx, outpoint_ndx);
+
+        let mut commit_tx_ctx = channel_initial_holder_commitment(&node_ctx, &chan_ctx);
+        let (csig, hsigs) =
+            counterparty_sign_holder_commitment(&node_ctx, &chan_ctx, &mut commit_tx_ctx);
+        validate_holder_commitment(&node_ctx, &chan_ctx, &commit_tx_ctx, &csig, &hsigs)
+            .expect("valid holder commitment");
+
+        assert_failed_precondition_err!(
+            funding_tx_sign(&node_ctx, &tx_ctx, &tx),
+            "policy failure: validate_onchain_tx: validate_rules: custom rule requires approval: \
+             op.onchain_tx && channel_value_sat > 2_000_000 => require_approval"
+        );
+
+        node_ctx.node.add_value_approval(value_approval_token(&key, &tx.txid()));
+        let witvec = funding_tx_sign(&node_ctx, &tx_ctx, &tx).expect("witvec");
+        funding_tx_validate_sig(&node_ctx, &tx_ctx, &mut tx, &witvec);
+    }
+
     #[test]
     fn sign_funding_tx_with_p2wpkh_wallet() {
         sign_funding_tx_with_output_and_change(false);
@@ -1059,4 +1336,52 @@ mod tests {
              non-beneficial value above maximum: 301000 > 200000"
         );
     }
+
+    #[test]
+    fn sign_tx_with_fee_reserve_input() {
+        let secp_ctx = Secp256k1::signing_only();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
+        let reserved = OutPoint { txid, vout: 0 };
+        node.add_fee_reserve(&vec![reserved]).expect("add fee reserve");
+        assert_eq!(node.fee_reserve(), vec![reserved]);
+
+        let input = TxIn {
+            previous_output: reserved,
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: vec![],
+        };
+        let ipaths = vec![vec![0u32]];
+        let values_sat = vec![200u64];
+        let spendtypes = vec![SpendType::P2wpkh];
+
+        // A fee bump pays everything but the fee back to our wallet
+        let (opath, tx) = make_test_funding_tx(&secp_ctx, &node, vec![input.clone()], 190);
+        node.sign_onchain_tx(
+            &tx,
+            &ipaths,
+            &values_sat,
+            &spendtypes,
+            vec![None],
+            &vec![opath.clone()],
+        )
+        .expect("fee bump");
+
+        // An ordinary send can't use the reserve
+        let (_, mut tx) = make_test_funding_tx(&secp_ctx, &node, vec![input], 90);
+        let dest = Address::p2wpkh(&make_test_bitcoin_pubkey(3), Network::Testnet).unwrap();
+        tx.output.insert(0, TxOut { value: 100, script_pubkey: dest.script_pubkey() });
+        let opaths = vec![vec![], opath];
+        assert_failed_precondition_err!(
+            node.sign_onchain_tx(&tx, &ipaths, &values_sat, &spendtypes, vec![None], &opaths),
+            "policy failure: sign_onchain_tx: output 0 is not to our wallet, \
+             but the tx spends inputs reserved for fee bumping"
+        );
+
+        node.remove_fee_reserve(&vec![reserved]).expect("remove fee reserve");
+        assert!(node.fee_reserve().is_empty());
+        node.sign_onchain_tx(&tx, &ipaths, &values_sat, &spendtypes, vec![None], &opaths)
+            .expect("send");
+    }
 }
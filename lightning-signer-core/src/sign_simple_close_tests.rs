@@ -0,0 +1,344 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::hex::FromHex;
+    use bitcoin::secp256k1::{Secp256k1, Signature};
+    use bitcoin::{self, Address, Network, Script, Transaction};
+    use lightning::ln::chan_utils::make_funding_redeemscript;
+
+    use test_log::test;
+
+    use crate::channel::{ChannelId, ChannelSetup, TypedSignature};
+    use crate::node::Node;
+    use crate::sync::Arc;
+    use crate::tx::simple_close::build_simple_close_tx;
+    use crate::tx::tx::CommitmentInfo2;
+    use crate::util::key_utils::*;
+    use crate::util::status::{Code, Status};
+    use crate::util::test_utils::*;
+
+    const COMMITMENT_FEE_SAT: u64 = 2000;
+    const HOLDER_WALLET_PATH: [u32; 1] = [7];
+
+    // A channel after shutdown, with the holder balance before fees.  The
+    // funder pays the commitment fee.
+    fn setup_simple_close(outbound: bool, holder_balance_sat: u64) -> (Arc<Node>, ChannelId) {
+        let mut setup = make_test_channel_setup();
+        setup.is_outbound = outbound;
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+
+        let mut to_holder_value_sat = holder_balance_sat;
+        let mut to_counterparty_value_sat = setup.channel_value_sat - holder_balance_sat;
+        if outbound {
+            to_holder_value_sat -= COMMITMENT_FEE_SAT;
+        } else {
+            to_counterparty_value_sat -= COMMITMENT_FEE_SAT;
+        }
+
+        node.with_ready_channel(&channel_id, |chan| {
+            let estate = &mut chan.enforcement_state;
+            estate.current_holder_commit_info = Some(CommitmentInfo2 {
+                is_counterparty_broadcaster: false,
+                to_countersigner_pubkey: make_test_pubkey(100),
+                to_countersigner_value_sat: to_counterparty_value_sat,
+                revocation_pubkey: make_test_pubkey(101),
+                to_broadcaster_delayed_pubkey: make_test_pubkey(102),
+                to_broadcaster_value_sat: to_holder_value_sat,
+                to_self_delay: setup.counterparty_selected_contest_delay,
+                offered_htlcs: vec![],
+                received_htlcs: vec![],
+                feerate_per_kw: 7500,
+            });
+            estate.current_counterparty_commit_info = Some(CommitmentInfo2 {
+                is_counterparty_broadcaster: true,
+                to_countersigner_pubkey: make_test_pubkey(110),
+                to_countersigner_value_sat: to_holder_value_sat,
+                revocation_pubkey: make_test_pubkey(111),
+                to_broadcaster_delayed_pubkey: make_test_pubkey(112),
+                to_broadcaster_value_sat: to_counterparty_value_sat,
+                to_self_delay: setup.holder_selected_contest_delay,
+                offered_htlcs: vec![],
+                received_htlcs: vec![],
+                feerate_per_kw: 7500,
+            });
+            Ok(())
+        })
+        .expect("state setup");
+        (node, channel_id)
+    }
+
+    fn holder_script(node: &Node) -> Script {
+        let secp_ctx = Secp256k1::signing_only();
+        Address::p2wpkh(
+            &node.get_wallet_pubkey(&secp_ctx, &HOLDER_WALLET_PATH.to_vec()).unwrap(),
+            Network::Testnet,
+        )
+        .expect("Address")
+        .script_pubkey()
+    }
+
+    fn counterparty_script() -> Script {
+        Script::from_hex("0014be56df7de366ad8ee9ccdad54e9a9993e99ef565").expect("script_pubkey")
+    }
+
+    // Sign a closing transaction variant and check the signature.  An output
+    // with a zero value is omitted.
+    fn sign_simple_close(
+        node: &Arc<Node>,
+        channel_id: &ChannelId,
+        holder_is_closer: bool,
+        locktime: u32,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: Script,
+    ) -> Result<(Transaction, Signature), Status> {
+        let holder_script = Some(holder_script);
+        let counterparty_script = Some(counterparty_script());
+        let (setup, sig) = node.with_ready_channel(channel_id, |chan| {
+            let sig = chan.sign_simple_close_tx(
+                holder_is_closer,
+                locktime,
+                to_holder_value_sat,
+                to_counterparty_value_sat,
+                &holder_script,
+                &counterparty_script,
+                &HOLDER_WALLET_PATH.to_vec(),
+            )?;
+            assert!(chan.enforcement_state.mutual_close_signed);
+            Ok((chan.setup.clone(), sig))
+        })?;
+
+        let tx = build_simple_close_tx(
+            setup.funding_outpoint,
+            locktime,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            &holder_script,
+            &counterparty_script,
+        );
+        check_close_signature(node, channel_id, &setup, &tx, sig);
+        Ok((tx, sig))
+    }
+
+    fn check_close_signature(
+        node: &Node,
+        channel_id: &ChannelId,
+        setup: &ChannelSetup,
+        tx: &Transaction,
+        sig: Signature,
+    ) {
+        let funding_pubkey = get_channel_funding_pubkey(node, channel_id);
+        let counterparty_points = make_test_counterparty_points();
+        let redeemscript =
+            make_funding_redeemscript(&funding_pubkey, &counterparty_points.funding_pubkey);
+        check_signature(
+            tx,
+            0,
+            TypedSignature::all(sig),
+            &funding_pubkey,
+            setup.channel_value_sat,
+            &redeemscript,
+        );
+    }
+
+    fn assert_not_closed(node: &Arc<Node>, channel_id: &ChannelId) {
+        assert_status_ok!(node.with_ready_channel(channel_id, |chan| {
+            assert!(!chan.enforcement_state.mutual_close_signed);
+            Ok(())
+        }));
+    }
+
+    // The funder closes and pays the closing fee from its output.  The
+    // commitment fee is part of its balance.
+    #[test]
+    fn sign_simple_close_closer_funder_success() {
+        let (node, channel_id) = setup_simple_close(true, 2_000_000);
+        let (tx, _) = sign_simple_close(
+            &node,
+            &channel_id,
+            true,
+            0,
+            1_999_000,
+            1_000_000,
+            holder_script(&node),
+        )
+        .unwrap();
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.input[0].sequence, 0xfffffffd);
+    }
+
+    // The non-funder can close, and then pays the closing fee even though
+    // it didn't pay the commitment fee.
+    #[test]
+    fn sign_simple_close_closee_funder_success() {
+        let (node, channel_id) = setup_simple_close(true, 2_000_000);
+        assert_status_ok!(sign_simple_close(
+            &node,
+            &channel_id,
+            false,
+            0,
+            2_000_000,
+            999_000,
+            holder_script(&node)
+        ));
+    }
+
+    // Each closing_complete may replace the previous transaction with a
+    // higher fee and a new locktime.
+    #[test]
+    fn sign_simple_close_rbf_success() {
+        let (node, channel_id) = setup_simple_close(false, 1_000_000);
+        let (tx1, _) = sign_simple_close(
+            &node,
+            &channel_id,
+            true,
+            800_000,
+            999_000,
+            2_000_000,
+            holder_script(&node),
+        )
+        .unwrap();
+        let (tx2, _) = sign_simple_close(
+            &node,
+            &channel_id,
+            true,
+            800_010,
+            998_000,
+            2_000_000,
+            holder_script(&node),
+        )
+        .unwrap();
+        assert_eq!(tx1.input[0].previous_output, tx2.input[0].previous_output);
+        assert_eq!(tx2.lock_time, 800_010);
+        assert_ne!(tx1.txid(), tx2.txid());
+    }
+
+    // The closer omits the closee's dust output, which goes to fees.
+    #[test]
+    fn sign_simple_close_closee_dust_success() {
+        let channel_value_sat = make_test_channel_setup().channel_value_sat;
+        let (node, channel_id) = setup_simple_close(true, channel_value_sat - 300);
+        let (tx, _) = sign_simple_close(
+            &node,
+            &channel_id,
+            true,
+            0,
+            channel_value_sat - 1_000,
+            0,
+            holder_script(&node),
+        )
+        .unwrap();
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].script_pubkey, holder_script(&node));
+    }
+
+    // The closer can omit its own output, leaving only the closee's.
+    #[test]
+    fn sign_simple_close_closer_output_omitted_success() {
+        let channel_value_sat = make_test_channel_setup().channel_value_sat;
+        let (node, channel_id) = setup_simple_close(false, 3_000);
+        let (tx, _) = sign_simple_close(
+            &node,
+            &channel_id,
+            true,
+            0,
+            0,
+            channel_value_sat - 3_000,
+            holder_script(&node),
+        )
+        .unwrap();
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].script_pubkey, counterparty_script());
+    }
+
+    // policy-simple-close-closee-dust
+    #[test]
+    fn sign_simple_close_closee_output_omitted() {
+        let (node, channel_id) = setup_simple_close(true, 2_000_000);
+        assert_failed_precondition_err!(
+            sign_simple_close(&node, &channel_id, false, 0, 0, 999_000, holder_script(&node)),
+            "policy failure: validate_simple_close_tx: \
+             closee holder output omitted with balance 2000000"
+        );
+        assert_not_closed(&node, &channel_id);
+    }
+
+    // policy-simple-close-closer-pays-fee
+    #[test]
+    fn sign_simple_close_closee_pays_fee() {
+        let (node, channel_id) = setup_simple_close(false, 1_000_000);
+        assert_failed_precondition_err!(
+            sign_simple_close(
+                &node,
+                &channel_id,
+                false,
+                0,
+                980_000,
+                2_000_000,
+                holder_script(&node)
+            ),
+            "policy failure: validate_simple_close_tx: \
+             closee holder output 980000 is smaller than its balance 1000000"
+        );
+        assert_not_closed(&node, &channel_id);
+    }
+
+    // policy-simple-close-closer-pays-fee
+    #[test]
+    fn sign_simple_close_closer_shorts_closee() {
+        let (node, channel_id) = setup_simple_close(true, 2_000_000);
+        assert_failed_precondition_err!(
+            sign_simple_close(
+                &node,
+                &channel_id,
+                true,
+                0,
+                2_019_000,
+                980_000,
+                holder_script(&node)
+            ),
+            "policy failure: validate_simple_close_tx: \
+             closee counterparty output 980000 is smaller than its balance 1000000"
+        );
+        assert_not_closed(&node, &channel_id);
+    }
+
+    // policy-mutual-fee-range
+    #[test]
+    fn sign_simple_close_fee_too_large() {
+        let (node, channel_id) = setup_simple_close(true, 2_000_000);
+        assert_failed_precondition_err!(
+            sign_simple_close(
+                &node,
+                &channel_id,
+                true,
+                0,
+                1_700_000,
+                1_000_000,
+                holder_script(&node)
+            ),
+            "policy failure: validate_simple_close_tx: validate_fee: \
+             fee above maximum: 300000 > 200000"
+        );
+        assert_not_closed(&node, &channel_id);
+    }
+
+    // policy-mutual-destination-allowlisted
+    #[test]
+    fn sign_simple_close_holder_output_not_in_wallet() {
+        let (node, channel_id) = setup_simple_close(true, 2_000_000);
+        assert_failed_precondition_err!(
+            sign_simple_close(
+                &node,
+                &channel_id,
+                true,
+                0,
+                1_999_000,
+                1_000_000,
+                counterparty_script()
+            ),
+            "policy failure: validate_simple_close_tx: holder output not to wallet or in allowlist"
+        );
+        assert_not_closed(&node, &channel_id);
+    }
+}
@@ -0,0 +1,58 @@
+use bitcoin::secp256k1::PublicKey;
+
+use crate::persist::Error;
+use crate::prelude::*;
+
+/// Holds the seeds of the nodes, apart from the rest of their state.
+///
+/// By default, the seed of a node is written to the persister with the
+/// node.  With a key store, see [super::multi_signer::MultiSigner::new_with_key_store],
+/// the persister only gets an empty seed, and the seed is put in the key
+/// store instead, such as an OS keyring, a TPM, a PKCS#11 HSM or a secure
+/// enclave.  The signer gets the seed back when it restores the node, and
+/// derives the node keys from it in memory.
+pub trait KeyStore: SendSync {
+    /// Store the seed of a new node, replacing any previous seed
+    fn put_seed(&self, node_id: &PublicKey, seed: &[u8]) -> Result<(), Error>;
+
+    /// Get the seed of a node
+    fn get_seed(&self, node_id: &PublicKey) -> Result<Vec<u8>, Error>;
+
+    /// Forget the seed of a node
+    fn delete_seed(&self, node_id: &PublicKey) -> Result<(), Error>;
+}
+
+/// A key store in memory, for tests
+pub struct MemoryKeyStore {
+    seeds: Mutex<Map<PublicKey, Vec<u8>>>,
+}
+
+impl MemoryKeyStore {
+    /// Create an empty key store
+    pub fn new() -> Self {
+        MemoryKeyStore { seeds: Mutex::new(Map::new()) }
+    }
+}
+
+impl SendSync for MemoryKeyStore {}
+
+impl KeyStore for MemoryKeyStore {
+    fn put_seed(&self, node_id: &PublicKey, seed: &[u8]) -> Result<(), Error> {
+        self.seeds.lock().unwrap().insert(*node_id, seed.to_vec());
+        Ok(())
+    }
+
+    fn get_seed(&self, node_id: &PublicKey) -> Result<Vec<u8>, Error> {
+        self.seeds
+            .lock()
+            .unwrap()
+            .get(node_id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("seed of node {}", node_id)))
+    }
+
+    fn delete_seed(&self, node_id: &PublicKey) -> Result<(), Error> {
+        self.seeds.lock().unwrap().remove(node_id);
+        Ok(())
+    }
+}
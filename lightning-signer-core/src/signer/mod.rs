@@ -1,3 +1,5 @@
+/// Storage of the node seeds apart from the node state
+pub mod key_store;
 /// An implementation of KeysInterface
 pub mod my_keys_manager;
 /// A multi-node signer
@@ -4,17 +4,22 @@ use bitcoin::OutPoint;
 use log::info;
 #[cfg(feature = "std")]
 use rand::{OsRng, Rng};
+use zeroize::Zeroize;
 
 use crate::chain::tracker::ChainTracker;
 use crate::channel::{Channel, ChannelBase, ChannelId, ChannelSlot};
+use crate::migration::MigrationPlan;
 use crate::monitor::ChainMonitor;
 use crate::node::{Node, NodeConfig};
+use crate::persist::model::NodeEntry;
 use crate::persist::{DummyPersister, Persist};
 use crate::policy::simple_validator::SimpleValidatorFactory;
-use crate::policy::validator::ValidatorFactory;
+use crate::policy::validator::{CoreTypes, ValidatorFactory};
 use crate::prelude::*;
+use crate::signer::key_store::KeyStore;
 use crate::sync::Arc;
-use crate::util::status::{invalid_argument, Status};
+use crate::tx::interactive::InteractiveInput;
+use crate::util::status::{invalid_argument, persist_error, Status};
 
 /// A signer for multiple nodes.
 ///
@@ -24,7 +29,8 @@ pub struct MultiSigner {
     pub(crate) persister: Arc<dyn Persist>,
     pub(crate) test_mode: bool,
     pub(crate) initial_allowlist: Vec<String>,
-    validator_factory: Arc<dyn ValidatorFactory>,
+    validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
+    key_store: Option<Arc<dyn KeyStore>>,
 }
 
 impl MultiSigner {
@@ -42,7 +48,9 @@ impl MultiSigner {
     }
 
     /// Construct
-    pub fn new_with_validator(validator_factory: Arc<dyn ValidatorFactory>) -> MultiSigner {
+    pub fn new_with_validator(
+        validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
+    ) -> MultiSigner {
         let signer = MultiSigner::new_with_persister(
             Arc::new(DummyPersister),
             true,
@@ -58,7 +66,7 @@ impl MultiSigner {
         persister: Arc<dyn Persist>,
         test_mode: bool,
         initial_allowlist: Vec<String>,
-        validator_factory: Arc<dyn ValidatorFactory>,
+        validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
     ) -> MultiSigner {
         let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory.clone());
         MultiSigner {
@@ -67,26 +75,74 @@ impl MultiSigner {
             test_mode,
             initial_allowlist,
             validator_factory,
+            key_store: None,
         }
     }
 
+    /// Construct, with the node seeds in `key_store` instead of the
+    /// persister.  See [KeyStore].
+    pub fn new_with_key_store(
+        persister: Arc<dyn Persist>,
+        test_mode: bool,
+        initial_allowlist: Vec<String>,
+        validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
+        key_store: Arc<dyn KeyStore>,
+    ) -> MultiSigner {
+        let signer = MultiSigner {
+            nodes: Mutex::new(Map::new()),
+            persister,
+            test_mode,
+            initial_allowlist,
+            validator_factory,
+            key_store: Some(key_store),
+        };
+        {
+            let mut nodes = signer.nodes.lock().unwrap();
+            for (node_id, node_entry) in signer.persister.get_nodes().expect("nodes") {
+                nodes.insert(node_id, signer.restore_node(&node_id, node_entry));
+            }
+        }
+        signer
+    }
+
+    // Restore a node, with its seed from the key store if the persister
+    // doesn't have it
+    fn restore_node(&self, node_id: &PublicKey, mut node_entry: NodeEntry) -> Arc<Node> {
+        if let Some(key_store) = self.key_store.as_ref() {
+            if node_entry.seed.is_empty() {
+                node_entry.seed = key_store.get_seed(node_id).expect("seed in key store");
+            }
+        }
+        Node::restore_node(
+            node_id,
+            node_entry,
+            Arc::clone(&self.persister),
+            self.validator_factory.clone(),
+        )
+    }
+
     /// Create a node with a random seed
     #[cfg(feature = "std")]
-    pub fn new_node(&self, node_config: NodeConfig) -> PublicKey {
+    pub fn new_node(&self, node_config: NodeConfig) -> Result<PublicKey, Status> {
         let mut rng = OsRng::new().unwrap();
 
         let mut seed = [0; 32];
         rng.fill_bytes(&mut seed);
 
-        let node =
-            Node::new(node_config, &seed, &self.persister, vec![], self.validator_factory.clone());
+        let node = Node::new(
+            node_config.clone(),
+            &seed,
+            &self.persister,
+            vec![],
+            self.validator_factory.clone(),
+        )?;
         let node_id = node.get_id();
         let mut nodes = self.nodes.lock().unwrap();
         node.add_allowlist(&self.initial_allowlist).expect("valid initialallowlist");
-        self.persister.new_node(&node_id, &node_config, &seed);
-        self.persister.new_chain_tracker(&node_id, &node.get_tracker());
+        self.persist_new_node(&node, &node_config, &seed)?;
+        seed.zeroize();
         nodes.insert(node_id, Arc::new(node));
-        node_id
+        Ok(node_id)
     }
 
     /// Create a node with a random seed, given extended initialization parameters
@@ -95,14 +151,16 @@ impl MultiSigner {
         &self,
         node_config: NodeConfig,
         tracker: ChainTracker<ChainMonitor>,
-        validator_factory: Arc<dyn ValidatorFactory>,
+        validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
     ) -> PublicKey {
         let mut rng = OsRng::new().unwrap();
 
         let mut seed = [0; 32];
         rng.fill_bytes(&mut seed);
 
-        self.new_node_with_seed(node_config, tracker, validator_factory, seed)
+        let node_id = self.new_node_with_seed(node_config, tracker, validator_factory, seed);
+        seed.zeroize();
+        node_id
     }
 
     /// New node with externally supplied cryptographic seed
@@ -110,11 +168,11 @@ impl MultiSigner {
         &self,
         node_config: NodeConfig,
         tracker: ChainTracker<ChainMonitor>,
-        validator_factory: Arc<dyn ValidatorFactory>,
-        seed: [u8; 32],
+        validator_factory: Arc<dyn ValidatorFactory<CoreTypes>>,
+        mut seed: [u8; 32],
     ) -> PublicKey {
         let node = Node::new_extended(
-            node_config,
+            node_config.clone(),
             &seed,
             &self.persister,
             vec![],
@@ -124,8 +182,8 @@ impl MultiSigner {
         let node_id = node.get_id();
         let mut nodes = self.nodes.lock().unwrap();
         node.add_allowlist(&self.initial_allowlist).expect("valid initialallowlist");
-        self.persister.new_node(&node_id, &node_config, &seed);
-        self.persister.new_chain_tracker(&node_id, &node.get_tracker());
+        self.persist_new_node(&node, &node_config, &seed).expect("new node persist failed");
+        seed.zeroize();
         nodes.insert(node_id, Arc::new(node));
         node_id
     }
@@ -136,13 +194,20 @@ impl MultiSigner {
         node_config: NodeConfig,
         seed: &[u8],
     ) -> Result<PublicKey, Status> {
-        let node =
-            Node::new(node_config, &seed, &self.persister, vec![], self.validator_factory.clone());
+        let node = Node::new(
+            node_config.clone(),
+            &seed,
+            &self.persister,
+            vec![],
+            self.validator_factory.clone(),
+        )?;
         let node_id = node.get_id();
         let mut nodes = self.nodes.lock().unwrap();
         if self.test_mode {
             // In test mode we allow overwriting the node (thereby resetting all of its channels)
-            self.persister.delete_node(&node_id);
+            self.persister
+                .delete_node(&node_id)
+                .map_err(|e| persist_error("node delete failed", e))?;
         } else {
             // In production, the node must not have existed
 
@@ -151,12 +216,140 @@ impl MultiSigner {
             }
         }
         node.add_allowlist(&self.initial_allowlist).expect("valid initialallowlist");
-        self.persister.new_node(&node_id, &node_config, seed);
-        self.persister.new_chain_tracker(&node_id, &node.get_tracker());
+        self.persist_new_node(&node, &node_config, seed)?;
         nodes.insert(node_id, Arc::new(node));
         Ok(node_id)
     }
 
+    /// Move a node to a new random seed, after a suspected compromise of
+    /// its seed.
+    ///
+    /// The new node gets the configuration and the allowlist of the old node.
+    /// The old node signs a sweep of its wallet outputs in `inputs` to the
+    /// wallet of the new node, see [Node::plan_seed_migration], and stays
+    /// in the signer until its channels close.
+    #[cfg(feature = "std")]
+    pub fn migrate_node(
+        &self,
+        node_id: &PublicKey,
+        inputs: &Vec<InteractiveInput>,
+        fee_sat: u64,
+    ) -> Result<MigrationPlan, Status> {
+        let old_node = self.get_node(node_id)?;
+        let mut rng = OsRng::new().unwrap();
+
+        let mut seed = [0; 32];
+        rng.fill_bytes(&mut seed);
+
+        let node_config = old_node.node_config.clone();
+        let new_node = Node::new(
+            node_config.clone(),
+            &seed,
+            &self.persister,
+            vec![],
+            self.validator_factory.clone(),
+        )?;
+        let plan = old_node.plan_seed_migration(&new_node, inputs, fee_sat)?;
+
+        let new_node_id = new_node.get_id();
+        let mut nodes = self.nodes.lock().unwrap();
+        new_node.add_allowlist(&old_node.allowlist()?)?;
+        self.persist_new_node(&new_node, &node_config, &seed)?;
+        seed.zeroize();
+        nodes.insert(new_node_id, Arc::new(new_node));
+        Ok(plan)
+    }
+
+    fn persist_new_node(
+        &self,
+        node: &Node,
+        node_config: &NodeConfig,
+        seed: &[u8],
+    ) -> Result<(), Status> {
+        let node_id = node.get_id();
+        let persisted_seed: &[u8] = match self.key_store.as_ref() {
+            Some(key_store) => {
+                key_store
+                    .put_seed(&node_id, seed)
+                    .map_err(|e| persist_error("key store failed", e))?;
+                &[]
+            }
+            None => seed,
+        };
+        self.persister
+            .new_node(&node_id, node_config, persisted_seed)
+            .map_err(|e| persist_error("node persist failed", e))?;
+        self.persister
+            .new_chain_tracker(&node_id, &node.get_tracker())
+            .map_err(|e| persist_error("tracker persist failed", e))
+    }
+
+    /// Export all nodes as an encrypted archive, see [Persist::export_all]
+    pub fn export_backup(&self, passphrase: &str) -> Result<Vec<u8>, Status> {
+        self.persister.export_all(passphrase).map_err(|e| persist_error("backup export failed", e))
+    }
+
+    /// Import an archive created by [MultiSigner::export_backup] and start
+    /// signing for its nodes, which must not exist yet
+    pub fn import_backup(
+        &self,
+        passphrase: &str,
+        archive: &[u8],
+    ) -> Result<Vec<PublicKey>, Status> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let node_ids = self
+            .persister
+            .import_all(passphrase, archive)
+            .map_err(|e| persist_error("backup import failed", e))?;
+        let entries =
+            self.persister.get_nodes().map_err(|e| persist_error("node restore failed", e))?;
+        for (node_id, node_entry) in entries {
+            if node_ids.contains(&node_id) {
+                nodes.insert(node_id, self.restore_node(&node_id, node_entry));
+            }
+        }
+        info!("imported {} nodes from backup", node_ids.len());
+        Ok(node_ids)
+    }
+
+    /// Persist the chain tracker of every node and flush the persister,
+    /// before the process exits
+    pub fn flush(&self) -> Result<(), Status> {
+        let nodes = self.nodes.lock().unwrap();
+        for (node_id, node) in nodes.iter() {
+            self.persister
+                .update_tracker(node_id, &node.get_tracker())
+                .map_err(|e| persist_error("tracker persist failed", e))?;
+        }
+        self.persister.flush().map_err(|e| persist_error("flush failed", e))
+    }
+
+    /// Reload nodes and their channels from the persister, replacing them
+    /// in memory, such as on a standby signer that mirrors the state
+    /// written by its primary.  Nodes that are not in the persister
+    /// anymore are dropped.
+    pub fn reload_nodes(&self, node_ids: &[PublicKey]) -> Result<(), Status> {
+        let mut entries: Map<PublicKey, NodeEntry> = self
+            .persister
+            .get_nodes()
+            .map_err(|e| persist_error("node reload failed", e))?
+            .into_iter()
+            .filter(|(node_id, _)| node_ids.contains(node_id))
+            .collect();
+        let mut nodes = self.nodes.lock().unwrap();
+        for node_id in node_ids {
+            match entries.remove(node_id) {
+                Some(entry) => {
+                    nodes.insert(*node_id, self.restore_node(node_id, entry));
+                }
+                None => {
+                    nodes.remove(node_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Get all node IDs
     pub fn get_node_ids(&self) -> Vec<PublicKey> {
         let nodes = self.nodes.lock().unwrap();
@@ -169,13 +362,22 @@ impl MultiSigner {
         node_config: NodeConfig,
         seed: &[u8],
     ) -> Result<PublicKey, Status> {
+        let chain_params = node_config.chain_params();
         let node =
-            Node::new(node_config, &seed, &self.persister, vec![], self.validator_factory.clone());
+            Node::new(node_config, &seed, &self.persister, vec![], self.validator_factory.clone())?;
         let node_id = node.get_id();
         let nodes = self.nodes.lock().unwrap();
-        nodes.get(&node_id).ok_or_else(|| {
+        let existing = nodes.get(&node_id).ok_or_else(|| {
             invalid_argument(format!("warmstart failed: no such node: {}", node_id))
         })?;
+        // Don't allow the node to be pointed at a different chain, such as
+        // another signet
+        if existing.node_config.chain_params() != chain_params {
+            return Err(invalid_argument(format!(
+                "warmstart failed: chain params mismatch for node {}",
+                node_id
+            )));
+        }
         Ok(node_id)
     }
 
@@ -192,8 +394,7 @@ impl MultiSigner {
             } else if chan.setup.funding_outpoint != outpoint {
                 panic!("funding outpoint changed");
             }
-            self.persist_channel(node_id, chan);
-            Ok(())
+            self.persist_channel(node_id, chan)
         })
     }
 
@@ -211,7 +412,8 @@ impl MultiSigner {
         let mut slot = slot_arc.lock().unwrap();
         let base = match &mut *slot {
             ChannelSlot::Stub(stub) => stub as &mut ChannelBase,
-            ChannelSlot::Ready(chan) => chan as &mut ChannelBase,
+            ChannelSlot::Ready(chan) | ChannelSlot::Closing(chan) | ChannelSlot::Closed(chan) =>
+                chan as &mut ChannelBase,
         };
         f(base)
     }
@@ -242,23 +444,30 @@ impl MultiSigner {
     where
         F: Fn(&mut Channel) -> Result<T, Status>,
     {
-        let slot_arc = self.get_channel(&node_id, &channel_id)?;
-        let mut slot = slot_arc.lock().unwrap();
-        match &mut *slot {
-            ChannelSlot::Stub(_) =>
-                Err(invalid_argument(format!("channel not ready: {}", &channel_id))),
-            ChannelSlot::Ready(chan) => f(chan),
-        }
+        self.get_node(node_id)?.with_ready_channel(channel_id, f)
+    }
+
+    /// See [`Node::with_ready_or_closing_channel`]
+    pub fn with_ready_or_closing_channel<F: Sized, T>(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+        f: F,
+    ) -> Result<T, Status>
+    where
+        F: Fn(&mut Channel) -> Result<T, Status>,
+    {
+        self.get_node(node_id)?.with_ready_or_closing_channel(channel_id, f)
     }
 
-    fn persist_channel(&self, node_id: &PublicKey, chan: &Channel) {
+    fn persist_channel(&self, node_id: &PublicKey, chan: &Channel) -> Result<(), Status> {
         self.persister
             .update_channel(&node_id, &chan)
-            .expect("channel was in storage but not in memory");
+            .map_err(|e| persist_error("channel persist failed", e))
     }
 
     /// Get the configured validator factory
-    pub fn validator_factory(&self) -> Arc<dyn ValidatorFactory> {
+    pub fn validator_factory(&self) -> Arc<dyn ValidatorFactory<CoreTypes>> {
         self.validator_factory.clone()
     }
 }
@@ -268,7 +477,13 @@ mod tests {
     use crate::util::status::Code;
     use crate::util::test_utils::hex_decode;
     use crate::util::test_utils::*;
+    use bitcoin::blockdata::constants::genesis_block;
     use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::Network;
+
+    use crate::node::ChainParams;
+    use crate::persist::memory::MemoryPersister;
+    use crate::signer::key_store::MemoryKeyStore;
 
     use super::*;
 
@@ -292,6 +507,45 @@ mod tests {
         let result = signer.warmstart_with_seed(TEST_NODE_CONFIG, &seed);
         assert!(!result.is_err());
         assert_eq!(result.unwrap(), node_id);
+
+        // But not on a different chain
+        let mut config = TEST_NODE_CONFIG;
+        config.chain_params = Some(ChainParams {
+            genesis: genesis_block(Network::Regtest).header,
+            signet_challenge: None,
+        });
+        let result = signer.warmstart_with_seed(config, &seed);
+        assert_eq!(
+            result.unwrap_err().message(),
+            format!("warmstart failed: chain params mismatch for node {}", node_id)
+        );
+    }
+
+    #[test]
+    fn key_store_test() {
+        let persister: Arc<dyn Persist> = Arc::new(MemoryPersister::new());
+        let key_store = Arc::new(MemoryKeyStore::new());
+        let make_signer = || {
+            MultiSigner::new_with_key_store(
+                Arc::clone(&persister),
+                false,
+                vec![],
+                Arc::new(SimpleValidatorFactory::new()),
+                key_store.clone(),
+            )
+        };
+        let signer = make_signer();
+        let seed = hex_decode(TEST_SEED[1]).unwrap();
+        let node_id = signer.new_node_from_seed(TEST_NODE_CONFIG, &seed).unwrap();
+
+        // the seed is only in the key store
+        let nodes = persister.get_nodes().unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert!(nodes[0].1.seed.is_empty());
+        assert_eq!(key_store.get_seed(&node_id).unwrap(), seed);
+
+        let signer = make_signer();
+        assert_eq!(signer.get_node_ids(), vec![node_id]);
     }
 
     #[test]
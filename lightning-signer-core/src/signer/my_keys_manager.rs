@@ -25,7 +25,8 @@ use lightning::ln::script::ShutdownScript;
 use crate::channel::ChannelId;
 use crate::util::crypto_utils::{
     channels_seed, derive_key_lnd, get_account_extended_key_lnd, get_account_extended_key_native,
-    hkdf_sha256, hkdf_sha256_keys, node_keys_lnd, node_keys_native,
+    hkdf_sha256, hkdf_sha256_keys, node_keys_ldk, node_keys_lnd, node_keys_native,
+    scrub_extended_key, scrub_secret_key,
 };
 use crate::util::transaction_utils::MAX_VALUE_MSAT;
 use crate::util::{byte_utils, transaction_utils};
@@ -35,6 +36,7 @@ use bitcoin::util::bip143;
 use hashbrown::HashSet as UnorderedSet;
 use lightning::util::invoice::construct_invoice_preimage;
 use secp256k1_xonly::XOnlyPublicKey;
+use zeroize::Zeroize;
 
 /// The key derivation style
 #[derive(Clone, Copy, Debug)]
@@ -43,16 +45,20 @@ pub enum KeyDerivationStyle {
     Native = 1,
     /// The LND style
     Lnd = 2,
+    /// The style of the LDK `KeysManager`, for nodes imported from LDK.
+    /// The layer-1 wallet is BIP84, as with LND.
+    Ldk = 3,
 }
 
 impl TryFrom<u8> for KeyDerivationStyle {
     type Error = ();
 
     fn try_from(v: u8) -> Result<Self, Self::Error> {
-        use KeyDerivationStyle::{Lnd, Native};
+        use KeyDerivationStyle::{Ldk, Lnd, Native};
         match v {
             x if x == Native as u8 => Ok(Native),
             x if x == Lnd as u8 => Ok(Lnd),
+            x if x == Ldk as u8 => Ok(Ldk),
             _ => Err(()),
         }
     }
@@ -67,6 +73,8 @@ impl KeyDerivationStyle {
             // lnd uses two BIP32 branches, one for external and one
             // for internal (change) addresses.
             KeyDerivationStyle::Lnd => 2,
+            // BIP84, as with lnd
+            KeyDerivationStyle::Ldk => 2,
         }
     }
 
@@ -78,7 +86,8 @@ impl KeyDerivationStyle {
     ) -> ExtendedPrivKey {
         match self {
             KeyDerivationStyle::Native => get_account_extended_key_native(secp_ctx, network, seed),
-            KeyDerivationStyle::Lnd => get_account_extended_key_lnd(secp_ctx, network, seed),
+            KeyDerivationStyle::Lnd | KeyDerivationStyle::Ldk =>
+                get_account_extended_key_lnd(secp_ctx, network, seed),
         }
     }
 }
@@ -92,6 +101,7 @@ pub struct MyKeysManager {
     master_key: ExtendedPrivKey,
     node_secret: SecretKey,
     bolt12_keypair: KeyPair,
+    audit_secret: SecretKey,
     inbound_payment_key: KeyMaterial,
     channel_seed_base: [u8; 32],
     account_extended_key: ExtendedPrivKey,
@@ -128,6 +138,7 @@ impl MyKeysManager {
         let (_, node_secret) = match key_derivation_style {
             KeyDerivationStyle::Native => node_keys_native(&secp_ctx, seed),
             KeyDerivationStyle::Lnd => node_keys_lnd(&secp_ctx, network.clone(), master_key),
+            KeyDerivationStyle::Ldk => node_keys_ldk(&secp_ctx, master_key),
         };
         let destination_script =
             match master_key.ckd_priv(&secp_ctx, ChildNumber::from_hardened_idx(1).unwrap()) {
@@ -170,13 +181,14 @@ impl MyKeysManager {
         let rand_bytes_master_key = master_key
             .ckd_priv(&secp_ctx, ChildNumber::from_hardened_idx(4).unwrap())
             .expect("Your RNG is busted");
-        let inbound_payment_key: SecretKey = master_key
+        let mut inbound_payment_key: SecretKey = master_key
             .ckd_priv(&secp_ctx, ChildNumber::from_hardened_idx(5).unwrap())
             .expect("Your RNG is busted")
             .private_key
             .key;
         let mut inbound_pmt_key_bytes = [0; 32];
         inbound_pmt_key_bytes.copy_from_slice(&inbound_payment_key[..]);
+        scrub_secret_key(&mut inbound_payment_key);
 
         let mut rand_bytes_unique_start = Sha256::engine();
         rand_bytes_unique_start.input(&byte_utils::be64_to_array(starting_time_secs));
@@ -188,6 +200,8 @@ impl MyKeysManager {
             .expect("Your RNG is busted")
             .private_key;
         let bolt12_keypair = KeyPair::from_secret_key(&secp_ctx, bolt12_child.key);
+        let audit_secret = SecretKey::from_slice(&hkdf_sha256(seed, "audit key".as_bytes(), &[]))
+            .expect("audit key");
         let mut res = MyKeysManager {
             secp_ctx,
             seed: seed.to_vec(),
@@ -196,6 +210,7 @@ impl MyKeysManager {
             master_key,
             node_secret,
             bolt12_keypair,
+            audit_secret,
             inbound_payment_key: KeyMaterial(inbound_pmt_key_bytes),
             channel_seed_base,
             account_extended_key,
@@ -217,19 +232,25 @@ impl MyKeysManager {
         res
     }
 
+    /// The key that signs the audit log
+    pub fn get_audit_secret(&self) -> &SecretKey {
+        &self.audit_secret
+    }
+
     /// BOLT 12 x-only pubkey
     pub fn get_bolt12_pubkey(&self) -> XOnlyPublicKey {
         XOnlyPublicKey::from_keypair(&self.bolt12_keypair)
     }
 
-    /// BOLT 12 sign
+    /// BOLT 12 sign.  Also returns the signed message and the public key,
+    /// for verification of the signature.
     pub fn sign_bolt12(
         &self,
         messagename: &[u8],
         fieldname: &[u8],
         merkleroot: &[u8; 32],
         publictweak_opt: Option<&[u8]>,
-    ) -> Result<schnorrsig::Signature, ()> {
+    ) -> Result<(schnorrsig::Signature, Message, schnorrsig::PublicKey), ()> {
         // BIP340 init
         let mut sha = Sha256::engine();
         sha.input("lightning".as_bytes());
@@ -257,7 +278,10 @@ impl MyKeysManager {
             KeyPair::from_secret_key(&self.secp_ctx, self.node_secret)
         };
         let msg = Message::from_slice(&sig_hash).unwrap();
-        Ok(self.secp_ctx.schnorrsig_sign_no_aux_rand(&msg, &kp))
+        let pubkey =
+            schnorrsig::PublicKey::from_slice(&XOnlyPublicKey::from_keypair(&kp).serialize())
+                .map_err(|_| ())?;
+        Ok((self.secp_ctx.schnorrsig_sign_no_aux_rand(&msg, &kp), msg, pubkey))
     }
 
     /// Get the layer-1 xpub
@@ -301,6 +325,8 @@ impl MyKeysManager {
             ),
             KeyDerivationStyle::Lnd =>
                 self.get_channel_keys_with_nonce_lnd(channel_id, channel_nonce, channel_value_sat),
+            KeyDerivationStyle::Ldk =>
+                self.get_channel_keys_with_nonce_ldk(channel_id, channel_nonce, channel_value_sat),
         };
         self.id_to_nonce.lock().unwrap().insert(channel_id, channel_nonce.to_vec());
         res
@@ -313,10 +339,11 @@ impl MyKeysManager {
         channel_value_sat: u64,
     ) -> InMemorySigner {
         let hkdf_info = "c-lightning";
-        let channel_seed =
+        let mut channel_seed =
             hkdf_sha256(&self.channel_seed_base, "per-peer seed".as_bytes(), channel_nonce);
 
-        let keys_buf = hkdf_sha256_keys(&channel_seed, hkdf_info.as_bytes(), &[]);
+        let mut keys_buf = hkdf_sha256_keys(&channel_seed, hkdf_info.as_bytes(), &[]);
+        channel_seed.zeroize();
         let mut ndx = 0;
         let funding_key = SecretKey::from_slice(&keys_buf[ndx..ndx + 32]).unwrap();
         ndx += 32;
@@ -329,6 +356,7 @@ impl MyKeysManager {
         let delayed_payment_base_key = SecretKey::from_slice(&keys_buf[ndx..ndx + 32]).unwrap();
         ndx += 32;
         let commitment_seed = keys_buf[ndx..ndx + 32].try_into().unwrap();
+        keys_buf.zeroize();
         let secp_ctx = Secp256k1::signing_only();
         InMemorySigner::new(
             &secp_ctx,
@@ -354,9 +382,10 @@ impl MyKeysManager {
         // native (really c-lightning) version.
         //
         let hkdf_info = "c-lightning";
-        let channel_seed =
+        let mut channel_seed =
             hkdf_sha256(&self.channel_seed_base, "per-peer seed".as_bytes(), channel_nonce);
-        let keys_buf = hkdf_sha256_keys(&channel_seed, hkdf_info.as_bytes(), &[]);
+        let mut keys_buf = hkdf_sha256_keys(&channel_seed, hkdf_info.as_bytes(), &[]);
+        channel_seed.zeroize();
         let mut ndx = 0;
         ndx += 32;
         ndx += 32;
@@ -364,6 +393,7 @@ impl MyKeysManager {
         ndx += 32;
         ndx += 32;
         let commitment_seed = keys_buf[ndx..ndx + 32].try_into().unwrap();
+        keys_buf.zeroize();
 
         let secp_ctx = Secp256k1::new();
 
@@ -399,6 +429,66 @@ impl MyKeysManager {
         )
     }
 
+    // Must match KeysManager::derive_channel_keys in LDK, where the nonce
+    // is the 32 byte channel_keys_id that LDK starts with the BIP32 child
+    // index of the channel.  Other nonces are hashed to 32 bytes first.
+    fn get_channel_keys_with_nonce_ldk(
+        &self,
+        channel_id: ChannelId,
+        channel_nonce: &[u8],
+        channel_value_sat: u64,
+    ) -> InMemorySigner {
+        let params: [u8; 32] = if channel_nonce.len() == 32 {
+            channel_nonce.try_into().unwrap()
+        } else {
+            Sha256::hash(channel_nonce).into_inner()
+        };
+        let child_ix = (byte_utils::slice_to_be64(&params[0..8]) & 0x7fff_ffff) as u32;
+        let mut unique_start = Sha256::engine();
+        unique_start.input(&params);
+        unique_start.input(&self.seed);
+        let mut child_privkey = self
+            .channel_master_key
+            .ckd_priv(&self.secp_ctx, ChildNumber::from_hardened_idx(child_ix).unwrap())
+            .expect("Your RNG is busted");
+        unique_start.input(&child_privkey.private_key.key[..]);
+        scrub_extended_key(&mut child_privkey);
+        let mut seed = Sha256::from_engine(unique_start).into_inner();
+
+        let commitment_seed = {
+            let mut sha = Sha256::engine();
+            sha.input(&seed);
+            sha.input(&b"commitment seed"[..]);
+            Sha256::from_engine(sha).into_inner()
+        };
+        let key_step = |info: &[u8], prev_key: &[u8]| {
+            let mut sha = Sha256::engine();
+            sha.input(&seed);
+            sha.input(prev_key);
+            sha.input(info);
+            SecretKey::from_slice(&Sha256::from_engine(sha).into_inner()).expect("SHA is busted")
+        };
+        let funding_key = key_step(b"funding key", &commitment_seed);
+        let revocation_base_key = key_step(b"revocation base key", &funding_key[..]);
+        let payment_key = key_step(b"payment key", &revocation_base_key[..]);
+        let delayed_payment_base_key = key_step(b"delayed payment base key", &payment_key[..]);
+        let htlc_base_key = key_step(b"HTLC base key", &delayed_payment_base_key[..]);
+        seed.zeroize();
+
+        InMemorySigner::new(
+            &self.secp_ctx,
+            self.get_node_secret(Recipient::Node).unwrap(),
+            funding_key,
+            revocation_base_key,
+            payment_key,
+            delayed_payment_base_key,
+            htlc_base_key,
+            commitment_seed,
+            channel_value_sat,
+            channel_id.0,
+        )
+    }
+
     pub(crate) fn get_channel_id(&self) -> [u8; 32] {
         let mut sha = self.unique_start.clone();
 
@@ -590,6 +680,24 @@ impl MyKeysManager {
     }
 }
 
+// Scrub the seed and the secrets derived from it, so that they don't linger
+// in freed memory.
+impl Drop for MyKeysManager {
+    fn drop(&mut self) {
+        self.seed.zeroize();
+        self.channel_seed_base.zeroize();
+        self.inbound_payment_key.0.zeroize();
+        scrub_secret_key(&mut self.node_secret);
+        scrub_secret_key(&mut self.audit_secret);
+        self.bolt12_keypair = KeyPair::from_secret_key(&self.secp_ctx, secp256k1::ONE_KEY);
+        scrub_extended_key(&mut self.master_key);
+        scrub_extended_key(&mut self.account_extended_key);
+        scrub_extended_key(&mut self.channel_master_key);
+        scrub_extended_key(&mut self.channel_id_master_key);
+        scrub_extended_key(&mut self.rand_bytes_master_key);
+    }
+}
+
 impl KeysInterface for MyKeysManager {
     type Signer = InMemorySigner;
 
@@ -659,7 +767,7 @@ mod tests {
     use crate::util::INITIAL_COMMITMENT_NUMBER;
 
     use super::*;
-    use lightning::chain::keysinterface::BaseSign;
+    use lightning::chain::keysinterface::{BaseSign, KeysManager};
 
     use crate::util::test_utils::hex_encode;
     use test_log::test;
@@ -737,6 +845,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn keys_test_ldk() {
+        let seed = [3u8; 32];
+        let manager = MyKeysManager::new(KeyDerivationStyle::Ldk, &seed, Network::Testnet, 0, 0);
+        let ldk_manager = KeysManager::new(&seed, 0, 0);
+        assert_eq!(
+            manager.get_node_secret(Recipient::Node).unwrap(),
+            ldk_manager.get_node_secret(Recipient::Node).unwrap()
+        );
+        // LDK puts the child index of the channel at the start of the keys ID
+        let mut params = [0u8; 32];
+        params[7] = 5;
+        params[20] = 9;
+        let keys = manager.get_channel_keys_with_id(ChannelId([0u8; 32]), &params, 0);
+        let ldk_keys = ldk_manager.derive_channel_keys(0, &params);
+        assert_eq!(keys.funding_key, ldk_keys.funding_key);
+        assert_eq!(keys.revocation_base_key, ldk_keys.revocation_base_key);
+        assert_eq!(keys.payment_key, ldk_keys.payment_key);
+        assert_eq!(keys.delayed_payment_base_key, ldk_keys.delayed_payment_base_key);
+        assert_eq!(keys.htlc_base_key, ldk_keys.htlc_base_key);
+        assert_eq!(keys.commitment_seed, ldk_keys.commitment_seed);
+    }
+
     #[test]
     fn per_commit_test() -> Result<(), ()> {
         let manager =
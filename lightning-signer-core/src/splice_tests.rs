@@ -0,0 +1,266 @@
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Message, Secp256k1, Signature};
+    use bitcoin::util::bip143::SigHashCache;
+    use bitcoin::{OutPoint, Script, SigHashType, Transaction, TxIn, TxOut, Txid};
+    use lightning::ln::chan_utils::make_funding_redeemscript;
+
+    use test_log::test;
+
+    use crate::chain::tracker::ChainListener;
+    use crate::channel::ChannelSetup;
+    use crate::tx::interactive::{InteractiveInput, InteractiveOutput};
+    use crate::tx::tx::CommitmentInfo2;
+    use crate::util::key_utils::make_test_pubkey;
+    use crate::util::status::{Code, Status};
+    use crate::util::test_utils::*;
+
+    #[allow(unused_imports)]
+    use log::debug;
+
+    const CHANNEL_VALUE: u64 = 3_000_000;
+    const INPUT_VALUE: u64 = 2_000_000;
+
+    struct SpliceTestState {
+        node_ctx: TestNodeContext,
+        chan_ctx: TestChannelContext,
+        setup: ChannelSetup,
+        inputs: Vec<InteractiveInput>,
+        outputs: Vec<InteractiveOutput>,
+    }
+
+    // Fund a channel with a wallet input to splice in.  If quiescent, the
+    // counterparty also has its initial commitment.
+    fn setup_splice(quiescent: bool) -> SpliceTestState {
+        let node_ctx = test_node_ctx(1);
+        let chan_ctx = fund_test_channel(&node_ctx, CHANNEL_VALUE);
+        let setup = node_ctx
+            .node
+            .with_ready_channel(&chan_ctx.channel_id, |chan| {
+                if !quiescent {
+                    return Ok(chan.setup.clone());
+                }
+                let estate = &mut chan.enforcement_state;
+                let holder_info = estate.current_holder_commit_info.clone().unwrap();
+                estate.set_next_counterparty_commit_num_for_testing(1, make_test_pubkey(0x10));
+                estate.current_counterparty_commit_info = Some(CommitmentInfo2 {
+                    is_counterparty_broadcaster: true,
+                    to_countersigner_value_sat: holder_info.to_broadcaster_value_sat,
+                    to_broadcaster_value_sat: holder_info.to_countersigner_value_sat,
+                    ..holder_info
+                });
+                Ok(chan.setup.clone())
+            })
+            .expect("ready");
+
+        let input = InteractiveInput {
+            outpoint: OutPoint { txid: Txid::from_slice(&[9u8; 32]).unwrap(), vout: 1 },
+            prev_output: TxOut {
+                value: INPUT_VALUE,
+                script_pubkey: make_test_funding_wallet_addr(
+                    &node_ctx.secp_ctx,
+                    &node_ctx.node,
+                    1,
+                    false,
+                )
+                .script_pubkey(),
+            },
+            ipath: vec![1],
+        };
+        SpliceTestState { node_ctx, chan_ctx, setup, inputs: vec![input], outputs: vec![] }
+    }
+
+    fn add_change(state: &mut SpliceTestState, value: u64) {
+        let node_ctx = &state.node_ctx;
+        let output =
+            make_test_funding_wallet_output(&node_ctx.secp_ctx, &node_ctx.node, 2, value, false);
+        state.outputs.push(InteractiveOutput { output, opath: vec![2] });
+    }
+
+    fn make_txin(previous_output: OutPoint) -> TxIn {
+        TxIn { previous_output, script_sig: Script::new(), sequence: 0, witness: vec![] }
+    }
+
+    // The splice transaction, with the new funding output first
+    fn make_splice_tx(state: &SpliceTestState, new_value: u64) -> Transaction {
+        let mut inputs = vec![make_txin(state.setup.funding_outpoint)];
+        inputs.extend(state.inputs.iter().map(|i| make_txin(i.outpoint)));
+        let mut outputs = vec![make_test_funding_channel_outpoint(
+            &state.node_ctx.node,
+            &state.setup,
+            &state.chan_ctx.channel_id,
+            new_value,
+        )];
+        outputs.extend(state.outputs.iter().map(|o| o.output.clone()));
+        make_test_funding_tx_with_ins_outs(inputs, outputs)
+    }
+
+    fn begin_splice(
+        state: &SpliceTestState,
+        tx: &Transaction,
+    ) -> Result<(Signature, Vec<Vec<Vec<u8>>>), Status> {
+        state.node_ctx.node.begin_splice(
+            &state.chan_ctx.channel_id,
+            tx,
+            0,
+            &state.inputs,
+            &state.outputs,
+        )
+    }
+
+    fn get_setup(state: &SpliceTestState) -> ChannelSetup {
+        state
+            .node_ctx
+            .node
+            .with_ready_channel(&state.chan_ctx.channel_id, |chan| Ok(chan.setup.clone()))
+            .unwrap()
+    }
+
+    #[test]
+    fn splice_in_test() {
+        let mut state = setup_splice(true);
+        add_change(&mut state, 499_000);
+        let tx = make_splice_tx(&state, CHANNEL_VALUE + 1_500_000);
+        let (sig, witvec) = begin_splice(&state, &tx).expect("splice");
+
+        // the funding input is signed with the funding key
+        let node = &state.node_ctx.node;
+        let funding_pubkey = get_channel_funding_pubkey(node, &state.chan_ctx.channel_id);
+        let redeemscript = make_funding_redeemscript(
+            &funding_pubkey,
+            &state.setup.counterparty_points.funding_pubkey,
+        );
+        let sighash = SigHashCache::new(&tx).signature_hash(
+            0,
+            &redeemscript,
+            CHANNEL_VALUE,
+            SigHashType::All,
+        );
+        let message = Message::from_slice(&sighash[..]).unwrap();
+        Secp256k1::verification_only().verify(&message, &sig, &funding_pubkey).unwrap();
+
+        // only our wallet input has a witness
+        assert_eq!(witvec.len(), 2);
+        assert!(witvec[0].is_empty());
+        assert_eq!(witvec[1].len(), 2);
+
+        let setup = get_setup(&state);
+        assert_eq!(setup.funding_outpoint, OutPoint::new(tx.txid(), 0));
+        assert_eq!(setup.channel_value_sat, CHANNEL_VALUE + 1_500_000);
+        let splice = node
+            .with_ready_channel(&state.chan_ctx.channel_id, |chan| {
+                Ok(chan.enforcement_state.splice.clone())
+            })
+            .unwrap()
+            .expect("splice state");
+        assert_eq!(splice.previous_outpoint, state.setup.funding_outpoint);
+        assert_eq!(splice.previous_value_sat, CHANNEL_VALUE);
+
+        // a second splice can't start until this one is locked
+        assert_failed_precondition_err!(
+            begin_splice(&state, &tx),
+            "policy failure: validate_splice_tx: splice already in progress"
+        );
+    }
+
+    #[test]
+    fn splice_not_quiescent_test() {
+        let mut state = setup_splice(false);
+        add_change(&mut state, 499_000);
+        let tx = make_splice_tx(&state, CHANNEL_VALUE + 1_500_000);
+        assert_failed_precondition_err!(
+            begin_splice(&state, &tx),
+            "policy failure: validate_splice_tx: channel is not quiescent"
+        );
+    }
+
+    #[test]
+    fn splice_unknown_input_test() {
+        let mut state = setup_splice(true);
+        add_change(&mut state, 499_000);
+        let mut tx = make_splice_tx(&state, CHANNEL_VALUE + 1_500_000);
+        let outpoint = OutPoint { txid: Txid::from_slice(&[8u8; 32]).unwrap(), vout: 0 };
+        tx.input.push(make_txin(outpoint));
+        assert_failed_precondition_err!(
+            begin_splice(&state, &tx),
+            format!("policy failure: validate_splice_tx: unknown input {}", outpoint)
+        );
+    }
+
+    #[test]
+    fn splice_value_not_conserved_test() {
+        let mut state = setup_splice(true);
+        add_change(&mut state, 499_000);
+        let tx = make_splice_tx(&state, CHANNEL_VALUE + 1_601_000);
+        assert_failed_precondition_err!(
+            begin_splice(&state, &tx),
+            "policy failure: validate_splice_tx: validate_beneficial_value: \
+             non-beneficial value underflow: \
+             sum of our inputs 5000000 < sum of our outputs 5100000"
+        );
+    }
+
+    #[test]
+    fn splice_out_exceeds_balance_test() {
+        let mut state = setup_splice(true);
+        state.inputs.clear();
+        add_change(&mut state, 2_999_000);
+        let tx = make_splice_tx(&state, 0);
+        assert_failed_precondition_err!(
+            begin_splice(&state, &tx),
+            "policy failure: validate_splice_tx: splice-out exceeds holder balance: \
+             3000000 > 2999000"
+        );
+    }
+
+    #[test]
+    fn splice_rollback_test() {
+        let mut state = setup_splice(true);
+        add_change(&mut state, 499_000);
+        let tx = make_splice_tx(&state, CHANNEL_VALUE + 1_500_000);
+        begin_splice(&state, &tx).expect("splice");
+        let node = &state.node_ctx.node;
+        let channel_id = &state.chan_ctx.channel_id;
+
+        // the splice can't be locked before it confirms
+        assert_failed_precondition_err!(
+            node.with_ready_channel(channel_id, |chan| chan.splice_locked()),
+            "splice transaction not confirmed"
+        );
+
+        // the splice confirms and is reorged-out
+        let monitor = node.with_ready_channel(channel_id, |chan| Ok(chan.monitor.clone())).unwrap();
+        monitor.on_add_block(vec![&tx]);
+        assert_eq!(monitor.splice_depth(), 1);
+        monitor.on_remove_block(vec![&tx]);
+
+        // the channel is back on the previous funding
+        let setup = get_setup(&state);
+        assert_eq!(setup.funding_outpoint, state.setup.funding_outpoint);
+        assert_eq!(setup.channel_value_sat, CHANNEL_VALUE);
+        assert_failed_precondition_err!(
+            node.with_ready_channel(channel_id, |chan| chan.rollback_splice()),
+            "no splice in progress"
+        );
+    }
+
+    #[test]
+    fn splice_locked_test() {
+        let mut state = setup_splice(true);
+        add_change(&mut state, 499_000);
+        let tx = make_splice_tx(&state, CHANNEL_VALUE + 1_500_000);
+        begin_splice(&state, &tx).expect("splice");
+        let node = &state.node_ctx.node;
+        let channel_id = &state.chan_ctx.channel_id;
+
+        let monitor = node.with_ready_channel(channel_id, |chan| Ok(chan.monitor.clone())).unwrap();
+        monitor.on_add_block(vec![&tx]);
+        node.with_ready_channel(channel_id, |chan| chan.splice_locked()).expect("locked");
+        assert_failed_precondition_err!(
+            node.with_ready_channel(channel_id, |chan| chan.rollback_splice()),
+            "no splice in progress"
+        );
+        assert_eq!(get_setup(&state).funding_outpoint, OutPoint::new(tx.txid(), 0));
+    }
+}
@@ -0,0 +1,117 @@
+use crate::prelude::*;
+
+use bitcoin::{OutPoint, Transaction, TxOut};
+
+/// A wallet input we contribute to an interactively constructed
+/// (dual-funded) funding transaction
+#[derive(Clone, Debug, PartialEq)]
+pub struct InteractiveInput {
+    /// The outpoint being spent
+    pub outpoint: OutPoint,
+    /// The output being spent
+    pub prev_output: TxOut,
+    /// The derivation path of the wallet key spending the output
+    pub ipath: Vec<u32>,
+}
+
+/// An output we contribute to an interactively constructed funding
+/// transaction - change to our wallet or to an allowlisted address
+#[derive(Clone, Debug, PartialEq)]
+pub struct InteractiveOutput {
+    /// The output
+    pub output: TxOut,
+    /// The derivation path of the wallet key, or empty if allowlisted
+    pub opath: Vec<u32>,
+}
+
+/// Our side of an interactive funding transaction construction
+/// (BOLT-2 interactive-tx).
+///
+/// The counterparty's inputs and outputs are not tracked - everything in
+/// the final transaction that is not listed here belongs to them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InteractiveFunding {
+    /// Our contribution to the channel value
+    pub holder_contribution_sat: u64,
+    /// Our inputs, in the order they were added
+    pub inputs: Vec<InteractiveInput>,
+    /// Our outputs, in the order they were added
+    pub outputs: Vec<InteractiveOutput>,
+}
+
+impl InteractiveFunding {
+    /// Start a construction with our contribution to the channel value
+    pub fn new(holder_contribution_sat: u64) -> Self {
+        InteractiveFunding { holder_contribution_sat, inputs: Vec::new(), outputs: Vec::new() }
+    }
+
+    /// The total value of our inputs, or None on overflow
+    pub fn inputs_sat(&self) -> Option<u64> {
+        self.inputs.iter().try_fold(0u64, |sum, i| sum.checked_add(i.prev_output.value))
+    }
+
+    /// The transaction input index of each of our inputs, or None if
+    /// the input is missing from the transaction
+    pub fn input_indices(&self, tx: &Transaction) -> Vec<Option<usize>> {
+        self.inputs
+            .iter()
+            .map(|i| tx.input.iter().position(|txin| txin.previous_output == i.outpoint))
+            .collect()
+    }
+
+    /// The transaction output index of each of our outputs, or None if
+    /// the output is missing from the transaction.  Each transaction output
+    /// is matched at most once.
+    pub fn output_indices(&self, tx: &Transaction) -> Vec<Option<usize>> {
+        let mut used = vec![false; tx.output.len()];
+        self.outputs
+            .iter()
+            .map(|o| {
+                let found = tx
+                    .output
+                    .iter()
+                    .enumerate()
+                    .position(|(ndx, txout)| !used[ndx] && *txout == o.output);
+                if let Some(ndx) = found {
+                    used[ndx] = true;
+                }
+                found
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{Script, TxIn, Txid};
+
+    #[test]
+    fn output_indices_test() {
+        let out = TxOut { value: 1000, script_pubkey: Script::new() };
+        let mut funding = InteractiveFunding::new(5000);
+        funding.outputs.push(InteractiveOutput { output: out.clone(), opath: vec![1] });
+        funding.outputs.push(InteractiveOutput { output: out.clone(), opath: vec![2] });
+        let outpoint = OutPoint { txid: Txid::default(), vout: 0 };
+        funding.inputs.push(InteractiveInput {
+            outpoint,
+            prev_output: TxOut { value: 7000, script_pubkey: Script::new() },
+            ipath: vec![0],
+        });
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                script_sig: Script::new(),
+                sequence: 0xfffffffd,
+                witness: vec![],
+            }],
+            output: vec![out.clone()],
+        };
+        assert_eq!(funding.inputs_sat(), Some(7000));
+        assert_eq!(funding.input_indices(&tx), vec![Some(0)]);
+        // the same transaction output can't account for two of ours
+        assert_eq!(funding.output_indices(&tx), vec![Some(0), None]);
+    }
+}
@@ -1,4 +1,9 @@
+/// Interactive (dual-funded) funding transaction construction
+pub mod interactive;
 /// Script parsing and construction
 pub mod script;
+/// option_simple_close closing transaction construction
+#[cfg(feature = "simple_close")]
+pub mod simple_close;
 /// Transaction parsing and construction
 pub mod tx;
@@ -2,10 +2,11 @@ use crate::prelude::*;
 use bitcoin::blockdata::opcodes;
 use bitcoin::blockdata::opcodes::Class;
 use bitcoin::blockdata::script::{read_scriptint, Builder, Instruction, Instructions};
-use bitcoin::hash_types::WPubkeyHash;
+use bitcoin::hash_types::{PubkeyHash, WPubkeyHash};
 use bitcoin::hashes::Hash;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::{blockdata, Script};
+use lightning::ln::chan_utils::TxCreationKeys;
 
 use crate::policy::error::{mismatch_error, ValidationError};
 
@@ -101,6 +102,72 @@ pub(crate) fn get_delayed_redeemscript(delayed_key: &PublicKey) -> Script {
         .into_script()
 }
 
+/// The redeemscript of an HTLC output in a commitment transaction,
+/// given the hash160 of the payment hash.
+// TODO - Mirrors chan_utils::get_htlc_redeemscript, which needs the payment hash itself
+pub(crate) fn get_htlc_redeemscript_with_hash160(
+    offered: bool,
+    payment_hash160: &[u8],
+    cltv_expiry: u32,
+    option_anchor_outputs: bool,
+    keys: &TxCreationKeys,
+) -> Script {
+    let mut bldr = Builder::new()
+        .push_opcode(opcodes::all::OP_DUP)
+        .push_opcode(opcodes::all::OP_HASH160)
+        .push_slice(&PubkeyHash::hash(&keys.revocation_key.serialize())[..])
+        .push_opcode(opcodes::all::OP_EQUAL)
+        .push_opcode(opcodes::all::OP_IF)
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .push_opcode(opcodes::all::OP_ELSE)
+        .push_slice(&keys.countersignatory_htlc_key.serialize()[..])
+        .push_opcode(opcodes::all::OP_SWAP)
+        .push_opcode(opcodes::all::OP_SIZE)
+        .push_int(32)
+        .push_opcode(opcodes::all::OP_EQUAL);
+    if offered {
+        bldr = bldr
+            .push_opcode(opcodes::all::OP_NOTIF)
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_int(2)
+            .push_opcode(opcodes::all::OP_SWAP)
+            .push_slice(&keys.broadcaster_htlc_key.serialize()[..])
+            .push_int(2)
+            .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+            .push_opcode(opcodes::all::OP_ELSE)
+            .push_opcode(opcodes::all::OP_HASH160)
+            .push_slice(payment_hash160)
+            .push_opcode(opcodes::all::OP_EQUALVERIFY)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .push_opcode(opcodes::all::OP_ENDIF);
+    } else {
+        bldr = bldr
+            .push_opcode(opcodes::all::OP_IF)
+            .push_opcode(opcodes::all::OP_HASH160)
+            .push_slice(payment_hash160)
+            .push_opcode(opcodes::all::OP_EQUALVERIFY)
+            .push_int(2)
+            .push_opcode(opcodes::all::OP_SWAP)
+            .push_slice(&keys.broadcaster_htlc_key.serialize()[..])
+            .push_int(2)
+            .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+            .push_opcode(opcodes::all::OP_ELSE)
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_int(cltv_expiry as i64)
+            .push_opcode(opcodes::all::OP_CLTV)
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .push_opcode(opcodes::all::OP_ENDIF);
+    }
+    if option_anchor_outputs {
+        bldr = bldr
+            .push_opcode(opcodes::all::OP_PUSHNUM_1)
+            .push_opcode(opcodes::all::OP_CSV)
+            .push_opcode(opcodes::all::OP_DROP);
+    }
+    bldr.push_opcode(opcodes::all::OP_ENDIF).into_script()
+}
+
 #[cfg(test)]
 mod tests {
     use core::{i16, i32, i8, u16, u8};
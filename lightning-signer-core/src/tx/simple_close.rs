@@ -0,0 +1,89 @@
+use bitcoin::{OutPoint, Script, Transaction, TxIn, TxOut};
+
+use crate::prelude::*;
+use crate::tx::tx::sort_outputs;
+
+/// The input sequence of an option_simple_close closing transaction,
+/// which signals RBF
+pub const SIMPLE_CLOSE_SEQUENCE: u32 = 0xfffffffd;
+
+/// Build an option_simple_close closing transaction.
+///
+/// Unlike the legacy closing transaction, the transaction has a locktime
+/// chosen by the closer and its input signals RBF, so that the closer can
+/// replace it with a higher fee variant.
+///
+/// A side's output is omitted if it has no script or a zero value.  The
+/// outputs are sorted as in BOLT #3.
+pub fn build_simple_close_tx(
+    funding_outpoint: OutPoint,
+    locktime: u32,
+    to_holder_value_sat: u64,
+    to_counterparty_value_sat: u64,
+    holder_script: &Option<Script>,
+    counterparty_script: &Option<Script>,
+) -> Transaction {
+    let mut txouts = Vec::with_capacity(2);
+    for (value, script) in
+        [(to_holder_value_sat, holder_script), (to_counterparty_value_sat, counterparty_script)]
+            .iter()
+    {
+        if let (true, Some(script)) = (*value > 0, script) {
+            txouts.push((TxOut { script_pubkey: script.clone(), value: *value }, ()));
+        }
+    }
+    sort_outputs(&mut txouts, |_, _| core::cmp::Ordering::Equal);
+
+    Transaction {
+        version: 2,
+        lock_time: locktime,
+        input: vec![TxIn {
+            previous_output: funding_outpoint,
+            script_sig: Script::new(),
+            sequence: SIMPLE_CLOSE_SEQUENCE,
+            witness: vec![],
+        }],
+        output: txouts.into_iter().map(|(txout, _)| txout).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::hex::FromHex;
+    use bitcoin::Txid;
+
+    use super::*;
+
+    #[test]
+    fn build_simple_close_tx_test() {
+        let outpoint = OutPoint { txid: Txid::default(), vout: 1 };
+        let holder_script = Some(Script::from_hex("0014aa").unwrap());
+        let counterparty_script = Some(Script::from_hex("0014bb").unwrap());
+
+        let tx = build_simple_close_tx(
+            outpoint,
+            800_000,
+            2_000,
+            1_000,
+            &holder_script,
+            &counterparty_script,
+        );
+        assert_eq!(tx.lock_time, 800_000);
+        assert_eq!(tx.input[0].previous_output, outpoint);
+        assert_eq!(tx.input[0].sequence, SIMPLE_CLOSE_SEQUENCE);
+        // sorted by value
+        assert_eq!(tx.output[0].value, 1_000);
+        assert_eq!(tx.output[0].script_pubkey, counterparty_script.clone().unwrap());
+        assert_eq!(tx.output[1].value, 2_000);
+
+        // the closee's dust output is omitted
+        let tx = build_simple_close_tx(outpoint, 0, 2_000, 0, &holder_script, &counterparty_script);
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].script_pubkey, holder_script.unwrap());
+
+        // as is an output without a script
+        let tx = build_simple_close_tx(outpoint, 0, 2_000, 1_000, &None, &counterparty_script);
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].value, 1_000);
+    }
+}
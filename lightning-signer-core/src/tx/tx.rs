@@ -26,6 +26,7 @@ use crate::policy::error::{
 };
 use crate::tx::script::{
     expect_data, expect_number, expect_op, expect_script_end, get_delayed_redeemscript,
+    get_htlc_redeemscript_with_hash160,
 };
 use crate::util::crypto_utils::payload_for_p2wpkh;
 use crate::util::debug_utils::DebugPayload;
@@ -266,6 +267,29 @@ impl fmt::Debug for HTLCInfo2 {
     }
 }
 
+/// The kind of a revoked counterparty commitment output
+#[derive(Clone, Debug, PartialEq)]
+pub enum JusticeOutputKind {
+    /// The counterparty's to_local output
+    ToLocal,
+    /// An HTLC offered by the counterparty
+    OfferedHtlc(HTLCInfo2),
+    /// An HTLC received by the counterparty
+    ReceivedHtlc(HTLCInfo2),
+}
+
+/// An output of a revoked counterparty commitment transaction, to be swept
+/// by a justice transaction
+#[derive(Clone, Debug, PartialEq)]
+pub struct JusticeOutput {
+    /// The output index in the revoked commitment transaction
+    pub vout: u32,
+    /// The value in satoshi
+    pub value_sat: u64,
+    /// The kind of output
+    pub kind: JusticeOutputKind,
+}
+
 /// This trait answers whether a preimage is known
 pub trait PreimageMap {
     /// Whether a preimage is known for the payment hash
@@ -530,6 +554,40 @@ pub(crate) fn parse_offered_htlc_script(
     Ok((revocation_hash, remote_htlc_pubkey, local_htlc_pubkey, payment_hash_vec))
 }
 
+/// Reconstruct the expected redeemscript of an HTLC output from the
+/// broadcaster's commitment keys.  The HTLC parameters (the payment hash
+/// and the expiry of a received HTLC) are taken from the supplied script.
+///
+/// Returns None if the supplied script is not an HTLC script.
+pub(crate) fn recompose_htlc_redeemscript(
+    script: &Script,
+    option_anchor_outputs: bool,
+    keys: &TxCreationKeys,
+) -> Option<Script> {
+    if let Ok((_, _, _, payment_hash160)) = parse_offered_htlc_script(script, option_anchor_outputs)
+    {
+        return Some(get_htlc_redeemscript_with_hash160(
+            true,
+            &payment_hash160,
+            0,
+            option_anchor_outputs,
+            keys,
+        ));
+    }
+    if let Ok((_, _, payment_hash160, _, cltv_expiry)) =
+        parse_received_htlc_script(script, option_anchor_outputs)
+    {
+        return Some(get_htlc_redeemscript_with_hash160(
+            false,
+            &payment_hash160,
+            cltv_expiry.try_into().ok()?,
+            option_anchor_outputs,
+            keys,
+        ));
+    }
+    None
+}
+
 pub(crate) fn parse_revokeable_redeemscript(
     script: &Script,
     _option_anchor_outputs: bool,
@@ -1067,4 +1125,40 @@ mod tests {
             transaction_format_error("script pubkey doesn\'t match inner script".to_string())
         );
     }
+
+    #[test]
+    fn recompose_htlc_redeemscript_test() {
+        let make_keys = |n: u8| TxCreationKeys {
+            per_commitment_point: make_test_pubkey(n),
+            revocation_key: make_test_pubkey(n + 1),
+            broadcaster_htlc_key: make_test_pubkey(n + 2),
+            countersignatory_htlc_key: make_test_pubkey(n + 3),
+            broadcaster_delayed_payment_key: make_test_pubkey(n + 4),
+        };
+        let keys = make_keys(1);
+        for offered in vec![true, false] {
+            for option_anchor_outputs in vec![false, true] {
+                let htlc = HTLCOutputInCommitment {
+                    offered,
+                    amount_msat: 4_000_000,
+                    cltv_expiry: 2 << 16,
+                    payment_hash: PaymentHash([3; 32]),
+                    transaction_output_index: Some(0),
+                };
+                let script = chan_utils::get_htlc_redeemscript(&htlc, option_anchor_outputs, &keys);
+                assert_eq!(
+                    recompose_htlc_redeemscript(&script, option_anchor_outputs, &keys),
+                    Some(script.clone())
+                );
+                // a script with other keys doesn't match
+                let other = make_keys(10);
+                assert_ne!(
+                    recompose_htlc_redeemscript(&script, option_anchor_outputs, &other),
+                    Some(script)
+                );
+            }
+        }
+        let script = Builder::new().push_slice(&[0u8; 42]).into_script();
+        assert_eq!(recompose_htlc_redeemscript(&script, false, &keys), None);
+    }
 }
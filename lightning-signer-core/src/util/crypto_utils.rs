@@ -1,13 +1,21 @@
 use crate::prelude::*;
+use core::sync::atomic::{compiler_fence, AtomicUsize, Ordering};
+
 use bitcoin::hashes::hash160::Hash as BitcoinHash160;
 use bitcoin::hashes::sha256::Hash as BitcoinSha256;
 use bitcoin::hashes::{Hash, HashEngine, Hmac, HmacEngine};
+use bitcoin::schnorr::KeyPair;
 use bitcoin::secp256k1;
-use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey, Signature};
+use bitcoin::secp256k1::schnorrsig;
+use bitcoin::secp256k1::{All, Message, PublicKey, Secp256k1, SecretKey, Signature, Signing};
 use bitcoin::util::address::Payload;
-use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey};
+use bitcoin::util::bip32::{ChainCode, ChildNumber, ExtendedPrivKey, ExtendedPubKey};
 use bitcoin::Network;
 use bitcoin::{bech32, Script, SigHashType};
+use secp256k1_xonly::XOnlyPublicKey;
+
+use crate::util::musig2::tagged_hash;
+use crate::util::status::{internal_error, Status};
 
 fn hkdf_extract_expand(salt: &[u8], secret: &[u8], info: &[u8], output: &mut [u8]) {
     let mut hmac = HmacEngine::<BitcoinSha256>::new(salt);
@@ -46,6 +54,22 @@ pub(crate) fn channels_seed(node_seed: &[u8]) -> [u8; 32] {
     hkdf_sha256(node_seed, "peer seed".as_bytes(), &[])
 }
 
+/// Overwrite a secret key before it is dropped.
+///
+/// This is best effort - secp256k1 doesn't expose the key bytes mutably, so
+/// the key is replaced with a constant rather than zeroized, and copies made
+/// by moves are out of our reach.
+pub(crate) fn scrub_secret_key(key: &mut SecretKey) {
+    *key = secp256k1::ONE_KEY;
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Overwrite the private key and chain code of an extended key
+pub(crate) fn scrub_extended_key(key: &mut ExtendedPrivKey) {
+    scrub_secret_key(&mut key.private_key.key);
+    key.chain_code = ChainCode::from(&[0u8; 32][..]);
+}
+
 // This function will panic if the SecretKey::from_slice fails.  Only
 // use where failure is an option (ie, startup).
 pub(crate) fn node_keys_native(
@@ -68,6 +92,19 @@ pub(crate) fn node_keys_lnd(
     derive_key_lnd(secp_ctx, network, master, key_family_node_key, index)
 }
 
+// The node key of the LDK KeysManager
+pub(crate) fn node_keys_ldk(
+    secp_ctx: &Secp256k1<secp256k1::All>,
+    master: ExtendedPrivKey,
+) -> (PublicKey, SecretKey) {
+    let node_secret = master
+        .ckd_priv(&secp_ctx, ChildNumber::from_hardened_idx(0).unwrap())
+        .unwrap()
+        .private_key
+        .key;
+    (PublicKey::from_secret_key(&secp_ctx, &node_secret), node_secret)
+}
+
 pub(crate) fn derive_key_lnd(
     secp_ctx: &Secp256k1<secp256k1::All>,
     network: Network,
@@ -228,6 +265,87 @@ pub(crate) fn payload_for_p2wsh(script: &Script) -> Payload {
     }
 }
 
+pub(crate) fn payload_for_p2tr(output_key: &XOnlyPublicKey) -> Payload {
+    Payload::WitnessProgram {
+        version: bech32::u5::try_from_u8(1).expect("1<32"),
+        program: output_key.serialize().to_vec(),
+    }
+}
+
+// The BIP-86 tweak of a key, for a taproot output without a script path.
+// The x-only public key of the tweaked keypair is the output key.
+pub(crate) fn bip86_tweaked_keypair(secp_ctx: &Secp256k1<All>, key: &SecretKey) -> KeyPair {
+    let mut keypair = KeyPair::from_secret_key(secp_ctx, *key);
+    let internal_key = XOnlyPublicKey::from_keypair(&keypair).serialize();
+    let tweak = tagged_hash("TapTweak", &[&internal_key]);
+    keypair.tweak_add_assign(secp_ctx, &tweak).expect("tweak is a valid scalar");
+    keypair
+}
+
+// The number of signatures that failed verification before release
+static SIGNATURE_VERIFY_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of signatures that failed verification before release since
+/// startup.  Anything but zero points to faulty hardware.
+pub fn signature_verify_failures() -> usize {
+    SIGNATURE_VERIFY_FAILURES.load(Ordering::Relaxed)
+}
+
+fn signature_verify_failed(kind: &str) -> Status {
+    SIGNATURE_VERIFY_FAILURES.fetch_add(1, Ordering::Relaxed);
+    internal_error(format!("{} signature failed verification, not released", kind))
+}
+
+/// Verify an ECDSA signature we produced before releasing it
+pub fn verify_ecdsa(message: &Message, sig: &Signature, pubkey: &PublicKey) -> Result<(), Status> {
+    Secp256k1::verification_only()
+        .verify(message, sig, pubkey)
+        .map_err(|_| signature_verify_failed("ECDSA"))
+}
+
+/// Verify a BIP340 signature we produced before releasing it
+pub fn verify_schnorr(
+    message: &Message,
+    sig: &schnorrsig::Signature,
+    pubkey: &schnorrsig::PublicKey,
+) -> Result<(), Status> {
+    Secp256k1::verification_only()
+        .schnorrsig_verify(sig, message, pubkey)
+        .map_err(|_| signature_verify_failed("schnorr"))
+}
+
+/// Sign a message as [sign_ecdsa] does, and if `verify` is set, verify the
+/// signature before returning it
+pub fn sign_ecdsa_verified<C: Signing>(
+    secp_ctx: &Secp256k1<C>,
+    message: &Message,
+    key: &SecretKey,
+    grind_low_r: bool,
+    verify: bool,
+) -> Result<Signature, Status> {
+    let sig = sign_ecdsa(secp_ctx, message, key, grind_low_r);
+    if verify {
+        verify_ecdsa(message, &sig, &PublicKey::from_secret_key(secp_ctx, key))?;
+    }
+    Ok(sig)
+}
+
+/// Sign a message, optionally grinding the nonce until R is low, which
+/// makes the signature at most 71 bytes including the sighash type, as
+/// bitcoind does
+pub fn sign_ecdsa<C: Signing>(
+    secp_ctx: &Secp256k1<C>,
+    message: &Message,
+    key: &SecretKey,
+    grind_low_r: bool,
+) -> Signature {
+    if grind_low_r {
+        secp_ctx.sign_low_r(message, key)
+    } else {
+        secp_ctx.sign(message, key)
+    }
+}
+
 /// Convert a [Signature] to Bitcoin signature bytes, with SIGHASH_ALL
 pub fn signature_to_bitcoin_vec(sig: Signature) -> Vec<u8> {
     let mut sigvec = sig.serialize_der().to_vec();
@@ -256,10 +374,7 @@ pub fn bitcoin_vec_to_signature(
 mod tests {
     use super::*;
     use bitcoin::hashes::hex::ToHex;
-    use bitcoin::schnorr::KeyPair;
-    use bitcoin::secp256k1::Message;
     use bitcoin::Network::Testnet;
-    use secp256k1_xonly::XOnlyPublicKey;
 
     #[test]
     fn node_keys_native_test() -> Result<(), ()> {
@@ -287,6 +402,45 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_ecdsa_low_r_test() {
+        let secp_ctx = Secp256k1::new();
+        let key = SecretKey::from_slice(&[3u8; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&secp_ctx, &key);
+        for i in 0..32u8 {
+            let message = Message::from_slice(&[i; 32]).unwrap();
+            let sig = sign_ecdsa(&secp_ctx, &message, &key, true);
+            assert!(sig.serialize_der().len() <= 70);
+            secp_ctx.verify(&message, &sig, &pubkey).unwrap();
+            // without grinding, the signature is the plain RFC6979 one
+            assert_eq!(sign_ecdsa(&secp_ctx, &message, &key, false), secp_ctx.sign(&message, &key));
+        }
+    }
+
+    #[test]
+    fn verify_signature_test() {
+        let secp_ctx = Secp256k1::new();
+        let key = SecretKey::from_slice(&[3u8; 32]).unwrap();
+        let message = Message::from_slice(&[1u8; 32]).unwrap();
+        let sig = sign_ecdsa_verified(&secp_ctx, &message, &key, true, true).unwrap();
+        assert_eq!(sig, sign_ecdsa(&secp_ctx, &message, &key, true));
+
+        // a faulty signature is not released, and is counted
+        let failures = signature_verify_failures();
+        let other_message = Message::from_slice(&[2u8; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&secp_ctx, &key);
+        assert!(verify_ecdsa(&other_message, &sig, &pubkey).is_err());
+        assert!(signature_verify_failures() > failures);
+
+        let keypair = KeyPair::from_secret_key(&secp_ctx, key);
+        let xonly =
+            schnorrsig::PublicKey::from_slice(&XOnlyPublicKey::from_keypair(&keypair).serialize())
+                .unwrap();
+        let sig = secp_ctx.schnorrsig_sign_no_aux_rand(&message, &keypair);
+        verify_schnorr(&message, &sig, &xonly).unwrap();
+        assert!(verify_schnorr(&other_message, &sig, &xonly).is_err());
+    }
+
     #[test]
     fn channels_seed_test() -> Result<(), ()> {
         let seed = channels_seed(&[0u8; 32]);
@@ -339,4 +493,17 @@ mod tests {
         let msg = Message::from_slice(&[11; 32]).unwrap();
         let _sig = secp.schnorrsig_sign_no_aux_rand(&msg, &keypair);
     }
+
+    #[test]
+    fn scrub_test() {
+        let mut key = SecretKey::from_slice(&[7; 32]).unwrap();
+        scrub_secret_key(&mut key);
+        assert_eq!(key, secp256k1::ONE_KEY);
+
+        let secp_ctx = Secp256k1::new();
+        let mut xkey = get_account_extended_key_native(&secp_ctx, Testnet, &[0u8; 32]);
+        scrub_extended_key(&mut xkey);
+        assert_eq!(xkey.private_key.key, secp256k1::ONE_KEY);
+        assert_eq!(xkey.chain_code.as_bytes(), &[0u8; 32]);
+    }
 }
@@ -0,0 +1,225 @@
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{OutPoint, Script, Txid};
+use lightning::ln::msgs::DecodeError;
+use lightning::util::ser::{BigSize, Readable};
+
+use crate::channel::Channel;
+use crate::io_extras::Read;
+use crate::prelude::*;
+use crate::util::shachain::CounterpartyRevocationSecrets;
+use crate::util::INITIAL_COMMITMENT_NUMBER;
+
+// The LDK channel monitor serialization version we can decode
+const SERIALIZATION_VERSION: u8 = 1;
+// The number of stored counterparty secrets
+const SECRET_SLOTS: usize = 49;
+
+/// The fields of a serialized LDK `ChannelMonitor` that can be
+/// cross-checked against the signer's state.
+///
+/// LDK doesn't expose these fields, so the prefix of the serialization
+/// that contains them is decoded directly, and the rest is ignored.
+/// In particular, the holder commitment number is not available.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LdkMonitorSummary {
+    /// The latest monitor update ID
+    pub latest_update_id: u64,
+    /// Our revocation basepoint
+    pub holder_revocation_basepoint: PublicKey,
+    /// The funding outpoint
+    pub funding_outpoint: OutPoint,
+    /// The counterparty's delayed payment basepoint
+    pub counterparty_delayed_payment_basepoint: PublicKey,
+    /// The counterparty's HTLC basepoint
+    pub counterparty_htlc_basepoint: PublicKey,
+    /// The channel value
+    pub channel_value_sat: u64,
+    /// The (forward counting) number and per-commitment point of the
+    /// latest counterparty commitment, if any
+    pub current_counterparty_commitment: Option<(u64, PublicKey)>,
+    /// The revealed counterparty revocation secrets
+    pub counterparty_secrets: CounterpartyRevocationSecrets,
+}
+
+/// A difference between an LDK channel monitor and the signer's state
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorDivergence {
+    /// The name of the diverging field
+    pub field: &'static str,
+    /// The value in the monitor
+    pub monitor: String,
+    /// The value in the signer
+    pub signer: String,
+}
+
+impl LdkMonitorSummary {
+    /// Decode a serialized `ChannelMonitor`
+    pub fn decode(mut reader: &[u8]) -> Result<Self, DecodeError> {
+        let r = &mut reader;
+        let _version: u8 = Readable::read(r)?;
+        let min_version: u8 = Readable::read(r)?;
+        if min_version > SERIALIZATION_VERSION {
+            return Err(DecodeError::UnknownVersion);
+        }
+        let latest_update_id: u64 = Readable::read(r)?;
+        let _obscure_factor = read_u48(r)?;
+        let _destination_script: Script = Readable::read(r)?;
+        // The broadcasted holder revokable script, 0 means present
+        match <u8 as Readable>::read(r)? {
+            0 => {
+                let _script: Script = Readable::read(r)?;
+                let _revocation_key: PublicKey = Readable::read(r)?;
+                let _delayed_key: PublicKey = Readable::read(r)?;
+            }
+            1 => {}
+            _ => return Err(DecodeError::InvalidValue),
+        }
+        let _counterparty_payment_script: Script = Readable::read(r)?;
+        let _shutdown_script: Script = Readable::read(r)?;
+        let _channel_keys_id: [u8; 32] = Readable::read(r)?;
+        let holder_revocation_basepoint = Readable::read(r)?;
+        let funding_txid: Txid = Readable::read(r)?;
+        let funding_vout: u16 = Readable::read(r)?;
+        let _funding_script: Script = Readable::read(r)?;
+        let _current_counterparty_txid: Option<Txid> = Readable::read(r)?;
+        let _prev_counterparty_txid: Option<Txid> = Readable::read(r)?;
+        let (counterparty_delayed_payment_basepoint, counterparty_htlc_basepoint) =
+            read_counterparty_basepoints(r)?;
+        let _funding_redeemscript: Script = Readable::read(r)?;
+        let channel_value_sat: u64 = Readable::read(r)?;
+        let idx = read_u48(r)?;
+        let current_counterparty_commitment = if idx == 0 {
+            None
+        } else {
+            let point: PublicKey = Readable::read(r)?;
+            // the previous point, or zeroes
+            let mut _prev_point = [0u8; 33];
+            r.read_exact(&mut _prev_point)?;
+            Some((INITIAL_COMMITMENT_NUMBER - idx, point))
+        };
+        let _on_holder_tx_csv: u16 = Readable::read(r)?;
+        let mut old_secrets = Vec::with_capacity(SECRET_SLOTS);
+        for _ in 0..SECRET_SLOTS {
+            let secret: [u8; 32] = Readable::read(r)?;
+            let idx: u64 = Readable::read(r)?;
+            old_secrets.push((secret, idx));
+        }
+        Ok(LdkMonitorSummary {
+            latest_update_id,
+            holder_revocation_basepoint,
+            funding_outpoint: OutPoint { txid: funding_txid, vout: funding_vout as u32 },
+            counterparty_delayed_payment_basepoint,
+            counterparty_htlc_basepoint,
+            channel_value_sat,
+            current_counterparty_commitment,
+            counterparty_secrets: CounterpartyRevocationSecrets { old_secrets },
+        })
+    }
+
+    /// Compare against the signer's state of the channel
+    pub fn divergences(&self, chan: &Channel) -> Vec<MonitorDivergence> {
+        let mut res = Vec::new();
+        let mut check = |field: &'static str, monitor: String, signer: String| {
+            if monitor != signer {
+                res.push(MonitorDivergence { field, monitor, signer });
+            }
+        };
+        let estate = &chan.enforcement_state;
+        let holder_points = chan.keys.pubkeys();
+        let counterparty_points = &chan.setup.counterparty_points;
+
+        check(
+            "funding_outpoint",
+            self.funding_outpoint.to_string(),
+            chan.setup.funding_outpoint.to_string(),
+        );
+        check(
+            "channel_value_sat",
+            self.channel_value_sat.to_string(),
+            chan.setup.channel_value_sat.to_string(),
+        );
+        check(
+            "holder_revocation_basepoint",
+            self.holder_revocation_basepoint.to_string(),
+            holder_points.revocation_basepoint.to_string(),
+        );
+        check(
+            "counterparty_delayed_payment_basepoint",
+            self.counterparty_delayed_payment_basepoint.to_string(),
+            counterparty_points.delayed_payment_basepoint.to_string(),
+        );
+        check(
+            "counterparty_htlc_basepoint",
+            self.counterparty_htlc_basepoint.to_string(),
+            counterparty_points.htlc_basepoint.to_string(),
+        );
+        let next_counterparty_commit_num =
+            self.current_counterparty_commitment.map(|(num, _)| num + 1).unwrap_or(0);
+        check(
+            "next_counterparty_commit_num",
+            next_counterparty_commit_num.to_string(),
+            estate.next_counterparty_commit_num.to_string(),
+        );
+        if let (Some((_, point)), Some(our_point)) =
+            (self.current_counterparty_commitment, estate.current_counterparty_point)
+        {
+            check("current_counterparty_point", point.to_string(), our_point.to_string());
+        }
+        let num_revealed = self.counterparty_secrets.num_revealed();
+        check(
+            "next_counterparty_revoke_num",
+            num_revealed.to_string(),
+            estate.next_counterparty_revoke_num.to_string(),
+        );
+        if num_revealed > 0 {
+            let num = num_revealed - 1;
+            // our store may have started mid-channel, so only compare if we have it
+            if let Some(our_secret) = estate.get_counterparty_revocation_secret(num) {
+                let matches = self.counterparty_secrets.get_secret(num) == Some(our_secret);
+                // don't leak the secrets into the report
+                check("counterparty_secret_matches", matches.to_string(), true.to_string());
+            }
+        }
+        res
+    }
+}
+
+fn read_u48(r: &mut &[u8]) -> Result<u64, DecodeError> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf[2..])?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+// The counterparty commitment parameters are a legacy zero u64, followed
+// by a length-prefixed TLV stream
+fn read_counterparty_basepoints(r: &mut &[u8]) -> Result<(PublicKey, PublicKey), DecodeError> {
+    let _legacy: u64 = Readable::read(r)?;
+    let len: BigSize = Readable::read(r)?;
+    if len.0 > r.len() as u64 {
+        return Err(DecodeError::ShortRead);
+    }
+    let (mut tlvs, rest) = r.split_at(len.0 as usize);
+    *r = rest;
+    let mut delayed_payment_basepoint = None;
+    let mut htlc_basepoint = None;
+    while !tlvs.is_empty() {
+        let typ: BigSize = Readable::read(&mut tlvs)?;
+        let length: BigSize = Readable::read(&mut tlvs)?;
+        if length.0 > tlvs.len() as u64 {
+            return Err(DecodeError::ShortRead);
+        }
+        let (mut value, rest) = tlvs.split_at(length.0 as usize);
+        tlvs = rest;
+        match typ.0 {
+            0 => delayed_payment_basepoint = Some(Readable::read(&mut value)?),
+            2 => htlc_basepoint = Some(Readable::read(&mut value)?),
+            _ => {}
+        }
+    }
+    match (delayed_payment_basepoint, htlc_basepoint) {
+        (Some(delayed_payment_basepoint), Some(htlc_basepoint)) => {
+            Ok((delayed_payment_basepoint, htlc_basepoint))
+        }
+        _ => Err(DecodeError::InvalidValue),
+    }
+}
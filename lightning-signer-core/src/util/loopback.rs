@@ -104,7 +104,9 @@ impl LoopbackChannelSigner {
 
     fn get_channel_setup(&self) -> Result<ChannelSetup, ()> {
         self.signer
-            .with_ready_channel(&self.node_id, &self.channel_id, |chan| Ok(chan.setup.clone()))
+            .with_ready_or_closing_channel(&self.node_id, &self.channel_id, |chan| {
+                Ok(chan.setup.clone())
+            })
             .map_err(|s| self.bad_status(s))
     }
 
@@ -191,7 +193,7 @@ impl LoopbackChannelSigner {
 
     fn option_anchor_outputs(&self) -> bool {
         let setup = self.get_channel_setup().expect("not ready");
-        setup.commitment_type == CommitmentType::Anchors
+        setup.option_anchor_outputs()
     }
 }
 
@@ -374,7 +376,7 @@ impl BaseSign for LoopbackChannelSigner {
         // TODO phase 2
         let sig = self
             .signer
-            .with_ready_channel(&self.node_id, &self.channel_id, |chan| {
+            .with_ready_or_closing_channel(&self.node_id, &self.channel_id, |chan| {
                 chan.sign_justice_sweep(
                     justice_tx,
                     input,
@@ -407,7 +409,7 @@ impl BaseSign for LoopbackChannelSigner {
         // TODO phase 2
         let sig = self
             .signer
-            .with_ready_channel(&self.node_id, &self.channel_id, |chan| {
+            .with_ready_or_closing_channel(&self.node_id, &self.channel_id, |chan| {
                 chan.sign_justice_sweep(
                     justice_tx,
                     input,
@@ -439,7 +441,7 @@ impl BaseSign for LoopbackChannelSigner {
         // TODO phase 2
         let sig = self
             .signer
-            .with_ready_channel(&self.node_id, &self.channel_id, |chan| {
+            .with_ready_or_closing_channel(&self.node_id, &self.channel_id, |chan| {
                 chan.sign_counterparty_htlc_sweep(
                     htlc_tx,
                     input,
@@ -489,7 +491,7 @@ impl BaseSign for LoopbackChannelSigner {
         let (nsig, bsig) = self
             .signer
             .with_ready_channel(&self.node_id, &self.channel_id, |chan| {
-                Ok(chan.sign_channel_announcement(&msg.encode()))
+                chan.sign_channel_announcement(&msg.encode())
             })
             .map_err(|s| self.bad_status(s))?;
         Ok((nsig, bsig))
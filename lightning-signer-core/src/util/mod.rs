@@ -27,6 +27,12 @@ pub mod test_utils;
 pub mod functional_test_utils;
 /// Key utilities
 pub mod key_utils;
+/// Decoding and verification of LDK channel monitor backups
+pub mod ldk_monitor;
+/// MuSig2 multi-signatures (BIP-327)
+pub mod musig2;
+/// Compact storage of counterparty revocation secrets
+pub mod shachain;
 /// Status error results
 pub mod status;
 /// Transaction utilities
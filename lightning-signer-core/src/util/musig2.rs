@@ -0,0 +1,470 @@
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey, Signing, Verification};
+
+use crate::prelude::*;
+
+/// A MuSig2 error
+#[derive(Clone, Debug, PartialEq)]
+pub enum Musig2Error {
+    /// A public key or point was invalid, or the aggregate was the point at infinity
+    InvalidKey,
+    /// A nonce was malformed, or the aggregate was the point at infinity
+    InvalidNonce,
+    /// A scalar was out of range, or an intermediate value was zero
+    InvalidScalar,
+    /// The secret key doesn't match the public key the nonce was generated for
+    KeyMismatch,
+}
+
+/// A BIP-340 tagged hash
+pub fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = Sha256Hash::hash(tag.as_bytes());
+    let mut engine = Sha256Hash::engine();
+    engine.input(&tag_hash[..]);
+    engine.input(&tag_hash[..]);
+    for d in data {
+        engine.input(d);
+    }
+    Sha256Hash::from_engine(engine).into_inner()
+}
+
+fn has_even_y(point: &PublicKey) -> bool {
+    point.serialize()[0] == 0x02
+}
+
+fn xbytes(point: &PublicKey) -> [u8; 32] {
+    let mut res = [0u8; 32];
+    res.copy_from_slice(&point.serialize()[1..]);
+    res
+}
+
+// A hash output as a scalar.  The probability of being out of range is
+// negligible, so this is reported as an error rather than reduced.
+fn scalar(bytes: &[u8; 32]) -> Result<SecretKey, Musig2Error> {
+    SecretKey::from_slice(bytes).map_err(|_| Musig2Error::InvalidScalar)
+}
+
+fn add(a: &SecretKey, b: &SecretKey) -> Result<SecretKey, Musig2Error> {
+    let mut res = *a;
+    res.add_assign(&b[..]).map_err(|_| Musig2Error::InvalidScalar)?;
+    Ok(res)
+}
+
+fn mul(a: &SecretKey, b: &SecretKey) -> Result<SecretKey, Musig2Error> {
+    let mut res = *a;
+    res.mul_assign(&b[..]).map_err(|_| Musig2Error::InvalidScalar)?;
+    Ok(res)
+}
+
+fn negate(a: &SecretKey) -> SecretKey {
+    let mut res = *a;
+    res.negate_assign();
+    res
+}
+
+fn point_mul<C: Verification>(
+    secp_ctx: &Secp256k1<C>,
+    point: &PublicKey,
+    s: &SecretKey,
+) -> Result<PublicKey, Musig2Error> {
+    let mut res = *point;
+    res.mul_assign(secp_ctx, &s[..]).map_err(|_| Musig2Error::InvalidKey)?;
+    Ok(res)
+}
+
+fn point_negate<C: Verification>(secp_ctx: &Secp256k1<C>, point: &PublicKey) -> PublicKey {
+    let mut res = *point;
+    res.negate_assign(secp_ctx);
+    res
+}
+
+/// The key aggregation context of BIP-327, including any tweaks
+#[derive(Clone, Debug)]
+pub struct KeyAggContext {
+    list_hash: [u8; 32],
+    second_key: Option<PublicKey>,
+    q: PublicKey,
+    // gacc is -1 if true, 1 otherwise
+    gacc_negated: bool,
+    // the accumulated tweak, None if zero
+    tacc: Option<SecretKey>,
+}
+
+impl KeyAggContext {
+    /// Aggregate the public keys, in the given order.
+    ///
+    /// The caller is responsible for sorting the keys if the order is not
+    /// otherwise agreed upon.
+    pub fn new<C: Verification>(
+        secp_ctx: &Secp256k1<C>,
+        pubkeys: &[PublicKey],
+    ) -> Result<Self, Musig2Error> {
+        if pubkeys.is_empty() {
+            return Err(Musig2Error::InvalidKey);
+        }
+        let serialized: Vec<[u8; 33]> = pubkeys.iter().map(|pk| pk.serialize()).collect();
+        let slices: Vec<&[u8]> = serialized.iter().map(|s| &s[..]).collect();
+        let list_hash = tagged_hash("KeyAgg list", &slices);
+        let second_key = pubkeys.iter().find(|pk| **pk != pubkeys[0]).cloned();
+        let mut ctx =
+            KeyAggContext { list_hash, second_key, q: pubkeys[0], gacc_negated: false, tacc: None };
+        let mut q: Option<PublicKey> = None;
+        for pk in pubkeys {
+            let point = match ctx.coef(pk)? {
+                Some(coef) => point_mul(secp_ctx, pk, &coef)?,
+                None => *pk,
+            };
+            q = Some(match q {
+                None => point,
+                Some(acc) => acc.combine(&point).map_err(|_| Musig2Error::InvalidKey)?,
+            });
+        }
+        ctx.q = q.expect("at least one key");
+        Ok(ctx)
+    }
+
+    // The key aggregation coefficient, None if it is one
+    fn coef(&self, pubkey: &PublicKey) -> Result<Option<SecretKey>, Musig2Error> {
+        if Some(*pubkey) == self.second_key {
+            return Ok(None);
+        }
+        let coef = tagged_hash("KeyAgg coefficient", &[&self.list_hash, &pubkey.serialize()]);
+        Ok(Some(scalar(&coef)?))
+    }
+
+    /// Apply an x-only tweak, as used for taproot outputs
+    pub fn apply_xonly_tweak<C: Verification>(
+        &mut self,
+        secp_ctx: &Secp256k1<C>,
+        tweak: &[u8; 32],
+    ) -> Result<(), Musig2Error> {
+        let t = scalar(tweak)?;
+        let negated = !has_even_y(&self.q);
+        let mut q = if negated { point_negate(secp_ctx, &self.q) } else { self.q };
+        q.add_exp_assign(secp_ctx, &t[..]).map_err(|_| Musig2Error::InvalidKey)?;
+        self.q = q;
+        self.tacc = Some(match self.tacc {
+            None => t,
+            Some(tacc) => add(&t, &if negated { negate(&tacc) } else { tacc })?,
+        });
+        self.gacc_negated ^= negated;
+        Ok(())
+    }
+
+    /// Apply the BIP-86 tweak, for a taproot output without a script path
+    pub fn apply_bip86_tweak<C: Verification>(
+        &mut self,
+        secp_ctx: &Secp256k1<C>,
+    ) -> Result<(), Musig2Error> {
+        let tweak = tagged_hash("TapTweak", &[&xbytes(&self.q)]);
+        self.apply_xonly_tweak(secp_ctx, &tweak)
+    }
+
+    /// The aggregate public key, including tweaks
+    pub fn aggregate_pubkey(&self) -> PublicKey {
+        self.q
+    }
+
+    /// The x-only aggregate public key, including tweaks
+    pub fn xonly_pubkey(&self) -> [u8; 32] {
+        xbytes(&self.q)
+    }
+
+    // The nonce coefficient b, the final nonce R and the challenge e
+    fn session_values<C: Verification>(
+        &self,
+        secp_ctx: &Secp256k1<C>,
+        aggnonce: &PubNonce,
+        msg: &[u8; 32],
+    ) -> Result<(SecretKey, PublicKey, SecretKey), Musig2Error> {
+        let b = scalar(&tagged_hash(
+            "MuSig/noncecoef",
+            &[&aggnonce.serialize(), &xbytes(&self.q), msg],
+        ))?;
+        let r = aggnonce
+            .r1
+            .combine(&point_mul(secp_ctx, &aggnonce.r2, &b)?)
+            .map_err(|_| Musig2Error::InvalidNonce)?;
+        let e = scalar(&tagged_hash("BIP0340/challenge", &[&xbytes(&r), &xbytes(&self.q), msg]))?;
+        Ok((b, r, e))
+    }
+
+    // Whether the signer's key must be negated, i.e. g * gacc == -1
+    fn negate_key(&self) -> bool {
+        !has_even_y(&self.q) ^ self.gacc_negated
+    }
+}
+
+/// A public nonce, consisting of two points
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PubNonce {
+    /// The first point
+    pub r1: PublicKey,
+    /// The second point
+    pub r2: PublicKey,
+}
+
+impl PubNonce {
+    /// Serialize as 66 bytes
+    pub fn serialize(&self) -> [u8; 66] {
+        let mut res = [0u8; 66];
+        res[..33].copy_from_slice(&self.r1.serialize());
+        res[33..].copy_from_slice(&self.r2.serialize());
+        res
+    }
+
+    /// Parse from 66 bytes
+    pub fn from_slice(data: &[u8]) -> Result<Self, Musig2Error> {
+        if data.len() != 66 {
+            return Err(Musig2Error::InvalidNonce);
+        }
+        let r1 = PublicKey::from_slice(&data[..33]).map_err(|_| Musig2Error::InvalidNonce)?;
+        let r2 = PublicKey::from_slice(&data[33..]).map_err(|_| Musig2Error::InvalidNonce)?;
+        Ok(PubNonce { r1, r2 })
+    }
+}
+
+/// A secret nonce.
+///
+/// This is consumed by [partial_sign], because signing twice with the same
+/// nonce leaks the secret key.
+pub struct SecNonce {
+    k1: SecretKey,
+    k2: SecretKey,
+    pubkey: PublicKey,
+}
+
+/// Generate a nonce pair as in the BIP-327 NonceGen algorithm.
+///
+/// `rand` must never be repeated for the same secret key.
+pub fn nonce_gen<C: Signing>(
+    secp_ctx: &Secp256k1<C>,
+    rand: &[u8; 32],
+    secret_key: &SecretKey,
+    aggregate_pubkey: Option<&[u8; 32]>,
+    msg: Option<&[u8; 32]>,
+    extra: &[u8],
+) -> Result<(SecNonce, PubNonce), Musig2Error> {
+    let pubkey = PublicKey::from_secret_key(secp_ctx, secret_key);
+    let aux = tagged_hash("MuSig/aux", &[rand]);
+    let mut masked = [0u8; 32];
+    for (i, b) in secret_key[..].iter().enumerate() {
+        masked[i] = b ^ aux[i];
+    }
+    let pk = pubkey.serialize();
+    let aggpk: &[u8] = aggregate_pubkey.map(|a| &a[..]).unwrap_or(&[]);
+    let mut msg_prefixed = Vec::new();
+    match msg {
+        None => msg_prefixed.push(0),
+        Some(m) => {
+            msg_prefixed.push(1);
+            msg_prefixed.extend_from_slice(&(m.len() as u64).to_be_bytes());
+            msg_prefixed.extend_from_slice(m);
+        }
+    }
+    let k = |i: u8| {
+        scalar(&tagged_hash(
+            "MuSig/nonce",
+            &[
+                &masked,
+                &[pk.len() as u8],
+                &pk,
+                &[aggpk.len() as u8],
+                aggpk,
+                &msg_prefixed,
+                &(extra.len() as u32).to_be_bytes(),
+                extra,
+                &[i],
+            ],
+        ))
+    };
+    let k1 = k(0)?;
+    let k2 = k(1)?;
+    let pubnonce = PubNonce {
+        r1: PublicKey::from_secret_key(secp_ctx, &k1),
+        r2: PublicKey::from_secret_key(secp_ctx, &k2),
+    };
+    Ok((SecNonce { k1, k2, pubkey }, pubnonce))
+}
+
+/// Aggregate the public nonces of all signers
+pub fn nonce_agg(nonces: &[PubNonce]) -> Result<PubNonce, Musig2Error> {
+    let (first, rest) = nonces.split_first().ok_or(Musig2Error::InvalidNonce)?;
+    let mut res = *first;
+    for nonce in rest {
+        res.r1 = res.r1.combine(&nonce.r1).map_err(|_| Musig2Error::InvalidNonce)?;
+        res.r2 = res.r2.combine(&nonce.r2).map_err(|_| Musig2Error::InvalidNonce)?;
+    }
+    Ok(res)
+}
+
+/// A partial signature
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PartialSig(SecretKey);
+
+impl PartialSig {
+    /// Serialize as 32 bytes
+    pub fn serialize(&self) -> [u8; 32] {
+        let mut res = [0u8; 32];
+        res.copy_from_slice(&self.0[..]);
+        res
+    }
+
+    /// Parse from 32 bytes
+    pub fn from_slice(data: &[u8]) -> Result<Self, Musig2Error> {
+        SecretKey::from_slice(data).map(PartialSig).map_err(|_| Musig2Error::InvalidScalar)
+    }
+}
+
+/// Create a partial signature over a 32 byte message
+pub fn partial_sign<C: Signing + Verification>(
+    secp_ctx: &Secp256k1<C>,
+    secnonce: SecNonce,
+    secret_key: &SecretKey,
+    ctx: &KeyAggContext,
+    aggnonce: &PubNonce,
+    msg: &[u8; 32],
+) -> Result<PartialSig, Musig2Error> {
+    let pubkey = PublicKey::from_secret_key(secp_ctx, secret_key);
+    if pubkey != secnonce.pubkey {
+        return Err(Musig2Error::KeyMismatch);
+    }
+    let (b, r, e) = ctx.session_values(secp_ctx, aggnonce, msg)?;
+    let (k1, k2) = if has_even_y(&r) {
+        (secnonce.k1, secnonce.k2)
+    } else {
+        (negate(&secnonce.k1), negate(&secnonce.k2))
+    };
+    let d = if ctx.negate_key() { negate(secret_key) } else { *secret_key };
+    let mut ead = mul(&e, &d)?;
+    if let Some(coef) = ctx.coef(&pubkey)? {
+        ead = mul(&ead, &coef)?;
+    }
+    let s = add(&add(&k1, &mul(&b, &k2)?)?, &ead)?;
+    Ok(PartialSig(s))
+}
+
+/// Verify a partial signature of a signer with the given public key and nonce
+pub fn partial_sig_verify<C: Signing + Verification>(
+    secp_ctx: &Secp256k1<C>,
+    sig: &PartialSig,
+    pubnonce: &PubNonce,
+    pubkey: &PublicKey,
+    ctx: &KeyAggContext,
+    aggnonce: &PubNonce,
+    msg: &[u8; 32],
+) -> Result<bool, Musig2Error> {
+    let (b, r, e) = ctx.session_values(secp_ctx, aggnonce, msg)?;
+    let mut re = pubnonce
+        .r1
+        .combine(&point_mul(secp_ctx, &pubnonce.r2, &b)?)
+        .map_err(|_| Musig2Error::InvalidNonce)?;
+    if !has_even_y(&r) {
+        re = point_negate(secp_ctx, &re);
+    }
+    let mut p = if ctx.negate_key() { point_negate(secp_ctx, pubkey) } else { *pubkey };
+    p = point_mul(secp_ctx, &p, &e)?;
+    if let Some(coef) = ctx.coef(pubkey)? {
+        p = point_mul(secp_ctx, &p, &coef)?;
+    }
+    let expected = re.combine(&p).map_err(|_| Musig2Error::InvalidKey)?;
+    Ok(PublicKey::from_secret_key(secp_ctx, &sig.0) == expected)
+}
+
+/// Aggregate the partial signatures into a BIP-340 signature
+pub fn partial_sig_agg<C: Verification>(
+    secp_ctx: &Secp256k1<C>,
+    sigs: &[PartialSig],
+    ctx: &KeyAggContext,
+    aggnonce: &PubNonce,
+    msg: &[u8; 32],
+) -> Result<[u8; 64], Musig2Error> {
+    let (_b, r, e) = ctx.session_values(secp_ctx, aggnonce, msg)?;
+    let (first, rest) = sigs.split_first().ok_or(Musig2Error::InvalidScalar)?;
+    let mut s = first.0;
+    for sig in rest {
+        s = add(&s, &sig.0)?;
+    }
+    if let Some(tacc) = ctx.tacc {
+        let et = mul(&e, &tacc)?;
+        s = add(&s, &if has_even_y(&ctx.q) { et } else { negate(&et) })?;
+    }
+    let mut res = [0u8; 64];
+    res[..32].copy_from_slice(&xbytes(&r));
+    res[32..].copy_from_slice(&s[..]);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::{schnorrsig, Message};
+
+    use super::*;
+    use crate::util::test_utils::{hex_decode, hex_encode};
+
+    fn pubkey(h: &str) -> PublicKey {
+        PublicKey::from_slice(&hex_decode(h).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn key_agg_vector_test() {
+        let secp_ctx = Secp256k1::verification_only();
+        let x1 = pubkey("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9");
+        let x2 = pubkey("03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659");
+        let x3 = pubkey("023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66");
+        let ctx = KeyAggContext::new(&secp_ctx, &[x1, x2, x3]).unwrap();
+        assert_eq!(
+            hex_encode(&ctx.xonly_pubkey()),
+            "90539eede565f5d054f32cc0c220126889ed1e5d193baf15aef344fe59d4610c"
+        );
+    }
+
+    #[test]
+    fn sign_and_aggregate_test() {
+        let secp_ctx = Secp256k1::new();
+        let sk1 = SecretKey::from_slice(&[1; 32]).unwrap();
+        let sk2 = SecretKey::from_slice(&[2; 32]).unwrap();
+        let pk1 = PublicKey::from_secret_key(&secp_ctx, &sk1);
+        let pk2 = PublicKey::from_secret_key(&secp_ctx, &sk2);
+        let mut pubkeys = vec![pk1, pk2];
+        pubkeys.sort_by_key(|pk| pk.serialize());
+        let mut ctx = KeyAggContext::new(&secp_ctx, &pubkeys).unwrap();
+        ctx.apply_bip86_tweak(&secp_ctx).unwrap();
+        let aggpk = ctx.xonly_pubkey();
+        let msg = [42u8; 32];
+
+        let (secnonce1, pubnonce1) =
+            nonce_gen(&secp_ctx, &[3; 32], &sk1, Some(&aggpk), Some(&msg), &[]).unwrap();
+        let (secnonce2, pubnonce2) =
+            nonce_gen(&secp_ctx, &[4; 32], &sk2, Some(&aggpk), None, &[]).unwrap();
+        assert_eq!(PubNonce::from_slice(&pubnonce1.serialize()).unwrap(), pubnonce1);
+        let aggnonce = nonce_agg(&[pubnonce1, pubnonce2]).unwrap();
+
+        // the nonce belongs to another key
+        let (wrong_secnonce, _) =
+            nonce_gen(&secp_ctx, &[3; 32], &sk2, Some(&aggpk), None, &[]).unwrap();
+        assert_eq!(
+            partial_sign(&secp_ctx, wrong_secnonce, &sk1, &ctx, &aggnonce, &msg),
+            Err(Musig2Error::KeyMismatch)
+        );
+
+        let psig1 = partial_sign(&secp_ctx, secnonce1, &sk1, &ctx, &aggnonce, &msg).unwrap();
+        let psig2 = partial_sign(&secp_ctx, secnonce2, &sk2, &ctx, &aggnonce, &msg).unwrap();
+        assert_eq!(PartialSig::from_slice(&psig1.serialize()).unwrap(), psig1);
+        assert!(
+            partial_sig_verify(&secp_ctx, &psig1, &pubnonce1, &pk1, &ctx, &aggnonce, &msg).unwrap()
+        );
+        assert!(
+            partial_sig_verify(&secp_ctx, &psig2, &pubnonce2, &pk2, &ctx, &aggnonce, &msg).unwrap()
+        );
+        // a partial signature doesn't verify against another signer
+        assert!(!partial_sig_verify(&secp_ctx, &psig1, &pubnonce2, &pk2, &ctx, &aggnonce, &msg)
+            .unwrap());
+
+        let sig = partial_sig_agg(&secp_ctx, &[psig1, psig2], &ctx, &aggnonce, &msg).unwrap();
+        let sig = schnorrsig::Signature::from_slice(&sig).unwrap();
+        let xonly = schnorrsig::PublicKey::from_slice(&aggpk).unwrap();
+        secp_ctx.schnorrsig_verify(&sig, &Message::from_slice(&msg).unwrap(), &xonly).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::SecretKey;
+
+use crate::prelude::*;
+use crate::util::INITIAL_COMMITMENT_NUMBER;
+
+// One slot per possible trailing-zero count of a 48-bit index, plus one
+const SECRET_SLOTS: usize = 49;
+// An index larger than any valid index, marking an unused slot
+const UNUSED_INDEX: u64 = 1 << 48;
+
+/// Compact storage of the per-commitment secrets revealed by the
+/// counterparty, as described in BOLT-3 "Efficient Per-commitment Secret
+/// Storage".
+///
+/// At most 49 secrets are stored, from which any previously revealed
+/// secret can be derived.  Secrets are addressed by commitment number,
+/// counting up from zero.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CounterpartyRevocationSecrets {
+    /// The stored secrets and their BOLT-3 (count down) indexes
+    pub old_secrets: Vec<([u8; 32], u64)>,
+}
+
+impl core::fmt::Debug for CounterpartyRevocationSecrets {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // don't leak the secrets into the logs
+        f.debug_struct("CounterpartyRevocationSecrets")
+            .field("min_revealed_index", &self.min_seen_index())
+            .finish()
+    }
+}
+
+impl Default for CounterpartyRevocationSecrets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CounterpartyRevocationSecrets {
+    /// Create an empty store
+    pub fn new() -> Self {
+        CounterpartyRevocationSecrets { old_secrets: vec![([0; 32], UNUSED_INDEX); SECRET_SLOTS] }
+    }
+
+    /// Store the secret for commitment number `commit_num`.
+    ///
+    /// Returns an error if the secret is inconsistent with the previously
+    /// stored secrets.  Storing an already known secret is allowed.
+    pub fn provide_secret(&mut self, commit_num: u64, secret: &SecretKey) -> Result<(), ()> {
+        if commit_num > INITIAL_COMMITMENT_NUMBER {
+            return Err(());
+        }
+        let idx = INITIAL_COMMITMENT_NUMBER - commit_num;
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&secret[..]);
+        let pos = Self::place_secret(idx);
+        for i in 0..pos {
+            let (old_secret, old_idx) = self.old_secrets[i as usize];
+            // Slots may be unused if we started storing mid-channel
+            if old_idx != UNUSED_INDEX && Self::derive_secret(bytes, pos, old_idx) != old_secret {
+                return Err(());
+            }
+        }
+        if self.min_seen_index() <= idx {
+            // already known, and consistent
+            return Ok(());
+        }
+        self.old_secrets[pos as usize] = (bytes, idx);
+        Ok(())
+    }
+
+    /// The secret for commitment number `commit_num`, if it was revealed
+    pub fn get_secret(&self, commit_num: u64) -> Option<SecretKey> {
+        if commit_num > INITIAL_COMMITMENT_NUMBER {
+            return None;
+        }
+        let idx = INITIAL_COMMITMENT_NUMBER - commit_num;
+        for (i, (secret, old_idx)) in self.old_secrets.iter().enumerate() {
+            if *old_idx != UNUSED_INDEX && idx & !((1u64 << i) - 1) == *old_idx {
+                let bytes = Self::derive_secret(*secret, i as u8, idx);
+                return Some(SecretKey::from_slice(&bytes).expect("secret"));
+            }
+        }
+        None
+    }
+
+    /// The number of revealed secrets, which is also the next commitment
+    /// number whose secret is expected
+    pub fn num_revealed(&self) -> u64 {
+        let min = self.min_seen_index();
+        if min == UNUSED_INDEX {
+            0
+        } else {
+            INITIAL_COMMITMENT_NUMBER - min + 1
+        }
+    }
+
+    fn min_seen_index(&self) -> u64 {
+        self.old_secrets.iter().map(|(_, idx)| *idx).min().unwrap_or(UNUSED_INDEX)
+    }
+
+    // The slot for an index is the number of trailing zeros
+    fn place_secret(idx: u64) -> u8 {
+        for i in 0..48 {
+            if idx & (1 << i) == (1 << i) {
+                return i;
+            }
+        }
+        48
+    }
+
+    // Derive the secret for `idx` from a secret that has `bits` trailing
+    // bits free to vary
+    fn derive_secret(secret: [u8; 32], bits: u8, idx: u64) -> [u8; 32] {
+        let mut res = secret;
+        for i in 0..bits {
+            let bitpos = bits - 1 - i;
+            if idx & (1 << bitpos) == (1 << bitpos) {
+                res[(bitpos / 8) as usize] ^= 1 << (bitpos & 7);
+                res = Sha256::hash(&res).into_inner();
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning::ln::chan_utils::build_commitment_secret;
+
+    use super::*;
+
+    fn secret_for(seed: &[u8; 32], commit_num: u64) -> SecretKey {
+        let bytes = build_commitment_secret(seed, INITIAL_COMMITMENT_NUMBER - commit_num);
+        SecretKey::from_slice(&bytes).unwrap()
+    }
+
+    #[test]
+    fn store_and_derive_test() {
+        let seed = [1u8; 32];
+        let mut secrets = CounterpartyRevocationSecrets::new();
+        assert_eq!(secrets.num_revealed(), 0);
+        assert!(secrets.get_secret(0).is_none());
+        for num in 0..100 {
+            secrets.provide_secret(num, &secret_for(&seed, num)).unwrap();
+            assert_eq!(secrets.num_revealed(), num + 1);
+        }
+        for num in 0..100 {
+            assert_eq!(secrets.get_secret(num), Some(secret_for(&seed, num)));
+        }
+        assert!(secrets.get_secret(100).is_none());
+        assert_eq!(secrets.old_secrets.len(), SECRET_SLOTS);
+
+        // re-providing a known secret is allowed
+        secrets.provide_secret(42, &secret_for(&seed, 42)).unwrap();
+        assert_eq!(secrets.num_revealed(), 100);
+    }
+
+    #[test]
+    fn inconsistent_secret_test() {
+        let seed = [1u8; 32];
+        let other_seed = [2u8; 32];
+        let mut secrets = CounterpartyRevocationSecrets::new();
+        secrets.provide_secret(0, &secret_for(&seed, 0)).unwrap();
+        // commitment 1 is index ...fffe, which must derive index ...ffff
+        assert!(secrets.provide_secret(1, &secret_for(&other_seed, 1)).is_err());
+        secrets.provide_secret(1, &secret_for(&seed, 1)).unwrap();
+    }
+
+    #[test]
+    fn start_mid_channel_test() {
+        let seed = [1u8; 32];
+        let mut secrets = CounterpartyRevocationSecrets::new();
+        for num in 23..39 {
+            secrets.provide_secret(num, &secret_for(&seed, num)).unwrap();
+        }
+        for num in 23..39 {
+            assert_eq!(secrets.get_secret(num), Some(secret_for(&seed, num)));
+        }
+        // earlier secrets can be derived from later ones
+        assert_eq!(secrets.get_secret(22), Some(secret_for(&seed, 22)));
+        // commitment 39 can derive 36 through 38
+        assert!(secrets.provide_secret(39, &secret_for(&[2u8; 32], 39)).is_err());
+        secrets.provide_secret(39, &secret_for(&seed, 39)).unwrap();
+    }
+}
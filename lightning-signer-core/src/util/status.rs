@@ -5,7 +5,19 @@ use core::fmt;
 use backtrace::Backtrace;
 use log::error;
 
-use crate::policy::error::ValidationError;
+use crate::persist;
+use crate::policy::error::{PolicyError, ValidationError};
+
+/// The gRPC metadata key of a policy violation's tag
+pub const POLICY_TAG_METADATA_KEY: &str = "policy-tag";
+/// The gRPC metadata key of a policy violation's offending values, as
+/// comma separated `name=value` pairs
+pub const POLICY_VALUES_METADATA_KEY: &str = "policy-values";
+/// The gRPC metadata key of the channel of a policy violation
+pub const POLICY_CHANNEL_METADATA_KEY: &str = "policy-channel-id";
+/// The gRPC metadata key that marks an error as transient, so that the
+/// request can be retried
+pub const RETRYABLE_METADATA_KEY: &str = "retryable";
 
 /// gRPC compatible error status
 #[derive(Clone)]
@@ -14,6 +26,11 @@ pub struct Status {
     code: Code,
     /// A relevant error message, found in the `grpc-message` header.
     message: String,
+    /// The details of a policy violation, found in the `policy-*` metadata.
+    policy: Option<PolicyError>,
+    /// Whether the operation may succeed if retried, found in the
+    /// `retryable` metadata.
+    retryable: bool,
 }
 
 /// gRPC compatible error status code
@@ -35,7 +52,7 @@ pub enum Code {
 impl Status {
     /// Create a new `Status` with the associated code and message.
     pub fn new(code: Code, message: impl Into<String>) -> Self {
-        Status { code, message: message.into() }
+        Status { code, message: message.into(), policy: None, retryable: false }
     }
 
     /// Get the gRPC `Code` of this `Status`.
@@ -48,6 +65,18 @@ impl Status {
         &self.message
     }
 
+    /// Get the details of the policy violation, if this `Status` is a
+    /// classified policy violation.
+    pub fn policy_violation(&self) -> Option<&PolicyError> {
+        self.policy.as_ref()
+    }
+
+    /// Whether the failure is transient, so that the operation may succeed
+    /// if retried.
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+
     /// Construct an invalid argument status
     pub fn invalid_argument(message: impl Into<String>) -> Status {
         Self::new(Code::InvalidArgument, message)
@@ -75,6 +104,14 @@ impl fmt::Debug for Status {
             builder.field("message", &self.message);
         }
 
+        if let Some(policy) = &self.policy {
+            builder.field("policy", policy);
+        }
+
+        if self.retryable {
+            builder.field("retryable", &self.retryable);
+        }
+
         builder.finish()
     }
 }
@@ -94,8 +131,29 @@ use core::convert::TryInto;
 #[cfg(feature = "grpc")]
 impl From<Status> for tonic::Status {
     fn from(s: Status) -> Self {
+        use tonic::metadata::{MetadataMap, MetadataValue};
+
         let code = s.code() as i32;
-        tonic::Status::new(code.try_into().unwrap(), s.message())
+        let mut metadata = MetadataMap::new();
+        if let Some(policy) = &s.policy {
+            let values: Vec<String> =
+                policy.values.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            let entries = [
+                (POLICY_TAG_METADATA_KEY, Some(policy.tag.name().to_string())),
+                (POLICY_VALUES_METADATA_KEY, Some(values.join(","))),
+                (POLICY_CHANNEL_METADATA_KEY, policy.channel_id.clone()),
+            ];
+            for (key, value) in entries.iter() {
+                // skip values that are not valid metadata
+                if let Some(Ok(value)) = value.as_ref().map(|v| MetadataValue::from_str(v)) {
+                    metadata.insert(*key, value);
+                }
+            }
+        }
+        if s.retryable {
+            metadata.insert(RETRYABLE_METADATA_KEY, MetadataValue::from_static("true"));
+        }
+        tonic::Status::with_metadata(code.try_into().unwrap(), s.message(), metadata)
     }
 }
 
@@ -116,6 +174,13 @@ pub(crate) fn internal_error(msg: impl Into<String>) -> Status {
     Status::internal(s)
 }
 
+/// A persister error, mapped to an internal error that is marked retryable
+/// if the failure is transient
+pub(crate) fn persist_error(context: &str, e: persist::Error) -> Status {
+    let retryable = e.is_retryable();
+    Status { retryable, ..internal_error(format!("{}: {}", context, e)) }
+}
+
 #[allow(unused)]
 pub(crate) fn failed_precondition(msg: impl Into<String>) -> Status {
     let s = msg.into();
@@ -130,6 +195,6 @@ impl From<ValidationError> for Status {
         error!("FAILED PRECONDITION: {}", &s);
         #[cfg(feature = "backtrace")]
         error!("BACKTRACE:\n{:?}", &ve.resolved_backtrace());
-        Status::failed_precondition(s)
+        Status { policy: ve.policy, ..Status::failed_precondition(s) }
     }
 }
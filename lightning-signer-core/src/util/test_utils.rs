@@ -31,28 +31,32 @@ use lightning::ln::chan_utils::{
 };
 use lightning::ln::PaymentHash;
 use lightning::util::test_utils;
+use secp256k1_xonly::XOnlyPublicKey;
 
 use super::key_utils::{
     make_test_bitcoin_pubkey, make_test_counterparty_points, make_test_privkey, make_test_pubkey,
 };
+use crate::chain::tracker::ChainTracker;
 use crate::channel::{
     channel_nonce_to_id, Channel, ChannelBase, ChannelId, ChannelSetup, ChannelStub,
     CommitmentType, TypedSignature,
 };
-use crate::node::SpendType;
 use crate::node::{Node, NodeConfig};
+use crate::node::{SpendType, UnilateralCloseInfo};
 use crate::persist::{DummyPersister, Persist};
 use crate::policy::simple_validator::SimpleValidatorFactory;
 use crate::policy::validator::ChainState;
 use crate::prelude::*;
 use crate::signer::my_keys_manager::KeyDerivationStyle;
+use crate::tx::interactive::InteractiveInput;
 use crate::tx::script::{
     get_p2wpkh_redeemscript, get_to_countersignatory_with_anchors_redeemscript,
     ANCHOR_OUTPUT_VALUE_SATOSHI,
 };
 use crate::tx::tx::{sort_outputs, CommitmentInfo2, HTLCInfo2};
 use crate::util::crypto_utils::{
-    derive_public_key, derive_revocation_pubkey, payload_for_p2wpkh, payload_for_p2wsh,
+    bip86_tweaked_keypair, derive_public_key, derive_revocation_pubkey, payload_for_p2tr,
+    payload_for_p2wpkh, payload_for_p2wsh,
 };
 use crate::util::loopback::LoopbackChannelSigner;
 use crate::util::status::Status;
@@ -249,8 +253,10 @@ pub fn make_test_chain_state() -> ChainState {
     ChainState {
         current_height: 1000,
         funding_depth: 0,
+        funding_watched: true,
         funding_double_spent_depth: 0,
         closing_depth: 0,
+        clock_skew_secs: None,
     }
 }
 
@@ -307,7 +313,7 @@ pub fn init_node(node_config: NodeConfig, seedstr: &str) -> Arc<Node> {
 
     let validator_factory = Arc::new(SimpleValidatorFactory::new());
 
-    let node = Node::new(node_config, &seed, persister, vec![], validator_factory);
+    let node = Node::new(node_config, &seed, persister, vec![], validator_factory).unwrap();
     Arc::new(node)
 }
 
@@ -318,13 +324,14 @@ pub fn init_node_and_channel(
 ) -> (Arc<Node>, ChannelId) {
     let node = init_node(node_config, seedstr);
     {
+        // Start the chain tracker a few blocks in.  The blocks are mined
+        // at a low difficulty, so they are not valid testnet blocks.
         let mut tracker = node.get_tracker();
-        let header = make_testnet_header(tracker.tip(), Default::default());
-        tracker.add_block(header, vec![], None).unwrap();
-        let header = make_testnet_header(tracker.tip(), Default::default());
-        tracker.add_block(header, vec![], None).unwrap();
-        let header = make_testnet_header(tracker.tip(), Default::default());
-        tracker.add_block(header, vec![], None).unwrap();
+        let mut header = tracker.tip();
+        for _ in 0..3 {
+            header = make_testnet_header(header, Default::default());
+        }
+        *tracker = ChainTracker::new(tracker.network, 3, header).unwrap();
     }
     let channel_nonce = "nonce1".as_bytes().to_vec();
     let channel_id = channel_nonce_to_id(&channel_nonce);
@@ -415,6 +422,9 @@ pub fn make_test_wallet_dest(
     let script_pubkey = match spend_type {
         SpendType::P2wpkh => Address::p2wpkh(&pubkey, node_ctx.node.network()),
         SpendType::P2shP2wpkh => Address::p2shwpkh(&pubkey, node_ctx.node.network()),
+        SpendType::P2tr => {
+            return (node_ctx.node.get_taproot_script_pubkey(&child_path).unwrap(), child_path)
+        }
         _ => panic!("invalid spend_type {:?}", spend_type),
     }
     .unwrap()
@@ -423,6 +433,11 @@ pub fn make_test_wallet_dest(
     (script_pubkey, vec![wallet_index])
 }
 
+pub fn make_test_taproot_address(index: u8, network: Network) -> Address {
+    let keypair = bip86_tweaked_keypair(&Secp256k1::new(), &make_test_privkey(index));
+    Address { payload: payload_for_p2tr(&XOnlyPublicKey::from_keypair(&keypair)), network }
+}
+
 pub fn make_test_nonwallet_dest(
     node_ctx: &TestNodeContext,
     index: u8,
@@ -432,6 +447,7 @@ pub fn make_test_nonwallet_dest(
     let script_pubkey = match spend_type {
         SpendType::P2wpkh => Address::p2wpkh(&pubkey, node_ctx.node.network()),
         SpendType::P2shP2wpkh => Address::p2shwpkh(&pubkey, node_ctx.node.network()),
+        SpendType::P2tr => Ok(make_test_taproot_address(index, node_ctx.node.network())),
         _ => panic!("invalid spend_type {:?}", spend_type),
     }
     .unwrap()
@@ -459,7 +475,7 @@ pub struct TestFundingTxContext {
     pub ipaths: Vec<Vec<u32>>,
     pub ivals: Vec<u64>,
     pub ispnds: Vec<SpendType>,
-    pub iuckeys: Vec<Option<(SecretKey, Vec<Vec<u8>>)>>,
+    pub iuckeys: Vec<Option<UnilateralCloseInfo>>,
     pub outputs: Vec<TxOut>,
     pub opaths: Vec<Vec<u32>>,
 }
@@ -765,6 +781,43 @@ pub fn funding_tx_sign(
     )
 }
 
+// The wallet inputs of a funding tx, skipping the ones to be signed by
+// someone else
+pub fn funding_tx_wallet_inputs(
+    node_ctx: &TestNodeContext,
+    tx_ctx: &TestFundingTxContext,
+    tx: &bitcoin::Transaction,
+) -> Vec<InteractiveInput> {
+    tx.input
+        .iter()
+        .enumerate()
+        .filter(|(ndx, _)| tx_ctx.ispnds[*ndx] != SpendType::Invalid)
+        .map(|(ndx, txin)| InteractiveInput {
+            outpoint: txin.previous_output,
+            prev_output: TxOut {
+                value: tx_ctx.ivals[ndx],
+                script_pubkey: make_test_funding_wallet_addr(
+                    &node_ctx.secp_ctx,
+                    &node_ctx.node,
+                    tx_ctx.ipaths[ndx][0],
+                    tx_ctx.ispnds[ndx] == SpendType::P2shP2wpkh,
+                )
+                .script_pubkey(),
+            },
+            ipath: tx_ctx.ipaths[ndx].clone(),
+        })
+        .collect()
+}
+
+pub fn funding_tx_sign_validated(
+    node_ctx: &TestNodeContext,
+    tx_ctx: &TestFundingTxContext,
+    tx: &bitcoin::Transaction,
+) -> Result<Vec<Vec<Vec<u8>>>, Status> {
+    let inputs = funding_tx_wallet_inputs(node_ctx, tx_ctx, tx);
+    node_ctx.node.sign_funding_tx(&tx, &inputs, &tx_ctx.opaths)
+}
+
 pub fn funding_tx_validate_sig(
     node_ctx: &TestNodeContext,
     tx_ctx: &TestFundingTxContext,
@@ -974,7 +1027,7 @@ pub fn counterparty_sign_holder_commitment(
             for htlc in tx.htlcs() {
                 let htlc_tx = build_htlc_transaction(
                     &commitment_txid,
-                    tx.feerate_per_kw(),
+                    chan_ctx.setup.htlc_feerate_per_kw(tx.feerate_per_kw()),
                     chan_ctx.setup.counterparty_selected_contest_delay,
                     htlc,
                     chan_ctx.setup.option_anchor_outputs(),
@@ -1140,11 +1193,19 @@ pub fn make_test_commitment_info() -> CommitmentInfo2 {
     )
 }
 
-pub const TEST_NODE_CONFIG: NodeConfig =
-    NodeConfig { network: Network::Testnet, key_derivation_style: KeyDerivationStyle::Native };
+pub const TEST_NODE_CONFIG: NodeConfig = NodeConfig {
+    network: Network::Testnet,
+    key_derivation_style: KeyDerivationStyle::Native,
+    chain_params: None,
+    checkpoint: None,
+};
 
-pub const REGTEST_NODE_CONFIG: NodeConfig =
-    NodeConfig { network: Network::Regtest, key_derivation_style: KeyDerivationStyle::Native };
+pub const REGTEST_NODE_CONFIG: NodeConfig = NodeConfig {
+    network: Network::Regtest,
+    key_derivation_style: KeyDerivationStyle::Native,
+    chain_params: None,
+    checkpoint: None,
+};
 
 pub const TEST_SEED: &[&str] = &[
     "6c696768746e696e672d31000000000000000000000000000000000000000000",
@@ -1524,7 +1585,7 @@ pub fn make_outpoint(vout: u32) -> BitcoinOutPoint {
 
 pub fn make_header(tip: BlockHeader, merkle_root: TxMerkleNode) -> BlockHeader {
     let bits = tip.bits;
-    mine_header_with_bits(tip.block_hash(), merkle_root, bits)
+    mine_header_with_bits(tip.block_hash(), merkle_root, bits, tip.time + 1)
 }
 
 pub fn make_block(tip: BlockHeader, txs: Vec<Transaction>) -> Block {
@@ -1547,24 +1608,19 @@ pub fn make_testnet_header(tip: BlockHeader, merkle_root: TxMerkleNode) -> Block
     // use lower bits so it doesn't take forever
     let regtest_genesis = genesis_block(Network::Regtest);
     let bits = regtest_genesis.header.bits;
-    mine_header_with_bits(tip.block_hash(), merkle_root, bits)
+    mine_header_with_bits(tip.block_hash(), merkle_root, bits, tip.time + 1)
 }
 
 pub fn mine_header_with_bits(
     prev_hash: BlockHash,
     merkle_root: TxMerkleNode,
     bits: u32,
+    time: u32,
 ) -> BlockHeader {
     let mut nonce = 0;
     loop {
-        let header = BlockHeader {
-            version: 0,
-            prev_blockhash: prev_hash,
-            merkle_root,
-            time: 0,
-            bits,
-            nonce,
-        };
+        let header =
+            BlockHeader { version: 0, prev_blockhash: prev_hash, merkle_root, time, bits, nonce };
         if header.validate_pow(&header.target()).is_ok() {
             // println!("mined block with nonce {}", nonce);
             return header;
@@ -1591,7 +1647,9 @@ pub(crate) fn make_node() -> (PublicKey, Arc<Node>, [u8; 32]) {
 
     let persister: Arc<dyn Persist> = Arc::new(DummyPersister {});
     let validator_factory = Arc::new(SimpleValidatorFactory::new());
-    let node = Arc::new(Node::new(TEST_NODE_CONFIG, &seed, &persister, vec![], validator_factory));
+    let node = Arc::new(
+        Node::new(TEST_NODE_CONFIG, &seed, &persister, vec![], validator_factory).unwrap(),
+    );
     let node_id = node.get_id();
     (node_id, node, seed)
 }
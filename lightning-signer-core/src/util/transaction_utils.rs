@@ -1,5 +1,10 @@
 use crate::io_extras::sink;
+use crate::prelude::*;
+use crate::util::musig2::tagged_hash;
+use bitcoin::consensus::encode::serialize;
 use bitcoin::consensus::Encodable;
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::{Hash, HashEngine};
 use bitcoin::{Script, Transaction, TxOut, VarInt};
 
 /// The maximum value of an input or output in milli satoshi
@@ -61,3 +66,46 @@ pub fn maybe_add_change_output(
 
     Ok(())
 }
+
+/// The BIP-341 signature hash of a key path spend with SIGHASH_DEFAULT and
+/// no annex.
+///
+/// `prevouts` are the outputs spent by each of the inputs.
+pub fn taproot_key_spend_sighash(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+) -> Result<[u8; 32], ()> {
+    if input_index >= tx.input.len() || prevouts.len() != tx.input.len() {
+        return Err(());
+    }
+    fn sha256<I: Iterator<Item = Vec<u8>>>(items: I) -> [u8; 32] {
+        let mut engine = Sha256Hash::engine();
+        for item in items {
+            engine.input(&item);
+        }
+        Sha256Hash::from_engine(engine).into_inner()
+    }
+    let sha_prevouts = sha256(tx.input.iter().map(|i| serialize(&i.previous_output)));
+    let sha_amounts = sha256(prevouts.iter().map(|o| o.value.to_le_bytes().to_vec()));
+    let sha_scriptpubkeys = sha256(prevouts.iter().map(|o| serialize(&o.script_pubkey)));
+    let sha_sequences = sha256(tx.input.iter().map(|i| i.sequence.to_le_bytes().to_vec()));
+    let sha_outputs = sha256(tx.output.iter().map(|o| serialize(o)));
+    Ok(tagged_hash(
+        "TapSighash",
+        &[
+            // epoch and hash type
+            &[0, 0],
+            &tx.version.to_le_bytes(),
+            &tx.lock_time.to_le_bytes(),
+            &sha_prevouts,
+            &sha_amounts,
+            &sha_scriptpubkeys,
+            &sha_sequences,
+            &sha_outputs,
+            // key path spend without annex
+            &[0],
+            &(input_index as u32).to_le_bytes(),
+        ],
+    ))
+}
@@ -2,16 +2,23 @@
 mod tests {
     use bitcoin;
     use bitcoin::hashes::hex::ToHex;
-    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
     use bitcoin::util::psbt::serialize::Serialize;
     use lightning::chain::keysinterface::BaseSign;
+    use lightning::ln::chan_utils::build_commitment_secret;
 
     use test_log::test;
 
     use crate::channel::{Channel, CommitmentType};
+    use crate::node::{NodeEvent, NodeEventListener};
+    use crate::prelude::*;
+    use crate::sync::Arc;
+    use crate::tx::tx::JusticeOutputKind;
     use crate::util::key_utils::*;
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
+    use crate::util::INITIAL_COMMITMENT_NUMBER;
+    use crate::watchtower::{locator, WatchtowerExport};
 
     // TODO - policy-v2-commitment-retry-same (tx)
     // TODO - policy-v2-commitment-retry-same (output_witscripts)
@@ -19,6 +26,18 @@ mod tests {
 
     const REV_COMMIT_NUM: u64 = 23;
 
+    // Counterparty secrets derived as specified in BOLT-3, so that they can
+    // be stored compactly
+    fn make_commitment_secret(commit_num: u64) -> SecretKey {
+        let seed = [0x33u8; 32];
+        let secret = build_commitment_secret(&seed, INITIAL_COMMITMENT_NUMBER - commit_num);
+        SecretKey::from_slice(&secret).unwrap()
+    }
+
+    fn make_commitment_point(commit_num: u64) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::new(), &make_commitment_secret(commit_num))
+    }
+
     fn validate_counterparty_revocation_with_mutator<RevocationMutator, ChannelStateValidator>(
         mutate_revocation_input: RevocationMutator,
         validate_channel_state: ChannelStateValidator,
@@ -236,7 +255,7 @@ mod tests {
             chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(REV_COMMIT_NUM - 1);
             chan.enforcement_state.set_next_counterparty_commit_num_for_testing(
                 REV_COMMIT_NUM,
-                make_test_pubkey((REV_COMMIT_NUM - 1) as u8),
+                make_commitment_point(REV_COMMIT_NUM - 1),
             );
             // commit 21: revoked
             // commit 22: current  <- next revoke
@@ -248,7 +267,7 @@ mod tests {
         assert_status_ok!(node.with_ready_channel(&channel_id, |chan| {
             let channel_parameters = chan.make_channel_parameters();
 
-            let remote_percommit_point = make_test_pubkey(REV_COMMIT_NUM as u8);
+            let remote_percommit_point = make_commitment_point(REV_COMMIT_NUM);
 
             let feerate_per_kw = 0;
             let to_broadcaster = 1_979_997;
@@ -303,9 +322,14 @@ mod tests {
         assert_status_ok!(node.with_ready_channel(&channel_id, |chan| {
             assert_status_ok!(chan.validate_counterparty_revocation(
                 REV_COMMIT_NUM - 1,
-                &make_test_privkey((REV_COMMIT_NUM - 1) as u8)
+                &make_commitment_secret(REV_COMMIT_NUM - 1)
             ));
 
+            assert_eq!(
+                chan.get_counterparty_revocation_secret(REV_COMMIT_NUM - 1),
+                Some(make_commitment_secret(REV_COMMIT_NUM - 1))
+            );
+
             // commit 22: revoked
             // commit 23: current   <- next revoke
             // commit 24: next      <- next commit
@@ -316,7 +340,7 @@ mod tests {
         assert_status_ok!(node.with_ready_channel(&channel_id, |chan| {
             let channel_parameters = chan.make_channel_parameters();
 
-            let remote_percommit_point = make_test_pubkey((REV_COMMIT_NUM + 1) as u8);
+            let remote_percommit_point = make_commitment_point(REV_COMMIT_NUM + 1);
 
             let feerate_per_kw = 0;
             let to_broadcaster = 1_979_097; // -900
@@ -377,7 +401,7 @@ mod tests {
             assert_failed_precondition_err!(
                 chan.validate_counterparty_revocation(
                     REV_COMMIT_NUM - 2,
-                    &make_test_privkey((REV_COMMIT_NUM - 2) as u8)
+                    &make_commitment_secret(REV_COMMIT_NUM - 2)
                 ),
                 "policy failure: validate_counterparty_revocation: \
                  invalid counterparty revoke_num 21 with next_counterparty_revoke_num 23"
@@ -391,7 +415,7 @@ mod tests {
             assert_failed_precondition_err!(
                 chan.validate_counterparty_revocation(
                     REV_COMMIT_NUM + 1,
-                    &make_test_privkey((REV_COMMIT_NUM + 1) as u8)
+                    &make_commitment_secret(REV_COMMIT_NUM + 1)
                 ),
                 "policy failure: validate_counterparty_revocation: \
                  invalid counterparty revoke_num 24 with next_counterparty_revoke_num 23"
@@ -404,17 +428,26 @@ mod tests {
             // can revoke correctly
             assert_status_ok!(chan.validate_counterparty_revocation(
                 REV_COMMIT_NUM,
-                &make_test_privkey(REV_COMMIT_NUM as u8)
+                &make_commitment_secret(REV_COMMIT_NUM)
             ));
 
             // state is modified
             assert_eq!(chan.enforcement_state.next_counterparty_revoke_num, REV_COMMIT_NUM + 1);
             assert!(chan.enforcement_state.previous_counterparty_commit_info.is_none());
 
+            // both revealed secrets are available
+            for num in REV_COMMIT_NUM - 1..=REV_COMMIT_NUM {
+                assert_eq!(
+                    chan.get_counterparty_revocation_secret(num),
+                    Some(make_commitment_secret(num))
+                );
+            }
+            assert_eq!(chan.get_counterparty_revocation_secret(REV_COMMIT_NUM + 1), None);
+
             // Retry is ok
             assert_status_ok!(chan.validate_counterparty_revocation(
                 REV_COMMIT_NUM,
-                &make_test_privkey(REV_COMMIT_NUM as u8)
+                &make_commitment_secret(REV_COMMIT_NUM)
             ));
 
             // state is unchanged
@@ -425,7 +458,7 @@ mod tests {
             assert_failed_precondition_err!(
                 chan.validate_counterparty_revocation(
                     REV_COMMIT_NUM - 1,
-                    &make_test_privkey((REV_COMMIT_NUM - 1) as u8)
+                    &make_commitment_secret(REV_COMMIT_NUM - 1)
                 ),
                 "policy failure: validate_counterparty_revocation: \
                  invalid counterparty revoke_num 22 with next_counterparty_revoke_num 24"
@@ -439,7 +472,7 @@ mod tests {
             assert_failed_precondition_err!(
                 chan.validate_counterparty_revocation(
                     REV_COMMIT_NUM + 2,
-                    &make_test_privkey((REV_COMMIT_NUM + 2) as u8)
+                    &make_commitment_secret(REV_COMMIT_NUM + 2)
                 ),
                 "policy failure: validate_counterparty_revocation: \
                  invalid counterparty revoke_num 25 with next_counterparty_revoke_num 24"
@@ -452,4 +485,158 @@ mod tests {
             Ok(())
         }))
     }
+
+    struct TestEventListener {
+        events: Mutex<Vec<NodeEvent>>,
+    }
+
+    impl SendSync for TestEventListener {}
+
+    impl NodeEventListener for TestEventListener {
+        fn on_event(&self, event: &NodeEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn validate_counterparty_revocation_event_test() {
+        let (node, _setup, channel_id, offered_htlcs, received_htlcs) =
+            sign_commitment_tx_with_mutators_setup(CommitmentType::StaticRemoteKey);
+        let listener = Arc::new(TestEventListener { events: Mutex::new(vec![]) });
+        node.add_event_listener(listener.clone());
+
+        assert_status_ok!(node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(REV_COMMIT_NUM - 1);
+            chan.enforcement_state.set_next_counterparty_commit_num_for_testing(
+                REV_COMMIT_NUM,
+                make_commitment_point(REV_COMMIT_NUM - 1),
+            );
+            let htlcs = Channel::htlcs_info2_to_oic(offered_htlcs.clone(), received_htlcs.clone());
+            let revoked_tx = chan.make_counterparty_commitment_tx(
+                &make_commitment_point(REV_COMMIT_NUM),
+                REV_COMMIT_NUM,
+                0,
+                1_000_000,
+                1_979_997,
+                htlcs,
+            );
+            let revoked_txid = revoked_tx.trust().txid();
+
+            for commit_num in REV_COMMIT_NUM..=REV_COMMIT_NUM + 1 {
+                chan.sign_counterparty_commitment_tx_phase2(
+                    &make_commitment_point(commit_num),
+                    commit_num,
+                    0,
+                    1_000_000,
+                    1_979_997,
+                    offered_htlcs.clone(),
+                    received_htlcs.clone(),
+                )?;
+                chan.validate_counterparty_revocation(
+                    commit_num - 1,
+                    &make_commitment_secret(commit_num - 1),
+                )?;
+            }
+
+            // we didn't have the info for the commitment revoked first
+            let events = listener.events.lock().unwrap().clone();
+            assert_eq!(events.len(), 1);
+            let outputs = match &events[0] {
+                NodeEvent::CounterpartyRevocation { channel_id, txid, commit_num, outputs } => {
+                    assert_eq!(*channel_id, chan.id());
+                    assert_eq!(*txid, revoked_txid);
+                    assert_eq!(*commit_num, REV_COMMIT_NUM);
+                    outputs.clone()
+                }
+                event => panic!("unexpected event {:?}", event),
+            };
+            assert_eq!(outputs.len(), 1 + offered_htlcs.len() + received_htlcs.len());
+            assert!(matches!(outputs[0].kind, JusticeOutputKind::ToLocal));
+
+            // a retry doesn't raise another event
+            chan.validate_counterparty_revocation(
+                REV_COMMIT_NUM,
+                &make_commitment_secret(REV_COMMIT_NUM),
+            )?;
+            assert_eq!(listener.events.lock().unwrap().len(), 1);
+
+            // the outputs are enough to build a justice transaction
+            let tx = chan.build_and_sign_justice_tx(
+                &revoked_txid,
+                REV_COMMIT_NUM,
+                &outputs,
+                1000,
+                &vec![1],
+            )?;
+            assert_eq!(tx.input.len(), outputs.len());
+            Ok(())
+        }));
+    }
+
+    #[test]
+    fn export_watchtower_blob_test() {
+        let (node, _setup, channel_id, offered_htlcs, received_htlcs) =
+            sign_commitment_tx_with_mutators_setup(CommitmentType::StaticRemoteKey);
+        node.set_watchtower_export(Some(WatchtowerExport {
+            feerate_per_kw: 1000,
+            wallet_path: vec![1],
+        }));
+
+        assert_status_ok!(node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(REV_COMMIT_NUM - 1);
+            chan.enforcement_state.set_next_counterparty_commit_num_for_testing(
+                REV_COMMIT_NUM,
+                make_commitment_point(REV_COMMIT_NUM - 1),
+            );
+            let htlcs = Channel::htlcs_info2_to_oic(offered_htlcs.clone(), received_htlcs.clone());
+            let revoked_txid = chan
+                .make_counterparty_commitment_tx(
+                    &make_commitment_point(REV_COMMIT_NUM),
+                    REV_COMMIT_NUM,
+                    0,
+                    1_000_000,
+                    1_979_997,
+                    htlcs,
+                )
+                .trust()
+                .txid();
+
+            for commit_num in REV_COMMIT_NUM..=REV_COMMIT_NUM + 1 {
+                chan.sign_counterparty_commitment_tx_phase2(
+                    &make_commitment_point(commit_num),
+                    commit_num,
+                    0,
+                    1_000_000,
+                    1_979_997,
+                    offered_htlcs.clone(),
+                    received_htlcs.clone(),
+                )?;
+                chan.validate_counterparty_revocation(
+                    commit_num - 1,
+                    &make_commitment_secret(commit_num - 1),
+                )?;
+            }
+
+            // only the commitment we had the info for was exported
+            let blobs: Vec<_> = node.watchtower_blobs_since(0).collect();
+            assert_eq!(blobs.len(), 1);
+            let (sequence, blob) = &blobs[0];
+            assert_eq!(blob.channel_id, chan.id());
+            assert_eq!(blob.commit_num, REV_COMMIT_NUM);
+            assert_eq!(blob.locator, locator(&revoked_txid));
+            let tx = blob.decrypt(&revoked_txid).expect("decrypt");
+            assert_eq!(tx.input.len(), 1 + offered_htlcs.len() + received_htlcs.len());
+            assert_eq!(node.watchtower_blobs_since(*sequence).count(), 0);
+
+            // the blob can be exported again on demand
+            let exported = chan.export_watchtower_blob(REV_COMMIT_NUM, 1000, &vec![1])?;
+            assert_eq!(exported.locator, blob.locator);
+            assert_eq!(exported.decrypt(&revoked_txid), Some(tx));
+
+            // but not for an older commitment
+            let err = chan.export_watchtower_blob(REV_COMMIT_NUM - 1, 1000, &vec![1]).unwrap_err();
+            assert_eq!(err.code(), Code::FailedPrecondition);
+            Ok(())
+        }));
+    }
 }
@@ -341,7 +341,10 @@ mod tests {
             let mut cstate = make_test_chain_state();
 
             mutate_validation_input(&mut ValidationMutationState {
-                opt_anchors: commitment_type == CommitmentType::Anchors,
+                opt_anchors: matches!(
+                    commitment_type,
+                    CommitmentType::Anchors | CommitmentType::AnchorsZeroFeeHtlc
+                ),
                 chan: chan,
                 cstate: &mut cstate,
                 commit_tx_ctx: &mut commit_tx_ctx,
@@ -486,6 +489,15 @@ mod tests {
                     );
                 }
             }
+            paste! {
+                #[test]
+                fn [<$name _zero_fee_anchors>]() {
+                    assert_status_ok!(
+                        validate_holder_commitment_with_mutators(
+                            CommitmentType::AnchorsZeroFeeHtlc, $tms, $kms, $vms, $vs)
+                    );
+                }
+            }
         };
     }
 
@@ -12,6 +12,14 @@ pub trait Wallet {
     /// True if the script_pubkey is in the node's allowlist
     fn allowlist_contains(&self, script_pubkey: &Script) -> bool;
 
+    /// The lowest sweep threshold of the cold storage destinations in the
+    /// node's allowlist, if there are any
+    fn cold_storage_threshold(&self) -> Option<u64>;
+
+    /// True if the script_pubkey is a cold storage destination in the
+    /// node's allowlist
+    fn cold_storage_contains(&self, script_pubkey: &Script) -> bool;
+
     /// Returns the network
     fn network(&self) -> Network;
 
@@ -20,4 +28,75 @@ pub trait Wallet {
 
     /// Returns the wrapped segwit address at path
     fn get_wrapped_address(&self, child_path: &Vec<u32>) -> Result<Address, Status>;
+
+    /// Returns the script_pubkey of the BIP-86 taproot output at path, which
+    /// is spent with the tweaked key and has no script path
+    fn get_taproot_script_pubkey(&self, child_path: &Vec<u32>) -> Result<Script, Status>;
+
+    /// True if the value approval token was supplied to the node
+    fn has_value_approval(&self, token: &[u8; 32]) -> bool;
+}
+
+const DESCRIPTOR_INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const DESCRIPTOR_CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn descriptor_polymod(mut chk: u64, value: u64) -> u64 {
+    const GENERATOR: [u64; 5] =
+        [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+    let top = chk >> 35;
+    chk = ((chk & 0x7ffffffff) << 5) ^ value;
+    for (i, gen) in GENERATOR.iter().enumerate() {
+        if (top >> i) & 1 == 1 {
+            chk ^= gen;
+        }
+    }
+    chk
+}
+
+/// Append the BIP-380 checksum to an output descriptor, as in
+/// `wpkh(xpub/*)#checksum`.
+///
+/// Returns None if the descriptor has characters outside the descriptor
+/// character set.
+pub fn add_descriptor_checksum(desc: &str) -> Option<String> {
+    let mut chk = 1;
+    let mut groups = Vec::new();
+    for ch in desc.chars() {
+        let pos = DESCRIPTOR_INPUT_CHARSET.find(ch)? as u64;
+        chk = descriptor_polymod(chk, pos & 31);
+        groups.push(pos >> 5);
+        if groups.len() == 3 {
+            chk = descriptor_polymod(chk, groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    match groups.len() {
+        1 => chk = descriptor_polymod(chk, groups[0]),
+        2 => chk = descriptor_polymod(chk, groups[0] * 3 + groups[1]),
+        _ => {}
+    }
+    for _ in 0..8 {
+        chk = descriptor_polymod(chk, 0);
+    }
+    chk ^= 1;
+    let checksum: String = (0..8)
+        .map(|i| DESCRIPTOR_CHECKSUM_CHARSET[((chk >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect();
+    Some(format!("{}#{}", desc, checksum))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_descriptor_checksum_test() {
+        assert_eq!(add_descriptor_checksum("raw(deadbeef)").unwrap(), "raw(deadbeef)#89f8spxm");
+        assert_eq!(
+            add_descriptor_checksum("sh(multi(2,[00000000/111'/222]xprvA1RpRA33e1JQ7ifknakTFpgNXPmW2YvmhqLQYMmrj4xJXXWYpDPS3xz7iAxn8L39njGVyuoseXzU6rcxFLJ8HFsTjSyQbLYnMpCqE2VbFWc,xprv9uPDJpEQgRQfDcW7BkF7eTya6RPxXeJCqCJGHuCJ4GiRVLzkTXBAJMu2qaMWPrS7AANYqdq6vcBcBUdJCVVFceUvJFjaPdGZ2y9WACViL4L/0))").unwrap(),
+            "sh(multi(2,[00000000/111'/222]xprvA1RpRA33e1JQ7ifknakTFpgNXPmW2YvmhqLQYMmrj4xJXXWYpDPS3xz7iAxn8L39njGVyuoseXzU6rcxFLJ8HFsTjSyQbLYnMpCqE2VbFWc,xprv9uPDJpEQgRQfDcW7BkF7eTya6RPxXeJCqCJGHuCJ4GiRVLzkTXBAJMu2qaMWPrS7AANYqdq6vcBcBUdJCVVFceUvJFjaPdGZ2y9WACViL4L/0))#ggrsrxfy"
+        );
+        assert!(add_descriptor_checksum("wpkh(\u{e9})").is_none());
+    }
 }
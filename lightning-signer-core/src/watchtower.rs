@@ -0,0 +1,172 @@
+use alloc::collections::VecDeque;
+
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::Hash;
+use bitcoin::{Transaction, Txid};
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use crate::channel::ChannelId;
+use crate::prelude::*;
+
+/// The length of a watchtower locator, in bytes
+pub const LOCATOR_LEN: usize = 16;
+
+/// Maximum number of blobs held by a node for export, the oldest blobs are
+/// dropped first
+pub const MAX_WATCHTOWER_BLOBS: usize = 1024;
+
+/// How the node builds the justice transactions it exports to watchtowers
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchtowerExport {
+    /// The feerate of the justice transactions
+    pub feerate_per_kw: u32,
+    /// The layer-1 wallet path the justice transactions sweep to
+    pub wallet_path: Vec<u32>,
+}
+
+/// The justice data a watchtower needs for a revoked counterparty
+/// commitment, in the format of rust-teos.
+///
+/// The locator is the first half of the revoked commitment txid.  The blob
+/// is the ChaCha20-Poly1305 encryption of the serialized justice
+/// transaction, with the SHA256 of the txid as key and an all-zero nonce.
+/// A tower can only decrypt it once the revoked commitment is broadcast.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchtowerBlob {
+    /// The channel
+    pub channel_id: ChannelId,
+    /// The revoked commitment number
+    pub commit_num: u64,
+    /// The locator of the revoked commitment transaction
+    pub locator: [u8; LOCATOR_LEN],
+    /// The encrypted justice transaction
+    pub encrypted_blob: Vec<u8>,
+}
+
+impl WatchtowerBlob {
+    /// Encrypt the justice transaction for a revoked commitment transaction
+    pub fn new(
+        channel_id: ChannelId,
+        commit_num: u64,
+        breach_txid: &Txid,
+        justice_tx: &Transaction,
+    ) -> Self {
+        let encrypted_blob = cipher(breach_txid)
+            .encrypt(&zero_nonce(), serialize(justice_tx).as_slice())
+            .expect("encrypt");
+        WatchtowerBlob { channel_id, commit_num, locator: locator(breach_txid), encrypted_blob }
+    }
+
+    /// Decrypt the justice transaction, as the tower does once the breach
+    /// transaction is seen on-chain
+    pub fn decrypt(&self, breach_txid: &Txid) -> Option<Transaction> {
+        if self.locator != locator(breach_txid) {
+            return None;
+        }
+        let plaintext =
+            cipher(breach_txid).decrypt(&zero_nonce(), self.encrypted_blob.as_slice()).ok()?;
+        deserialize(&plaintext).ok()
+    }
+}
+
+/// The watchtower locator of a commitment transaction
+pub fn locator(txid: &Txid) -> [u8; LOCATOR_LEN] {
+    let mut locator = [0u8; LOCATOR_LEN];
+    locator.copy_from_slice(&txid[..LOCATOR_LEN]);
+    locator
+}
+
+fn cipher(breach_txid: &Txid) -> ChaCha20Poly1305 {
+    let key = Sha256Hash::hash(&breach_txid[..]);
+    ChaCha20Poly1305::new(Key::from_slice(&key[..]))
+}
+
+fn zero_nonce() -> Nonce {
+    *Nonce::from_slice(&[0u8; 12])
+}
+
+/// The blobs exported by a node, numbered in the order of the revocations.
+///
+/// Not persisted, so an exporter that falls behind across a restart must
+/// use [crate::channel::Channel::export_watchtower_blob] for the missed
+/// revocations.
+pub(crate) struct WatchtowerQueue {
+    next_sequence: u64,
+    blobs: VecDeque<(u64, WatchtowerBlob)>,
+}
+
+impl WatchtowerQueue {
+    pub(crate) fn new() -> Self {
+        WatchtowerQueue { next_sequence: 1, blobs: VecDeque::new() }
+    }
+
+    pub(crate) fn push(&mut self, blob: WatchtowerBlob) -> u64 {
+        if self.blobs.len() >= MAX_WATCHTOWER_BLOBS {
+            self.blobs.pop_front();
+        }
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.blobs.push_back((sequence, blob));
+        sequence
+    }
+
+    // The blobs after the given sequence number, oldest first
+    pub(crate) fn since(&self, sequence: u64) -> Vec<(u64, WatchtowerBlob)> {
+        self.blobs.iter().filter(|(s, _)| *s > sequence).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{OutPoint, Script, TxIn, TxOut};
+
+    use super::*;
+
+    fn make_justice_tx() -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: Txid::from_slice(&[2; 32]).unwrap(), vout: 0 },
+                script_sig: Script::new(),
+                sequence: 0xffff_ffff,
+                witness: vec![vec![1]],
+            }],
+            output: vec![TxOut { value: 1000, script_pubkey: Script::new() }],
+        }
+    }
+
+    #[test]
+    fn watchtower_blob_test() {
+        let breach_txid = Txid::from_slice(&[1; 32]).unwrap();
+        let justice_tx = make_justice_tx();
+        let blob = WatchtowerBlob::new(ChannelId([3; 32]), 7, &breach_txid, &justice_tx);
+        assert_eq!(blob.locator, [1; LOCATOR_LEN]);
+        assert_eq!(blob.decrypt(&breach_txid), Some(justice_tx.clone()));
+        // the encryption is deterministic, as in rust-teos
+        assert_eq!(blob, WatchtowerBlob::new(ChannelId([3; 32]), 7, &breach_txid, &justice_tx));
+        assert_eq!(blob.decrypt(&Txid::from_slice(&[4; 32]).unwrap()), None);
+    }
+
+    #[test]
+    fn watchtower_queue_test() {
+        let mut queue = WatchtowerQueue::new();
+        let blob = WatchtowerBlob::new(
+            ChannelId([3; 32]),
+            7,
+            &Txid::from_slice(&[1; 32]).unwrap(),
+            &make_justice_tx(),
+        );
+        for _ in 0..MAX_WATCHTOWER_BLOBS + 2 {
+            queue.push(blob.clone());
+        }
+        let all = queue.since(0);
+        assert_eq!(all.len(), MAX_WATCHTOWER_BLOBS);
+        assert_eq!(all[0].0, 3);
+        let last = all.last().unwrap().0;
+        assert_eq!(queue.since(last - 1).len(), 1);
+        assert!(queue.since(last).is_empty());
+    }
+}
@@ -32,6 +32,7 @@ use lightning::ln::msgs::{ChannelMessageHandler, ChannelUpdate};
 use lightning::util::config::{ChannelHandshakeConfig, UserConfig};
 use lightning::util::events::{Event, EventsProvider, MessageSendEvent, MessageSendEventsProvider, ClosureReason};
 use lightning::util::logger::Logger;
+use lightning::util::ser::Writeable;
 
 use lightning_signer::policy::null_validator::NullValidatorFactory;
 use lightning_signer::signer::multi_signer::MultiSigner;
@@ -72,7 +73,7 @@ pub fn create_node_cfgs_with_signer<'a>(
     let tip = genesis_block(network).header;
 
     for i in 0..node_count {
-        let cfg = create_node_cfg(signer, chanmon_cfgs, config, network, tip, i);
+        let cfg = create_node_cfg(signer, chanmon_cfgs, config.clone(), network, tip, i);
         nodes.push(cfg);
     }
 
@@ -725,6 +726,41 @@ fn test_static_output_closing_tx() {
     check_spends!(spend_txn[0], closing_tx);
 }
 
+#[test]
+fn channel_monitor_backup_test() {
+    let signer = new_signer();
+
+    let chanmon_cfgs = create_chanmon_cfgs(2);
+    let node_cfgs = create_node_cfgs_with_signer(2, &signer, &chanmon_cfgs);
+    let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+    let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+    create_default_chan(&nodes, 0, 1);
+    send_payment(&nodes[0], &vec![&nodes[1]][..], 5000000);
+    send_payment(&nodes[1], &vec![&nodes[0]][..], 1000000);
+
+    let outpoints = nodes[0].chain_monitor.chain_monitor.list_monitors();
+    assert_eq!(outpoints.len(), 1);
+    let serialized = nodes[0].chain_monitor.chain_monitor.get_monitor(outpoints[0]).unwrap().encode();
+
+    let signer_node0 = nodes[0].keys_manager.get_node();
+    let channel_id = signer_node0.channels().keys().next().cloned().unwrap();
+    signer_node0.with_ready_channel(&channel_id, |chan| {
+        let divergences = chan.check_ldk_monitor(&serialized)?;
+        assert!(divergences.is_empty(), "{:?}", divergences);
+
+        // a signer that has seen one more revocation than the monitor
+        let mut ahead = chan.clone();
+        ahead.enforcement_state.next_counterparty_revoke_num += 1;
+        let divergences = ahead.check_ldk_monitor(&serialized)?;
+        assert_eq!(divergences.len(), 1);
+        assert_eq!(divergences[0].field, "next_counterparty_revoke_num");
+
+        assert!(chan.check_ldk_monitor(&serialized[..100]).is_err());
+        Ok(())
+    }).unwrap();
+}
+
 // Local Variables:
 // inhibit-rust-format-buffer: t
 // End:
@@ -14,6 +14,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "ReadyChannelRequest.counterparty_shutdown_script",
             "#[serde(serialize_with = \"crate::util::as_hex\")]",
         )
+        .field_attribute(
+            "NegotiateRequest.features",
+            "#[serde(serialize_with = \"crate::util::as_hex\")]",
+        )
+        .field_attribute(
+            "NegotiateReply.features",
+            "#[serde(serialize_with = \"crate::util::as_hex\")]",
+        )
         .field_attribute(
             "SignChannelAnnouncementRequest.channel_announcement",
             "#[serde(serialize_with = \"crate::util::as_hex\")]",
@@ -26,6 +34,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "SignChannelUpdateRequest.channel_update",
             "#[serde(serialize_with = \"crate::util::as_hex\")]",
         )
+        .field_attribute(
+            "SignGossipMessageRequest.message",
+            "#[serde(serialize_with = \"crate::util::as_hex\")]",
+        )
         .field_attribute(
             "SignInvoiceRequest.data_part",
             "#[serde(serialize_with = \"crate::util::as_hex\")]",
@@ -51,6 +63,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "SignMutualCloseTxPhase2Request.counterparty_shutdown_script",
             "#[serde(serialize_with = \"crate::util::as_hex\")]",
         )
+        .field_attribute(
+            "SignMutualCloseTxPhase2Request.approval_token",
+            "#[serde(serialize_with = \"crate::util::as_hex\")]",
+        )
+        .field_attribute(
+            "SignOnchainTxRequest.approval_token",
+            "#[serde(serialize_with = \"crate::util::as_hex\")]",
+        )
+        .field_attribute(
+            "SignFundingTxRequest.input_scripts",
+            "#[serde(serialize_with = \"crate::util::as_hex_vec\")]",
+        )
+        .field_attribute(
+            "SignFundingTxRequest.approval_token",
+            "#[serde(serialize_with = \"crate::util::as_hex\")]",
+        )
+        .field_attribute(
+            "SignPsbtRequest.psbt",
+            "#[serde(serialize_with = \"crate::util::as_hex\")]",
+        )
+        .field_attribute(
+            "SignPsbtRequest.approval_token",
+            "#[serde(serialize_with = \"crate::util::as_hex\")]",
+        )
+        .field_attribute("SignPsbtReply.psbt", "#[serde(serialize_with = \"crate::util::as_hex\")]")
         .field_attribute(
             "HTLCInfo.payment_hash",
             "#[serde(serialize_with = \"crate::util::as_hex\")]",
@@ -1,9 +1,11 @@
-use bitcoind_client::{BitcoindClient, BlockSource};
+use bitcoind_client::{block_txs_proof, BitcoindClient, BlockSource, EsploraClient};
 use clap::Clap;
-use lightning_signer::bitcoin::util::merkleblock::PartialMerkleTree;
-use lightning_signer::bitcoin::{Network, Transaction, Txid};
+use lightning_signer::bitcoin::consensus::deserialize;
+use lightning_signer::bitcoin::hashes::hex::FromHex;
+use lightning_signer::bitcoin::Network;
 use lightning_signer::chain::tracker::{ChainTracker, Error as TrackerError};
 use lightning_signer::monitor::ChainMonitor;
+use lightning_signer::node::ChainParams;
 use rand::random;
 use std::fmt::{self, Display, Formatter};
 use url::Url;
@@ -35,6 +37,10 @@ struct Opts {
     network: Network,
     #[clap(short, long, about = "bitcoind RPC URL, must have http(s) schema")]
     rpc: Option<String>,
+    #[clap(long, about = "Esplora API URL, to follow the chain without bitcoind")]
+    esplora: Option<String>,
+    #[clap(long, about = "genesis block header of a non-standard chain, in hex")]
+    genesis_header: Option<String>,
 }
 
 #[tokio::main]
@@ -53,23 +59,48 @@ async fn main() -> anyhow::Result<()> {
         .to_owned()
     });
 
-    let rpc = Url::parse(&rpc_s).expect("rpc url");
+    let mut chain_params = ChainParams::for_network(network);
+    if let Some(hex) = &opts.genesis_header {
+        chain_params.genesis = deserialize(&Vec::from_hex(hex)?)?;
+    }
+
+    let client: Box<dyn BlockSource> = match &opts.esplora {
+        Some(url) => Box::new(EsploraClient::new(url)),
+        None => {
+            let rpc = Url::parse(&rpc_s).expect("rpc url");
+            Box::new(
+                BitcoindClient::new(
+                    rpc.host_str().expect("rpc host").to_owned(),
+                    rpc.port().expect("rpc port"),
+                    rpc.username().to_owned(),
+                    rpc.password().to_owned().expect("rpc password").to_owned(),
+                )
+                .await?,
+            )
+        }
+    };
 
-    run_test(network, rpc).await?;
+    run_test(network, chain_params, client.as_ref()).await?;
     Ok(())
 }
 
-async fn run_test(network: Network, rpc: Url) -> anyhow::Result<()> {
-    let client = BitcoindClient::new(
-        rpc.host_str().expect("rpc host").to_owned(),
-        rpc.port().expect("rpc port"),
-        rpc.username().to_owned(),
-        rpc.password().to_owned().expect("rpc password").to_owned(),
-    )
-    .await?;
-    let info = client.get_blockchain_info().await;
-    println!("{:?}", info);
-    let start_height = info.latest_height as u32 - 100000;
+async fn run_test(
+    network: Network,
+    chain_params: ChainParams,
+    client: &dyn BlockSource,
+) -> anyhow::Result<()> {
+    let (best_hash, best_height) = client.get_best_block().await?;
+    println!("best block {} at {}", best_hash, best_height);
+    // Make sure we are following the expected chain
+    let genesis_hash = client.get_block_hash(0).await?.expect("genesis");
+    if genesis_hash != chain_params.genesis_hash() {
+        anyhow::bail!(
+            "chain source genesis {} does not match the expected {}",
+            genesis_hash,
+            chain_params.genesis_hash()
+        );
+    }
+    let start_height = best_height - 100000;
     let start_hash = client.get_block_hash(start_height).await?.expect("block disappeared");
     let tip = client.get_header(&start_hash, None).await?;
     assert_eq!(start_height, tip.height);
@@ -81,22 +112,13 @@ async fn run_test(network: Network, rpc: Url) -> anyhow::Result<()> {
         if let Some(hash) = hash_opt {
             let (txs, proof) = if random::<f32>() < 0.002 {
                 let block = client.get_block(&hash).await?;
-                let txs = block.txdata;
-                let txids: Vec<Txid> = txs.iter().map(|tx| tx.txid()).collect();
-                let matches: Vec<bool> = txs.iter().map(|_| random::<f32>() < 0.01).collect();
-                let matched_txs: Vec<Transaction> = txs
-                    .into_iter()
-                    .zip(matches.clone())
-                    .filter(|(_, m)| *m)
-                    .map(|(tx, _)| tx)
-                    .collect();
-                if matched_txs.is_empty() {
-                    (vec![], None)
-                } else {
-                    let proof = PartialMerkleTree::from_txids(&txids, &matches);
+                let matches: Vec<bool> =
+                    block.txdata.iter().map(|_| random::<f32>() < 0.01).collect();
+                let (matched_txs, proof) = block_txs_proof(block, &matches);
+                if !matched_txs.is_empty() {
                     println!("proof for {} txs", matched_txs.len());
-                    (matched_txs, Some(proof))
                 }
+                (matched_txs, proof)
             } else {
                 (vec![], None)
             };
@@ -1,25 +1,109 @@
-use tonic::{transport, Request};
+use std::str::FromStr;
+
+use bitcoin::hashes::Hash;
+use log::warn;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::{InterceptedService, Interceptor};
+use tonic::{transport, Code, Request, Status};
+
+use lightning_signer::util::status::{
+    POLICY_CHANNEL_METADATA_KEY, POLICY_TAG_METADATA_KEY, POLICY_VALUES_METADATA_KEY,
+};
 
 use remotesigner::signer_client::SignerClient;
 
+use crate::server::auth::AUTHORIZATION_METADATA_KEY;
+use crate::server::protocol;
 use crate::server::remotesigner;
+use crate::server::remotesigner::get_channel_status_reply::State;
 use crate::server::remotesigner::node_config::KeyDerivationStyle;
+use crate::server::remotesigner::parked_request::Decision;
+use crate::server::remotesigner::payment::Resolution;
 use crate::server::remotesigner::{
-    AddAllowlistRequest, Bip32Seed, ChainParams, ChannelNonce, GetPerCommitmentPointRequest,
-    InitRequest, ListAllowlistRequest, ListChannelsRequest, ListNodesRequest, NewChannelRequest,
-    NodeConfig, NodeId, PingRequest, RemoveAllowlistRequest,
+    AcknowledgeChannelReviewRequest, AddAllowlistRequest, AddFeeReserveRequest, Bip32Seed,
+    ChainParams, ChannelNonce, CreateBackupRequest, DecideApprovalRequest, GetAddressRequest,
+    GetChannelStatusRequest, GetMetadataRequest, GetNodeStatusRequest,
+    GetPerCommitmentPointRequest, GetReplicationStatusRequest, GetStatsRequest,
+    GetWalletDescriptorRequest, InitRequest, ListAllowlistRequest, ListApprovalsRequest,
+    ListChannelReviewsRequest, ListChannelsRequest, ListFeeReserveRequest, ListNodesRequest,
+    ListPaymentsRequest, ListTenantsRequest, ListTokensRequest, ListWatchtowerBlobsRequest,
+    MetadataEntry, MintTokenRequest, NewChannelRequest, NodeConfig, NodeId, Outpoint, PingRequest,
+    PromoteRequest, RemoveAllowlistRequest, RemoveFeeReserveRequest, RemoveTenantRequest,
+    RestoreBackupRequest, RevokeTokenRequest, RotateTokenRequest, SetMetadataRequest,
+    SetTenantRequest, ShutdownRequest, SpendType, Tenant,
 };
 
 use bip39::{Language, Mnemonic};
 use rand::{OsRng, Rng};
 
-pub async fn connect() -> Result<SignerClient<transport::Channel>, Box<dyn std::error::Error>> {
-    Ok(SignerClient::connect("http://127.0.0.1:50051").await?)
+/// The address of a local signer server
+pub const DEFAULT_SERVER: &str = "http://127.0.0.1:50051";
+
+/// Client TLS settings, for a server that requires client certificates
+pub struct ClientTls {
+    /// The PEM CA certificate that the server certificate is signed by
+    pub ca: Vec<u8>,
+    /// The PEM certificate of the client
+    pub cert: Vec<u8>,
+    /// The PEM private key of the client certificate
+    pub key: Vec<u8>,
+    /// The name in the server certificate, if it isn't the server host
+    pub domain: Option<String>,
 }
 
-pub async fn ping(
-    client: &mut SignerClient<transport::Channel>,
-) -> Result<(), Box<dyn std::error::Error>> {
+/// Adds the bearer token, if any, to each request
+#[derive(Clone)]
+pub struct TokenInterceptor {
+    authorization: Option<MetadataValue<Ascii>>,
+}
+
+impl Interceptor for TokenInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(authorization) = self.authorization.as_ref() {
+            request.metadata_mut().insert(AUTHORIZATION_METADATA_KEY, authorization.clone());
+        }
+        Ok(request)
+    }
+}
+
+pub type Client = SignerClient<InterceptedService<transport::Channel, TokenInterceptor>>;
+
+pub async fn connect(
+    server: &str,
+    tls: Option<ClientTls>,
+    token: Option<String>,
+) -> Result<Client, Box<dyn std::error::Error>> {
+    let mut endpoint = transport::Endpoint::from_shared(server.to_string())?;
+    if let Some(tls) = tls {
+        let mut config = transport::ClientTlsConfig::new()
+            .ca_certificate(transport::Certificate::from_pem(tls.ca))
+            .identity(transport::Identity::from_pem(tls.cert, tls.key));
+        if let Some(domain) = tls.domain {
+            config = config.domain_name(domain);
+        }
+        endpoint = endpoint.tls_config(config)?;
+    }
+    let authorization = token.map(|t| format!("Bearer {}", t).parse()).transpose()?;
+    let channel = endpoint.connect().await?;
+    let mut client = SignerClient::with_interceptor(channel, TokenInterceptor { authorization });
+    negotiate(&mut client).await?;
+    Ok(client)
+}
+
+// Negotiate the protocol version and features with the signer
+async fn negotiate(client: &mut Client) -> Result<u32, Status> {
+    match client.negotiate(Request::new(protocol::negotiate_request())).await {
+        Ok(reply) => protocol::check_reply(&reply.into_inner()),
+        // a signer from before the negotiation speaks the first version
+        Err(status) if status.code() == Code::Unimplemented => {
+            warn!("the signer doesn't negotiate the protocol, assuming version 1");
+            Ok(1)
+        }
+        Err(status) => Err(status),
+    }
+}
+
+pub async fn ping(client: &mut Client) -> Result<(), Box<dyn std::error::Error>> {
     let ping_request = Request::new(PingRequest { message: "hello".into() });
 
     let response = client.ping(ping_request).await?;
@@ -28,22 +112,68 @@ pub async fn ping(
     Ok(())
 }
 
+pub async fn get_replication_status(client: &mut Client) -> Result<(), Box<dyn std::error::Error>> {
+    let status_request = Request::new(GetReplicationStatusRequest {});
+
+    let response = client.get_replication_status(status_request).await?.into_inner();
+    println!("role: {}", response.role);
+    println!("fencing token: {}", response.fencing_token);
+    println!("mirrored change: {}", response.mirrored_sequence);
+    Ok(())
+}
+
+pub async fn promote(client: &mut Client) -> Result<(), Box<dyn std::error::Error>> {
+    let promote_request = Request::new(PromoteRequest {});
+
+    let response = client.promote(promote_request).await?.into_inner();
+    println!("promoted with fencing token {}", response.fencing_token);
+    Ok(())
+}
+
+pub async fn shutdown(client: &mut Client) -> Result<(), Box<dyn std::error::Error>> {
+    let shutdown_request = Request::new(ShutdownRequest {});
+
+    client.shutdown(shutdown_request).await?.into_inner();
+    Ok(())
+}
+
+/// The names of the key derivation styles, where `cln` is the same as `native`
+pub const KEY_DERIVATION_STYLE_NAMES: [&str; 4] = ["native", "cln", "lnd", "ldk"];
+
+/// The key derivation style with a name in [KEY_DERIVATION_STYLE_NAMES]
+pub fn key_derivation_style(name: &str) -> Result<KeyDerivationStyle, String> {
+    match name {
+        "native" | "cln" => Ok(KeyDerivationStyle::Native),
+        "lnd" => Ok(KeyDerivationStyle::Lnd),
+        "ldk" => Ok(KeyDerivationStyle::Ldk),
+        _ => Err(format!("unknown key derivation style {}", name)),
+    }
+}
+
 pub async fn new_node(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut Client,
     network_name: String,
+    key_derivation_style: KeyDerivationStyle,
+    bip39_passphrase: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mnemonic = Mnemonic::generate_in(Language::English, 12).unwrap();
-    new_node_with_mnemonic(client, mnemonic, network_name).await
+    new_node_with_mnemonic(client, mnemonic, network_name, key_derivation_style, bip39_passphrase)
+        .await
 }
 
+/// Add a node with the BIP39 seed of the mnemonic and passphrase, so that
+/// a node imported from another implementation derives the same keys there
+/// and here, given the matching key derivation style.
 pub async fn new_node_with_mnemonic(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut Client,
     mnemonic: Mnemonic,
     network_name: String,
+    key_derivation_style: KeyDerivationStyle,
+    bip39_passphrase: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let secret = mnemonic.to_seed("");
+    let secret = mnemonic.to_seed(bip39_passphrase);
     let init_request = Request::new(InitRequest {
-        node_config: Some(NodeConfig { key_derivation_style: KeyDerivationStyle::Native as i32 }),
+        node_config: Some(NodeConfig { key_derivation_style: key_derivation_style as i32 }),
         chainparams: Some(ChainParams { network_name }),
         coldstart: true,
         hsm_secret: Some(Bip32Seed { data: secret.to_vec() }),
@@ -57,41 +187,152 @@ pub async fn new_node_with_mnemonic(
     Ok(())
 }
 
-pub async fn list_nodes(
-    client: &mut SignerClient<transport::Channel>,
-) -> Result<(), Box<dyn std::error::Error>> {
+/// The hex node IDs, sorted
+pub async fn get_node_ids(client: &mut Client) -> Result<Vec<String>, Box<dyn std::error::Error>> {
     let list_request = Request::new(ListNodesRequest {});
 
     let response = client.list_nodes(list_request).await?.into_inner();
-    let mut node_ids: Vec<&Vec<u8>> = response.node_ids.iter().map(|id| &id.data).collect();
+    let mut node_ids: Vec<String> =
+        response.node_ids.iter().map(|id| hex::encode(&id.data)).collect();
     node_ids.sort();
+    Ok(node_ids)
+}
 
-    for node_id in node_ids {
-        println!("{}", hex::encode(node_id));
+pub async fn list_nodes(client: &mut Client) -> Result<(), Box<dyn std::error::Error>> {
+    for node_id in get_node_ids(client).await? {
+        println!("{}", node_id);
     }
     Ok(())
 }
 
-pub async fn list_channels(
-    client: &mut SignerClient<transport::Channel>,
+/// The hex channel nonces of a node, sorted
+pub async fn get_channel_nonces(
+    client: &mut Client,
     node_id: Vec<u8>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
     let list_request =
         Request::new(ListChannelsRequest { node_id: Some(NodeId { data: node_id }) });
 
     let response = client.list_channels(list_request).await?.into_inner();
-    let mut channel_ids: Vec<&Vec<u8>> =
-        response.channel_nonces.iter().map(|id| &id.data).collect();
+    let mut channel_ids: Vec<String> =
+        response.channel_nonces.iter().map(|id| hex::encode(&id.data)).collect();
     channel_ids.sort();
+    Ok(channel_ids)
+}
 
-    for channel_nonce in channel_ids {
-        println!("{}", hex::encode(channel_nonce));
+pub async fn list_channels(
+    client: &mut Client,
+    node_id: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for channel_nonce in get_channel_nonces(client, node_id).await? {
+        println!("{}", channel_nonce);
+    }
+    Ok(())
+}
+
+pub async fn get_node_status(
+    client: &mut Client,
+    node_id: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let status_request =
+        Request::new(GetNodeStatusRequest { node_id: Some(NodeId { data: node_id }) });
+
+    let status = client.get_node_status(status_request).await?.into_inner();
+    println!("height {} tip {}", status.height, status.tip);
+    println!(
+        "channels: {} stub, {} ready, {} closing, {} closed",
+        status.stub_channels,
+        status.ready_channels,
+        status.closing_channels,
+        status.closed_channels
+    );
+    Ok(())
+}
+
+pub async fn get_wallet_descriptor(
+    client: &mut Client,
+    node_id: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let descriptor_request =
+        Request::new(GetWalletDescriptorRequest { node_id: Some(NodeId { data: node_id }) });
+
+    let response = client.get_wallet_descriptor(descriptor_request).await?.into_inner();
+    for descriptor in response.descriptors {
+        println!("{}", descriptor);
+    }
+    Ok(())
+}
+
+pub async fn get_address(
+    client: &mut Client,
+    node_id: Vec<u8>,
+    index: u32,
+    spend_type: SpendType,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let address_request = Request::new(GetAddressRequest {
+        node_id: Some(NodeId { data: node_id }),
+        index,
+        spend_type: spend_type as i32,
+    });
+
+    let response = client.get_address(address_request).await?.into_inner();
+    println!("{}", response.address);
+    Ok(())
+}
+
+pub async fn get_channel_status(
+    client: &mut Client,
+    node_id: Vec<u8>,
+    channel_nonce: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let status_request = Request::new(GetChannelStatusRequest {
+        node_id: Some(NodeId { data: node_id }),
+        channel_nonce: Some(ChannelNonce { data: channel_nonce }),
+    });
+
+    let status = client.get_channel_status(status_request).await?.into_inner();
+    let state = State::from_i32(status.state).unwrap_or(State::Stub);
+    println!("{:?}", state);
+    if state == State::Stub {
+        return Ok(());
+    }
+    if let Some(outpoint) = status.funding_outpoint {
+        let txid = bitcoin::Txid::from_slice(&outpoint.txid)?;
+        println!(
+            "funding {}:{} {} sat, depth {}, double-spent depth {}, closing depth {}",
+            txid,
+            outpoint.index,
+            status.channel_value_sat,
+            status.funding_depth,
+            status.funding_double_spent_depth,
+            status.closing_depth
+        );
+    }
+    println!(
+        "holder commit {} revoke {}, counterparty commit {} revoke {}",
+        status.next_holder_commit_num,
+        status.next_holder_revoke_num,
+        status.next_counterparty_commit_num,
+        status.next_counterparty_revoke_num
+    );
+    let commitments =
+        [("holder", status.holder_commitment), ("counterparty", status.counterparty_commitment)];
+    for (name, balance) in commitments {
+        if let Some(b) = balance {
+            println!(
+                "{} commitment: to holder {} sat, to counterparty {} sat, offered {} sat, received {} sat",
+                name, b.to_holder_sat, b.to_counterparty_sat, b.offered_htlc_sat, b.received_htlc_sat
+            );
+        }
+    }
+    if status.mutual_close_signed {
+        println!("mutual close signed");
     }
     Ok(())
 }
 
 pub async fn list_allowlist(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut Client,
     node_id: Vec<u8>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let list_request =
@@ -105,7 +346,7 @@ pub async fn list_allowlist(
 }
 
 pub async fn add_allowlist(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut Client,
     node_id: Vec<u8>,
     addresses: Vec<String>,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -117,7 +358,7 @@ pub async fn add_allowlist(
 }
 
 pub async fn remove_allowlist(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut Client,
     node_id: Vec<u8>,
     addresses: Vec<String>,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -128,8 +369,353 @@ pub async fn remove_allowlist(
     Ok(())
 }
 
+// Parse outpoints given as <txid>:<vout>
+fn parse_outpoints(outpoints: Vec<String>) -> Result<Vec<Outpoint>, Box<dyn std::error::Error>> {
+    outpoints
+        .iter()
+        .map(|s| {
+            let outpoint =
+                bitcoin::OutPoint::from_str(s).map_err(|e| format!("bad outpoint {}: {}", s, e))?;
+            Ok(Outpoint { txid: outpoint.txid[..].to_vec(), index: outpoint.vout })
+        })
+        .collect()
+}
+
+pub async fn list_fee_reserve(
+    client: &mut Client,
+    node_id: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let list_request =
+        Request::new(ListFeeReserveRequest { node_id: Some(NodeId { data: node_id }) });
+
+    let response = client.list_fee_reserve(list_request).await?.into_inner();
+    for outpoint in response.outpoints {
+        let txid = bitcoin::Txid::from_slice(&outpoint.txid)?;
+        println!("{}", bitcoin::OutPoint { txid, vout: outpoint.index });
+    }
+    Ok(())
+}
+
+pub async fn add_fee_reserve(
+    client: &mut Client,
+    node_id: Vec<u8>,
+    outpoints: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let outpoints = parse_outpoints(outpoints)?;
+    let add_request =
+        Request::new(AddFeeReserveRequest { node_id: Some(NodeId { data: node_id }), outpoints });
+
+    client.add_fee_reserve(add_request).await?.into_inner();
+    Ok(())
+}
+
+pub async fn remove_fee_reserve(
+    client: &mut Client,
+    node_id: Vec<u8>,
+    outpoints: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let outpoints = parse_outpoints(outpoints)?;
+    let remove_request = Request::new(RemoveFeeReserveRequest {
+        node_id: Some(NodeId { data: node_id }),
+        outpoints,
+    });
+
+    client.remove_fee_reserve(remove_request).await?.into_inner();
+    Ok(())
+}
+
+pub async fn list_payments(
+    client: &mut Client,
+    node_id: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let list_request =
+        Request::new(ListPaymentsRequest { node_id: Some(NodeId { data: node_id }) });
+
+    let response = client.list_payments(list_request).await?.into_inner();
+    for payment in response.payments {
+        let resolution = Resolution::from_i32(payment.resolution).unwrap_or(Resolution::Pending);
+        let direction = if payment.outgoing { "out" } else { "in" };
+        println!(
+            "{} {} {} msat {:?} expires {}",
+            hex::encode(payment.payment_hash),
+            direction,
+            payment.amount_msat,
+            resolution,
+            payment.expiry_time
+        );
+    }
+    println!("paid {} msat, received {} msat", response.paid_msat, response.received_msat);
+    println!(
+        "pending out {} msat, pending in {} msat",
+        response.pending_outgoing_msat, response.pending_incoming_msat
+    );
+    Ok(())
+}
+
+pub async fn list_watchtower_blobs(
+    client: &mut Client,
+    node_id: Vec<u8>,
+    since_sequence: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let list_request = Request::new(ListWatchtowerBlobsRequest {
+        node_id: Some(NodeId { data: node_id }),
+        since_sequence,
+    });
+
+    let response = client.list_watchtower_blobs(list_request).await?.into_inner();
+    for blob in response.blobs {
+        println!(
+            "{} {} {} {} {}",
+            blob.sequence,
+            hex::encode(blob.channel_id),
+            blob.commit_num,
+            hex::encode(blob.locator),
+            hex::encode(blob.encrypted_blob)
+        );
+    }
+    Ok(())
+}
+
+pub async fn mint_token(
+    client: &mut Client,
+    label: String,
+    scopes: Vec<String>,
+    tenant: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mint_request = Request::new(MintTokenRequest { label, scopes, tenant });
+
+    let response = client.mint_token(mint_request).await?.into_inner();
+    println!("{}", response.token);
+    Ok(())
+}
+
+pub async fn rotate_token(
+    client: &mut Client,
+    id: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rotate_request = Request::new(RotateTokenRequest { id });
+
+    let response = client.rotate_token(rotate_request).await?.into_inner();
+    println!("{}", response.token);
+    Ok(())
+}
+
+pub async fn revoke_token(
+    client: &mut Client,
+    id: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let revoke_request = Request::new(RevokeTokenRequest { id });
+
+    client.revoke_token(revoke_request).await?.into_inner();
+    Ok(())
+}
+
+pub async fn list_tokens(client: &mut Client) -> Result<(), Box<dyn std::error::Error>> {
+    let list_request = Request::new(ListTokensRequest {});
+
+    let response = client.list_tokens(list_request).await?.into_inner();
+    for token in response.tokens {
+        println!("{} {} {} {}", token.id, token.label, token.scopes.join(","), token.tenant);
+    }
+    Ok(())
+}
+
+pub async fn set_tenant(
+    client: &mut Client,
+    name: String,
+    node_ids: Vec<Vec<u8>>,
+    max_channels: u32,
+    max_signs_per_minute: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let node_ids = node_ids.into_iter().map(|data| NodeId { data }).collect();
+    let tenant = Tenant { name, node_ids, max_channels, max_signs_per_minute };
+    let set_request = Request::new(SetTenantRequest { tenant: Some(tenant) });
+
+    client.set_tenant(set_request).await?.into_inner();
+    Ok(())
+}
+
+pub async fn list_tenants(client: &mut Client) -> Result<(), Box<dyn std::error::Error>> {
+    let list_request = Request::new(ListTenantsRequest {});
+
+    let response = client.list_tenants(list_request).await?.into_inner();
+    for tenant in response.tenants {
+        println!(
+            "{} max-channels {} max-signs-per-minute {}",
+            tenant.name, tenant.max_channels, tenant.max_signs_per_minute
+        );
+        for node_id in tenant.node_ids {
+            println!("  {}", hex::encode(node_id.data));
+        }
+    }
+    Ok(())
+}
+
+pub async fn remove_tenant(
+    client: &mut Client,
+    name: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let remove_request = Request::new(RemoveTenantRequest { name });
+
+    client.remove_tenant(remove_request).await?.into_inner();
+    Ok(())
+}
+
+pub async fn get_metadata(
+    client: &mut Client,
+    node_id: Vec<u8>,
+    channel_nonce: Option<Vec<u8>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let get_request = Request::new(GetMetadataRequest {
+        node_id: Some(NodeId { data: node_id }),
+        channel_nonce: channel_nonce.map(|data| ChannelNonce { data }),
+    });
+
+    let response = client.get_metadata(get_request).await?.into_inner();
+    for entry in response.entries {
+        println!("{}={}", entry.key, entry.value);
+    }
+    Ok(())
+}
+
+pub async fn set_metadata(
+    client: &mut Client,
+    node_id: Vec<u8>,
+    channel_nonce: Option<Vec<u8>>,
+    entries: Vec<(String, String)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let set_request = Request::new(SetMetadataRequest {
+        node_id: Some(NodeId { data: node_id }),
+        channel_nonce: channel_nonce.map(|data| ChannelNonce { data }),
+        entries: entries.into_iter().map(|(key, value)| MetadataEntry { key, value }).collect(),
+    });
+
+    client.set_metadata(set_request).await?.into_inner();
+    Ok(())
+}
+
+pub async fn list_channel_reviews(
+    client: &mut Client,
+    node_id: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let list_request =
+        Request::new(ListChannelReviewsRequest { node_id: Some(NodeId { data: node_id }) });
+
+    let response = client.list_channel_reviews(list_request).await?.into_inner();
+    for review in response.reviews {
+        let nonce = review.channel_nonce.map(|n| hex::encode(n.data)).unwrap_or_default();
+        println!("{} {}", nonce, review.reason);
+    }
+    Ok(())
+}
+
+pub async fn acknowledge_channel_review(
+    client: &mut Client,
+    node_id: Vec<u8>,
+    channel_nonce: Vec<u8>,
+    lease_id: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ack_request = Request::new(AcknowledgeChannelReviewRequest {
+        node_id: Some(NodeId { data: node_id }),
+        channel_nonce: Some(ChannelNonce { data: channel_nonce }),
+        lease_id,
+    });
+
+    client.acknowledge_channel_review(ack_request).await?.into_inner();
+    Ok(())
+}
+
+pub async fn create_backup(
+    client: &mut Client,
+    passphrase: String,
+    path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backup_request = Request::new(CreateBackupRequest { passphrase });
+
+    let response = client.create_backup(backup_request).await?.into_inner();
+    std::fs::write(path, &response.archive)?;
+    println!("wrote {} bytes to {}", response.archive.len(), path);
+    Ok(())
+}
+
+pub async fn restore_backup(
+    client: &mut Client,
+    passphrase: String,
+    path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let archive = std::fs::read(path)?;
+    let restore_request = Request::new(RestoreBackupRequest { passphrase, archive });
+
+    let response = client.restore_backup(restore_request).await?.into_inner();
+    for node_id in response.node_ids {
+        println!("{}", hex::encode(node_id.data));
+    }
+    Ok(())
+}
+
+pub async fn list_approvals(client: &mut Client) -> Result<(), Box<dyn std::error::Error>> {
+    let list_request = Request::new(ListApprovalsRequest {});
+
+    let response = client.list_approvals(list_request).await?.into_inner();
+    for parked in response.requests {
+        let decision = Decision::from_i32(parked.decision).unwrap_or(Decision::Pending);
+        let node_id = parked.node_id.map(|n| hex::encode(n.data)).unwrap_or_default();
+        println!("{} {:?} {} {}", parked.id, decision, parked.method, node_id);
+        println!("  {}", parked.message);
+        println!("  {}", parked.request);
+    }
+    Ok(())
+}
+
+pub async fn decide_approval(
+    client: &mut Client,
+    id: String,
+    approve: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let decide_request = Request::new(DecideApprovalRequest { id, approve });
+
+    client.decide_approval(decide_request).await?.into_inner();
+    Ok(())
+}
+
+pub async fn get_stats(client: &mut Client, limit: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let stats_request = Request::new(GetStatsRequest { limit });
+
+    let response = client.get_stats(stats_request).await?.into_inner();
+    println!("timestamp  period  signs  violations  warnings  verify_failures  chain_lag");
+    for s in response.snapshots {
+        println!(
+            "{}  {}  {}  {}  {}  {}  {}",
+            s.timestamp,
+            s.period_secs,
+            s.sign_operations,
+            s.policy_violations,
+            s.policy_warnings,
+            s.signature_verify_failures,
+            s.max_chain_lag_secs
+        );
+    }
+    Ok(())
+}
+
+/// Describe the policy violation carried in the metadata of a failed
+/// request, if any
+pub fn policy_violation(status: &Status) -> Option<String> {
+    let metadata = status.metadata();
+    let get = |key: &str| metadata.get(key).and_then(|v| v.to_str().ok());
+    let tag = get(POLICY_TAG_METADATA_KEY)?;
+    let mut desc = format!("policy violation: {}", tag);
+    if let Some(values) = get(POLICY_VALUES_METADATA_KEY).filter(|v| !v.is_empty()) {
+        desc.push_str(&format!(" [{}]", values));
+    }
+    if let Some(channel_id) = get(POLICY_CHANNEL_METADATA_KEY) {
+        desc.push_str(&format!(" channel {}", channel_id));
+    }
+    Some(desc)
+}
+
 pub async fn new_channel(
-    client: &mut SignerClient<transport::Channel>,
+    client: &mut Client,
     node_id: Vec<u8>,
     nonce_hex: Option<&str>,
     no_nonce: bool,
@@ -149,6 +735,8 @@ pub async fn new_channel(
         } else {
             Some(ChannelNonce { data: channel_nonce.to_vec() })
         },
+        peer_id: None,
+        dbid: 0,
     });
     let response = client.new_channel(new_chan_request).await?.into_inner();
     if !no_nonce {
@@ -158,9 +746,7 @@ pub async fn new_channel(
     Ok(())
 }
 
-pub async fn integration_test(
-    client: &mut SignerClient<transport::Channel>,
-) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn integration_test(client: &mut Client) -> Result<(), Box<dyn std::error::Error>> {
     ping(client).await?;
 
     let init_request = Request::new(InitRequest {
@@ -179,6 +765,8 @@ pub async fn integration_test(
     let new_chan_request = Request::new(NewChannelRequest {
         node_id: Some(NodeId { data: node_id.clone() }),
         channel_nonce0: Some(ChannelNonce { data: channel_nonce.to_vec() }),
+        peer_id: None,
+        dbid: 0,
     });
     let response = client.new_channel(new_chan_request).await?;
 
@@ -0,0 +1,622 @@
+//! An LDK signer backed by a remote signer server, so that LDK based nodes
+//! can keep their keys in VLS.
+//!
+//! [RemoteSignerKeysInterface] implements the LDK [KeysInterface] for a node
+//! of the server, and its [RemoteChannelSigner]s implement [BaseSign] with
+//! the gRPC methods of the server, as the loopback signer of the core does
+//! in process.  The LDK traits are blocking, so the calls are made on a
+//! runtime owned by the adapter, and must not be made from a task of
+//! another tokio runtime.
+//!
+//! Some LDK methods, such as [BaseSign::get_per_commitment_point], can't
+//! report a failure, so they panic if the server can't be reached.  The
+//! others log the status and return an error.
+
+use std::convert::TryInto;
+use std::io::Error as IOError;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use bitcoin::bech32::u5;
+use bitcoin::consensus::serialize;
+use bitcoin::hash_types::WPubkeyHash;
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::secp256k1::recovery::{RecoverableSignature, RecoveryId};
+use bitcoin::secp256k1::{All, PublicKey, Secp256k1, SecretKey, Signature};
+use bitcoin::util::bip32::{ChildNumber, ExtendedPubKey};
+use bitcoin::{Script, SigHashType, Transaction};
+use log::{error, info};
+use rand::{OsRng, Rng};
+use tokio::runtime::Runtime;
+use tonic::{Request, Status};
+
+use lightning_signer::util::crypto_utils::bitcoin_vec_to_signature;
+use lightning_signer::util::INITIAL_COMMITMENT_NUMBER;
+
+use crate::lightning::chain::keysinterface::{
+    BaseSign, KeyMaterial, KeysInterface, Recipient, Sign,
+};
+use crate::lightning::ln::chan_utils::{
+    self, ChannelPublicKeys, ChannelTransactionParameters, ClosingTransaction,
+    CommitmentTransaction, HTLCOutputInCommitment, HolderCommitmentTransaction, TxCreationKeys,
+};
+use crate::lightning::ln::msgs::{DecodeError, UnsignedChannelAnnouncement};
+use crate::lightning::ln::script::ShutdownScript;
+use crate::lightning::ln::PaymentPreimage;
+use crate::lightning::util::ser::{Readable, Writeable, Writer};
+use crate::server::remotesigner::ready_channel_request::CommitmentType;
+use crate::server::remotesigner::{
+    Basepoints, BitcoinSignature, ChannelNonce, CommitmentInfo, GetChannelBasepointsRequest,
+    GetNodeParamRequest, GetPerCommitmentPointRequest, InputDescriptor, KeyLocator,
+    NewChannelRequest, NodeId, OutputDescriptor, ReadyChannelRequest,
+    SignChannelAnnouncementRequest, SignCounterpartyCommitmentTxPhase2Request,
+    SignCounterpartyHtlcSweepRequest, SignHolderCommitmentTxPhase2Request, SignInvoiceRequest,
+    SignJusticeSweepRequest, SignMutualCloseTxPhase2Request, ValidateCounterpartyRevocationRequest,
+    ValidateHolderCommitmentTxPhase2Request,
+};
+
+use super::driver::{connect, Client, ClientTls};
+
+// The wallet path of the destination and shutdown scripts, as used by the
+// loopback signer
+const WALLET_PATH: [u32; 1] = [1];
+
+// The connection shared by the keys interface and its channel signers
+struct Remote {
+    runtime: Runtime,
+    client: Client,
+    node_id: PublicKey,
+}
+
+impl Remote {
+    fn node_id(&self) -> Option<NodeId> {
+        Some(self.node_id.into())
+    }
+}
+
+// Call a gRPC method of the server, and wait for the reply
+macro_rules! call {
+    ($remote: expr, $method: ident, $request: expr) => {
+        $remote
+            .runtime
+            .block_on($remote.client.clone().$method(Request::new($request)))
+            .map(|response| response.into_inner())
+    };
+}
+
+fn bad_status(method: &str, status: Status) {
+    error!("{}: bad status {:?} from the signer", method, status);
+}
+
+fn public_key(data: &[u8]) -> Result<PublicKey, Status> {
+    PublicKey::from_slice(data).map_err(|e| Status::internal(format!("bad public key: {}", e)))
+}
+
+fn signature(sig: Option<BitcoinSignature>) -> Result<Signature, Status> {
+    let sig = sig.ok_or_else(|| Status::internal("missing signature"))?;
+    bitcoin_vec_to_signature(&sig.data, SigHashType::All)
+        .map_err(|e| Status::internal(format!("bad signature: {}", e)))
+}
+
+fn signatures(sigs: Vec<BitcoinSignature>) -> Result<Vec<Signature>, Status> {
+    sigs.into_iter().map(|sig| signature(Some(sig))).collect()
+}
+
+/// Adapt a node of a remote signer server to the LDK [KeysInterface]
+pub struct RemoteSignerKeysInterface {
+    remote: Arc<Remote>,
+    xpub: ExtendedPubKey,
+    node_secret: SecretKey,
+}
+
+impl RemoteSignerKeysInterface {
+    /// Connect to a node of the server, which must already exist, see the
+    /// Init method
+    pub fn connect(
+        server: &str,
+        tls: Option<ClientTls>,
+        token: Option<String>,
+        node_id: PublicKey,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let runtime = Runtime::new()?;
+        let client = runtime.block_on(connect(server, tls, token))?;
+        let remote = Remote { runtime, client, node_id };
+        let reply =
+            call!(remote, get_node_param, GetNodeParamRequest { node_id: remote.node_id() })?;
+        let xpub = ExtendedPubKey::from_str(&reply.xpub.unwrap_or_default().encoded)?;
+        // TODO secret key leaking, LDK needs the node secret for the peer
+        // handshake and onion decoding
+        let node_secret = SecretKey::from_slice(&reply.node_secret.unwrap_or_default().data)?;
+        info!("remote signer for node {} at {}", node_id, server);
+        Ok(RemoteSignerKeysInterface { remote: Arc::new(remote), xpub, node_secret })
+    }
+
+    /// The node at the server
+    pub fn node_id(&self) -> PublicKey {
+        self.remote.node_id
+    }
+
+    fn wallet_pubkey(&self) -> PublicKey {
+        let secp_ctx = Secp256k1::verification_only();
+        let path: Vec<ChildNumber> =
+            WALLET_PATH.iter().map(|i| ChildNumber::from_normal_idx(*i).unwrap()).collect();
+        self.xpub.derive_pub(&secp_ctx, &path).expect("wallet key").public_key.key
+    }
+}
+
+/// An LDK channel signer, for a channel of a node of a remote signer server
+pub struct RemoteChannelSigner {
+    remote: Arc<Remote>,
+    channel_nonce: [u8; 32],
+    pubkeys: ChannelPublicKeys,
+    is_outbound: bool,
+    channel_value_sat: u64,
+    // Set once the channel is ready
+    channel_parameters: Option<ChannelTransactionParameters>,
+}
+
+impl RemoteChannelSigner {
+    fn new(
+        remote: Arc<Remote>,
+        channel_nonce: [u8; 32],
+        is_outbound: bool,
+        channel_value_sat: u64,
+        channel_parameters: Option<ChannelTransactionParameters>,
+    ) -> Result<Self, Status> {
+        let request = GetChannelBasepointsRequest {
+            node_id: remote.node_id(),
+            channel_nonce: Some(ChannelNonce { data: channel_nonce.to_vec() }),
+        };
+        let points = call!(remote, get_channel_basepoints, request)?
+            .basepoints
+            .ok_or_else(|| Status::internal("missing basepoints"))?;
+        let point = |p: Option<_>| public_key(&p.unwrap_or_default().data);
+        let pubkeys = ChannelPublicKeys {
+            funding_pubkey: point(points.funding_pubkey)?,
+            revocation_basepoint: point(points.revocation)?,
+            payment_point: point(points.payment)?,
+            delayed_payment_basepoint: point(points.delayed_payment)?,
+            htlc_basepoint: point(points.htlc)?,
+        };
+        Ok(RemoteChannelSigner {
+            remote,
+            channel_nonce,
+            pubkeys,
+            is_outbound,
+            channel_value_sat,
+            channel_parameters,
+        })
+    }
+
+    fn channel_nonce(&self) -> Option<ChannelNonce> {
+        Some(ChannelNonce { data: self.channel_nonce.to_vec() })
+    }
+
+    fn channel_parameters(&self) -> Result<&ChannelTransactionParameters, ()> {
+        self.channel_parameters.as_ref().ok_or_else(|| error!("channel is not ready"))
+    }
+
+    // The keys of a counterparty commitment
+    fn make_counterparty_tx_keys(
+        &self,
+        per_commitment_point: &PublicKey,
+        secp_ctx: &Secp256k1<All>,
+    ) -> Result<TxCreationKeys, ()> {
+        let parameters = self.channel_parameters()?;
+        let counterparty_pubkeys = &parameters.counterparty_parameters.as_ref().ok_or(())?.pubkeys;
+        TxCreationKeys::derive_new(
+            secp_ctx,
+            per_commitment_point,
+            &counterparty_pubkeys.delayed_payment_basepoint,
+            &counterparty_pubkeys.htlc_basepoint,
+            &self.pubkeys.revocation_basepoint,
+            &self.pubkeys.htlc_basepoint,
+        )
+        .map_err(|_| error!("could not derive the counterparty keys"))
+    }
+
+    // A transaction spending a channel output, as the sweep methods take it
+    fn sweep_tx(
+        tx: &Transaction,
+        amount: u64,
+        redeem_script: &Script,
+    ) -> crate::server::remotesigner::Transaction {
+        // the server reads the first descriptor, for the input being signed
+        crate::server::remotesigner::Transaction {
+            raw_tx_bytes: serialize(tx),
+            input_descs: vec![InputDescriptor {
+                value_sat: amount as i64,
+                redeem_script: redeem_script.to_bytes(),
+                ..Default::default()
+            }],
+            output_descs: vec![OutputDescriptor {
+                key_loc: Some(KeyLocator { key_path: WALLET_PATH.to_vec(), close_info: None }),
+                witscript: vec![],
+            }],
+        }
+    }
+
+    fn sign_justice_sweep(
+        &self,
+        justice_tx: &Transaction,
+        input: usize,
+        amount: u64,
+        per_commitment_key: &SecretKey,
+        redeem_script: &Script,
+    ) -> Result<Signature, Status> {
+        let request = SignJusticeSweepRequest {
+            node_id: self.remote.node_id(),
+            channel_nonce: self.channel_nonce(),
+            tx: Some(Self::sweep_tx(justice_tx, amount, redeem_script)),
+            input: input as u32,
+            revocation_secret: Some((*per_commitment_key).into()),
+        };
+        signature(call!(self.remote, sign_justice_sweep, request)?.signature)
+    }
+}
+
+impl Writeable for RemoteChannelSigner {
+    fn write<W: Writer>(&self, writer: &mut W) -> Result<(), IOError> {
+        self.channel_nonce.write(writer)?;
+        self.is_outbound.write(writer)?;
+        self.channel_value_sat.write(writer)?;
+        self.channel_parameters.write(writer)?;
+        Ok(())
+    }
+}
+
+impl Clone for RemoteChannelSigner {
+    fn clone(&self) -> Self {
+        RemoteChannelSigner {
+            remote: Arc::clone(&self.remote),
+            channel_nonce: self.channel_nonce,
+            pubkeys: self.pubkeys.clone(),
+            is_outbound: self.is_outbound,
+            channel_value_sat: self.channel_value_sat,
+            channel_parameters: self.channel_parameters.clone(),
+        }
+    }
+}
+
+impl BaseSign for RemoteChannelSigner {
+    fn get_per_commitment_point(&self, idx: u64, _secp_ctx: &Secp256k1<All>) -> PublicKey {
+        // the signer counts commitment numbers forward, and LDK backwards
+        let request = GetPerCommitmentPointRequest {
+            node_id: self.remote.node_id(),
+            channel_nonce: self.channel_nonce(),
+            n: INITIAL_COMMITMENT_NUMBER - idx,
+            point_only: true,
+        };
+        let reply = call!(self.remote, get_per_commitment_point, request)
+            .expect("get_per_commitment_point");
+        public_key(&reply.per_commitment_point.unwrap_or_default().data).expect("point")
+    }
+
+    fn release_commitment_secret(&self, idx: u64) -> [u8; 32] {
+        // the secret of a commitment is released with the point two
+        // commitments later
+        let request = GetPerCommitmentPointRequest {
+            node_id: self.remote.node_id(),
+            channel_nonce: self.channel_nonce(),
+            n: INITIAL_COMMITMENT_NUMBER - idx + 2,
+            point_only: false,
+        };
+        let reply = call!(self.remote, get_per_commitment_point, request)
+            .expect("release_commitment_secret");
+        reply.old_secret.expect("old secret").data.as_slice().try_into().expect("secret")
+    }
+
+    fn validate_holder_commitment(
+        &self,
+        holder_tx: &HolderCommitmentTransaction,
+        _preimages: Vec<PaymentPreimage>,
+    ) -> Result<(), ()> {
+        // TODO the preimages can't be passed to the server yet
+        let request = ValidateHolderCommitmentTxPhase2Request {
+            node_id: self.remote.node_id(),
+            channel_nonce: self.channel_nonce(),
+            commitment_info: Some(CommitmentInfo::from((&**holder_tx, true))),
+            commit_signature: Some(holder_tx.counterparty_sig.into()),
+            htlc_signatures: holder_tx
+                .counterparty_htlc_sigs
+                .iter()
+                .map(|sig| (*sig).into())
+                .collect(),
+            lease_id: vec![],
+        };
+        call!(self.remote, validate_holder_commitment_tx_phase2, request)
+            .map_err(|s| bad_status("validate_holder_commitment", s))?;
+        Ok(())
+    }
+
+    fn pubkeys(&self) -> &ChannelPublicKeys {
+        &self.pubkeys
+    }
+
+    fn channel_keys_id(&self) -> [u8; 32] {
+        self.channel_nonce
+    }
+
+    fn sign_counterparty_commitment(
+        &self,
+        commitment_tx: &CommitmentTransaction,
+        _preimages: Vec<PaymentPreimage>,
+        _secp_ctx: &Secp256k1<All>,
+    ) -> Result<(Signature, Vec<Signature>), ()> {
+        let request = SignCounterpartyCommitmentTxPhase2Request {
+            node_id: self.remote.node_id(),
+            channel_nonce: self.channel_nonce(),
+            commitment_info: Some(CommitmentInfo::from((commitment_tx, false))),
+            lease_id: vec![],
+        };
+        call!(self.remote, sign_counterparty_commitment_tx_phase2, request)
+            .and_then(|reply| Ok((signature(reply.signature)?, signatures(reply.htlc_signatures)?)))
+            .map_err(|s| bad_status("sign_counterparty_commitment", s))
+    }
+
+    fn validate_counterparty_revocation(&self, idx: u64, secret: &SecretKey) -> Result<(), ()> {
+        let request = ValidateCounterpartyRevocationRequest {
+            node_id: self.remote.node_id(),
+            channel_nonce: self.channel_nonce(),
+            revoke_num: INITIAL_COMMITMENT_NUMBER - idx,
+            old_secret: Some((*secret).into()),
+            lease_id: vec![],
+        };
+        call!(self.remote, validate_counterparty_revocation, request)
+            .map_err(|s| bad_status("validate_counterparty_revocation", s))?;
+        Ok(())
+    }
+
+    fn sign_holder_commitment_and_htlcs(
+        &self,
+        hct: &HolderCommitmentTransaction,
+        _secp_ctx: &Secp256k1<All>,
+    ) -> Result<(Signature, Vec<Signature>), ()> {
+        // the commitment was validated by validate_holder_commitment
+        let request = SignHolderCommitmentTxPhase2Request {
+            node_id: self.remote.node_id(),
+            channel_nonce: self.channel_nonce(),
+            commit_num: INITIAL_COMMITMENT_NUMBER - hct.commitment_number(),
+            lease_id: vec![],
+        };
+        call!(self.remote, sign_holder_commitment_tx_phase2, request)
+            .and_then(|reply| Ok((signature(reply.signature)?, signatures(reply.htlc_signatures)?)))
+            .map_err(|s| bad_status("sign_holder_commitment_and_htlcs", s))
+    }
+
+    fn sign_justice_revoked_output(
+        &self,
+        justice_tx: &Transaction,
+        input: usize,
+        amount: u64,
+        per_commitment_key: &SecretKey,
+        secp_ctx: &Secp256k1<All>,
+    ) -> Result<Signature, ()> {
+        let per_commitment_point = PublicKey::from_secret_key(secp_ctx, per_commitment_key);
+        let keys = self.make_counterparty_tx_keys(&per_commitment_point, secp_ctx)?;
+        let redeem_script = chan_utils::get_revokeable_redeemscript(
+            &keys.revocation_key,
+            self.channel_parameters()?.holder_selected_contest_delay,
+            &keys.broadcaster_delayed_payment_key,
+        );
+        self.sign_justice_sweep(justice_tx, input, amount, per_commitment_key, &redeem_script)
+            .map_err(|s| bad_status("sign_justice_revoked_output", s))
+    }
+
+    fn sign_justice_revoked_htlc(
+        &self,
+        justice_tx: &Transaction,
+        input: usize,
+        amount: u64,
+        per_commitment_key: &SecretKey,
+        htlc: &HTLCOutputInCommitment,
+        secp_ctx: &Secp256k1<All>,
+    ) -> Result<Signature, ()> {
+        let per_commitment_point = PublicKey::from_secret_key(secp_ctx, per_commitment_key);
+        let keys = self.make_counterparty_tx_keys(&per_commitment_point, secp_ctx)?;
+        let opt_anchors = self.channel_parameters()?.opt_anchors.is_some();
+        let redeem_script = chan_utils::get_htlc_redeemscript(htlc, opt_anchors, &keys);
+        self.sign_justice_sweep(justice_tx, input, amount, per_commitment_key, &redeem_script)
+            .map_err(|s| bad_status("sign_justice_revoked_htlc", s))
+    }
+
+    fn sign_counterparty_htlc_transaction(
+        &self,
+        htlc_tx: &Transaction,
+        input: usize,
+        amount: u64,
+        per_commitment_point: &PublicKey,
+        htlc: &HTLCOutputInCommitment,
+        secp_ctx: &Secp256k1<All>,
+    ) -> Result<Signature, ()> {
+        let keys = self.make_counterparty_tx_keys(per_commitment_point, secp_ctx)?;
+        let opt_anchors = self.channel_parameters()?.opt_anchors.is_some();
+        let redeem_script = chan_utils::get_htlc_redeemscript(htlc, opt_anchors, &keys);
+        let request = SignCounterpartyHtlcSweepRequest {
+            node_id: self.remote.node_id(),
+            channel_nonce: self.channel_nonce(),
+            tx: Some(Self::sweep_tx(htlc_tx, amount, &redeem_script)),
+            input: input as u32,
+            remote_per_commit_point: Some((*per_commitment_point).into()),
+        };
+        call!(self.remote, sign_counterparty_htlc_sweep, request)
+            .and_then(|reply| signature(reply.signature))
+            .map_err(|s| bad_status("sign_counterparty_htlc_transaction", s))
+    }
+
+    fn sign_closing_transaction(
+        &self,
+        closing_tx: &ClosingTransaction,
+        _secp_ctx: &Secp256k1<All>,
+    ) -> Result<Signature, ()> {
+        // the holder script is the shutdown script of the keys interface,
+        // in the wallet
+        let request = SignMutualCloseTxPhase2Request {
+            node_id: self.remote.node_id(),
+            channel_nonce: self.channel_nonce(),
+            to_holder_value_sat: closing_tx.to_holder_value_sat(),
+            to_counterparty_value_sat: closing_tx.to_counterparty_value_sat(),
+            holder_shutdown_script: closing_tx.to_holder_script().to_bytes(),
+            counterparty_shutdown_script: closing_tx.to_counterparty_script().to_bytes(),
+            holder_wallet_path_hint: WALLET_PATH.to_vec(),
+            approval_token: vec![],
+            lease_id: vec![],
+        };
+        call!(self.remote, sign_mutual_close_tx_phase2, request)
+            .and_then(|reply| signature(reply.signature))
+            .map_err(|s| bad_status("sign_closing_transaction", s))
+    }
+
+    fn sign_channel_announcement(
+        &self,
+        msg: &UnsignedChannelAnnouncement,
+        _secp_ctx: &Secp256k1<All>,
+    ) -> Result<(Signature, Signature), ()> {
+        let request = SignChannelAnnouncementRequest {
+            node_id: self.remote.node_id(),
+            channel_nonce: self.channel_nonce(),
+            channel_announcement: msg.encode(),
+        };
+        let reply = call!(self.remote, sign_channel_announcement, request)
+            .map_err(|s| bad_status("sign_channel_announcement", s))?;
+        let node_sig = reply.node_signature.ok_or(())?.try_into()?;
+        let bitcoin_sig = reply.bitcoin_signature.ok_or(())?.try_into()?;
+        Ok((node_sig, bitcoin_sig))
+    }
+
+    fn ready_channel(&mut self, parameters: &ChannelTransactionParameters) {
+        info!("ready channel {}", hex::encode(self.channel_nonce));
+        let counterparty = parameters.counterparty_parameters.as_ref().expect("counterparty");
+        let points = &counterparty.pubkeys;
+        let commitment_type = if parameters.opt_anchors.is_some() {
+            CommitmentType::Anchors
+        } else {
+            CommitmentType::StaticRemotekey
+        };
+        let request = ReadyChannelRequest {
+            node_id: self.remote.node_id(),
+            channel_nonce0: self.channel_nonce(),
+            option_channel_nonce: None,
+            is_outbound: self.is_outbound,
+            channel_value_sat: self.channel_value_sat,
+            push_value_msat: 0, // TODO
+            funding_outpoint: Some(parameters.funding_outpoint.expect("funding outpoint").into()),
+            holder_selected_contest_delay: parameters.holder_selected_contest_delay as u32,
+            holder_shutdown_script: vec![], // use the signer's shutdown script
+            holder_shutdown_key_path: vec![],
+            counterparty_basepoints: Some(Basepoints {
+                revocation: Some(points.revocation_basepoint.into()),
+                payment: Some(points.payment_point.into()),
+                htlc: Some(points.htlc_basepoint.into()),
+                delayed_payment: Some(points.delayed_payment_basepoint.into()),
+                funding_pubkey: Some(points.funding_pubkey.into()),
+            }),
+            counterparty_selected_contest_delay: counterparty.selected_contest_delay as u32,
+            counterparty_shutdown_script: vec![],
+            commitment_type: commitment_type as i32,
+            lease_id: vec![],
+        };
+        call!(self.remote, ready_channel, request).expect("ready_channel");
+        self.channel_parameters = Some(parameters.clone());
+    }
+}
+
+impl Sign for RemoteChannelSigner {}
+
+impl KeysInterface for RemoteSignerKeysInterface {
+    type Signer = RemoteChannelSigner;
+
+    // TODO secret key leaking
+    fn get_node_secret(&self, recipient: Recipient) -> Result<SecretKey, ()> {
+        match recipient {
+            Recipient::Node => Ok(self.node_secret),
+            Recipient::PhantomNode => Err(()),
+        }
+    }
+
+    fn get_destination_script(&self) -> Script {
+        Script::new_v0_wpkh(&WPubkeyHash::hash(&self.wallet_pubkey().serialize()))
+    }
+
+    fn get_shutdown_scriptpubkey(&self) -> ShutdownScript {
+        ShutdownScript::new_p2wpkh(&WPubkeyHash::hash(&self.wallet_pubkey().serialize()))
+    }
+
+    fn get_channel_signer(&self, is_inbound: bool, channel_value_sat: u64) -> Self::Signer {
+        let channel_nonce = self.get_secure_random_bytes();
+        let request = NewChannelRequest {
+            node_id: self.remote.node_id(),
+            channel_nonce0: Some(ChannelNonce { data: channel_nonce.to_vec() }),
+            peer_id: None,
+            dbid: 0,
+        };
+        call!(self.remote, new_channel, request).expect("new_channel");
+        RemoteChannelSigner::new(
+            Arc::clone(&self.remote),
+            channel_nonce,
+            !is_inbound,
+            channel_value_sat,
+            None,
+        )
+        .expect("channel signer")
+    }
+
+    fn get_secure_random_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        OsRng::new().expect("OsRng").fill_bytes(&mut bytes);
+        bytes
+    }
+
+    fn read_chan_signer(&self, mut reader: &[u8]) -> Result<Self::Signer, DecodeError> {
+        let channel_nonce = Readable::read(&mut reader)?;
+        let is_outbound = Readable::read(&mut reader)?;
+        let channel_value_sat = Readable::read(&mut reader)?;
+        let channel_parameters = Readable::read(&mut reader)?;
+        RemoteChannelSigner::new(
+            Arc::clone(&self.remote),
+            channel_nonce,
+            is_outbound,
+            channel_value_sat,
+            channel_parameters,
+        )
+        .map_err(|s| {
+            bad_status("read_chan_signer", s);
+            DecodeError::InvalidValue
+        })
+    }
+
+    fn sign_invoice(
+        &self,
+        hrp_bytes: &[u8],
+        invoice_data: &[u5],
+        recipient: Recipient,
+    ) -> Result<RecoverableSignature, ()> {
+        match recipient {
+            Recipient::Node => {}
+            Recipient::PhantomNode => return Err(()),
+        };
+        let request = SignInvoiceRequest {
+            node_id: self.remote.node_id(),
+            data_part: invoice_data.iter().map(|u| u.to_u8()).collect(),
+            human_readable_part: String::from_utf8(hrp_bytes.to_vec()).map_err(|_| ())?,
+        };
+        let reply =
+            call!(self.remote, sign_invoice, request).map_err(|s| bad_status("sign_invoice", s))?;
+        // the compact signature and the recovery id
+        let data = reply.signature.ok_or(())?.data;
+        if data.len() != 65 {
+            return Err(());
+        }
+        let recovery_id = RecoveryId::from_i32(data[64] as i32).map_err(|_| ())?;
+        RecoverableSignature::from_compact(&data[..64], recovery_id).map_err(|_| ())
+    }
+
+    fn get_inbound_payment_key_material(&self) -> KeyMaterial {
+        // stable for the node, since the server doesn't export its own
+        let mut engine = Sha256::engine();
+        engine.input(b"inbound payment key");
+        engine.input(&self.node_secret[..]);
+        KeyMaterial(Sha256::from_engine(engine).into_inner())
+    }
+}
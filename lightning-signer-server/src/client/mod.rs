@@ -1,2 +1,4 @@
 pub mod convert;
 pub mod driver;
+pub mod ldk_signer;
+pub mod shell;
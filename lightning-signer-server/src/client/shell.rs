@@ -0,0 +1,136 @@
+//! Support for the interactive `vls-cli shell` mode.
+//!
+//! The shell keeps one gRPC connection open across commands, remembers
+//! the current node, and completes command names and the node and channel
+//! IDs it last fetched from the server.
+
+use std::env;
+use std::path::PathBuf;
+
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+/// The name of the shell history file in the home directory
+pub const HISTORY_FILE_NAME: &str = ".vls-cli_history";
+
+/// Completion for the shell's line editor
+pub struct ShellHelper {
+    commands: Vec<String>,
+    ids: Vec<String>,
+}
+
+impl ShellHelper {
+    /// Create a helper that completes the given command names
+    pub fn new(commands: Vec<String>) -> Self {
+        ShellHelper { commands, ids: vec![] }
+    }
+
+    /// Replace the node and channel IDs offered for completion
+    pub fn set_ids(&mut self, node_ids: Vec<String>, channel_nonces: Vec<String>) {
+        self.ids = node_ids;
+        self.ids.extend(channel_nonces);
+    }
+
+    // The candidates for the word before pos, and where that word starts
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos].rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+        let prefix = &line[start..pos];
+        let mut candidates: Vec<String> = self
+            .commands
+            .iter()
+            .chain(self.ids.iter())
+            .filter(|w| w.starts_with(prefix))
+            .cloned()
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        (start, candidates)
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// The shell history file, if there is a home directory
+pub fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE_NAME))
+}
+
+/// The command line arguments for a shell line, starting with the program
+/// name.  The current node is passed with `--node` unless the line names
+/// a node itself.
+pub fn line_to_args(program: &str, line: &str, node_id: Option<&str>) -> Vec<String> {
+    let words: Vec<String> = line.split_whitespace().map(|w| w.to_string()).collect();
+    let mut args = vec![program.to_string()];
+    if let Some(node_id) = node_id {
+        if !words.iter().any(|w| w == "-n" || w == "--node" || w.starts_with("--node=")) {
+            args.push("--node".to_string());
+            args.push(node_id.to_string());
+        }
+    }
+    args.extend(words);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    fn helper() -> ShellHelper {
+        let mut helper =
+            ShellHelper::new(vec!["node".to_string(), "new".to_string(), "list".to_string()]);
+        helper.set_ids(vec!["02aa".to_string(), "03bb".to_string()], vec!["0102".to_string()]);
+        helper
+    }
+
+    #[test]
+    fn complete_test() {
+        let helper = helper();
+        assert_eq!(helper.candidates("n", 1), (0, vec!["new".to_string(), "node".to_string()]));
+        assert_eq!(helper.candidates("node l", 6), (5, vec!["list".to_string()]));
+        assert_eq!(
+            helper.candidates("use 0", 5),
+            (4, vec!["0102".to_string(), "02aa".to_string(), "03bb".to_string()])
+        );
+        assert_eq!(helper.candidates("channel list -n 03", 18), (16, vec!["03bb".to_string()]));
+        assert_eq!(helper.candidates("node x", 6), (5, vec![]));
+    }
+
+    #[test]
+    fn line_to_args_test() {
+        assert_eq!(line_to_args("vls-cli", " node  list ", None), vec!["vls-cli", "node", "list"]);
+        assert_eq!(
+            line_to_args("vls-cli", "channel list", Some("02aa")),
+            vec!["vls-cli", "--node", "02aa", "channel", "list"]
+        );
+        assert_eq!(
+            line_to_args("vls-cli", "channel list -n 03bb", Some("02aa")),
+            vec!["vls-cli", "channel", "list", "-n", "03bb"]
+        );
+    }
+}
@@ -1,24 +1,34 @@
 extern crate clap;
 
+use std::error::Error;
+use std::fs;
 use std::io;
 
 use clap::{App, Arg, ArgMatches};
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
 
 use bip39::Mnemonic;
-use lightning_signer_server::client::driver;
+use lightning_signer_server::client::driver::{self, Client};
+use lightning_signer_server::client::shell::{self, ShellHelper};
+use lightning_signer_server::server::remotesigner::SpendType;
 use lightning_signer_server::CLIENT_APP_NAME;
 use lightning_signer_server::NETWORK_NAMES;
 
+// The node ID from the global --node argument
+fn node_id(matches: &ArgMatches) -> Result<Vec<u8>, Box<dyn Error>> {
+    let node_id =
+        matches.value_of("node").ok_or("missing --node, or `use <node_id>` in the shell")?;
+    Ok(hex::decode(node_id)?)
+}
+
 fn make_test_subapp() -> App<'static> {
     App::new("test").about("run a test scenario").subcommand(App::new("integration"))
 }
 
-#[tokio::main]
-async fn test_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = driver::connect().await?;
-
+async fn test_subcommand(client: &mut Client, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     match matches.subcommand() {
-        Some(("integration", _)) => driver::integration_test(&mut client).await?,
+        Some(("integration", _)) => driver::integration_test(client).await?,
         Some((name, _)) => panic!("unimplemented command {}", name),
         None => {
             println!("missing sub-command");
@@ -28,12 +38,6 @@ async fn test_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error:
     Ok(())
 }
 
-#[tokio::main]
-async fn ping_subcommand() -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = driver::connect().await?;
-    driver::ping(&mut client).await
-}
-
 fn make_node_subapp() -> App<'static> {
     App::new("node")
         .about("control a node")
@@ -52,27 +56,101 @@ fn make_node_subapp() -> App<'static> {
                      .possible_values(&NETWORK_NAMES)
                      .default_value(NETWORK_NAMES[0]),
                 )
+                .arg(Arg::new("key-derivation-style")
+                     .about("how the node keys are derived from the seed, for compatibility with the node implementation")
+                     .long("key-derivation-style")
+                     .takes_value(true)
+                     .possible_values(&driver::KEY_DERIVATION_STYLE_NAMES)
+                     .default_value(driver::KEY_DERIVATION_STYLE_NAMES[0]),
+                )
+                .arg(Arg::new("bip39-passphrase-file")
+                     .about("file holding the BIP39 passphrase of the mnemonic")
+                     .long("bip39-passphrase-file")
+                     .takes_value(true),
+                )
         )
         .subcommand(App::new("list").about("List configured nodes."))
+        .subcommand(App::new("status").about("Show the signer-side state of a node"))
+        .subcommand(
+            App::new("descriptor")
+                .about("Show the output descriptors of the layer-1 wallet, for watch-only wallets"),
+        )
+        .subcommand(
+            App::new("address")
+                .about("Show a layer-1 wallet address")
+                .arg(
+                    Arg::new("index")
+                        .takes_value(true)
+                        .required(true)
+                        .validator(|v| v.parse::<u32>())
+                        .about("address index on the external chain"),
+                )
+                .arg(
+                    Arg::new("wrapped")
+                        .about("p2sh wrapped segwit instead of native segwit")
+                        .long("wrapped")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::new("taproot")
+                        .about("BIP-86 taproot instead of native segwit")
+                        .long("taproot")
+                        .takes_value(false)
+                        .conflicts_with("wrapped"),
+                ),
+        )
 }
 
-#[tokio::main]
-async fn node_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = driver::connect().await?;
+// The BIP39 passphrase is optional, and read from a file for the same
+// reason as the backup passphrase
+fn bip39_passphrase(matches: &ArgMatches) -> Result<String, Box<dyn Error>> {
+    match matches.value_of("bip39-passphrase-file") {
+        Some(path) => {
+            let passphrase = fs::read_to_string(path)?;
+            Ok(passphrase.trim_end_matches(&['\r', '\n'][..]).to_string())
+        }
+        None => Ok(String::new()),
+    }
+}
 
+async fn node_subcommand(client: &mut Client, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     match matches.subcommand() {
         Some(("new", matches)) => {
             let network_name = matches.value_of_t("network").expect("network");
+            let style = driver::key_derivation_style(
+                matches.value_of("key-derivation-style").expect("key-derivation-style"),
+            )?;
+            let bip39_passphrase = bip39_passphrase(matches)?;
             if matches.is_present("mnemonic") {
                 let mut buf = String::new();
                 io::stdin().read_line(&mut buf).expect("stdin");
                 let mnemonic = Mnemonic::parse(buf.trim())?;
-                driver::new_node_with_mnemonic(&mut client, mnemonic, network_name).await?
+                driver::new_node_with_mnemonic(
+                    client,
+                    mnemonic,
+                    network_name,
+                    style,
+                    &bip39_passphrase,
+                )
+                .await?
             } else {
-                driver::new_node(&mut client, network_name).await?
+                driver::new_node(client, network_name, style, &bip39_passphrase).await?
             }
         }
-        Some(("list", _)) => driver::list_nodes(&mut client).await?,
+        Some(("list", _)) => driver::list_nodes(client).await?,
+        Some(("status", _)) => driver::get_node_status(client, node_id(matches)?).await?,
+        Some(("descriptor", _)) => driver::get_wallet_descriptor(client, node_id(matches)?).await?,
+        Some(("address", submatches)) => {
+            let index = submatches.value_of_t("index").expect("index");
+            let spend_type = if submatches.is_present("wrapped") {
+                SpendType::P2shP2wpkh
+            } else if submatches.is_present("taproot") {
+                SpendType::P2tr
+            } else {
+                SpendType::P2wpkh
+            };
+            driver::get_address(client, node_id(matches)?, index, spend_type).await?
+        }
         Some((name, _)) => panic!("unimplemented command {}", name),
         None => {
             println!("missing sub-command");
@@ -102,24 +180,34 @@ fn make_chan_subapp() -> App<'static> {
                 ),
         )
         .subcommand(App::new("list").about("List channels in a node"))
+        .subcommand(
+            App::new("status").about("Show the signer-side state of a channel").arg(
+                Arg::new("nonce")
+                    .takes_value(true)
+                    .required(true)
+                    .validator(|v| hex::decode(v))
+                    .about("channel nonce in hex"),
+            ),
+        )
 }
 
-#[tokio::main]
-async fn chan_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = driver::connect().await?;
-    // TODO give a nice error message if node_id is missing
-    let node_id = hex::decode(matches.value_of("node").expect("missing node_id"))?;
+async fn chan_subcommand(client: &mut Client, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let node_id = node_id(matches)?;
 
     match matches.subcommand() {
         Some(("new", matches)) =>
             driver::new_channel(
-                &mut client,
+                client,
                 node_id,
                 matches.value_of("nonce"),
                 matches.is_present("no-nonce"),
             )
             .await?,
-        Some(("list", _)) => driver::list_channels(&mut client, node_id).await?,
+        Some(("list", _)) => driver::list_channels(client, node_id).await?,
+        Some(("status", matches)) => {
+            let nonce = hex::decode(matches.value_of("nonce").expect("missing nonce"))?;
+            driver::get_channel_status(client, node_id, nonce).await?
+        }
         Some((name, _)) => panic!("unimplemented command {}", name),
         None => {
             println!("missing sub-command");
@@ -152,21 +240,18 @@ fn make_allowlist_subapp() -> App<'static> {
         )
 }
 
-#[tokio::main]
-async fn alst_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = driver::connect().await?;
-    // TODO give a nice error message if node_id is missing
-    let node_id = hex::decode(matches.value_of("node").expect("missing node_id"))?;
+async fn alst_subcommand(client: &mut Client, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let node_id = node_id(matches)?;
 
     match matches.subcommand() {
-        Some(("list", _)) => driver::list_allowlist(&mut client, node_id).await?,
+        Some(("list", _)) => driver::list_allowlist(client, node_id).await?,
         Some(("add", matches)) => {
             let addrs = vec![matches.value_of("address").expect("missing address").to_string()];
-            driver::add_allowlist(&mut client, node_id, addrs).await?
+            driver::add_allowlist(client, node_id, addrs).await?
         }
         Some(("remove", matches)) => {
             let addrs = vec![matches.value_of("address").expect("missing address").to_string()];
-            driver::remove_allowlist(&mut client, node_id, addrs).await?
+            driver::remove_allowlist(client, node_id, addrs).await?
         }
         Some((name, _)) => panic!("unimplemented command {}", name),
         None => {
@@ -177,12 +262,464 @@ async fn alst_subcommand(matches: &ArgMatches) -> Result<(), Box<dyn std::error:
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let test_subapp = make_test_subapp();
-    let node_subapp = make_node_subapp();
-    let chan_subapp = make_chan_subapp();
-    let alst_subapp = make_allowlist_subapp();
-    let app = App::new(CLIENT_APP_NAME)
+fn make_fee_reserve_subapp() -> App<'static> {
+    App::new("feereserve")
+        .about("manage the wallet UTXOs reserved for fee bumping")
+        .subcommand(App::new("list").about("List the reserved UTXOs of a node"))
+        .subcommand(
+            App::new("add").about("Reserve a wallet UTXO for fee bumping").arg(
+                Arg::new("outpoint")
+                    .takes_value(true)
+                    .required(true)
+                    .about("the UTXO to reserve, as <txid>:<vout>"),
+            ),
+        )
+        .subcommand(
+            App::new("remove").about("Release a wallet UTXO from the fee reserve").arg(
+                Arg::new("outpoint")
+                    .takes_value(true)
+                    .required(true)
+                    .about("the UTXO to release, as <txid>:<vout>"),
+            ),
+        )
+}
+
+async fn fee_reserve_subcommand(
+    client: &mut Client,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    let node_id = node_id(matches)?;
+
+    match matches.subcommand() {
+        Some(("list", _)) => driver::list_fee_reserve(client, node_id).await?,
+        Some(("add", matches)) => {
+            let outpoints =
+                vec![matches.value_of("outpoint").expect("missing outpoint").to_string()];
+            driver::add_fee_reserve(client, node_id, outpoints).await?
+        }
+        Some(("remove", matches)) => {
+            let outpoints =
+                vec![matches.value_of("outpoint").expect("missing outpoint").to_string()];
+            driver::remove_fee_reserve(client, node_id, outpoints).await?
+        }
+        Some((name, _)) => panic!("unimplemented command {}", name),
+        None => {
+            println!("missing sub-command");
+            make_fee_reserve_subapp().print_help()?
+        }
+    };
+    Ok(())
+}
+
+fn make_payments_subapp() -> App<'static> {
+    App::new("payments")
+        .about("inspect the payment ledger")
+        .subcommand(App::new("list").about("List the payments of a node, with their totals"))
+}
+
+async fn payments_subcommand(
+    client: &mut Client,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    let node_id = node_id(matches)?;
+
+    match matches.subcommand() {
+        Some(("list", _)) => driver::list_payments(client, node_id).await?,
+        Some((name, _)) => panic!("unimplemented command {}", name),
+        None => {
+            println!("missing sub-command");
+            make_payments_subapp().print_help()?
+        }
+    };
+    Ok(())
+}
+
+fn make_watchtower_subapp() -> App<'static> {
+    App::new("watchtower").about("export justice data for external watchtowers").subcommand(
+        App::new("list").about("List the queued watchtower blobs of a node").arg(
+            Arg::new("since")
+                .long("since")
+                .takes_value(true)
+                .default_value("0")
+                .about("list the blobs after this sequence number"),
+        ),
+    )
+}
+
+async fn watchtower_subcommand(
+    client: &mut Client,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    let node_id = node_id(matches)?;
+
+    match matches.subcommand() {
+        Some(("list", matches)) => {
+            let since = matches.value_of_t("since")?;
+            driver::list_watchtower_blobs(client, node_id, since).await?
+        }
+        Some((name, _)) => panic!("unimplemented command {}", name),
+        None => {
+            println!("missing sub-command");
+            make_watchtower_subapp().print_help()?
+        }
+    };
+    Ok(())
+}
+
+fn make_metadata_subapp() -> App<'static> {
+    let channel_arg = Arg::new("channel")
+        .about("channel nonce in hex, otherwise the node's metadata is used")
+        .long("channel")
+        .short('c')
+        .takes_value(true)
+        .validator(|v| hex::decode(v));
+    App::new("metadata")
+        .alias("meta")
+        .about("manage operator-defined node and channel metadata")
+        .subcommand(App::new("get").about("List metadata entries").arg(channel_arg.clone()))
+        .subcommand(
+            App::new("set")
+                .about("Set a metadata entry, an empty value removes it")
+                .arg(channel_arg)
+                .arg(Arg::new("key").takes_value(true).required(true).about("metadata key"))
+                .arg(Arg::new("value").takes_value(true).required(true).about("metadata value")),
+        )
+}
+
+async fn meta_subcommand(client: &mut Client, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let node_id = node_id(matches)?;
+
+    match matches.subcommand() {
+        Some(("get", matches)) => {
+            let nonce = matches.value_of("channel").map(|v| hex::decode(v).unwrap());
+            driver::get_metadata(client, node_id, nonce).await?
+        }
+        Some(("set", matches)) => {
+            let nonce = matches.value_of("channel").map(|v| hex::decode(v).unwrap());
+            let key = matches.value_of("key").expect("missing key").to_string();
+            let value = matches.value_of("value").expect("missing value").to_string();
+            driver::set_metadata(client, node_id, nonce, vec![(key, value)]).await?
+        }
+        Some((name, _)) => panic!("unimplemented command {}", name),
+        None => {
+            println!("missing sub-command");
+            make_metadata_subapp().print_help()?
+        }
+    };
+    Ok(())
+}
+
+fn make_review_subapp() -> App<'static> {
+    App::new("review")
+        .about("manage channels due for operator review")
+        .subcommand(App::new("list").about("List channels due for review"))
+        .subcommand(
+            App::new("ack")
+                .about("Acknowledge the review of a channel")
+                .arg(
+                    Arg::new("channel")
+                        .takes_value(true)
+                        .required(true)
+                        .about("channel nonce in hex")
+                        .validator(|v| hex::decode(v)),
+                )
+                .arg(
+                    Arg::new("lease")
+                        .about("channel lease ID in hex, if the signer requires leases")
+                        .long("lease")
+                        .takes_value(true)
+                        .validator(|v| hex::decode(v)),
+                ),
+        )
+}
+
+async fn review_subcommand(
+    client: &mut Client,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    let node_id = node_id(matches)?;
+
+    match matches.subcommand() {
+        Some(("list", _)) => driver::list_channel_reviews(client, node_id).await?,
+        Some(("ack", matches)) => {
+            let nonce = hex::decode(matches.value_of("channel").expect("missing channel"))?;
+            let lease_id = matches.value_of("lease").map(|v| hex::decode(v).unwrap());
+            driver::acknowledge_channel_review(client, node_id, nonce, lease_id.unwrap_or_default())
+                .await?
+        }
+        Some((name, _)) => panic!("unimplemented command {}", name),
+        None => {
+            println!("missing sub-command");
+            make_review_subapp().print_help()?
+        }
+    };
+    Ok(())
+}
+
+fn make_token_subapp() -> App<'static> {
+    let id_arg = Arg::new("id").takes_value(true).required(true).about("token ID");
+    App::new("token")
+        .about("manage the bearer tokens of a signer that requires them")
+        .subcommand(App::new("list").about("List the tokens"))
+        .subcommand(
+            App::new("mint")
+                .about("Mint a token, which is printed only once")
+                .arg(Arg::new("label").takes_value(true).required(true).about("token label"))
+                .arg(
+                    Arg::new("scope")
+                        .long("scope")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .required(true)
+                        .possible_values(&[
+                            "read-only",
+                            "channel-ops",
+                            "node-admin",
+                            "allowlist-admin",
+                        ])
+                        .about("a granted scope, may be repeated"),
+                )
+                .arg(
+                    Arg::new("tenant")
+                        .long("tenant")
+                        .takes_value(true)
+                        .about("confine the token to the nodes of a tenant"),
+                ),
+        )
+        .subcommand(
+            App::new("rotate")
+                .about("Replace the secret of a token, which is printed only once")
+                .arg(id_arg.clone()),
+        )
+        .subcommand(App::new("revoke").about("Revoke a token").arg(id_arg))
+}
+
+async fn token_subcommand(client: &mut Client, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("list", _)) => driver::list_tokens(client).await?,
+        Some(("mint", matches)) => {
+            let label = matches.value_of("label").expect("missing label").to_string();
+            let scopes = matches.values_of("scope").expect("missing scope");
+            let scopes = scopes.map(|s| s.to_string()).collect();
+            let tenant = matches.value_of("tenant").unwrap_or_default().to_string();
+            driver::mint_token(client, label, scopes, tenant).await?
+        }
+        Some(("rotate", matches)) => {
+            let id = matches.value_of("id").expect("missing id").to_string();
+            driver::rotate_token(client, id).await?
+        }
+        Some(("revoke", matches)) => {
+            let id = matches.value_of("id").expect("missing id").to_string();
+            driver::revoke_token(client, id).await?
+        }
+        Some((name, _)) => panic!("unimplemented command {}", name),
+        None => {
+            println!("missing sub-command");
+            make_token_subapp().print_help()?
+        }
+    };
+    Ok(())
+}
+
+fn make_tenant_subapp() -> App<'static> {
+    let name_arg = Arg::new("name").takes_value(true).required(true).about("tenant name");
+    let quota_arg = |name: &'static str| {
+        Arg::new(name)
+            .long(name)
+            .takes_value(true)
+            .default_value("0")
+            .validator(|v| v.parse::<u32>())
+    };
+    App::new("tenant")
+        .about("manage the tenants of a signer hosting the nodes of several customers")
+        .subcommand(App::new("list").about("List the tenants"))
+        .subcommand(
+            App::new("set")
+                .about("Create or replace a tenant, with the nodes it owns and its quotas")
+                .arg(name_arg.clone())
+                .arg(
+                    Arg::new("node_ids")
+                        .takes_value(true)
+                        .multiple_values(true)
+                        .validator(|v| hex::decode(v))
+                        .about("the node IDs owned by the tenant, in hex"),
+                )
+                .arg(quota_arg("max-channels").about("the channel quota, 0 for unlimited"))
+                .arg(
+                    quota_arg("max-signs-per-minute")
+                        .about("the sign request quota, 0 for unlimited"),
+                ),
+        )
+        .subcommand(App::new("remove").about("Remove a tenant and its tokens").arg(name_arg))
+}
+
+async fn tenant_subcommand(
+    client: &mut Client,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("list", _)) => driver::list_tenants(client).await?,
+        Some(("set", matches)) => {
+            let name = matches.value_of("name").expect("missing name").to_string();
+            let node_ids = matches
+                .values_of("node_ids")
+                .map(|ids| ids.map(hex::decode).collect::<Result<Vec<_>, _>>())
+                .transpose()?
+                .unwrap_or_default();
+            let max_channels = matches.value_of_t("max-channels")?;
+            let max_signs_per_minute = matches.value_of_t("max-signs-per-minute")?;
+            driver::set_tenant(client, name, node_ids, max_channels, max_signs_per_minute).await?
+        }
+        Some(("remove", matches)) => {
+            let name = matches.value_of("name").expect("missing name").to_string();
+            driver::remove_tenant(client, name).await?
+        }
+        Some((name, _)) => panic!("unimplemented command {}", name),
+        None => {
+            println!("missing sub-command");
+            make_tenant_subapp().print_help()?
+        }
+    };
+    Ok(())
+}
+
+fn make_approval_subapp() -> App<'static> {
+    let id_arg = Arg::new("id").takes_value(true).required(true).about("request ID");
+    App::new("approval")
+        .about("manage sign requests parked for operator approval")
+        .subcommand(App::new("list").about("List parked requests"))
+        .subcommand(
+            App::new("approve")
+                .about("Approve a request, which is signed when it is retried")
+                .arg(id_arg.clone()),
+        )
+        .subcommand(App::new("reject").about("Reject a request").arg(id_arg))
+}
+
+async fn approval_subcommand(
+    client: &mut Client,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("list", _)) => driver::list_approvals(client).await?,
+        Some(("approve", matches)) => {
+            let id = matches.value_of("id").expect("missing id").to_string();
+            driver::decide_approval(client, id, true).await?
+        }
+        Some(("reject", matches)) => {
+            let id = matches.value_of("id").expect("missing id").to_string();
+            driver::decide_approval(client, id, false).await?
+        }
+        Some((name, _)) => panic!("unimplemented command {}", name),
+        None => {
+            println!("missing sub-command");
+            make_approval_subapp().print_help()?
+        }
+    };
+    Ok(())
+}
+
+fn make_backup_subapp() -> App<'static> {
+    let file_arg = Arg::new("file").takes_value(true).required(true).about("archive file");
+    let passphrase_arg = Arg::new("passphrase-file")
+        .about("file holding the passphrase the archive is encrypted with")
+        .long("passphrase-file")
+        .takes_value(true)
+        .required(true);
+    App::new("backup")
+        .about("back up or restore all nodes of the signer")
+        .subcommand(
+            App::new("create")
+                .about("Write an encrypted archive of all nodes, channels, allowlists and trackers")
+                .arg(file_arg.clone())
+                .arg(passphrase_arg.clone()),
+        )
+        .subcommand(
+            App::new("restore")
+                .about("Import an archive, for nodes that don't exist in the signer yet")
+                .arg(file_arg)
+                .arg(passphrase_arg),
+        )
+}
+
+// The passphrase is read from a file, so that it doesn't show in the
+// process list or the shell history
+fn passphrase(matches: &ArgMatches) -> Result<String, Box<dyn Error>> {
+    let path = matches.value_of("passphrase-file").expect("missing passphrase-file");
+    let passphrase = std::fs::read_to_string(path)?;
+    let passphrase = passphrase.trim_end_matches(&['\r', '\n'][..]);
+    if passphrase.is_empty() {
+        return Err(format!("empty passphrase in {}", path).into());
+    }
+    Ok(passphrase.to_string())
+}
+
+async fn backup_subcommand(
+    client: &mut Client,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("create", matches)) => {
+            let file = matches.value_of("file").expect("missing file");
+            driver::create_backup(client, passphrase(matches)?, file).await?
+        }
+        Some(("restore", matches)) => {
+            let file = matches.value_of("file").expect("missing file");
+            driver::restore_backup(client, passphrase(matches)?, file).await?
+        }
+        Some((name, _)) => panic!("unimplemented command {}", name),
+        None => {
+            println!("missing sub-command");
+            make_backup_subapp().print_help()?
+        }
+    };
+    Ok(())
+}
+
+fn make_replication_subapp() -> App<'static> {
+    App::new("replication")
+        .about("manage a primary and standby pair of signers sharing a store")
+        .subcommand(App::new("status").about("Show the role and fencing token of the signer"))
+        .subcommand(
+            App::new("promote").about("Promote a standby to primary, fencing off the old primary"),
+        )
+}
+
+async fn replication_subcommand(
+    client: &mut Client,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("status", _)) => driver::get_replication_status(client).await?,
+        Some(("promote", _)) => driver::promote(client).await?,
+        Some((name, _)) => panic!("unimplemented command {}", name),
+        None => {
+            println!("missing sub-command");
+            make_replication_subapp().print_help()?
+        }
+    };
+    Ok(())
+}
+
+fn make_stats_subapp() -> App<'static> {
+    App::new("stats").about("show the hourly metric snapshots of the signer").arg(
+        Arg::new("limit")
+            .about("only the latest snapshots, all if 0")
+            .long("limit")
+            .takes_value(true)
+            .default_value("24"),
+    )
+}
+
+fn make_shell_subapp() -> App<'static> {
+    App::new("shell").about(
+        "Run commands interactively over a single connection.  \
+         `use <node_id>` sets the current node, `exit` leaves the shell.",
+    )
+}
+
+fn make_app() -> App<'static> {
+    App::new(CLIENT_APP_NAME)
         .about("a CLI utility which communicates with a running Validating Lightning Signer server via gRPC")
         .arg(
             Arg::new("node")
@@ -192,21 +729,226 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .global(true)
                 .validator(|v| hex::decode(v)),
         )
-        .subcommand(test_subapp)
-        .subcommand(node_subapp)
-        .subcommand(chan_subapp)
-        .subcommand(alst_subapp)
-        .subcommand(App::new("ping"));
-    let matches = app.clone().get_matches();
+        .arg(
+            Arg::new("server")
+                .about("the URL of the signer, use https with the TLS arguments")
+                .long("server")
+                .takes_value(true)
+                .default_value(driver::DEFAULT_SERVER),
+        )
+        .arg(
+            Arg::new("tls-ca")
+                .about("the PEM CA certificate that the server certificate is signed by")
+                .long("tls-ca")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires_all(&["tls-cert", "tls-key"]),
+        )
+        .arg(
+            Arg::new("tls-cert")
+                .about("the PEM client certificate")
+                .long("tls-cert")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("tls-ca"),
+        )
+        .arg(
+            Arg::new("tls-key")
+                .about("the PEM private key of the client certificate")
+                .long("tls-key")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("tls-ca"),
+        )
+        .arg(
+            Arg::new("tls-domain")
+                .about("the name in the server certificate, if it isn't the server host")
+                .long("tls-domain")
+                .takes_value(true)
+                .requires("tls-ca"),
+        )
+        .arg(
+            Arg::new("token-file")
+                .about("a file with the bearer token, for a server that requires tokens")
+                .long("token-file")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .subcommand(make_test_subapp())
+        .subcommand(make_node_subapp())
+        .subcommand(make_chan_subapp())
+        .subcommand(make_allowlist_subapp())
+        .subcommand(make_fee_reserve_subapp())
+        .subcommand(make_payments_subapp())
+        .subcommand(make_watchtower_subapp())
+        .subcommand(make_metadata_subapp())
+        .subcommand(make_review_subapp())
+        .subcommand(make_approval_subapp())
+        .subcommand(make_token_subapp())
+        .subcommand(make_tenant_subapp())
+        .subcommand(make_backup_subapp())
+        .subcommand(make_replication_subapp())
+        .subcommand(make_stats_subapp())
+        .subcommand(make_shell_subapp())
+        .subcommand(App::new("ping"))
+        .subcommand(
+            App::new("shutdown")
+                .about("flush the signer state and stop the server, after the requests in flight"),
+        )
+}
 
+async fn run_command(client: &mut Client, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     match matches.subcommand() {
-        Some(("test", submatches)) => test_subcommand(submatches)?,
-        Some(("ping", _)) => ping_subcommand()?,
-        Some(("node", submatches)) => node_subcommand(submatches)?,
-        Some(("channel", submatches)) => chan_subcommand(submatches)?,
-        Some(("allowlist", submatches)) => alst_subcommand(submatches)?,
+        Some(("test", submatches)) => test_subcommand(client, submatches).await?,
+        Some(("ping", _)) => driver::ping(client).await?,
+        Some(("shutdown", _)) => driver::shutdown(client).await?,
+        Some(("node", submatches)) => node_subcommand(client, submatches).await?,
+        Some(("channel", submatches)) => chan_subcommand(client, submatches).await?,
+        Some(("allowlist", submatches)) => alst_subcommand(client, submatches).await?,
+        Some(("feereserve", submatches)) => fee_reserve_subcommand(client, submatches).await?,
+        Some(("payments", submatches)) => payments_subcommand(client, submatches).await?,
+        Some(("watchtower", submatches)) => watchtower_subcommand(client, submatches).await?,
+        Some(("metadata", submatches)) => meta_subcommand(client, submatches).await?,
+        Some(("review", submatches)) => review_subcommand(client, submatches).await?,
+        Some(("approval", submatches)) => approval_subcommand(client, submatches).await?,
+        Some(("token", submatches)) => token_subcommand(client, submatches).await?,
+        Some(("tenant", submatches)) => tenant_subcommand(client, submatches).await?,
+        Some(("backup", submatches)) => backup_subcommand(client, submatches).await?,
+        Some(("replication", submatches)) => replication_subcommand(client, submatches).await?,
+        Some(("stats", submatches)) => {
+            driver::get_stats(client, submatches.value_of_t("limit")?).await?
+        }
+        Some(("shell", _)) => return Err("already in the shell".into()),
         Some((name, _)) => panic!("unimplemented command {}", name),
         None => panic!("unmatched command?!"),
     };
     Ok(())
 }
+
+// All subcommand names, for completion in the shell
+fn command_names(app: &App, names: &mut Vec<String>) {
+    for sub in app.get_subcommands() {
+        names.push(sub.get_name().to_string());
+        command_names(sub, names);
+    }
+}
+
+// Fetch the IDs offered for completion.  Failures only affect completion,
+// so they are ignored.
+async fn refresh_ids(client: &mut Client, helper: &mut ShellHelper, node_id: Option<&str>) {
+    let node_ids = driver::get_node_ids(client).await.unwrap_or_default();
+    let channel_nonces = match node_id.map(hex::decode) {
+        Some(Ok(node_id)) => driver::get_channel_nonces(client, node_id).await.unwrap_or_default(),
+        _ => vec![],
+    };
+    helper.set_ids(node_ids, channel_nonces);
+}
+
+async fn shell_subcommand(
+    client: &mut Client,
+    app: &mut App<'static>,
+) -> Result<(), Box<dyn Error>> {
+    let mut commands = vec!["use".to_string(), "exit".to_string()];
+    command_names(app, &mut commands);
+    let mut editor = Editor::<ShellHelper>::new();
+    editor.set_helper(Some(ShellHelper::new(commands)));
+    let history = shell::history_path();
+    if let Some(path) = &history {
+        // there is no history the first time
+        let _ = editor.load_history(path);
+    }
+
+    let mut node_id: Option<String> = None;
+    loop {
+        if let Some(helper) = editor.helper_mut() {
+            refresh_ids(client, helper, node_id.as_deref()).await;
+        }
+        let prompt = match &node_id {
+            Some(id) => format!("{} {}> ", CLIENT_APP_NAME, &id[..id.len().min(8)]),
+            None => format!("{}> ", CLIENT_APP_NAME),
+        };
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str());
+
+        match words[0] {
+            "exit" | "quit" => break,
+            "use" => {
+                match words.get(1) {
+                    Some(id) if hex::decode(id).is_ok() => node_id = Some(id.to_string()),
+                    Some(id) => eprintln!("invalid node ID {}", id),
+                    None => node_id = None,
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let args = shell::line_to_args(CLIENT_APP_NAME, &line, node_id.as_deref());
+        match app.try_get_matches_from_mut(args) {
+            Ok(matches) => {
+                if let Err(err) = run_command(client, &matches).await {
+                    eprintln!("error: {}", err);
+                    print_policy_violation(err.as_ref());
+                }
+            }
+            // includes help requests
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+// Show the details of a policy violation returned by the server
+fn print_policy_violation(err: &(dyn Error + 'static)) {
+    if let Some(desc) = err.downcast_ref::<tonic::Status>().and_then(driver::policy_violation) {
+        eprintln!("{}", desc);
+    }
+}
+
+// The client certificate and the server CA, if TLS is configured
+fn client_tls(matches: &ArgMatches) -> Result<Option<driver::ClientTls>, Box<dyn Error>> {
+    let ca_path = match matches.value_of("tls-ca") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    Ok(Some(driver::ClientTls {
+        ca: fs::read(ca_path)?,
+        cert: fs::read(matches.value_of("tls-cert").expect("tls-cert"))?,
+        key: fs::read(matches.value_of("tls-key").expect("tls-key"))?,
+        domain: matches.value_of("tls-domain").map(|s| s.to_string()),
+    }))
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let mut app = make_app();
+    let matches = app.clone().get_matches();
+    let token = match matches.value_of("token-file") {
+        Some(path) => Some(fs::read_to_string(path)?.trim().to_string()),
+        None => None,
+    };
+    let mut client =
+        driver::connect(matches.value_of("server").unwrap(), client_tls(&matches)?, token).await?;
+
+    let res = match matches.subcommand() {
+        Some(("shell", _)) => shell_subcommand(&mut client, &mut app).await,
+        _ => run_command(&mut client, &matches).await,
+    };
+    if let Err(err) = &res {
+        print_policy_violation(err.as_ref());
+    }
+    res
+}
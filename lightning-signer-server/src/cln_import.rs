@@ -0,0 +1,362 @@
+//! Import a node and its channels from a c-lightning (CLN) installation.
+//!
+//! The node seed is read from CLN's `hsm_secret` file, and the channels are
+//! read from its sqlite database, `lightningd.sqlite3`.  The keys derived by
+//! the signer are then identical to those CLN's own `hsmd` uses.
+//!
+//! The CLN node must be stopped, and its channels should be quiescent - with
+//! no HTLCs in flight and no pending commitment updates.  Only the commitment
+//! numbers and the counterparty's current point are imported, not the balance
+//! history, so the balance policies apply starting at the next commitments.
+
+use std::convert::TryInto;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+use lightning_signer::bitcoin::hashes::Hash;
+use lightning_signer::bitcoin::secp256k1::PublicKey;
+use lightning_signer::bitcoin::{Network, OutPoint, Script, Txid};
+use lightning_signer::channel::{channel_nonce_to_id, ChannelSetup, CommitmentType};
+use lightning_signer::lightning::ln::chan_utils::ChannelPublicKeys;
+use lightning_signer::node::NodeConfig;
+use lightning_signer::signer::multi_signer::MultiSigner;
+use lightning_signer::signer::my_keys_manager::KeyDerivationStyle;
+use lightning_signer::util::status::Status;
+use log::info;
+use rusqlite::{Connection, OpenFlags, Row};
+
+/// The length of a plaintext `hsm_secret`
+const HSM_SECRET_LEN: usize = 32;
+/// The length of an `hsm_secret` encrypted by `lightningd --encrypted-hsm`
+const ENCRYPTED_HSM_SECRET_LEN: usize = 73;
+
+/// CLN channel states that are imported - CHANNELD_AWAITING_LOCKIN,
+/// CHANNELD_NORMAL and CHANNELD_SHUTTING_DOWN
+const MIN_IMPORT_STATE: i64 = 2;
+const MAX_IMPORT_STATE: i64 = 4;
+
+/// An import error
+#[derive(Debug)]
+pub enum ImportError {
+    /// Could not read the hsm_secret
+    Io(std::io::Error),
+    /// The hsm_secret is malformed or encrypted
+    HsmSecret(String),
+    /// Could not read the database
+    Database(rusqlite::Error),
+    /// A database row has an unexpected value
+    BadRow(String),
+    /// The signer refused the node or a channel
+    Signer(Status),
+}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for ImportError {
+    fn from(e: rusqlite::Error) -> Self {
+        ImportError::Database(e)
+    }
+}
+
+impl From<Status> for ImportError {
+    fn from(s: Status) -> Self {
+        ImportError::Signer(s)
+    }
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(format!("{:?}", self).as_str())
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Read the node seed from a CLN `hsm_secret` file
+pub fn read_hsm_secret<P: AsRef<Path>>(path: P) -> Result<[u8; 32], ImportError> {
+    let data = fs::read(path)?;
+    match data.len() {
+        HSM_SECRET_LEN => Ok(data.as_slice().try_into().unwrap()),
+        ENCRYPTED_HSM_SECRET_LEN => Err(ImportError::HsmSecret(
+            "hsm_secret is encrypted, decrypt it first with `lightning-hsmtool decrypt`"
+                .to_string(),
+        )),
+        len => Err(ImportError::HsmSecret(format!("unexpected hsm_secret length {}", len))),
+    }
+}
+
+/// A channel read from the CLN database
+#[derive(Debug)]
+pub struct ClnChannel {
+    /// The counterparty node
+    pub peer_id: PublicKey,
+    /// The CLN database id of the channel, used to derive the channel keys
+    pub dbid: u64,
+    /// The channel parameters
+    pub setup: ChannelSetup,
+    /// The number of the next holder commitment
+    pub next_index_local: u64,
+    /// The number of the next counterparty commitment
+    pub next_index_remote: u64,
+    /// The counterparty's per-commitment point for their current commitment
+    pub remote_per_commit: Option<PublicKey>,
+}
+
+impl ClnChannel {
+    /// The channel nonce CLN's hsmd derives the channel keys from
+    pub fn channel_nonce(&self) -> Vec<u8> {
+        channel_nonce(&self.peer_id, self.dbid)
+    }
+}
+
+/// The channel nonce for a CLN channel - the peer id followed by the
+/// little-endian database id
+pub fn channel_nonce(peer_id: &PublicKey, dbid: u64) -> Vec<u8> {
+    let mut nonce = peer_id.serialize().to_vec();
+    nonce.extend_from_slice(&dbid.to_le_bytes());
+    nonce
+}
+
+const CHANNELS_QUERY: &str = "SELECT p.node_id, c.id, c.funder, c.funding_tx_id, \
+    c.funding_tx_outnum, c.funding_satoshi, c.push_msatoshi, lc.to_self_delay, \
+    rc.to_self_delay, c.fundingkey_remote, c.revocation_basepoint_remote, \
+    c.payment_basepoint_remote, c.htlc_basepoint_remote, \
+    c.delayed_payment_basepoint_remote, c.old_per_commit_remote, \
+    c.shutdown_scriptpubkey_remote, c.option_static_remotekey, \
+    c.option_anchor_outputs, c.next_index_local, c.next_index_remote \
+    FROM channels c \
+    JOIN peers p ON c.peer_id = p.id \
+    JOIN channel_configs lc ON c.channel_config_local = lc.id \
+    JOIN channel_configs rc ON c.channel_config_remote = rc.id \
+    WHERE c.state BETWEEN ?1 AND ?2 \
+    ORDER BY c.id";
+
+fn get_pubkey(row: &Row, idx: usize) -> Result<PublicKey, ImportError> {
+    let data: Vec<u8> = row.get(idx)?;
+    PublicKey::from_slice(&data)
+        .map_err(|_| ImportError::BadRow(format!("bad public key in column {}", idx)))
+}
+
+fn get_u64(row: &Row, idx: usize) -> Result<u64, ImportError> {
+    let val: i64 = row.get(idx)?;
+    val.try_into().map_err(|_| ImportError::BadRow(format!("negative value in column {}", idx)))
+}
+
+fn get_u16(row: &Row, idx: usize) -> Result<u16, ImportError> {
+    let val: i64 = row.get(idx)?;
+    val.try_into().map_err(|_| ImportError::BadRow(format!("value out of range in column {}", idx)))
+}
+
+fn row_to_channel(row: &Row) -> Result<ClnChannel, ImportError> {
+    let peer_id = get_pubkey(row, 0)?;
+    let dbid = get_u64(row, 1)?;
+    // CLN's `enum side` - LOCAL is zero
+    let is_outbound = get_u64(row, 2)? == 0;
+    let txid_data: Vec<u8> = row.get(3)?;
+    // Stored in internal byte order
+    let txid = Txid::from_slice(&txid_data)
+        .map_err(|_| ImportError::BadRow("bad funding_tx_id".to_string()))?;
+    let vout: u32 = get_u64(row, 4)?
+        .try_into()
+        .map_err(|_| ImportError::BadRow("bad funding_tx_outnum".to_string()))?;
+    let counterparty_points = ChannelPublicKeys {
+        funding_pubkey: get_pubkey(row, 9)?,
+        revocation_basepoint: get_pubkey(row, 10)?,
+        payment_point: get_pubkey(row, 11)?,
+        htlc_basepoint: get_pubkey(row, 12)?,
+        delayed_payment_basepoint: get_pubkey(row, 13)?,
+    };
+    let old_per_commit_remote: Option<Vec<u8>> = row.get(14)?;
+    let remote_per_commit = match old_per_commit_remote {
+        Some(_) => Some(get_pubkey(row, 14)?),
+        None => None,
+    };
+    let shutdown_script: Option<Vec<u8>> = row.get(15)?;
+    let counterparty_shutdown_script = shutdown_script.filter(|s| !s.is_empty()).map(Script::from);
+    let option_static_remotekey = get_u64(row, 16)? != 0;
+    let option_anchor_outputs = get_u64(row, 17)? != 0;
+    let commitment_type = if option_anchor_outputs {
+        CommitmentType::Anchors
+    } else if option_static_remotekey {
+        CommitmentType::StaticRemoteKey
+    } else {
+        CommitmentType::Legacy
+    };
+    let setup = ChannelSetup {
+        is_outbound,
+        channel_value_sat: get_u64(row, 5)?,
+        push_value_msat: get_u64(row, 6)?,
+        funding_outpoint: OutPoint { txid, vout },
+        // our config's to_self_delay is imposed on the counterparty
+        holder_selected_contest_delay: get_u16(row, 7)?,
+        holder_shutdown_script: None,
+        counterparty_points,
+        counterparty_selected_contest_delay: get_u16(row, 8)?,
+        counterparty_shutdown_script,
+        commitment_type,
+    };
+    Ok(ClnChannel {
+        peer_id,
+        dbid,
+        setup,
+        next_index_local: get_u64(row, 18)?,
+        next_index_remote: get_u64(row, 19)?,
+        remote_per_commit,
+    })
+}
+
+/// Read the open channels from a CLN database
+pub fn read_channels<P: AsRef<Path>>(db_path: P) -> Result<Vec<ClnChannel>, ImportError> {
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(CHANNELS_QUERY)?;
+    let mut rows = stmt.query([MIN_IMPORT_STATE, MAX_IMPORT_STATE])?;
+    let mut channels = Vec::new();
+    while let Some(row) = rows.next()? {
+        channels.push(row_to_channel(row)?);
+    }
+    Ok(channels)
+}
+
+/// Create the node in the signer and import the channels.
+///
+/// Returns the node id and the number of channels imported.
+pub fn import(
+    signer: &MultiSigner,
+    network: Network,
+    seed: &[u8; 32],
+    channels: Vec<ClnChannel>,
+) -> Result<(PublicKey, usize), ImportError> {
+    let node_config = NodeConfig {
+        network,
+        key_derivation_style: KeyDerivationStyle::Native,
+        chain_params: None,
+        checkpoint: None,
+    };
+    let node_id = signer.new_node_from_seed(node_config, seed)?;
+    let node = signer.get_node(&node_id)?;
+    let count = channels.len();
+    for chan in channels {
+        let nonce = chan.channel_nonce();
+        let channel_id = channel_nonce_to_id(&nonce);
+        info!("importing channel {} with {} dbid {}", channel_id, chan.peer_id, chan.dbid);
+        node.new_channel(Some(channel_id), Some(nonce), &node)?;
+        node.ready_channel(channel_id, None, chan.setup, &vec![])?;
+        node.with_ready_channel(&channel_id, |channel| {
+            channel.import_commitment_state(
+                chan.next_index_local,
+                chan.next_index_remote,
+                chan.remote_per_commit,
+            )
+        })?;
+    }
+    Ok((node_id, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lightning_signer::bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use std::io::Write;
+    use tempfile::{tempdir, NamedTempFile};
+
+    fn make_pubkey(i: u8) -> Vec<u8> {
+        let secp = Secp256k1::signing_only();
+        let secret = SecretKey::from_slice(&[i; 32]).unwrap();
+        PublicKey::from_secret_key(&secp, &secret).serialize().to_vec()
+    }
+
+    #[test]
+    fn read_hsm_secret_test() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&[3u8; 32]).unwrap();
+        assert_eq!(read_hsm_secret(file.path()).unwrap(), [3u8; 32]);
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&[3u8; ENCRYPTED_HSM_SECRET_LEN]).unwrap();
+        match read_hsm_secret(file.path()) {
+            Err(ImportError::HsmSecret(msg)) => assert!(msg.contains("encrypted")),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    fn make_db(path: &Path) {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE peers (id INTEGER PRIMARY KEY, node_id BLOB);
+             CREATE TABLE channel_configs (id INTEGER PRIMARY KEY, to_self_delay INTEGER);
+             CREATE TABLE channels (id INTEGER PRIMARY KEY, peer_id INTEGER, state INTEGER,
+                funder INTEGER, funding_tx_id BLOB, funding_tx_outnum INTEGER,
+                funding_satoshi INTEGER, push_msatoshi INTEGER,
+                channel_config_local INTEGER, channel_config_remote INTEGER,
+                fundingkey_remote BLOB, revocation_basepoint_remote BLOB,
+                payment_basepoint_remote BLOB, htlc_basepoint_remote BLOB,
+                delayed_payment_basepoint_remote BLOB, old_per_commit_remote BLOB,
+                shutdown_scriptpubkey_remote BLOB, option_static_remotekey INTEGER,
+                option_anchor_outputs INTEGER, next_index_local INTEGER,
+                next_index_remote INTEGER);
+             INSERT INTO channel_configs VALUES (1, 144), (2, 288);",
+        )
+        .unwrap();
+        conn.execute("INSERT INTO peers VALUES (1, ?1)", [make_pubkey(1)]).unwrap();
+        for (dbid, state) in [(5, 3), (6, 7)] {
+            conn.execute(
+                "INSERT INTO channels VALUES (?1, 1, ?2, 0, ?3, 1, 1000000, 0, 1, 2, \
+                 ?4, ?5, ?6, ?7, ?8, ?9, NULL, 1, 0, 12, 11)",
+                rusqlite::params![
+                    dbid,
+                    state,
+                    vec![9u8; 32],
+                    make_pubkey(2),
+                    make_pubkey(3),
+                    make_pubkey(4),
+                    make_pubkey(5),
+                    make_pubkey(6),
+                    make_pubkey(7),
+                ],
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn import_test() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("lightningd.sqlite3");
+        make_db(&db_path);
+
+        let channels = read_channels(&db_path).unwrap();
+        // the closed channel is skipped
+        assert_eq!(channels.len(), 1);
+        let chan = &channels[0];
+        assert_eq!(chan.dbid, 5);
+        assert!(chan.setup.is_outbound);
+        assert_eq!(chan.setup.holder_selected_contest_delay, 144);
+        assert_eq!(chan.setup.counterparty_selected_contest_delay, 288);
+        assert_eq!(chan.setup.commitment_type, CommitmentType::StaticRemoteKey);
+        assert_eq!(chan.setup.funding_outpoint.vout, 1);
+        let nonce = chan.channel_nonce();
+        assert_eq!(nonce.len(), 33 + 8);
+        assert_eq!(nonce[33..], 5u64.to_le_bytes());
+
+        let signer = MultiSigner::new();
+        let (node_id, count) = import(&signer, Network::Testnet, &[3u8; 32], channels).unwrap();
+        assert_eq!(count, 1);
+        let node = signer.get_node(&node_id).unwrap();
+        let channel_id = channel_nonce_to_id(&nonce);
+        node.with_ready_channel(&channel_id, |chan| {
+            let estate = &chan.enforcement_state;
+            assert_eq!(estate.next_holder_commit_num, 12);
+            assert_eq!(estate.next_holder_revoke_num, 11);
+            assert_eq!(estate.next_counterparty_commit_num, 11);
+            assert_eq!(estate.next_counterparty_revoke_num, 10);
+            assert!(estate.current_counterparty_point.is_some());
+            Ok(())
+        })
+        .unwrap();
+    }
+}
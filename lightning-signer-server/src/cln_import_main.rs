@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::Clap;
+use lightning_signer::bitcoin::Network;
+use lightning_signer::policy::simple_validator::SimpleValidatorFactory;
+use lightning_signer::signer::multi_signer::MultiSigner;
+use lightning_signer_server::cln_import;
+use lightning_signer_server::persist::persist_json::KVJsonPersister;
+
+/// Import a node and its open channels from a stopped c-lightning node
+#[derive(Clap)]
+#[clap(version = "0.1")]
+struct Opts {
+    #[clap(short, long, default_value = "testnet")]
+    network: Network,
+    #[clap(long, default_value = ".lightning-signer", about = "signer data directory")]
+    datadir: String,
+    #[clap(long, about = "path to the CLN hsm_secret file")]
+    hsm_secret: String,
+    #[clap(long, about = "path to the CLN lightningd.sqlite3 database")]
+    db: Option<String>,
+}
+
+pub fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let opts: Opts = Opts::parse();
+    let seed = cln_import::read_hsm_secret(&opts.hsm_secret)?;
+    let channels = match &opts.db {
+        Some(db) => cln_import::read_channels(db)?,
+        None => vec![],
+    };
+
+    let data_path: PathBuf = [&opts.datadir, &opts.network.to_string()].iter().collect();
+    let persister = Arc::new(KVJsonPersister::new(data_path)?);
+    let validator_factory = Arc::new(SimpleValidatorFactory::new());
+    let signer = MultiSigner::new_with_persister(persister, false, vec![], validator_factory);
+    let (node_id, count) = cln_import::import(&signer, opts.network, &seed, channels)?;
+    println!("imported node {} with {} channels", node_id, count);
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use std::fs;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
 use time::OffsetDateTime;
@@ -11,26 +12,26 @@ use log::{LevelFilter, Log, Metadata, Record};
 pub struct FilesystemLogger {
     disk_log_level: LevelFilter,
     console_log_level: LevelFilter,
-    logs_file_path: String,
+    logs_file_path: PathBuf,
     file: Mutex<fs::File>,
 }
 
 impl FilesystemLogger {
     /// Create a new logger
-    pub fn new(
-        data_dir: String,
+    pub fn new<P: AsRef<Path>>(
+        data_dir: P,
         disk_log_level: LevelFilter,
         console_log_level: LevelFilter,
     ) -> Self {
-        let logs_path = format!("{}/logs", data_dir);
-        fs::create_dir_all(logs_path.clone()).expect("Cannot create logs directory");
-        let logs_file_path = format!("{}/logs.txt", logs_path.clone());
+        let logs_path = data_dir.as_ref().join("logs");
+        fs::create_dir_all(&logs_path).expect("Cannot create logs directory");
+        let logs_file_path = logs_path.join("logs.txt");
         let file = Mutex::new(
             fs::OpenOptions::new()
                 .create(true)
                 .append(true)
                 .open(&logs_file_path)
-                .expect(&format!("Failed to open file: {}", &logs_file_path)),
+                .expect(&format!("Failed to open file: {}", logs_file_path.display())),
         );
         Self { disk_log_level, console_log_level, logs_file_path, file }
     }
@@ -59,7 +60,7 @@ impl Log for FilesystemLogger {
                     .lock()
                     .unwrap()
                     .write_all(log.as_bytes())
-                    .expect(&format!("Failed to write to file: {}", &self.logs_file_path));
+                    .expect(&format!("Failed to write to file: {}", self.logs_file_path.display()));
             }
             if record.level() <= self.console_log_level {
                 print!("{}", &log);
@@ -0,0 +1,782 @@
+//! Translate hsmd requests to [Node] and [Channel] calls.
+//!
+//! lightningd starts the signer with a connection on file descriptor 3,
+//! the master connection, and asks for a new connection for each
+//! subdaemon with [Message::ClientHsmfd].  A connection is limited to the
+//! requests allowed by its capabilities, and a connection for a channel
+//! only signs for that channel.  Channel keys are derived from the peer
+//! and the CLN database ID of the channel, as CLN's own hsmd does, so a
+//! node imported with [crate::cln_import] keeps its channels.
+
+use std::convert::TryInto;
+use std::io::{self, ErrorKind};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::thread;
+
+use lightning_signer::bitcoin::bech32::CheckBase32;
+use lightning_signer::bitcoin::blockdata::constants::genesis_block;
+use lightning_signer::bitcoin::secp256k1::recovery::{RecoverableSignature, RecoveryId};
+use lightning_signer::bitcoin::secp256k1::{self, PublicKey, Secp256k1, SecretKey, Signature};
+use lightning_signer::bitcoin::util::bip143::SigHashCache;
+use lightning_signer::bitcoin::{BlockHash, Network, OutPoint, Script, SigHashType, Transaction};
+use lightning_signer::channel::{
+    channel_nonce_from_peer_dbid, channel_nonce_to_id, Channel, ChannelBase, ChannelId,
+    ChannelSetup, CommitmentType,
+};
+use lightning_signer::lightning::ln::chan_utils::{make_funding_redeemscript, ChannelPublicKeys};
+use lightning_signer::lightning::ln::PaymentHash;
+use lightning_signer::node::{Node, NodeConfig};
+use lightning_signer::signer::multi_signer::MultiSigner;
+use lightning_signer::signer::my_keys_manager::KeyDerivationStyle;
+use lightning_signer::tx::tx::HTLCInfo2;
+use lightning_signer::util::status::{invalid_argument, Status};
+use log::{info, warn};
+use nix::sys::socket::{sendmsg, ControlMessage, MsgFlags};
+use nix::sys::uio::IoVec;
+
+use super::wire::{self, Basepoints, BitcoinSignature, Message, SimpleHtlc};
+
+/// May ask for ECDH with the node key
+pub const CAP_ECDH: u64 = 1;
+/// May ask for gossip signatures
+pub const CAP_SIGN_GOSSIP: u64 = 2;
+/// May ask for on-chain signatures, which are not served yet
+pub const CAP_SIGN_ONCHAIN_TX: u64 = 4;
+/// May ask for per-commitment points and check secrets
+pub const CAP_COMMITMENT_POINT: u64 = 8;
+/// May set up a channel and sign and validate commitments
+pub const CAP_SIGN_REMOTE_TX: u64 = 16;
+/// May sign the mutual close
+pub const CAP_SIGN_CLOSING_TX: u64 = 32;
+/// The master connection, from lightningd
+pub const CAP_MASTER: u64 = 1024;
+
+/// CLN's SIGHASH_ALL
+const SIGHASH_ALL: u8 = 1;
+
+/// Who is on the other end of a connection
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientContext {
+    /// The peer of the channel, or our node
+    pub peer_id: PublicKey,
+    /// The CLN database ID of the channel, zero if the client is not for a
+    /// channel
+    pub dbid: u64,
+    /// The `CAP_*` flags of the client
+    pub capabilities: u64,
+}
+
+/// Serves the requests of one connection
+pub struct Handler {
+    node: Arc<Node>,
+    context: ClientContext,
+}
+
+/// The channel ID for a CLN channel
+pub fn channel_id_for(peer_id: &PublicKey, dbid: u64) -> ChannelId {
+    channel_nonce_to_id(&channel_nonce_from_peer_dbid(peer_id, dbid))
+}
+
+/// The network of the chain with this genesis block, as CLN identifies
+/// the chain
+pub fn network_for(chain_hash: &BlockHash) -> Option<Network> {
+    [Network::Bitcoin, Network::Testnet, Network::Signet, Network::Regtest]
+        .iter()
+        .find(|network| genesis_block(**network).block_hash() == *chain_hash)
+        .cloned()
+}
+
+/// Create the node on the first start, or load it, and answer the
+/// [Message::Init] request.
+///
+/// Returns the handler for the master connection and the reply.
+pub fn init(
+    signer: &MultiSigner,
+    seed: &[u8; 32],
+    request: &Message,
+) -> Result<(Handler, Message), Status> {
+    let chain_hash = match request {
+        Message::Init { hsm_encryption_key: Some(_), .. } => return Err(invalid_argument(
+            "encrypted hsm_secret is not supported, decrypt it with `lightning-hsmtool decrypt`",
+        )),
+        Message::Init { dev_overrides: true, .. } =>
+            return Err(invalid_argument("developer key overrides are not supported")),
+        Message::Init { chain_hash, .. } => chain_hash,
+        _ => return Err(invalid_argument("expected init")),
+    };
+    let network = network_for(chain_hash)
+        .ok_or_else(|| invalid_argument(format!("unknown chain {}", chain_hash)))?;
+    let node_config = NodeConfig {
+        network,
+        key_derivation_style: KeyDerivationStyle::Native,
+        chain_params: None,
+        checkpoint: None,
+    };
+    let node_id = match signer.warmstart_with_seed(node_config.clone(), seed) {
+        Ok(node_id) => node_id,
+        Err(_) => {
+            let node_id = signer.new_node_from_seed(node_config, seed)?;
+            info!("created node {} on {}", node_id, network);
+            node_id
+        }
+    };
+    let node = signer.get_node(&node_id)?;
+    let reply = Message::InitReply {
+        node_id,
+        bip32: node.get_account_extended_pubkey().encode(),
+        bolt12: node.get_bolt12_pubkey().serialize(),
+    };
+    let context = ClientContext {
+        peer_id: node_id,
+        dbid: 0,
+        capabilities: CAP_MASTER | CAP_SIGN_GOSSIP | CAP_ECDH,
+    };
+    Ok((Handler { node, context }, reply))
+}
+
+fn convert_basepoints(points: &ChannelPublicKeys) -> Basepoints {
+    Basepoints {
+        revocation: points.revocation_basepoint,
+        payment: points.payment_point,
+        htlc: points.htlc_basepoint,
+        delayed_payment: points.delayed_payment_basepoint,
+    }
+}
+
+// Split the HTLCs into those offered by the owner of the commitment and
+// those it received
+fn convert_htlcs(
+    htlcs: &[SimpleHtlc],
+    offered_side: u8,
+) -> Result<(Vec<HTLCInfo2>, Vec<HTLCInfo2>), Status> {
+    let mut offered = vec![];
+    let mut received = vec![];
+    for htlc in htlcs {
+        let info = HTLCInfo2 {
+            value_sat: htlc.amount_msat / 1000,
+            payment_hash: PaymentHash(htlc.payment_hash),
+            cltv_expiry: htlc.cltv_expiry,
+        };
+        if htlc.side != wire::SIDE_LOCAL && htlc.side != wire::SIDE_REMOTE {
+            return Err(invalid_argument(format!("bad htlc side {}", htlc.side)));
+        }
+        if htlc.side == offered_side {
+            offered.push(info);
+        } else {
+            received.push(info);
+        }
+    }
+    Ok((offered, received))
+}
+
+fn bitcoin_signature(signature: Signature) -> BitcoinSignature {
+    BitcoinSignature { signature, sighash_type: SIGHASH_ALL }
+}
+
+fn secret_bytes(secret: &SecretKey) -> [u8; 32] {
+    secret[..].try_into().expect("32 bytes")
+}
+
+fn check_funding_key(chan: &Channel, remote_funding_key: &PublicKey) -> Result<(), Status> {
+    if chan.setup.counterparty_points.funding_pubkey != *remote_funding_key {
+        return Err(invalid_argument("remote funding key mismatch"));
+    }
+    Ok(())
+}
+
+// Check that our signature of the funding input is for this transaction
+fn verify_funding_signature(
+    chan: &Channel,
+    tx: &Transaction,
+    signature: &Signature,
+) -> Result<(), Status> {
+    let holder_funding_key = chan.get_channel_basepoints().funding_pubkey;
+    let redeemscript = make_funding_redeemscript(
+        &holder_funding_key,
+        &chan.setup.counterparty_points.funding_pubkey,
+    );
+    if tx.input.is_empty() {
+        return Err(invalid_argument("tx has no inputs"));
+    }
+    let sighash = SigHashCache::new(tx).signature_hash(
+        0,
+        &redeemscript,
+        chan.setup.channel_value_sat,
+        SigHashType::All,
+    );
+    let message = secp256k1::Message::from_slice(&sighash[..]).expect("sighash is 32 bytes");
+    Secp256k1::verification_only()
+        .verify(&message, signature, &holder_funding_key)
+        .map_err(|_| invalid_argument("tx is not the current holder commitment"))
+}
+
+fn optional_script(script: &[u8]) -> Option<Script> {
+    if script.is_empty() {
+        None
+    } else {
+        Some(Script::from(script.to_vec()))
+    }
+}
+
+impl Handler {
+    /// The handler for a new connection, for a client of the connection of
+    /// this handler
+    pub fn for_client(&self, context: ClientContext) -> Handler {
+        Handler { node: Arc::clone(&self.node), context }
+    }
+
+    /// Who this handler serves
+    pub fn context(&self) -> &ClientContext {
+        &self.context
+    }
+
+    fn require(&self, capability: u64) -> Result<(), Status> {
+        if self.context.capabilities & capability != capability {
+            return Err(invalid_argument(format!(
+                "client with capabilities {:#x} lacks {:#x}",
+                self.context.capabilities, capability
+            )));
+        }
+        Ok(())
+    }
+
+    // The channel of a client for a channel
+    fn channel_id(&self) -> Result<ChannelId, Status> {
+        if self.context.dbid == 0 {
+            return Err(invalid_argument("client is not for a channel"));
+        }
+        Ok(channel_id_for(&self.context.peer_id, self.context.dbid))
+    }
+
+    // Create the channel stub the first time the channel is seen
+    fn ensure_channel(&self, peer_id: &PublicKey, dbid: u64) -> Result<ChannelId, Status> {
+        let channel_id = channel_id_for(peer_id, dbid);
+        if self.node.get_channel(&channel_id).is_err() {
+            self.node.new_channel_with_dbid(peer_id, dbid, &self.node)?;
+        }
+        Ok(channel_id)
+    }
+
+    /// Serve a request
+    pub fn handle(&self, request: &Message) -> Result<Message, Status> {
+        match request {
+            Message::ClientHsmfd { peer_id, dbid, capabilities: _ } => {
+                self.require(CAP_MASTER)?;
+                if *dbid != 0 {
+                    self.ensure_channel(peer_id, *dbid)?;
+                }
+                Ok(Message::ClientHsmfdReply)
+            }
+            Message::GetChannelBasepoints { peer_id, dbid } => {
+                self.require(CAP_MASTER)?;
+                let channel_id = self.ensure_channel(peer_id, *dbid)?;
+                let points = self
+                    .node
+                    .with_channel_base(&channel_id, |base| Ok(base.get_channel_basepoints()))?;
+                Ok(Message::GetChannelBasepointsReply {
+                    basepoints: convert_basepoints(&points),
+                    funding_pubkey: points.funding_pubkey,
+                })
+            }
+            Message::Ecdh { point } => {
+                self.require(CAP_ECDH)?;
+                let shared_secret = self.node.ecdh(point);
+                Ok(Message::EcdhReply {
+                    shared_secret: shared_secret[..].try_into().expect("32 bytes"),
+                })
+            }
+            Message::CannouncementSig { announcement } => {
+                self.require(CAP_SIGN_GOSSIP)?;
+                // the type and the four signatures
+                if announcement.len() < 2 + 4 * 64 {
+                    return Err(invalid_argument("channel announcement too short"));
+                }
+                let contents = announcement[2 + 4 * 64..].to_vec();
+                let (node_signature, bitcoin_signature) =
+                    self.node.with_ready_channel(&self.channel_id()?, |chan| {
+                        chan.sign_channel_announcement(&contents)
+                    })?;
+                Ok(Message::CannouncementSigReply { node_signature, bitcoin_signature })
+            }
+            Message::CupdateSig { update } => {
+                self.require(CAP_SIGN_GOSSIP)?;
+                let signature = self.node.sign_gossip_message(update)?;
+                let mut update = update.clone();
+                update[2..2 + 64].copy_from_slice(&signature.serialize_compact());
+                Ok(Message::CupdateSigReply { update })
+            }
+            Message::NodeAnnouncementSig { announcement } => {
+                self.require(CAP_SIGN_GOSSIP)?;
+                let signature = self.node.sign_gossip_message(announcement)?;
+                Ok(Message::NodeAnnouncementSigReply { signature })
+            }
+            Message::SignInvoice { u5bytes, hrp } => {
+                self.require(CAP_MASTER)?;
+                let data =
+                    u5bytes.check_base32().map_err(|_| invalid_argument("invalid base32 data"))?;
+                let signature = self.node.sign_invoice(hrp, &data)?;
+                Ok(Message::SignInvoiceReply { signature })
+            }
+            Message::SignMessage { message } => {
+                self.require(CAP_MASTER)?;
+                // the compact signature and the recovery ID
+                let data = self.node.sign_message(message)?;
+                let id = RecoveryId::from_i32(data[64] as i32)
+                    .map_err(|_| Status::internal("bad recovery id"))?;
+                let signature = RecoverableSignature::from_compact(&data[..64], id)
+                    .map_err(|_| Status::internal("bad signature"))?;
+                Ok(Message::SignMessageReply { signature })
+            }
+            Message::SignCommitmentTx { peer_id, dbid, tx, remote_funding_key } => {
+                self.require(CAP_MASTER)?;
+                let channel_id = channel_id_for(peer_id, *dbid);
+                let signature = self.node.with_ready_channel(&channel_id, |chan| {
+                    check_funding_key(chan, remote_funding_key)?;
+                    let commit_num = chan
+                        .enforcement_state
+                        .next_holder_commit_num
+                        .checked_sub(1)
+                        .ok_or_else(|| invalid_argument("no holder commitment"))?;
+                    let (signature, _) = chan.sign_holder_commitment_tx_phase2(commit_num)?;
+                    verify_funding_signature(chan, &tx.tx, &signature)?;
+                    Ok(signature)
+                })?;
+                Ok(Message::SignCommitmentTxReply { signature: bitcoin_signature(signature) })
+            }
+            Message::GetPerCommitmentPoint { n } => {
+                self.require(CAP_COMMITMENT_POINT)?;
+                let n = *n;
+                let (point, old_secret) =
+                    self.node.with_channel_base(&self.channel_id()?, |base| {
+                        let point = base.get_per_commitment_point(n)?;
+                        let old_secret = if n >= 2 {
+                            Some(secret_bytes(&base.get_revocation(n - 2)?.per_commitment_secret))
+                        } else {
+                            None
+                        };
+                        Ok((point, old_secret))
+                    })?;
+                Ok(Message::GetPerCommitmentPointReply { point, old_secret })
+            }
+            Message::CheckFutureSecret { n, secret } => {
+                self.require(CAP_COMMITMENT_POINT)?;
+                // a secret that isn't a valid key can't be ours
+                let correct = match SecretKey::from_slice(secret) {
+                    Ok(secret) => self.node.with_channel_base(&self.channel_id()?, |base| {
+                        base.check_future_secret(*n, &secret)
+                    })?,
+                    Err(_) => false,
+                };
+                Ok(Message::CheckFutureSecretReply { correct })
+            }
+            Message::ReadyChannel {
+                is_outbound,
+                channel_value_sat,
+                push_value_msat,
+                funding_txid,
+                funding_txout,
+                local_to_self_delay,
+                local_shutdown_script,
+                remote_basepoints,
+                remote_funding_pubkey,
+                remote_to_self_delay,
+                remote_shutdown_script,
+                option_static_remotekey,
+                option_anchor_outputs,
+            } => {
+                self.require(CAP_SIGN_REMOTE_TX)?;
+                let channel_id = self.channel_id()?;
+                let commitment_type = if *option_anchor_outputs {
+                    CommitmentType::Anchors
+                } else if *option_static_remotekey {
+                    CommitmentType::StaticRemoteKey
+                } else {
+                    CommitmentType::Legacy
+                };
+                let setup = ChannelSetup {
+                    is_outbound: *is_outbound,
+                    channel_value_sat: *channel_value_sat,
+                    push_value_msat: *push_value_msat,
+                    funding_outpoint: OutPoint { txid: *funding_txid, vout: *funding_txout as u32 },
+                    holder_selected_contest_delay: *local_to_self_delay,
+                    holder_shutdown_script: optional_script(local_shutdown_script),
+                    counterparty_points: ChannelPublicKeys {
+                        funding_pubkey: *remote_funding_pubkey,
+                        revocation_basepoint: remote_basepoints.revocation,
+                        payment_point: remote_basepoints.payment,
+                        htlc_basepoint: remote_basepoints.htlc,
+                        delayed_payment_basepoint: remote_basepoints.delayed_payment,
+                    },
+                    counterparty_selected_contest_delay: *remote_to_self_delay,
+                    counterparty_shutdown_script: optional_script(remote_shutdown_script),
+                    commitment_type,
+                };
+                self.node.ready_channel(channel_id, None, setup, &vec![])?;
+                Ok(Message::ReadyChannelReply)
+            }
+            Message::SignRemoteCommitmentTx {
+                tx,
+                remote_funding_key,
+                remote_per_commit,
+                option_static_remotekey: _,
+                commit_num,
+                htlcs,
+                feerate_per_kw,
+            } => {
+                self.require(CAP_SIGN_REMOTE_TX)?;
+                let witscripts = tx.output_witscripts();
+                let (offered, received) = convert_htlcs(htlcs, wire::SIDE_REMOTE)?;
+                let signature = self.node.with_ready_channel(&self.channel_id()?, |chan| {
+                    check_funding_key(chan, remote_funding_key)?;
+                    chan.sign_counterparty_commitment_tx(
+                        &tx.tx,
+                        &witscripts,
+                        remote_per_commit,
+                        *commit_num,
+                        *feerate_per_kw,
+                        offered.clone(),
+                        received.clone(),
+                    )
+                })?;
+                Ok(Message::SignTxReply { signature: bitcoin_signature(signature) })
+            }
+            Message::ValidateCommitmentTx {
+                tx,
+                htlcs,
+                commit_num,
+                feerate_per_kw,
+                signature,
+                htlc_signatures,
+            } => {
+                self.require(CAP_SIGN_REMOTE_TX)?;
+                let witscripts = tx.output_witscripts();
+                let (offered, received) = convert_htlcs(htlcs, wire::SIDE_LOCAL)?;
+                let htlc_signatures: Vec<Signature> =
+                    htlc_signatures.iter().map(|s| s.signature).collect();
+                let (next_point, old_secret) =
+                    self.node.with_ready_channel(&self.channel_id()?, |chan| {
+                        chan.validate_holder_commitment_tx(
+                            &tx.tx,
+                            &witscripts,
+                            *commit_num,
+                            *feerate_per_kw,
+                            offered.clone(),
+                            received.clone(),
+                            &signature.signature,
+                            &htlc_signatures,
+                        )
+                    })?;
+                Ok(Message::ValidateCommitmentTxReply {
+                    old_secret: old_secret.as_ref().map(secret_bytes),
+                    next_point,
+                })
+            }
+            Message::ValidateRevocation { commit_num, secret } => {
+                self.require(CAP_SIGN_REMOTE_TX)?;
+                let secret =
+                    SecretKey::from_slice(secret).map_err(|_| invalid_argument("bad secret"))?;
+                self.node.with_ready_channel(&self.channel_id()?, |chan| {
+                    chan.validate_counterparty_revocation(*commit_num, &secret)
+                })?;
+                Ok(Message::ValidateRevocationReply)
+            }
+            Message::SignMutualCloseTx { tx, remote_funding_key } => {
+                self.require(CAP_SIGN_CLOSING_TX)?;
+                // CLN doesn't say which outputs are ours, they must be
+                // allowlisted
+                let opaths = vec![vec![]; tx.tx.output.len()];
+                let signature = self.node.with_ready_channel(&self.channel_id()?, |chan| {
+                    check_funding_key(chan, remote_funding_key)?;
+                    chan.sign_mutual_close_tx(&tx.tx, &opaths)
+                })?;
+                Ok(Message::SignTxReply { signature: bitcoin_signature(signature) })
+            }
+            _ => Err(invalid_argument(format!("unexpected message type {}", request.msg_type()))),
+        }
+    }
+}
+
+/// Pass a file descriptor over a unix socket, with a single byte, as CLN's
+/// `fdpass_send` does
+pub fn send_fd(stream: &UnixStream, fd: RawFd) -> io::Result<()> {
+    let fds = [fd];
+    let iov = [IoVec::from_slice(&[0u8])];
+    sendmsg(stream.as_raw_fd(), &iov, &[ControlMessage::ScmRights(&fds)], MsgFlags::empty(), None)?;
+    Ok(())
+}
+
+/// Serve the requests of a connection until it is closed.
+///
+/// A new connection for a client is served by its own thread.  An error
+/// closes the connection, which CLN treats as fatal for the subdaemon, and
+/// for lightningd if this is the master connection.
+pub fn serve(mut stream: UnixStream, handler: Handler) -> io::Result<()> {
+    loop {
+        let data = match wire::read_message(&mut stream) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let request = Message::decode(&data)?;
+        let reply = handler.handle(&request).map_err(|s| {
+            io::Error::new(
+                ErrorKind::Other,
+                format!("request type {} failed: {}", request.msg_type(), s.message()),
+            )
+        })?;
+        wire::write_message(&mut stream, &reply.encode())?;
+        if let Message::ClientHsmfd { peer_id, dbid, capabilities } = request {
+            let client = handler.for_client(ClientContext { peer_id, dbid, capabilities });
+            let (ours, theirs) = UnixStream::pair()?;
+            send_fd(&stream, theirs.as_raw_fd())?;
+            // the client has its own copy now
+            drop(theirs);
+            thread::spawn(move || {
+                let context = client.context().clone();
+                if let Err(e) = serve(ours, client) {
+                    warn!("client {:?} closed: {}", context, e);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning_signer::bitcoin::hashes::Hash;
+    use lightning_signer::bitcoin::Txid;
+    use lightning_signer::util::key_utils::{make_test_counterparty_points, make_test_pubkey};
+    use nix::sys::socket::{recvmsg, ControlMessageOwned};
+    use nix::{cmsg_space, unistd};
+    use test_log::test;
+
+    use super::*;
+
+    const SEED: [u8; 32] = [3u8; 32];
+
+    fn make_init() -> Message {
+        Message::Init {
+            bip32_key_version: (0x043587CF, 0x04358394),
+            chain_hash: genesis_block(Network::Regtest).block_hash(),
+            hsm_encryption_key: None,
+            dev_overrides: false,
+        }
+    }
+
+    fn make_master() -> Handler {
+        let signer = MultiSigner::new();
+        init(&signer, &SEED, &make_init()).unwrap().0
+    }
+
+    fn make_channel_client(master: &Handler, dbid: u64) -> Handler {
+        let peer_id = make_test_pubkey(9);
+        let request = Message::ClientHsmfd {
+            peer_id,
+            dbid,
+            capabilities: CAP_COMMITMENT_POINT | CAP_SIGN_REMOTE_TX | CAP_SIGN_CLOSING_TX,
+        };
+        assert_eq!(master.handle(&request).unwrap(), Message::ClientHsmfdReply);
+        master.for_client(ClientContext {
+            peer_id,
+            dbid,
+            capabilities: CAP_COMMITMENT_POINT | CAP_SIGN_REMOTE_TX | CAP_SIGN_CLOSING_TX,
+        })
+    }
+
+    fn make_ready_channel() -> Message {
+        let points = make_test_counterparty_points();
+        Message::ReadyChannel {
+            is_outbound: true,
+            channel_value_sat: 3_000_000,
+            push_value_msat: 0,
+            funding_txid: Txid::from_slice(&[2u8; 32]).unwrap(),
+            funding_txout: 0,
+            local_to_self_delay: 6,
+            local_shutdown_script: vec![],
+            remote_basepoints: convert_basepoints(&points),
+            remote_funding_pubkey: points.funding_pubkey,
+            remote_to_self_delay: 7,
+            remote_shutdown_script: vec![],
+            option_static_remotekey: true,
+            option_anchor_outputs: false,
+        }
+    }
+
+    #[test]
+    fn init_test() {
+        let signer = MultiSigner::new();
+        let (master, reply) = init(&signer, &SEED, &make_init()).unwrap();
+        let node_id = master.context().peer_id;
+        match reply {
+            Message::InitReply { node_id: reply_node_id, .. } => assert_eq!(reply_node_id, node_id),
+            m => panic!("unexpected {:?}", m),
+        }
+        // a restart finds the node
+        let (master, _) = init(&signer, &SEED, &make_init()).unwrap();
+        assert_eq!(master.context().peer_id, node_id);
+        assert_eq!(signer.get_node_ids(), vec![node_id]);
+    }
+
+    #[test]
+    fn init_rejects_test() {
+        let signer = MultiSigner::new();
+        let encrypted = Message::Init {
+            bip32_key_version: (0, 0),
+            chain_hash: genesis_block(Network::Regtest).block_hash(),
+            hsm_encryption_key: Some([1u8; 32]),
+            dev_overrides: false,
+        };
+        assert!(init(&signer, &SEED, &encrypted).is_err());
+        let unknown_chain = Message::Init {
+            bip32_key_version: (0, 0),
+            chain_hash: Default::default(),
+            hsm_encryption_key: None,
+            dev_overrides: false,
+        };
+        assert!(init(&signer, &SEED, &unknown_chain).is_err());
+        assert!(signer.get_node_ids().is_empty());
+    }
+
+    #[test]
+    fn capabilities_test() {
+        let master = make_master();
+        let point = make_test_pubkey(2);
+        match master.handle(&Message::Ecdh { point }).unwrap() {
+            Message::EcdhReply { shared_secret } =>
+                assert_eq!(shared_secret.to_vec(), master.node.ecdh(&point)),
+            m => panic!("unexpected {:?}", m),
+        }
+        assert!(master.handle(&Message::SignMessage { message: b"hello".to_vec() }).is_ok());
+        // the master connection is not for a channel
+        assert!(master.handle(&Message::GetPerCommitmentPoint { n: 0 }).is_err());
+
+        let client = make_channel_client(&master, 1);
+        assert!(client.handle(&Message::Ecdh { point }).is_err());
+        assert!(client.handle(&Message::SignMessage { message: b"hello".to_vec() }).is_err());
+        assert!(client
+            .handle(&Message::ClientHsmfd { peer_id: point, dbid: 2, capabilities: CAP_MASTER })
+            .is_err());
+    }
+
+    #[test]
+    fn channel_test() {
+        let master = make_master();
+        let peer_id = make_test_pubkey(9);
+        let basepoints =
+            master.handle(&Message::GetChannelBasepoints { peer_id, dbid: 1 }).unwrap();
+        let client = make_channel_client(&master, 1);
+        let point = match client.handle(&Message::GetPerCommitmentPoint { n: 0 }).unwrap() {
+            Message::GetPerCommitmentPointReply { point, old_secret: None } => point,
+            m => panic!("unexpected {:?}", m),
+        };
+        assert_eq!(client.handle(&make_ready_channel()).unwrap(), Message::ReadyChannelReply);
+
+        // the keys are those of the stub
+        let channel_id = channel_id_for(&peer_id, 1);
+        let channel_point = master
+            .node
+            .with_ready_channel(&channel_id, |chan| chan.get_per_commitment_point(0))
+            .unwrap();
+        assert_eq!(point, channel_point);
+        assert_eq!(
+            master.handle(&Message::GetChannelBasepoints { peer_id, dbid: 1 }).unwrap(),
+            basepoints
+        );
+
+        // another channel has other keys
+        let other = make_channel_client(&master, 2);
+        assert_ne!(
+            other.handle(&Message::GetPerCommitmentPoint { n: 0 }).unwrap(),
+            client.handle(&Message::GetPerCommitmentPoint { n: 0 }).unwrap()
+        );
+    }
+
+    #[test]
+    fn htlcs_test() {
+        let htlcs = vec![
+            SimpleHtlc {
+                side: wire::SIDE_LOCAL,
+                amount_msat: 5_000_999,
+                payment_hash: [1; 32],
+                cltv_expiry: 1,
+            },
+            SimpleHtlc {
+                side: wire::SIDE_REMOTE,
+                amount_msat: 6_000_000,
+                payment_hash: [2; 32],
+                cltv_expiry: 2,
+            },
+        ];
+        let (offered, received) = convert_htlcs(&htlcs, wire::SIDE_REMOTE).unwrap();
+        assert_eq!(offered.len(), 1);
+        assert_eq!(offered[0].value_sat, 6000);
+        assert_eq!(received[0].value_sat, 5000);
+        let bad = SimpleHtlc { side: 2, ..htlcs[0].clone() };
+        assert!(convert_htlcs(&[bad], wire::SIDE_LOCAL).is_err());
+    }
+
+    // The received connection is only a file descriptor
+    fn write_raw(fd: RawFd, message: &Message) {
+        let mut data = vec![];
+        wire::write_message(&mut data, &message.encode()).unwrap();
+        assert_eq!(unistd::write(fd, &data).unwrap(), data.len());
+    }
+
+    fn read_exact_raw(fd: RawFd, buf: &mut [u8]) {
+        let mut pos = 0;
+        while pos < buf.len() {
+            let len = unistd::read(fd, &mut buf[pos..]).unwrap();
+            assert!(len > 0, "connection closed");
+            pos += len;
+        }
+    }
+
+    fn read_raw(fd: RawFd) -> Message {
+        let mut len = [0u8; 4];
+        read_exact_raw(fd, &mut len);
+        let mut data = vec![0u8; u32::from_be_bytes(len) as usize];
+        read_exact_raw(fd, &mut data);
+        Message::decode(&data).unwrap()
+    }
+
+    #[test]
+    fn serve_test() {
+        let master = make_master();
+        let (lightningd, hsmd) = UnixStream::pair().unwrap();
+        let server = thread::spawn(move || serve(hsmd, master));
+
+        let peer_id = make_test_pubkey(9);
+        let request = Message::ClientHsmfd { peer_id, dbid: 1, capabilities: CAP_COMMITMENT_POINT };
+        wire::write_message(&mut &lightningd, &request.encode()).unwrap();
+        // read no further than the reply, so that the new connection isn't lost
+        let reply = wire::read_message(&mut &lightningd).unwrap();
+        assert_eq!(Message::decode(&reply).unwrap(), Message::ClientHsmfdReply);
+
+        // the new connection follows the reply
+        let mut byte = [0u8; 1];
+        let iov = [IoVec::from_mut_slice(&mut byte)];
+        let mut cmsg_buffer = cmsg_space!([RawFd; 1]);
+        let received =
+            recvmsg(lightningd.as_raw_fd(), &iov, Some(&mut cmsg_buffer), MsgFlags::empty())
+                .unwrap();
+        let fd = match received.cmsgs().next() {
+            Some(ControlMessageOwned::ScmRights(fds)) => fds[0],
+            c => panic!("unexpected {:?}", c),
+        };
+
+        write_raw(fd, &Message::GetPerCommitmentPoint { n: 0 });
+        match read_raw(fd) {
+            Message::GetPerCommitmentPointReply { old_secret: None, .. } => {}
+            m => panic!("unexpected {:?}", m),
+        }
+        // the client can't sign for the node
+        write_raw(fd, &Message::SignMessage { message: vec![] });
+        assert_eq!(unistd::read(fd, &mut [0u8; 16]).unwrap(), 0);
+        unistd::close(fd).unwrap();
+
+        drop(lightningd);
+        server.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,5 @@
+//! A drop-in replacement for c-lightning's (CLN's) `hsmd`, so that CLN can
+//! use the validating signer without patching.
+
+pub mod handler;
+pub mod wire;
@@ -0,0 +1,1071 @@
+//! The c-lightning hsmd wire protocol, as of CLN v0.10.2.
+//!
+//! Each message is sent with its length as a big endian 32 bit integer,
+//! and starts with its type as a big endian 16 bit integer, followed by
+//! its fields, as described by CLN's `hsmd/hsmd_wire.csv`.  Optional fields
+//! are preceded by a presence byte, and variable length fields by their
+//! length as a 16 bit integer.  Transactions are sent as the consensus
+//! serialization followed by the PSBT, which carries the output witness
+//! scripts.
+//!
+//! Only the messages served by [super::handler] are decoded.  Trailing
+//! bytes are ignored, as CLN does, so that fields can be added.
+
+use std::convert::TryInto;
+use std::io::{self, Cursor, ErrorKind, Read, Write};
+
+use lightning_signer::bitcoin::consensus::encode::{deserialize, serialize};
+use lightning_signer::bitcoin::consensus::Decodable;
+use lightning_signer::bitcoin::hashes::Hash;
+use lightning_signer::bitcoin::secp256k1::recovery::{RecoverableSignature, RecoveryId};
+use lightning_signer::bitcoin::secp256k1::{PublicKey, Signature};
+use lightning_signer::bitcoin::util::psbt::PartiallySignedTransaction;
+use lightning_signer::bitcoin::{BlockHash, Transaction, Txid};
+
+/// The largest message, CLN's hsmd has no limit but the largest messages
+/// are transactions
+pub const MAX_MESSAGE_LEN: usize = 1 << 20;
+
+pub const HSMD_ECDH_REQ: u16 = 1;
+pub const HSMD_CANNOUNCEMENT_SIG_REQ: u16 = 2;
+pub const HSMD_CUPDATE_SIG_REQ: u16 = 3;
+pub const HSMD_SIGN_COMMITMENT_TX: u16 = 5;
+pub const HSMD_NODE_ANNOUNCEMENT_SIG_REQ: u16 = 6;
+pub const HSMD_SIGN_INVOICE: u16 = 8;
+pub const HSMD_CLIENT_HSMFD: u16 = 9;
+pub const HSMD_GET_CHANNEL_BASEPOINTS: u16 = 10;
+pub const HSMD_INIT: u16 = 11;
+pub const HSMD_GET_PER_COMMITMENT_POINT: u16 = 18;
+pub const HSMD_SIGN_REMOTE_COMMITMENT_TX: u16 = 19;
+pub const HSMD_SIGN_MUTUAL_CLOSE_TX: u16 = 21;
+pub const HSMD_CHECK_FUTURE_SECRET: u16 = 22;
+pub const HSMD_SIGN_MESSAGE: u16 = 23;
+pub const HSMD_READY_CHANNEL: u16 = 31;
+pub const HSMD_VALIDATE_COMMITMENT_TX: u16 = 35;
+pub const HSMD_VALIDATE_REVOCATION: u16 = 36;
+
+pub const HSMD_ECDH_RESP: u16 = 100;
+pub const HSMD_CANNOUNCEMENT_SIG_REPLY: u16 = 102;
+pub const HSMD_CUPDATE_SIG_REPLY: u16 = 103;
+pub const HSMD_SIGN_COMMITMENT_TX_REPLY: u16 = 105;
+pub const HSMD_NODE_ANNOUNCEMENT_SIG_REPLY: u16 = 106;
+pub const HSMD_SIGN_INVOICE_REPLY: u16 = 108;
+pub const HSMD_CLIENT_HSMFD_REPLY: u16 = 109;
+pub const HSMD_GET_CHANNEL_BASEPOINTS_REPLY: u16 = 110;
+pub const HSMD_INIT_REPLY: u16 = 111;
+pub const HSMD_SIGN_TX_REPLY: u16 = 112;
+pub const HSMD_GET_PER_COMMITMENT_POINT_REPLY: u16 = 118;
+pub const HSMD_CHECK_FUTURE_SECRET_REPLY: u16 = 122;
+pub const HSMD_SIGN_MESSAGE_REPLY: u16 = 123;
+pub const HSMD_READY_CHANNEL_REPLY: u16 = 131;
+pub const HSMD_VALIDATE_COMMITMENT_TX_REPLY: u16 = 135;
+pub const HSMD_VALIDATE_REVOCATION_REPLY: u16 = 136;
+
+/// CLN's `enum side`, who offered an HTLC
+pub const SIDE_LOCAL: u8 = 0;
+/// See [SIDE_LOCAL]
+pub const SIDE_REMOTE: u8 = 1;
+
+/// A transaction, with the PSBT CLN sends along
+#[derive(Clone, Debug, PartialEq)]
+pub struct WireTx {
+    /// The transaction
+    pub tx: Transaction,
+    /// The PSBT of the transaction
+    pub psbt: PartiallySignedTransaction,
+}
+
+impl WireTx {
+    /// A transaction with an empty PSBT
+    pub fn new(tx: Transaction) -> Self {
+        let mut unsigned = tx.clone();
+        for input in unsigned.input.iter_mut() {
+            input.script_sig = Default::default();
+            input.witness = vec![];
+        }
+        let psbt =
+            PartiallySignedTransaction::from_unsigned_tx(unsigned).expect("inputs were cleared");
+        WireTx { tx, psbt }
+    }
+
+    /// The witness script of each output, empty if the PSBT has none
+    pub fn output_witscripts(&self) -> Vec<Vec<u8>> {
+        (0..self.tx.output.len())
+            .map(|i| {
+                self.psbt
+                    .outputs
+                    .get(i)
+                    .and_then(|o| o.witness_script.as_ref())
+                    .map(|s| s.to_bytes())
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+}
+
+/// CLN's `struct simple_htlc`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimpleHtlc {
+    /// Who offered the HTLC, [SIDE_LOCAL] or [SIDE_REMOTE]
+    pub side: u8,
+    /// The amount
+    pub amount_msat: u64,
+    /// The payment hash
+    pub payment_hash: [u8; 32],
+    /// The absolute timelock
+    pub cltv_expiry: u32,
+}
+
+/// The channel basepoints, in CLN's order
+#[derive(Clone, Debug, PartialEq)]
+pub struct Basepoints {
+    pub revocation: PublicKey,
+    pub payment: PublicKey,
+    pub htlc: PublicKey,
+    pub delayed_payment: PublicKey,
+}
+
+/// A signature with its sighash type, CLN's `struct bitcoin_signature`
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitcoinSignature {
+    /// The signature
+    pub signature: Signature,
+    /// The sighash type
+    pub sighash_type: u8,
+}
+
+/// A message of the hsmd protocol
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// lightningd starts the signer
+    Init {
+        /// The BIP32 public and private key versions of the network
+        bip32_key_version: (u32, u32),
+        /// The genesis block of the chain
+        chain_hash: BlockHash,
+        /// Set if the `hsm_secret` is encrypted
+        hsm_encryption_key: Option<[u8; 32]>,
+        /// Set if any of CLN's developer key overrides are supplied
+        dev_overrides: bool,
+    },
+    InitReply {
+        node_id: PublicKey,
+        /// The serialized BIP32 wallet public key
+        bip32: [u8; 78],
+        /// The x-only BOLT12 key
+        bolt12: [u8; 32],
+    },
+    /// lightningd asks for a connection for a subdaemon
+    ClientHsmfd {
+        /// The peer of the channel, or our node for a subdaemon that isn't
+        /// for a channel
+        peer_id: PublicKey,
+        /// The CLN database ID of the channel, zero if none
+        dbid: u64,
+        /// What the subdaemon may ask for, see [super::handler]
+        capabilities: u64,
+    },
+    /// Followed by the new connection, as an SCM_RIGHTS file descriptor
+    ClientHsmfdReply,
+    GetChannelBasepoints {
+        peer_id: PublicKey,
+        dbid: u64,
+    },
+    GetChannelBasepointsReply {
+        basepoints: Basepoints,
+        funding_pubkey: PublicKey,
+    },
+    Ecdh {
+        point: PublicKey,
+    },
+    EcdhReply {
+        shared_secret: [u8; 32],
+    },
+    /// The announcement, starting with its type and signatures
+    CannouncementSig {
+        announcement: Vec<u8>,
+    },
+    CannouncementSigReply {
+        node_signature: Signature,
+        bitcoin_signature: Signature,
+    },
+    /// The update, starting with its type and signature
+    CupdateSig {
+        update: Vec<u8>,
+    },
+    /// The update, with the signature set
+    CupdateSigReply {
+        update: Vec<u8>,
+    },
+    /// The announcement, starting with its type and signature
+    NodeAnnouncementSig {
+        announcement: Vec<u8>,
+    },
+    NodeAnnouncementSigReply {
+        signature: Signature,
+    },
+    /// lightningd signs our commitment, to close the channel unilaterally
+    SignCommitmentTx {
+        peer_id: PublicKey,
+        dbid: u64,
+        tx: WireTx,
+        remote_funding_key: PublicKey,
+    },
+    SignCommitmentTxReply {
+        signature: BitcoinSignature,
+    },
+    SignInvoice {
+        /// One 5 bit value per byte
+        u5bytes: Vec<u8>,
+        hrp: Vec<u8>,
+    },
+    SignInvoiceReply {
+        signature: RecoverableSignature,
+    },
+    SignMessage {
+        message: Vec<u8>,
+    },
+    SignMessageReply {
+        signature: RecoverableSignature,
+    },
+    GetPerCommitmentPoint {
+        n: u64,
+    },
+    GetPerCommitmentPointReply {
+        point: PublicKey,
+        /// The secret of commitment `n - 2`, which is revoked
+        old_secret: Option<[u8; 32]>,
+    },
+    CheckFutureSecret {
+        n: u64,
+        secret: [u8; 32],
+    },
+    CheckFutureSecretReply {
+        correct: bool,
+    },
+    ReadyChannel {
+        is_outbound: bool,
+        channel_value_sat: u64,
+        push_value_msat: u64,
+        funding_txid: Txid,
+        funding_txout: u16,
+        /// The delay we impose on the counterparty
+        local_to_self_delay: u16,
+        local_shutdown_script: Vec<u8>,
+        remote_basepoints: Basepoints,
+        remote_funding_pubkey: PublicKey,
+        /// The delay the counterparty imposes on us
+        remote_to_self_delay: u16,
+        remote_shutdown_script: Vec<u8>,
+        option_static_remotekey: bool,
+        option_anchor_outputs: bool,
+    },
+    ReadyChannelReply,
+    SignRemoteCommitmentTx {
+        tx: WireTx,
+        remote_funding_key: PublicKey,
+        remote_per_commit: PublicKey,
+        option_static_remotekey: bool,
+        commit_num: u64,
+        htlcs: Vec<SimpleHtlc>,
+        feerate_per_kw: u32,
+    },
+    SignMutualCloseTx {
+        tx: WireTx,
+        remote_funding_key: PublicKey,
+    },
+    /// The reply to the transaction signing requests
+    SignTxReply {
+        signature: BitcoinSignature,
+    },
+    ValidateCommitmentTx {
+        tx: WireTx,
+        htlcs: Vec<SimpleHtlc>,
+        commit_num: u64,
+        feerate_per_kw: u32,
+        signature: BitcoinSignature,
+        htlc_signatures: Vec<BitcoinSignature>,
+    },
+    ValidateCommitmentTxReply {
+        old_secret: Option<[u8; 32]>,
+        next_point: PublicKey,
+    },
+    ValidateRevocation {
+        commit_num: u64,
+        secret: [u8; 32],
+    },
+    ValidateRevocationReply,
+}
+
+/// Reads the fields of a message
+struct Reader<'a> {
+    cursor: Cursor<&'a [u8]>,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { cursor: Cursor::new(data) }
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        self.cursor.read_exact(&mut buf).map_err(|_| invalid("message truncated"))?;
+        Ok(buf)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().expect("length was read"))
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    fn bool(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("bad bool")),
+        }
+    }
+
+    // A u16 length, then the bytes
+    fn var_bytes(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.u16()? as usize;
+        self.bytes(len)
+    }
+
+    fn option<T, F: FnOnce(&mut Self) -> io::Result<T>>(&mut self, f: F) -> io::Result<Option<T>> {
+        if self.bool()? {
+            Ok(Some(f(self)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn pubkey(&mut self) -> io::Result<PublicKey> {
+        PublicKey::from_slice(&self.bytes(33)?).map_err(|_| invalid("bad public key"))
+    }
+
+    fn signature(&mut self) -> io::Result<Signature> {
+        Signature::from_compact(&self.bytes(64)?).map_err(|_| invalid("bad signature"))
+    }
+
+    fn bitcoin_signature(&mut self) -> io::Result<BitcoinSignature> {
+        Ok(BitcoinSignature { signature: self.signature()?, sighash_type: self.u8()? })
+    }
+
+    fn recoverable_signature(&mut self) -> io::Result<RecoverableSignature> {
+        let data = self.bytes(65)?;
+        let id = RecoveryId::from_i32(data[64] as i32).map_err(|_| invalid("bad recovery id"))?;
+        RecoverableSignature::from_compact(&data[..64], id).map_err(|_| invalid("bad signature"))
+    }
+
+    fn basepoints(&mut self) -> io::Result<Basepoints> {
+        Ok(Basepoints {
+            revocation: self.pubkey()?,
+            payment: self.pubkey()?,
+            htlc: self.pubkey()?,
+            delayed_payment: self.pubkey()?,
+        })
+    }
+
+    fn tx(&mut self) -> io::Result<WireTx> {
+        let tx = Transaction::consensus_decode(&mut self.cursor)
+            .map_err(|e| invalid(&format!("bad tx: {}", e)))?;
+        let len = self.u32()? as usize;
+        if len > MAX_MESSAGE_LEN {
+            return Err(invalid("psbt too long"));
+        }
+        let psbt =
+            deserialize(&self.bytes(len)?).map_err(|e| invalid(&format!("bad psbt: {}", e)))?;
+        Ok(WireTx { tx, psbt })
+    }
+
+    fn htlcs(&mut self) -> io::Result<Vec<SimpleHtlc>> {
+        let count = self.u16()?;
+        (0..count)
+            .map(|_| {
+                Ok(SimpleHtlc {
+                    side: self.u8()?,
+                    amount_msat: self.u64()?,
+                    payment_hash: self.array()?,
+                    cltv_expiry: self.u32()?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Writes the fields of a message
+struct Writer {
+    out: Vec<u8>,
+}
+
+impl Writer {
+    fn new(msg_type: u16) -> Self {
+        Writer { out: msg_type.to_be_bytes().to_vec() }
+    }
+
+    fn bytes(&mut self, data: &[u8]) {
+        self.out.extend_from_slice(data);
+    }
+
+    fn u8(&mut self, val: u8) {
+        self.out.push(val);
+    }
+
+    fn u16(&mut self, val: u16) {
+        self.bytes(&val.to_be_bytes());
+    }
+
+    fn u32(&mut self, val: u32) {
+        self.bytes(&val.to_be_bytes());
+    }
+
+    fn u64(&mut self, val: u64) {
+        self.bytes(&val.to_be_bytes());
+    }
+
+    fn bool(&mut self, val: bool) {
+        self.u8(val as u8);
+    }
+
+    fn var_bytes(&mut self, data: &[u8]) {
+        assert!(data.len() <= u16::MAX as usize, "field too long");
+        self.u16(data.len() as u16);
+        self.bytes(data);
+    }
+
+    fn pubkey(&mut self, key: &PublicKey) {
+        self.bytes(&key.serialize());
+    }
+
+    fn signature(&mut self, sig: &Signature) {
+        self.bytes(&sig.serialize_compact());
+    }
+
+    fn bitcoin_signature(&mut self, sig: &BitcoinSignature) {
+        self.signature(&sig.signature);
+        self.u8(sig.sighash_type);
+    }
+
+    fn recoverable_signature(&mut self, sig: &RecoverableSignature) {
+        let (id, data) = sig.serialize_compact();
+        self.bytes(&data);
+        self.u8(id.to_i32() as u8);
+    }
+
+    fn optional_secret(&mut self, secret: &Option<[u8; 32]>) {
+        self.bool(secret.is_some());
+        if let Some(secret) = secret {
+            self.bytes(secret);
+        }
+    }
+
+    fn basepoints(&mut self, points: &Basepoints) {
+        self.pubkey(&points.revocation);
+        self.pubkey(&points.payment);
+        self.pubkey(&points.htlc);
+        self.pubkey(&points.delayed_payment);
+    }
+
+    fn tx(&mut self, tx: &WireTx) {
+        self.bytes(&serialize(&tx.tx));
+        let psbt = serialize(&tx.psbt);
+        self.u32(psbt.len() as u32);
+        self.bytes(&psbt);
+    }
+
+    fn htlcs(&mut self, htlcs: &[SimpleHtlc]) {
+        self.u16(htlcs.len() as u16);
+        for htlc in htlcs {
+            self.u8(htlc.side);
+            self.u64(htlc.amount_msat);
+            self.bytes(&htlc.payment_hash);
+            self.u32(htlc.cltv_expiry);
+        }
+    }
+}
+
+impl Message {
+    /// Serialize the message, without the length
+    pub fn encode(&self) -> Vec<u8> {
+        let w = match self {
+            Message::Init { bip32_key_version, chain_hash, hsm_encryption_key, dev_overrides } => {
+                let mut w = Writer::new(HSMD_INIT);
+                w.u32(bip32_key_version.0);
+                w.u32(bip32_key_version.1);
+                w.bytes(&chain_hash[..]);
+                w.optional_secret(hsm_encryption_key);
+                assert!(!dev_overrides, "developer overrides are not encoded");
+                // dev_force_privkey, dev_force_bip32_seed,
+                // dev_force_channel_secrets, dev_force_channel_secrets_shaseed
+                w.bytes(&[0, 0, 0, 0]);
+                w
+            }
+            Message::InitReply { node_id, bip32, bolt12 } => {
+                let mut w = Writer::new(HSMD_INIT_REPLY);
+                w.pubkey(node_id);
+                w.bytes(bip32);
+                w.bytes(bolt12);
+                w
+            }
+            Message::ClientHsmfd { peer_id, dbid, capabilities } => {
+                let mut w = Writer::new(HSMD_CLIENT_HSMFD);
+                w.pubkey(peer_id);
+                w.u64(*dbid);
+                w.u64(*capabilities);
+                w
+            }
+            Message::ClientHsmfdReply => Writer::new(HSMD_CLIENT_HSMFD_REPLY),
+            Message::GetChannelBasepoints { peer_id, dbid } => {
+                let mut w = Writer::new(HSMD_GET_CHANNEL_BASEPOINTS);
+                w.pubkey(peer_id);
+                w.u64(*dbid);
+                w
+            }
+            Message::GetChannelBasepointsReply { basepoints, funding_pubkey } => {
+                let mut w = Writer::new(HSMD_GET_CHANNEL_BASEPOINTS_REPLY);
+                w.basepoints(basepoints);
+                w.pubkey(funding_pubkey);
+                w
+            }
+            Message::Ecdh { point } => {
+                let mut w = Writer::new(HSMD_ECDH_REQ);
+                w.pubkey(point);
+                w
+            }
+            Message::EcdhReply { shared_secret } => {
+                let mut w = Writer::new(HSMD_ECDH_RESP);
+                w.bytes(shared_secret);
+                w
+            }
+            Message::CannouncementSig { announcement } => {
+                let mut w = Writer::new(HSMD_CANNOUNCEMENT_SIG_REQ);
+                w.var_bytes(announcement);
+                w
+            }
+            Message::CannouncementSigReply { node_signature, bitcoin_signature } => {
+                let mut w = Writer::new(HSMD_CANNOUNCEMENT_SIG_REPLY);
+                w.signature(node_signature);
+                w.signature(bitcoin_signature);
+                w
+            }
+            Message::CupdateSig { update } => {
+                let mut w = Writer::new(HSMD_CUPDATE_SIG_REQ);
+                w.var_bytes(update);
+                w
+            }
+            Message::CupdateSigReply { update } => {
+                let mut w = Writer::new(HSMD_CUPDATE_SIG_REPLY);
+                w.var_bytes(update);
+                w
+            }
+            Message::NodeAnnouncementSig { announcement } => {
+                let mut w = Writer::new(HSMD_NODE_ANNOUNCEMENT_SIG_REQ);
+                w.var_bytes(announcement);
+                w
+            }
+            Message::NodeAnnouncementSigReply { signature } => {
+                let mut w = Writer::new(HSMD_NODE_ANNOUNCEMENT_SIG_REPLY);
+                w.signature(signature);
+                w
+            }
+            Message::SignCommitmentTx { peer_id, dbid, tx, remote_funding_key } => {
+                let mut w = Writer::new(HSMD_SIGN_COMMITMENT_TX);
+                w.pubkey(peer_id);
+                w.u64(*dbid);
+                w.tx(tx);
+                w.pubkey(remote_funding_key);
+                w
+            }
+            Message::SignCommitmentTxReply { signature } => {
+                let mut w = Writer::new(HSMD_SIGN_COMMITMENT_TX_REPLY);
+                w.bitcoin_signature(signature);
+                w
+            }
+            Message::SignInvoice { u5bytes, hrp } => {
+                let mut w = Writer::new(HSMD_SIGN_INVOICE);
+                w.var_bytes(u5bytes);
+                w.var_bytes(hrp);
+                w
+            }
+            Message::SignInvoiceReply { signature } => {
+                let mut w = Writer::new(HSMD_SIGN_INVOICE_REPLY);
+                w.recoverable_signature(signature);
+                w
+            }
+            Message::SignMessage { message } => {
+                let mut w = Writer::new(HSMD_SIGN_MESSAGE);
+                w.var_bytes(message);
+                w
+            }
+            Message::SignMessageReply { signature } => {
+                let mut w = Writer::new(HSMD_SIGN_MESSAGE_REPLY);
+                w.recoverable_signature(signature);
+                w
+            }
+            Message::GetPerCommitmentPoint { n } => {
+                let mut w = Writer::new(HSMD_GET_PER_COMMITMENT_POINT);
+                w.u64(*n);
+                w
+            }
+            Message::GetPerCommitmentPointReply { point, old_secret } => {
+                let mut w = Writer::new(HSMD_GET_PER_COMMITMENT_POINT_REPLY);
+                w.pubkey(point);
+                w.optional_secret(old_secret);
+                w
+            }
+            Message::CheckFutureSecret { n, secret } => {
+                let mut w = Writer::new(HSMD_CHECK_FUTURE_SECRET);
+                w.u64(*n);
+                w.bytes(secret);
+                w
+            }
+            Message::CheckFutureSecretReply { correct } => {
+                let mut w = Writer::new(HSMD_CHECK_FUTURE_SECRET_REPLY);
+                w.bool(*correct);
+                w
+            }
+            Message::ReadyChannel {
+                is_outbound,
+                channel_value_sat,
+                push_value_msat,
+                funding_txid,
+                funding_txout,
+                local_to_self_delay,
+                local_shutdown_script,
+                remote_basepoints,
+                remote_funding_pubkey,
+                remote_to_self_delay,
+                remote_shutdown_script,
+                option_static_remotekey,
+                option_anchor_outputs,
+            } => {
+                let mut w = Writer::new(HSMD_READY_CHANNEL);
+                w.bool(*is_outbound);
+                w.u64(*channel_value_sat);
+                w.u64(*push_value_msat);
+                w.bytes(&funding_txid[..]);
+                w.u16(*funding_txout);
+                w.u16(*local_to_self_delay);
+                w.var_bytes(local_shutdown_script);
+                w.basepoints(remote_basepoints);
+                w.pubkey(remote_funding_pubkey);
+                w.u16(*remote_to_self_delay);
+                w.var_bytes(remote_shutdown_script);
+                w.bool(*option_static_remotekey);
+                w.bool(*option_anchor_outputs);
+                w
+            }
+            Message::ReadyChannelReply => Writer::new(HSMD_READY_CHANNEL_REPLY),
+            Message::SignRemoteCommitmentTx {
+                tx,
+                remote_funding_key,
+                remote_per_commit,
+                option_static_remotekey,
+                commit_num,
+                htlcs,
+                feerate_per_kw,
+            } => {
+                let mut w = Writer::new(HSMD_SIGN_REMOTE_COMMITMENT_TX);
+                w.tx(tx);
+                w.pubkey(remote_funding_key);
+                w.pubkey(remote_per_commit);
+                w.bool(*option_static_remotekey);
+                w.u64(*commit_num);
+                w.htlcs(htlcs);
+                w.u32(*feerate_per_kw);
+                w
+            }
+            Message::SignMutualCloseTx { tx, remote_funding_key } => {
+                let mut w = Writer::new(HSMD_SIGN_MUTUAL_CLOSE_TX);
+                w.tx(tx);
+                w.pubkey(remote_funding_key);
+                w
+            }
+            Message::SignTxReply { signature } => {
+                let mut w = Writer::new(HSMD_SIGN_TX_REPLY);
+                w.bitcoin_signature(signature);
+                w
+            }
+            Message::ValidateCommitmentTx {
+                tx,
+                htlcs,
+                commit_num,
+                feerate_per_kw,
+                signature,
+                htlc_signatures,
+            } => {
+                let mut w = Writer::new(HSMD_VALIDATE_COMMITMENT_TX);
+                w.tx(tx);
+                w.htlcs(htlcs);
+                w.u64(*commit_num);
+                w.u32(*feerate_per_kw);
+                w.bitcoin_signature(signature);
+                w.u16(htlc_signatures.len() as u16);
+                for sig in htlc_signatures {
+                    w.bitcoin_signature(sig);
+                }
+                w
+            }
+            Message::ValidateCommitmentTxReply { old_secret, next_point } => {
+                let mut w = Writer::new(HSMD_VALIDATE_COMMITMENT_TX_REPLY);
+                w.optional_secret(old_secret);
+                w.pubkey(next_point);
+                w
+            }
+            Message::ValidateRevocation { commit_num, secret } => {
+                let mut w = Writer::new(HSMD_VALIDATE_REVOCATION);
+                w.u64(*commit_num);
+                w.bytes(secret);
+                w
+            }
+            Message::ValidateRevocationReply => Writer::new(HSMD_VALIDATE_REVOCATION_REPLY),
+        };
+        w.out
+    }
+
+    /// Deserialize a message read by [read_message]
+    pub fn decode(data: &[u8]) -> io::Result<Self> {
+        let mut r = Reader::new(data);
+        let msg_type = r.u16()?;
+        let message = match msg_type {
+            HSMD_INIT => {
+                let bip32_key_version = (r.u32()?, r.u32()?);
+                let chain_hash = BlockHash::from_slice(&r.bytes(32)?).expect("32 bytes");
+                let hsm_encryption_key = r.option(|r| r.array())?;
+                let dev_privkey = r.option(|r| r.bytes(32))?;
+                let dev_bip32_seed = r.option(|r| r.bytes(32))?;
+                let dev_channel_secrets = r.option(|r| r.bytes(5 * 32))?;
+                let dev_shaseed = r.option(|r| r.bytes(32))?;
+                let dev_overrides = dev_privkey.is_some()
+                    || dev_bip32_seed.is_some()
+                    || dev_channel_secrets.is_some()
+                    || dev_shaseed.is_some();
+                Message::Init { bip32_key_version, chain_hash, hsm_encryption_key, dev_overrides }
+            }
+            HSMD_INIT_REPLY =>
+                Message::InitReply { node_id: r.pubkey()?, bip32: r.array()?, bolt12: r.array()? },
+            HSMD_CLIENT_HSMFD => Message::ClientHsmfd {
+                peer_id: r.pubkey()?,
+                dbid: r.u64()?,
+                capabilities: r.u64()?,
+            },
+            HSMD_CLIENT_HSMFD_REPLY => Message::ClientHsmfdReply,
+            HSMD_GET_CHANNEL_BASEPOINTS =>
+                Message::GetChannelBasepoints { peer_id: r.pubkey()?, dbid: r.u64()? },
+            HSMD_GET_CHANNEL_BASEPOINTS_REPLY => Message::GetChannelBasepointsReply {
+                basepoints: r.basepoints()?,
+                funding_pubkey: r.pubkey()?,
+            },
+            HSMD_ECDH_REQ => Message::Ecdh { point: r.pubkey()? },
+            HSMD_ECDH_RESP => Message::EcdhReply { shared_secret: r.array()? },
+            HSMD_CANNOUNCEMENT_SIG_REQ =>
+                Message::CannouncementSig { announcement: r.var_bytes()? },
+            HSMD_CANNOUNCEMENT_SIG_REPLY => Message::CannouncementSigReply {
+                node_signature: r.signature()?,
+                bitcoin_signature: r.signature()?,
+            },
+            HSMD_CUPDATE_SIG_REQ => Message::CupdateSig { update: r.var_bytes()? },
+            HSMD_CUPDATE_SIG_REPLY => Message::CupdateSigReply { update: r.var_bytes()? },
+            HSMD_NODE_ANNOUNCEMENT_SIG_REQ =>
+                Message::NodeAnnouncementSig { announcement: r.var_bytes()? },
+            HSMD_NODE_ANNOUNCEMENT_SIG_REPLY =>
+                Message::NodeAnnouncementSigReply { signature: r.signature()? },
+            HSMD_SIGN_COMMITMENT_TX => Message::SignCommitmentTx {
+                peer_id: r.pubkey()?,
+                dbid: r.u64()?,
+                tx: r.tx()?,
+                remote_funding_key: r.pubkey()?,
+            },
+            HSMD_SIGN_COMMITMENT_TX_REPLY =>
+                Message::SignCommitmentTxReply { signature: r.bitcoin_signature()? },
+            HSMD_SIGN_INVOICE =>
+                Message::SignInvoice { u5bytes: r.var_bytes()?, hrp: r.var_bytes()? },
+            HSMD_SIGN_INVOICE_REPLY =>
+                Message::SignInvoiceReply { signature: r.recoverable_signature()? },
+            HSMD_SIGN_MESSAGE => Message::SignMessage { message: r.var_bytes()? },
+            HSMD_SIGN_MESSAGE_REPLY =>
+                Message::SignMessageReply { signature: r.recoverable_signature()? },
+            HSMD_GET_PER_COMMITMENT_POINT => Message::GetPerCommitmentPoint { n: r.u64()? },
+            HSMD_GET_PER_COMMITMENT_POINT_REPLY => Message::GetPerCommitmentPointReply {
+                point: r.pubkey()?,
+                old_secret: r.option(|r| r.array())?,
+            },
+            HSMD_CHECK_FUTURE_SECRET =>
+                Message::CheckFutureSecret { n: r.u64()?, secret: r.array()? },
+            HSMD_CHECK_FUTURE_SECRET_REPLY =>
+                Message::CheckFutureSecretReply { correct: r.bool()? },
+            HSMD_READY_CHANNEL => Message::ReadyChannel {
+                is_outbound: r.bool()?,
+                channel_value_sat: r.u64()?,
+                push_value_msat: r.u64()?,
+                funding_txid: Txid::from_slice(&r.bytes(32)?).expect("32 bytes"),
+                funding_txout: r.u16()?,
+                local_to_self_delay: r.u16()?,
+                local_shutdown_script: r.var_bytes()?,
+                remote_basepoints: r.basepoints()?,
+                remote_funding_pubkey: r.pubkey()?,
+                remote_to_self_delay: r.u16()?,
+                remote_shutdown_script: r.var_bytes()?,
+                option_static_remotekey: r.bool()?,
+                option_anchor_outputs: r.bool()?,
+            },
+            HSMD_READY_CHANNEL_REPLY => Message::ReadyChannelReply,
+            HSMD_SIGN_REMOTE_COMMITMENT_TX => Message::SignRemoteCommitmentTx {
+                tx: r.tx()?,
+                remote_funding_key: r.pubkey()?,
+                remote_per_commit: r.pubkey()?,
+                option_static_remotekey: r.bool()?,
+                commit_num: r.u64()?,
+                htlcs: r.htlcs()?,
+                feerate_per_kw: r.u32()?,
+            },
+            HSMD_SIGN_MUTUAL_CLOSE_TX =>
+                Message::SignMutualCloseTx { tx: r.tx()?, remote_funding_key: r.pubkey()? },
+            HSMD_SIGN_TX_REPLY => Message::SignTxReply { signature: r.bitcoin_signature()? },
+            HSMD_VALIDATE_COMMITMENT_TX => Message::ValidateCommitmentTx {
+                tx: r.tx()?,
+                htlcs: r.htlcs()?,
+                commit_num: r.u64()?,
+                feerate_per_kw: r.u32()?,
+                signature: r.bitcoin_signature()?,
+                htlc_signatures: {
+                    let count = r.u16()?;
+                    (0..count).map(|_| r.bitcoin_signature()).collect::<io::Result<_>>()?
+                },
+            },
+            HSMD_VALIDATE_COMMITMENT_TX_REPLY => Message::ValidateCommitmentTxReply {
+                old_secret: r.option(|r| r.array())?,
+                next_point: r.pubkey()?,
+            },
+            HSMD_VALIDATE_REVOCATION =>
+                Message::ValidateRevocation { commit_num: r.u64()?, secret: r.array()? },
+            HSMD_VALIDATE_REVOCATION_REPLY => Message::ValidateRevocationReply,
+            t => return Err(invalid(&format!("unknown message type {}", t))),
+        };
+        Ok(message)
+    }
+
+    /// The message type
+    pub fn msg_type(&self) -> u16 {
+        let data = self.encode();
+        u16::from_be_bytes([data[0], data[1]])
+    }
+}
+
+/// Read a message with its length, as CLN's `wire_sync_read` does
+pub fn read_message<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(invalid(&format!("message of {} bytes over {}", len, MAX_MESSAGE_LEN)));
+    }
+    let mut data = vec![0u8; len];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Write a message with its length, as CLN's `wire_sync_write` does
+pub fn write_message<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(data)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning_signer::bitcoin::blockdata::constants::genesis_block;
+    use lightning_signer::bitcoin::secp256k1::{Message as SecpMessage, Secp256k1};
+    use lightning_signer::bitcoin::{Network, OutPoint, Script, TxIn, TxOut};
+    use lightning_signer::util::key_utils::{make_test_key, make_test_pubkey};
+    use test_log::test;
+
+    use super::*;
+
+    fn make_basepoints() -> Basepoints {
+        Basepoints {
+            revocation: make_test_pubkey(1),
+            payment: make_test_pubkey(2),
+            htlc: make_test_pubkey(3),
+            delayed_payment: make_test_pubkey(4),
+        }
+    }
+
+    fn make_signature() -> BitcoinSignature {
+        let (_, key) = make_test_key(5);
+        let message = SecpMessage::from_slice(&[7u8; 32]).unwrap();
+        BitcoinSignature { signature: Secp256k1::new().sign(&message, &key), sighash_type: 1 }
+    }
+
+    fn make_tx() -> WireTx {
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0x20_000_001,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: Txid::from_slice(&[2u8; 32]).unwrap(), vout: 0 },
+                script_sig: Script::new(),
+                sequence: 0x80_000_002,
+                witness: vec![],
+            }],
+            output: vec![
+                TxOut { value: 1000, script_pubkey: Script::from(vec![0u8; 34]) },
+                TxOut { value: 2000, script_pubkey: Script::from(vec![1u8; 22]) },
+            ],
+        };
+        let mut tx = WireTx::new(tx);
+        tx.psbt.outputs[0].witness_script = Some(Script::from(vec![0x51]));
+        tx
+    }
+
+    fn make_htlcs() -> Vec<SimpleHtlc> {
+        vec![
+            SimpleHtlc {
+                side: SIDE_LOCAL,
+                amount_msat: 5_000_000,
+                payment_hash: [8u8; 32],
+                cltv_expiry: 500,
+            },
+            SimpleHtlc {
+                side: SIDE_REMOTE,
+                amount_msat: 6_000_000,
+                payment_hash: [9u8; 32],
+                cltv_expiry: 600,
+            },
+        ]
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let messages = vec![
+            Message::Init {
+                bip32_key_version: (0x043587CF, 0x04358394),
+                chain_hash: genesis_block(Network::Regtest).block_hash(),
+                hsm_encryption_key: None,
+                dev_overrides: false,
+            },
+            Message::ClientHsmfd { peer_id: make_test_pubkey(1), dbid: 7, capabilities: 8 | 16 },
+            Message::GetChannelBasepointsReply {
+                basepoints: make_basepoints(),
+                funding_pubkey: make_test_pubkey(5),
+            },
+            Message::GetPerCommitmentPointReply {
+                point: make_test_pubkey(6),
+                old_secret: Some([3u8; 32]),
+            },
+            Message::CupdateSig { update: vec![1, 2, 3] },
+            Message::ReadyChannel {
+                is_outbound: true,
+                channel_value_sat: 3_000_000,
+                push_value_msat: 1000,
+                funding_txid: Txid::from_slice(&[2u8; 32]).unwrap(),
+                funding_txout: 1,
+                local_to_self_delay: 6,
+                local_shutdown_script: vec![],
+                remote_basepoints: make_basepoints(),
+                remote_funding_pubkey: make_test_pubkey(5),
+                remote_to_self_delay: 7,
+                remote_shutdown_script: vec![0u8; 22],
+                option_static_remotekey: true,
+                option_anchor_outputs: false,
+            },
+            Message::SignRemoteCommitmentTx {
+                tx: make_tx(),
+                remote_funding_key: make_test_pubkey(5),
+                remote_per_commit: make_test_pubkey(6),
+                option_static_remotekey: true,
+                commit_num: 3,
+                htlcs: make_htlcs(),
+                feerate_per_kw: 253,
+            },
+            Message::ValidateCommitmentTx {
+                tx: make_tx(),
+                htlcs: make_htlcs(),
+                commit_num: 4,
+                feerate_per_kw: 253,
+                signature: make_signature(),
+                htlc_signatures: vec![make_signature(), make_signature()],
+            },
+            Message::SignTxReply { signature: make_signature() },
+            Message::ValidateRevocation { commit_num: 2, secret: [4u8; 32] },
+            Message::ReadyChannelReply,
+        ];
+        for message in messages {
+            assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn output_witscripts_test() {
+        assert_eq!(make_tx().output_witscripts(), vec![vec![0x51], vec![]]);
+    }
+
+    #[test]
+    fn decode_test() {
+        let message = Message::Ecdh { point: make_test_pubkey(1) };
+        let mut data = message.encode();
+        // fields added later are ignored
+        data.push(42);
+        assert_eq!(Message::decode(&data).unwrap(), message);
+        // a truncated message is not
+        assert!(Message::decode(&data[..20]).is_err());
+        assert!(Message::decode(&[0xFF, 0xFF]).is_err());
+    }
+
+    #[test]
+    fn init_dev_overrides_test() {
+        let message = Message::Init {
+            bip32_key_version: (1, 2),
+            chain_hash: genesis_block(Network::Regtest).block_hash(),
+            hsm_encryption_key: None,
+            dev_overrides: false,
+        };
+        let mut data = message.encode();
+        // set dev_force_privkey
+        let len = data.len();
+        data.truncate(len - 4);
+        data.push(1);
+        data.extend_from_slice(&[5u8; 32]);
+        data.extend_from_slice(&[0, 0, 0]);
+        match Message::decode(&data).unwrap() {
+            Message::Init { dev_overrides, .. } => assert!(dev_overrides),
+            m => panic!("unexpected {:?}", m),
+        }
+    }
+
+    #[test]
+    fn framing_test() {
+        let message = Message::GetPerCommitmentPoint { n: 3 }.encode();
+        let mut stream = vec![];
+        write_message(&mut stream, &message).unwrap();
+        assert_eq!(&stream[..4], &[0, 0, 0, 10]);
+        let mut reader = stream.as_slice();
+        assert_eq!(read_message(&mut reader).unwrap(), message);
+        assert_eq!(read_message(&mut reader).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let too_long = ((MAX_MESSAGE_LEN + 1) as u32).to_be_bytes();
+        assert_eq!(
+            read_message(&mut too_long.as_ref()).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! A drop-in replacement for c-lightning's `hsmd`.
+//!
+//! Install it as `lightning_hsmd` in CLN's subdaemon directory, or point
+//! lightningd at it with `--subdaemon=hsmd:/path/to/vls-hsmd`.  lightningd
+//! starts it in the network directory, where it reads the `hsm_secret`,
+//! creating it on the first start as CLN does.
+
+use std::env;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use lightning_signer::policy::simple_validator::SimpleValidatorFactory;
+use lightning_signer::signer::multi_signer::MultiSigner;
+use lightning_signer_server::cln_import;
+use lightning_signer_server::hsmd::handler;
+use lightning_signer_server::hsmd::wire::{self, Message};
+use lightning_signer_server::persist::persist_json::KVJsonPersister;
+use lightning_signer_server::util::write_private_file;
+use log::info;
+use rand::{OsRng, Rng};
+
+/// The signer data directory, lightningd passes its own arguments
+const DATADIR_ENV: &str = "VLS_DATADIR";
+const DEFAULT_DATADIR: &str = ".lightning-signer";
+/// The version to report, lightningd refuses a subdaemon of another version
+const VERSION_ENV: &str = "VLS_CLN_VERSION";
+/// lightningd passes the master connection as this file descriptor
+const MASTER_FD: i32 = 3;
+
+fn load_or_create_hsm_secret(path: &Path) -> anyhow::Result<[u8; 32]> {
+    if !path.exists() {
+        let mut seed = [0u8; 32];
+        OsRng::new()?.fill_bytes(&mut seed);
+        write_private_file(path, &seed)?;
+        info!("created {}", path.display());
+    }
+    Ok(cln_import::read_hsm_secret(path)?)
+}
+
+pub fn main() -> anyhow::Result<()> {
+    if env::args().any(|arg| arg == "--version") {
+        let version =
+            env::var(VERSION_ENV).unwrap_or_else(|_| env!("CARGO_PKG_VERSION").to_string());
+        println!("{}", version);
+        return Ok(());
+    }
+    env_logger::init();
+
+    // the master connection is only used by this process
+    let mut stream = unsafe { UnixStream::from_raw_fd(MASTER_FD) };
+    let request = Message::decode(&wire::read_message(&mut stream)?)?;
+    let network = match &request {
+        Message::Init { chain_hash, .. } => handler::network_for(chain_hash),
+        _ => return Err(anyhow!("expected init, got message type {}", request.msg_type())),
+    }
+    .ok_or_else(|| anyhow!("unknown chain"))?;
+
+    let seed = load_or_create_hsm_secret(Path::new("hsm_secret"))?;
+    let datadir = env::var(DATADIR_ENV).unwrap_or_else(|_| DEFAULT_DATADIR.to_string());
+    // the same layout as cln_import, so that an imported node is found
+    let data_path: PathBuf = [&datadir, &network.to_string()].iter().collect();
+    let persister = Arc::new(KVJsonPersister::new(data_path)?);
+    let validator_factory = Arc::new(SimpleValidatorFactory::new());
+    let signer = MultiSigner::new_with_persister(persister, false, vec![], validator_factory);
+
+    let (master, reply) = handler::init(&signer, &seed, &request)?;
+    info!("serving node {} on {}", master.context().peer_id, network);
+    wire::write_message(&mut stream, &reply.encode())?;
+    handler::serve(stream, master)?;
+    Ok(())
+}
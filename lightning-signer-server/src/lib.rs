@@ -10,7 +10,11 @@ extern crate tonic;
 
 use lightning_signer::lightning;
 
+#[cfg(feature = "cln_import")]
+pub mod cln_import;
 pub mod fslogger;
+#[cfg(feature = "hsmd")]
+pub mod hsmd;
 pub mod persist;
 pub mod util;
 #[macro_use]
@@ -0,0 +1,203 @@
+//! Encrypted backup archives of the signer state.
+//!
+//! An archive holds the persisted entries of each node, its channels, its
+//! allowlist, its fee reserve, its payment ledger and its chain tracker, so
+//! that it can be imported by any persister.  The entries are serialized as JSON and encrypted with
+//! ChaCha20-Poly1305, with a key derived from the operator's passphrase.
+//!
+//! The archive starts with a header, which is authenticated but not
+//! encrypted:
+//!
+//! * the magic bytes `VLSB`
+//! * the archive version, a big-endian u32
+//! * the number of key derivation rounds, a big-endian u32
+//! * a random 16 byte salt
+//! * a random 12 byte nonce
+
+use std::convert::TryInto;
+
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::{OsRng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use lightning_signer::channel::ChannelId;
+use lightning_signer::persist::model::PaymentLedgerEntry;
+use lightning_signer::persist::Error;
+
+use super::model::{ChainTrackerEntry, ChannelEntry, NodeEntry};
+use super::ser_util::{ChannelIdHandler, OutPointDef, PaymentLedgerEntryDef, PublicKeyHandler};
+
+/// The current archive version
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const MAGIC: &[u8] = b"VLSB";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 4 + 4 + SALT_LEN + NONCE_LEN;
+
+// Rounds of PBKDF2 to derive the key from the passphrase.  Lowered in
+// tests, which are not optimized.
+#[cfg(not(test))]
+const KDF_ROUNDS: u32 = 100_000;
+#[cfg(test)]
+const KDF_ROUNDS: u32 = 16;
+
+/// A channel in an archive
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+pub struct ChannelArchive {
+    #[serde_as(as = "ChannelIdHandler")]
+    pub channel_id: ChannelId,
+    pub entry: ChannelEntry,
+}
+
+/// A node in an archive, with its channels, allowlist, fee reserve,
+/// payment ledger and chain tracker
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+pub struct NodeArchive {
+    #[serde_as(as = "PublicKeyHandler")]
+    pub node_id: PublicKey,
+    pub entry: NodeEntry,
+    pub allowlist: Vec<String>,
+    // Added after the first release of the archive format
+    #[serde_as(as = "Vec<OutPointDef>")]
+    #[serde(default)]
+    pub fee_reserve: Vec<OutPoint>,
+    // Added after the first release of the archive format
+    #[serde_as(as = "Vec<PaymentLedgerEntryDef>")]
+    #[serde(default)]
+    pub payments: Vec<PaymentLedgerEntry>,
+    pub tracker: Option<ChainTrackerEntry>,
+    pub channels: Vec<ChannelArchive>,
+}
+
+/// The decrypted content of an archive
+#[derive(Serialize, Deserialize, Default)]
+pub struct Archive {
+    pub nodes: Vec<NodeArchive>,
+}
+
+impl Archive {
+    /// Serialize and encrypt the archive
+    pub fn seal(&self, passphrase: &str) -> Vec<u8> {
+        let mut rng = OsRng::new().unwrap();
+        let mut salt = [0u8; SALT_LEN];
+        rng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+
+        let mut res = MAGIC.to_vec();
+        res.extend_from_slice(&ARCHIVE_VERSION.to_be_bytes());
+        res.extend_from_slice(&KDF_ROUNDS.to_be_bytes());
+        res.extend_from_slice(&salt);
+        res.extend_from_slice(&nonce);
+        let plaintext = serde_json::to_vec(self).expect("serialize archive");
+        let payload = Payload { msg: &plaintext, aad: &res };
+        let ciphertext = cipher(passphrase, &salt, KDF_ROUNDS)
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .expect("encrypt");
+        res.extend(ciphertext);
+        res
+    }
+
+    /// Decrypt and deserialize an archive
+    pub fn open(passphrase: &str, archive: &[u8]) -> Result<Self, Error> {
+        if archive.len() < HEADER_LEN || &archive[..MAGIC.len()] != MAGIC {
+            return Err(Error::Corrupt("not a signer backup".to_string()));
+        }
+        let (header, ciphertext) = archive.split_at(HEADER_LEN);
+        let (version, rest) = header[MAGIC.len()..].split_at(4);
+        let (rounds, rest) = rest.split_at(4);
+        let (salt, nonce) = rest.split_at(SALT_LEN);
+        let version = u32::from_be_bytes(version.try_into().unwrap());
+        if version > ARCHIVE_VERSION {
+            return Err(Error::Corrupt(format!(
+                "backup version {} is newer than supported {}",
+                version, ARCHIVE_VERSION
+            )));
+        }
+        let rounds = u32::from_be_bytes(rounds.try_into().unwrap());
+        let payload = Payload { msg: ciphertext, aad: header };
+        let plaintext = cipher(passphrase, salt, rounds)
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| {
+                Error::Corrupt("backup could not be decrypted, check the passphrase".to_string())
+            })?;
+        let archive: Archive = serde_json::from_slice(&plaintext)
+            .map_err(|e| Error::Corrupt(format!("backup: {}", e)))?;
+        // Refuse a bad tracker before anything is imported
+        for node in archive.nodes.iter() {
+            if let Some(tracker) = node.tracker.as_ref() {
+                tracker.validate()?;
+            }
+        }
+        Ok(archive)
+    }
+}
+
+fn cipher(passphrase: &str, salt: &[u8], rounds: u32) -> ChaCha20Poly1305 {
+    let key = derive_key(passphrase, salt, rounds);
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+// PBKDF2 with HMAC-SHA256, whose first block is a whole 32 byte key
+fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> [u8; 32] {
+    let mut engine = HmacEngine::<Sha256Hash>::new(passphrase.as_bytes());
+    engine.input(salt);
+    engine.input(&1u32.to_be_bytes());
+    let mut block = Hmac::from_engine(engine).into_inner();
+    let mut key = block;
+    for _ in 1..rounds {
+        let mut engine = HmacEngine::<Sha256Hash>::new(passphrase.as_bytes());
+        engine.input(&block);
+        block = Hmac::from_engine(engine).into_inner();
+        for (k, b) in key.iter_mut().zip(block.iter()) {
+            *k ^= b;
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_key_test() {
+        // RFC 7914 PBKDF2-HMAC-SHA256 test vector, first 32 bytes
+        let key = derive_key("passwd", b"salt", 1);
+        assert_eq!(
+            hex::encode(key),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+    }
+
+    #[test]
+    fn seal_open_test() {
+        let sealed = Archive::default().seal("secret");
+        assert!(Archive::open("secret", &sealed).unwrap().nodes.is_empty());
+        assert_ne!(Archive::default().seal("secret"), sealed);
+
+        let err = |archive: &[u8]| match Archive::open("secret", archive) {
+            Err(Error::Corrupt(msg)) => msg,
+            _ => panic!("expected corrupt archive"),
+        };
+        assert!(Archive::open("wrong", &sealed).is_err());
+        assert_eq!(err(&sealed[..10]), "not a signer backup");
+        // the header is authenticated
+        let mut tampered = sealed.clone();
+        tampered[HEADER_LEN - 1] ^= 1;
+        assert!(err(&tampered).contains("could not be decrypted"));
+        let mut newer = sealed.clone();
+        newer[4..8].copy_from_slice(&(ARCHIVE_VERSION + 1).to_be_bytes());
+        assert!(err(&newer).contains("newer than supported"));
+    }
+}
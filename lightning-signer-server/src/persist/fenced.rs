@@ -0,0 +1,277 @@
+use std::sync::{Arc, Mutex};
+
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
+use lightning_signer::chain::tracker::ChainTracker;
+use lightning_signer::channel::{Channel, ChannelId, ChannelStub};
+use lightning_signer::close_plan::ClosePlan;
+use lightning_signer::monitor::ChainMonitor;
+use lightning_signer::node::NodeConfig;
+use lightning_signer::persist::{model, Error, Persist, PersistBatch};
+use log::{error, info};
+
+/// A persister wrapper that only writes while this signer holds the
+/// fencing token of a shared store, see [Persist::advance_fence].
+///
+/// A standby signer wraps the store it shares with its primary without
+/// taking the token, so that all of its writes are refused.  Once it takes
+/// the token, the writes of the old primary are refused, even if the old
+/// primary is still running.
+///
+/// The token is read before each write.  A write that races with the
+/// token being taken can still land, but the channel writes of the
+/// shared persisters are conditional on the channel state the signer last
+/// read, so the new primary cannot sign from stale channel state.
+pub struct FencedPersister {
+    inner: Arc<dyn Persist>,
+    token: Mutex<Option<u64>>,
+}
+
+impl FencedPersister {
+    /// Wrap a shared persister, without taking the fencing token
+    pub fn new(inner: Arc<dyn Persist>) -> Self {
+        FencedPersister { inner, token: Mutex::new(None) }
+    }
+
+    /// Take the next fencing token, fencing off any other signer
+    pub fn acquire(&self) -> Result<u64, Error> {
+        let mut token = self.token.lock().unwrap();
+        let current = self.inner.get_fence()?;
+        let next = self.inner.advance_fence(current)?;
+        info!("took fencing token {}", next);
+        *token = Some(next);
+        Ok(next)
+    }
+
+    /// The fencing token held by this signer, if any
+    pub fn token(&self) -> Option<u64> {
+        *self.token.lock().unwrap()
+    }
+
+    /// Check that this signer still holds the current fencing token
+    pub fn check(&self) -> Result<(), Error> {
+        let token = self.token().ok_or_else(|| Error::Refused("standby".to_string()))?;
+        let current = self.inner.get_fence()?;
+        if current != token {
+            error!("fenced: another signer took fencing token {}, ours is {}", current, token);
+            return Err(Error::Refused(format!("fenced by token {}", current)));
+        }
+        Ok(())
+    }
+}
+
+impl Persist for FencedPersister {
+    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]) -> Result<(), Error> {
+        self.check()?;
+        self.inner.new_node(node_id, config, seed)
+    }
+
+    fn delete_node(&self, node_id: &PublicKey) -> Result<(), Error> {
+        self.check()?;
+        self.inner.delete_node(node_id)
+    }
+
+    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), Error> {
+        self.check()?;
+        self.inner.new_channel(node_id, stub)
+    }
+
+    fn new_chain_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        self.check()?;
+        self.inner.new_chain_tracker(node_id, tracker)
+    }
+
+    fn update_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        self.check()?;
+        self.inner.update_tracker(node_id, tracker)
+    }
+
+    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, Error> {
+        self.inner.get_tracker(node_id)
+    }
+
+    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), Error> {
+        self.check()?;
+        self.inner.update_channel(node_id, channel)
+    }
+
+    fn update_batch(&self, node_id: &PublicKey, batch: &PersistBatch) -> Result<(), Error> {
+        self.check()?;
+        self.inner.update_batch(node_id, batch)
+    }
+
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), Error> {
+        self.check()?;
+        self.inner.delete_channel(node_id, id0)
+    }
+
+    fn get_channel(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+    ) -> Result<model::ChannelEntry, Error> {
+        self.inner.get_channel(node_id, channel_id)
+    }
+
+    fn get_node_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Result<Vec<(ChannelId, model::ChannelEntry)>, Error> {
+        self.inner.get_node_channels(node_id)
+    }
+
+    fn update_node_allowlist(
+        &self,
+        node_id: &PublicKey,
+        allowlist: Vec<String>,
+    ) -> Result<(), Error> {
+        self.check()?;
+        self.inner.update_node_allowlist(node_id, allowlist)
+    }
+
+    fn get_node_allowlist(&self, node_id: &PublicKey) -> Result<Vec<String>, Error> {
+        self.inner.get_node_allowlist(node_id)
+    }
+
+    fn update_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+        metadata: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        self.check()?;
+        self.inner.update_metadata(node_id, channel_id, metadata)
+    }
+
+    fn get_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        self.inner.get_metadata(node_id, channel_id)
+    }
+
+    fn get_nodes(&self) -> Result<Vec<(PublicKey, model::NodeEntry)>, Error> {
+        self.inner.get_nodes()
+    }
+
+    fn export_since(&self, sequence: u64) -> Result<Vec<model::ChangeRecord>, Error> {
+        self.inner.export_since(sequence)
+    }
+
+    fn append_audit_record(
+        &self,
+        node_id: &PublicKey,
+        record: &model::AuditRecord,
+    ) -> Result<(), Error> {
+        self.check()?;
+        self.inner.append_audit_record(node_id, record)
+    }
+
+    fn get_audit_log(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<model::AuditRecord>, Error> {
+        self.inner.get_audit_log(node_id, channel_id)
+    }
+
+    fn update_close_plan(
+        &self,
+        node_id: &PublicKey,
+        plan: Option<&ClosePlan>,
+    ) -> Result<(), Error> {
+        self.check()?;
+        self.inner.update_close_plan(node_id, plan)
+    }
+
+    fn get_close_plan(&self, node_id: &PublicKey) -> Result<Option<ClosePlan>, Error> {
+        self.inner.get_close_plan(node_id)
+    }
+
+    fn update_fee_reserve(&self, node_id: &PublicKey, reserve: Vec<OutPoint>) -> Result<(), Error> {
+        self.check()?;
+        self.inner.update_fee_reserve(node_id, reserve)
+    }
+
+    fn get_fee_reserve(&self, node_id: &PublicKey) -> Result<Vec<OutPoint>, Error> {
+        self.inner.get_fee_reserve(node_id)
+    }
+
+    fn update_payment(
+        &self,
+        node_id: &PublicKey,
+        entry: &model::PaymentLedgerEntry,
+    ) -> Result<(), Error> {
+        self.check()?;
+        self.inner.update_payment(node_id, entry)
+    }
+
+    fn get_payments(&self, node_id: &PublicKey) -> Result<Vec<model::PaymentLedgerEntry>, Error> {
+        self.inner.get_payments(node_id)
+    }
+
+    fn export_all(&self, passphrase: &str) -> Result<Vec<u8>, Error> {
+        self.inner.export_all(passphrase)
+    }
+
+    fn import_all(&self, passphrase: &str, archive: &[u8]) -> Result<Vec<PublicKey>, Error> {
+        self.check()?;
+        self.inner.import_all(passphrase, archive)
+    }
+
+    fn get_fence(&self) -> Result<u64, Error> {
+        self.inner.get_fence()
+    }
+
+    fn advance_fence(&self, current: u64) -> Result<u64, Error> {
+        self.inner.advance_fence(current)
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+
+    fn clear_database(&self) -> Result<(), Error> {
+        self.check()?;
+        self.inner.clear_database()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning_signer::persist::memory::MemoryPersister;
+    use lightning_signer::util::test_utils::make_dummy_pubkey;
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn fenced_test() {
+        let store: Arc<dyn Persist> = Arc::new(MemoryPersister::new());
+        let primary = FencedPersister::new(Arc::clone(&store));
+        let standby = FencedPersister::new(Arc::clone(&store));
+        let node_id = make_dummy_pubkey(0x12);
+        let allowlist = vec!["addr".to_string()];
+
+        assert_eq!(primary.acquire().unwrap(), 1);
+        primary.update_node_allowlist(&node_id, allowlist.clone()).unwrap();
+        // the standby reads, but doesn't write
+        assert_eq!(standby.get_node_allowlist(&node_id).unwrap(), allowlist);
+        assert!(matches!(standby.update_node_allowlist(&node_id, vec![]), Err(Error::Refused(_))));
+
+        // once the standby takes over, the old primary is fenced off
+        assert_eq!(standby.acquire().unwrap(), 2);
+        assert!(matches!(primary.update_node_allowlist(&node_id, vec![]), Err(Error::Refused(_))));
+        standby.update_node_allowlist(&node_id, vec![]).unwrap();
+        assert!(store.get_node_allowlist(&node_id).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,520 @@
+use std::collections::{BTreeMap as OrderedMap, BTreeSet as OrderedSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
+use kv::Json;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use serde_with::hex::Hex;
+use serde_with::serde_as;
+
+use lightning_signer::chain::tracker::ChainTracker;
+use lightning_signer::channel::{Channel, ChannelId, ChannelSetup, ChannelStub};
+use lightning_signer::close_plan::ClosePlan;
+use lightning_signer::monitor::ChainMonitor;
+use lightning_signer::node::NodeConfig;
+use lightning_signer::persist::{model, Error, Persist, PersistBatch};
+use lightning_signer::policy::validator::EnforcementState;
+
+use crate::persist::model::{ChannelEntry, NodeChannelId};
+use crate::persist::persist_json::KVJsonPersister;
+use crate::persist::ser_util::{
+    ChannelIdHandler, ChannelSetupDef, EnforcementStateDef, PublicKeyHandler,
+};
+use crate::persist::versioned::Versioned;
+
+/// The name of the journal file in the data directory
+pub const JOURNAL_FILE_NAME: &str = "journal.jsonl";
+
+type JournalKey = ([u8; 33], ChannelId);
+
+/// A journal record, one JSON object per line
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalRecord {
+    /// A channel became ready, or was first seen since journaling was enabled
+    Ready {
+        #[serde_as(as = "PublicKeyHandler")]
+        node_id: PublicKey,
+        #[serde_as(as = "ChannelIdHandler")]
+        id0: ChannelId,
+        #[serde_as(as = "Option<ChannelIdHandler>")]
+        id: Option<ChannelId>,
+        #[serde_as(as = "Hex")]
+        nonce: Vec<u8>,
+        #[serde_as(as = "ChannelSetupDef")]
+        setup: ChannelSetup,
+        #[serde_as(as = "EnforcementStateDef")]
+        enforcement_state: EnforcementState,
+    },
+    /// The enforcement state of a ready channel changed, such as on a
+    /// commitment advance
+    Advance {
+        #[serde_as(as = "PublicKeyHandler")]
+        node_id: PublicKey,
+        #[serde_as(as = "ChannelIdHandler")]
+        id0: ChannelId,
+        #[serde_as(as = "EnforcementStateDef")]
+        enforcement_state: EnforcementState,
+    },
+    /// A channel was deleted
+    Delete {
+        #[serde_as(as = "PublicKeyHandler")]
+        node_id: PublicKey,
+        #[serde_as(as = "ChannelIdHandler")]
+        id0: ChannelId,
+    },
+}
+
+/// A persister wrapper that appends every ready channel and channel state
+/// change to an append-only journal, before passing it through.
+///
+/// The journal can be replayed with [replay] to reconstruct the channel
+/// database if it is lost or corrupted, as long as the journal and the
+/// node seeds survive.  Only channels updated since journaling was
+/// enabled are in the journal.
+pub struct JournalingPersister {
+    inner: Arc<dyn Persist>,
+    journal: Mutex<Journal>,
+}
+
+struct Journal {
+    path: PathBuf,
+    file: File,
+    // Channels that have a Ready record
+    ready: OrderedSet<JournalKey>,
+}
+
+impl JournalingPersister {
+    /// Wrap a persister, appending to the journal in the data directory
+    pub fn new(inner: Arc<dyn Persist>, data_path: &Path) -> io::Result<Self> {
+        let path = data_path.join(JOURNAL_FILE_NAME);
+        let (records, valid_len) = read_records(&path)?;
+        let ready = apply(&path, records)
+            .into_iter()
+            .map(|(node_id, id0, _)| (node_id.serialize(), id0))
+            .collect();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        // Drop a truncated last record, so that the next record starts on
+        // a new line
+        if file.metadata()?.len() > valid_len {
+            file.set_len(valid_len)?;
+        }
+        Ok(JournalingPersister { inner, journal: Mutex::new(Journal { path, file, ready }) })
+    }
+
+    // Journal a channel update, before it is written to the store
+    fn journal_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), Error> {
+        let mut journal = self.journal.lock().unwrap();
+        let key = (node_id.serialize(), channel.id0);
+        let record = if journal.ready.contains(&key) {
+            JournalRecord::Advance {
+                node_id: *node_id,
+                id0: channel.id0,
+                enforcement_state: channel.enforcement_state.clone(),
+            }
+        } else {
+            JournalRecord::Ready {
+                node_id: *node_id,
+                id0: channel.id0,
+                id: channel.id,
+                nonce: channel.nonce.clone(),
+                setup: channel.setup.clone(),
+                enforcement_state: channel.enforcement_state.clone(),
+            }
+        };
+        journal.append(&record)?;
+        journal.ready.insert(key);
+        Ok(())
+    }
+}
+
+impl Journal {
+    fn append(&mut self, record: &JournalRecord) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(record).expect("journal record");
+        line.push(b'\n');
+        self.file.write_all(&line).and_then(|_| self.file.sync_data()).map_err(|e| {
+            error!("journal {}: {}", self.path.display(), e);
+            Error::Unavailable(format!("journal: {}", e))
+        })
+    }
+
+    fn delete(&mut self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), Error> {
+        self.ready.remove(&(node_id.serialize(), *id0));
+        self.append(&JournalRecord::Delete { node_id: *node_id, id0: *id0 })
+    }
+}
+
+impl Persist for JournalingPersister {
+    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]) -> Result<(), Error> {
+        self.inner.new_node(node_id, config, seed)
+    }
+
+    fn delete_node(&self, node_id: &PublicKey) -> Result<(), Error> {
+        {
+            let mut journal = self.journal.lock().unwrap();
+            for (id0, _) in self.inner.get_node_channels(node_id)? {
+                let _ = journal.delete(node_id, &id0);
+            }
+        }
+        self.inner.delete_node(node_id)
+    }
+
+    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), Error> {
+        self.inner.new_channel(node_id, stub)
+    }
+
+    fn new_chain_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        self.inner.new_chain_tracker(node_id, tracker)
+    }
+
+    fn update_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        self.inner.update_tracker(node_id, tracker)
+    }
+
+    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, Error> {
+        self.inner.get_tracker(node_id)
+    }
+
+    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), Error> {
+        self.journal_channel(node_id, channel)?;
+        self.inner.update_channel(node_id, channel)
+    }
+
+    fn update_batch(&self, node_id: &PublicKey, batch: &PersistBatch) -> Result<(), Error> {
+        for channel in batch.channels.iter() {
+            self.journal_channel(node_id, channel)?;
+        }
+        self.inner.update_batch(node_id, batch)
+    }
+
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), Error> {
+        self.journal.lock().unwrap().delete(node_id, id0)?;
+        self.inner.delete_channel(node_id, id0)
+    }
+
+    fn get_channel(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+    ) -> Result<model::ChannelEntry, Error> {
+        self.inner.get_channel(node_id, channel_id)
+    }
+
+    fn get_node_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Result<Vec<(ChannelId, model::ChannelEntry)>, Error> {
+        self.inner.get_node_channels(node_id)
+    }
+
+    fn update_node_allowlist(
+        &self,
+        node_id: &PublicKey,
+        allowlist: Vec<String>,
+    ) -> Result<(), Error> {
+        self.inner.update_node_allowlist(node_id, allowlist)
+    }
+
+    fn get_node_allowlist(&self, node_id: &PublicKey) -> Result<Vec<String>, Error> {
+        self.inner.get_node_allowlist(node_id)
+    }
+
+    fn update_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+        metadata: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        self.inner.update_metadata(node_id, channel_id, metadata)
+    }
+
+    fn get_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        self.inner.get_metadata(node_id, channel_id)
+    }
+
+    fn get_nodes(&self) -> Result<Vec<(PublicKey, model::NodeEntry)>, Error> {
+        self.inner.get_nodes()
+    }
+
+    fn export_since(&self, sequence: u64) -> Result<Vec<model::ChangeRecord>, Error> {
+        self.inner.export_since(sequence)
+    }
+
+    fn append_audit_record(
+        &self,
+        node_id: &PublicKey,
+        record: &model::AuditRecord,
+    ) -> Result<(), Error> {
+        self.inner.append_audit_record(node_id, record)
+    }
+
+    fn get_audit_log(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<model::AuditRecord>, Error> {
+        self.inner.get_audit_log(node_id, channel_id)
+    }
+
+    fn update_close_plan(
+        &self,
+        node_id: &PublicKey,
+        plan: Option<&ClosePlan>,
+    ) -> Result<(), Error> {
+        self.inner.update_close_plan(node_id, plan)
+    }
+
+    fn get_close_plan(&self, node_id: &PublicKey) -> Result<Option<ClosePlan>, Error> {
+        self.inner.get_close_plan(node_id)
+    }
+
+    fn update_fee_reserve(&self, node_id: &PublicKey, reserve: Vec<OutPoint>) -> Result<(), Error> {
+        self.inner.update_fee_reserve(node_id, reserve)
+    }
+
+    fn get_fee_reserve(&self, node_id: &PublicKey) -> Result<Vec<OutPoint>, Error> {
+        self.inner.get_fee_reserve(node_id)
+    }
+
+    fn update_payment(
+        &self,
+        node_id: &PublicKey,
+        entry: &model::PaymentLedgerEntry,
+    ) -> Result<(), Error> {
+        self.inner.update_payment(node_id, entry)
+    }
+
+    fn get_payments(&self, node_id: &PublicKey) -> Result<Vec<model::PaymentLedgerEntry>, Error> {
+        self.inner.get_payments(node_id)
+    }
+
+    fn export_all(&self, passphrase: &str) -> Result<Vec<u8>, Error> {
+        self.inner.export_all(passphrase)
+    }
+
+    fn import_all(&self, passphrase: &str, archive: &[u8]) -> Result<Vec<PublicKey>, Error> {
+        self.inner.import_all(passphrase, archive)
+    }
+
+    fn get_fence(&self) -> Result<u64, Error> {
+        self.inner.get_fence()
+    }
+
+    fn advance_fence(&self, current: u64) -> Result<u64, Error> {
+        self.inner.advance_fence(current)
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        {
+            let journal = self.journal.lock().unwrap();
+            journal.file.sync_all().map_err(|e| Error::Unavailable(format!("journal: {}", e)))?;
+        }
+        self.inner.flush()
+    }
+
+    fn clear_database(&self) -> Result<(), Error> {
+        {
+            let mut journal = self.journal.lock().unwrap();
+            journal.ready.clear();
+            journal.file.set_len(0).map_err(|e| Error::Unavailable(format!("journal: {}", e)))?;
+        }
+        self.inner.clear_database()
+    }
+}
+
+/// Replay a journal, returning the latest entry of each channel that was
+/// not deleted.  A missing journal is empty.
+///
+/// A truncated last record, such as after a crash during a write, is
+/// ignored.  Any other bad record is an error.
+pub fn replay(path: &Path) -> io::Result<Vec<(PublicKey, ChannelId, ChannelEntry)>> {
+    let (records, _) = read_records(path)?;
+    Ok(apply(path, records))
+}
+
+// Read the complete records, and the length of the journal they span
+fn read_records(path: &Path) -> io::Result<(Vec<JournalRecord>, u64)> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(e) => return Err(e),
+    };
+    // Every record ends with a newline, so the last piece is empty unless
+    // the last record was truncated
+    let mut lines: Vec<&str> = contents.split('\n').collect();
+    let partial = lines.pop().unwrap_or("");
+    if !partial.is_empty() {
+        warn!("journal {}: ignoring truncated last record", path.display());
+    }
+    let mut records = Vec::with_capacity(lines.len());
+    for (num, line) in lines.iter().enumerate() {
+        let record = serde_json::from_str(line).map_err(|e| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("journal {} line {}: {}", path.display(), num + 1, e),
+            )
+        })?;
+        records.push(record);
+    }
+    Ok((records, (contents.len() - partial.len()) as u64))
+}
+
+fn apply(path: &Path, records: Vec<JournalRecord>) -> Vec<(PublicKey, ChannelId, ChannelEntry)> {
+    let mut channels: OrderedMap<JournalKey, (PublicKey, ChannelEntry)> = OrderedMap::new();
+    for (num, record) in records.into_iter().enumerate() {
+        match record {
+            JournalRecord::Ready { node_id, id0, id, nonce, setup, enforcement_state } => {
+                let entry = ChannelEntry {
+                    version: ChannelEntry::VERSION,
+                    nonce,
+                    channel_value_satoshis: setup.channel_value_sat,
+                    channel_setup: Some(setup),
+                    id,
+                    enforcement_state,
+                    sequence: 0,
+                };
+                channels.insert((node_id.serialize(), id0), (node_id, entry));
+            }
+            JournalRecord::Advance { node_id, id0, enforcement_state } =>
+                match channels.get_mut(&(node_id.serialize(), id0)) {
+                    Some((_, entry)) => entry.enforcement_state = enforcement_state,
+                    None => warn!(
+                        "journal {} line {}: ignoring advance of unknown channel {}/{}",
+                        path.display(),
+                        num + 1,
+                        node_id,
+                        id0
+                    ),
+                },
+            JournalRecord::Delete { node_id, id0 } => {
+                channels.remove(&(node_id.serialize(), id0));
+            }
+        }
+    }
+    channels.into_iter().map(|((_, id0), (node_id, entry))| (node_id, id0, entry)).collect()
+}
+
+/// Rebuild the channel entries in the persister from the journal in the
+/// data directory, overwriting any existing entries of the journaled
+/// channels.  Returns the number of restored channels.
+pub fn restore(persister: &KVJsonPersister, data_path: &Path) -> io::Result<usize> {
+    let channels = replay(&data_path.join(JOURNAL_FILE_NAME))?;
+    let count = channels.len();
+    let mut last_sequence = persister.last_sequence.lock().unwrap();
+    for (node_id, id0, mut entry) in channels {
+        *last_sequence += 1;
+        entry.sequence = *last_sequence;
+        persister
+            .channel_bucket
+            .set(NodeChannelId::new(&node_id, &id0), Json(entry))
+            .map_err(|e| io::Error::new(ErrorKind::Other, e.to_string()))?;
+    }
+    persister
+        .channel_bucket
+        .flush()
+        .map_err(|e| io::Error::new(ErrorKind::Other, e.to_string()))?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use test_log::test;
+
+    use lightning_signer::persist::DummyPersister;
+    use lightning_signer::util::test_utils::*;
+
+    use super::*;
+
+    #[test]
+    fn journal_replay_test() {
+        let dir = TempDir::new().unwrap();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let node_id = node.get_id();
+        let persister = JournalingPersister::new(Arc::new(DummyPersister), dir.path()).unwrap();
+
+        node.with_ready_channel(&channel_id, |chan| {
+            persister.update_channel(&node_id, chan).unwrap();
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(3);
+            persister.update_channel(&node_id, chan).unwrap();
+            Ok(())
+        })
+        .unwrap();
+
+        let path = dir.path().join(JOURNAL_FILE_NAME);
+        let channels = replay(&path).unwrap();
+        assert_eq!(channels.len(), 1);
+        let (replayed_node_id, id0, entry) = &channels[0];
+        assert_eq!(*replayed_node_id, node_id);
+        assert_eq!(*id0, channel_id);
+        assert_eq!(entry.enforcement_state.next_holder_commit_num, 3);
+        assert_eq!(entry.channel_value_satoshis, make_test_channel_setup().channel_value_sat);
+
+        // a reopened journal appends advances, not another ready record
+        drop(persister);
+        let persister = JournalingPersister::new(Arc::new(DummyPersister), dir.path()).unwrap();
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(4);
+            persister.update_channel(&node_id, chan)
+        })
+        .unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.matches("\"op\":\"ready\"").count(), 1);
+        assert_eq!(replay(&path).unwrap()[0].2.enforcement_state.next_holder_commit_num, 4);
+
+        // a truncated last record is ignored, and dropped when reopening
+        drop(persister);
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"op\":\"adv").unwrap();
+        assert_eq!(replay(&path).unwrap()[0].2.enforcement_state.next_holder_commit_num, 4);
+        let persister = JournalingPersister::new(Arc::new(DummyPersister), dir.path()).unwrap();
+
+        persister.delete_channel(&node_id, &channel_id).unwrap();
+        assert!(replay(&path).unwrap().is_empty());
+
+        // a bad record in the middle is an error
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, format!("{{}}\n{}", contents)).unwrap();
+        assert!(replay(&path).is_err());
+    }
+
+    #[test]
+    fn journal_restore_test() {
+        let dir = TempDir::new().unwrap();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let node_id = node.get_id();
+        {
+            let persister = JournalingPersister::new(Arc::new(DummyPersister), dir.path()).unwrap();
+            node.with_ready_channel(&channel_id, |chan| {
+                chan.enforcement_state.set_next_holder_commit_num_for_testing(7);
+                persister.update_channel(&node_id, chan)
+            })
+            .unwrap();
+        }
+
+        let kv_persister = KVJsonPersister::new(dir.path().join("kv")).unwrap();
+        assert_eq!(restore(&kv_persister, dir.path()).unwrap(), 1);
+        let entry = kv_persister.get_channel(&node_id, &channel_id).unwrap();
+        assert_eq!(entry.enforcement_state.next_holder_commit_num, 7);
+        assert!(entry.channel_setup.is_some());
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use bitcoin::secp256k1::PublicKey;
+use lightning_signer::persist::Error;
+use lightning_signer::signer::key_store::KeyStore;
+use lightning_signer::SendSync;
+
+use crate::util::write_private_file;
+
+/// A [KeyStore] with a file per node seed, in a directory apart from the
+/// data directory, such as a mounted secrets volume.
+///
+/// On Unix, the seed files are only readable by the owner.
+pub struct DirKeyStore {
+    path: PathBuf,
+}
+
+impl DirKeyStore {
+    /// Use the directory, creating it if it doesn't exist
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path)?;
+        Ok(DirKeyStore { path })
+    }
+
+    fn seed_path(&self, node_id: &PublicKey) -> PathBuf {
+        self.path.join(format!("{}.seed", node_id))
+    }
+}
+
+impl SendSync for DirKeyStore {}
+
+impl KeyStore for DirKeyStore {
+    fn put_seed(&self, node_id: &PublicKey, seed: &[u8]) -> Result<(), Error> {
+        write_private_file(&self.seed_path(node_id), seed)
+            .map_err(|e| Error::Unavailable(format!("seed of node {}: {}", node_id, e)))
+    }
+
+    fn get_seed(&self, node_id: &PublicKey) -> Result<Vec<u8>, Error> {
+        fs::read(self.seed_path(node_id)).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => Error::NotFound(format!("seed of node {}", node_id)),
+            _ => Error::Unavailable(format!("seed of node {}: {}", node_id, e)),
+        })
+    }
+
+    fn delete_seed(&self, node_id: &PublicKey) -> Result<(), Error> {
+        match fs::remove_file(self.seed_path(node_id)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(Error::Unavailable(format!("seed of node {}: {}", node_id, e)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning_signer::util::test_utils::make_dummy_pubkey;
+    use tempfile::TempDir;
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn dir_key_store_test() {
+        let dir = TempDir::new().unwrap();
+        let key_store = DirKeyStore::new(dir.path().join("seeds")).unwrap();
+        let node_id = make_dummy_pubkey(0x12);
+        assert!(matches!(key_store.get_seed(&node_id), Err(Error::NotFound(_))));
+        key_store.put_seed(&node_id, &[7u8; 32]).unwrap();
+        assert_eq!(key_store.get_seed(&node_id).unwrap(), vec![7u8; 32]);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(key_store.seed_path(&node_id)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+        key_store.delete_seed(&node_id).unwrap();
+        key_store.delete_seed(&node_id).unwrap();
+        assert!(key_store.get_seed(&node_id).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use fs2::FileExt;
+
+/// The name of the lock file created in a locked data directory
+pub const LOCK_FILE_NAME: &str = "vlsd.lock";
+
+/// An advisory lock on a data directory, to prevent two servers from
+/// using the same persistent store.
+///
+/// The lock is an OS advisory lock on a file in the directory, `flock` on
+/// Unix and `LockFileEx` on Windows.  The OS releases it when the file is
+/// closed, including when the server crashes, so a stale lock file never
+/// has to be removed by hand.  The file contains the process ID of the
+/// last holder, for the error message.
+#[derive(Debug)]
+pub struct DirLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl DirLock {
+    /// Lock the directory, creating it if it doesn't exist
+    pub fn acquire<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let path = dir.join(LOCK_FILE_NAME);
+        let mut file =
+            OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        if let Err(e) = file.try_lock_exclusive() {
+            if e.kind() != fs2::lock_contended_error().kind() {
+                return Err(e);
+            }
+            let holder = Self::read_holder(&path).unwrap_or_else(|| "unknown".to_string());
+            return Err(io::Error::new(
+                ErrorKind::WouldBlock,
+                format!("data directory {} is locked by process {}", dir.display(), holder),
+            ));
+        }
+        file.set_len(0)?;
+        write!(file, "{}", process::id())?;
+        file.sync_all()?;
+        Ok(DirLock { path, _file: file })
+    }
+
+    /// The path of the lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read_holder(path: &Path) -> Option<String> {
+        let mut contents = String::new();
+        File::open(path).ok()?.read_to_string(&mut contents).ok()?;
+        Some(contents.trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn lock_exclusive_test() {
+        let dir = TempDir::new().unwrap();
+        let lock = DirLock::acquire(dir.path()).unwrap();
+        let err = DirLock::acquire(dir.path()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert!(err.to_string().contains(&process::id().to_string()));
+        drop(lock);
+        DirLock::acquire(dir.path()).unwrap();
+    }
+
+    #[test]
+    fn lock_stale_file_test() {
+        // a lock file left behind by a crashed server doesn't hold the lock
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join(LOCK_FILE_NAME), "4294967295 and more").unwrap();
+        let lock = DirLock::acquire(dir.path()).unwrap();
+        let contents = fs::read_to_string(lock.path()).unwrap();
+        assert_eq!(contents, process::id().to_string());
+    }
+
+    #[test]
+    fn lock_path_edge_cases_test() {
+        let dir = TempDir::new().unwrap();
+        let paths = vec![
+            dir.path().join("with space"),
+            dir.path().join("nested").join("not").join("yet").join("created"),
+            dir.path().join("ünïcödé"),
+            dir.path().join("dotted.dir"),
+        ];
+        for path in paths {
+            let lock = DirLock::acquire(&path).unwrap();
+            assert_eq!(lock.path(), path.join(LOCK_FILE_NAME));
+            assert!(lock.path().exists());
+        }
+
+        // a trailing separator names the same directory
+        let plain = dir.path().join("trailing");
+        let mut trailing = plain.clone().into_os_string();
+        trailing.push(std::path::MAIN_SEPARATOR.to_string());
+        let _lock = DirLock::acquire(&plain).unwrap();
+        assert!(DirLock::acquire(&trailing).is_err());
+    }
+
+    #[test]
+    fn lock_on_file_fails_test() {
+        let dir = TempDir::new().unwrap();
+        let file_path = dir.path().join("file");
+        File::create(&file_path).unwrap();
+        assert!(DirLock::acquire(&file_path).is_err());
+    }
+}
@@ -1,7 +1,22 @@
+pub mod backup;
+pub mod fenced;
+pub mod key_store;
+pub mod lock;
 pub mod model;
+pub mod read_only;
 pub mod ser_util;
+pub mod tee;
+pub mod versioned;
 
 pub mod util;
 
+#[cfg(feature = "persist_kv_json")]
+pub mod journal;
 #[cfg(feature = "persist_kv_json")]
 pub mod persist_json;
+#[cfg(feature = "persist_postgres")]
+pub mod persist_postgres;
+#[cfg(feature = "persist_s3")]
+pub mod persist_s3;
+#[cfg(feature = "persist_sqlite")]
+pub mod persist_sqlite;
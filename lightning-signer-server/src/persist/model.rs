@@ -1,54 +1,133 @@
 use std::collections::BTreeMap as OrderedMap;
-use std::convert::TryInto;
+use std::collections::VecDeque;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::iter::FromIterator;
 
 use bitcoin::consensus::{deserialize, serialize};
 use bitcoin::secp256k1::PublicKey;
-use bitcoin::{Network, OutPoint};
+use bitcoin::util::uint::Uint256;
+use bitcoin::{BlockHeader, Network, OutPoint, Script};
 use kv::{Key, Raw};
-use lightning_signer::chain::tracker::{ChainTracker, ListenSlot};
-use serde::{Deserialize, Serialize};
+use lightning_signer::chain::tracker::{
+    min_chainwork, ChainTracker, ListenSlot, DEFAULT_MAX_REORG_DEPTH,
+};
+use log::warn;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 use serde_with::hex::Hex;
 use serde_with::serde_as;
 
 use lightning_signer::channel::ChannelId;
 use lightning_signer::channel::ChannelSetup;
+use lightning_signer::close_plan::ClosePlan;
 use lightning_signer::monitor::ChainMonitor;
 use lightning_signer::monitor::State as ChainMonitorState;
+use lightning_signer::node::ChainParams;
 use lightning_signer::persist::model::{
-    ChannelEntry as CoreChannelEntry, NodeEntry as CoreNodeEntry,
+    AuditRecord, ChannelEntry as CoreChannelEntry, NodeEntry as CoreNodeEntry, PaymentLedgerEntry,
 };
+use lightning_signer::persist::Error;
 use lightning_signer::policy::validator::EnforcementState;
 
 use super::ser_util::{
-    ChainMonitorStateDef, ChannelIdHandler, ChannelSetupDef, EnforcementStateDef, ListenSlotDef,
-    OutPointDef,
+    AuditRecordDef, ChainMonitorStateDef, ChannelIdHandler, ChannelSetupDef, ClosePlanDef,
+    EnforcementStateDef, ListenSlotDef, OutPointDef, PaymentLedgerEntryDef,
 };
+use super::versioned::{self, from_unversioned, Migration, Versioned};
+
+// Implement serde for a versioned entry, whose derived implementation is
+// generated with `#[serde(remote = "Self")]`, so that the entry is migrated
+// to the current version before it is deserialized
+macro_rules! impl_versioned_entry {
+    ($entry: ident, $kind: expr, [$($migration: expr),* $(,)?]) => {
+        impl Versioned for $entry {
+            const KIND: &'static str = $kind;
+            const MIGRATIONS: &'static [Migration] = &[$($migration),*];
+
+            fn from_current(value: Value) -> Result<Self, serde_json::Error> {
+                $entry::deserialize(value)
+            }
+        }
+
+        impl Serialize for $entry {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                $entry::serialize(self, serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $entry {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                versioned::deserialize(deserializer)
+            }
+        }
+    };
+}
 
 #[serde_as]
 #[derive(Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct NodeEntry {
+    #[serde(default)]
+    pub version: u64,
     #[serde_as(as = "Hex")]
     pub seed: Vec<u8>,
     pub key_derivation_style: u8,
     pub network: String,
+    // Consensus encoded genesis block header, for non-standard chain params
+    #[serde(default)]
+    #[serde_as(as = "Option<Hex>")]
+    pub genesis: Option<Vec<u8>>,
+    #[serde(default)]
+    #[serde_as(as = "Option<Hex>")]
+    pub signet_challenge: Option<Vec<u8>>,
+}
+
+impl NodeEntry {
+    pub fn new(
+        seed: Vec<u8>,
+        key_derivation_style: u8,
+        network: String,
+        chain_params: &Option<ChainParams>,
+    ) -> Self {
+        NodeEntry {
+            version: NodeEntry::VERSION,
+            seed,
+            key_derivation_style,
+            network,
+            genesis: chain_params.as_ref().map(|p| serialize(&p.genesis)),
+            signet_challenge: chain_params
+                .as_ref()
+                .and_then(|p| p.signet_challenge.as_ref().map(|s| s.to_bytes())),
+        }
+    }
 }
 
+impl_versioned_entry!(NodeEntry, "node", [from_unversioned]);
+
 impl From<NodeEntry> for CoreNodeEntry {
     fn from(e: NodeEntry) -> Self {
+        let signet_challenge = e.signet_challenge;
+        let chain_params = e.genesis.map(|genesis| ChainParams {
+            genesis: deserialize(&genesis).expect("genesis header"),
+            signet_challenge: signet_challenge.map(Script::from),
+        });
         CoreNodeEntry {
             seed: e.seed,
             key_derivation_style: e.key_derivation_style,
             network: e.network,
+            chain_params,
         }
     }
 }
 
 #[serde_as]
 #[derive(Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct ChannelEntry {
+    #[serde(default)]
+    pub version: u64,
     #[serde_as(as = "Hex")]
     pub nonce: Vec<u8>,
     pub channel_value_satoshis: u64,
@@ -59,8 +138,13 @@ pub struct ChannelEntry {
     pub id: Option<ChannelId>,
     #[serde_as(as = "EnforcementStateDef")]
     pub enforcement_state: EnforcementState,
+    // The sequence number of the last change, for incremental export
+    #[serde(default)] // TODO remove default once everyone upgrades
+    pub sequence: u64,
 }
 
+impl_versioned_entry!(ChannelEntry, "channel", [from_unversioned]);
+
 impl From<ChannelEntry> for CoreChannelEntry {
     fn from(e: ChannelEntry) -> Self {
         CoreChannelEntry {
@@ -79,6 +163,27 @@ pub struct AllowlistItemEntry {
     pub allowlist: Vec<String>,
 }
 
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FeeReserveEntry {
+    #[serde_as(as = "Vec<OutPointDef>")]
+    pub reserve: Vec<OutPoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MetadataEntry {
+    pub metadata: Vec<(String, String)>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuditRecordEntry(#[serde(with = "AuditRecordDef")] pub AuditRecord);
+
+#[derive(Serialize, Deserialize)]
+pub struct ClosePlanEntry(#[serde(with = "ClosePlanDef")] pub ClosePlan);
+
+#[derive(Serialize, Deserialize)]
+pub struct PaymentEntry(#[serde(with = "PaymentLedgerEntryDef")] pub PaymentLedgerEntry);
+
 /// Fully qualified channel ID
 #[derive(Clone)]
 pub struct NodeChannelId(Vec<u8>);
@@ -131,7 +236,10 @@ impl<'a> Key<'a> for NodeChannelId {
 
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(remote = "Self")]
 pub struct ChainTrackerEntry {
+    #[serde(default)]
+    version: u64,
     // Serialized headers beyond tip
     #[serde_as(as = "Vec<Hex>")]
     headers: Vec<Vec<u8>>,
@@ -139,11 +247,27 @@ pub struct ChainTrackerEntry {
     #[serde_as(as = "Hex")]
     tip: Vec<u8>,
     height: u32,
+    // Serialized total work up to the tip, missing in older entries
+    #[serde(default)]
+    #[serde_as(as = "Option<Hex>")]
+    chainwork: Option<Vec<u8>>,
     network: Network,
     #[serde_as(as = "Vec<(OutPointDef, (ChainMonitorStateDef, ListenSlotDef))>")]
     listeners: OrderedMap<OutPoint, (ChainMonitorState, ListenSlot)>,
+    #[serde(default = "default_max_reorg_depth")]
+    max_reorg_depth: u32,
+    #[serde(default)]
+    suspect: bool,
+    #[serde(default)]
+    period_start_time: Option<u32>,
 }
 
+fn default_max_reorg_depth() -> u32 {
+    DEFAULT_MAX_REORG_DEPTH
+}
+
+impl_versioned_entry!(ChainTrackerEntry, "chain tracker", [from_unversioned]);
+
 impl From<&ChainTracker<ChainMonitor>> for ChainTrackerEntry {
     fn from(t: &ChainTracker<ChainMonitor>) -> Self {
         let tip = serialize(&t.tip);
@@ -153,19 +277,121 @@ impl From<&ChainTracker<ChainMonitor>> for ChainTrackerEntry {
             .iter()
             .map(|(l, s)| (l.funding_outpoint, (l.get_state().clone(), s.clone())))
             .collect();
-        ChainTrackerEntry { headers, tip, height: t.height(), network: t.network, listeners }
+        ChainTrackerEntry {
+            version: ChainTrackerEntry::VERSION,
+            headers,
+            tip,
+            height: t.height(),
+            chainwork: Some(serialize(&t.chainwork)),
+            network: t.network,
+            listeners,
+            max_reorg_depth: t.max_reorg_depth,
+            suspect: t.suspect,
+            period_start_time: t.period_start_time,
+        }
+    }
+}
+
+impl ChainTrackerEntry {
+    /// Check that the headers and the chainwork can be deserialized, such
+    /// as before importing the entry
+    pub fn validate(&self) -> Result<(), Error> {
+        self.decode_chain().map(|_| ())
+    }
+
+    fn decode_chain(&self) -> Result<(BlockHeader, VecDeque<BlockHeader>, Uint256), Error> {
+        let corrupt = |what: &str, e: bitcoin::consensus::encode::Error| {
+            Error::Corrupt(format!("chain tracker {}: {}", what, e))
+        };
+        let tip: BlockHeader = deserialize(&self.tip).map_err(|e| corrupt("tip", e))?;
+        let headers = self
+            .headers
+            .iter()
+            .map(|h| deserialize(h).map_err(|e| corrupt("header", e)))
+            .collect::<Result<_, _>>()?;
+        let chainwork = match self.chainwork {
+            Some(ref w) => deserialize(w).map_err(|e| corrupt("chainwork", e))?,
+            None => {
+                // Older entries didn't record it, so the least work of
+                // a chain of this height is used instead
+                let chainwork = min_chainwork(self.network, self.height);
+                warn!(
+                    "chain tracker at height {} has no chainwork, assuming the minimum {}",
+                    self.height, chainwork
+                );
+                chainwork
+            }
+        };
+        Ok((tip, headers, chainwork))
     }
 }
 
-impl Into<ChainTracker<ChainMonitor>> for ChainTrackerEntry {
-    fn into(self) -> ChainTracker<ChainMonitor> {
-        let tip = deserialize(&self.tip).expect("deserialize tip");
-        let headers =
-            self.headers.iter().map(|h| deserialize(h).expect("deserialize header")).collect();
+impl TryFrom<ChainTrackerEntry> for ChainTracker<ChainMonitor> {
+    type Error = Error;
+
+    fn try_from(entry: ChainTrackerEntry) -> Result<Self, Error> {
+        let (tip, headers, chainwork) = entry.decode_chain()?;
         let listeners =
-            OrderedMap::from_iter(self.listeners.into_iter().map(|(outpoint, (state, slot))| {
+            OrderedMap::from_iter(entry.listeners.into_iter().map(|(outpoint, (state, slot))| {
                 (ChainMonitor::new_from_persistence(outpoint, state), slot)
             }));
-        ChainTracker { headers, tip, height: self.height, network: self.network, listeners }
+        Ok(ChainTracker {
+            headers,
+            tip,
+            height: entry.height,
+            chainwork,
+            network: entry.network,
+            listeners,
+            max_reorg_depth: entry.max_reorg_depth,
+            suspect: entry.suspect,
+            period_start_time: entry.period_start_time,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::constants::genesis_block;
+    use lightning_signer::util::test_utils::make_header;
+
+    #[test]
+    fn node_entry_version_test() {
+        let entry = NodeEntry::new(vec![1; 32], 0, "regtest".to_string(), &None);
+        let mut json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["version"], NodeEntry::VERSION);
+
+        // records written before versioning are upgraded on load
+        json.as_object_mut().unwrap().remove("version");
+        let loaded: NodeEntry = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(loaded.version, NodeEntry::VERSION);
+        assert_eq!(loaded.seed, entry.seed);
+
+        json["version"] = (NodeEntry::VERSION + 1).into();
+        assert!(serde_json::from_value::<NodeEntry>(json).is_err());
+    }
+
+    #[test]
+    fn chain_tracker_entry_test() {
+        let genesis = genesis_block(Network::Regtest).header;
+        let mut tracker = ChainTracker::new(Network::Regtest, 0, genesis).unwrap();
+        let header = make_header(genesis, Default::default());
+        tracker.add_block(header, vec![], None).unwrap();
+        let entry = ChainTrackerEntry::from(&tracker);
+        let json = serde_json::to_value(&entry).unwrap();
+
+        // older entries without the chainwork get the minimum for the height
+        let mut older = json.clone();
+        older.as_object_mut().unwrap().remove("chainwork");
+        let loaded: ChainTrackerEntry = serde_json::from_value(older).unwrap();
+        let loaded = ChainTracker::<ChainMonitor>::try_from(loaded).unwrap();
+        assert_eq!(loaded.chainwork, min_chainwork(Network::Regtest, 1));
+
+        // a corrupt entry is an error rather than a panic
+        let mut corrupt = json;
+        corrupt["chainwork"] = "0102".into();
+        let loaded: ChainTrackerEntry = serde_json::from_value(corrupt).unwrap();
+        assert!(loaded.validate().is_err());
+        assert!(matches!(ChainTracker::<ChainMonitor>::try_from(loaded), Err(Error::Corrupt(_))));
     }
 }
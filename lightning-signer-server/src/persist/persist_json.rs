@@ -1,140 +1,424 @@
-use kv::{Bucket, Config, Json, Store, TransactionError};
+use std::convert::TryInto;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use kv::{Bucket, Config, Json, Store};
 
 use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
 use lightning_signer::chain::tracker::ChainTracker;
 
 use lightning_signer::channel::{Channel, ChannelId, ChannelStub};
+use lightning_signer::close_plan::ClosePlan;
 use lightning_signer::monitor::ChainMonitor;
 use lightning_signer::node::NodeConfig;
 use lightning_signer::persist::model::{
-    ChannelEntry as CoreChannelEntry, NodeEntry as CoreNodeEntry,
+    AuditRecord, ChangeRecord, ChannelChange, ChannelEntry as CoreChannelEntry,
+    NodeEntry as CoreNodeEntry, PaymentLedgerEntry,
 };
-use lightning_signer::persist::Persist;
+use lightning_signer::persist::{Error, Persist, PersistBatch};
 use lightning_signer::policy::validator::EnforcementState;
 use log::error;
 
+use crate::persist::backup::{Archive, ChannelArchive, NodeArchive};
+use crate::persist::lock::DirLock;
 use crate::persist::model::ChainTrackerEntry;
 use crate::persist::model::NodeChannelId;
-use crate::persist::model::{AllowlistItemEntry, ChannelEntry, NodeEntry};
+use crate::persist::model::{
+    AllowlistItemEntry, AuditRecordEntry, ChannelEntry, ClosePlanEntry, FeeReserveEntry,
+    MetadataEntry, NodeEntry, PaymentEntry,
+};
+use crate::persist::versioned::Versioned;
+
+// Failures of the underlying store are reported as possibly transient,
+// except for records that can't be decoded
+fn store_error(e: kv::Error) -> Error {
+    error!("kv store error: {}", e);
+    match e {
+        kv::Error::Json(_) | kv::Error::Utf8(_) | kv::Error::FromUtf8(_) => {
+            Error::Corrupt(e.to_string())
+        }
+        _ => Error::Unavailable(e.to_string()),
+    }
+}
+
+// A data directory locked by another process is refused
+pub(crate) fn lock_error(e: io::Error) -> Error {
+    match e.kind() {
+        ErrorKind::WouldBlock => Error::Refused(e.to_string()),
+        _ => Error::Unavailable(format!("lock datadir: {}", e)),
+    }
+}
+
+/// The default number of audit records kept per channel
+pub const DEFAULT_AUDIT_RETENTION: usize = 10_000;
 
 /// A persister that uses the kv crate and JSON serialization for values.
+///
+/// The data directory is locked for as long as the persister is alive.
+///
+/// Each channel entry records the sequence number of its last change, and
+/// each deleted channel leaves a tombstone with the sequence number of the
+/// deletion, for [Persist::export_since].
+///
+/// The audit log keeps the last `audit_retention` records of each channel,
+/// and of the node-level operations of each node.
 pub struct KVJsonPersister<'a> {
     pub node_bucket: Bucket<'a, Vec<u8>, Json<NodeEntry>>,
     pub channel_bucket: Bucket<'a, NodeChannelId, Json<ChannelEntry>>,
     pub allowlist_bucket: Bucket<'a, Vec<u8>, Json<AllowlistItemEntry>>,
     pub chain_tracker_bucket: Bucket<'a, Vec<u8>, Json<ChainTrackerEntry>>,
+    pub metadata_bucket: Bucket<'a, NodeChannelId, Json<MetadataEntry>>,
+    pub tombstone_bucket: Bucket<'a, NodeChannelId, Json<u64>>,
+    pub audit_bucket: Bucket<'a, Vec<u8>, Json<AuditRecordEntry>>,
+    pub close_plan_bucket: Bucket<'a, Vec<u8>, Json<ClosePlanEntry>>,
+    pub fee_reserve_bucket: Bucket<'a, Vec<u8>, Json<FeeReserveEntry>>,
+    pub payment_bucket: Bucket<'a, Vec<u8>, Json<PaymentEntry>>,
+    /// The last assigned change sequence number.  Held while a change is
+    /// written, so that changes are written in sequence order.
+    pub last_sequence: Mutex<u64>,
+    /// The last assigned audit record sequence number
+    pub last_audit_sequence: Mutex<u64>,
+    /// The number of audit records kept per channel, zero to keep all
+    pub audit_retention: usize,
+    _lock: DirLock,
 }
 
 impl KVJsonPersister<'_> {
-    pub fn new(path: &str) -> Self {
+    /// Open the store in the data directory at `path`, creating it if
+    /// needed.  Fails with [Error::Refused] if another process has the
+    /// data directory open.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let lock = DirLock::acquire(path).map_err(lock_error)?;
         let cfg = Config::new(path);
-        let store = Store::new(cfg).expect("create store");
-        let node_bucket = store.bucket(Some("nodes")).expect("create node bucket");
-        let channel_bucket = store.bucket(Some("channels")).expect("create channel bucket");
-        let allowlist_bucket = store.bucket(Some("allowlists")).expect("create allowlist bucket");
-        let chain_tracker_bucket =
-            store.bucket(Some("chain_tracker")).expect("create chain tracker bucket");
-        Self { node_bucket, channel_bucket, allowlist_bucket, chain_tracker_bucket }
+        let store = Store::new(cfg).map_err(store_error)?;
+        let node_bucket = store.bucket(Some("nodes")).map_err(store_error)?;
+        let channel_bucket = store.bucket(Some("channels")).map_err(store_error)?;
+        let allowlist_bucket = store.bucket(Some("allowlists")).map_err(store_error)?;
+        let chain_tracker_bucket = store.bucket(Some("chain_tracker")).map_err(store_error)?;
+        let metadata_bucket = store.bucket(Some("metadata")).map_err(store_error)?;
+        let tombstone_bucket = store.bucket(Some("channel_tombstones")).map_err(store_error)?;
+        let audit_bucket = store.bucket(Some("audit_log")).map_err(store_error)?;
+        let close_plan_bucket = store.bucket(Some("close_plans")).map_err(store_error)?;
+        let fee_reserve_bucket = store.bucket(Some("fee_reserves")).map_err(store_error)?;
+        let payment_bucket = store.bucket(Some("payments")).map_err(store_error)?;
+        let last_sequence =
+            Self::init_sequence(&channel_bucket, &tombstone_bucket).map_err(store_error)?;
+        let mut last_audit_sequence = 0;
+        for item_res in audit_bucket.iter() {
+            let value: Json<AuditRecordEntry> =
+                item_res.map_err(store_error)?.value().map_err(store_error)?;
+            last_audit_sequence = last_audit_sequence.max(value.0 .0.sequence);
+        }
+        Ok(Self {
+            node_bucket,
+            channel_bucket,
+            allowlist_bucket,
+            chain_tracker_bucket,
+            metadata_bucket,
+            tombstone_bucket,
+            audit_bucket,
+            close_plan_bucket,
+            fee_reserve_bucket,
+            payment_bucket,
+            last_sequence: Mutex::new(last_sequence),
+            last_audit_sequence: Mutex::new(last_audit_sequence),
+            audit_retention: DEFAULT_AUDIT_RETENTION,
+            _lock: lock,
+        })
+    }
+
+    /// Keep the given number of audit records per channel, zero to keep all
+    pub fn with_audit_retention(mut self, audit_retention: usize) -> Self {
+        self.audit_retention = audit_retention;
+        self
+    }
+
+    // Number the channel entries written before sequence numbers were
+    // persisted, and return the last sequence number
+    fn init_sequence(
+        channel_bucket: &Bucket<NodeChannelId, Json<ChannelEntry>>,
+        tombstone_bucket: &Bucket<NodeChannelId, Json<u64>>,
+    ) -> Result<u64, kv::Error> {
+        let mut last_sequence = 0;
+        let mut unnumbered = Vec::new();
+        for item_res in channel_bucket.iter() {
+            let item = item_res?;
+            let value: Json<ChannelEntry> = item.value()?;
+            if value.0.sequence == 0 {
+                let key: NodeChannelId = item.key()?;
+                unnumbered.push((key, value.0));
+            } else {
+                last_sequence = last_sequence.max(value.0.sequence);
+            }
+        }
+        for item_res in tombstone_bucket.iter() {
+            let value: Json<u64> = item_res?.value()?;
+            last_sequence = last_sequence.max(value.0);
+        }
+        for (key, mut entry) in unnumbered {
+            last_sequence += 1;
+            entry.sequence = last_sequence;
+            channel_bucket.set(key, Json(entry))?;
+        }
+        channel_bucket.flush()?;
+        Ok(last_sequence)
+    }
+
+    // Delete a channel entry, leaving a tombstone
+    fn remove_channel(&self, id: NodeChannelId, last_sequence: &mut u64) -> Result<(), kv::Error> {
+        self.tombstone_bucket.set(id.clone(), Json(*last_sequence + 1))?;
+        *last_sequence += 1;
+        self.channel_bucket.remove(id)?;
+        Ok(())
+    }
+
+    // Node metadata is keyed by the node ID alone
+    fn metadata_key(node_id: &PublicKey, channel_id: Option<&ChannelId>) -> NodeChannelId {
+        match channel_id {
+            Some(channel_id) => NodeChannelId::new(node_id, channel_id),
+            None => NodeChannelId::new_prefix(node_id),
+        }
+    }
+
+    // Audit records are keyed by node ID, channel ID and sequence number.
+    // Node-level records have an all-zero channel ID.
+    fn audit_prefix(node_id: &PublicKey, channel_id: Option<&ChannelId>) -> Vec<u8> {
+        let mut key = node_id.serialize().to_vec();
+        key.extend_from_slice(&channel_id.map(|c| c.0).unwrap_or([0; 32]));
+        key
+    }
+
+    // Drop the oldest audit records under a prefix, beyond the retention
+    fn prune_audit_log(&self, prefix: Vec<u8>) -> Result<(), kv::Error> {
+        if self.audit_retention == 0 {
+            return Ok(());
+        }
+        let keys = self
+            .audit_bucket
+            .iter_prefix(prefix)
+            .map(|item_res| item_res.and_then(|item| item.key()))
+            .collect::<Result<Vec<Vec<u8>>, _>>()?;
+        let excess = keys.len().saturating_sub(self.audit_retention);
+        for key in keys.into_iter().take(excess) {
+            self.audit_bucket.remove(key)?;
+        }
+        Ok(())
     }
 }
 
 impl<'a> Persist for KVJsonPersister<'a> {
-    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]) {
+    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]) -> Result<(), Error> {
         let key = node_id.serialize().to_vec();
-        assert!(!self.node_bucket.contains(key.clone()).unwrap());
-        let entry = NodeEntry {
-            seed: seed.to_vec(),
-            key_derivation_style: config.key_derivation_style as u8,
-            network: config.network.to_string(),
-        };
-        self.node_bucket.set(key, Json(entry)).expect("insert node");
-        self.node_bucket.flush().expect("flush");
+        if self.node_bucket.contains(key.clone()).map_err(store_error)? {
+            return Err(Error::AlreadyExists(format!("node {}", node_id)));
+        }
+        let entry = NodeEntry::new(
+            seed.to_vec(),
+            config.key_derivation_style as u8,
+            config.network.to_string(),
+            &config.chain_params,
+        );
+        self.node_bucket.set(key, Json(entry)).map_err(store_error)?;
+        self.node_bucket.flush().map_err(store_error)?;
+        Ok(())
     }
 
-    fn delete_node(&self, node_id: &PublicKey) {
+    fn delete_node(&self, node_id: &PublicKey) -> Result<(), Error> {
+        let mut last_sequence = self.last_sequence.lock().unwrap();
         for item_res in self.channel_bucket.iter_prefix(NodeChannelId::new_prefix(node_id)) {
-            let id: NodeChannelId = item_res.unwrap().key().unwrap();
-            self.channel_bucket.remove(id).unwrap();
+            let id: NodeChannelId = item_res.and_then(|item| item.key()).map_err(store_error)?;
+            self.remove_channel(id, &mut last_sequence).map_err(store_error)?;
+        }
+        self.tombstone_bucket.flush().map_err(store_error)?;
+        for item_res in self.metadata_bucket.iter_prefix(NodeChannelId::new_prefix(node_id)) {
+            let id: NodeChannelId = item_res.and_then(|item| item.key()).map_err(store_error)?;
+            self.metadata_bucket.remove(id).map_err(store_error)?;
+        }
+        for item_res in self.audit_bucket.iter_prefix(node_id.serialize().to_vec()) {
+            let key: Vec<u8> = item_res.and_then(|item| item.key()).map_err(store_error)?;
+            self.audit_bucket.remove(key).map_err(store_error)?;
+        }
+        for item_res in self.payment_bucket.iter_prefix(node_id.serialize().to_vec()) {
+            let key: Vec<u8> = item_res.and_then(|item| item.key()).map_err(store_error)?;
+            self.payment_bucket.remove(key).map_err(store_error)?;
         }
         let key = node_id.serialize().to_vec();
-        self.node_bucket.remove(key.clone()).unwrap();
-        self.chain_tracker_bucket.remove(key).unwrap();
+        self.node_bucket.remove(key.clone()).map_err(store_error)?;
+        self.close_plan_bucket.remove(key.clone()).map_err(store_error)?;
+        self.fee_reserve_bucket.remove(key.clone()).map_err(store_error)?;
+        self.chain_tracker_bucket.remove(key).map_err(store_error)?;
+        Ok(())
     }
 
-    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), ()> {
+    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), Error> {
         let channel_value_satoshis = 0; // TODO not known yet
 
-        self.channel_bucket
+        let mut last_sequence = self.last_sequence.lock().unwrap();
+        let sequence = *last_sequence + 1;
+        let created = self
+            .channel_bucket
             .transaction(|txn| {
                 let id = NodeChannelId::new(node_id, &stub.id0);
                 let entry = ChannelEntry {
+                    version: ChannelEntry::VERSION,
                     nonce: stub.nonce.clone(),
                     channel_value_satoshis,
                     channel_setup: None,
                     id: None,
                     enforcement_state: EnforcementState::new(0),
+                    sequence,
                 };
-                if txn.get(id.clone()).unwrap().is_some() {
-                    return Err(TransactionError::Abort(kv::Error::Message(
-                        "already exists".to_string(),
-                    )));
+                if txn.get(id.clone())?.is_some() {
+                    return Ok(false);
                 }
-                txn.set(id, Json(entry)).expect("insert channel");
-                Ok(())
+                txn.set(id, Json(entry))?;
+                Ok(true)
             })
-            .expect("new transaction");
-        self.channel_bucket.flush().expect("flush");
+            .map_err(store_error)?;
+        if !created {
+            return Err(Error::AlreadyExists(format!("channel {}", stub.id0)));
+        }
+        self.channel_bucket.flush().map_err(store_error)?;
+        *last_sequence = sequence;
+        // A channel ID can be reused after the channel is deleted
+        self.tombstone_bucket
+            .remove(NodeChannelId::new(node_id, &stub.id0))
+            .map_err(store_error)?;
+        self.tombstone_bucket.flush().map_err(store_error)?;
         Ok(())
     }
 
-    fn new_chain_tracker(&self, node_id: &PublicKey, tracker: &ChainTracker<ChainMonitor>) {
+    fn new_chain_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
         let key = node_id.serialize().to_vec();
-        assert!(!self.chain_tracker_bucket.contains(key.clone()).unwrap());
-        self.chain_tracker_bucket.set(key, Json(tracker.into())).expect("insert chain tracker");
-        self.chain_tracker_bucket.flush().expect("flush");
+        if self.chain_tracker_bucket.contains(key.clone()).map_err(store_error)? {
+            return Err(Error::AlreadyExists(format!("tracker of node {}", node_id)));
+        }
+        self.chain_tracker_bucket.set(key, Json(tracker.into())).map_err(store_error)?;
+        self.chain_tracker_bucket.flush().map_err(store_error)?;
+        Ok(())
     }
 
     fn update_tracker(
         &self,
         node_id: &PublicKey,
         tracker: &ChainTracker<ChainMonitor>,
-    ) -> Result<(), ()> {
+    ) -> Result<(), Error> {
         let key = node_id.serialize().to_vec();
-        self.chain_tracker_bucket.set(key, Json(tracker.into())).expect("update chain tracker");
-        self.chain_tracker_bucket.flush().expect("flush");
+        self.chain_tracker_bucket.set(key, Json(tracker.into())).map_err(store_error)?;
+        self.chain_tracker_bucket.flush().map_err(store_error)?;
         Ok(())
     }
 
-    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, ()> {
+    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, Error> {
         let key = node_id.serialize().to_vec();
-        let value = self.chain_tracker_bucket.get(key).unwrap().ok_or_else(|| ())?;
-        Ok(value.0.into())
+        let value = self
+            .chain_tracker_bucket
+            .get(key)
+            .map_err(store_error)?
+            .ok_or_else(|| Error::NotFound(format!("tracker of node {}", node_id)))?;
+        value.0.try_into()
     }
 
-    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), ()> {
+    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), Error> {
         let channel_value_satoshis = channel.setup.channel_value_sat;
 
-        self.channel_bucket
+        let mut last_sequence = self.last_sequence.lock().unwrap();
+        let sequence = *last_sequence + 1;
+        let found = self
+            .channel_bucket
             .transaction(|txn| {
                 let node_channel_id = NodeChannelId::new(node_id, &channel.id0);
                 let entry = ChannelEntry {
+                    version: ChannelEntry::VERSION,
                     nonce: channel.nonce.clone(),
                     channel_value_satoshis,
                     channel_setup: Some(channel.setup.clone()),
                     id: channel.id,
                     enforcement_state: channel.enforcement_state.clone(),
+                    sequence,
                 };
-                if txn.get(node_channel_id.clone()).unwrap().is_none() {
-                    return Err(TransactionError::Abort(kv::Error::Message(
-                        "not found".to_string(),
-                    )));
+                if txn.get(node_channel_id.clone())?.is_none() {
+                    return Ok(false);
                 }
-                let json = Json(entry);
-                txn.set(node_channel_id, json).expect("update channel");
-                Ok(())
+                txn.set(node_channel_id, Json(entry))?;
+                Ok(true)
             })
-            .expect("update transaction");
-        self.channel_bucket.flush().expect("flush");
+            .map_err(store_error)?;
+        if !found {
+            return Err(Error::NotFound(format!("channel {}", channel.id0)));
+        }
+        self.channel_bucket.flush().map_err(store_error)?;
+        *last_sequence = sequence;
+        Ok(())
+    }
+
+    fn update_batch(&self, node_id: &PublicKey, batch: &PersistBatch) -> Result<(), Error> {
+        let mut last_sequence = self.last_sequence.lock().unwrap();
+        let first_sequence = *last_sequence + 1;
+        let tracker_key = node_id.serialize().to_vec();
+        let missing = self
+            .channel_bucket
+            .transaction2(&self.chain_tracker_bucket, |channel_txn, tracker_txn| {
+                // Check before writing anything, so that nothing is
+                // committed if a channel is missing
+                for channel in batch.channels.iter() {
+                    let node_channel_id = NodeChannelId::new(node_id, &channel.id0);
+                    if channel_txn.get(node_channel_id)?.is_none() {
+                        return Ok(Some(channel.id0));
+                    }
+                }
+                for (sequence, channel) in (first_sequence..).zip(batch.channels.iter()) {
+                    let entry = ChannelEntry {
+                        version: ChannelEntry::VERSION,
+                        nonce: channel.nonce.clone(),
+                        channel_value_satoshis: channel.setup.channel_value_sat,
+                        channel_setup: Some(channel.setup.clone()),
+                        id: channel.id,
+                        enforcement_state: channel.enforcement_state.clone(),
+                        sequence,
+                    };
+                    channel_txn.set(NodeChannelId::new(node_id, &channel.id0), Json(entry))?;
+                }
+                if let Some(tracker) = batch.tracker {
+                    tracker_txn.set(tracker_key.clone(), Json(tracker.into()))?;
+                }
+                Ok(None)
+            })
+            .map_err(store_error)?;
+        if let Some(id0) = missing {
+            return Err(Error::NotFound(format!("channel {}", id0)));
+        }
+        self.channel_bucket.flush().map_err(store_error)?;
+        self.chain_tracker_bucket.flush().map_err(store_error)?;
+        *last_sequence += batch.channels.len() as u64;
+        Ok(())
+    }
+
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), Error> {
+        let node_channel_id = NodeChannelId::new(node_id, id0);
+        let value = self
+            .channel_bucket
+            .get(node_channel_id.clone())
+            .map_err(store_error)?
+            .ok_or_else(|| Error::NotFound(format!("channel {}", id0)))?;
+        // Metadata may have been set under either channel ID
+        self.metadata_bucket.remove(node_channel_id.clone()).map_err(store_error)?;
+        if let Some(id) = value.0.id {
+            self.metadata_bucket.remove(NodeChannelId::new(node_id, &id)).map_err(store_error)?;
+        }
+        self.metadata_bucket.flush().map_err(store_error)?;
+        let mut last_sequence = self.last_sequence.lock().unwrap();
+        self.remove_channel(node_channel_id, &mut last_sequence).map_err(store_error)?;
+        self.tombstone_bucket.flush().map_err(store_error)?;
+        self.channel_bucket.flush().map_err(store_error)?;
         Ok(())
     }
 
@@ -142,79 +426,351 @@ impl<'a> Persist for KVJsonPersister<'a> {
         &self,
         node_id: &PublicKey,
         channel_id: &ChannelId,
-    ) -> Result<CoreChannelEntry, ()> {
+    ) -> Result<CoreChannelEntry, Error> {
         let id = NodeChannelId::new(node_id, channel_id);
-        let value = self.channel_bucket.get(id).unwrap().ok_or_else(|| ())?;
+        let value = self
+            .channel_bucket
+            .get(id)
+            .map_err(store_error)?
+            .ok_or_else(|| Error::NotFound(format!("channel {}", channel_id)))?;
         let entry = CoreChannelEntry::from(value.0);
         Ok(entry)
     }
 
-    fn get_node_channels(&self, node_id: &PublicKey) -> Vec<(ChannelId, CoreChannelEntry)> {
+    fn get_node_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Result<Vec<(ChannelId, CoreChannelEntry)>, Error> {
         let mut res = Vec::new();
         for item_res in self.channel_bucket.iter_prefix(NodeChannelId::new_prefix(node_id)) {
-            let item = item_res.unwrap();
-            let value: Json<ChannelEntry> = item.value().unwrap();
+            let item = item_res.map_err(store_error)?;
+            let value: Json<ChannelEntry> = item.value().map_err(store_error)?;
             let entry = CoreChannelEntry::from(value.0);
-            let key: NodeChannelId = item.key().unwrap();
+            let key: NodeChannelId = item.key().map_err(store_error)?;
             res.push((key.channel_id(), entry));
         }
-        res
+        Ok(res)
     }
 
-    fn update_node_allowlist(&self, node_id: &PublicKey, allowlist: Vec<String>) -> Result<(), ()> {
+    fn update_node_allowlist(
+        &self,
+        node_id: &PublicKey,
+        allowlist: Vec<String>,
+    ) -> Result<(), Error> {
         let key = node_id.serialize().to_vec();
         let entry = AllowlistItemEntry { allowlist };
-        self.allowlist_bucket.set(key, Json(entry)).expect("update transaction");
-        self.allowlist_bucket.flush().expect("flush");
+        self.allowlist_bucket.set(key, Json(entry)).map_err(store_error)?;
+        self.allowlist_bucket.flush().map_err(store_error)?;
 
         Ok(())
     }
 
-    fn get_node_allowlist(&self, node_id: &PublicKey) -> Vec<String> {
+    fn get_node_allowlist(&self, node_id: &PublicKey) -> Result<Vec<String>, Error> {
         let key = node_id.serialize().to_vec();
-        let entry = self.allowlist_bucket.get(key);
-        if entry.is_err() {
-            // TODO make this fatal
-            error!("allowlist entry error {:?}", entry.err());
-            return vec![];
-        }
-        let entry2 = entry.unwrap();
-        if entry2.is_none() {
-            return vec![];
+        let entry = self.allowlist_bucket.get(key).map_err(store_error)?;
+        Ok(entry.map(|v| v.0.allowlist).unwrap_or_default())
+    }
+
+    fn update_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+        metadata: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        let key = Self::metadata_key(node_id, channel_id);
+        let entry = MetadataEntry { metadata };
+        self.metadata_bucket.set(key, Json(entry)).map_err(store_error)?;
+        self.metadata_bucket.flush().map_err(store_error)?;
+        Ok(())
+    }
+
+    fn get_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        let key = Self::metadata_key(node_id, channel_id);
+        match self.metadata_bucket.get(key).map_err(store_error)? {
+            Some(entry) => Ok(entry.0.metadata),
+            None => Ok(vec![]),
         }
-        entry2.unwrap().0.allowlist
     }
 
-    fn get_nodes(&self) -> Vec<(PublicKey, CoreNodeEntry)> {
+    fn get_nodes(&self) -> Result<Vec<(PublicKey, CoreNodeEntry)>, Error> {
         let mut res = Vec::new();
         for item_res in self.node_bucket.iter() {
-            let item = item_res.unwrap();
-            let value: Json<NodeEntry> = item.value().unwrap();
+            let item = item_res.map_err(store_error)?;
+            let value: Json<NodeEntry> = item.value().map_err(store_error)?;
             let entry = CoreNodeEntry::from(value.0);
-            let key: Vec<u8> = item.key().unwrap();
-            res.push((PublicKey::from_slice(key.as_slice()).unwrap(), entry));
+            let key: Vec<u8> = item.key().map_err(store_error)?;
+            let node_id = PublicKey::from_slice(key.as_slice())
+                .map_err(|e| Error::Corrupt(format!("node id {}: {}", hex::encode(&key), e)))?;
+            res.push((node_id, entry));
+        }
+        Ok(res)
+    }
+
+    fn export_since(&self, sequence: u64) -> Result<Vec<ChangeRecord>, Error> {
+        // Don't observe a change while it is being written
+        let _last_sequence = self.last_sequence.lock().unwrap();
+        let mut res = Vec::new();
+        for item_res in self.channel_bucket.iter() {
+            let item = item_res.map_err(store_error)?;
+            let value: Json<ChannelEntry> = item.value().map_err(store_error)?;
+            if value.0.sequence > sequence {
+                let key: NodeChannelId = item.key().map_err(store_error)?;
+                res.push(ChangeRecord {
+                    sequence: value.0.sequence,
+                    node_id: key.node_id(),
+                    id0: key.channel_id(),
+                    change: ChannelChange::Update(CoreChannelEntry::from(value.0)),
+                });
+            }
+        }
+        for item_res in self.tombstone_bucket.iter() {
+            let item = item_res.map_err(store_error)?;
+            let value: Json<u64> = item.value().map_err(store_error)?;
+            if value.0 > sequence {
+                let key: NodeChannelId = item.key().map_err(store_error)?;
+                res.push(ChangeRecord {
+                    sequence: value.0,
+                    node_id: key.node_id(),
+                    id0: key.channel_id(),
+                    change: ChannelChange::Delete,
+                });
+            }
+        }
+        res.sort_by_key(|r| r.sequence);
+        Ok(res)
+    }
+
+    fn append_audit_record(&self, node_id: &PublicKey, record: &AuditRecord) -> Result<(), Error> {
+        let mut last_audit_sequence = self.last_audit_sequence.lock().unwrap();
+        let sequence = *last_audit_sequence + 1;
+        let mut record = record.clone();
+        record.sequence = sequence;
+        record.timestamp =
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let prefix = Self::audit_prefix(node_id, record.channel_id.as_ref());
+        let mut key = prefix.clone();
+        key.extend_from_slice(&sequence.to_be_bytes());
+        self.audit_bucket.set(key, Json(AuditRecordEntry(record))).map_err(store_error)?;
+        self.prune_audit_log(prefix).map_err(store_error)?;
+        self.audit_bucket.flush().map_err(store_error)?;
+        *last_audit_sequence = sequence;
+        Ok(())
+    }
+
+    fn get_audit_log(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<AuditRecord>, Error> {
+        let prefix = match channel_id {
+            Some(_) => Self::audit_prefix(node_id, channel_id),
+            None => node_id.serialize().to_vec(),
+        };
+        let mut res = Vec::new();
+        for item_res in self.audit_bucket.iter_prefix(prefix) {
+            let value: Json<AuditRecordEntry> =
+                item_res.map_err(store_error)?.value().map_err(store_error)?;
+            res.push(value.0 .0);
         }
-        res
+        res.sort_by_key(|r| r.sequence);
+        Ok(res)
     }
 
-    fn clear_database(&self) {
-        self.channel_bucket.clear().unwrap();
-        self.node_bucket.clear().unwrap();
+    fn update_close_plan(
+        &self,
+        node_id: &PublicKey,
+        plan: Option<&ClosePlan>,
+    ) -> Result<(), Error> {
+        let key = node_id.serialize().to_vec();
+        match plan {
+            Some(plan) => self
+                .close_plan_bucket
+                .set(key, Json(ClosePlanEntry(plan.clone())))
+                .map(|_| ())
+                .map_err(store_error)?,
+            None => self.close_plan_bucket.remove(key).map(|_| ()).map_err(store_error)?,
+        }
+        self.close_plan_bucket.flush().map_err(store_error)?;
+        Ok(())
+    }
+
+    fn get_close_plan(&self, node_id: &PublicKey) -> Result<Option<ClosePlan>, Error> {
+        let key = node_id.serialize().to_vec();
+        let value: Option<Json<ClosePlanEntry>> =
+            self.close_plan_bucket.get(key).map_err(store_error)?;
+        Ok(value.map(|v| v.0 .0))
+    }
+
+    fn update_fee_reserve(&self, node_id: &PublicKey, reserve: Vec<OutPoint>) -> Result<(), Error> {
+        let key = node_id.serialize().to_vec();
+        self.fee_reserve_bucket.set(key, Json(FeeReserveEntry { reserve })).map_err(store_error)?;
+        self.fee_reserve_bucket.flush().map_err(store_error)?;
+        Ok(())
+    }
+
+    fn get_fee_reserve(&self, node_id: &PublicKey) -> Result<Vec<OutPoint>, Error> {
+        let key = node_id.serialize().to_vec();
+        let value: Option<Json<FeeReserveEntry>> =
+            self.fee_reserve_bucket.get(key).map_err(store_error)?;
+        Ok(value.map(|v| v.0.reserve).unwrap_or_default())
+    }
+
+    fn update_payment(&self, node_id: &PublicKey, entry: &PaymentLedgerEntry) -> Result<(), Error> {
+        let mut key = node_id.serialize().to_vec();
+        key.extend_from_slice(&entry.payment_hash.0);
+        self.payment_bucket.set(key, Json(PaymentEntry(entry.clone()))).map_err(store_error)?;
+        self.payment_bucket.flush().map_err(store_error)?;
+        Ok(())
+    }
+
+    fn get_payments(&self, node_id: &PublicKey) -> Result<Vec<PaymentLedgerEntry>, Error> {
+        let mut res = Vec::new();
+        for item_res in self.payment_bucket.iter_prefix(node_id.serialize().to_vec()) {
+            let value: Json<PaymentEntry> =
+                item_res.map_err(store_error)?.value().map_err(store_error)?;
+            res.push(value.0 .0);
+        }
+        Ok(res)
+    }
+
+    fn export_all(&self, passphrase: &str) -> Result<Vec<u8>, Error> {
+        // Don't observe a change while it is being written
+        let _last_sequence = self.last_sequence.lock().unwrap();
+        let mut archive = Archive::default();
+        for item_res in self.node_bucket.iter() {
+            let item = item_res.map_err(store_error)?;
+            let key: Vec<u8> = item.key().map_err(store_error)?;
+            let value: Json<NodeEntry> = item.value().map_err(store_error)?;
+            let node_id = PublicKey::from_slice(&key)
+                .map_err(|e| Error::Corrupt(format!("node id {}: {}", hex::encode(&key), e)))?;
+            let allowlist = self.allowlist_bucket.get(key.clone()).map_err(store_error)?;
+            let fee_reserve = self.fee_reserve_bucket.get(key.clone()).map_err(store_error)?;
+            let tracker = self.chain_tracker_bucket.get(key).map_err(store_error)?;
+            let mut channels = Vec::new();
+            for item_res in self.channel_bucket.iter_prefix(NodeChannelId::new_prefix(&node_id)) {
+                let item = item_res.map_err(store_error)?;
+                let key: NodeChannelId = item.key().map_err(store_error)?;
+                let value: Json<ChannelEntry> = item.value().map_err(store_error)?;
+                channels.push(ChannelArchive { channel_id: key.channel_id(), entry: value.0 });
+            }
+            let mut payments = Vec::new();
+            for item_res in self.payment_bucket.iter_prefix(node_id.serialize().to_vec()) {
+                let value: Json<PaymentEntry> =
+                    item_res.map_err(store_error)?.value().map_err(store_error)?;
+                payments.push(value.0 .0);
+            }
+            archive.nodes.push(NodeArchive {
+                node_id,
+                entry: value.0,
+                allowlist: allowlist.map(|v| v.0.allowlist).unwrap_or_default(),
+                fee_reserve: fee_reserve.map(|v| v.0.reserve).unwrap_or_default(),
+                payments,
+                tracker: tracker.map(|v| v.0),
+                channels,
+            });
+        }
+        Ok(archive.seal(passphrase))
+    }
+
+    fn import_all(&self, passphrase: &str, archive: &[u8]) -> Result<Vec<PublicKey>, Error> {
+        let archive = Archive::open(passphrase, archive)?;
+        for node in archive.nodes.iter() {
+            if self.node_bucket.contains(node.node_id.serialize().to_vec()).map_err(store_error)? {
+                return Err(Error::AlreadyExists(format!("node {}", node.node_id)));
+            }
+        }
+        let mut last_sequence = self.last_sequence.lock().unwrap();
+        let mut node_ids = Vec::new();
+        for node in archive.nodes {
+            let key = node.node_id.serialize().to_vec();
+            // The channels get new sequence numbers, so that replicas of
+            // this store pick them up
+            for channel in node.channels {
+                let id = NodeChannelId::new(&node.node_id, &channel.channel_id);
+                let mut entry = channel.entry;
+                entry.sequence = *last_sequence + 1;
+                self.channel_bucket.set(id.clone(), Json(entry)).map_err(store_error)?;
+                self.tombstone_bucket.remove(id).map_err(store_error)?;
+                *last_sequence += 1;
+            }
+            let allowlist = AllowlistItemEntry { allowlist: node.allowlist };
+            self.allowlist_bucket.set(key.clone(), Json(allowlist)).map_err(store_error)?;
+            let fee_reserve = FeeReserveEntry { reserve: node.fee_reserve };
+            self.fee_reserve_bucket.set(key.clone(), Json(fee_reserve)).map_err(store_error)?;
+            for entry in node.payments {
+                let mut payment_key = key.clone();
+                payment_key.extend_from_slice(&entry.payment_hash.0);
+                self.payment_bucket
+                    .set(payment_key, Json(PaymentEntry(entry)))
+                    .map_err(store_error)?;
+            }
+            if let Some(tracker) = node.tracker {
+                self.chain_tracker_bucket.set(key.clone(), Json(tracker)).map_err(store_error)?;
+            }
+            // The node is written last, so that a partial import is not
+            // restored as a node
+            self.node_bucket.set(key, Json(node.entry)).map_err(store_error)?;
+            node_ids.push(node.node_id);
+        }
+        self.channel_bucket.flush().map_err(store_error)?;
+        self.tombstone_bucket.flush().map_err(store_error)?;
+        self.allowlist_bucket.flush().map_err(store_error)?;
+        self.fee_reserve_bucket.flush().map_err(store_error)?;
+        self.payment_bucket.flush().map_err(store_error)?;
+        self.chain_tracker_bucket.flush().map_err(store_error)?;
+        self.node_bucket.flush().map_err(store_error)?;
+        Ok(node_ids)
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.node_bucket.flush().map_err(store_error)?;
+        self.channel_bucket.flush().map_err(store_error)?;
+        self.allowlist_bucket.flush().map_err(store_error)?;
+        self.chain_tracker_bucket.flush().map_err(store_error)?;
+        self.metadata_bucket.flush().map_err(store_error)?;
+        self.tombstone_bucket.flush().map_err(store_error)?;
+        self.audit_bucket.flush().map_err(store_error)?;
+        self.close_plan_bucket.flush().map_err(store_error)?;
+        self.fee_reserve_bucket.flush().map_err(store_error)?;
+        self.payment_bucket.flush().map_err(store_error)?;
+        Ok(())
+    }
+
+    fn clear_database(&self) -> Result<(), Error> {
+        self.channel_bucket.clear().map_err(store_error)?;
+        self.node_bucket.clear().map_err(store_error)?;
+        self.metadata_bucket.clear().map_err(store_error)?;
+        self.tombstone_bucket.clear().map_err(store_error)?;
+        self.audit_bucket.clear().map_err(store_error)?;
+        self.close_plan_bucket.clear().map_err(store_error)?;
+        self.fee_reserve_bucket.clear().map_err(store_error)?;
+        self.payment_bucket.clear().map_err(store_error)?;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
     use std::sync::Arc;
 
     use crate::lightning;
+    use bitcoin::Network;
     use lightning::chain::keysinterface::InMemorySigner;
     use lightning::util::ser::Writeable;
     use tempfile::TempDir;
     use test_log::test;
 
+    use bitcoin::Script;
+    use lightning::ln::PaymentHash;
     use lightning_signer::channel::{channel_nonce_to_id, ChannelSlot};
-    use lightning_signer::node::Node;
+    use lightning_signer::close_plan::PlannedClose;
+    use lightning_signer::node::{ChainParams, Node};
+    use lightning_signer::persist::model::PaymentResolution;
     use lightning_signer::policy::simple_validator::SimpleValidatorFactory;
     use lightning_signer::util::test_utils::*;
 
@@ -227,11 +783,64 @@ mod tests {
         let path = dir.path().to_owned();
         let path_str = path.to_str().unwrap();
 
-        let persister = KVJsonPersister::new(path_str);
-        persister.clear_database();
+        let persister = KVJsonPersister::new(path_str).unwrap();
+        persister.clear_database().unwrap();
         (persister, dir, path_str.to_string())
     }
 
+    #[test]
+    fn double_open_test() {
+        let (_persister, _temp_dir, path) = make_temp_persister();
+        assert!(matches!(KVJsonPersister::new(path.as_str()), Err(Error::Refused(_))));
+    }
+
+    #[test]
+    fn path_edge_cases_test() {
+        let dir = TempDir::new().unwrap();
+        for path in vec![dir.path().join("with space"), dir.path().join("a").join("b")] {
+            {
+                let persister = KVJsonPersister::new(&path).unwrap();
+                persister
+                    .new_node(&make_dummy_pubkey(0x12), &TEST_NODE_CONFIG, &[3u8; 32])
+                    .unwrap();
+            }
+            let persister = KVJsonPersister::new(&path).unwrap();
+            assert_eq!(persister.get_nodes().unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn update_batch_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let (node_id, node, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let channel_nonce1 = "nonce1".as_bytes().to_vec();
+        let channel_id1 = channel_nonce_to_id(&channel_nonce1);
+        node.new_channel(Some(channel_id1), Some(channel_nonce1), &node).unwrap();
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed).unwrap();
+        persister.new_channel(&node_id, &stub).unwrap();
+
+        let setup = create_test_channel_setup(make_dummy_pubkey(0x12));
+        let channel = node.ready_channel(channel_id0, None, setup.clone(), &vec![]).unwrap();
+        let unknown_channel = node.ready_channel(channel_id1, None, setup, &vec![]).unwrap();
+        let tracker = node.get_tracker();
+
+        // nothing is written if a channel doesn't exist
+        let batch =
+            PersistBatch { tracker: Some(&tracker), channels: vec![&channel, &unknown_channel] };
+        assert!(persister.update_batch(&node_id, &batch).is_err());
+        assert!(persister.get_tracker(&node_id).is_err());
+        assert!(persister.export_since(1).unwrap().is_empty());
+
+        let batch = PersistBatch { tracker: Some(&tracker), channels: vec![&channel] };
+        persister.update_batch(&node_id, &batch).unwrap();
+        assert_eq!(persister.get_tracker(&node_id).unwrap().height(), tracker.height());
+        let changes = persister.export_since(1).unwrap();
+        assert_eq!((changes.len(), changes[0].sequence, changes[0].id0), (1, 2, channel_id0));
+        assert!(persister.get_channel(&node_id, &channel_id0).unwrap().channel_setup.is_some());
+    }
+
     #[test]
     fn round_trip_signer_test() {
         let channel_nonce = "nonce0".as_bytes().to_vec();
@@ -245,8 +854,8 @@ mod tests {
         let (temp_dir, path) = {
             let (persister, temp_dir, path) = make_temp_persister();
             let persister: Arc<dyn Persist> = Arc::new(persister);
-            persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
-            persister.new_chain_tracker(&node_id, &node.get_tracker());
+            persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed).unwrap();
+            persister.new_chain_tracker(&node_id, &node.get_tracker()).unwrap();
             persister.new_channel(&node_id, &stub).unwrap();
 
             let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory.clone());
@@ -291,20 +900,305 @@ mod tests {
         };
 
         {
-            let persister1 = KVJsonPersister::new(path.as_str());
-            let nodes = persister1.get_nodes();
+            let persister1 = KVJsonPersister::new(path.as_str()).unwrap();
+            let nodes = persister1.get_nodes().unwrap();
             assert_eq!(nodes.len(), 1);
         }
 
         drop(temp_dir);
 
         {
-            let persister1 = KVJsonPersister::new(path.as_str());
-            let nodes = persister1.get_nodes();
+            let persister1 = KVJsonPersister::new(path.as_str()).unwrap();
+            let nodes = persister1.get_nodes().unwrap();
             assert_eq!(nodes.len(), 0);
         }
     }
 
+    #[test]
+    fn metadata_test() {
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        let node_id = make_dummy_pubkey(0x12);
+        let other_node_id = make_dummy_pubkey(0x13);
+        let channel_id = channel_nonce_to_id(&"nonce0".as_bytes().to_vec());
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &[3u8; 32]).unwrap();
+        persister.new_node(&other_node_id, &TEST_NODE_CONFIG, &[4u8; 32]).unwrap();
+
+        assert!(persister.get_metadata(&node_id, None).unwrap().is_empty());
+        let node_metadata = vec![("customer".to_string(), "c1".to_string())];
+        let channel_metadata = vec![("cohort".to_string(), "a".to_string())];
+        persister.update_metadata(&node_id, None, node_metadata.clone()).unwrap();
+        persister.update_metadata(&node_id, Some(&channel_id), channel_metadata.clone()).unwrap();
+        persister.update_metadata(&other_node_id, None, channel_metadata.clone()).unwrap();
+        assert_eq!(persister.get_metadata(&node_id, None).unwrap(), node_metadata);
+        assert_eq!(persister.get_metadata(&node_id, Some(&channel_id)).unwrap(), channel_metadata);
+
+        persister.delete_node(&node_id).unwrap();
+        assert!(persister.get_metadata(&node_id, None).unwrap().is_empty());
+        assert!(persister.get_metadata(&node_id, Some(&channel_id)).unwrap().is_empty());
+        assert_eq!(persister.get_metadata(&other_node_id, None).unwrap(), channel_metadata);
+    }
+
+    #[test]
+    fn chain_params_test() {
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        let node_id = make_dummy_pubkey(0x12);
+        let other_node_id = make_dummy_pubkey(0x13);
+        let mut config = TEST_NODE_CONFIG;
+        config.network = Network::Signet;
+        config.chain_params = Some(ChainParams::for_network(Network::Signet));
+        persister.new_node(&node_id, &config, &[3u8; 32]).unwrap();
+        persister.new_node(&other_node_id, &TEST_NODE_CONFIG, &[4u8; 32]).unwrap();
+
+        let nodes: BTreeMap<_, _> = persister.get_nodes().unwrap().into_iter().collect();
+        assert_eq!(nodes.get(&node_id).unwrap().chain_params, config.chain_params);
+        assert_eq!(nodes.get(&other_node_id).unwrap().chain_params, None);
+    }
+
+    #[test]
+    fn delete_channel_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let (node_id, _node_arc, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed).unwrap();
+        persister.new_channel(&node_id, &stub).unwrap();
+        let metadata = vec![("cohort".to_string(), "a".to_string())];
+        persister.update_metadata(&node_id, Some(&channel_id0), metadata).unwrap();
+
+        persister.delete_channel(&node_id, &channel_id0).unwrap();
+        assert!(persister.get_channel(&node_id, &channel_id0).is_err());
+        assert!(persister.get_node_channels(&node_id).unwrap().is_empty());
+        assert!(persister.get_metadata(&node_id, Some(&channel_id0)).unwrap().is_empty());
+        assert!(persister.delete_channel(&node_id, &channel_id0).is_err());
+    }
+
+    #[test]
+    fn export_since_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let (node_id, node, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let channel_nonce1 = "nonce1".as_bytes().to_vec();
+        let channel_id1 = channel_nonce_to_id(&channel_nonce1);
+        let (_, stub1) = node.new_channel(Some(channel_id1), Some(channel_nonce1), &node).unwrap();
+        let (persister, _temp_dir, path) = make_temp_persister();
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed).unwrap();
+        persister.new_channel(&node_id, &stub).unwrap();
+        persister.new_channel(&node_id, &stub1.unwrap()).unwrap();
+        let sequences: Vec<u64> =
+            persister.export_since(0).unwrap().iter().map(|c| c.sequence).collect();
+        assert_eq!(sequences, vec![1, 2]);
+
+        let setup = create_test_channel_setup(make_dummy_pubkey(0x12));
+        let channel = node.ready_channel(channel_id0, None, setup, &vec![]).unwrap();
+        persister.update_channel(&node_id, &channel).unwrap();
+        persister.delete_channel(&node_id, &channel_id1).unwrap();
+
+        // only the changes since the last export, and each channel once
+        for since in vec![0, 2] {
+            let changes = persister.export_since(since).unwrap();
+            assert_eq!(changes.len(), 2);
+            assert_eq!((changes[0].sequence, changes[0].id0), (3, channel_id0));
+            match &changes[0].change {
+                ChannelChange::Update(entry) => assert!(entry.channel_setup.is_some()),
+                ChannelChange::Delete => panic!("expected update"),
+            }
+            assert_eq!((changes[1].sequence, changes[1].id0), (4, channel_id1));
+            assert!(matches!(changes[1].change, ChannelChange::Delete));
+        }
+        assert!(persister.export_since(4).unwrap().is_empty());
+
+        // an entry written before sequence numbers is numbered when opened
+        let id = NodeChannelId::new(&node_id, &channel_id0);
+        let mut entry = persister.channel_bucket.get(id.clone()).unwrap().unwrap().0;
+        entry.sequence = 0;
+        persister.channel_bucket.set(id, Json(entry)).unwrap();
+        drop(persister);
+        let persister = KVJsonPersister::new(path.as_str()).unwrap();
+        let changes = persister.export_since(4).unwrap();
+        assert_eq!((changes.len(), changes[0].sequence), (1, 5));
+
+        persister.update_channel(&node_id, &channel).unwrap();
+        assert_eq!(persister.export_since(5).unwrap()[0].sequence, 6);
+    }
+
+    #[test]
+    fn backup_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let (node_id, node, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let allowlist = vec!["tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z".to_string()];
+        let fee_reserve = vec![OutPoint { txid: Default::default(), vout: 1 }];
+        let payment = make_payment(1, PaymentResolution::Fulfilled);
+        let archive = {
+            let (persister, _temp_dir, _path) = make_temp_persister();
+            persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed).unwrap();
+            persister.new_chain_tracker(&node_id, &node.get_tracker()).unwrap();
+            persister.new_channel(&node_id, &stub).unwrap();
+            let setup = create_test_channel_setup(make_dummy_pubkey(0x12));
+            let channel = node.ready_channel(channel_id0, None, setup, &vec![]).unwrap();
+            persister.update_channel(&node_id, &channel).unwrap();
+            persister.update_node_allowlist(&node_id, allowlist.clone()).unwrap();
+            persister.update_fee_reserve(&node_id, fee_reserve.clone()).unwrap();
+            persister.update_payment(&node_id, &payment).unwrap();
+            persister.export_all("secret").unwrap()
+        };
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        assert!(matches!(persister.import_all("wrong", &archive), Err(Error::Corrupt(_))));
+        assert!(persister.get_nodes().unwrap().is_empty());
+        assert_eq!(persister.import_all("secret", &archive).unwrap(), vec![node_id]);
+        assert_eq!(persister.get_node_allowlist(&node_id).unwrap(), allowlist);
+        assert_eq!(persister.get_fee_reserve(&node_id).unwrap(), fee_reserve);
+        assert_eq!(persister.get_payments(&node_id).unwrap(), vec![payment]);
+        assert_eq!(persister.get_tracker(&node_id).unwrap().height(), node.get_tracker().height());
+        assert!(persister.get_channel(&node_id, &channel_id0).unwrap().channel_setup.is_some());
+        let sequences: Vec<u64> =
+            persister.export_since(0).unwrap().iter().map(|c| c.sequence).collect();
+        assert_eq!(sequences, vec![1]);
+
+        // existing nodes are not overwritten
+        assert!(matches!(persister.import_all("secret", &archive), Err(Error::AlreadyExists(_))));
+    }
+
+    #[test]
+    fn audit_log_test() {
+        let (persister, _temp_dir, path) = make_temp_persister();
+        let persister = persister.with_audit_retention(2);
+        let node_id = make_dummy_pubkey(0x12);
+        let other_node_id = make_dummy_pubkey(0x13);
+        let channel_id = channel_nonce_to_id(&"nonce0".as_bytes().to_vec());
+        let record = |channel_id: Option<ChannelId>, commit_num| {
+            AuditRecord::new(
+                channel_id,
+                "sign_counterparty_commitment_tx",
+                Some(commit_num),
+                vec![1000, 2000],
+                vec![],
+            )
+        };
+
+        for commit_num in 0..3 {
+            persister.append_audit_record(&node_id, &record(Some(channel_id), commit_num)).unwrap();
+        }
+        let mut onchain = AuditRecord::new(None, "sign_onchain_tx", None, vec![], vec![]);
+        onchain.error = Some("policy failure: validate_onchain_tx: bad".to_string());
+        persister.append_audit_record(&node_id, &onchain).unwrap();
+        persister.append_audit_record(&other_node_id, &record(None, 0)).unwrap();
+
+        // the oldest record of the channel was dropped
+        let log = persister.get_audit_log(&node_id, Some(&channel_id)).unwrap();
+        assert_eq!(log.iter().map(|r| r.commit_num).collect::<Vec<_>>(), vec![Some(1), Some(2)]);
+        assert_eq!(log[0].channel_id, Some(channel_id));
+        assert_eq!(log[0].values_sat, vec![1000, 2000]);
+        assert!(log[0].timestamp > 0);
+
+        let log = persister.get_audit_log(&node_id, None).unwrap();
+        assert_eq!(log.iter().map(|r| r.sequence).collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(log[2].error, onchain.error);
+
+        // sequence numbers continue after a restart
+        drop(persister);
+        let persister = KVJsonPersister::new(path.as_str()).unwrap();
+        persister.append_audit_record(&node_id, &record(Some(channel_id), 3)).unwrap();
+        assert_eq!(
+            persister.get_audit_log(&node_id, Some(&channel_id)).unwrap().last().unwrap().sequence,
+            6
+        );
+
+        persister.delete_node(&node_id).unwrap();
+        assert!(persister.get_audit_log(&node_id, None).unwrap().is_empty());
+        assert_eq!(persister.get_audit_log(&other_node_id, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn close_plan_test() {
+        let (persister, _temp_dir, path) = make_temp_persister();
+        let node_id = make_dummy_pubkey(0x12);
+        assert!(persister.get_close_plan(&node_id).unwrap().is_none());
+
+        let channel_id = channel_nonce_to_id(&"nonce0".as_bytes().to_vec());
+        let mut plan = ClosePlan::new(
+            vec![PlannedClose {
+                channel_id,
+                to_holder_value_sat: 2000,
+                to_counterparty_value_sat: 1000,
+                holder_script: Some(Script::new()),
+                counterparty_script: None,
+                holder_wallet_path_hint: vec![7],
+                signed: false,
+            }],
+            500,
+            2000,
+        );
+        plan.approved = true;
+        persister.update_close_plan(&node_id, Some(&plan)).unwrap();
+
+        // the plan survives a restart
+        drop(persister);
+        let persister = KVJsonPersister::new(path.as_str()).unwrap();
+        assert_eq!(persister.get_close_plan(&node_id).unwrap(), Some(plan));
+        assert!(persister.get_close_plan(&make_dummy_pubkey(0x13)).unwrap().is_none());
+
+        persister.update_close_plan(&node_id, None).unwrap();
+        assert!(persister.get_close_plan(&node_id).unwrap().is_none());
+    }
+
+    #[test]
+    fn fee_reserve_test() {
+        let (persister, _temp_dir, path) = make_temp_persister();
+        let node_id = make_dummy_pubkey(0x12);
+        assert!(persister.get_fee_reserve(&node_id).unwrap().is_empty());
+
+        let reserve = vec![
+            OutPoint { txid: Default::default(), vout: 0 },
+            OutPoint { txid: Default::default(), vout: 3 },
+        ];
+        persister.update_fee_reserve(&node_id, reserve.clone()).unwrap();
+
+        // the reserve survives a restart
+        drop(persister);
+        let persister = KVJsonPersister::new(path.as_str()).unwrap();
+        assert_eq!(persister.get_fee_reserve(&node_id).unwrap(), reserve);
+        assert!(persister.get_fee_reserve(&make_dummy_pubkey(0x13)).unwrap().is_empty());
+
+        persister.delete_node(&node_id).unwrap();
+        assert!(persister.get_fee_reserve(&node_id).unwrap().is_empty());
+    }
+
+    fn make_payment(hash: u8, resolution: PaymentResolution) -> PaymentLedgerEntry {
+        PaymentLedgerEntry {
+            payment_hash: PaymentHash([hash; 32]),
+            outgoing: true,
+            amount_msat: 100_000,
+            invoice_hash: [hash + 1; 32],
+            expiry_time: 123_456_789,
+            resolution,
+        }
+    }
+
+    #[test]
+    fn payment_ledger_test() {
+        let (persister, _temp_dir, path) = make_temp_persister();
+        let node_id = make_dummy_pubkey(0x12);
+        assert!(persister.get_payments(&node_id).unwrap().is_empty());
+
+        let paid = make_payment(1, PaymentResolution::Pending);
+        let unpaid = make_payment(2, PaymentResolution::Pending);
+        persister.update_payment(&node_id, &paid).unwrap();
+        persister.update_payment(&node_id, &unpaid).unwrap();
+        let paid = PaymentLedgerEntry { resolution: PaymentResolution::Fulfilled, ..paid };
+        persister.update_payment(&node_id, &paid).unwrap();
+
+        // the ledger survives a restart
+        drop(persister);
+        let persister = KVJsonPersister::new(path.as_str()).unwrap();
+        assert_eq!(persister.get_payments(&node_id).unwrap(), vec![paid, unpaid]);
+        assert!(persister.get_payments(&make_dummy_pubkey(0x13)).unwrap().is_empty());
+
+        persister.delete_node(&node_id).unwrap();
+        assert!(persister.get_payments(&node_id).unwrap().is_empty());
+    }
+
     fn check_signer_roundtrip(existing_signer: &InMemorySigner, signer: &InMemorySigner) {
         let mut existing_w = VecWriter(Vec::new());
         existing_signer.write(&mut existing_w).unwrap();
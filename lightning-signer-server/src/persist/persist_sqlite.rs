@@ -0,0 +1,1438 @@
+use std::convert::TryInto;
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use kv::Json;
+use log::{error, info};
+use rusqlite::{params, Connection, ErrorCode, OpenFlags, OptionalExtension, Transaction};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
+use lightning_signer::chain::tracker::ChainTracker;
+use lightning_signer::channel::{Channel, ChannelId, ChannelStub};
+use lightning_signer::close_plan::ClosePlan;
+use lightning_signer::monitor::ChainMonitor;
+use lightning_signer::node::NodeConfig;
+use lightning_signer::persist::model::{
+    AuditRecord, ChangeRecord, ChannelChange, ChannelEntry as CoreChannelEntry,
+    NodeEntry as CoreNodeEntry, PaymentLedgerEntry,
+};
+use lightning_signer::persist::{Error, Persist, PersistBatch};
+use lightning_signer::policy::validator::EnforcementState;
+
+use crate::persist::backup::{Archive, ChannelArchive, NodeArchive};
+use crate::persist::lock::DirLock;
+use crate::persist::model::{
+    AllowlistItemEntry, AuditRecordEntry, ChainTrackerEntry, ChannelEntry, ClosePlanEntry,
+    FeeReserveEntry, MetadataEntry, NodeChannelId, NodeEntry, PaymentEntry,
+};
+use crate::persist::persist_json::{lock_error, KVJsonPersister, DEFAULT_AUDIT_RETENTION};
+use crate::persist::versioned::Versioned;
+
+/// The name of the database file in the data directory
+pub const DB_FILE_NAME: &str = "signer.sqlite3";
+
+/// The schema migrations, in order.  The schema version of a database,
+/// kept in its `user_version`, is the number of migrations applied.
+const MIGRATIONS: [&str; 3] = [
+    // Version 1 - the values are the JSON entries of the kv store
+    "CREATE TABLE nodes (node_id BLOB PRIMARY KEY, entry TEXT NOT NULL);
+     CREATE TABLE channels (node_id BLOB NOT NULL, channel_id BLOB NOT NULL,
+        sequence INTEGER NOT NULL, entry TEXT NOT NULL, PRIMARY KEY (node_id, channel_id));
+     CREATE INDEX channels_sequence ON channels (sequence);
+     CREATE TABLE channel_tombstones (node_id BLOB NOT NULL, channel_id BLOB NOT NULL,
+        sequence INTEGER NOT NULL, PRIMARY KEY (node_id, channel_id));
+     CREATE TABLE allowlists (node_id BLOB PRIMARY KEY, entry TEXT NOT NULL);
+     CREATE TABLE chain_trackers (node_id BLOB PRIMARY KEY, entry TEXT NOT NULL);
+     CREATE TABLE metadata (node_id BLOB NOT NULL, channel_id BLOB NOT NULL,
+        entry TEXT NOT NULL, PRIMARY KEY (node_id, channel_id));
+     CREATE TABLE audit_log (sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+        node_id BLOB NOT NULL, channel_id BLOB, entry TEXT NOT NULL);
+     CREATE INDEX audit_log_channel ON audit_log (node_id, channel_id, sequence);
+     CREATE TABLE close_plans (node_id BLOB PRIMARY KEY, entry TEXT NOT NULL);",
+    // Version 2 - wallet UTXOs reserved for fee bumping
+    "CREATE TABLE fee_reserves (node_id BLOB PRIMARY KEY, entry TEXT NOT NULL);",
+    // Version 3 - the payment ledger
+    "CREATE TABLE payments (node_id BLOB NOT NULL, payment_hash BLOB NOT NULL,
+        entry TEXT NOT NULL, PRIMARY KEY (node_id, payment_hash));",
+];
+
+/// The current schema version
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+/// A migration error
+#[derive(Debug)]
+pub enum MigrateError {
+    /// Could not open the kv store or the database, or encode an entry
+    Persist(Error),
+    /// Could not read the kv store
+    Store(kv::Error),
+    /// Could not write the database
+    Database(rusqlite::Error),
+    /// The database already has nodes
+    NotEmpty,
+}
+
+impl From<Error> for MigrateError {
+    fn from(e: Error) -> Self {
+        MigrateError::Persist(e)
+    }
+}
+
+impl From<kv::Error> for MigrateError {
+    fn from(e: kv::Error) -> Self {
+        MigrateError::Store(e)
+    }
+}
+
+impl From<rusqlite::Error> for MigrateError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrateError::Database(e)
+    }
+}
+
+impl Display for MigrateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(format!("{:?}", self).as_str())
+    }
+}
+
+impl std::error::Error for MigrateError {}
+
+/// A persister that keeps everything in a single SQLite database file.
+///
+/// The data directory is locked for as long as the persister is alive.
+/// Each write is a transaction, so a crash leaves either the old or the
+/// new state, never a partial change.  The values are the same JSON
+/// entries as in [KVJsonPersister], and [migrate_from_kv_json] copies
+/// an existing kv store into a new database.
+///
+/// Channel sequence numbers, tombstones and audit log retention behave
+/// as in [KVJsonPersister].
+pub struct SqlitePersister {
+    conn: Mutex<Connection>,
+    /// The number of audit records kept per channel, zero to keep all
+    pub audit_retention: usize,
+    _lock: Option<DirLock>,
+}
+
+impl SqlitePersister {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let lock = DirLock::acquire(path).map_err(lock_error)?;
+        let conn = open_database(path)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            audit_retention: DEFAULT_AUDIT_RETENTION,
+            _lock: Some(lock),
+        })
+    }
+
+    /// Open an existing database read-only, without locking the data
+    /// directory, so that it can be inspected while a server is using it.
+    ///
+    /// The schema is not upgraded, so the database must be at the current
+    /// schema version.  Writes fail.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().join(DB_FILE_NAME);
+        if !path.is_file() {
+            return Err(Error::NotFound(format!("database {}", path.display())));
+        }
+        let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(db_error)?;
+        let version: i64 =
+            conn.query_row("PRAGMA user_version", [], |row| row.get(0)).map_err(db_error)?;
+        if version as usize != SCHEMA_VERSION {
+            return Err(Error::Refused(format!(
+                "database schema version {} is not the supported {}",
+                version, SCHEMA_VERSION
+            )));
+        }
+        Ok(Self { conn: Mutex::new(conn), audit_retention: DEFAULT_AUDIT_RETENTION, _lock: None })
+    }
+
+    /// Keep the given number of audit records per channel, zero to keep all
+    pub fn with_audit_retention(mut self, audit_retention: usize) -> Self {
+        self.audit_retention = audit_retention;
+        self
+    }
+
+    // Delete a channel entry, leaving a tombstone
+    fn remove_channel(tx: &Transaction, node_id: &[u8], channel_id: &[u8]) -> rusqlite::Result<()> {
+        let sequence = next_sequence(tx)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO channel_tombstones (node_id, channel_id, sequence) \
+             VALUES (?1, ?2, ?3)",
+            params![node_id, channel_id, sequence as i64],
+        )?;
+        tx.execute(
+            "DELETE FROM channels WHERE node_id = ?1 AND channel_id = ?2",
+            params![node_id, channel_id],
+        )?;
+        Ok(())
+    }
+
+    // Drop the oldest audit records of a channel, beyond the retention
+    fn prune_audit_log(
+        &self,
+        tx: &Transaction,
+        node_id: &[u8],
+        channel_id: Option<&[u8]>,
+    ) -> rusqlite::Result<()> {
+        if self.audit_retention == 0 {
+            return Ok(());
+        }
+        tx.execute(
+            "DELETE FROM audit_log WHERE node_id = ?1 AND channel_id IS ?2 AND sequence NOT IN \
+             (SELECT sequence FROM audit_log WHERE node_id = ?1 AND channel_id IS ?2 \
+              ORDER BY sequence DESC LIMIT ?3)",
+            params![node_id, channel_id, self.audit_retention as i64],
+        )?;
+        Ok(())
+    }
+}
+
+// Open the database in a directory, creating or upgrading the schema
+fn open_database(path: &Path) -> Result<Connection, Error> {
+    let mut conn = Connection::open(path.join(DB_FILE_NAME)).map_err(db_error)?;
+    let version: i64 =
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0)).map_err(db_error)?;
+    let version = version as usize;
+    if version > SCHEMA_VERSION {
+        return Err(Error::Internal(format!(
+            "database schema version {} is newer than supported {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+    if version < SCHEMA_VERSION {
+        let tx = conn.transaction().map_err(db_error)?;
+        for migration in &MIGRATIONS[version..] {
+            tx.execute_batch(migration).map_err(db_error)?;
+        }
+        tx.pragma_update(None, "user_version", &(SCHEMA_VERSION as i64)).map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+        info!("database schema upgraded from version {} to {}", version, SCHEMA_VERSION);
+    }
+    Ok(conn)
+}
+
+// The next channel sequence number, after the last change or deletion
+fn next_sequence(tx: &Transaction) -> rusqlite::Result<u64> {
+    let last: i64 = tx.query_row(
+        "SELECT COALESCE(MAX(sequence), 0) FROM \
+         (SELECT MAX(sequence) AS sequence FROM channels \
+          UNION ALL SELECT MAX(sequence) FROM channel_tombstones)",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(last as u64 + 1)
+}
+
+// Update an existing channel entry with the next sequence number
+fn write_channel(tx: &Transaction, node_id: &PublicKey, channel: &Channel) -> Result<(), Error> {
+    let sequence = next_sequence(tx).map_err(db_error)?;
+    let entry = ChannelEntry {
+        version: ChannelEntry::VERSION,
+        nonce: channel.nonce.clone(),
+        channel_value_satoshis: channel.setup.channel_value_sat,
+        channel_setup: Some(channel.setup.clone()),
+        id: channel.id,
+        enforcement_state: channel.enforcement_state.clone(),
+        sequence,
+    };
+    let updated = tx
+        .execute(
+            "UPDATE channels SET sequence = ?3, entry = ?4 WHERE node_id = ?1 AND channel_id = ?2",
+            params![
+                node_id.serialize().to_vec(),
+                channel.id0.0.to_vec(),
+                sequence as i64,
+                to_json(&entry)?
+            ],
+        )
+        .map_err(db_error)?;
+    if updated == 0 {
+        return Err(Error::NotFound(format!("channel {}", channel.id0)));
+    }
+    Ok(())
+}
+
+// Busy, locked or failing storage may clear up, so that the operation
+// can be retried
+fn db_error(e: rusqlite::Error) -> Error {
+    error!("database error: {}", e);
+    match &e {
+        rusqlite::Error::SqliteFailure(failure, _)
+            if matches!(
+                failure.code,
+                ErrorCode::DatabaseBusy
+                    | ErrorCode::DatabaseLocked
+                    | ErrorCode::DiskFull
+                    | ErrorCode::SystemIoFailure
+                    | ErrorCode::CannotOpen
+            ) =>
+        {
+            Error::Unavailable(e.to_string())
+        }
+        _ => Error::Internal(e.to_string()),
+    }
+}
+
+fn to_channel_id(bytes: &[u8]) -> Result<ChannelId, Error> {
+    let id = bytes
+        .try_into()
+        .map_err(|_| Error::Corrupt(format!("channel id {}", hex::encode(bytes))))?;
+    Ok(ChannelId(id))
+}
+
+fn to_node_id(bytes: &[u8]) -> Result<PublicKey, Error> {
+    PublicKey::from_slice(bytes)
+        .map_err(|e| Error::Corrupt(format!("node id {}: {}", hex::encode(bytes), e)))
+}
+
+// Node metadata has an empty channel ID
+fn metadata_channel_id(channel_id: Option<&ChannelId>) -> Vec<u8> {
+    channel_id.map(|c| c.0.to_vec()).unwrap_or_default()
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, Error> {
+    serde_json::to_string(value).map_err(|e| Error::Internal(format!("serialize entry: {}", e)))
+}
+
+fn from_json<T: DeserializeOwned>(json: String) -> Result<T, Error> {
+    serde_json::from_str(&json).map_err(|e| Error::Corrupt(format!("deserialize entry: {}", e)))
+}
+
+impl Persist for SqlitePersister {
+    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]) -> Result<(), Error> {
+        let entry = NodeEntry::new(
+            seed.to_vec(),
+            config.key_derivation_style as u8,
+            config.network.to_string(),
+            &config.chain_params,
+        );
+        let conn = self.conn.lock().unwrap();
+        let inserted = conn
+            .execute(
+                "INSERT OR IGNORE INTO nodes (node_id, entry) VALUES (?1, ?2)",
+                params![node_id.serialize().to_vec(), to_json(&entry)?],
+            )
+            .map_err(db_error)?;
+        if inserted == 0 {
+            return Err(Error::AlreadyExists(format!("node {}", node_id)));
+        }
+        Ok(())
+    }
+
+    fn delete_node(&self, node_id: &PublicKey) -> Result<(), Error> {
+        let key = node_id.serialize().to_vec();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(db_error)?;
+        let mut channel_ids: Vec<Vec<u8>> = Vec::new();
+        {
+            let mut stmt = tx
+                .prepare("SELECT channel_id FROM channels WHERE node_id = ?1")
+                .map_err(db_error)?;
+            let rows = stmt.query_map(params![key], |row| row.get(0)).map_err(db_error)?;
+            for r in rows {
+                channel_ids.push(r.map_err(db_error)?);
+            }
+        }
+        for channel_id in channel_ids {
+            Self::remove_channel(&tx, &key, &channel_id).map_err(db_error)?;
+        }
+        for table in [
+            "metadata",
+            "audit_log",
+            "nodes",
+            "close_plans",
+            "fee_reserves",
+            "payments",
+            "chain_trackers",
+        ]
+        .iter()
+        {
+            tx.execute(&format!("DELETE FROM {} WHERE node_id = ?1", table), params![key])
+                .map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)
+    }
+
+    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), Error> {
+        let channel_value_satoshis = 0; // TODO not known yet
+
+        let key = node_id.serialize().to_vec();
+        let channel_id = stub.id0.0.to_vec();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(db_error)?;
+        let sequence = next_sequence(&tx).map_err(db_error)?;
+        let entry = ChannelEntry {
+            version: ChannelEntry::VERSION,
+            nonce: stub.nonce.clone(),
+            channel_value_satoshis,
+            channel_setup: None,
+            id: None,
+            enforcement_state: EnforcementState::new(0),
+            sequence,
+        };
+        let inserted = tx
+            .execute(
+                "INSERT OR IGNORE INTO channels (node_id, channel_id, sequence, entry) \
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![key, channel_id, sequence as i64, to_json(&entry)?],
+            )
+            .map_err(db_error)?;
+        if inserted == 0 {
+            return Err(Error::AlreadyExists(format!("channel {}", stub.id0)));
+        }
+        // A channel ID can be reused after the channel is deleted
+        tx.execute(
+            "DELETE FROM channel_tombstones WHERE node_id = ?1 AND channel_id = ?2",
+            params![key, channel_id],
+        )
+        .map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+        Ok(())
+    }
+
+    fn new_chain_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        let conn = self.conn.lock().unwrap();
+        let inserted = conn
+            .execute(
+                "INSERT OR IGNORE INTO chain_trackers (node_id, entry) VALUES (?1, ?2)",
+                params![node_id.serialize().to_vec(), to_json(&ChainTrackerEntry::from(tracker))?],
+            )
+            .map_err(db_error)?;
+        if inserted == 0 {
+            return Err(Error::AlreadyExists(format!("tracker of node {}", node_id)));
+        }
+        Ok(())
+    }
+
+    fn update_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO chain_trackers (node_id, entry) VALUES (?1, ?2)",
+            params![node_id.serialize().to_vec(), to_json(&ChainTrackerEntry::from(tracker))?],
+        )
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let json: String = conn
+            .query_row(
+                "SELECT entry FROM chain_trackers WHERE node_id = ?1",
+                params![node_id.serialize().to_vec()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?
+            .ok_or_else(|| Error::NotFound(format!("tracker of node {}", node_id)))?;
+        let entry: ChainTrackerEntry = from_json(json)?;
+        entry.try_into()
+    }
+
+    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(db_error)?;
+        write_channel(&tx, node_id, channel)?;
+        tx.commit().map_err(db_error)?;
+        Ok(())
+    }
+
+    fn update_batch(&self, node_id: &PublicKey, batch: &PersistBatch) -> Result<(), Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(db_error)?;
+        if let Some(tracker) = batch.tracker {
+            tx.execute(
+                "INSERT OR REPLACE INTO chain_trackers (node_id, entry) VALUES (?1, ?2)",
+                params![node_id.serialize().to_vec(), to_json(&ChainTrackerEntry::from(tracker))?],
+            )
+            .map_err(db_error)?;
+        }
+        // Dropping the transaction on error rolls it back
+        for channel in batch.channels.iter() {
+            write_channel(&tx, node_id, channel)?;
+        }
+        tx.commit().map_err(db_error)?;
+        Ok(())
+    }
+
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), Error> {
+        let key = node_id.serialize().to_vec();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(db_error)?;
+        let json: String = tx
+            .query_row(
+                "SELECT entry FROM channels WHERE node_id = ?1 AND channel_id = ?2",
+                params![key, id0.0.to_vec()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?
+            .ok_or_else(|| Error::NotFound(format!("channel {}", id0)))?;
+        let entry: ChannelEntry = from_json(json)?;
+        // Metadata may have been set under either channel ID
+        let mut channel_ids = vec![id0.0.to_vec()];
+        channel_ids.extend(entry.id.map(|id| id.0.to_vec()));
+        for channel_id in channel_ids {
+            tx.execute(
+                "DELETE FROM metadata WHERE node_id = ?1 AND channel_id = ?2",
+                params![key, channel_id],
+            )
+            .map_err(db_error)?;
+        }
+        Self::remove_channel(&tx, &key, &id0.0).map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+        Ok(())
+    }
+
+    fn get_channel(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+    ) -> Result<CoreChannelEntry, Error> {
+        let conn = self.conn.lock().unwrap();
+        let json: String = conn
+            .query_row(
+                "SELECT entry FROM channels WHERE node_id = ?1 AND channel_id = ?2",
+                params![node_id.serialize().to_vec(), channel_id.0.to_vec()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?
+            .ok_or_else(|| Error::NotFound(format!("channel {}", channel_id)))?;
+        let entry: ChannelEntry = from_json(json)?;
+        Ok(CoreChannelEntry::from(entry))
+    }
+
+    fn get_node_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Result<Vec<(ChannelId, CoreChannelEntry)>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT channel_id, entry FROM channels WHERE node_id = ?1 ORDER BY channel_id",
+            )
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![node_id.serialize().to_vec()], |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(db_error)?;
+        let mut res = Vec::new();
+        for r in rows {
+            let (channel_id, json) = r.map_err(db_error)?;
+            let entry: ChannelEntry = from_json(json)?;
+            res.push((to_channel_id(&channel_id)?, CoreChannelEntry::from(entry)));
+        }
+        Ok(res)
+    }
+
+    fn update_node_allowlist(
+        &self,
+        node_id: &PublicKey,
+        allowlist: Vec<String>,
+    ) -> Result<(), Error> {
+        let entry = AllowlistItemEntry { allowlist };
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO allowlists (node_id, entry) VALUES (?1, ?2)",
+            params![node_id.serialize().to_vec(), to_json(&entry)?],
+        )
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn get_node_allowlist(&self, node_id: &PublicKey) -> Result<Vec<String>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let json: Option<String> = conn
+            .query_row(
+                "SELECT entry FROM allowlists WHERE node_id = ?1",
+                params![node_id.serialize().to_vec()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?;
+        Ok(match json {
+            Some(json) => from_json::<AllowlistItemEntry>(json)?.allowlist,
+            None => vec![],
+        })
+    }
+
+    fn update_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+        metadata: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        let entry = MetadataEntry { metadata };
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (node_id, channel_id, entry) VALUES (?1, ?2, ?3)",
+            params![
+                node_id.serialize().to_vec(),
+                metadata_channel_id(channel_id),
+                to_json(&entry)?
+            ],
+        )
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn get_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let json: Option<String> = conn
+            .query_row(
+                "SELECT entry FROM metadata WHERE node_id = ?1 AND channel_id = ?2",
+                params![node_id.serialize().to_vec(), metadata_channel_id(channel_id)],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?;
+        Ok(match json {
+            Some(json) => from_json::<MetadataEntry>(json)?.metadata,
+            None => vec![],
+        })
+    }
+
+    fn get_nodes(&self) -> Result<Vec<(PublicKey, CoreNodeEntry)>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT node_id, entry FROM nodes ORDER BY node_id").map_err(db_error)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, String>(1)?)))
+            .map_err(db_error)?;
+        let mut res = Vec::new();
+        for r in rows {
+            let (node_id, json) = r.map_err(db_error)?;
+            let entry: NodeEntry = from_json(json)?;
+            let node_id = to_node_id(&node_id)?;
+            res.push((node_id, CoreNodeEntry::from(entry)));
+        }
+        Ok(res)
+    }
+
+    fn export_since(&self, sequence: u64) -> Result<Vec<ChangeRecord>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut res = Vec::new();
+        let mut stmt = conn
+            .prepare(
+                "SELECT node_id, channel_id, sequence, entry FROM channels WHERE sequence > ?1",
+            )
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![sequence as i64], |row| {
+                Ok((
+                    row.get::<_, Vec<u8>>(0)?,
+                    row.get::<_, Vec<u8>>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .map_err(db_error)?;
+        for r in rows {
+            let (node_id, channel_id, sequence, json) = r.map_err(db_error)?;
+            let entry: ChannelEntry = from_json(json)?;
+            res.push(ChangeRecord {
+                sequence: sequence as u64,
+                node_id: to_node_id(&node_id)?,
+                id0: to_channel_id(&channel_id)?,
+                change: ChannelChange::Update(CoreChannelEntry::from(entry)),
+            });
+        }
+        let mut stmt = conn
+            .prepare(
+                "SELECT node_id, channel_id, sequence FROM channel_tombstones WHERE sequence > ?1",
+            )
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![sequence as i64], |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?, row.get::<_, i64>(2)?))
+            })
+            .map_err(db_error)?;
+        for r in rows {
+            let (node_id, channel_id, sequence) = r.map_err(db_error)?;
+            res.push(ChangeRecord {
+                sequence: sequence as u64,
+                node_id: to_node_id(&node_id)?,
+                id0: to_channel_id(&channel_id)?,
+                change: ChannelChange::Delete,
+            });
+        }
+        res.sort_by_key(|r| r.sequence);
+        Ok(res)
+    }
+
+    fn append_audit_record(&self, node_id: &PublicKey, record: &AuditRecord) -> Result<(), Error> {
+        let key = node_id.serialize().to_vec();
+        let channel_id = record.channel_id.map(|c| c.0.to_vec());
+        let mut record = record.clone();
+        // The sequence number is assigned by the database and filled in
+        // when the record is read
+        record.sequence = 0;
+        record.timestamp =
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(db_error)?;
+        tx.execute(
+            "INSERT INTO audit_log (node_id, channel_id, entry) VALUES (?1, ?2, ?3)",
+            params![key, channel_id, to_json(&AuditRecordEntry(record))?],
+        )
+        .map_err(db_error)?;
+        self.prune_audit_log(&tx, &key, channel_id.as_deref()).map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+        Ok(())
+    }
+
+    fn get_audit_log(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<AuditRecord>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let key = node_id.serialize().to_vec();
+        let mut stmt = conn
+            .prepare(
+                "SELECT sequence, entry FROM audit_log \
+                 WHERE node_id = ?1 AND (?2 IS NULL OR channel_id = ?2) ORDER BY sequence",
+            )
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![key, channel_id.map(|c| c.0.to_vec())], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(db_error)?;
+        let mut res = Vec::new();
+        for r in rows {
+            let (sequence, json) = r.map_err(db_error)?;
+            let mut record = from_json::<AuditRecordEntry>(json)?.0;
+            record.sequence = sequence as u64;
+            res.push(record);
+        }
+        Ok(res)
+    }
+
+    fn update_close_plan(
+        &self,
+        node_id: &PublicKey,
+        plan: Option<&ClosePlan>,
+    ) -> Result<(), Error> {
+        let key = node_id.serialize().to_vec();
+        let conn = self.conn.lock().unwrap();
+        match plan {
+            Some(plan) => conn
+                .execute(
+                    "INSERT OR REPLACE INTO close_plans (node_id, entry) VALUES (?1, ?2)",
+                    params![key, to_json(&ClosePlanEntry(plan.clone()))?],
+                )
+                .map(|_| ())
+                .map_err(db_error)?,
+            None => conn
+                .execute("DELETE FROM close_plans WHERE node_id = ?1", params![key])
+                .map(|_| ())
+                .map_err(db_error)?,
+        }
+        Ok(())
+    }
+
+    fn get_close_plan(&self, node_id: &PublicKey) -> Result<Option<ClosePlan>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let json: Option<String> = conn
+            .query_row(
+                "SELECT entry FROM close_plans WHERE node_id = ?1",
+                params![node_id.serialize().to_vec()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?;
+        Ok(json.map(from_json::<ClosePlanEntry>).transpose()?.map(|e| e.0))
+    }
+
+    fn update_fee_reserve(&self, node_id: &PublicKey, reserve: Vec<OutPoint>) -> Result<(), Error> {
+        let entry = FeeReserveEntry { reserve };
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO fee_reserves (node_id, entry) VALUES (?1, ?2)",
+            params![node_id.serialize().to_vec(), to_json(&entry)?],
+        )
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn get_fee_reserve(&self, node_id: &PublicKey) -> Result<Vec<OutPoint>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let json: Option<String> = conn
+            .query_row(
+                "SELECT entry FROM fee_reserves WHERE node_id = ?1",
+                params![node_id.serialize().to_vec()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?;
+        Ok(match json {
+            Some(json) => from_json::<FeeReserveEntry>(json)?.reserve,
+            None => vec![],
+        })
+    }
+
+    fn update_payment(&self, node_id: &PublicKey, entry: &PaymentLedgerEntry) -> Result<(), Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO payments (node_id, payment_hash, entry) VALUES (?1, ?2, ?3)",
+            params![
+                node_id.serialize().to_vec(),
+                entry.payment_hash.0.to_vec(),
+                to_json(&PaymentEntry(entry.clone()))?
+            ],
+        )
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn get_payments(&self, node_id: &PublicKey) -> Result<Vec<PaymentLedgerEntry>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT entry FROM payments WHERE node_id = ?1 ORDER BY payment_hash")
+            .map_err(db_error)?;
+        let rows = stmt
+            .query_map(params![node_id.serialize().to_vec()], |row| row.get::<_, String>(0))
+            .map_err(db_error)?;
+        let mut res = Vec::new();
+        for r in rows {
+            res.push(from_json::<PaymentEntry>(r.map_err(db_error)?)?.0);
+        }
+        Ok(res)
+    }
+
+    fn export_all(&self, passphrase: &str) -> Result<Vec<u8>, Error> {
+        let mut conn = self.conn.lock().unwrap();
+        // A single transaction, for a consistent snapshot
+        let tx = conn.transaction().map_err(db_error)?;
+        let mut archive = Archive::default();
+        let nodes: Vec<(Vec<u8>, String)> = {
+            let mut stmt = tx
+                .prepare("SELECT node_id, entry FROM nodes ORDER BY node_id")
+                .map_err(db_error)?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, String>(1)?)))
+                .map_err(db_error)?
+                .collect::<rusqlite::Result<_>>();
+            rows.map_err(db_error)?
+        };
+        for (key, json) in nodes {
+            let node_id = to_node_id(&key)?;
+            let allowlist: Option<String> = tx
+                .query_row("SELECT entry FROM allowlists WHERE node_id = ?1", params![key], |row| {
+                    row.get(0)
+                })
+                .optional()
+                .map_err(db_error)?;
+            let fee_reserve: Option<String> = tx
+                .query_row(
+                    "SELECT entry FROM fee_reserves WHERE node_id = ?1",
+                    params![key],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(db_error)?;
+            let tracker: Option<String> = tx
+                .query_row(
+                    "SELECT entry FROM chain_trackers WHERE node_id = ?1",
+                    params![key],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(db_error)?;
+            let payments: Vec<String> = {
+                let mut stmt = tx
+                    .prepare("SELECT entry FROM payments WHERE node_id = ?1 ORDER BY payment_hash")
+                    .map_err(db_error)?;
+                let rows = stmt
+                    .query_map(params![key], |row| row.get::<_, String>(0))
+                    .map_err(db_error)?
+                    .collect::<rusqlite::Result<_>>();
+                rows.map_err(db_error)?
+            };
+            let channels: Vec<(Vec<u8>, String)> = {
+                let mut stmt = tx
+                    .prepare(
+                        "SELECT channel_id, entry FROM channels WHERE node_id = ?1 \
+                         ORDER BY channel_id",
+                    )
+                    .map_err(db_error)?;
+                let rows = stmt
+                    .query_map(params![key], |row| {
+                        Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, String>(1)?))
+                    })
+                    .map_err(db_error)?
+                    .collect::<rusqlite::Result<_>>();
+                rows.map_err(db_error)?
+            };
+            archive.nodes.push(NodeArchive {
+                node_id,
+                entry: from_json(json)?,
+                allowlist: match allowlist {
+                    Some(json) => from_json::<AllowlistItemEntry>(json)?.allowlist,
+                    None => vec![],
+                },
+                fee_reserve: match fee_reserve {
+                    Some(json) => from_json::<FeeReserveEntry>(json)?.reserve,
+                    None => vec![],
+                },
+                payments: payments
+                    .into_iter()
+                    .map(|json| from_json::<PaymentEntry>(json).map(|e| e.0))
+                    .collect::<Result<_, _>>()?,
+                tracker: tracker.map(from_json).transpose()?,
+                channels: channels
+                    .into_iter()
+                    .map(|(channel_id, json)| {
+                        Ok(ChannelArchive {
+                            channel_id: to_channel_id(&channel_id)?,
+                            entry: from_json(json)?,
+                        })
+                    })
+                    .collect::<Result<_, Error>>()?,
+            });
+        }
+        Ok(archive.seal(passphrase))
+    }
+
+    fn import_all(&self, passphrase: &str, archive: &[u8]) -> Result<Vec<PublicKey>, Error> {
+        let archive = Archive::open(passphrase, archive)?;
+        let mut conn = self.conn.lock().unwrap();
+        // Dropping the transaction on error rolls it back
+        let tx = conn.transaction().map_err(db_error)?;
+        let mut node_ids = Vec::new();
+        for node in archive.nodes {
+            let key = node.node_id.serialize().to_vec();
+            let inserted = tx
+                .execute(
+                    "INSERT OR IGNORE INTO nodes (node_id, entry) VALUES (?1, ?2)",
+                    params![key, to_json(&node.entry)?],
+                )
+                .map_err(db_error)?;
+            if inserted == 0 {
+                return Err(Error::AlreadyExists(format!("node {}", node.node_id)));
+            }
+            // The channels get new sequence numbers, so that replicas of
+            // this database pick them up
+            for channel in node.channels {
+                let channel_id = channel.channel_id.0.to_vec();
+                let mut entry = channel.entry;
+                entry.sequence = next_sequence(&tx).map_err(db_error)?;
+                tx.execute(
+                    "INSERT OR REPLACE INTO channels (node_id, channel_id, sequence, entry) \
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![key, channel_id, entry.sequence as i64, to_json(&entry)?],
+                )
+                .map_err(db_error)?;
+                tx.execute(
+                    "DELETE FROM channel_tombstones WHERE node_id = ?1 AND channel_id = ?2",
+                    params![key, channel_id],
+                )
+                .map_err(db_error)?;
+            }
+            let allowlist = AllowlistItemEntry { allowlist: node.allowlist };
+            tx.execute(
+                "INSERT OR REPLACE INTO allowlists (node_id, entry) VALUES (?1, ?2)",
+                params![key, to_json(&allowlist)?],
+            )
+            .map_err(db_error)?;
+            let fee_reserve = FeeReserveEntry { reserve: node.fee_reserve };
+            tx.execute(
+                "INSERT OR REPLACE INTO fee_reserves (node_id, entry) VALUES (?1, ?2)",
+                params![key, to_json(&fee_reserve)?],
+            )
+            .map_err(db_error)?;
+            for entry in node.payments {
+                tx.execute(
+                    "INSERT OR REPLACE INTO payments (node_id, payment_hash, entry) \
+                     VALUES (?1, ?2, ?3)",
+                    params![key, entry.payment_hash.0.to_vec(), to_json(&PaymentEntry(entry))?],
+                )
+                .map_err(db_error)?;
+            }
+            if let Some(tracker) = node.tracker {
+                tx.execute(
+                    "INSERT OR REPLACE INTO chain_trackers (node_id, entry) VALUES (?1, ?2)",
+                    params![key, to_json(&tracker)?],
+                )
+                .map_err(db_error)?;
+            }
+            node_ids.push(node.node_id);
+        }
+        tx.commit().map_err(db_error)?;
+        Ok(node_ids)
+    }
+
+    fn clear_database(&self) -> Result<(), Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute_batch(
+            "BEGIN;
+             DELETE FROM nodes; DELETE FROM channels; DELETE FROM channel_tombstones;
+             DELETE FROM allowlists; DELETE FROM chain_trackers; DELETE FROM metadata;
+             DELETE FROM audit_log; DELETE FROM close_plans; DELETE FROM fee_reserves;
+             DELETE FROM payments;
+             COMMIT;",
+        )
+        .map_err(db_error)
+    }
+}
+
+/// Copy the kv store in a data directory into a new database in the same
+/// directory, in a single transaction, and return the number of channels
+/// copied.
+///
+/// Sequence numbers, tombstones and the audit log are kept, so that
+/// replicas and audit log verification are not affected.  The kv store
+/// is left in place.  Fails if the database already has nodes.
+pub fn migrate_from_kv_json<P: AsRef<Path>>(path: P) -> Result<usize, MigrateError> {
+    let path = path.as_ref();
+    // The kv persister holds the data directory lock during the migration
+    let kv = KVJsonPersister::new(path)?;
+    let mut conn = open_database(path)?;
+    let tx = conn.transaction()?;
+    let nodes: i64 = tx.query_row("SELECT COUNT(*) FROM nodes", [], |row| row.get(0))?;
+    if nodes > 0 {
+        return Err(MigrateError::NotEmpty);
+    }
+
+    for item_res in kv.node_bucket.iter() {
+        let item = item_res?;
+        let key: Vec<u8> = item.key()?;
+        let value: Json<NodeEntry> = item.value()?;
+        tx.execute(
+            "INSERT INTO nodes (node_id, entry) VALUES (?1, ?2)",
+            params![key, to_json(&value.0)?],
+        )?;
+    }
+    let mut channels = 0;
+    for item_res in kv.channel_bucket.iter() {
+        let item = item_res?;
+        let key: NodeChannelId = item.key()?;
+        let value: Json<ChannelEntry> = item.value()?;
+        tx.execute(
+            "INSERT INTO channels (node_id, channel_id, sequence, entry) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                key.node_id().serialize().to_vec(),
+                key.channel_id().0.to_vec(),
+                value.0.sequence as i64,
+                to_json(&value.0)?
+            ],
+        )?;
+        channels += 1;
+    }
+    for item_res in kv.tombstone_bucket.iter() {
+        let item = item_res?;
+        let key: NodeChannelId = item.key()?;
+        let value: Json<u64> = item.value()?;
+        tx.execute(
+            "INSERT INTO channel_tombstones (node_id, channel_id, sequence) VALUES (?1, ?2, ?3)",
+            params![
+                key.node_id().serialize().to_vec(),
+                key.channel_id().0.to_vec(),
+                value.0 as i64
+            ],
+        )?;
+    }
+    for item_res in kv.allowlist_bucket.iter() {
+        let item = item_res?;
+        let key: Vec<u8> = item.key()?;
+        let value: Json<AllowlistItemEntry> = item.value()?;
+        tx.execute(
+            "INSERT INTO allowlists (node_id, entry) VALUES (?1, ?2)",
+            params![key, to_json(&value.0)?],
+        )?;
+    }
+    for item_res in kv.chain_tracker_bucket.iter() {
+        let item = item_res?;
+        let key: Vec<u8> = item.key()?;
+        let value: Json<ChainTrackerEntry> = item.value()?;
+        tx.execute(
+            "INSERT INTO chain_trackers (node_id, entry) VALUES (?1, ?2)",
+            params![key, to_json(&value.0)?],
+        )?;
+    }
+    for item_res in kv.metadata_bucket.iter() {
+        let item = item_res?;
+        let key: NodeChannelId = item.key()?;
+        let value: Json<MetadataEntry> = item.value()?;
+        // Node metadata is keyed by the node ID alone
+        let channel_id =
+            if key.as_ref().len() > 33 { key.channel_id().0.to_vec() } else { Vec::new() };
+        tx.execute(
+            "INSERT INTO metadata (node_id, channel_id, entry) VALUES (?1, ?2, ?3)",
+            params![key.node_id().serialize().to_vec(), channel_id, to_json(&value.0)?],
+        )?;
+    }
+    for item_res in kv.audit_bucket.iter() {
+        let item = item_res?;
+        let key: Vec<u8> = item.key()?;
+        let value: Json<AuditRecordEntry> = item.value()?;
+        let record = &value.0 .0;
+        tx.execute(
+            "INSERT INTO audit_log (sequence, node_id, channel_id, entry) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                record.sequence as i64,
+                &key[0..33],
+                record.channel_id.map(|c| c.0.to_vec()),
+                to_json(&value.0)?
+            ],
+        )?;
+    }
+    for item_res in kv.close_plan_bucket.iter() {
+        let item = item_res?;
+        let key: Vec<u8> = item.key()?;
+        let value: Json<ClosePlanEntry> = item.value()?;
+        tx.execute(
+            "INSERT INTO close_plans (node_id, entry) VALUES (?1, ?2)",
+            params![key, to_json(&value.0)?],
+        )?;
+    }
+    for item_res in kv.fee_reserve_bucket.iter() {
+        let item = item_res?;
+        let key: Vec<u8> = item.key()?;
+        let value: Json<FeeReserveEntry> = item.value()?;
+        tx.execute(
+            "INSERT INTO fee_reserves (node_id, entry) VALUES (?1, ?2)",
+            params![key, to_json(&value.0)?],
+        )?;
+    }
+    for item_res in kv.payment_bucket.iter() {
+        let item = item_res?;
+        let key: Vec<u8> = item.key()?;
+        let value: Json<PaymentEntry> = item.value()?;
+        let (node_id, payment_hash) = key.split_at(33);
+        tx.execute(
+            "INSERT INTO payments (node_id, payment_hash, entry) VALUES (?1, ?2, ?3)",
+            params![node_id, payment_hash, to_json(&value.0)?],
+        )?;
+    }
+    tx.commit()?;
+    Ok(channels)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tempfile::TempDir;
+    use test_log::test;
+
+    use crate::lightning::ln::PaymentHash;
+    use lightning_signer::channel::{channel_nonce_to_id, ChannelSlot};
+    use lightning_signer::node::Node;
+    use lightning_signer::persist::model::PaymentResolution;
+    use lightning_signer::policy::simple_validator::SimpleValidatorFactory;
+    use lightning_signer::util::test_utils::*;
+
+    use super::*;
+
+    fn make_temp_persister() -> (SqlitePersister, TempDir) {
+        let dir = TempDir::new().unwrap();
+        let persister = SqlitePersister::new(dir.path()).unwrap();
+        (persister, dir)
+    }
+
+    #[test]
+    fn double_open_test() {
+        let (_persister, temp_dir) = make_temp_persister();
+        assert!(matches!(SqlitePersister::new(temp_dir.path()), Err(Error::Refused(_))));
+    }
+
+    #[test]
+    fn schema_test() {
+        let (persister, temp_dir) = make_temp_persister();
+        drop(persister);
+        let conn = Connection::open(temp_dir.path().join(DB_FILE_NAME)).unwrap();
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version as usize, SCHEMA_VERSION);
+
+        // opening again doesn't reapply the migrations
+        drop(conn);
+        let persister = SqlitePersister::new(temp_dir.path()).unwrap();
+        assert!(persister.get_nodes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn open_read_only_test() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(matches!(
+            SqlitePersister::open_read_only(temp_dir.path()),
+            Err(Error::NotFound(_))
+        ));
+
+        // the data directory stays locked by the writer
+        let (persister, temp_dir) = make_temp_persister();
+        let node_id = make_dummy_pubkey(0x12);
+        persister.update_node_allowlist(&node_id, vec!["tb1qexample".to_string()]).unwrap();
+        let reader = SqlitePersister::open_read_only(temp_dir.path()).unwrap();
+        assert_eq!(reader.get_node_allowlist(&node_id).unwrap(), vec!["tb1qexample".to_string()]);
+        assert!(reader.update_node_allowlist(&node_id, vec![]).is_err());
+    }
+
+    #[test]
+    fn corrupt_entry_test() {
+        let (persister, _temp_dir) = make_temp_persister();
+        let node_id = make_dummy_pubkey(0x12);
+        let key = node_id.serialize().to_vec();
+        {
+            let conn = persister.conn.lock().unwrap();
+            conn.execute(
+                "INSERT INTO nodes (node_id, entry) VALUES (?1, ?2)",
+                params![key, "not json"],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO metadata (node_id, channel_id, entry) VALUES (?1, ?2, ?3)",
+                params![key, Vec::<u8>::new(), "{}"],
+            )
+            .unwrap();
+        }
+        assert!(matches!(persister.get_nodes(), Err(Error::Corrupt(_))));
+        assert!(matches!(persister.get_metadata(&node_id, None), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn round_trip_signer_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let (node_id, node, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+
+        let (persister, temp_dir) = make_temp_persister();
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed).unwrap();
+        persister.new_chain_tracker(&node_id, &node.get_tracker()).unwrap();
+        persister.new_channel(&node_id, &stub).unwrap();
+        assert!(persister.new_channel(&node_id, &stub).is_err());
+
+        let setup = create_test_channel_setup(make_dummy_pubkey(0x12));
+        let channel_id1 = channel_nonce_to_id(&"nonce1".as_bytes().to_vec());
+        let channel = node.ready_channel(channel_id0, Some(channel_id1), setup, &vec![]).unwrap();
+        persister.update_channel(&node_id, &channel).unwrap();
+        persister.update_node_allowlist(&node_id, vec!["tb1qexample".to_string()]).unwrap();
+
+        // everything survives a restart
+        drop(persister);
+        let persister: Arc<dyn Persist> = Arc::new(SqlitePersister::new(temp_dir.path()).unwrap());
+        assert_eq!(
+            persister.get_node_allowlist(&node_id).unwrap(),
+            vec!["tb1qexample".to_string()]
+        );
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory);
+        let restored_node = nodes.get(&node_id).unwrap();
+        assert_eq!(restored_node.get_tracker().height(), node.get_tracker().height());
+        let slot = restored_node.get_channel(&channel_id0).unwrap();
+        let guard = slot.lock().unwrap();
+        if let ChannelSlot::Ready(chan) = &*guard {
+            assert_eq!(chan.id, Some(channel_id1));
+            assert_eq!(chan.setup.channel_value_sat, channel.setup.channel_value_sat);
+        } else {
+            panic!("expected ready channel")
+        }
+    }
+
+    #[test]
+    fn update_batch_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let (node_id, node, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let channel_nonce1 = "nonce1".as_bytes().to_vec();
+        let channel_id1 = channel_nonce_to_id(&channel_nonce1);
+        node.new_channel(Some(channel_id1), Some(channel_nonce1), &node).unwrap();
+        let (persister, _temp_dir) = make_temp_persister();
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed).unwrap();
+        persister.new_channel(&node_id, &stub).unwrap();
+
+        let setup = create_test_channel_setup(make_dummy_pubkey(0x12));
+        let channel = node.ready_channel(channel_id0, None, setup.clone(), &vec![]).unwrap();
+        let unknown_channel = node.ready_channel(channel_id1, None, setup, &vec![]).unwrap();
+        let tracker = node.get_tracker();
+
+        // nothing is written if a channel doesn't exist
+        let batch =
+            PersistBatch { tracker: Some(&tracker), channels: vec![&channel, &unknown_channel] };
+        assert!(persister.update_batch(&node_id, &batch).is_err());
+        assert!(persister.get_tracker(&node_id).is_err());
+        assert!(persister.export_since(1).unwrap().is_empty());
+
+        let batch = PersistBatch { tracker: Some(&tracker), channels: vec![&channel] };
+        persister.update_batch(&node_id, &batch).unwrap();
+        assert_eq!(persister.get_tracker(&node_id).unwrap().height(), tracker.height());
+        let changes = persister.export_since(1).unwrap();
+        assert_eq!((changes.len(), changes[0].sequence, changes[0].id0), (1, 2, channel_id0));
+        assert!(persister.get_channel(&node_id, &channel_id0).unwrap().channel_setup.is_some());
+    }
+
+    #[test]
+    fn export_since_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let (node_id, node, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let channel_nonce1 = "nonce1".as_bytes().to_vec();
+        let channel_id1 = channel_nonce_to_id(&channel_nonce1);
+        let (_, stub1) = node.new_channel(Some(channel_id1), Some(channel_nonce1), &node).unwrap();
+        let (persister, _temp_dir) = make_temp_persister();
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed).unwrap();
+        persister.new_channel(&node_id, &stub).unwrap();
+        persister.new_channel(&node_id, &stub1.unwrap()).unwrap();
+        let metadata = vec![("cohort".to_string(), "a".to_string())];
+        persister.update_metadata(&node_id, Some(&channel_id1), metadata).unwrap();
+
+        let setup = create_test_channel_setup(make_dummy_pubkey(0x12));
+        let channel = node.ready_channel(channel_id0, None, setup, &vec![]).unwrap();
+        persister.update_channel(&node_id, &channel).unwrap();
+        persister.delete_channel(&node_id, &channel_id1).unwrap();
+        assert!(persister.delete_channel(&node_id, &channel_id1).is_err());
+        assert!(persister.get_metadata(&node_id, Some(&channel_id1)).unwrap().is_empty());
+
+        let changes = persister.export_since(2).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!((changes[0].sequence, changes[0].id0), (3, channel_id0));
+        assert!(matches!(changes[0].change, ChannelChange::Update(_)));
+        assert_eq!((changes[1].sequence, changes[1].id0), (4, channel_id1));
+        assert!(matches!(changes[1].change, ChannelChange::Delete));
+        assert!(persister.export_since(4).unwrap().is_empty());
+
+        // numbering continues after the last deletion
+        persister.update_channel(&node_id, &channel).unwrap();
+        assert_eq!(persister.export_since(4).unwrap()[0].sequence, 5);
+    }
+
+    #[test]
+    fn audit_log_test() {
+        let (persister, _temp_dir) = make_temp_persister();
+        let persister = persister.with_audit_retention(2);
+        let node_id = make_dummy_pubkey(0x12);
+        let channel_id = channel_nonce_to_id(&"nonce0".as_bytes().to_vec());
+        let record = |channel_id: Option<ChannelId>, commit_num| {
+            AuditRecord::new(
+                channel_id,
+                "sign_counterparty_commitment_tx",
+                Some(commit_num),
+                vec![1000, 2000],
+                vec![],
+            )
+        };
+
+        for commit_num in 0..3 {
+            persister.append_audit_record(&node_id, &record(Some(channel_id), commit_num)).unwrap();
+        }
+        persister.append_audit_record(&node_id, &record(None, 0)).unwrap();
+
+        // the oldest record of the channel was dropped
+        let log = persister.get_audit_log(&node_id, Some(&channel_id)).unwrap();
+        assert_eq!(log.iter().map(|r| r.commit_num).collect::<Vec<_>>(), vec![Some(1), Some(2)]);
+        assert!(log[0].timestamp > 0);
+        let log = persister.get_audit_log(&node_id, None).unwrap();
+        assert_eq!(log.iter().map(|r| r.sequence).collect::<Vec<_>>(), vec![2, 3, 4]);
+
+        persister.delete_node(&node_id).unwrap();
+        assert!(persister.get_audit_log(&node_id, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn backup_from_kv_json_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let (node_id, node, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let fee_reserve = vec![OutPoint { txid: Default::default(), vout: 1 }];
+        let archive = {
+            let temp_dir = TempDir::new().unwrap();
+            let kv = KVJsonPersister::new(temp_dir.path()).unwrap();
+            kv.new_node(&node_id, &TEST_NODE_CONFIG, &seed).unwrap();
+            kv.new_chain_tracker(&node_id, &node.get_tracker()).unwrap();
+            kv.new_channel(&node_id, &stub).unwrap();
+            kv.update_fee_reserve(&node_id, fee_reserve.clone()).unwrap();
+            kv.export_all("secret").unwrap()
+        };
+
+        let (persister, _temp_dir) = make_temp_persister();
+        assert_eq!(persister.import_all("secret", &archive).unwrap(), vec![node_id]);
+        assert!(matches!(persister.import_all("secret", &archive), Err(Error::AlreadyExists(_))));
+        let persister: Arc<dyn Persist> = Arc::new(persister);
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory);
+        let restored_node = nodes.get(&node_id).unwrap();
+        assert!(restored_node.get_channel(&channel_id0).is_ok());
+        assert_eq!(restored_node.fee_reserve(), fee_reserve);
+
+        // and back again
+        let archive = persister.export_all("secret").unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let kv = KVJsonPersister::new(temp_dir.path()).unwrap();
+        assert_eq!(kv.import_all("secret", &archive).unwrap(), vec![node_id]);
+        assert_eq!(kv.get_node_channels(&node_id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn migrate_from_kv_json_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let (node_id, node, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = vec![("customer".to_string(), "c1".to_string())];
+        let payment = PaymentLedgerEntry {
+            payment_hash: PaymentHash([1; 32]),
+            outgoing: true,
+            amount_msat: 100_000,
+            invoice_hash: [2; 32],
+            expiry_time: 123_456_789,
+            resolution: PaymentResolution::Fulfilled,
+        };
+        let (changes, audit_log) = {
+            let kv = KVJsonPersister::new(temp_dir.path()).unwrap();
+            kv.new_node(&node_id, &TEST_NODE_CONFIG, &seed).unwrap();
+            kv.new_chain_tracker(&node_id, &node.get_tracker()).unwrap();
+            kv.new_channel(&node_id, &stub).unwrap();
+            kv.update_metadata(&node_id, None, metadata.clone()).unwrap();
+            kv.update_payment(&node_id, &payment).unwrap();
+            let audit = AuditRecord::new(
+                Some(channel_id0),
+                "sign_holder_commitment_tx",
+                Some(0),
+                vec![],
+                vec![],
+            );
+            kv.append_audit_record(&node_id, &audit).unwrap();
+            (kv.export_since(0).unwrap(), kv.get_audit_log(&node_id, None).unwrap())
+        };
+
+        assert_eq!(migrate_from_kv_json(temp_dir.path()).unwrap(), 1);
+        match migrate_from_kv_json(temp_dir.path()) {
+            Err(MigrateError::NotEmpty) => {}
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let persister = SqlitePersister::new(temp_dir.path()).unwrap();
+        assert_eq!(persister.get_nodes().unwrap().len(), 1);
+        assert!(persister.get_tracker(&node_id).is_ok());
+        assert_eq!(persister.get_metadata(&node_id, None).unwrap(), metadata);
+        let migrated = persister.export_since(0).unwrap();
+        assert_eq!(migrated.len(), changes.len());
+        assert_eq!((migrated[0].sequence, migrated[0].id0), (changes[0].sequence, channel_id0));
+        assert_eq!(persister.get_audit_log(&node_id, None).unwrap(), audit_log);
+        assert_eq!(persister.get_payments(&node_id).unwrap(), vec![payment]);
+    }
+}
@@ -0,0 +1,208 @@
+use std::sync::Arc;
+
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
+use lightning_signer::chain::tracker::ChainTracker;
+use lightning_signer::channel::{Channel, ChannelId, ChannelStub};
+use lightning_signer::close_plan::ClosePlan;
+use lightning_signer::monitor::ChainMonitor;
+use lightning_signer::node::NodeConfig;
+use lightning_signer::persist::{model, Error, Persist};
+use log::warn;
+
+/// A persister wrapper that passes reads through and drops all writes.
+///
+/// Used when inspecting a data directory without modifying it, such as in
+/// the server's `--check` mode.  Writes that can report failure do so.
+pub struct ReadOnlyPersister {
+    inner: Arc<dyn Persist>,
+}
+
+impl ReadOnlyPersister {
+    /// Wrap a persister
+    pub fn new(inner: Arc<dyn Persist>) -> Self {
+        ReadOnlyPersister { inner }
+    }
+}
+
+fn refused() -> Error {
+    Error::Refused("read-only".to_string())
+}
+
+impl Persist for ReadOnlyPersister {
+    fn new_node(
+        &self,
+        node_id: &PublicKey,
+        _config: &NodeConfig,
+        _seed: &[u8],
+    ) -> Result<(), Error> {
+        warn!("read-only: not persisting new node {}", node_id);
+        Err(refused())
+    }
+
+    fn delete_node(&self, node_id: &PublicKey) -> Result<(), Error> {
+        warn!("read-only: not deleting node {}", node_id);
+        Err(refused())
+    }
+
+    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), Error> {
+        warn!("read-only: not persisting new channel {}/{}", node_id, stub.id0);
+        Err(refused())
+    }
+
+    fn new_chain_tracker(
+        &self,
+        node_id: &PublicKey,
+        _tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        warn!("read-only: not persisting new tracker for {}", node_id);
+        Err(refused())
+    }
+
+    fn update_tracker(
+        &self,
+        node_id: &PublicKey,
+        _tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        warn!("read-only: not updating tracker for {}", node_id);
+        Err(refused())
+    }
+
+    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, Error> {
+        self.inner.get_tracker(node_id)
+    }
+
+    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), Error> {
+        warn!("read-only: not updating channel {}/{}", node_id, channel.id0);
+        Err(refused())
+    }
+
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), Error> {
+        warn!("read-only: not deleting channel {}/{}", node_id, id0);
+        Err(refused())
+    }
+
+    fn get_channel(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+    ) -> Result<model::ChannelEntry, Error> {
+        self.inner.get_channel(node_id, channel_id)
+    }
+
+    fn get_node_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Result<Vec<(ChannelId, model::ChannelEntry)>, Error> {
+        self.inner.get_node_channels(node_id)
+    }
+
+    fn update_node_allowlist(
+        &self,
+        node_id: &PublicKey,
+        _allowlist: Vec<String>,
+    ) -> Result<(), Error> {
+        warn!("read-only: not updating allowlist for {}", node_id);
+        Err(refused())
+    }
+
+    fn get_node_allowlist(&self, node_id: &PublicKey) -> Result<Vec<String>, Error> {
+        self.inner.get_node_allowlist(node_id)
+    }
+
+    fn update_metadata(
+        &self,
+        node_id: &PublicKey,
+        _channel_id: Option<&ChannelId>,
+        _metadata: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        warn!("read-only: not updating metadata for {}", node_id);
+        Err(refused())
+    }
+
+    fn get_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        self.inner.get_metadata(node_id, channel_id)
+    }
+
+    fn get_nodes(&self) -> Result<Vec<(PublicKey, model::NodeEntry)>, Error> {
+        self.inner.get_nodes()
+    }
+
+    fn export_since(&self, sequence: u64) -> Result<Vec<model::ChangeRecord>, Error> {
+        self.inner.export_since(sequence)
+    }
+
+    fn append_audit_record(
+        &self,
+        node_id: &PublicKey,
+        record: &model::AuditRecord,
+    ) -> Result<(), Error> {
+        warn!("read-only: not recording {} for {}", record.operation, node_id);
+        Err(refused())
+    }
+
+    fn get_audit_log(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<model::AuditRecord>, Error> {
+        self.inner.get_audit_log(node_id, channel_id)
+    }
+
+    fn update_close_plan(
+        &self,
+        node_id: &PublicKey,
+        _plan: Option<&ClosePlan>,
+    ) -> Result<(), Error> {
+        warn!("read-only: not persisting close plan for {}", node_id);
+        Err(refused())
+    }
+
+    fn get_close_plan(&self, node_id: &PublicKey) -> Result<Option<ClosePlan>, Error> {
+        self.inner.get_close_plan(node_id)
+    }
+
+    fn update_fee_reserve(
+        &self,
+        node_id: &PublicKey,
+        _reserve: Vec<OutPoint>,
+    ) -> Result<(), Error> {
+        warn!("read-only: not persisting fee reserve for {}", node_id);
+        Err(refused())
+    }
+
+    fn get_fee_reserve(&self, node_id: &PublicKey) -> Result<Vec<OutPoint>, Error> {
+        self.inner.get_fee_reserve(node_id)
+    }
+
+    fn update_payment(
+        &self,
+        node_id: &PublicKey,
+        _entry: &model::PaymentLedgerEntry,
+    ) -> Result<(), Error> {
+        warn!("read-only: not persisting payment ledger for {}", node_id);
+        Err(refused())
+    }
+
+    fn get_payments(&self, node_id: &PublicKey) -> Result<Vec<model::PaymentLedgerEntry>, Error> {
+        self.inner.get_payments(node_id)
+    }
+
+    fn export_all(&self, passphrase: &str) -> Result<Vec<u8>, Error> {
+        self.inner.export_all(passphrase)
+    }
+
+    fn import_all(&self, _passphrase: &str, _archive: &[u8]) -> Result<Vec<PublicKey>, Error> {
+        warn!("read-only: not importing backup");
+        Err(refused())
+    }
+
+    fn clear_database(&self) -> Result<(), Error> {
+        warn!("read-only: not clearing database");
+        Err(refused())
+    }
+}
@@ -16,13 +16,17 @@ use lightning::ln::PaymentHash;
 use lightning::util::ser::Writer;
 use lightning_signer::chain::tracker::ListenSlot;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::hex::Hex;
 use serde_with::serde_as;
 use serde_with::{DeserializeAs, SerializeAs};
 
 use lightning_signer::channel::{ChannelId, ChannelSetup, CommitmentType};
+use lightning_signer::close_plan::{ClosePlan, PlannedClose};
 use lightning_signer::monitor::State as ChainMonitorState;
-use lightning_signer::policy::validator::EnforcementState;
+use lightning_signer::persist::model::{AuditRecord, PaymentLedgerEntry, PaymentResolution};
+use lightning_signer::policy::validator::{EnforcementState, SigningIntent, SpliceState};
 use lightning_signer::tx::tx::{CommitmentInfo2, HTLCInfo2};
+use lightning_signer::util::shachain::CounterpartyRevocationSecrets;
 
 #[derive(Copy, Clone, Debug, Default)]
 pub struct PublicKeyHandler;
@@ -174,6 +178,8 @@ pub enum CommitmentTypeDef {
     Legacy,
     StaticRemoteKey,
     Anchors,
+    AnchorsZeroFeeHtlc,
+    Taproot,
 }
 
 #[derive(Deserialize)]
@@ -327,6 +333,126 @@ impl<'de> DeserializeAs<'de, HTLCInfo2> for HTLCInfo2Def {
     }
 }
 
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "AuditRecord")]
+pub struct AuditRecordDef {
+    pub sequence: u64,
+    pub timestamp: u64,
+    #[serde_as(as = "Option<ChannelIdHandler>")]
+    pub channel_id: Option<ChannelId>,
+    pub operation: String,
+    pub commit_num: Option<u64>,
+    pub values_sat: Vec<u64>,
+    #[serde_as(as = "Vec<ScriptDef>")]
+    pub destinations: Vec<Script>,
+    pub error: Option<String>,
+    // records written before the log was signed don't have these
+    #[serde(default)]
+    #[serde_as(as = "Hex")]
+    pub prev_hash: [u8; 32],
+    #[serde(default)]
+    #[serde_as(as = "Hex")]
+    pub signature: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "PaymentResolution")]
+pub enum PaymentResolutionDef {
+    Pending,
+    Fulfilled,
+    Failed,
+    Expired,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "PaymentLedgerEntry")]
+pub struct PaymentLedgerEntryDef {
+    #[serde_as(as = "PaymentHashDef")]
+    pub payment_hash: PaymentHash,
+    pub outgoing: bool,
+    pub amount_msat: u64,
+    #[serde_as(as = "Hex")]
+    pub invoice_hash: [u8; 32],
+    pub expiry_time: u64,
+    #[serde(with = "PaymentResolutionDef")]
+    pub resolution: PaymentResolution,
+}
+
+#[derive(Deserialize)]
+struct PaymentLedgerEntryHelper(#[serde(with = "PaymentLedgerEntryDef")] PaymentLedgerEntry);
+
+impl SerializeAs<PaymentLedgerEntry> for PaymentLedgerEntryDef {
+    fn serialize_as<S>(value: &PaymentLedgerEntry, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        PaymentLedgerEntryDef::serialize(value, serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, PaymentLedgerEntry> for PaymentLedgerEntryDef {
+    fn deserialize_as<D>(
+        deserializer: D,
+    ) -> Result<PaymentLedgerEntry, <D as Deserializer<'de>>::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        PaymentLedgerEntryHelper::deserialize(deserializer).map(|h| h.0)
+    }
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "PlannedClose")]
+pub struct PlannedCloseDef {
+    #[serde_as(as = "ChannelIdHandler")]
+    pub channel_id: ChannelId,
+    pub to_holder_value_sat: u64,
+    pub to_counterparty_value_sat: u64,
+    #[serde_as(as = "Option<ScriptDef>")]
+    pub holder_script: Option<Script>,
+    #[serde_as(as = "Option<ScriptDef>")]
+    pub counterparty_script: Option<Script>,
+    pub holder_wallet_path_hint: Vec<u32>,
+    pub signed: bool,
+}
+
+#[derive(Deserialize)]
+struct PlannedCloseHelper(#[serde(with = "PlannedCloseDef")] PlannedClose);
+
+impl SerializeAs<PlannedClose> for PlannedCloseDef {
+    fn serialize_as<S>(value: &PlannedClose, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        PlannedCloseDef::serialize(value, serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, PlannedClose> for PlannedCloseDef {
+    fn deserialize_as<D>(deserializer: D) -> Result<PlannedClose, <D as Deserializer<'de>>::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        PlannedCloseHelper::deserialize(deserializer).map(|h| h.0)
+    }
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ClosePlan")]
+pub struct ClosePlanDef {
+    #[serde_as(as = "Hex")]
+    pub id: [u8; 32],
+    #[serde_as(as = "Vec<PlannedCloseDef>")]
+    pub closes: Vec<PlannedClose>,
+    pub fee_sat: u64,
+    pub non_wallet_sat: u64,
+    pub approved: bool,
+}
+
 #[serde_as]
 #[derive(Serialize, Deserialize)]
 #[serde(remote = "CommitmentInfo2")]
@@ -369,11 +495,81 @@ impl<'de> DeserializeAs<'de, CommitmentInfo2> for CommitmentInfo2Def {
     }
 }
 
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "CounterpartyRevocationSecrets")]
+pub struct CounterpartyRevocationSecretsDef {
+    #[serde_as(as = "Vec<(Hex, _)>")]
+    pub old_secrets: Vec<([u8; 32], u64)>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SpliceState")]
+pub struct SpliceStateDef {
+    #[serde_as(as = "OutPointDef")]
+    pub previous_outpoint: OutPoint,
+    pub previous_value_sat: u64,
+    pub first_holder_commit_num: u64,
+    pub first_counterparty_commit_num: u64,
+}
+
+#[derive(Deserialize)]
+struct SpliceStateHelper(#[serde(with = "SpliceStateDef")] SpliceState);
+
+impl SerializeAs<SpliceState> for SpliceStateDef {
+    fn serialize_as<S>(value: &SpliceState, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        SpliceStateDef::serialize(value, serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, SpliceState> for SpliceStateDef {
+    fn deserialize_as<D>(deserializer: D) -> Result<SpliceState, <D as Deserializer<'de>>::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        SpliceStateHelper::deserialize(deserializer).map(|h| h.0)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SigningIntent")]
+pub struct SigningIntentDef {
+    pub operation: String,
+    pub commit_num: u64,
+}
+
+#[derive(Deserialize)]
+struct SigningIntentHelper(#[serde(with = "SigningIntentDef")] SigningIntent);
+
+impl SerializeAs<SigningIntent> for SigningIntentDef {
+    fn serialize_as<S>(value: &SigningIntent, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        SigningIntentDef::serialize(value, serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, SigningIntent> for SigningIntentDef {
+    fn deserialize_as<D>(deserializer: D) -> Result<SigningIntent, <D as Deserializer<'de>>::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        SigningIntentHelper::deserialize(deserializer).map(|h| h.0)
+    }
+}
+
 #[serde_as]
 #[derive(Serialize, Deserialize)]
 #[serde(remote = "EnforcementState")]
 pub struct EnforcementStateDef {
     pub next_holder_commit_num: u64,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    pub next_holder_revoke_num: u64,
     pub next_counterparty_commit_num: u64,
     pub next_counterparty_revoke_num: u64,
     pub current_counterparty_point: Option<PublicKey>,
@@ -387,6 +583,27 @@ pub struct EnforcementStateDef {
     pub mutual_close_signed: bool,
     #[serde(default)] // TODO remove default once everyone upgrades
     pub initial_holder_value: u64,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    #[serde(with = "CounterpartyRevocationSecretsDef")]
+    pub counterparty_secrets: CounterpartyRevocationSecrets,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    pub closing_txid: Option<Txid>,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    pub closing_height: Option<u32>,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    pub next_musig_nonce_index: u64,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    pub pending_musig_nonce_index: Option<u64>,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    #[serde_as(as = "Option<SpliceStateDef>")]
+    pub splice: Option<SpliceState>,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    pub review_height: u32,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    pub last_activity_height: u32,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    #[serde_as(as = "Option<SigningIntentDef>")]
+    pub signing_intent: Option<SigningIntent>,
 }
 
 #[derive(Deserialize)]
@@ -457,6 +674,20 @@ pub struct ChainMonitorStateDef {
     funding_outpoint: Option<OutPoint>,
     funding_double_spent_height: Option<u32>,
     closing_height: Option<u32>,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    closing_commitment_txid: Option<Txid>,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    splice_outpoint: Option<OutPoint>,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    splice_height: Option<u32>,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    previous_funding_outpoint: Option<OutPoint>,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    splice_reorged: bool,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    expected_funding_value_sat: Option<u64>,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    funding_value_sat: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -510,7 +741,8 @@ mod tests {
         let json = serde_json::to_string(&entry).expect("json");
         println!("{}", json);
         let entry_de: ChainTrackerEntry = serde_json::from_str(&json).expect("de json");
-        let _tracker_de: ChainTracker<ChainMonitor> = entry_de.into();
+        let tracker_de: ChainTracker<ChainMonitor> = entry_de.try_into().expect("tracker");
+        assert_eq!(tracker_de.chainwork, tracker.chainwork);
         Ok(())
     }
 }
@@ -0,0 +1,352 @@
+use std::sync::{Arc, Mutex};
+
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::OutPoint;
+use lightning_signer::chain::tracker::ChainTracker;
+use lightning_signer::channel::{Channel, ChannelId, ChannelStub};
+use lightning_signer::close_plan::ClosePlan;
+use lightning_signer::monitor::ChainMonitor;
+use lightning_signer::node::NodeConfig;
+use lightning_signer::persist::{model, Error, Persist, PersistBatch};
+use log::{error, info};
+use serde::Serialize;
+
+/// A persister wrapper that writes every mutation to a primary persister
+/// and then to one or more secondary persisters, such as a local kv store
+/// replicated to a remote database.
+///
+/// Reads are served by the primary.  A failed write to the primary fails
+/// the operation.  A failed write to a secondary is recorded in its
+/// health, and fails the operation only in strict mode, so that nothing
+/// is signed unless it is durable in every store.
+///
+/// A secondary that missed writes is out of date until it is rebuilt, for
+/// example from a backup of the primary.
+pub struct TeePersister {
+    primary: Arc<dyn Persist>,
+    secondaries: Vec<Secondary>,
+    strict: bool,
+}
+
+struct Secondary {
+    persister: Arc<dyn Persist>,
+    health: Mutex<SecondaryHealth>,
+}
+
+/// The write health of a secondary persister
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SecondaryHealth {
+    /// The name of the secondary, for logs and reports
+    pub name: String,
+    /// Failed writes since the last successful write
+    pub consecutive_failures: u64,
+    /// Failed writes since startup, which the secondary is missing
+    pub missed_writes: u64,
+    /// The error of the last failed write
+    pub last_error: Option<String>,
+}
+
+impl SecondaryHealth {
+    /// Whether the last write succeeded
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failures == 0
+    }
+}
+
+impl TeePersister {
+    /// Wrap a primary persister, writing to no secondaries
+    pub fn new(primary: Arc<dyn Persist>) -> Self {
+        TeePersister { primary, secondaries: Vec::new(), strict: false }
+    }
+
+    /// Also write to a secondary persister
+    pub fn with_secondary(mut self, name: &str, persister: Arc<dyn Persist>) -> Self {
+        let health = SecondaryHealth {
+            name: name.to_string(),
+            consecutive_failures: 0,
+            missed_writes: 0,
+            last_error: None,
+        };
+        self.secondaries.push(Secondary { persister, health: Mutex::new(health) });
+        self
+    }
+
+    /// Fail the operation if a write to a secondary fails
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// The health of the secondaries, in the order they were added
+    pub fn health(&self) -> Vec<SecondaryHealth> {
+        self.secondaries.iter().map(|s| s.health.lock().unwrap().clone()).collect()
+    }
+
+    // Write to the primary and then to each secondary, returning the
+    // result of the primary
+    fn write<T, F>(&self, op: &str, f: F) -> Result<T, Error>
+    where
+        F: Fn(&dyn Persist) -> Result<T, Error>,
+    {
+        let res = f(&*self.primary)?;
+        for secondary in self.secondaries.iter() {
+            if let Err(e) = f(&*secondary.persister) {
+                secondary.failed(op, &e);
+                if self.strict {
+                    return Err(e);
+                }
+            } else {
+                secondary.succeeded();
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl Secondary {
+    fn failed(&self, op: &str, e: &Error) {
+        let mut health = self.health.lock().unwrap();
+        if health.is_healthy() {
+            error!(
+                "secondary {}: {} failed, the secondary is now unhealthy: {}",
+                health.name, op, e
+            );
+        }
+        health.consecutive_failures += 1;
+        health.missed_writes += 1;
+        health.last_error = Some(format!("{}: {}", op, e));
+    }
+
+    fn succeeded(&self) {
+        let mut health = self.health.lock().unwrap();
+        if !health.is_healthy() {
+            info!(
+                "secondary {}: recovered after {} failed writes, {} writes missed since startup",
+                health.name, health.consecutive_failures, health.missed_writes
+            );
+            health.consecutive_failures = 0;
+        }
+    }
+}
+
+impl Persist for TeePersister {
+    fn new_node(&self, node_id: &PublicKey, config: &NodeConfig, seed: &[u8]) -> Result<(), Error> {
+        self.write("new_node", |p| p.new_node(node_id, config, seed))
+    }
+
+    fn delete_node(&self, node_id: &PublicKey) -> Result<(), Error> {
+        self.write("delete_node", |p| p.delete_node(node_id))
+    }
+
+    fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), Error> {
+        self.write("new_channel", |p| p.new_channel(node_id, stub))
+    }
+
+    fn new_chain_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        self.write("new_chain_tracker", |p| p.new_chain_tracker(node_id, tracker))
+    }
+
+    fn update_tracker(
+        &self,
+        node_id: &PublicKey,
+        tracker: &ChainTracker<ChainMonitor>,
+    ) -> Result<(), Error> {
+        self.write("update_tracker", |p| p.update_tracker(node_id, tracker))
+    }
+
+    fn get_tracker(&self, node_id: &PublicKey) -> Result<ChainTracker<ChainMonitor>, Error> {
+        self.primary.get_tracker(node_id)
+    }
+
+    fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), Error> {
+        self.write("update_channel", |p| p.update_channel(node_id, channel))
+    }
+
+    fn update_batch(&self, node_id: &PublicKey, batch: &PersistBatch) -> Result<(), Error> {
+        self.write("update_batch", |p| p.update_batch(node_id, batch))
+    }
+
+    fn delete_channel(&self, node_id: &PublicKey, id0: &ChannelId) -> Result<(), Error> {
+        self.write("delete_channel", |p| p.delete_channel(node_id, id0))
+    }
+
+    fn get_channel(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+    ) -> Result<model::ChannelEntry, Error> {
+        self.primary.get_channel(node_id, channel_id)
+    }
+
+    fn get_node_channels(
+        &self,
+        node_id: &PublicKey,
+    ) -> Result<Vec<(ChannelId, model::ChannelEntry)>, Error> {
+        self.primary.get_node_channels(node_id)
+    }
+
+    fn update_node_allowlist(
+        &self,
+        node_id: &PublicKey,
+        allowlist: Vec<String>,
+    ) -> Result<(), Error> {
+        self.write("update_node_allowlist", |p| p.update_node_allowlist(node_id, allowlist.clone()))
+    }
+
+    fn get_node_allowlist(&self, node_id: &PublicKey) -> Result<Vec<String>, Error> {
+        self.primary.get_node_allowlist(node_id)
+    }
+
+    fn update_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+        metadata: Vec<(String, String)>,
+    ) -> Result<(), Error> {
+        self.write("update_metadata", |p| p.update_metadata(node_id, channel_id, metadata.clone()))
+    }
+
+    fn get_metadata(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        self.primary.get_metadata(node_id, channel_id)
+    }
+
+    fn get_nodes(&self) -> Result<Vec<(PublicKey, model::NodeEntry)>, Error> {
+        self.primary.get_nodes()
+    }
+
+    fn export_since(&self, sequence: u64) -> Result<Vec<model::ChangeRecord>, Error> {
+        self.primary.export_since(sequence)
+    }
+
+    fn append_audit_record(
+        &self,
+        node_id: &PublicKey,
+        record: &model::AuditRecord,
+    ) -> Result<(), Error> {
+        self.write("append_audit_record", |p| p.append_audit_record(node_id, record))
+    }
+
+    fn get_audit_log(
+        &self,
+        node_id: &PublicKey,
+        channel_id: Option<&ChannelId>,
+    ) -> Result<Vec<model::AuditRecord>, Error> {
+        self.primary.get_audit_log(node_id, channel_id)
+    }
+
+    fn update_close_plan(
+        &self,
+        node_id: &PublicKey,
+        plan: Option<&ClosePlan>,
+    ) -> Result<(), Error> {
+        self.write("update_close_plan", |p| p.update_close_plan(node_id, plan))
+    }
+
+    fn get_close_plan(&self, node_id: &PublicKey) -> Result<Option<ClosePlan>, Error> {
+        self.primary.get_close_plan(node_id)
+    }
+
+    fn update_fee_reserve(&self, node_id: &PublicKey, reserve: Vec<OutPoint>) -> Result<(), Error> {
+        self.write("update_fee_reserve", |p| p.update_fee_reserve(node_id, reserve.clone()))
+    }
+
+    fn get_fee_reserve(&self, node_id: &PublicKey) -> Result<Vec<OutPoint>, Error> {
+        self.primary.get_fee_reserve(node_id)
+    }
+
+    fn update_payment(
+        &self,
+        node_id: &PublicKey,
+        entry: &model::PaymentLedgerEntry,
+    ) -> Result<(), Error> {
+        self.write("update_payment", |p| p.update_payment(node_id, entry))
+    }
+
+    fn get_payments(&self, node_id: &PublicKey) -> Result<Vec<model::PaymentLedgerEntry>, Error> {
+        self.primary.get_payments(node_id)
+    }
+
+    fn export_all(&self, passphrase: &str) -> Result<Vec<u8>, Error> {
+        self.primary.export_all(passphrase)
+    }
+
+    fn import_all(&self, passphrase: &str, archive: &[u8]) -> Result<Vec<PublicKey>, Error> {
+        self.write("import_all", |p| p.import_all(passphrase, archive))
+    }
+
+    // The secondaries are copies of the primary, so only the primary
+    // fences the signers sharing it
+    fn get_fence(&self) -> Result<u64, Error> {
+        self.primary.get_fence()
+    }
+
+    fn advance_fence(&self, current: u64) -> Result<u64, Error> {
+        self.primary.advance_fence(current)
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.write("flush", |p| p.flush())
+    }
+
+    fn clear_database(&self) -> Result<(), Error> {
+        self.write("clear_database", |p| p.clear_database())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning_signer::persist::DummyPersister;
+    use lightning_signer::util::test_utils::make_dummy_pubkey;
+    use test_log::test;
+
+    use crate::persist::read_only::ReadOnlyPersister;
+
+    use super::*;
+
+    fn make_tee(strict: bool) -> TeePersister {
+        let refusing = Arc::new(ReadOnlyPersister::new(Arc::new(DummyPersister)));
+        TeePersister::new(Arc::new(DummyPersister))
+            .with_secondary("ok", Arc::new(DummyPersister))
+            .with_secondary("refusing", refusing)
+            .with_strict(strict)
+    }
+
+    #[test]
+    fn tee_health_test() {
+        let node_id = make_dummy_pubkey(0x12);
+        let tee = make_tee(false);
+        tee.update_node_allowlist(&node_id, vec![]).unwrap();
+        tee.update_fee_reserve(&node_id, vec![]).unwrap();
+
+        let health = tee.health();
+        assert!(health[0].is_healthy());
+        assert_eq!(health[0].missed_writes, 0);
+        assert!(!health[1].is_healthy());
+        assert_eq!(health[1].name, "refusing");
+        assert_eq!(health[1].consecutive_failures, 2);
+        assert_eq!(health[1].missed_writes, 2);
+        assert_eq!(
+            health[1].last_error.as_ref().unwrap(),
+            "update_fee_reserve: refused: read-only"
+        );
+    }
+
+    #[test]
+    fn tee_strict_test() {
+        let node_id = make_dummy_pubkey(0x12);
+        let tee = make_tee(true);
+        assert_eq!(
+            tee.update_node_allowlist(&node_id, vec![]),
+            Err(Error::Refused("read-only".to_string()))
+        );
+        assert_eq!(tee.health()[1].missed_writes, 1);
+    }
+}
@@ -6,6 +6,6 @@ pub fn do_with_channel_stub<F: Fn(&ChannelStub) -> ()>(node: &Node, channel_id:
     let slot = guard.get(&channel_id).unwrap().lock().unwrap();
     match &*slot {
         ChannelSlot::Stub(s) => f(&s),
-        ChannelSlot::Ready(_) => panic!("expected channel stub"),
+        _ => panic!("expected channel stub"),
     }
 }
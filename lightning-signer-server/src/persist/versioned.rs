@@ -0,0 +1,145 @@
+//! Versioned persisted records.
+//!
+//! A versioned record carries a `version` field, and is migrated to the
+//! current version in its JSON form before it is deserialized, so that the
+//! structures it holds - such as `EnforcementState` and `ChannelSetup` - can
+//! change without breaking existing databases.  A record is written back at
+//! the current version on its next update.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+/// The name of the version field of a record
+pub const VERSION_FIELD: &str = "version";
+
+/// Upgrade a record from one version to the next, in its JSON form
+pub type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+/// A persisted record that is migrated to the current version when it is
+/// loaded
+pub trait Versioned: Sized {
+    /// The kind of record, for errors
+    const KIND: &'static str;
+
+    /// The migrations, indexed by the version they upgrade from.  The
+    /// current version is the number of migrations.
+    const MIGRATIONS: &'static [Migration];
+
+    /// The current version
+    const VERSION: u64 = Self::MIGRATIONS.len() as u64;
+
+    /// Deserialize a record at the current version
+    fn from_current(value: Value) -> Result<Self, serde_json::Error>;
+}
+
+/// Records written before versioning have no version field, and serde
+/// defaults for the fields added since
+pub fn from_unversioned(_record: &mut Map<String, Value>) -> Result<(), String> {
+    Ok(())
+}
+
+/// Upgrade a record to the current version.
+///
+/// A record with a newer version than the current one is refused, so that a
+/// downgraded signer doesn't drop fields it doesn't know about.
+pub fn migrate<T: Versioned>(value: Value) -> Result<Value, String> {
+    let mut record = match value {
+        Value::Object(record) => record,
+        _ => return Err(format!("{} record is not an object", T::KIND)),
+    };
+    let current = T::VERSION;
+    let version = match record.get(VERSION_FIELD) {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| format!("{} record has a bad version {}", T::KIND, version))?,
+    };
+    if version > current {
+        return Err(format!(
+            "{} record version {} is newer than supported {}",
+            T::KIND,
+            version,
+            current
+        ));
+    }
+    for (from, migration) in T::MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&mut record).map_err(|e| {
+            format!("{} record migration from version {} failed: {}", T::KIND, from, e)
+        })?;
+        record.insert(VERSION_FIELD.to_string(), Value::from(from as u64 + 1));
+    }
+    Ok(Value::Object(record))
+}
+
+/// Deserialize a record, migrating it to the current version.  This is the
+/// `Deserialize` implementation of versioned records.
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Versioned,
+{
+    let value = migrate::<T>(Value::deserialize(deserializer)?).map_err(D::Error::custom)?;
+    T::from_current(value).map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn from_json(json: Value) -> Result<Record, serde_json::Error> {
+        serde_json::from_str(&json.to_string())
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Record {
+        version: u64,
+        amount_sat: u64,
+    }
+
+    impl Versioned for Record {
+        const KIND: &'static str = "test";
+        const MIGRATIONS: &'static [Migration] = &[from_unversioned, to_sat];
+
+        fn from_current(value: Value) -> Result<Self, serde_json::Error> {
+            let version = value[VERSION_FIELD].as_u64().unwrap();
+            let amount_sat = value["amount_sat"].as_u64().unwrap();
+            Ok(Record { version, amount_sat })
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Record {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserialize(deserializer)
+        }
+    }
+
+    // Version 2 has the amount in satoshi rather than millisatoshi
+    fn to_sat(record: &mut Map<String, Value>) -> Result<(), String> {
+        let amount_msat = record
+            .remove("amount_msat")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| "missing amount_msat".to_string())?;
+        record.insert("amount_sat".to_string(), Value::from(amount_msat / 1000));
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_test() {
+        let unversioned = json!({"amount_msat": 5000});
+        assert_eq!(from_json(unversioned).unwrap(), Record { version: 2, amount_sat: 5 });
+        let v1 = json!({"version": 1, "amount_msat": 7000});
+        assert_eq!(from_json(v1).unwrap(), Record { version: 2, amount_sat: 7 });
+        let current = json!({"version": 2, "amount_sat": 9});
+        assert_eq!(from_json(current).unwrap(), Record { version: 2, amount_sat: 9 });
+
+        let err = from_json(json!({"version": 3, "amount_sat": 9}));
+        assert_eq!(err.unwrap_err().to_string(), "test record version 3 is newer than supported 2");
+        let err = from_json(json!({"version": 1}));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "test record migration from version 1 failed: missing amount_msat"
+        );
+    }
+}
@@ -8,8 +8,8 @@ use lightning_signer_server::persist::persist_json::KVJsonPersister;
 use lightning_signer_server::persist::util;
 
 pub fn main() {
-    let persister = KVJsonPersister::new("/tmp/signer.kv");
-    persister.clear_database();
+    let persister = KVJsonPersister::new(std::env::temp_dir().join("signer.kv")).unwrap();
+    persister.clear_database().unwrap();
     let channel_nonce = "nonce0".as_bytes().to_vec();
     let channel_id = channel_nonce_to_id(&channel_nonce);
     let channel_nonce1 = "nonce1".as_bytes().to_vec();
@@ -18,7 +18,7 @@ pub fn main() {
     let (node_id, node_arc, stub, _seed) = util::make_node_and_channel(&channel_nonce, channel_id);
     let node = &*node_arc;
 
-    persister.new_node(&node_id, &TEST_NODE_CONFIG, &[3u8; 32]);
+    persister.new_node(&node_id, &TEST_NODE_CONFIG, &[3u8; 32]).unwrap();
 
     persister.new_channel(&node_id, &stub).unwrap();
 
@@ -26,11 +26,11 @@ pub fn main() {
     let setup = util::create_test_channel_setup(dummy_pubkey);
     let channel = node.ready_channel(channel_id, Some(channel_id1), setup, &vec![]).unwrap();
 
-    for (id, entry) in persister.get_node_channels(&node_id) {
+    for (id, entry) in persister.get_node_channels(&node_id).unwrap() {
         println!("{} {:?}", id, entry);
     }
     persister.update_channel(&node_id, &channel).unwrap();
-    for (id, entry) in persister.get_node_channels(&node_id) {
+    for (id, entry) in persister.get_node_channels(&node_id).unwrap() {
         println!("{} {:?}", id, entry);
     }
 
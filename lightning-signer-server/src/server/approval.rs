@@ -0,0 +1,333 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::secp256k1::PublicKey;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tonic::{Code, Status};
+
+use lightning_signer::policy::approving_validator::Approver;
+
+/// The name of the approval queue file in the data directory
+pub const APPROVAL_QUEUE_FILE_NAME: &str = "approvals.json";
+
+const POLICY_FAILURE_PREFIX: &str = "policy failure: ";
+
+/// The operator's decision on a parked request
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Waiting for the operator
+    Pending,
+    /// The request is signed when it is retried
+    Approved,
+    /// The request fails when it is retried
+    Rejected,
+}
+
+/// A sign request that violated policy, parked for the operator
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ParkedRequest {
+    /// The deterministic request ID, see [ApprovalQueue::request_id]
+    pub id: String,
+    /// The gRPC method
+    pub method: String,
+    /// Hex encoded node ID
+    pub node_id: String,
+    /// The policy failure, as returned to the caller
+    pub message: String,
+    /// The request in JSON, for inspection by the operator
+    pub request: String,
+    /// The operator's decision
+    pub decision: Decision,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct QueueState {
+    requests: Vec<ParkedRequest>,
+}
+
+/// A queue of sign requests that violated policy, waiting for an operator
+/// to approve or reject them.
+///
+/// A request is identified by a hash of its method and encoded content,
+/// so the node retrying the same request finds the operator's decision.
+/// An approved request is validated again when it is retried, and the
+/// approved policy failure is then accepted by an
+/// [lightning_signer::policy::approving_validator::ApprovingValidator].
+/// The approval is used up once the request is signed.
+///
+/// The queue is persisted in the data directory.
+pub struct ApprovalQueue {
+    path: PathBuf,
+    state: Mutex<QueueState>,
+    // Approved requests that are being validated again
+    retrying: Mutex<BTreeSet<String>>,
+}
+
+impl ApprovalQueue {
+    /// Create a queue, loading any parked requests from the data directory
+    pub fn new(data_path: &Path) -> io::Result<Self> {
+        let path = data_path.join(APPROVAL_QUEUE_FILE_NAME);
+        let state = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => QueueState::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(ApprovalQueue { path, state: Mutex::new(state), retrying: Mutex::new(BTreeSet::new()) })
+    }
+
+    /// The deterministic ID of a request, the hex SHA256 of the method name
+    /// and the protobuf encoding of the request
+    pub fn request_id<R: prost::Message>(method: &str, request: &R) -> String {
+        let mut engine = Sha256Hash::engine();
+        engine.input(method.as_bytes());
+        engine.input(&[0]);
+        engine.input(&request.encode_to_vec());
+        hex::encode(Sha256Hash::from_engine(engine).into_inner())
+    }
+
+    /// The parked requests, oldest first
+    pub fn list(&self) -> Vec<ParkedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Approve or reject a pending request
+    pub fn decide(&self, id: &str, approve: bool) -> Result<(), Status> {
+        let mut state = self.state.lock().unwrap();
+        let request = state
+            .requests
+            .iter_mut()
+            .find(|r| r.id == id)
+            .ok_or_else(|| Status::invalid_argument(format!("unknown request {}", id)))?;
+        if request.decision != Decision::Pending {
+            return Err(Status::invalid_argument(format!("request {} is not pending", id)));
+        }
+        request.decision = if approve { Decision::Approved } else { Decision::Rejected };
+        info!("request {} {:?} by the operator: {}", id, request.decision, request.message);
+        self.save(&state)
+    }
+
+    /// Start handling a sign request.  Fails if the operator rejected the
+    /// request.
+    pub fn begin<R>(
+        self: &Arc<Self>,
+        method: &'static str,
+        node_id: &PublicKey,
+        request: &R,
+    ) -> Result<Ticket, Status>
+    where
+        R: prost::Message + Serialize,
+    {
+        let id = Self::request_id(method, request);
+        let state = self.state.lock().unwrap();
+        match state.requests.iter().find(|r| r.id == id).map(|r| r.decision) {
+            Some(Decision::Rejected) => {
+                return Err(Status::failed_precondition(format!(
+                    "request {} was rejected by the operator",
+                    id
+                )))
+            }
+            Some(Decision::Approved) => {
+                self.retrying.lock().unwrap().insert(id.clone());
+            }
+            Some(Decision::Pending) | None => {}
+        }
+        Ok(Ticket {
+            queue: Arc::clone(self),
+            id,
+            method,
+            node_id: *node_id,
+            request: serde_json::to_string(request).expect("request json"),
+        })
+    }
+
+    // Park a request that violated policy, or update the policy failure of
+    // a request that is already parked
+    fn park(&self, ticket: &Ticket, message: &str) -> Result<(), Status> {
+        let mut state = self.state.lock().unwrap();
+        let parked = ParkedRequest {
+            id: ticket.id.clone(),
+            method: ticket.method.to_string(),
+            node_id: ticket.node_id.to_string(),
+            message: message.to_string(),
+            request: ticket.request.clone(),
+            decision: Decision::Pending,
+        };
+        match state.requests.iter_mut().find(|r| r.id == ticket.id) {
+            Some(existing) => *existing = parked,
+            None => {
+                warn!("parked request {} for approval: {}", ticket.id, message);
+                state.requests.push(parked);
+            }
+        }
+        self.save(&state)
+    }
+
+    // Use up the approval of a request once it was signed
+    fn signed(&self, id: &str) -> Result<(), Status> {
+        let mut state = self.state.lock().unwrap();
+        let len = state.requests.len();
+        state.requests.retain(|r| r.id != id || r.decision != Decision::Approved);
+        if state.requests.len() == len {
+            return Ok(());
+        }
+        info!("signed approved request {}", id);
+        self.save(&state)
+    }
+
+    // Write to a temporary file and rename, so that a crash doesn't lose
+    // the queue
+    fn save(&self, state: &QueueState) -> Result<(), Status> {
+        let tmp_path = self.path.with_extension("tmp");
+        serde_json::to_vec_pretty(state)
+            .map_err(io::Error::from)
+            .and_then(|contents| fs::write(&tmp_path, contents))
+            .and_then(|_| fs::rename(&tmp_path, &self.path))
+            .map_err(|e| {
+                error!("approval queue {}: {}", self.path.display(), e);
+                Status::internal("approval queue persist failed")
+            })
+    }
+}
+
+impl Approver for ApprovalQueue {
+    fn is_approved(&self, node_id: &PublicKey, message: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        let retrying = self.retrying.lock().unwrap();
+        let node_id = node_id.to_string();
+        state.requests.iter().any(|r| {
+            r.decision == Decision::Approved
+                && retrying.contains(&r.id)
+                && r.node_id == node_id
+                && r.message == message
+        })
+    }
+}
+
+/// A sign request being handled, see [ApprovalQueue::begin]
+pub struct Ticket {
+    queue: Arc<ApprovalQueue>,
+    id: String,
+    method: &'static str,
+    node_id: PublicKey,
+    request: String,
+}
+
+impl Ticket {
+    /// Finish handling the request with the result of signing.  A policy
+    /// failure parks the request, and the returned status names the
+    /// request ID for the operator.
+    pub fn finish<T, E: Into<Status>>(self, res: Result<T, E>) -> Result<T, Status> {
+        match res.map_err(|e| e.into()) {
+            Ok(value) => {
+                self.queue.signed(&self.id)?;
+                Ok(value)
+            }
+            Err(status)
+                if status.code() == Code::FailedPrecondition
+                    && status.message().starts_with(POLICY_FAILURE_PREFIX) =>
+            {
+                self.queue.park(&self, status.message())?;
+                // keep the policy violation details in the metadata
+                Err(Status::with_metadata(
+                    Code::FailedPrecondition,
+                    format!(
+                        "{} - parked for operator approval as request {}",
+                        status.message(),
+                        self.id
+                    ),
+                    status.metadata().clone(),
+                ))
+            }
+            Err(status) => Err(status),
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        self.queue.retrying.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use test_log::test;
+
+    use lightning_signer::util::test_utils::make_dummy_pubkey;
+
+    use crate::server::remotesigner::PingRequest;
+
+    use super::*;
+
+    const FAILURE: &str = "policy failure: validate_onchain_tx: output is not to our wallet";
+
+    fn policy_failure() -> Result<(), Status> {
+        Err(Status::failed_precondition(FAILURE))
+    }
+
+    #[test]
+    fn approval_queue_test() {
+        let dir = TempDir::new().unwrap();
+        let queue = Arc::new(ApprovalQueue::new(dir.path()).unwrap());
+        let node_id = make_dummy_pubkey(0x12);
+        let request = PingRequest { message: "pay".to_string() };
+        let id = ApprovalQueue::request_id("sign_onchain_tx", &request);
+        assert_ne!(id, ApprovalQueue::request_id("sign_mutual_close_tx_phase2", &request));
+
+        // a policy failure is parked
+        let ticket = queue.begin("sign_onchain_tx", &node_id, &request).unwrap();
+        let status = ticket.finish(policy_failure()).unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert!(status.message().ends_with(&id));
+        assert_eq!(queue.list().len(), 1);
+        assert_eq!(queue.list()[0].decision, Decision::Pending);
+        assert!(!queue.is_approved(&node_id, FAILURE));
+
+        // other failures are not
+        let ticket = queue.begin("sign_onchain_tx", &node_id, &PingRequest::default()).unwrap();
+        let status = ticket.finish::<(), _>(Err(Status::invalid_argument("bad tx"))).unwrap_err();
+        assert_eq!(status.message(), "bad tx");
+        assert_eq!(queue.list().len(), 1);
+
+        // the queue survives a restart
+        let queue = Arc::new(ApprovalQueue::new(dir.path()).unwrap());
+        assert_eq!(queue.list()[0].id, id);
+        assert!(queue.decide("00", true).is_err());
+        queue.decide(&id, true).unwrap();
+        assert!(queue.decide(&id, false).is_err());
+
+        // the approval applies while the request is retried
+        assert!(!queue.is_approved(&node_id, FAILURE));
+        let ticket = queue.begin("sign_onchain_tx", &node_id, &request).unwrap();
+        assert!(queue.is_approved(&node_id, FAILURE));
+        assert!(!queue.is_approved(&make_dummy_pubkey(0x13), FAILURE));
+        assert!(!queue.is_approved(&node_id, "policy failure: something else"));
+        ticket.finish(Ok::<(), Status>(())).unwrap();
+        assert!(!queue.is_approved(&node_id, FAILURE));
+        assert!(queue.list().is_empty());
+    }
+
+    #[test]
+    fn approval_queue_reject_test() {
+        let dir = TempDir::new().unwrap();
+        let queue = Arc::new(ApprovalQueue::new(dir.path()).unwrap());
+        let node_id = make_dummy_pubkey(0x12);
+        let request = PingRequest { message: "pay".to_string() };
+        let ticket = queue.begin("sign_onchain_tx", &node_id, &request).unwrap();
+        ticket.finish(policy_failure()).unwrap_err();
+        let id = queue.list()[0].id.clone();
+        queue.decide(&id, false).unwrap();
+
+        let status = queue.begin("sign_onchain_tx", &node_id, &request).err().unwrap();
+        assert_eq!(status.message(), format!("request {} was rejected by the operator", id));
+    }
+}
@@ -0,0 +1,456 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::Hash;
+use hyper::Body;
+use log::{error, info, warn};
+use rand::{OsRng, Rng};
+use serde::{Deserialize, Serialize};
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::Status;
+use tower::{Layer, Service};
+
+use super::driver::now_secs;
+use super::tenant::{in_tenant, TenantStore};
+use crate::util::write_private_file;
+
+/// The name of the token file in the data directory
+pub const TOKEN_FILE_NAME: &str = "tokens.json";
+
+/// The name of the file in the data directory that the first admin token
+/// is written to
+pub const ADMIN_TOKEN_FILE_NAME: &str = "admin.token";
+
+/// The request metadata key of the bearer token, as `Bearer <token>`
+pub const AUTHORIZATION_METADATA_KEY: &str = "authorization";
+
+/// A permission granted by a token
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    /// Listing and inspecting state
+    ReadOnly,
+    /// The channel and signing operations of the node
+    ChannelOps,
+    /// Node creation, backups, approvals and tokens.  Grants every scope.
+    NodeAdmin,
+    /// Changing the allowlists
+    AllowlistAdmin,
+}
+
+impl Scope {
+    /// All the scopes
+    pub const ALL: [Scope; 4] =
+        [Scope::ReadOnly, Scope::ChannelOps, Scope::NodeAdmin, Scope::AllowlistAdmin];
+
+    /// The name of the scope in the RPCs and the CLI
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::ReadOnly => "read-only",
+            Scope::ChannelOps => "channel-ops",
+            Scope::NodeAdmin => "node-admin",
+            Scope::AllowlistAdmin => "allowlist-admin",
+        }
+    }
+
+    /// Parse a scope name
+    pub fn from_name(name: &str) -> Option<Scope> {
+        Scope::ALL.iter().find(|s| s.name() == name).copied()
+    }
+}
+
+// The scope required by each gRPC method, by name
+fn lookup_method_scope(method: &str) -> Option<Scope> {
+    let scope = match method {
+        "Ping"
+        | "Negotiate"
+        | "Version"
+        | "ListNodes"
+        | "ListChannels"
+        | "GetNodeStatus"
+        | "GetWalletDescriptor"
+        | "GetAddress"
+        | "GetChannelStatus"
+        | "GetReplicationStatus"
+        | "ListAllowlist"
+        | "ListFeeReserve"
+        | "ListPayments"
+        | "ListWatchtowerBlobs"
+        | "GetMetadata"
+        | "ListApprovals"
+        | "GetStats"
+        | "ListChannelReviews" => Scope::ReadOnly,
+        "AddAllowlist" | "RemoveAllowlist" => Scope::AllowlistAdmin,
+        "Init"
+        | "GetNodeParam"
+        | "AddFeeReserve"
+        | "RemoveFeeReserve"
+        | "SetMetadata"
+        | "DecideApproval"
+        | "AcknowledgeChannelReview"
+        | "CreateBackup"
+        | "RestoreBackup"
+        | "MintToken"
+        | "RotateToken"
+        | "RevokeToken"
+        | "ListTokens"
+        | "SetTenant"
+        | "ListTenants"
+        | "RemoveTenant"
+        | "Shutdown"
+        | "Promote" => Scope::NodeAdmin,
+        "AcquireChannelLease"
+        | "ReleaseChannelLease"
+        | "NewChannel"
+        | "ReadyChannel"
+        | "SignMutualCloseTx"
+        | "SignMutualCloseTxPhase2"
+        | "CheckFutureSecret"
+        | "VerifyChannelMonitor"
+        | "GetChannelBasepoints"
+        | "GetPerCommitmentPoint"
+        | "SignOnchainTx"
+        | "SignFundingTx"
+        | "SignPsbt"
+        | "SignCounterpartyCommitmentTx"
+        | "SignCounterpartyCommitmentTxPhase2"
+        | "ValidateHolderCommitmentTx"
+        | "ValidateHolderCommitmentTxPhase2"
+        | "ValidateCounterpartyRevocation"
+        | "SignHolderCommitmentTxPhase2"
+        | "SignHolderHTLCTx"
+        | "SignDelayedSweep"
+        | "SignCounterpartyHTLCTx"
+        | "SignCounterpartyHTLCSweep"
+        | "SignJusticeSweep"
+        | "SignHolderAnchorInput"
+        | "SignChannelAnnouncement"
+        | "SignNodeAnnouncement"
+        | "SignChannelUpdate"
+        | "SignGossipMessage"
+        | "ECDH"
+        | "SignInvoice"
+        | "SignBolt12"
+        | "SignMessage" => Scope::ChannelOps,
+        _ => return None,
+    };
+    Some(scope)
+}
+
+/// The scope required by a gRPC method.  Methods that aren't listed
+/// require node-admin.
+pub fn method_scope(method: &str) -> Scope {
+    lookup_method_scope(method).unwrap_or(Scope::NodeAdmin)
+}
+
+/// A token, without its secret
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TokenInfo {
+    /// The random token ID, the first part of the token
+    pub id: String,
+    /// Chosen by the operator, to tell tokens apart
+    pub label: String,
+    /// The granted scopes
+    pub scopes: Vec<Scope>,
+    /// In seconds since the UNIX epoch
+    pub created_at: u64,
+    /// The tenant the token is confined to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct StoredToken {
+    #[serde(flatten)]
+    info: TokenInfo,
+    // hex SHA256 of the secret
+    secret_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct TokenState {
+    tokens: BTreeMap<String, StoredToken>,
+}
+
+/// Bearer tokens with scoped permissions.
+///
+/// A token is `<id>.<secret>`, and is presented in the authorization
+/// metadata of each request.  Only the hash of the secret is stored.
+/// Rotating a token replaces its secret and keeps its ID and scopes.
+///
+/// The tokens are persisted in the data directory.
+pub struct TokenStore {
+    path: PathBuf,
+    state: Mutex<TokenState>,
+}
+
+impl TokenStore {
+    /// Create a store, loading any tokens from the data directory
+    pub fn new(data_path: &Path) -> io::Result<Self> {
+        let path = data_path.join(TOKEN_FILE_NAME);
+        let state = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => TokenState::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(TokenStore { path, state: Mutex::new(state) })
+    }
+
+    /// Whether no tokens were minted yet
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().unwrap().tokens.is_empty()
+    }
+
+    /// Mint a token, returning it with its info.  The token can't be
+    /// retrieved later.  A token confined to a tenant can't have the
+    /// node-admin scope.
+    pub fn mint(
+        &self,
+        label: &str,
+        scopes: Vec<Scope>,
+        tenant: Option<String>,
+        now: u64,
+    ) -> Result<(TokenInfo, String), Status> {
+        if scopes.is_empty() {
+            return Err(Status::invalid_argument("a token needs at least one scope"));
+        }
+        if tenant.is_some() && scopes.contains(&Scope::NodeAdmin) {
+            return Err(Status::invalid_argument("a tenant token can't have the node-admin scope"));
+        }
+        let mut state = self.state.lock().unwrap();
+        let id = hex::encode(random_bytes(8));
+        let secret = hex::encode(random_bytes(32));
+        let info =
+            TokenInfo { id: id.clone(), label: label.to_string(), scopes, created_at: now, tenant };
+        let stored = StoredToken { info: info.clone(), secret_hash: hash_secret(&secret) };
+        state.tokens.insert(id.clone(), stored);
+        self.save(&state)?;
+        info!("minted token {} {:?} with scopes {:?}", id, label, info.scopes);
+        Ok((info, format!("{}.{}", id, secret)))
+    }
+
+    /// Replace the secret of a token, returning the new token.  The old
+    /// token stops working.
+    pub fn rotate(&self, id: &str) -> Result<String, Status> {
+        let mut state = self.state.lock().unwrap();
+        let secret = hex::encode(random_bytes(32));
+        let stored = state
+            .tokens
+            .get_mut(id)
+            .ok_or_else(|| Status::not_found(format!("no token {}", id)))?;
+        stored.secret_hash = hash_secret(&secret);
+        self.save(&state)?;
+        info!("rotated token {}", id);
+        Ok(format!("{}.{}", id, secret))
+    }
+
+    /// Revoke a token
+    pub fn revoke(&self, id: &str) -> Result<(), Status> {
+        let mut state = self.state.lock().unwrap();
+        if state.tokens.remove(id).is_none() {
+            return Err(Status::not_found(format!("no token {}", id)));
+        }
+        self.save(&state)?;
+        info!("revoked token {}", id);
+        Ok(())
+    }
+
+    /// The tokens, by ID
+    pub fn list(&self) -> Vec<TokenInfo> {
+        self.state.lock().unwrap().tokens.values().map(|t| t.info.clone()).collect()
+    }
+
+    /// Check that a token grants the scope of a gRPC method, returning its
+    /// info
+    pub fn authorize(&self, token: &str, method: &str) -> Result<TokenInfo, Status> {
+        let (id, secret) =
+            token.split_once('.').ok_or_else(|| Status::unauthenticated("malformed token"))?;
+        let state = self.state.lock().unwrap();
+        let stored = state
+            .tokens
+            .get(id)
+            .filter(|t| t.secret_hash == hash_secret(secret))
+            .ok_or_else(|| Status::unauthenticated("unknown token"))?;
+        let scope = method_scope(method);
+        let scopes = &stored.info.scopes;
+        if !scopes.contains(&scope) && !scopes.contains(&Scope::NodeAdmin) {
+            warn!("token {} without scope {} called {}", id, scope.name(), method);
+            return Err(Status::permission_denied(format!(
+                "{} requires the {} scope",
+                method,
+                scope.name()
+            )));
+        }
+        Ok(stored.info.clone())
+    }
+
+    // The token hashes are only readable by the owner, and a crash
+    // doesn't lose the tokens
+    fn save(&self, state: &TokenState) -> Result<(), Status> {
+        serde_json::to_vec_pretty(state)
+            .map_err(io::Error::from)
+            .and_then(|contents| write_private_file(&self.path, &contents))
+            .map_err(|e| {
+                error!("token store {}: {}", self.path.display(), e);
+                Status::internal("token persist failed")
+            })
+    }
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    OsRng::new().unwrap().fill_bytes(&mut bytes);
+    bytes
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256Hash::hash(secret.as_bytes()).into_inner())
+}
+
+/// Check the bearer token of a request for a gRPC method, and the tenant of
+/// the token, if any.  Returns the tenant the request is served for.
+pub fn authorize_request(
+    tokens: &TokenStore,
+    tenants: &TenantStore,
+    headers: &http::HeaderMap,
+    method: &str,
+) -> Result<Option<String>, Status> {
+    let token = headers
+        .get(AUTHORIZATION_METADATA_KEY)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+    let info = tokens.authorize(token, method)?;
+    if let Some(name) = info.tenant.as_ref() {
+        tenants.admit(name, method, now_secs())?;
+    }
+    Ok(info.tenant)
+}
+
+/// Enforces the token scopes on every gRPC method, if tokens are required.
+/// The requests with a tenant token are checked against the tenant, and
+/// served on its behalf, see [super::tenant::current_tenant].
+#[derive(Clone)]
+pub struct AuthLayer {
+    tokens: Option<(Arc<TokenStore>, Arc<TenantStore>)>,
+}
+
+impl AuthLayer {
+    /// Check requests against `tokens` and their tenants, or let all
+    /// requests through if `None`
+    pub fn new(tokens: Option<(Arc<TokenStore>, Arc<TenantStore>)>) -> Self {
+        AuthLayer { tokens }
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = AuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService { inner, tokens: self.tokens.clone() }
+    }
+}
+
+/// The service of [AuthLayer]
+#[derive(Clone)]
+pub struct AuthService<S> {
+    inner: S,
+    tokens: Option<(Arc<TokenStore>, Arc<TenantStore>)>,
+}
+
+impl<S> Service<http::Request<Body>> for AuthService<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        let mut tenant = None;
+        if let Some((tokens, tenants)) = self.tokens.as_ref() {
+            // the path is /<package>.<service>/<method>
+            let method = request.uri().path().rsplit('/').next().unwrap_or_default();
+            match authorize_request(tokens, tenants, request.headers(), method) {
+                Ok(t) => tenant = t,
+                Err(status) => return Box::pin(async move { Ok(status.to_http()) }),
+            }
+        }
+        // use the service that was driven to readiness
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(in_tenant(tenant, inner.call(request)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use tonic::Code;
+
+    use super::*;
+
+    #[test]
+    fn token_store_test() {
+        let dir = TempDir::new().unwrap();
+        let store = TokenStore::new(dir.path()).unwrap();
+        assert!(store.is_empty());
+        let (info, reader) = store.mint("dashboard", vec![Scope::ReadOnly], None, 1).unwrap();
+        let (_, admin) = store.mint("admin", vec![Scope::NodeAdmin], None, 2).unwrap();
+
+        store.authorize(&reader, "ListChannels").unwrap();
+        let err = store.authorize(&reader, "SignInvoice").unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+        store.authorize(&admin, "SignInvoice").unwrap();
+        store.authorize(&admin, "AddAllowlist").unwrap();
+        let err = store.authorize("nope", "Ping").unwrap_err();
+        assert_eq!(err.code(), Code::Unauthenticated);
+
+        // the old secret stops working on rotation
+        let rotated = store.rotate(&info.id).unwrap();
+        assert_eq!(store.authorize(&reader, "Ping").unwrap_err().code(), Code::Unauthenticated);
+        store.authorize(&rotated, "Ping").unwrap();
+
+        // the tokens are persisted
+        let store = TokenStore::new(dir.path()).unwrap();
+        assert_eq!(store.list().len(), 2);
+        store.authorize(&rotated, "Ping").unwrap();
+        store.revoke(&info.id).unwrap();
+        assert_eq!(store.authorize(&rotated, "Ping").unwrap_err().code(), Code::Unauthenticated);
+        assert!(store.mint("none", vec![], None, 3).is_err());
+
+        // a tenant token is never an admin token
+        let tenant = Some("alice".to_string());
+        assert!(store.mint("alice", vec![Scope::NodeAdmin], tenant.clone(), 4).is_err());
+        let (_, alice) = store.mint("alice", vec![Scope::ChannelOps], tenant.clone(), 4).unwrap();
+        assert_eq!(store.authorize(&alice, "SignInvoice").unwrap().tenant, tenant);
+    }
+
+    #[test]
+    fn method_scope_test() {
+        // every method has an explicit scope
+        let proto = include_str!("remotesigner.proto");
+        for line in proto.lines() {
+            if let Some(rest) = line.trim().strip_prefix("rpc ") {
+                let method = rest.split(|c: char| c == ' ' || c == '(').next().unwrap();
+                assert!(lookup_method_scope(method).is_some(), "{} has no scope", method);
+            }
+        }
+        assert_eq!(method_scope("SomethingNew"), Scope::NodeAdmin);
+        assert_eq!(Scope::from_name("allowlist-admin"), Some(Scope::AllowlistAdmin));
+    }
+}
@@ -0,0 +1,113 @@
+//! Cancellation-safe execution of handler bodies.
+//!
+//! tonic drops a handler's future when the client disconnects or the
+//! request times out.  A dropped future stops at its current await point,
+//! so any handler that awaits while it mutates signer state could leave
+//! the in-memory state and the persisted state out of step.
+//!
+//! The core signer API is synchronous, so the handlers in [super::driver]
+//! currently have no await points between parsing a request and replying.
+//! The handlers that mutate and persist state run that work through
+//! [run_to_completion] anyway, so that they stay safe as the handlers
+//! evolve and so that the blocking work does not stall the runtime's
+//! worker threads.
+
+use tonic::Status;
+
+use super::driver::internal_error;
+
+/// Run `f` on the blocking thread pool and wait for its result.
+///
+/// `f` always runs to completion once the returned future has been polled,
+/// even if the future is dropped before `f` returns.
+pub async fn run_to_completion<F, T>(f: F) -> Result<T, Status>
+where
+    F: FnOnce() -> Result<T, Status> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| internal_error(format!("handler task failed: {}", err)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use test_log::test;
+
+    use lightning_signer::bitcoin::Network;
+    use lightning_signer::node::NodeConfig;
+    use lightning_signer::signer::multi_signer::MultiSigner;
+    use lightning_signer::signer::my_keys_manager::KeyDerivationStyle;
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    // Start `f` once released, and cancel the call while `f` is blocked
+    async fn cancel_mid_call<F>(f: F) -> Receiver<()>
+    where
+        F: FnOnce() -> Result<(), Status> + Send + 'static,
+    {
+        let (started_tx, started_rx) = channel();
+        let (release_tx, release_rx) = channel::<()>();
+        let (done_tx, done_rx) = channel();
+        {
+            let call = run_to_completion(move || {
+                started_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                f()?;
+                done_tx.send(()).unwrap();
+                Ok(())
+            });
+            tokio::pin!(call);
+
+            // poll until the operation is underway
+            tokio::select! {
+                _ = &mut call => panic!("completed while blocked"),
+                _ = tokio::task::spawn_blocking(move || started_rx.recv_timeout(TIMEOUT)) => {}
+            }
+            // the call is dropped here, while the operation is blocked
+        }
+
+        release_tx.send(()).unwrap();
+        done_rx
+    }
+
+    #[test(tokio::test)]
+    async fn run_to_completion_test() {
+        assert_eq!(run_to_completion(|| Ok(7)).await.unwrap(), 7);
+        assert_eq!(
+            run_to_completion(|| -> Result<(), Status> { Err(Status::invalid_argument("bad")) })
+                .await
+                .unwrap_err()
+                .message(),
+            "bad"
+        );
+        let err = run_to_completion(|| -> Result<(), Status> { panic!("boom") }).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Internal);
+    }
+
+    #[test(tokio::test)]
+    async fn cancel_mid_call_test() {
+        let signer = Arc::new(MultiSigner::new());
+        let signer1 = Arc::clone(&signer);
+        let done = cancel_mid_call(move || {
+            signer1.new_node(NodeConfig {
+                network: Network::Testnet,
+                key_derivation_style: KeyDerivationStyle::Native,
+                chain_params: None,
+                checkpoint: None,
+            })?;
+            Ok(())
+        })
+        .await;
+
+        // the node is created even though the call was cancelled
+        done.recv_timeout(TIMEOUT).expect("operation did not complete");
+        assert_eq!(signer.get_node_ids().len(), 1);
+    }
+}
@@ -0,0 +1,460 @@
+use std::fs::{self, DirBuilder};
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::DirBuilderExt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{env, process};
+
+use bitcoin::Network;
+use serde::Serialize;
+use url::Url;
+
+use bitcoind_client::{BitcoindClient, BlockSource, EsploraClient};
+use lightning_signer::channel::ChannelSlot;
+use lightning_signer::node::Node;
+use lightning_signer::persist::model::verify_audit_log;
+use lightning_signer::persist::{DummyPersister, Persist};
+use lightning_signer::policy::simple_validator::SimpleValidatorFactory;
+use lightning_signer::wallet::Wallet;
+use vls_policy::simple::SimplePolicy;
+
+use crate::persist::persist_json::KVJsonPersister;
+use crate::persist::persist_sqlite::SqlitePersister;
+use crate::persist::read_only::ReadOnlyPersister;
+
+/// The result of a `--check` run.
+///
+/// Serialized as JSON and printed to stdout.
+#[derive(Serialize, Debug)]
+pub struct StartupReport {
+    pub ok: bool,
+    pub network: String,
+    pub datadir: String,
+    pub persistence: String,
+    pub policy: PolicyReport,
+    pub nodes: Vec<NodeReport>,
+    pub chain: Option<ChainReport>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PolicyReport {
+    /// The version of the policy crate
+    pub version: String,
+    pub min_delay: u16,
+    pub max_delay: u16,
+    pub max_channel_size_sat: u64,
+    pub max_push_value_msat: u64,
+    pub max_push_percentage: u8,
+    pub require_invoices: bool,
+    pub enforce_balance: bool,
+    pub grind_low_r: bool,
+    pub verify_signatures: bool,
+    pub require_funding_watch: bool,
+    pub enforce_funding_depth: bool,
+    pub require_funding_registration: bool,
+    pub max_unapproved_value_sat: u64,
+    /// Whether large values require an approval token, the key itself is
+    /// not reported
+    pub require_value_approval: bool,
+    pub max_close_plan_fee_sat: u64,
+    pub max_close_plan_non_wallet_sat: u64,
+    pub max_channel_age_blocks: u32,
+    pub max_channel_inactivity_blocks: u32,
+    pub block_htlcs_pending_review: bool,
+    pub max_payment_retries: u32,
+    pub payment_retry_window_blocks: u32,
+    pub max_invoice_amount_msat: u64,
+    pub max_invoice_expiry_secs: u64,
+    pub max_onchain_velocity_sat: u64,
+    pub onchain_velocity_window_blocks: u32,
+    pub max_clock_skew_secs: u32,
+    pub enforce_clock_skew: bool,
+    /// The enforcement levels that differ from the default, as TAG=LEVEL
+    pub enforcement: Vec<String>,
+    /// The custom rules
+    pub rules: Vec<String>,
+}
+
+impl From<&SimplePolicy> for PolicyReport {
+    fn from(policy: &SimplePolicy) -> Self {
+        PolicyReport {
+            version: vls_policy::VERSION.to_string(),
+            min_delay: policy.min_delay,
+            max_delay: policy.max_delay,
+            max_channel_size_sat: policy.max_channel_size_sat,
+            max_push_value_msat: policy.max_push_value_msat,
+            max_push_percentage: policy.max_push_percentage,
+            require_invoices: policy.require_invoices,
+            enforce_balance: policy.enforce_balance,
+            grind_low_r: policy.grind_low_r,
+            verify_signatures: policy.verify_signatures,
+            require_funding_watch: policy.require_funding_watch,
+            enforce_funding_depth: policy.enforce_funding_depth,
+            require_funding_registration: policy.require_funding_registration,
+            max_unapproved_value_sat: policy.max_unapproved_value_sat,
+            require_value_approval: policy.value_approval_key.is_some(),
+            max_close_plan_fee_sat: policy.max_close_plan_fee_sat,
+            max_close_plan_non_wallet_sat: policy.max_close_plan_non_wallet_sat,
+            max_channel_age_blocks: policy.max_channel_age_blocks,
+            max_channel_inactivity_blocks: policy.max_channel_inactivity_blocks,
+            block_htlcs_pending_review: policy.block_htlcs_pending_review,
+            max_payment_retries: policy.max_payment_retries,
+            payment_retry_window_blocks: policy.payment_retry_window_blocks,
+            max_invoice_amount_msat: policy.max_invoice_amount_msat,
+            max_invoice_expiry_secs: policy.max_invoice_expiry_secs,
+            max_onchain_velocity_sat: policy.max_onchain_velocity_sat,
+            onchain_velocity_window_blocks: policy.onchain_velocity_window_blocks,
+            max_clock_skew_secs: policy.max_clock_skew_secs,
+            enforce_clock_skew: policy.enforce_clock_skew,
+            enforcement: policy
+                .enforcement
+                .iter()
+                .map(|(tag, level)| format!("{}={}", tag.name(), level.name()))
+                .collect(),
+            rules: policy.rules.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct NodeReport {
+    pub node_id: String,
+    pub network: String,
+    pub ready_channels: usize,
+    pub stub_channels: usize,
+    pub closing_channels: usize,
+    pub closed_channels: usize,
+    pub allowlist_len: usize,
+    pub audit_records: usize,
+    pub tracker_height: u32,
+    pub tracker_tip: String,
+    /// Whether the tracker tip is on the best chain of the chain source, if
+    /// checked
+    pub tracker_on_chain: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChainReport {
+    pub rpc: String,
+    pub best_height: u32,
+    pub best_hash: String,
+}
+
+/// A source of the best chain to check the chain trackers against
+pub enum ChainSource {
+    /// A bitcoind RPC URL
+    Bitcoind(Url),
+    /// An Esplora API URL
+    Esplora(Url),
+}
+
+/// Load persistence read-only, restore all nodes and optionally check
+/// the chain trackers against bitcoind or Esplora.
+///
+/// Nothing is written to the data directory, and it is not locked, so a
+/// running server can be checked.  A sqlite database is opened read-only,
+/// and a kv store is read from a private copy.
+pub async fn check(
+    network: Network,
+    data_path: &Path,
+    no_persist: bool,
+    sqlite: bool,
+    policy: SimplePolicy,
+    chain_source: Option<ChainSource>,
+) -> StartupReport {
+    let mut report = StartupReport {
+        ok: false,
+        network: network.to_string(),
+        datadir: data_path.display().to_string(),
+        persistence: if no_persist {
+            "none"
+        } else if sqlite {
+            "sqlite"
+        } else {
+            "kv-json"
+        }
+        .to_string(),
+        policy: PolicyReport::from(&policy),
+        nodes: vec![],
+        chain: None,
+        errors: vec![],
+    };
+
+    if !no_persist && !data_path.is_dir() {
+        report.errors.push(format!("datadir {} does not exist", data_path.display()));
+        return report;
+    }
+
+    // Declared first, so that it outlives the persister and the nodes
+    let mut _snapshot = None;
+    let persister: Arc<dyn Persist> = if no_persist {
+        Arc::new(DummyPersister)
+    } else {
+        let opened: Result<Arc<dyn Persist>, String> = if sqlite {
+            match SqlitePersister::open_read_only(data_path) {
+                Ok(persister) => Ok(Arc::new(persister)),
+                Err(e) => Err(e.to_string()),
+            }
+        } else {
+            match KvSnapshot::copy(data_path) {
+                Ok(copy) => {
+                    let opened = KVJsonPersister::new(&copy.path);
+                    _snapshot = Some(copy);
+                    match opened {
+                        Ok(persister) => Ok(Arc::new(persister)),
+                        Err(e) => Err(e.to_string()),
+                    }
+                }
+                Err(e) => Err(format!("copy kv store: {}", e)),
+            }
+        };
+        match opened {
+            Ok(persister) => Arc::new(ReadOnlyPersister::new(persister)),
+            Err(e) => {
+                report.errors.push(format!("open persistence: {}", e));
+                return report;
+            }
+        }
+    };
+
+    // The restore path panics on corrupt data, so catch that and report it
+    let validator_factory = Arc::new(SimpleValidatorFactory::new_with_policy(policy));
+    let nodes = match panic::catch_unwind(AssertUnwindSafe(|| {
+        Node::restore_nodes(Arc::clone(&persister), validator_factory)
+    })) {
+        Ok(nodes) => nodes,
+        Err(e) => {
+            report.errors.push(format!("restore nodes: {}", panic_message(&e)));
+            return report;
+        }
+    };
+
+    for (node_id, node) in nodes.iter() {
+        let (mut ready_channels, mut stub_channels) = (0, 0);
+        let (mut closing_channels, mut closed_channels) = (0, 0);
+        for slot in node.channels().values() {
+            match &*slot.lock().unwrap() {
+                ChannelSlot::Ready(_) => ready_channels += 1,
+                ChannelSlot::Stub(_) => stub_channels += 1,
+                ChannelSlot::Closing(_) => closing_channels += 1,
+                ChannelSlot::Closed(_) => closed_channels += 1,
+            }
+        }
+        let allowlist_len = match node.allowlist() {
+            Ok(allowlist) => allowlist.len(),
+            Err(e) => {
+                report.errors.push(format!("node {}: allowlist: {}", node_id, e.message()));
+                0
+            }
+        };
+        let audit_records = match node.get_audit_log(None) {
+            Ok(log) => {
+                if let Err(e) = verify_audit_log(&log, &node.get_audit_pubkey()) {
+                    report.errors.push(format!("node {}: audit log: {:?}", node_id, e));
+                }
+                log.len()
+            }
+            Err(e) => {
+                report.errors.push(format!("node {}: audit log: {}", node_id, e.message()));
+                0
+            }
+        };
+        let node_network = node.network();
+        if node_network != network {
+            report.errors.push(format!(
+                "node {}: network {} does not match configured {}",
+                node_id, node_network, network
+            ));
+        }
+        let tracker = node.get_tracker();
+        report.nodes.push(NodeReport {
+            node_id: node_id.to_string(),
+            network: node_network.to_string(),
+            ready_channels,
+            stub_channels,
+            closing_channels,
+            closed_channels,
+            allowlist_len,
+            audit_records,
+            tracker_height: tracker.height(),
+            tracker_tip: tracker.tip().block_hash().to_string(),
+            tracker_on_chain: None,
+        });
+    }
+
+    if let Some(chain_source) = chain_source {
+        check_chain(&chain_source, &mut report).await;
+    }
+
+    report.ok = report.errors.is_empty();
+    report
+}
+
+// A private copy of the kv store files in a data directory, since sled
+// locks and writes to a store even if it is only read.  The copy is
+// removed when dropped.
+struct KvSnapshot {
+    path: PathBuf,
+}
+
+impl KvSnapshot {
+    fn copy(data_path: &Path) -> io::Result<Self> {
+        if !data_path.join("db").is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no kv store in {}", data_path.display()),
+            ));
+        }
+        // The copy has the node seeds, so only the owner can read it.  The
+        // directory must not exist yet.
+        let path = env::temp_dir().join(format!(
+            "vlsd-check-{}-{:x}",
+            process::id(),
+            rand::random::<u32>()
+        ));
+        let mut builder = DirBuilder::new();
+        #[cfg(unix)]
+        builder.mode(0o700);
+        builder.create(&path)?;
+        let snapshot = KvSnapshot { path };
+        for entry in fs::read_dir(data_path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let snap = name.starts_with("snap.") && !name.ends_with(".generating");
+            if name == "conf" || name == "db" || snap {
+                fs::copy(entry.path(), snapshot.path.join(&name))?;
+            }
+        }
+        let blobs = data_path.join("blobs");
+        if blobs.is_dir() {
+            fs::create_dir(snapshot.path.join("blobs"))?;
+            for entry in fs::read_dir(&blobs)? {
+                let entry = entry?;
+                fs::copy(entry.path(), snapshot.path.join("blobs").join(entry.file_name()))?;
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+impl Drop for KvSnapshot {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+async fn check_chain(chain_source: &ChainSource, report: &mut StartupReport) {
+    let (name, url) = match chain_source {
+        ChainSource::Bitcoind(url) => ("bitcoind", url),
+        ChainSource::Esplora(url) => ("esplora", url),
+    };
+    // don't leak the RPC password into the report
+    let mut redacted = url.clone();
+    let _ = redacted.set_password(None);
+    let client: Box<dyn BlockSource> = match chain_source {
+        ChainSource::Bitcoind(rpc) => match BitcoindClient::new(
+            rpc.host_str().unwrap_or("localhost").to_owned(),
+            rpc.port().unwrap_or(8332),
+            rpc.username().to_owned(),
+            rpc.password().unwrap_or("").to_owned(),
+        )
+        .await
+        {
+            Ok(client) => Box::new(client),
+            Err(e) => {
+                report.errors.push(format!("{} {}: {}", name, redacted, e));
+                return;
+            }
+        },
+        ChainSource::Esplora(url) => Box::new(EsploraClient::new(url.as_str())),
+    };
+    let (best_hash, best_height) = match client.get_best_block().await {
+        Ok(best) => best,
+        Err(e) => {
+            report.errors.push(format!("{} {}: {}", name, redacted, e));
+            return;
+        }
+    };
+    report.chain = Some(ChainReport {
+        rpc: redacted.to_string(),
+        best_height,
+        best_hash: best_hash.to_string(),
+    });
+
+    for node in report.nodes.iter_mut() {
+        if node.tracker_height > best_height {
+            report.errors.push(format!(
+                "node {}: tracker height {} is ahead of {} {}",
+                node.node_id, node.tracker_height, name, best_height
+            ));
+            node.tracker_on_chain = Some(false);
+            continue;
+        }
+        match client.get_block_hash(node.tracker_height).await {
+            Ok(hash_opt) => {
+                let on_chain = hash_opt.map(|h| h.to_string()) == Some(node.tracker_tip.clone());
+                if !on_chain {
+                    report.errors.push(format!(
+                        "node {}: tracker tip {} at height {} is not on the {} chain",
+                        node.node_id, node.tracker_tip, node.tracker_height, name
+                    ));
+                }
+                node.tracker_on_chain = Some(on_chain);
+            }
+            Err(e) => report.errors.push(format!("{} {}: {}", name, redacted, e)),
+        }
+    }
+}
+
+fn panic_message(e: &Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = e.downcast_ref::<String>() {
+        s.clone()
+    } else if let Some(s) = e.downcast_ref::<&str>() {
+        s.to_string()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use test_log::test;
+    use vls_policy::simple::make_simple_policy;
+
+    use super::*;
+
+    async fn run_check(data_path: &Path, sqlite: bool) -> StartupReport {
+        let network = Network::Testnet;
+        check(network, data_path, false, sqlite, make_simple_policy(network), None).await
+    }
+
+    #[test(tokio::test)]
+    async fn missing_datadir_test() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_path = temp_dir.path().join("testnet");
+        let report = run_check(&data_path, false).await;
+        assert!(!report.ok);
+        assert!(report.errors[0].contains("does not exist"));
+        assert!(!data_path.exists());
+    }
+
+    #[test(tokio::test)]
+    async fn check_locked_datadir_test() {
+        let temp_dir = TempDir::new().unwrap();
+
+        // a running server holds the lock
+        let persister = KVJsonPersister::new(temp_dir.path()).unwrap();
+        let report = run_check(temp_dir.path(), false).await;
+        assert!(report.ok, "{:?}", report.errors);
+        drop(persister);
+
+        let persister = SqlitePersister::new(temp_dir.path()).unwrap();
+        let report = run_check(temp_dir.path(), true).await;
+        assert!(report.ok, "{:?}", report.errors);
+        drop(persister);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+use log::warn;
+use serde::Serialize;
+use tonic::metadata::{Ascii, MetadataMap, MetadataValue};
+use tonic::Request;
+
+/// The response metadata key of deprecation warnings, with a JSON
+/// [Deprecation] for each deprecated RPC or parameter the request used
+pub const DEPRECATION_METADATA_KEY: &str = "vls-deprecation";
+
+/// The request metadata key that identifies a client, so that each
+/// deprecation is logged once per client.  The peer address is used if it
+/// is missing.
+pub const CLIENT_METADATA_KEY: &str = "vls-client";
+
+/// A deprecated RPC or parameter
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Deprecation {
+    /// A stable identifier, as in the CHANGELOG
+    pub id: &'static str,
+    /// The release that deprecated it
+    pub since: &'static str,
+    /// The release that removes it
+    pub removal: &'static str,
+    /// How to migrate
+    pub message: &'static str,
+}
+
+/// The deprecated RPCs and parameters.  Each one is listed under
+/// "Deprecated" in the CHANGELOG.
+pub const DEPRECATIONS: &[Deprecation] = &[
+    Deprecation {
+        id: "sign-counterparty-commitment-tx",
+        since: "0.1.0",
+        removal: "0.2.0",
+        message: "use SignCounterpartyCommitmentTxPhase2, which rebuilds the transaction",
+    },
+    Deprecation {
+        id: "validate-holder-commitment-tx",
+        since: "0.1.0",
+        removal: "0.2.0",
+        message: "use ValidateHolderCommitmentTxPhase2, which rebuilds the transaction",
+    },
+    Deprecation {
+        id: "sign-mutual-close-tx",
+        since: "0.1.0",
+        removal: "0.2.0",
+        message: "use SignMutualCloseTxPhase2, which rebuilds the transaction",
+    },
+    Deprecation {
+        id: "legacy-commitment-type",
+        since: "0.1.0",
+        removal: "0.2.0",
+        message: "the LEGACY commitment type of ReadyChannel, use STATIC_REMOTEKEY or anchors",
+    },
+];
+
+/// Look up a deprecation by its identifier
+pub fn lookup(id: &str) -> Option<&'static Deprecation> {
+    DEPRECATIONS.iter().find(|d| d.id == id)
+}
+
+/// The client of a request, from its client metadata or else its peer
+/// address
+pub fn client_id<T>(request: &Request<T>) -> String {
+    request
+        .metadata()
+        .get(CLIENT_METADATA_KEY)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+        .or_else(|| request.remote_addr().map(|addr| addr.ip().to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Warns clients that use deprecated RPCs and parameters.
+///
+/// Every response to a deprecated call carries a warning in its metadata,
+/// so that integrators see it in their own logs.  The signer logs it only
+/// the first time each client uses each deprecation.
+#[derive(Default)]
+pub struct DeprecationRegistry {
+    warned: Mutex<BTreeSet<(String, &'static str)>>,
+}
+
+impl DeprecationRegistry {
+    /// Create a registry that has warned no clients
+    pub fn new() -> Self {
+        DeprecationRegistry { warned: Mutex::new(BTreeSet::new()) }
+    }
+
+    /// Add the warning of a deprecation to the response metadata, logging
+    /// it if this is the first use by the client.  Returns whether it was
+    /// logged.
+    pub fn warn(&self, client: &str, id: &str, metadata: &mut MetadataMap) -> bool {
+        let deprecation = lookup(id).expect("registered deprecation");
+        let value = serde_json::to_string(deprecation).expect("deprecation");
+        metadata.append(
+            DEPRECATION_METADATA_KEY,
+            value.parse::<MetadataValue<Ascii>>().expect("ascii"),
+        );
+        let first = self.warned.lock().unwrap().insert((client.to_string(), deprecation.id));
+        if first {
+            warn!(
+                "client {} uses {}, deprecated since {} and removed in {}: {}",
+                client, deprecation.id, deprecation.since, deprecation.removal, deprecation.message
+            );
+        }
+        first
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warn_once_test() {
+        let registry = DeprecationRegistry::new();
+        let mut metadata = MetadataMap::new();
+        assert!(registry.warn("a", "sign-mutual-close-tx", &mut metadata));
+        assert!(!registry.warn("a", "sign-mutual-close-tx", &mut metadata));
+        assert!(registry.warn("b", "sign-mutual-close-tx", &mut metadata));
+        assert!(registry.warn("a", "legacy-commitment-type", &mut metadata));
+
+        // every response is warned
+        let values: Vec<_> = metadata.get_all(DEPRECATION_METADATA_KEY).iter().collect();
+        assert_eq!(values.len(), 4);
+        let warning: serde_json::Value = serde_json::from_str(values[0].to_str().unwrap()).unwrap();
+        assert_eq!(warning["id"], "sign-mutual-close-tx");
+        assert_eq!(warning["removal"], "0.2.0");
+    }
+
+    #[test]
+    fn client_id_test() {
+        let mut request = Request::new(());
+        assert_eq!(client_id(&request), "unknown");
+        request.metadata_mut().insert(CLIENT_METADATA_KEY, "cln-1".parse().unwrap());
+        assert_eq!(client_id(&request), "cln-1");
+    }
+
+    #[test]
+    fn changelog_test() {
+        let changelog = include_str!("../../../CHANGELOG.md");
+        for deprecation in DEPRECATIONS {
+            let entry = format!("`{}`", deprecation.id);
+            assert!(changelog.contains(&entry), "{} is not in the CHANGELOG", deprecation.id);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! A transport-agnostic frontend to the signer.
+//!
+//! The protocol of the signer is the set of methods in remotesigner.proto,
+//! each taking and returning a protobuf message.  The gRPC server carries
+//! it over HTTP/2.  The frontend serves it from a method name and an
+//! encoded request, for the other transports, such as [super::serial].
+
+use prost::Message;
+use tonic::Status;
+
+use super::driver::invalid_grpc_argument;
+use super::remotesigner::signer_server::Signer;
+use super::remotesigner::version_server::Version;
+use super::remotesigner::*;
+
+// Decode the request of a method, call the method and encode the reply
+macro_rules! dispatch {
+    ($signer: expr, $method: expr, $request: expr, $($name: literal => $handler: ident($req: ty),)*) => {
+        match $method {
+            $($name => {
+                let request = <$req>::decode($request).map_err(|e| {
+                    invalid_grpc_argument(format!("could not decode {}: {}", $name, e))
+                })?;
+                let reply = $signer.$handler(tonic::Request::new(request)).await?.into_inner();
+                Ok(reply.encode_to_vec())
+            })*
+            _ => Err(Status::unimplemented(format!("unknown method {}", $method))),
+        }
+    };
+}
+
+/// Serves the methods of a signer from encoded requests
+pub struct Frontend<S> {
+    signer: S,
+}
+
+impl<S: Signer + Version> Frontend<S> {
+    /// Serve the methods of `signer`
+    pub fn new(signer: S) -> Self {
+        Frontend { signer }
+    }
+
+    /// Call a method by its name in remotesigner.proto, with an encoded
+    /// request, returning the encoded reply
+    pub async fn handle(&self, method: &str, request: &[u8]) -> Result<Vec<u8>, Status> {
+        dispatch!(
+            self.signer,
+            method,
+            request,
+            "Ping" => ping(PingRequest),
+            "Negotiate" => negotiate(NegotiateRequest),
+            "Init" => init(InitRequest),
+            "ListNodes" => list_nodes(ListNodesRequest),
+            "ListChannels" => list_channels(ListChannelsRequest),
+            "GetNodeStatus" => get_node_status(GetNodeStatusRequest),
+            "GetWalletDescriptor" => get_wallet_descriptor(GetWalletDescriptorRequest),
+            "GetAddress" => get_address(GetAddressRequest),
+            "GetChannelStatus" => get_channel_status(GetChannelStatusRequest),
+            "ListAllowlist" => list_allowlist(ListAllowlistRequest),
+            "AddAllowlist" => add_allowlist(AddAllowlistRequest),
+            "RemoveAllowlist" => remove_allowlist(RemoveAllowlistRequest),
+            "ListFeeReserve" => list_fee_reserve(ListFeeReserveRequest),
+            "AddFeeReserve" => add_fee_reserve(AddFeeReserveRequest),
+            "RemoveFeeReserve" => remove_fee_reserve(RemoveFeeReserveRequest),
+            "ListPayments" => list_payments(ListPaymentsRequest),
+            "ListWatchtowerBlobs" => list_watchtower_blobs(ListWatchtowerBlobsRequest),
+            "SetMetadata" => set_metadata(SetMetadataRequest),
+            "GetMetadata" => get_metadata(GetMetadataRequest),
+            "ListApprovals" => list_approvals(ListApprovalsRequest),
+            "DecideApproval" => decide_approval(DecideApprovalRequest),
+            "MintToken" => mint_token(MintTokenRequest),
+            "RotateToken" => rotate_token(RotateTokenRequest),
+            "RevokeToken" => revoke_token(RevokeTokenRequest),
+            "ListTokens" => list_tokens(ListTokensRequest),
+            "SetTenant" => set_tenant(SetTenantRequest),
+            "ListTenants" => list_tenants(ListTenantsRequest),
+            "RemoveTenant" => remove_tenant(RemoveTenantRequest),
+            "Shutdown" => shutdown(ShutdownRequest),
+            "GetReplicationStatus" => get_replication_status(GetReplicationStatusRequest),
+            "Promote" => promote(PromoteRequest),
+            "GetStats" => get_stats(GetStatsRequest),
+            "AcquireChannelLease" => acquire_channel_lease(AcquireChannelLeaseRequest),
+            "ReleaseChannelLease" => release_channel_lease(ReleaseChannelLeaseRequest),
+            "ListChannelReviews" => list_channel_reviews(ListChannelReviewsRequest),
+            "AcknowledgeChannelReview" => acknowledge_channel_review(AcknowledgeChannelReviewRequest),
+            "CreateBackup" => create_backup(CreateBackupRequest),
+            "RestoreBackup" => restore_backup(RestoreBackupRequest),
+            "GetNodeParam" => get_node_param(GetNodeParamRequest),
+            "NewChannel" => new_channel(NewChannelRequest),
+            "ReadyChannel" => ready_channel(ReadyChannelRequest),
+            "SignMutualCloseTx" => sign_mutual_close_tx(SignMutualCloseTxRequest),
+            "SignMutualCloseTxPhase2" => sign_mutual_close_tx_phase2(SignMutualCloseTxPhase2Request),
+            "CheckFutureSecret" => check_future_secret(CheckFutureSecretRequest),
+            "VerifyChannelMonitor" => verify_channel_monitor(VerifyChannelMonitorRequest),
+            "GetChannelBasepoints" => get_channel_basepoints(GetChannelBasepointsRequest),
+            "GetPerCommitmentPoint" => get_per_commitment_point(GetPerCommitmentPointRequest),
+            "SignOnchainTx" => sign_onchain_tx(SignOnchainTxRequest),
+            "SignFundingTx" => sign_funding_tx(SignFundingTxRequest),
+            "SignPsbt" => sign_psbt(SignPsbtRequest),
+            "SignCounterpartyCommitmentTx" => sign_counterparty_commitment_tx(SignCounterpartyCommitmentTxRequest),
+            "SignCounterpartyCommitmentTxPhase2" => sign_counterparty_commitment_tx_phase2(SignCounterpartyCommitmentTxPhase2Request),
+            "ValidateHolderCommitmentTx" => validate_holder_commitment_tx(ValidateHolderCommitmentTxRequest),
+            "ValidateHolderCommitmentTxPhase2" => validate_holder_commitment_tx_phase2(ValidateHolderCommitmentTxPhase2Request),
+            "ValidateCounterpartyRevocation" => validate_counterparty_revocation(ValidateCounterpartyRevocationRequest),
+            "SignHolderCommitmentTxPhase2" => sign_holder_commitment_tx_phase2(SignHolderCommitmentTxPhase2Request),
+            "SignHolderHTLCTx" => sign_holder_htlc_tx(SignHolderHtlcTxRequest),
+            "SignDelayedSweep" => sign_delayed_sweep(SignDelayedSweepRequest),
+            "SignCounterpartyHTLCTx" => sign_counterparty_htlc_tx(SignCounterpartyHtlcTxRequest),
+            "SignCounterpartyHTLCSweep" => sign_counterparty_htlc_sweep(SignCounterpartyHtlcSweepRequest),
+            "SignJusticeSweep" => sign_justice_sweep(SignJusticeSweepRequest),
+            "SignHolderAnchorInput" => sign_holder_anchor_input(SignHolderAnchorInputRequest),
+            "SignChannelAnnouncement" => sign_channel_announcement(SignChannelAnnouncementRequest),
+            "SignNodeAnnouncement" => sign_node_announcement(SignNodeAnnouncementRequest),
+            "SignChannelUpdate" => sign_channel_update(SignChannelUpdateRequest),
+            "SignGossipMessage" => sign_gossip_message(SignGossipMessageRequest),
+            "ECDH" => ecdh(EcdhRequest),
+            "SignInvoice" => sign_invoice(SignInvoiceRequest),
+            "SignBolt12" => sign_bolt12(SignBolt12Request),
+            "SignMessage" => sign_message(SignMessageRequest),
+            "Version" => version(VersionRequest),
+        )
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use bitcoin::secp256k1::PublicKey;
+use log::info;
+use rand::{OsRng, Rng};
+use tonic::Status;
+
+use lightning_signer::channel::ChannelId;
+
+/// The lease duration if the holder doesn't ask for one
+pub const DEFAULT_LEASE_SECS: u64 = 30;
+
+/// The longest lease granted, so that a crashed holder blocks the channel
+/// only briefly
+pub const MAX_LEASE_SECS: u64 = 300;
+
+/// An exclusive lease on a channel
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lease {
+    /// The random lease ID, presented with each state-mutating request
+    pub id: Vec<u8>,
+    /// Identifies the node replica holding the lease
+    pub holder: String,
+    /// In seconds since the UNIX epoch
+    pub expires_at: u64,
+}
+
+/// Short-lived exclusive leases on channels, for HA setups with several
+/// node replicas sharing a signer.
+///
+/// A replica acquires the lease of a channel before driving a commitment
+/// dance and presents the lease ID with each state-mutating request on
+/// the channel, so that two replicas can't interleave operations on it.
+/// The holder can renew its lease by acquiring it again before it
+/// expires.
+///
+/// Leases are kept in memory only, replicas acquire them again after a
+/// signer restart.
+#[derive(Default)]
+pub struct LeaseTable {
+    leases: Mutex<HashMap<(PublicKey, [u8; 32]), Lease>>,
+}
+
+impl LeaseTable {
+    /// Create an empty table
+    pub fn new() -> Self {
+        LeaseTable { leases: Mutex::new(HashMap::new()) }
+    }
+
+    /// Acquire or renew the lease of a channel for `duration_secs`, or
+    /// [DEFAULT_LEASE_SECS] if zero.  Fails if another holder has an
+    /// unexpired lease.
+    pub fn acquire(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+        holder: &str,
+        duration_secs: u64,
+        now: u64,
+    ) -> Result<Lease, Status> {
+        if holder.is_empty() {
+            return Err(Status::invalid_argument("missing lease holder"));
+        }
+        let duration_secs = match duration_secs {
+            0 => DEFAULT_LEASE_SECS,
+            d => d.min(MAX_LEASE_SECS),
+        };
+        let mut leases = self.leases.lock().unwrap();
+        let key = (*node_id, channel_id.0);
+        let id = match leases.get(&key) {
+            Some(lease) if lease.expires_at > now => {
+                if lease.holder != holder {
+                    return Err(Status::failed_precondition(format!(
+                        "channel {} is leased by {} for {} more seconds",
+                        channel_id,
+                        lease.holder,
+                        lease.expires_at - now
+                    )));
+                }
+                lease.id.clone()
+            }
+            _ => {
+                info!("channel {} leased by {}", channel_id, holder);
+                let mut id = vec![0u8; 16];
+                OsRng::new().expect("OsRng").fill_bytes(&mut id);
+                id
+            }
+        };
+        let lease = Lease { id, holder: holder.to_string(), expires_at: now + duration_secs };
+        leases.insert(key, lease.clone());
+        Ok(lease)
+    }
+
+    /// Release a lease before it expires.  Releasing a lease that has
+    /// already expired or been taken over is not an error.
+    pub fn release(&self, node_id: &PublicKey, channel_id: &ChannelId, lease_id: &[u8]) {
+        let mut leases = self.leases.lock().unwrap();
+        let key = (*node_id, channel_id.0);
+        if leases.get(&key).map(|lease| lease.id == lease_id).unwrap_or(false) {
+            info!("channel {} lease released", channel_id);
+            leases.remove(&key);
+        }
+    }
+
+    /// Check that a state-mutating request on a channel carries its
+    /// current lease
+    pub fn check(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+        lease_id: &[u8],
+        now: u64,
+    ) -> Result<(), Status> {
+        if lease_id.is_empty() {
+            return Err(Status::failed_precondition(format!(
+                "channel {} requires a lease",
+                channel_id
+            )));
+        }
+        let leases = self.leases.lock().unwrap();
+        match leases.get(&(*node_id, channel_id.0)) {
+            Some(lease) if lease.id == lease_id && lease.expires_at > now => Ok(()),
+            Some(lease) if lease.id == lease_id => {
+                Err(Status::failed_precondition(format!("channel {} lease expired", channel_id)))
+            }
+            _ => Err(Status::failed_precondition(format!(
+                "channel {} lease is not held",
+                channel_id
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use lightning_signer::util::test_utils::make_dummy_pubkey;
+
+    use super::*;
+
+    #[test]
+    fn lease_test() {
+        let table = LeaseTable::new();
+        let node_id = make_dummy_pubkey(0x12);
+        let channel_id = ChannelId([1; 32]);
+        let other_channel_id = ChannelId([2; 32]);
+
+        assert!(table.check(&node_id, &channel_id, &[], 1000).is_err());
+        let lease = table.acquire(&node_id, &channel_id, "a", 0, 1000).unwrap();
+        assert_eq!(lease.expires_at, 1000 + DEFAULT_LEASE_SECS);
+        table.check(&node_id, &channel_id, &lease.id, 1000).unwrap();
+        assert!(table.check(&node_id, &other_channel_id, &lease.id, 1000).is_err());
+
+        // another replica can't take over an unexpired lease
+        assert!(table.acquire(&node_id, &channel_id, "b", 0, 1010).is_err());
+
+        // the holder renews, keeping the lease ID
+        let renewed = table.acquire(&node_id, &channel_id, "a", 3600, 1020).unwrap();
+        assert_eq!(renewed.id, lease.id);
+        assert_eq!(renewed.expires_at, 1020 + MAX_LEASE_SECS);
+
+        // once expired, the lease is no longer valid and can be taken over
+        let expiry = renewed.expires_at;
+        assert!(table.check(&node_id, &channel_id, &lease.id, expiry).is_err());
+        let other = table.acquire(&node_id, &channel_id, "b", 10, expiry).unwrap();
+        assert_ne!(other.id, lease.id);
+        assert!(table.check(&node_id, &channel_id, &lease.id, expiry).is_err());
+
+        // a stale release doesn't drop the new holder's lease
+        table.release(&node_id, &channel_id, &lease.id);
+        table.check(&node_id, &channel_id, &other.id, expiry).unwrap();
+        table.release(&node_id, &channel_id, &other.id);
+        assert!(table.check(&node_id, &channel_id, &other.id, expiry).is_err());
+        table.acquire(&node_id, &channel_id, "a", 0, expiry).unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use lightning_signer::node::{policy_violations, sign_operations};
+use lightning_signer::policy::simple_validator::policy_warnings;
+use lightning_signer::signer::multi_signer::MultiSigner;
+use lightning_signer::util::crypto_utils::signature_verify_failures;
+
+/// The name of the metrics history file in the data directory
+pub const METRICS_FILE_NAME: &str = "metrics.json";
+
+/// Snapshots are taken this often
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// The number of snapshots kept, 30 days of hourly snapshots
+pub const MAX_SNAPSHOTS: usize = 24 * 30;
+
+/// The signer activity over a period, for trend analysis without an
+/// external metrics stack
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    /// The end of the period, in seconds since the UNIX epoch
+    pub timestamp: u64,
+    /// The length of the period, shorter than the interval after a restart
+    pub period_secs: u64,
+    /// Signing operations in the period
+    pub sign_operations: u64,
+    /// Signing operations refused by policy in the period
+    pub policy_violations: u64,
+    /// Policy violations let through at the warn enforcement level
+    pub policy_warnings: u64,
+    /// Signatures that failed verification before release
+    pub signature_verify_failures: u64,
+    /// The age of the oldest chain tip among the nodes, in seconds
+    pub max_chain_lag_secs: u64,
+}
+
+// The process-wide counters, which count from startup
+#[derive(Clone, Copy, Default)]
+struct Counters {
+    sign_operations: usize,
+    policy_violations: usize,
+    policy_warnings: usize,
+    signature_verify_failures: usize,
+}
+
+impl Counters {
+    fn current() -> Self {
+        Counters {
+            sign_operations: sign_operations(),
+            policy_violations: policy_violations(),
+            policy_warnings: policy_warnings().iter().map(|(_, count)| count).sum(),
+            signature_verify_failures: signature_verify_failures(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct History {
+    snapshots: VecDeque<MetricsSnapshot>,
+}
+
+struct RecorderState {
+    history: History,
+    // The counters and time at the end of the last period
+    last_counters: Counters,
+    last_timestamp: u64,
+}
+
+/// Records a [MetricsSnapshot] every [SNAPSHOT_INTERVAL] in a ring buffer
+/// of [MAX_SNAPSHOTS].
+///
+/// The ring buffer is persisted in the data directory, so that the history
+/// survives restarts.
+pub struct MetricsRecorder {
+    signer: Arc<MultiSigner>,
+    path: PathBuf,
+    state: Mutex<RecorderState>,
+}
+
+impl MetricsRecorder {
+    /// Create a recorder, loading the history from the data directory
+    pub fn new(signer: Arc<MultiSigner>, data_path: &Path) -> io::Result<Self> {
+        let path = data_path.join(METRICS_FILE_NAME);
+        let history = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => History::default(),
+            Err(e) => return Err(e),
+        };
+        let state = RecorderState {
+            history,
+            last_counters: Counters::default(),
+            last_timestamp: now_secs(),
+        };
+        Ok(MetricsRecorder { signer, path, state: Mutex::new(state) })
+    }
+
+    /// The recorded snapshots, oldest first, limited to the latest `limit`
+    /// if it is not zero
+    pub fn list(&self, limit: usize) -> Vec<MetricsSnapshot> {
+        let state = self.state.lock().unwrap();
+        let snapshots = &state.history.snapshots;
+        let skip = if limit == 0 { 0 } else { snapshots.len().saturating_sub(limit) };
+        snapshots.iter().skip(skip).cloned().collect()
+    }
+
+    /// Record a snapshot of the activity since the last one and persist
+    /// the history
+    pub fn record(&self, timestamp: u64) -> io::Result<MetricsSnapshot> {
+        let max_chain_lag_secs = self.max_chain_lag_secs(timestamp);
+        let counters = Counters::current();
+        let mut state = self.state.lock().unwrap();
+        let last = state.last_counters;
+        let snapshot = MetricsSnapshot {
+            timestamp,
+            period_secs: timestamp.saturating_sub(state.last_timestamp),
+            sign_operations: counters.sign_operations.saturating_sub(last.sign_operations) as u64,
+            policy_violations: counters.policy_violations.saturating_sub(last.policy_violations)
+                as u64,
+            policy_warnings: counters.policy_warnings.saturating_sub(last.policy_warnings) as u64,
+            signature_verify_failures: counters
+                .signature_verify_failures
+                .saturating_sub(last.signature_verify_failures)
+                as u64,
+            max_chain_lag_secs,
+        };
+        state.history.snapshots.push_back(snapshot.clone());
+        while state.history.snapshots.len() > MAX_SNAPSHOTS {
+            state.history.snapshots.pop_front();
+        }
+        state.last_counters = counters;
+        state.last_timestamp = timestamp;
+        self.save(&state.history)?;
+        Ok(snapshot)
+    }
+
+    /// Record snapshots periodically, forever
+    pub async fn run(self: Arc<Self>) {
+        info!("recording metrics to {} every {:?}", self.path.display(), SNAPSHOT_INTERVAL);
+        loop {
+            tokio::time::sleep(SNAPSHOT_INTERVAL).await;
+            if let Err(e) = self.record(now_secs()) {
+                warn!("metrics {}: {}", self.path.display(), e);
+            }
+        }
+    }
+
+    // The age of the oldest chain tip, zero if there are no nodes
+    fn max_chain_lag_secs(&self, timestamp: u64) -> u64 {
+        let mut max_lag = 0;
+        for node_id in self.signer.get_node_ids() {
+            let node = match self.signer.get_node(&node_id) {
+                Ok(node) => node,
+                Err(_) => continue, // removed concurrently
+            };
+            let tip_time = node.get_tracker().tip().time as u64;
+            max_lag = max_lag.max(timestamp.saturating_sub(tip_time));
+        }
+        max_lag
+    }
+
+    fn save(&self, history: &History) -> io::Result<()> {
+        // Write to a temporary file and rename, so that a crash never
+        // leaves a partial history
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(history)?)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Network;
+    use tempfile::TempDir;
+    use test_log::test;
+
+    use lightning_signer::node::NodeConfig;
+    use lightning_signer::signer::my_keys_manager::KeyDerivationStyle;
+
+    use super::*;
+
+    #[test]
+    fn metrics_ring_buffer_test() {
+        let dir = TempDir::new().unwrap();
+        let signer = Arc::new(MultiSigner::new());
+        signer
+            .new_node(NodeConfig {
+                network: Network::Testnet,
+                key_derivation_style: KeyDerivationStyle::Native,
+                chain_params: None,
+                checkpoint: None,
+            })
+            .unwrap();
+        let recorder = MetricsRecorder::new(Arc::clone(&signer), dir.path()).unwrap();
+        let start = recorder.state.lock().unwrap().last_timestamp;
+
+        let snapshot = recorder.record(start + 3600).unwrap();
+        assert_eq!(snapshot.period_secs, 3600);
+        // the tracker is at the genesis block
+        assert!(snapshot.max_chain_lag_secs > 3600);
+        for hour in 2..MAX_SNAPSHOTS as u64 + 3 {
+            recorder.record(start + hour * 3600).unwrap();
+        }
+        let snapshots = recorder.list(0);
+        assert_eq!(snapshots.len(), MAX_SNAPSHOTS);
+        assert_eq!(snapshots[0].timestamp, start + 3 * 3600);
+        assert_eq!(recorder.list(2).len(), 2);
+        assert_eq!(recorder.list(2)[1].timestamp, start + (MAX_SNAPSHOTS as u64 + 2) * 3600);
+
+        // the history survives a restart
+        drop(recorder);
+        let recorder = MetricsRecorder::new(signer, dir.path()).unwrap();
+        assert_eq!(recorder.list(0), snapshots);
+
+        fs::write(dir.path().join(METRICS_FILE_NAME), "not json").unwrap();
+        assert!(MetricsRecorder::new(Arc::new(MultiSigner::new()), dir.path()).is_err());
+    }
+}
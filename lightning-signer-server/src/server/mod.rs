@@ -1,4 +1,40 @@
 #[cfg(feature = "grpc")]
+pub mod approval;
+#[cfg(feature = "grpc")]
+pub mod auth;
+#[cfg(feature = "grpc")]
+pub mod cancel_safe;
+#[cfg(feature = "grpc")]
+pub mod check;
+#[cfg(feature = "grpc")]
+pub mod deprecation;
+#[cfg(feature = "grpc")]
 pub mod driver;
 #[cfg(feature = "grpc")]
+pub mod frontend;
+#[cfg(feature = "grpc")]
+pub mod lease;
+#[cfg(feature = "grpc")]
+pub mod metrics;
+#[cfg(feature = "grpc")]
+pub mod noise;
+#[cfg(feature = "grpc")]
+pub mod protocol;
+#[cfg(feature = "grpc")]
+pub mod rate_limit;
+#[cfg(feature = "grpc")]
 pub mod remotesigner;
+#[cfg(feature = "grpc")]
+pub mod replication;
+#[cfg(feature = "grpc")]
+pub mod rest;
+#[cfg(feature = "grpc")]
+pub mod serial;
+#[cfg(feature = "grpc")]
+pub mod shutdown;
+#[cfg(feature = "grpc")]
+pub mod status;
+#[cfg(feature = "grpc")]
+pub mod tenant;
+#[cfg(feature = "grpc")]
+pub mod watchtower;
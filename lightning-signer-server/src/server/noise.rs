@@ -0,0 +1,716 @@
+//! The signer protocol over TCP, encrypted and authenticated by the
+//! Noise_XK handshake of the Lightning peer protocol (BOLT 8), for devices
+//! where gRPC and TLS are too heavy.
+//!
+//! Both sides pin the static key of the other.  The node host knows the
+//! public key of the signer before it connects, and the signer only
+//! accepts the node hosts whose public keys it was given.  The static key
+//! of the signer is kept in the data directory, and its public key is
+//! logged at startup.
+//!
+//! After the handshake, the messages of the serial protocol, see
+//! [super::serial::Message], are sent as BOLT 8 messages.  A message over
+//! 64KiB is split into several, so each starts with a byte that is 1 if
+//! more parts of the message follow, and 0 on the last part.  The node host
+//! negotiates the protocol first, see [super::protocol].
+
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::{Hash, HashEngine, Hmac, HmacEngine};
+use bitcoin::secp256k1::ecdh::SharedSecret;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use log::{error, info, warn};
+use rand::{OsRng, Rng};
+use tonic::Status;
+
+use super::protocol;
+use super::remotesigner::NegotiateReply;
+use super::serial::{self, Message, MAX_MESSAGE_LEN};
+use crate::util::write_private_file;
+
+/// The name of the file with the static key of the signer, in the data
+/// directory
+pub const KEY_FILE_NAME: &str = "noise.key";
+
+const PROTOCOL_NAME: &[u8] = b"Noise_XK_secp256k1_ChaChaPoly_SHA256";
+const PROLOGUE: &[u8] = b"lightning";
+const TAG_LEN: usize = 16;
+const ACT_ONE_LEN: usize = 50;
+const ACT_TWO_LEN: usize = 50;
+const ACT_THREE_LEN: usize = 66;
+// The largest BOLT 8 message, with the byte marking the last part
+const MAX_PART_LEN: usize = u16::MAX as usize;
+// A key is rotated once it encrypted this many times
+const KEY_ROTATION_INDEX: u64 = 1000;
+// A node host must finish the handshake in this time
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn handshake_error(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("noise handshake: {}", msg))
+}
+
+// HKDF-SHA256 with two outputs, as defined by BOLT 8
+fn hkdf(salt: &[u8; 32], ikm: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut engine = HmacEngine::<Sha256>::new(salt);
+    engine.input(ikm);
+    let prk = Hmac::from_engine(engine).into_inner();
+    let mut engine = HmacEngine::<Sha256>::new(&prk);
+    engine.input(&[1]);
+    let first = Hmac::from_engine(engine).into_inner();
+    let mut engine = HmacEngine::<Sha256>::new(&prk);
+    engine.input(&first);
+    engine.input(&[2]);
+    (first, Hmac::from_engine(engine).into_inner())
+}
+
+// The SHA256 of the compressed shared point
+fn ecdh(key: &SecretKey, point: &PublicKey) -> [u8; 32] {
+    let mut secret = [0u8; 32];
+    secret.copy_from_slice(&SharedSecret::new(point, key)[..]);
+    secret
+}
+
+// The nonce is 32 zero bits and the counter, in little endian
+fn nonce(n: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&n.to_le_bytes());
+    nonce
+}
+
+fn encrypt_with_ad(key: &[u8; 32], n: u64, ad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(&nonce(n)), Payload { msg: plaintext, aad: ad })
+        .expect("encrypt")
+}
+
+fn decrypt_with_ad(key: &[u8; 32], n: u64, ad: &[u8], ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(&nonce(n)), Payload { msg: ciphertext, aad: ad })
+        .map_err(|_| io::Error::new(ErrorKind::InvalidData, "noise: bad authentication tag"))
+}
+
+fn random_key() -> SecretKey {
+    let mut rng = OsRng::new().expect("OsRng");
+    loop {
+        let mut bytes = [0u8; 32];
+        rng.fill_bytes(&mut bytes);
+        if let Ok(key) = SecretKey::from_slice(&bytes) {
+            return key;
+        }
+    }
+}
+
+// The chaining key and handshake hash of a handshake
+struct Handshake {
+    ck: [u8; 32],
+    h: [u8; 32],
+}
+
+impl Handshake {
+    fn new(responder_key: &PublicKey) -> Self {
+        let h = Sha256::hash(PROTOCOL_NAME).into_inner();
+        let mut handshake = Handshake { ck: h, h };
+        handshake.mix_hash(PROLOGUE);
+        handshake.mix_hash(&responder_key.serialize());
+        handshake
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        let mut engine = Sha256::engine();
+        engine.input(&self.h);
+        engine.input(data);
+        self.h = Sha256::from_engine(engine).into_inner();
+    }
+
+    // Mix a shared secret into the chaining key, returning the temporary key
+    fn mix_key(&mut self, secret: &[u8; 32]) -> [u8; 32] {
+        let (ck, key) = hkdf(&self.ck, secret);
+        self.ck = ck;
+        key
+    }
+
+    fn encrypt_and_hash(&mut self, key: &[u8; 32], n: u64, plaintext: &[u8]) -> Vec<u8> {
+        let ciphertext = encrypt_with_ad(key, n, &self.h, plaintext);
+        self.mix_hash(&ciphertext);
+        ciphertext
+    }
+
+    fn decrypt_and_hash(
+        &mut self,
+        key: &[u8; 32],
+        n: u64,
+        ciphertext: &[u8],
+    ) -> io::Result<Vec<u8>> {
+        let plaintext = decrypt_with_ad(key, n, &self.h, ciphertext)?;
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+
+    // The keys of the initiator and of the responder
+    fn split(self) -> (CipherState, CipherState) {
+        let (first, second) = hkdf(&self.ck, &[]);
+        (
+            CipherState { ck: self.ck, key: first, n: 0 },
+            CipherState { ck: self.ck, key: second, n: 0 },
+        )
+    }
+}
+
+// The ephemeral key of an act, after the version byte
+fn read_act_key(act: &[u8]) -> io::Result<PublicKey> {
+    if act[0] != 0 {
+        return Err(handshake_error(&format!("unknown version {}", act[0])));
+    }
+    PublicKey::from_slice(&act[1..34]).map_err(|_| handshake_error("bad ephemeral key"))
+}
+
+// The key and nonce of one direction of the transport
+struct CipherState {
+    ck: [u8; 32],
+    key: [u8; 32],
+    n: u64,
+}
+
+impl CipherState {
+    fn encrypt(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let ciphertext = encrypt_with_ad(&self.key, self.n, &[], plaintext);
+        self.next_nonce();
+        ciphertext
+    }
+
+    fn decrypt(&mut self, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+        let plaintext = decrypt_with_ad(&self.key, self.n, &[], ciphertext)?;
+        self.next_nonce();
+        Ok(plaintext)
+    }
+
+    fn next_nonce(&mut self) {
+        self.n += 1;
+        if self.n == KEY_ROTATION_INDEX {
+            let (ck, key) = hkdf(&self.ck, &self.key);
+            self.ck = ck;
+            self.key = key;
+            self.n = 0;
+        }
+    }
+}
+
+/// A stream encrypted by the Noise_XK handshake of BOLT 8
+pub struct NoiseStream<T> {
+    inner: T,
+    remote_key: PublicKey,
+    sender: CipherState,
+    receiver: CipherState,
+}
+
+impl<T: Read + Write> NoiseStream<T> {
+    /// Connect to the holder of `remote_key`, as the initiator
+    pub fn connect(inner: T, local_key: &SecretKey, remote_key: &PublicKey) -> io::Result<Self> {
+        Self::connect_with_ephemeral(inner, local_key, remote_key, &random_key())
+    }
+
+    /// Accept a connection from the holder of one of `allowed_keys`, as the
+    /// responder
+    pub fn accept(inner: T, local_key: &SecretKey, allowed_keys: &[PublicKey]) -> io::Result<Self> {
+        let stream = Self::accept_with_ephemeral(inner, local_key, &random_key())?;
+        if !allowed_keys.contains(&stream.remote_key) {
+            return Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("noise: key {} is not allowed", stream.remote_key),
+            ));
+        }
+        Ok(stream)
+    }
+
+    fn connect_with_ephemeral(
+        mut inner: T,
+        local_key: &SecretKey,
+        remote_key: &PublicKey,
+        ephemeral_key: &SecretKey,
+    ) -> io::Result<Self> {
+        let secp = Secp256k1::signing_only();
+        let mut handshake = Handshake::new(remote_key);
+
+        let ephemeral_pubkey = PublicKey::from_secret_key(&secp, ephemeral_key).serialize();
+        handshake.mix_hash(&ephemeral_pubkey);
+        let key = handshake.mix_key(&ecdh(ephemeral_key, remote_key));
+        let mut act = vec![0u8];
+        act.extend_from_slice(&ephemeral_pubkey);
+        act.extend(handshake.encrypt_and_hash(&key, 0, &[]));
+        inner.write_all(&act)?;
+        inner.flush()?;
+
+        let mut act = [0u8; ACT_TWO_LEN];
+        inner.read_exact(&mut act)?;
+        let remote_ephemeral = read_act_key(&act)?;
+        handshake.mix_hash(&act[1..34]);
+        let key = handshake.mix_key(&ecdh(ephemeral_key, &remote_ephemeral));
+        handshake.decrypt_and_hash(&key, 0, &act[34..])?;
+
+        let local_pubkey = PublicKey::from_secret_key(&secp, local_key).serialize();
+        let mut act = vec![0u8];
+        act.extend(handshake.encrypt_and_hash(&key, 1, &local_pubkey));
+        let key = handshake.mix_key(&ecdh(local_key, &remote_ephemeral));
+        act.extend(encrypt_with_ad(&key, 0, &handshake.h, &[]));
+        inner.write_all(&act)?;
+        inner.flush()?;
+
+        let (sender, receiver) = handshake.split();
+        Ok(NoiseStream { inner, remote_key: *remote_key, sender, receiver })
+    }
+
+    fn accept_with_ephemeral(
+        mut inner: T,
+        local_key: &SecretKey,
+        ephemeral_key: &SecretKey,
+    ) -> io::Result<Self> {
+        let secp = Secp256k1::signing_only();
+        let mut handshake = Handshake::new(&PublicKey::from_secret_key(&secp, local_key));
+
+        let mut act = [0u8; ACT_ONE_LEN];
+        inner.read_exact(&mut act)?;
+        let remote_ephemeral = read_act_key(&act)?;
+        handshake.mix_hash(&act[1..34]);
+        let key = handshake.mix_key(&ecdh(local_key, &remote_ephemeral));
+        handshake.decrypt_and_hash(&key, 0, &act[34..])?;
+
+        let ephemeral_pubkey = PublicKey::from_secret_key(&secp, ephemeral_key).serialize();
+        handshake.mix_hash(&ephemeral_pubkey);
+        let key = handshake.mix_key(&ecdh(ephemeral_key, &remote_ephemeral));
+        let mut act = vec![0u8];
+        act.extend_from_slice(&ephemeral_pubkey);
+        act.extend(handshake.encrypt_and_hash(&key, 0, &[]));
+        inner.write_all(&act)?;
+        inner.flush()?;
+
+        let mut act = [0u8; ACT_THREE_LEN];
+        inner.read_exact(&mut act)?;
+        if act[0] != 0 {
+            return Err(handshake_error(&format!("unknown version {}", act[0])));
+        }
+        let remote_key = handshake.decrypt_and_hash(&key, 1, &act[1..50])?;
+        let remote_key =
+            PublicKey::from_slice(&remote_key).map_err(|_| handshake_error("bad static key"))?;
+        let key = handshake.mix_key(&ecdh(ephemeral_key, &remote_key));
+        decrypt_with_ad(&key, 0, &handshake.h, &act[50..])?;
+
+        let (receiver, sender) = handshake.split();
+        Ok(NoiseStream { inner, remote_key, sender, receiver })
+    }
+
+    /// The static key of the other side
+    pub fn remote_key(&self) -> &PublicKey {
+        &self.remote_key
+    }
+
+    /// The underlying stream
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Send a message, split into parts if needed
+    pub fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        if message.len() > MAX_MESSAGE_LEN {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "noise: message too long"));
+        }
+        let mut chunks = message.chunks(MAX_PART_LEN - 1).peekable();
+        if chunks.peek().is_none() {
+            self.write_part(&[0])?;
+        }
+        while let Some(chunk) = chunks.next() {
+            let mut part = vec![chunks.peek().is_some() as u8];
+            part.extend_from_slice(chunk);
+            self.write_part(&part)?;
+        }
+        self.inner.flush()
+    }
+
+    /// Receive a message, joining its parts
+    pub fn read_message(&mut self) -> io::Result<Vec<u8>> {
+        let mut message = Vec::new();
+        loop {
+            let part = self.read_part()?;
+            let (more, data) =
+                part.split_first().ok_or_else(|| io::Error::from(ErrorKind::InvalidData))?;
+            if message.len() + data.len() > MAX_MESSAGE_LEN {
+                return Err(io::Error::new(ErrorKind::InvalidData, "noise: message too long"));
+            }
+            message.extend_from_slice(data);
+            if *more == 0 {
+                return Ok(message);
+            }
+        }
+    }
+
+    // Send a BOLT 8 message: the encrypted length and the encrypted part
+    fn write_part(&mut self, part: &[u8]) -> io::Result<()> {
+        let mut out = self.sender.encrypt(&(part.len() as u16).to_be_bytes());
+        out.extend(self.sender.encrypt(part));
+        self.inner.write_all(&out)
+    }
+
+    fn read_part(&mut self) -> io::Result<Vec<u8>> {
+        let mut header = [0u8; 2 + TAG_LEN];
+        self.inner.read_exact(&mut header)?;
+        let len = self.receiver.decrypt(&header)?;
+        let mut body = vec![0u8; u16::from_be_bytes([len[0], len[1]]) as usize + TAG_LEN];
+        self.inner.read_exact(&mut body)?;
+        self.receiver.decrypt(&body)
+    }
+}
+
+/// Load the static key of the signer from the data directory, creating it
+/// if it doesn't exist.  The key file must be readable and writable only
+/// by the owner.
+pub fn load_or_create_key(data_path: &Path) -> io::Result<SecretKey> {
+    let path = data_path.join(KEY_FILE_NAME);
+    match File::open(&path) {
+        Ok(mut file) => {
+            check_private(&path, &file)?;
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)?;
+            let bytes = hex::decode(contents.trim())
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{:?}", e)))?;
+            SecretKey::from_slice(&bytes)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let key = random_key();
+            fs::create_dir_all(data_path)?;
+            write_private_file(&path, hex::encode(&key[..]).as_bytes())?;
+            info!("created noise static key {}", path.display());
+            Ok(key)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Other users could have read the key, or replaced it with their own
+#[cfg(unix)]
+fn check_private(path: &Path, file: &File) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = file.metadata()?.permissions().mode() & 0o777;
+    if mode & !0o600 != 0 {
+        return Err(io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{} has mode {:o}, expected 600", path.display(), mode),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_private(_path: &Path, _file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Serve the node hosts holding one of `allowed_keys`, each in its own
+/// thread.  `handle` calls a method with an encoded request, as
+/// [super::frontend::Frontend::handle] does.
+pub fn serve<F>(
+    listener: TcpListener,
+    key: SecretKey,
+    allowed_keys: Vec<PublicKey>,
+    handle: F,
+) -> io::Result<()>
+where
+    F: FnMut(&str, &[u8]) -> Result<Vec<u8>, Status> + Clone + Send + 'static,
+{
+    info!(
+        "noise transport ready on {}, static key {}",
+        listener.local_addr()?,
+        PublicKey::from_secret_key(&Secp256k1::signing_only(), &key)
+    );
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("noise transport: could not accept: {}", e);
+                continue;
+            }
+        };
+        let allowed_keys = allowed_keys.clone();
+        let handle = handle.clone();
+        thread::spawn(move || {
+            let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+            match serve_connection(stream, &key, &allowed_keys, handle) {
+                Ok(()) => info!("node host {} disconnected", peer),
+                Err(e) => error!("node host {}: {}", peer, e),
+            }
+        });
+    }
+    Ok(())
+}
+
+fn serve_connection<F>(
+    stream: TcpStream,
+    key: &SecretKey,
+    allowed_keys: &[PublicKey],
+    handle: F,
+) -> io::Result<()>
+where
+    F: FnMut(&str, &[u8]) -> Result<Vec<u8>, Status>,
+{
+    let mut handle = protocol::require_negotiation(handle);
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut stream = NoiseStream::accept(stream, key, allowed_keys)?;
+    stream.get_ref().set_read_timeout(None)?;
+    info!("node host {} connected", stream.remote_key());
+    loop {
+        let data = match stream.read_message() {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if let Some(reply) = serial::answer(&data, &mut handle) {
+            stream.write_message(&reply.encode())?;
+        }
+    }
+}
+
+/// Calls the methods of a signer over the noise transport, on the node host
+pub struct NoiseClient {
+    stream: NoiseStream<TcpStream>,
+    next_seq: u16,
+}
+
+impl NoiseClient {
+    /// Connect to the signer at `addr` holding `signer_key`, with the static
+    /// key of the node host
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        local_key: &SecretKey,
+        signer_key: &PublicKey,
+    ) -> io::Result<Self> {
+        let stream = NoiseStream::connect(TcpStream::connect(addr)?, local_key, signer_key)?;
+        Ok(NoiseClient { stream, next_seq: 0 })
+    }
+
+    /// Negotiate the protocol, before calling the other methods, returning
+    /// the protocol version
+    pub fn negotiate(&mut self) -> Result<u32, Status> {
+        let reply: NegotiateReply = self.call("Negotiate", &protocol::negotiate_request())?;
+        protocol::check_reply(&reply)
+    }
+
+    /// Call a method by its name in remotesigner.proto
+    pub fn call<Req, Rep>(&mut self, method: &str, request: &Req) -> Result<Rep, Status>
+    where
+        Req: prost::Message,
+        Rep: prost::Message + Default,
+    {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let message =
+            Message::Request { seq, method: method.to_string(), body: request.encode_to_vec() };
+        self.stream.write_message(&message.encode()).map_err(transport_error)?;
+        loop {
+            let data = self.stream.read_message().map_err(transport_error)?;
+            if let Some(result) = serial::reply_to(method, seq, &data) {
+                return result;
+            }
+        }
+    }
+}
+
+fn transport_error(e: io::Error) -> Status {
+    Status::unavailable(format!("noise transport: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use prost::Message as _;
+    use tempfile::TempDir;
+    use test_log::test;
+    use tonic::Code;
+
+    use super::super::remotesigner::{NegotiateRequest, PingReply, PingRequest};
+    use super::*;
+
+    // Reads what the other side would send, and records what is written
+    struct Script {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn script(input: &str) -> Script {
+        Script { input: Cursor::new(hex::decode(input).unwrap()), output: Vec::new() }
+    }
+
+    fn key(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn pubkey(byte: u8) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::signing_only(), &key(byte))
+    }
+
+    const ACT_ONE: &str = "00036360e856310ce5d294e8be33fc807077dc56ac80d95d9cd4ddbd21325eff73f70df6086551151f58b8afe6c195782c6a";
+    const ACT_TWO: &str = "0002466d7fcae563e5cb09a0d1870bb580344804617879a14949cf22285f1bae3f276e2470b93aac583c9ef6eafca3f730ae";
+    const ACT_THREE: &str = "00b9e3a702e93e3a9948c2ed6e5fd7590a6e1c3a0344cfc9d5b57357049aa22355361aa02e55a8fc28fef5bd6d71ad0c38228dc68b1c466263b47fdf31e560e139ba";
+
+    // The test vectors of BOLT 8
+    #[test]
+    fn handshake_test() {
+        let stream = NoiseStream::connect_with_ephemeral(
+            script(ACT_TWO),
+            &key(0x11),
+            &pubkey(0x21),
+            &key(0x12),
+        )
+        .unwrap();
+        assert_eq!(hex::encode(&stream.inner.output), format!("{}{}", ACT_ONE, ACT_THREE));
+        assert_eq!(
+            hex::encode(stream.sender.key),
+            "969ab31b4d288cedf6218839b27a3e2140827047f2c0f01bf5c04435d43511a9"
+        );
+        assert_eq!(
+            hex::encode(stream.receiver.key),
+            "bb9020b8965f4df047e07f955f3c4b88418984aadc5cdb35096b9ea8fa5c3442"
+        );
+
+        let input = format!("{}{}", ACT_ONE, ACT_THREE);
+        let stream =
+            NoiseStream::accept_with_ephemeral(script(&input), &key(0x21), &key(0x22)).unwrap();
+        assert_eq!(hex::encode(&stream.inner.output), ACT_TWO);
+        assert_eq!(stream.remote_key, pubkey(0x11));
+        assert_eq!(
+            hex::encode(stream.receiver.key),
+            "969ab31b4d288cedf6218839b27a3e2140827047f2c0f01bf5c04435d43511a9"
+        );
+
+        // a tampered act three is refused
+        let mut tampered = hex::decode(&input).unwrap();
+        tampered[ACT_ONE_LEN + 10] ^= 1;
+        let tampered = script(&hex::encode(tampered));
+        assert!(NoiseStream::accept_with_ephemeral(tampered, &key(0x21), &key(0x22)).is_err());
+    }
+
+    #[test]
+    fn key_rotation_test() {
+        let mut stream = NoiseStream::connect_with_ephemeral(
+            script(ACT_TWO),
+            &key(0x11),
+            &pubkey(0x21),
+            &key(0x12),
+        )
+        .unwrap();
+        let mut sent = Vec::new();
+        for _ in 0..1002 {
+            stream.inner.output.clear();
+            stream.write_part(b"hello").unwrap();
+            sent.push(hex::encode(&stream.inner.output));
+        }
+        assert_eq!(
+            sent[0],
+            "cf2b30ddf0cf3f80e7c35a6e6730b59fe802473180f396d88a8fb0db8cbcf25d2f214cf9ea1d95"
+        );
+        assert_eq!(
+            sent[1],
+            "72887022101f0b6753e0c7de21657d35a4cb2a1f5cde2650528bbc8f837d0f0d7ad833b1a256a1"
+        );
+        assert_eq!(
+            sent[500],
+            "178cb9d7387190fa34db9c2d50027d21793c9bc2d40b1e14dcf30ebeeeb220f48364f7a4c68bf8"
+        );
+        assert_eq!(
+            sent[1000],
+            "4a2f3cc3b5e78ddb83dcb426d9863d9d9a723b0337c89dd0b005d89f8d3c05c52b76b29b740f09"
+        );
+    }
+
+    #[test]
+    fn serve_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let signer_key = key(0x21);
+        let signer_pubkey = pubkey(0x21);
+        thread::spawn(move || {
+            serve(listener, signer_key, vec![pubkey(0x11)], |method, request| match method {
+                "Ping" => {
+                    let request = PingRequest::decode(request).unwrap();
+                    Ok(PingReply { message: request.message }.encode_to_vec())
+                }
+                "Negotiate" => {
+                    let request = NegotiateRequest::decode(request).unwrap();
+                    Ok(protocol::negotiate(&request)?.encode_to_vec())
+                }
+                _ => Err(Status::unimplemented(method)),
+            })
+        });
+
+        let mut client = NoiseClient::connect(addr, &key(0x11), &signer_pubkey).unwrap();
+        assert_eq!(client.negotiate().unwrap(), protocol::PROTOCOL_VERSION);
+        let request = PingRequest { message: "hello".to_string() };
+        let reply: PingReply = client.call("Ping", &request).unwrap();
+        assert_eq!(reply.message, "hello");
+        // a message is split over several parts
+        let request = PingRequest { message: "x".repeat(3 * MAX_PART_LEN) };
+        let reply: PingReply = client.call("Ping", &request).unwrap();
+        assert_eq!(reply.message.len(), 3 * MAX_PART_LEN);
+        let err = client.call::<_, PingReply>("Nope", &request).unwrap_err();
+        assert_eq!(err.code(), Code::Unimplemented);
+
+        // a node host that isn't pinned is dropped after the handshake
+        let mut client = NoiseClient::connect(addr, &key(0x13), &signer_pubkey).unwrap();
+        let err = client.call::<_, PingReply>("Ping", &request).unwrap_err();
+        assert_eq!(err.code(), Code::Unavailable);
+
+        // the node host pins the signer key, so the signer can't complete
+        // the handshake with another key
+        assert!(NoiseClient::connect(addr, &key(0x11), &pubkey(0x22)).is_err());
+    }
+
+    #[test]
+    fn load_or_create_key_test() {
+        let dir = TempDir::new().unwrap();
+        let key = load_or_create_key(dir.path()).unwrap();
+        assert_eq!(load_or_create_key(dir.path()).unwrap(), key);
+        fs::write(dir.path().join(KEY_FILE_NAME), "nope").unwrap();
+        assert!(load_or_create_key(dir.path()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn load_or_create_key_mode_test() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(KEY_FILE_NAME);
+        let key = load_or_create_key(dir.path()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        // a key other users can read is refused
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let err = load_or_create_key(dir.path()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o400)).unwrap();
+        assert_eq!(load_or_create_key(dir.path()).unwrap(), key);
+    }
+}
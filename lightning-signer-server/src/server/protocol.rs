@@ -0,0 +1,213 @@
+//! Negotiation of the protocol version and features, at connection setup.
+//!
+//! The client sends the range of protocol versions it speaks and its
+//! feature bits, with the Negotiate method, and the signer replies with the
+//! version to use and its own feature bits.  As in BOLT 9, the features
+//! come in pairs of bits, the even bit meaning the feature is required and
+//! the odd bit that it is optional.  A side refuses a peer requiring a
+//! feature it doesn't know, so that a change to the messages or the policy
+//! can't be deployed to one side without the other noticing.
+//!
+//! The raw transports, [super::serial] and [super::noise], refuse the other
+//! methods until the protocol was negotiated.  The gRPC client negotiates
+//! when it connects.
+
+use log::{info, warn};
+use tonic::Status;
+
+use super::remotesigner::{NegotiateReply, NegotiateRequest};
+
+/// The protocol version spoken by this signer
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The oldest protocol version still spoken by this signer
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// The two-phase signing methods, taking the commitment info instead of the
+/// transaction
+pub const FEATURE_PHASE2: usize = 0;
+/// Channel leases, see AcquireChannelLease
+pub const FEATURE_CHANNEL_LEASES: usize = 2;
+/// Parked requests, approved by an operator, see ListApprovals
+pub const FEATURE_APPROVALS: usize = 4;
+/// Anchor outputs, see SignHolderAnchorInput
+pub const FEATURE_ANCHORS: usize = 6;
+
+// The features of this signer, by their even bit
+const KNOWN_FEATURES: [usize; 4] =
+    [FEATURE_PHASE2, FEATURE_CHANNEL_LEASES, FEATURE_APPROVALS, FEATURE_ANCHORS];
+
+/// The methods that may be called before the protocol was negotiated
+pub const UNNEGOTIATED_METHODS: [&str; 3] = ["Negotiate", "Ping", "Version"];
+
+/// Encode feature bits, with the highest bits first, as in BOLT 9
+pub fn encode_features(bits: &[usize]) -> Vec<u8> {
+    let len = bits.iter().map(|bit| bit / 8 + 1).max().unwrap_or(0);
+    let mut features = vec![0u8; len];
+    for bit in bits {
+        features[len - 1 - bit / 8] |= 1 << (bit % 8);
+    }
+    features
+}
+
+/// Whether `bit` is set in encoded feature bits
+pub fn has_feature(features: &[u8], bit: usize) -> bool {
+    let byte = bit / 8;
+    byte < features.len() && features[features.len() - 1 - byte] & (1 << (bit % 8)) != 0
+}
+
+// The set bits of encoded features
+fn feature_bits(features: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    (0..features.len() * 8).filter(move |bit| has_feature(features, *bit))
+}
+
+/// The features of this signer, all optional
+pub fn local_features() -> Vec<u8> {
+    let bits: Vec<usize> = KNOWN_FEATURES.iter().map(|bit| bit + 1).collect();
+    encode_features(&bits)
+}
+
+/// Check that the peer doesn't require a feature that isn't known
+pub fn check_features(features: &[u8]) -> Result<(), Status> {
+    for bit in feature_bits(features) {
+        if bit % 2 == 0 && !KNOWN_FEATURES.contains(&bit) {
+            return Err(Status::failed_precondition(format!(
+                "unknown required feature bit {}",
+                bit
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Negotiate the protocol with a client, on the signer side
+pub fn negotiate(request: &NegotiateRequest) -> Result<NegotiateReply, Status> {
+    if request.max_protocol_version < MIN_PROTOCOL_VERSION
+        || request.min_protocol_version > PROTOCOL_VERSION
+        || request.min_protocol_version > request.max_protocol_version
+    {
+        return Err(Status::failed_precondition(format!(
+            "protocol versions {}..={} not supported, the signer speaks {}..={}",
+            request.min_protocol_version,
+            request.max_protocol_version,
+            MIN_PROTOCOL_VERSION,
+            PROTOCOL_VERSION
+        )));
+    }
+    check_features(&request.features)?;
+    let protocol_version = request.max_protocol_version.min(PROTOCOL_VERSION);
+    info!("negotiated protocol version {}", protocol_version);
+    Ok(NegotiateReply { protocol_version, features: local_features() })
+}
+
+/// The negotiation request of a client
+pub fn negotiate_request() -> NegotiateRequest {
+    NegotiateRequest {
+        min_protocol_version: MIN_PROTOCOL_VERSION,
+        max_protocol_version: PROTOCOL_VERSION,
+        features: local_features(),
+    }
+}
+
+/// Check the negotiation reply of the signer, on the client side, returning
+/// the protocol version
+pub fn check_reply(reply: &NegotiateReply) -> Result<u32, Status> {
+    if reply.protocol_version < MIN_PROTOCOL_VERSION || reply.protocol_version > PROTOCOL_VERSION {
+        return Err(Status::failed_precondition(format!(
+            "the signer chose protocol version {}",
+            reply.protocol_version
+        )));
+    }
+    check_features(&reply.features)?;
+    Ok(reply.protocol_version)
+}
+
+/// Refuse the methods of a connection until the protocol was negotiated.
+/// `handle` calls a method with an encoded request, as
+/// [super::frontend::Frontend::handle] does.
+pub fn require_negotiation<F>(mut handle: F) -> impl FnMut(&str, &[u8]) -> Result<Vec<u8>, Status>
+where
+    F: FnMut(&str, &[u8]) -> Result<Vec<u8>, Status>,
+{
+    let mut negotiated = false;
+    move |method, request| {
+        if method == "Negotiate" {
+            let reply = handle(method, request);
+            negotiated = reply.is_ok();
+            return reply;
+        }
+        if !negotiated && !UNNEGOTIATED_METHODS.contains(&method) {
+            warn!("refusing {} before the protocol was negotiated", method);
+            return Err(Status::failed_precondition(format!(
+                "negotiate the protocol before calling {}",
+                method
+            )));
+        }
+        handle(method, request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+    use test_log::test;
+    use tonic::Code;
+
+    use super::*;
+
+    #[test]
+    fn features_test() {
+        let features = encode_features(&[1, 9]);
+        assert_eq!(features, vec![0x02, 0x02]);
+        assert!(has_feature(&features, 9));
+        assert!(!has_feature(&features, 8));
+        assert!(!has_feature(&features, 17));
+        assert!(check_features(&local_features()).is_ok());
+        // an unknown feature may be optional, but not required
+        assert!(check_features(&encode_features(&[FEATURE_ANCHORS, 41])).is_ok());
+        let err = check_features(&encode_features(&[40])).unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+    }
+
+    #[test]
+    fn negotiate_test() {
+        let reply = negotiate(&negotiate_request()).unwrap();
+        assert_eq!(check_reply(&reply).unwrap(), PROTOCOL_VERSION);
+
+        // a newer client falls back to this version
+        let request = NegotiateRequest {
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            max_protocol_version: PROTOCOL_VERSION + 3,
+            features: vec![],
+        };
+        assert_eq!(negotiate(&request).unwrap().protocol_version, PROTOCOL_VERSION);
+
+        let request = NegotiateRequest {
+            min_protocol_version: PROTOCOL_VERSION + 1,
+            max_protocol_version: PROTOCOL_VERSION + 3,
+            features: vec![],
+        };
+        assert_eq!(negotiate(&request).unwrap_err().code(), Code::FailedPrecondition);
+        let request = NegotiateRequest { features: encode_features(&[40]), ..negotiate_request() };
+        assert_eq!(negotiate(&request).unwrap_err().code(), Code::FailedPrecondition);
+    }
+
+    #[test]
+    fn require_negotiation_test() {
+        let mut handle = require_negotiation(|method, request| match method {
+            "Negotiate" => {
+                let request = NegotiateRequest::decode(request).unwrap();
+                Ok(negotiate(&request)?.encode_to_vec())
+            }
+            _ => Ok(vec![]),
+        });
+        handle("Ping", &[]).unwrap();
+        assert_eq!(handle("SignInvoice", &[]).unwrap_err().code(), Code::FailedPrecondition);
+        let bad_request =
+            NegotiateRequest { features: encode_features(&[40]), ..negotiate_request() };
+        assert!(handle("Negotiate", &bad_request.encode_to_vec()).is_err());
+        assert!(handle("SignInvoice", &[]).is_err());
+        handle("Negotiate", &negotiate_request().encode_to_vec()).unwrap();
+        handle("SignInvoice", &[]).unwrap();
+    }
+}
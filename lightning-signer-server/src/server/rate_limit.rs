@@ -0,0 +1,281 @@
+//! Rate limiting of the signing requests, so that a runaway or compromised
+//! node can't keep the signer busy.
+//!
+//! Each node has a token bucket over all its signing requests, and a token
+//! bucket for each method with its own limit.  A request that finds an
+//! empty bucket is refused with RESOURCE_EXHAUSTED, and the time until the
+//! bucket has a token again in the `retry-after-ms` metadata.
+//!
+//! ECDH is limited by default, see [DEFAULT_ECDH_QUOTA], since a node
+//! asking for many shared secrets with its node key may be trying to use
+//! the signer as an oracle.
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bitcoin::secp256k1::PublicKey;
+use hyper::Body;
+use log::warn;
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Status};
+use tower::{Layer, Service};
+
+use super::auth::{method_scope, Scope};
+
+/// The response metadata key of the milliseconds until a rate limited
+/// request can be retried
+pub const RETRY_AFTER_METADATA_KEY: &str = "retry-after-ms";
+
+tokio::task_local! {
+    // The signing method being served, if any
+    static METHOD: String;
+}
+
+/// The limit of a token bucket
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota {
+    /// Requests per second, on average
+    pub rate: f64,
+    /// Requests that can be made at once, after a pause
+    pub burst: f64,
+}
+
+/// The limit of the ECDH requests of a node, unless another is configured.
+/// This leaves plenty of room for onion decoding at a busy node.
+pub const DEFAULT_ECDH_QUOTA: Quota = Quota { rate: 100.0, burst: 1000.0 };
+
+/// Parse a quota, as `<per second>[/<burst>]`.  The burst defaults to one
+/// second of requests.
+pub fn parse_quota(s: &str) -> Result<Quota, String> {
+    let (rate, burst) = match s.split_once('/') {
+        Some((rate, burst)) => (rate, Some(burst)),
+        None => (s, None),
+    };
+    let rate: f64 = rate.parse().map_err(|e| format!("rate limit {}: {}", s, e))?;
+    let burst = match burst {
+        Some(burst) => burst.parse().map_err(|e| format!("rate limit {}: {}", s, e))?,
+        None => rate.max(1.0),
+    };
+    if !(rate > 0.0) || burst < 1.0 {
+        return Err(format!(
+            "rate limit {}: the rate must be positive and the burst at least 1",
+            s
+        ));
+    }
+    Ok(Quota { rate, burst })
+}
+
+/// Parse the quota of a method, as `<method>=<quota>`, see [parse_quota]
+pub fn parse_method_quota(s: &str) -> Result<(String, Quota), String> {
+    let (method, quota) =
+        s.split_once('=').ok_or_else(|| format!("rate limit {}: expected <method>=<quota>", s))?;
+    if method_scope(method) != Scope::ChannelOps {
+        return Err(format!("rate limit {}: {} is not a signing method", s, method));
+    }
+    Ok((method.to_string(), parse_quota(quota)?))
+}
+
+/// Add the default ECDH limit to the method quotas, if ECDH has none
+pub fn with_default_ecdh_quota(mut per_method: Vec<(String, Quota)>) -> Vec<(String, Quota)> {
+    if !per_method.iter().any(|(method, _)| method == "ECDH") {
+        per_method.push(("ECDH".to_string(), DEFAULT_ECDH_QUOTA));
+    }
+    per_method
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    // Refill the bucket, returning how long until it has a token
+    fn refill(&mut self, quota: &Quota, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * quota.rate).min(quota.burst);
+        self.updated_at = now;
+        if self.tokens >= 1.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / quota.rate)
+        }
+    }
+}
+
+/// Token buckets per node and per method of the node
+pub struct RateLimiter {
+    per_node: Option<Quota>,
+    per_method: BTreeMap<String, Quota>,
+    // by node, and by method if the method has its own limit
+    buckets: Mutex<HashMap<(PublicKey, Option<String>), Bucket>>,
+}
+
+impl RateLimiter {
+    /// Limit each node to `per_node` signing requests, and the methods in
+    /// `per_method` separately
+    pub fn new(per_node: Option<Quota>, per_method: Vec<(String, Quota)>) -> Self {
+        RateLimiter {
+            per_node,
+            per_method: per_method.into_iter().collect(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token for a request of a node, or return how long until the
+    /// request can be retried
+    pub fn check(&self, node_id: &PublicKey, method: &str, now: Instant) -> Result<(), Duration> {
+        let mut limits = Vec::new();
+        if let Some(quota) = self.per_node {
+            limits.push(((*node_id, None), quota));
+        }
+        if let Some(quota) = self.per_method.get(method) {
+            limits.push(((*node_id, Some(method.to_string())), *quota));
+        }
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut wait = Duration::from_secs(0);
+        for (key, quota) in limits.iter() {
+            let bucket = buckets
+                .entry(key.clone())
+                .or_insert_with(|| Bucket { tokens: quota.burst, updated_at: now });
+            wait = wait.max(bucket.refill(quota, now));
+        }
+        if wait > Duration::from_secs(0) {
+            return Err(wait);
+        }
+        // only take the tokens if all the buckets have one
+        for (key, _) in limits.iter() {
+            buckets.get_mut(key).expect("bucket").tokens -= 1.0;
+        }
+        Ok(())
+    }
+
+    /// Take a token for the signing request being served, if any, see
+    /// [RateLimitLayer]
+    pub fn check_request(&self, node_id: &PublicKey) -> Result<(), Status> {
+        let method = match METHOD.try_with(|m| m.clone()) {
+            Ok(method) => method,
+            Err(_) => return Ok(()),
+        };
+        self.check(node_id, &method, Instant::now()).map_err(|retry_after| {
+            warn!("rate limited {} for node {}", method, node_id);
+            let mut metadata = MetadataMap::new();
+            let millis = retry_after.as_millis() + 1;
+            metadata.insert(RETRY_AFTER_METADATA_KEY, millis.to_string().parse().expect("digits"));
+            Status::with_metadata(
+                Code::ResourceExhausted,
+                format!(
+                    "{} is rate limited for node {}, retry after {}ms",
+                    method, node_id, millis
+                ),
+                metadata,
+            )
+        })
+    }
+}
+
+/// A tower layer that marks the signing requests, so that the handlers
+/// check them against the rate limiter once they know the node
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    enabled: bool,
+}
+
+impl RateLimitLayer {
+    /// Mark the signing requests if `limiter` is set
+    pub fn new(limiter: Option<&Arc<RateLimiter>>) -> Self {
+        RateLimitLayer { enabled: limiter.is_some() }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService { inner, enabled: self.enabled }
+    }
+}
+
+/// The service of [RateLimitLayer]
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    enabled: bool,
+}
+
+impl<S> Service<http::Request<Body>> for RateLimitService<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        // use the service that was driven to readiness
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        // the path is /<package>.<service>/<method>
+        let method = request.uri().path().rsplit('/').next().unwrap_or_default().to_string();
+        if !self.enabled || method_scope(&method) != Scope::ChannelOps {
+            return Box::pin(inner.call(request));
+        }
+        Box::pin(METHOD.scope(method, inner.call(request)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning_signer::util::test_utils::make_dummy_pubkey;
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn parse_quota_test() {
+        assert_eq!(parse_quota("10").unwrap(), Quota { rate: 10.0, burst: 10.0 });
+        assert_eq!(parse_quota("0.5/3").unwrap(), Quota { rate: 0.5, burst: 3.0 });
+        assert!(parse_quota("0").is_err());
+        assert!(parse_quota("x").is_err());
+        let (method, quota) = parse_method_quota("SignInvoice=1/2").unwrap();
+        assert_eq!((method.as_str(), quota), ("SignInvoice", Quota { rate: 1.0, burst: 2.0 }));
+        assert!(parse_method_quota("ListNodes=1").is_err());
+        let (method, quota) = parse_method_quota("ECDH=5").unwrap();
+        assert_eq!(with_default_ecdh_quota(vec![]), vec![("ECDH".to_string(), DEFAULT_ECDH_QUOTA)]);
+        assert_eq!(with_default_ecdh_quota(vec![(method.clone(), quota)]), vec![(method, quota)]);
+    }
+
+    #[test]
+    fn rate_limiter_test() {
+        let per_node = Quota { rate: 10.0, burst: 3.0 };
+        let per_method = vec![("SignInvoice".to_string(), Quota { rate: 1.0, burst: 1.0 })];
+        let limiter = RateLimiter::new(Some(per_node), per_method);
+        let node_id = make_dummy_pubkey(0x12);
+        let other_node_id = make_dummy_pubkey(0x13);
+        let now = Instant::now();
+
+        limiter.check(&node_id, "SignInvoice", now).unwrap();
+        let wait = limiter.check(&node_id, "SignInvoice", now).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(1));
+        // the refused request didn't take from the node bucket
+        limiter.check(&node_id, "SignMessage", now).unwrap();
+        limiter.check(&node_id, "SignMessage", now).unwrap();
+        let wait = limiter.check(&node_id, "SignMessage", now).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(100));
+        limiter.check(&other_node_id, "SignMessage", now).unwrap();
+
+        // the buckets refill over time
+        limiter.check(&node_id, "SignMessage", now + Duration::from_millis(200)).unwrap();
+        limiter.check(&node_id, "SignInvoice", now + Duration::from_secs(1)).unwrap();
+    }
+}
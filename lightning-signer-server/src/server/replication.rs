@@ -0,0 +1,325 @@
+//! Hot-standby replication with fencing.
+//!
+//! A primary and a standby signer share a persister, such as a PostgreSQL
+//! database or an S3 bucket.  The primary takes the fencing token of the
+//! store when it starts.  The standby refuses the requests that sign or
+//! change state, and mirrors the channels written by the primary into
+//! memory, so that it can take over without a restart.
+//!
+//! Promoting the standby takes the next fencing token, which fences off
+//! the old primary even if it is still running, and reloads all nodes.
+//! From then on, the old primary refuses to sign, and its writes are
+//! refused by [FencedPersister].
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bitcoin::secp256k1::PublicKey;
+use hyper::Body;
+use log::{error, info};
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::Status;
+use tower::{Layer, Service};
+
+use lightning_signer::persist::{Error, Persist};
+use lightning_signer::signer::multi_signer::MultiSigner;
+
+use super::auth::{method_scope, Scope};
+use super::driver::internal_error;
+use crate::persist::fenced::FencedPersister;
+
+/// The role of a signer in a primary and standby pair
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Signs, holding the current fencing token
+    Primary,
+    /// Mirrors the primary, and doesn't sign until promoted
+    Standby,
+    /// Was the primary, until another signer took the fencing token
+    Fenced,
+}
+
+impl Role {
+    /// The name of the role, for the command line and status replies
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Primary => "primary",
+            Role::Standby => "standby",
+            Role::Fenced => "fenced",
+        }
+    }
+}
+
+/// The replication state of a signer sharing its persister with another
+/// signer
+pub struct Replication {
+    signer: Arc<MultiSigner>,
+    persister: Arc<FencedPersister>,
+    role: Mutex<Role>,
+    // The sequence number of the last channel change mirrored
+    sequence: AtomicU64,
+    interval: Duration,
+}
+
+impl Replication {
+    /// Start as a standby of the signer holding the fencing token of
+    /// `persister`.  The nodes of `signer` must have just been loaded
+    /// from `persister`.
+    pub fn new(
+        signer: Arc<MultiSigner>,
+        persister: Arc<FencedPersister>,
+        interval: Duration,
+    ) -> Result<Self, Error> {
+        let sequence = persister.export_since(0)?.iter().map(|c| c.sequence).max().unwrap_or(0);
+        Ok(Replication {
+            signer,
+            persister,
+            role: Mutex::new(Role::Standby),
+            sequence: AtomicU64::new(sequence),
+            interval,
+        })
+    }
+
+    /// The current role
+    pub fn role(&self) -> Role {
+        *self.role.lock().unwrap()
+    }
+
+    /// The fencing token held by this signer, if any
+    pub fn token(&self) -> Option<u64> {
+        self.persister.token()
+    }
+
+    /// The sequence number of the last channel change mirrored from the
+    /// primary
+    pub fn mirrored_sequence(&self) -> u64 {
+        self.sequence.load(Ordering::SeqCst)
+    }
+
+    /// Take the next fencing token and reload all nodes from the
+    /// persister, so that this signer signs from the latest state written
+    /// by the old primary
+    pub fn promote(&self) -> Result<u64, Status> {
+        let mut role = self.role.lock().unwrap();
+        if *role == Role::Primary {
+            return Err(Status::failed_precondition("already the primary"));
+        }
+        let token = self.persister.acquire().map_err(fence_error)?;
+        let mut node_ids = self.signer.get_node_ids();
+        let nodes = self
+            .persister
+            .get_nodes()
+            .map_err(|e| internal_error(format!("get nodes failed: {}", e)))?;
+        for (node_id, _) in nodes {
+            if !node_ids.contains(&node_id) {
+                node_ids.push(node_id);
+            }
+        }
+        self.signer.reload_nodes(&node_ids)?;
+        *role = Role::Primary;
+        info!("promoted to primary with fencing token {}, {} nodes", token, node_ids.len());
+        Ok(token)
+    }
+
+    /// Check that this signer may sign, as the primary holding the current
+    /// fencing token
+    pub fn check_primary(&self) -> Result<(), Status> {
+        match self.role() {
+            Role::Primary => {}
+            Role::Standby => {
+                return Err(Status::failed_precondition("standby signer, not signing"))
+            }
+            Role::Fenced => {
+                return Err(Status::failed_precondition("fenced off by another signer"));
+            }
+        }
+        match self.persister.check() {
+            Err(Error::Refused(msg)) => {
+                let mut role = self.role.lock().unwrap();
+                if *role == Role::Primary {
+                    error!("fenced off by another signer, not signing anymore");
+                    *role = Role::Fenced;
+                }
+                Err(Status::failed_precondition(format!("fenced off by another signer: {}", msg)))
+            }
+            res => res.map_err(fence_error),
+        }
+    }
+
+    /// Mirror the primary until promoted
+    pub async fn run(self: Arc<Self>) {
+        info!("standby, mirroring the primary every {:?}", self.interval);
+        while self.role() == Role::Standby {
+            self.mirror();
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    // Reload the nodes with channel changes since the last pass, and the
+    // nodes created since
+    fn mirror(&self) {
+        let since = self.mirrored_sequence();
+        let mut last = since;
+        let mut node_ids: Vec<PublicKey> = Vec::new();
+        let changes = match self.persister.export_since(since) {
+            Ok(changes) => changes,
+            Err(e) => {
+                error!("mirror failed to get the channel changes: {}", e);
+                return;
+            }
+        };
+        for change in changes {
+            last = last.max(change.sequence);
+            if !node_ids.contains(&change.node_id) {
+                node_ids.push(change.node_id);
+            }
+        }
+        let nodes = match self.persister.get_nodes() {
+            Ok(nodes) => nodes,
+            Err(e) => {
+                error!("mirror failed to get the nodes: {}", e);
+                return;
+            }
+        };
+        let known = self.signer.get_node_ids();
+        for (node_id, _) in nodes {
+            if !known.contains(&node_id) && !node_ids.contains(&node_id) {
+                node_ids.push(node_id);
+            }
+        }
+        // a promotion reloads everything, so the pass is moot
+        let role = self.role.lock().unwrap();
+        if *role == Role::Standby && !node_ids.is_empty() {
+            if let Err(e) = self.signer.reload_nodes(&node_ids) {
+                error!("mirror failed to reload the nodes: {}", e);
+                return;
+            }
+            self.sequence.store(last, Ordering::SeqCst);
+            info!("mirrored {} nodes up to change {}", node_ids.len(), last);
+        }
+    }
+}
+
+fn fence_error(e: Error) -> Status {
+    match e {
+        Error::Refused(msg) => Status::failed_precondition(format!("fencing: {}", msg)),
+        Error::Unavailable(msg) => Status::unavailable(format!("fencing: {}", msg)),
+        e => internal_error(format!("fencing: {}", e)),
+    }
+}
+
+/// A tower layer that refuses the requests that may sign or change
+/// signer state, unless this signer is the primary holding the current
+/// fencing token.  Read-only requests and promotions are always served.
+#[derive(Clone)]
+pub struct ReplicationLayer {
+    replication: Option<Arc<Replication>>,
+}
+
+impl ReplicationLayer {
+    /// Check requests against `replication`, or let all requests through
+    /// if `None`
+    pub fn new(replication: Option<Arc<Replication>>) -> Self {
+        ReplicationLayer { replication }
+    }
+}
+
+impl<S> Layer<S> for ReplicationLayer {
+    type Service = ReplicationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ReplicationService { inner, replication: self.replication.clone() }
+    }
+}
+
+/// The service of [ReplicationLayer]
+#[derive(Clone)]
+pub struct ReplicationService<S> {
+    inner: S,
+    replication: Option<Arc<Replication>>,
+}
+
+impl<S> Service<http::Request<Body>> for ReplicationService<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        // use the service that was driven to readiness
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        // the path is /<package>.<service>/<method>
+        let method = request.uri().path().rsplit('/').next().unwrap_or_default();
+        let replication = match self.replication.as_ref() {
+            Some(replication) if method != "Promote" && method_scope(method) != Scope::ReadOnly => {
+                Arc::clone(replication)
+            }
+            _ => return Box::pin(inner.call(request)),
+        };
+        Box::pin(async move {
+            // the check reads the fencing token from the persister
+            let checked = tokio::task::spawn_blocking(move || replication.check_primary())
+                .await
+                .unwrap_or_else(|e| Err(internal_error(format!("fencing check failed: {}", e))));
+            match checked {
+                Ok(()) => inner.call(request).await,
+                Err(status) => Ok(status.to_http()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning_signer::persist::memory::MemoryPersister;
+    use lightning_signer::policy::simple_validator::SimpleValidatorFactory;
+    use lightning_signer::util::test_utils::TEST_NODE_CONFIG;
+    use test_log::test;
+    use tonic::Code;
+
+    use super::*;
+
+    fn make_signer(store: &Arc<dyn Persist>) -> (Replication, Arc<MultiSigner>) {
+        let fenced = Arc::new(FencedPersister::new(Arc::clone(store)));
+        let signer = Arc::new(MultiSigner::new_with_persister(
+            Arc::clone(&fenced) as Arc<dyn Persist>,
+            true,
+            vec![],
+            Arc::new(SimpleValidatorFactory::new()),
+        ));
+        (Replication::new(Arc::clone(&signer), fenced, Duration::from_secs(1)).unwrap(), signer)
+    }
+
+    #[test]
+    fn replication_test() {
+        let store: Arc<dyn Persist> = Arc::new(MemoryPersister::new());
+        let (primary, primary_signer) = make_signer(&store);
+        assert_eq!(primary.promote().unwrap(), 1);
+        let (standby, standby_signer) = make_signer(&store);
+        assert_eq!(standby.check_primary().unwrap_err().code(), Code::FailedPrecondition);
+
+        let node_id = primary_signer.new_node(TEST_NODE_CONFIG).unwrap();
+        standby.mirror();
+        assert_eq!(standby_signer.get_node_ids(), vec![node_id]);
+
+        // the promoted standby fences off the old primary
+        primary.check_primary().unwrap();
+        assert_eq!(standby.promote().unwrap(), 2);
+        standby.check_primary().unwrap();
+        assert_eq!(primary.check_primary().unwrap_err().code(), Code::FailedPrecondition);
+        assert_eq!(primary.role(), Role::Fenced);
+    }
+}
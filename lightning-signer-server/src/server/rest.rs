@@ -0,0 +1,256 @@
+//! A JSON gateway to the administrative gRPC methods, for web dashboards
+//! that can't speak gRPC.
+//!
+//! Each endpoint calls a gRPC method of the signer in process, so it has
+//! the same validation, replies and errors.  The gateway checks the bearer
+//! token of the request against the scope of the method, and refuses
+//! changes during a shutdown or on a standby signer, as the gRPC server
+//! does.
+//!
+//! - `GET /v1/nodes` calls ListNodes
+//! - `GET /v1/nodes/<node_id>/channels` calls ListChannels
+//! - `GET /v1/nodes/<node_id>/allowlist` calls ListAllowlist
+//! - `POST /v1/nodes/<node_id>/allowlist` with `{"addresses": [...]}` calls
+//!   AddAllowlist
+//! - `DELETE /v1/nodes/<node_id>/allowlist` with `{"addresses": [...]}`
+//!   calls RemoveAllowlist
+//! - `GET /v1/approvals` calls ListApprovals
+//! - `POST /v1/approvals/<id>` with `{"approve": true}` calls DecideApproval
+//!
+//! Errors are returned with the HTTP status closest to the gRPC code, and
+//! a `{"code": ..., "message": ...}` body.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tonic::{Code, Status};
+
+use super::auth::{authorize_request, method_scope, Scope, TokenStore};
+use super::driver::{internal_error, invalid_grpc_argument};
+use super::remotesigner::signer_server::Signer;
+use super::remotesigner::{
+    AddAllowlistRequest, DecideApprovalRequest, ListAllowlistRequest, ListApprovalsRequest,
+    ListChannelsRequest, ListNodesRequest, NodeId, RemoveAllowlistRequest,
+};
+use super::replication::Replication;
+use super::shutdown::{InFlight, Shutdown};
+use super::tenant::{in_tenant, TenantStore};
+
+#[derive(Deserialize)]
+struct AllowlistBody {
+    addresses: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct DecisionBody {
+    approve: bool,
+}
+
+/// The JSON gateway of a signer
+pub struct Gateway<S> {
+    signer: S,
+    tokens: Option<(Arc<TokenStore>, Arc<TenantStore>)>,
+    shutdown: Arc<Shutdown>,
+    replication: Option<Arc<Replication>>,
+}
+
+impl<S: Signer> Gateway<S> {
+    /// Serve the methods of `signer`, with the checks of the gRPC server
+    pub fn new(
+        signer: S,
+        tokens: Option<(Arc<TokenStore>, Arc<TenantStore>)>,
+        shutdown: Arc<Shutdown>,
+        replication: Option<Arc<Replication>>,
+    ) -> Self {
+        Gateway { signer, tokens, shutdown, replication }
+    }
+
+    /// Listen on `addr` until `signal`
+    pub async fn serve(
+        self,
+        addr: SocketAddr,
+        signal: triggered::Listener,
+    ) -> Result<(), hyper::Error> {
+        let gateway = Arc::new(self);
+        let make_service = make_service_fn(move |_| {
+            let gateway = Arc::clone(&gateway);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let gateway = Arc::clone(&gateway);
+                    async move { Ok::<_, Infallible>(gateway.handle(request).await) }
+                }))
+            }
+        });
+        info!("JSON gateway ready on http://{}", addr);
+        Server::bind(&addr).serve(make_service).with_graceful_shutdown(signal).await
+    }
+
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        match self.route(request).await {
+            Ok(value) => json_response(StatusCode::OK, value),
+            Err(status) => json_response(
+                http_status(status.code()),
+                json!({ "code": format!("{:?}", status.code()), "message": status.message() }),
+            ),
+        }
+    }
+
+    async fn route(&self, request: Request<Body>) -> Result<Value, Status> {
+        let (parts, body) = request.into_parts();
+        let path: Vec<&str> = parts.uri.path().trim_matches('/').split('/').collect();
+        let method = route_method(&parts.method, &path).ok_or_else(|| {
+            Status::not_found(format!("no endpoint {} {}", parts.method, parts.uri.path()))
+        })?;
+        let tenant = match self.tokens.as_ref() {
+            Some((tokens, tenants)) => authorize_request(tokens, tenants, &parts.headers, method)?,
+            None => None,
+        };
+        let _in_flight = self.admit(method).await?;
+        let body = hyper::body::to_bytes(body)
+            .await
+            .map_err(|e| invalid_grpc_argument(format!("could not read the body: {}", e)))?;
+        in_tenant(tenant, self.call(method, &path, &body)).await
+    }
+
+    // Refuse changes once the shutdown started, or unless this signer is
+    // the primary, as the layers of the gRPC server do
+    async fn admit(&self, method: &str) -> Result<Option<InFlight>, Status> {
+        if method_scope(method) == Scope::ReadOnly {
+            return Ok(None);
+        }
+        let in_flight = self.shutdown.admit()?;
+        if let Some(replication) = self.replication.as_ref() {
+            let replication = Arc::clone(replication);
+            // the check reads the fencing token from the persister
+            tokio::task::spawn_blocking(move || replication.check_primary())
+                .await
+                .unwrap_or_else(|e| Err(internal_error(format!("fencing check failed: {}", e))))?;
+        }
+        Ok(Some(in_flight))
+    }
+
+    async fn call(&self, method: &str, path: &[&str], body: &[u8]) -> Result<Value, Status> {
+        let signer = &self.signer;
+        let value = match method {
+            "ListNodes" => {
+                let request = tonic::Request::new(ListNodesRequest {});
+                json!(signer.list_nodes(request).await?.into_inner())
+            }
+            "ListChannels" => {
+                let request = ListChannelsRequest { node_id: node_id(path[2])? };
+                json!(signer.list_channels(tonic::Request::new(request)).await?.into_inner())
+            }
+            "ListAllowlist" => {
+                let request = ListAllowlistRequest { node_id: node_id(path[2])? };
+                json!(signer.list_allowlist(tonic::Request::new(request)).await?.into_inner())
+            }
+            "AddAllowlist" => {
+                let body: AllowlistBody = parse_body(body)?;
+                let request =
+                    AddAllowlistRequest { node_id: node_id(path[2])?, addresses: body.addresses };
+                json!(signer.add_allowlist(tonic::Request::new(request)).await?.into_inner())
+            }
+            "RemoveAllowlist" => {
+                let body: AllowlistBody = parse_body(body)?;
+                let request = RemoveAllowlistRequest {
+                    node_id: node_id(path[2])?,
+                    addresses: body.addresses,
+                };
+                json!(signer.remove_allowlist(tonic::Request::new(request)).await?.into_inner())
+            }
+            "ListApprovals" => {
+                let request = tonic::Request::new(ListApprovalsRequest {});
+                json!(signer.list_approvals(request).await?.into_inner())
+            }
+            "DecideApproval" => {
+                let body: DecisionBody = parse_body(body)?;
+                let request =
+                    DecideApprovalRequest { id: path[2].to_string(), approve: body.approve };
+                json!(signer.decide_approval(tonic::Request::new(request)).await?.into_inner())
+            }
+            _ => return Err(internal_error(format!("no route to {}", method))),
+        };
+        Ok(value)
+    }
+}
+
+// The gRPC method of an endpoint
+fn route_method(method: &Method, path: &[&str]) -> Option<&'static str> {
+    let grpc_method = match (method, path) {
+        (&Method::GET, ["v1", "nodes"]) => "ListNodes",
+        (&Method::GET, ["v1", "nodes", _, "channels"]) => "ListChannels",
+        (&Method::GET, ["v1", "nodes", _, "allowlist"]) => "ListAllowlist",
+        (&Method::POST, ["v1", "nodes", _, "allowlist"]) => "AddAllowlist",
+        (&Method::DELETE, ["v1", "nodes", _, "allowlist"]) => "RemoveAllowlist",
+        (&Method::GET, ["v1", "approvals"]) => "ListApprovals",
+        (&Method::POST, ["v1", "approvals", _]) => "DecideApproval",
+        _ => return None,
+    };
+    Some(grpc_method)
+}
+
+// The HTTP status of a gRPC code
+fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn json_response(status: StatusCode, value: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .expect("response")
+}
+
+fn node_id(hex_id: &str) -> Result<Option<NodeId>, Status> {
+    let data = hex::decode(hex_id).map_err(|_| invalid_grpc_argument("node ID must be hex"))?;
+    Ok(Some(NodeId { data }))
+}
+
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, Status> {
+    serde_json::from_slice(body).map_err(|e| invalid_grpc_argument(format!("invalid body: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn route_method_test() {
+        let route = |method: Method, path: &str| {
+            let path: Vec<&str> = path.trim_matches('/').split('/').collect();
+            route_method(&method, &path)
+        };
+        assert_eq!(route(Method::GET, "/v1/nodes"), Some("ListNodes"));
+        assert_eq!(route(Method::GET, "/v1/nodes/02ab/channels"), Some("ListChannels"));
+        assert_eq!(route(Method::DELETE, "/v1/nodes/02ab/allowlist"), Some("RemoveAllowlist"));
+        assert_eq!(route(Method::POST, "/v1/approvals/ff00"), Some("DecideApproval"));
+        assert_eq!(route(Method::POST, "/v1/nodes"), None);
+        assert_eq!(route(Method::GET, "/v1/nodes/02ab/channels/extra"), None);
+        assert_eq!(http_status(Code::PermissionDenied), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,410 @@
+//! The signer protocol over a serial port, so that the signer can run on a
+//! device connected to the node host by a UART.
+//!
+//! Each message is sent in a frame delimited by `0x7E` flags, with HDLC
+//! byte stuffing, and ends with the CRC-16/CCITT-FALSE of its content, in
+//! big endian.  Frames with a bad CRC are dropped, so a request that is
+//! lost to line noise times out on the host.
+//!
+//! A message starts with a 16 bit sequence number, chosen by the host and
+//! echoed in the reply, and a kind byte:
+//! - a request carries the length of the method name as a byte, the
+//!   method name, and the encoded protobuf request, see [super::frontend]
+//! - a reply carries the encoded protobuf reply
+//! - an error carries the gRPC status code, as a 32 bit integer, and the
+//!   message
+//!
+//! The host negotiates the protocol first, see [super::protocol].  The port
+//! itself, such as its baud rate, is configured by the operating system,
+//! for example with `stty`.
+
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::mem;
+
+use log::warn;
+use tonic::{Code, Status};
+
+use super::protocol;
+use super::remotesigner::NegotiateReply;
+
+/// The frame delimiter
+pub const FLAG: u8 = 0x7E;
+
+/// Escapes a flag or an escape byte in a frame, which is sent XORed with
+/// [ESCAPE_XOR]
+pub const ESCAPE: u8 = 0x7D;
+
+/// See [ESCAPE]
+pub const ESCAPE_XOR: u8 = 0x20;
+
+/// The largest message, before byte stuffing and without the CRC
+pub const MAX_MESSAGE_LEN: usize = 1 << 20;
+
+const KIND_REQUEST: u8 = 0;
+const KIND_REPLY: u8 = 1;
+const KIND_ERROR: u8 = 2;
+
+/// The CRC-16/CCITT-FALSE of some bytes
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Frame a message, with its CRC
+pub fn encode_frame(message: &[u8]) -> Vec<u8> {
+    let crc = crc16(message).to_be_bytes();
+    let mut frame = Vec::with_capacity(message.len() + 8);
+    frame.push(FLAG);
+    for byte in message.iter().chain(crc.iter()) {
+        if *byte == FLAG || *byte == ESCAPE {
+            frame.push(ESCAPE);
+            frame.push(byte ^ ESCAPE_XOR);
+        } else {
+            frame.push(*byte);
+        }
+    }
+    frame.push(FLAG);
+    frame
+}
+
+/// Reads the messages framed by [encode_frame], dropping the frames that
+/// are corrupted
+pub struct FrameReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    escaped: bool,
+    oversized: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Read frames from `inner`, which should be buffered
+    pub fn new(inner: R) -> Self {
+        FrameReader { inner, buf: Vec::new(), escaped: false, oversized: false }
+    }
+
+    /// Read the next message with a good CRC
+    pub fn read_message(&mut self) -> io::Result<Vec<u8>> {
+        let mut byte = [0u8; 1];
+        loop {
+            self.inner.read_exact(&mut byte)?;
+            match byte[0] {
+                FLAG => {
+                    let frame = mem::take(&mut self.buf);
+                    let oversized = mem::replace(&mut self.oversized, false);
+                    self.escaped = false;
+                    if oversized {
+                        warn!("dropping a frame over {} bytes", MAX_MESSAGE_LEN);
+                    } else if frame.len() > 2 {
+                        let (message, crc) = frame.split_at(frame.len() - 2);
+                        if crc16(message).to_be_bytes() == crc {
+                            return Ok(message.to_vec());
+                        }
+                        warn!("dropping a frame with a bad CRC");
+                    }
+                    // otherwise the flag started a frame
+                }
+                ESCAPE => self.escaped = true,
+                mut b => {
+                    if mem::replace(&mut self.escaped, false) {
+                        b ^= ESCAPE_XOR;
+                    }
+                    if self.buf.len() < MAX_MESSAGE_LEN + 2 {
+                        self.buf.push(b);
+                    } else {
+                        self.oversized = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A message of the serial protocol
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// A call of a method, from the host
+    Request {
+        /// Echoed in the reply
+        seq: u16,
+        /// The method name in remotesigner.proto
+        method: String,
+        /// The encoded request
+        body: Vec<u8>,
+    },
+    /// The successful reply to a request
+    Reply {
+        /// The sequence number of the request
+        seq: u16,
+        /// The encoded reply
+        body: Vec<u8>,
+    },
+    /// The failure of a request
+    Error {
+        /// The sequence number of the request
+        seq: u16,
+        /// The gRPC status code
+        code: i32,
+        /// The status message
+        message: String,
+    },
+}
+
+impl Message {
+    /// Serialize the message, to be framed
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Message::Request { seq, method, body } => {
+                assert!(method.len() <= u8::MAX as usize, "method name too long");
+                out.extend_from_slice(&seq.to_be_bytes());
+                out.push(KIND_REQUEST);
+                out.push(method.len() as u8);
+                out.extend_from_slice(method.as_bytes());
+                out.extend_from_slice(body);
+            }
+            Message::Reply { seq, body } => {
+                out.extend_from_slice(&seq.to_be_bytes());
+                out.push(KIND_REPLY);
+                out.extend_from_slice(body);
+            }
+            Message::Error { seq, code, message } => {
+                out.extend_from_slice(&seq.to_be_bytes());
+                out.push(KIND_ERROR);
+                out.extend_from_slice(&code.to_be_bytes());
+                out.extend_from_slice(message.as_bytes());
+            }
+        }
+        out
+    }
+
+    /// Deserialize a message read by a [FrameReader]
+    pub fn decode(data: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidData, msg.to_string());
+        if data.len() < 3 {
+            return Err(invalid("message too short"));
+        }
+        let seq = u16::from_be_bytes([data[0], data[1]]);
+        let rest = &data[3..];
+        match data[2] {
+            KIND_REQUEST => {
+                let (len, rest) = rest.split_first().ok_or_else(|| invalid("missing method"))?;
+                let len = *len as usize;
+                if rest.len() < len {
+                    return Err(invalid("method name truncated"));
+                }
+                let method = String::from_utf8(rest[..len].to_vec())
+                    .map_err(|_| invalid("method name is not UTF-8"))?;
+                Ok(Message::Request { seq, method, body: rest[len..].to_vec() })
+            }
+            KIND_REPLY => Ok(Message::Reply { seq, body: rest.to_vec() }),
+            KIND_ERROR => {
+                if rest.len() < 4 {
+                    return Err(invalid("error code truncated"));
+                }
+                let code = i32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+                let message = String::from_utf8_lossy(&rest[4..]).to_string();
+                Ok(Message::Error { seq, code, message })
+            }
+            kind => Err(invalid(&format!("unknown message kind {}", kind))),
+        }
+    }
+}
+
+fn write_message<W: Write>(writer: &mut W, message: &Message) -> io::Result<()> {
+    writer.write_all(&encode_frame(&message.encode()))?;
+    writer.flush()
+}
+
+/// Serve requests from a serial port until it is closed, on the device
+/// side.  `handle` calls a method with an encoded request, as
+/// [super::frontend::Frontend::handle] does.
+pub fn serve<R, W, F>(reader: R, mut writer: W, handle: F) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&str, &[u8]) -> Result<Vec<u8>, Status>,
+{
+    let mut handle = protocol::require_negotiation(handle);
+    let mut frames = FrameReader::new(BufReader::new(reader));
+    loop {
+        let data = match frames.read_message() {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if let Some(reply) = answer(&data, &mut handle) {
+            write_message(&mut writer, &reply)?;
+        }
+    }
+}
+
+/// Answer a request message with `handle`, or return `None` if the message
+/// isn't a well formed request
+pub(super) fn answer<F>(data: &[u8], handle: &mut F) -> Option<Message>
+where
+    F: FnMut(&str, &[u8]) -> Result<Vec<u8>, Status>,
+{
+    let (seq, method, body) = match Message::decode(data) {
+        Ok(Message::Request { seq, method, body }) => (seq, method, body),
+        Ok(message) => {
+            warn!("ignoring a message that isn't a request: {:?}", message);
+            return None;
+        }
+        Err(e) => {
+            warn!("dropping a malformed message: {}", e);
+            return None;
+        }
+    };
+    let reply = match handle(&method, &body) {
+        Ok(body) => Message::Reply { seq, body },
+        Err(status) => {
+            Message::Error { seq, code: status.code() as i32, message: status.message().into() }
+        }
+    };
+    Some(reply)
+}
+
+/// The result of the request `seq` to `method`, or `None` if the message
+/// isn't its reply
+pub(super) fn reply_to<Rep>(method: &str, seq: u16, data: &[u8]) -> Option<Result<Rep, Status>>
+where
+    Rep: prost::Message + Default,
+{
+    match Message::decode(data) {
+        Ok(Message::Reply { seq: s, body }) if s == seq => {
+            Some(Rep::decode(body.as_slice()).map_err(|e| {
+                Status::internal(format!("could not decode the {} reply: {}", method, e))
+            }))
+        }
+        Ok(Message::Error { seq: s, code, message }) if s == seq => {
+            Some(Err(Status::new(Code::from_i32(code), message)))
+        }
+        Ok(message) => {
+            warn!("skipping a stale message: {:?}", message);
+            None
+        }
+        Err(e) => {
+            warn!("dropping a malformed message: {}", e);
+            None
+        }
+    }
+}
+
+/// Calls the methods of a signer over a serial port, on the host side
+pub struct SerialClient<R, W> {
+    frames: FrameReader<BufReader<R>>,
+    writer: W,
+    next_seq: u16,
+}
+
+impl<R: Read, W: Write> SerialClient<R, W> {
+    /// Call over a serial port.  The port should have a read timeout, so
+    /// that a lost request fails instead of blocking.
+    pub fn new(reader: R, writer: W) -> Self {
+        SerialClient { frames: FrameReader::new(BufReader::new(reader)), writer, next_seq: 0 }
+    }
+
+    /// Negotiate the protocol, before calling the other methods, returning
+    /// the protocol version
+    pub fn negotiate(&mut self) -> Result<u32, Status> {
+        let reply: NegotiateReply = self.call("Negotiate", &protocol::negotiate_request())?;
+        protocol::check_reply(&reply)
+    }
+
+    /// Call a method by its name in remotesigner.proto.  The replies to
+    /// earlier requests, that timed out, are skipped.
+    pub fn call<Req, Rep>(&mut self, method: &str, request: &Req) -> Result<Rep, Status>
+    where
+        Req: prost::Message,
+        Rep: prost::Message + Default,
+    {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let message =
+            Message::Request { seq, method: method.to_string(), body: request.encode_to_vec() };
+        write_message(&mut self.writer, &message).map_err(port_error)?;
+        loop {
+            let data = self.frames.read_message().map_err(port_error)?;
+            if let Some(result) = reply_to(method, seq, &data) {
+                return result;
+            }
+        }
+    }
+}
+
+fn port_error(e: io::Error) -> Status {
+    Status::unavailable(format!("serial port: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    use prost::Message as _;
+    use test_log::test;
+
+    use super::super::remotesigner::{NegotiateRequest, PingReply, PingRequest};
+    use super::*;
+
+    #[test]
+    fn frame_test() {
+        // the check value of CRC-16/CCITT-FALSE
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        let message = vec![1, FLAG, 2, ESCAPE, 3];
+        let frame = encode_frame(&message);
+        assert!(!frame[1..frame.len() - 1].contains(&FLAG));
+
+        // a corrupted frame is dropped, and the next frame is read
+        let mut corrupted = frame.clone();
+        corrupted[1] ^= 0x01;
+        let mut stream = corrupted;
+        stream.extend_from_slice(&frame);
+        let mut frames = FrameReader::new(stream.as_slice());
+        assert_eq!(frames.read_message().unwrap(), message);
+        assert_eq!(frames.read_message().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let error = Message::Error { seq: 7, code: Code::NotFound as i32, message: "no".into() };
+        assert_eq!(Message::decode(&error.encode()).unwrap(), error);
+    }
+
+    #[test]
+    fn serve_test() {
+        let (host, device) = UnixStream::pair().unwrap();
+        let device_writer = device.try_clone().unwrap();
+        let server = thread::spawn(move || {
+            serve(device, device_writer, |method, request| match method {
+                "Ping" => {
+                    let request = PingRequest::decode(request).unwrap();
+                    Ok(PingReply { message: request.message }.encode_to_vec())
+                }
+                "Negotiate" => {
+                    let request = NegotiateRequest::decode(request).unwrap();
+                    Ok(protocol::negotiate(&request)?.encode_to_vec())
+                }
+                _ => Err(Status::unimplemented(method)),
+            })
+        });
+
+        let mut client = SerialClient::new(host.try_clone().unwrap(), host);
+        let request = PingRequest { message: "hello".to_string() };
+        let reply: PingReply = client.call("Ping", &request).unwrap();
+        assert_eq!(reply.message, "hello");
+        // the other methods wait for the negotiation
+        let err = client.call::<_, PingReply>("Nope", &request).unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        assert_eq!(client.negotiate().unwrap(), protocol::PROTOCOL_VERSION);
+        let err = client.call::<_, PingReply>("Nope", &request).unwrap_err();
+        assert_eq!(err.code(), Code::Unimplemented);
+
+        // the device stops when the host closes the port
+        drop(client);
+        server.join().unwrap().unwrap();
+    }
+}
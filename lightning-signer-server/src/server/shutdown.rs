@@ -0,0 +1,196 @@
+//! Graceful shutdown of the server.
+//!
+//! A shutdown starts on SIGINT or SIGTERM, or on the Shutdown RPC.  From
+//! then on, requests that may mutate signer state are refused, tonic
+//! finishes the requests in flight and stops serving, and the signer
+//! state is flushed before the process exits.  Killing the process
+//! instead may interrupt a persister in the middle of a write.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use hyper::Body;
+use log::info;
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::Status;
+use tower::{Layer, Service};
+
+use super::auth::{method_scope, Scope};
+
+// How often to check whether the requests in flight are done
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Coordinates the shutdown of the server
+pub struct Shutdown {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    trigger: triggered::Trigger,
+}
+
+impl Shutdown {
+    /// Create the coordinator, and the signal that stops the server once
+    /// the shutdown starts
+    pub fn new() -> (Arc<Self>, triggered::Listener) {
+        let (trigger, listener) = triggered::trigger();
+        let shutdown =
+            Shutdown { draining: AtomicBool::new(false), in_flight: AtomicUsize::new(0), trigger };
+        (Arc::new(shutdown), listener)
+    }
+
+    /// Start the shutdown.  Returns false if it already started.
+    pub fn begin(&self, reason: &str) -> bool {
+        if self.draining.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        info!("shutting down: {}", reason);
+        self.trigger.trigger();
+        true
+    }
+
+    /// Whether the shutdown started
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Track a new request that may mutate signer state, unless the
+    /// shutdown started
+    pub fn admit(self: &Arc<Self>) -> Result<InFlight, Status> {
+        let in_flight = self.hold();
+        if self.is_draining() {
+            return Err(Status::unavailable("the signer is shutting down"));
+        }
+        Ok(in_flight)
+    }
+
+    /// Track work that was already admitted, such as a mutation that
+    /// outlives its cancelled request
+    pub fn hold(self: &Arc<Self>) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight { shutdown: Arc::clone(self) }
+    }
+
+    /// Wait until no tracked work is in flight
+    pub async fn wait_idle(&self) {
+        let mut logged = false;
+        loop {
+            let count = self.in_flight.load(Ordering::SeqCst);
+            if count == 0 {
+                return;
+            }
+            if !logged {
+                info!("waiting for {} requests in flight", count);
+                logged = true;
+            }
+            tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Tracks a request or mutation in flight until dropped
+pub struct InFlight {
+    shutdown: Arc<Shutdown>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.shutdown.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A tower layer that refuses the requests that may mutate signer state
+/// once the shutdown started.  Read-only requests are still served until
+/// the server stops.
+#[derive(Clone)]
+pub struct ShutdownLayer {
+    shutdown: Arc<Shutdown>,
+}
+
+impl ShutdownLayer {
+    /// Refuse requests once `shutdown` starts
+    pub fn new(shutdown: Arc<Shutdown>) -> Self {
+        ShutdownLayer { shutdown }
+    }
+}
+
+impl<S> Layer<S> for ShutdownLayer {
+    type Service = ShutdownService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ShutdownService { inner, shutdown: Arc::clone(&self.shutdown) }
+    }
+}
+
+/// The service of [ShutdownLayer]
+#[derive(Clone)]
+pub struct ShutdownService<S> {
+    inner: S,
+    shutdown: Arc<Shutdown>,
+}
+
+impl<S> Service<http::Request<Body>> for ShutdownService<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        // the path is /<package>.<service>/<method>
+        let method = request.uri().path().rsplit('/').next().unwrap_or_default();
+        let in_flight = if method_scope(method) == Scope::ReadOnly {
+            None
+        } else {
+            match self.shutdown.admit() {
+                Ok(in_flight) => Some(in_flight),
+                Err(status) => return Box::pin(async move { Ok(status.to_http()) }),
+            }
+        };
+        // use the service that was driven to readiness
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let response = inner.call(request);
+        Box::pin(async move {
+            let response = response.await;
+            drop(in_flight);
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+    use tokio::time::timeout;
+    use tonic::Code;
+
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn shutdown_test() {
+        let (shutdown, listener) = Shutdown::new();
+        let admitted = shutdown.admit().unwrap();
+        assert!(shutdown.begin("test"));
+        assert!(!shutdown.begin("test"));
+        assert!(shutdown.is_draining());
+        listener.await;
+
+        assert_eq!(shutdown.admit().err().unwrap().code(), Code::Unavailable);
+        // admitted work can still hold the shutdown
+        let held = shutdown.hold();
+        drop(admitted);
+        assert!(timeout(IDLE_POLL_INTERVAL * 3, shutdown.wait_idle()).await.is_err());
+        drop(held);
+        shutdown.wait_idle().await;
+    }
+}
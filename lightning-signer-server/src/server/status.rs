@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::bail;
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::Network;
+use hyper::{Body, Client, Method, Request};
+use log::{info, warn};
+use rand::{OsRng, Rng};
+use serde::Serialize;
+use url::Url;
+
+use lightning_signer::policy::simple_validator::policy_warnings;
+use lightning_signer::signer::multi_signer::MultiSigner;
+use lightning_signer::util::crypto_utils::signature_verify_failures;
+use vls_policy::simple::SimplePolicy;
+
+use crate::server::check::PolicyReport;
+use crate::util::write_private_file;
+
+/// The name of the status signing key file in the data directory
+pub const STATUS_KEY_FILE_NAME: &str = "status.key";
+
+/// Status documents are not published more often than this
+pub const MIN_STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A public status document, for external monitoring.
+///
+/// Contains nothing that would help an attacker beyond what is visible
+/// on-chain.
+#[derive(Serialize, Debug)]
+pub struct StatusDocument {
+    pub version: String,
+    pub networks: Vec<String>,
+    pub nodes: Vec<NodeStatus>,
+    /// Hash of the policy profile, to detect configuration drift
+    pub policy_hash: String,
+    /// Signatures that failed verification before release, see the
+    /// verify_signatures policy
+    pub signature_verify_failures: usize,
+    /// Policy violations let through at the warn enforcement level, by tag
+    pub policy_warnings: BTreeMap<String, usize>,
+    pub uptime_secs: u64,
+    /// Seconds since the UNIX epoch
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct NodeStatus {
+    pub node_id: String,
+    pub network: String,
+    pub height: u32,
+    pub tip: String,
+}
+
+/// A status document with a signature by the status key.
+///
+/// The signature is a DER encoded ECDSA signature over the SHA256 of the
+/// compact JSON serialization of `status`.
+#[derive(Serialize, Debug)]
+pub struct SignedStatus {
+    pub status: StatusDocument,
+    pub pubkey: String,
+    pub signature: String,
+}
+
+/// Where to publish the status document
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatusTarget {
+    /// Replace a file
+    File(PathBuf),
+    /// POST to an HTTP endpoint
+    Http(Url),
+}
+
+impl StatusTarget {
+    /// Parse an `http://` URL or a file path
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if s.starts_with("https://") {
+            return Err("https status endpoints are not supported, use a local proxy".to_string());
+        }
+        if s.starts_with("http://") {
+            let url = Url::parse(s).map_err(|e| format!("status URL {}: {}", s, e))?;
+            return Ok(StatusTarget::Http(url));
+        }
+        Ok(StatusTarget::File(PathBuf::from(s)))
+    }
+}
+
+/// Load the status signing key from the data directory, creating it if
+/// it doesn't exist.
+pub fn load_or_create_key(data_path: &Path) -> io::Result<SecretKey> {
+    let path = data_path.join(STATUS_KEY_FILE_NAME);
+    match fs::read_to_string(&path) {
+        Ok(contents) => {
+            let bytes = hex::decode(contents.trim())
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{:?}", e)))?;
+            SecretKey::from_slice(&bytes)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let mut rng = OsRng::new().unwrap();
+            let mut bytes = [0; 32];
+            rng.fill_bytes(&mut bytes);
+            let key = SecretKey::from_slice(&bytes).expect("secret key");
+            fs::create_dir_all(data_path)?;
+            write_private_file(&path, hex::encode(&bytes[..]).as_bytes())?;
+            info!("created status key {}", path.display());
+            Ok(key)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Periodically publishes a signed [StatusDocument]
+pub struct StatusPublisher {
+    signer: Arc<MultiSigner>,
+    network: Network,
+    policy_hash: String,
+    target: StatusTarget,
+    key: SecretKey,
+    interval: Duration,
+    started: Instant,
+    last_published: Option<Instant>,
+}
+
+impl StatusPublisher {
+    /// Create a publisher.  The interval is raised to [MIN_STATUS_INTERVAL]
+    /// if it is shorter.
+    pub fn new(
+        signer: Arc<MultiSigner>,
+        network: Network,
+        policy: &SimplePolicy,
+        target: StatusTarget,
+        key: SecretKey,
+        interval: Duration,
+    ) -> Self {
+        let policy_json = serde_json::to_vec(&PolicyReport::from(policy)).expect("policy json");
+        StatusPublisher {
+            signer,
+            network,
+            policy_hash: Sha256Hash::hash(&policy_json).to_hex(),
+            target,
+            key,
+            interval: interval.max(MIN_STATUS_INTERVAL),
+            started: Instant::now(),
+            last_published: None,
+        }
+    }
+
+    /// The public key that verifies the status signatures
+    pub fn pubkey(&self) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::signing_only(), &self.key)
+    }
+
+    /// Build the current status document
+    pub fn build_status(&self, now: Instant) -> StatusDocument {
+        let mut networks = BTreeSet::new();
+        networks.insert(self.network.to_string());
+        let mut nodes = Vec::new();
+        for node_id in self.signer.get_node_ids() {
+            let node = match self.signer.get_node(&node_id) {
+                Ok(node) => node,
+                Err(_) => continue, // removed concurrently
+            };
+            let tracker = node.get_tracker();
+            networks.insert(node.network().to_string());
+            nodes.push(NodeStatus {
+                node_id: node_id.to_string(),
+                network: node.network().to_string(),
+                height: tracker.height(),
+                tip: tracker.tip().block_hash().to_string(),
+            });
+        }
+        StatusDocument {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            networks: networks.into_iter().collect(),
+            nodes,
+            policy_hash: self.policy_hash.clone(),
+            signature_verify_failures: signature_verify_failures(),
+            policy_warnings: policy_warnings()
+                .into_iter()
+                .map(|(tag, count)| (tag.name().to_string(), count))
+                .collect(),
+            uptime_secs: now.saturating_duration_since(self.started).as_secs(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Sign a status document
+    pub fn sign(&self, status: StatusDocument) -> SignedStatus {
+        let secp_ctx = Secp256k1::signing_only();
+        let json = serde_json::to_vec(&status).expect("status json");
+        let hash = Sha256Hash::hash(&json);
+        let message = Message::from_slice(&hash[..]).expect("message");
+        let signature = secp_ctx.sign(&message, &self.key);
+        SignedStatus {
+            status,
+            pubkey: self.pubkey().to_string(),
+            signature: signature.serialize_der().to_hex(),
+        }
+    }
+
+    /// Whether enough time has passed since the last publication
+    pub fn is_due(&self, now: Instant) -> bool {
+        match self.last_published {
+            None => true,
+            Some(last) => now.saturating_duration_since(last) >= self.interval,
+        }
+    }
+
+    /// Publish the status if due.  Returns whether it was published.
+    pub async fn maybe_publish(&mut self, now: Instant) -> anyhow::Result<bool> {
+        if !self.is_due(now) {
+            return Ok(false);
+        }
+        // Count failed attempts too, so that a failing endpoint is not hammered
+        self.last_published = Some(now);
+        let signed = self.sign(self.build_status(now));
+        let body = serde_json::to_vec_pretty(&signed)?;
+        match &self.target {
+            StatusTarget::File(path) => {
+                // Write to a temporary file and rename, so that readers
+                // never see a partial document
+                let tmp_path = path.with_extension("tmp");
+                fs::write(&tmp_path, &body)?;
+                fs::rename(&tmp_path, path)?;
+            }
+            StatusTarget::Http(url) => {
+                let request = Request::builder()
+                    .method(Method::POST)
+                    .uri(url.as_str())
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))?;
+                let response = Client::new().request(request).await?;
+                if !response.status().is_success() {
+                    bail!("status endpoint {} returned {}", url, response.status());
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Publish the status periodically, forever
+    pub async fn run(mut self) {
+        info!(
+            "publishing status to {:?} every {:?}, key {}",
+            self.target,
+            self.interval,
+            self.pubkey()
+        );
+        loop {
+            if let Err(e) = self.maybe_publish(Instant::now()).await {
+                warn!("status publish to {:?} failed: {}", self.target, e);
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::Signature;
+    use tempfile::TempDir;
+    use test_log::test;
+
+    use lightning_signer::node::NodeConfig;
+    use lightning_signer::signer::my_keys_manager::KeyDerivationStyle;
+    use vls_policy::simple::make_simple_policy;
+
+    use super::*;
+
+    fn make_publisher(target: StatusTarget) -> StatusPublisher {
+        let signer = Arc::new(MultiSigner::new());
+        signer
+            .new_node(NodeConfig {
+                network: Network::Testnet,
+                key_derivation_style: KeyDerivationStyle::Native,
+                chain_params: None,
+                checkpoint: None,
+            })
+            .unwrap();
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let policy = make_simple_policy(Network::Testnet);
+        StatusPublisher::new(signer, Network::Testnet, &policy, target, key, Duration::from_secs(1))
+    }
+
+    #[test]
+    fn status_target_parse_test() {
+        assert_eq!(
+            StatusTarget::parse("/var/www/status.json").unwrap(),
+            StatusTarget::File(PathBuf::from("/var/www/status.json"))
+        );
+        assert!(matches!(
+            StatusTarget::parse("http://localhost:8080/status").unwrap(),
+            StatusTarget::Http(_)
+        ));
+        assert!(StatusTarget::parse("https://example.com/status").is_err());
+    }
+
+    #[test]
+    fn status_sign_test() {
+        let publisher = make_publisher(StatusTarget::File(PathBuf::from("unused")));
+        let status = publisher.build_status(Instant::now());
+        assert_eq!(status.networks, vec!["testnet".to_string()]);
+        assert_eq!(status.nodes.len(), 1);
+        assert_eq!(status.nodes[0].height, 0);
+        assert_eq!(status.signature_verify_failures, 0);
+        assert_eq!(status.policy_warnings["fee-range"], 0);
+
+        let json = serde_json::to_vec(&status).unwrap();
+        let signed = publisher.sign(status);
+        let message = Message::from_slice(&Sha256Hash::hash(&json)[..]).unwrap();
+        let signature = Signature::from_der(&hex::decode(&signed.signature).unwrap()).unwrap();
+        Secp256k1::verification_only()
+            .verify(&message, &signature, &publisher.pubkey())
+            .expect("verify");
+        assert_eq!(signed.pubkey, publisher.pubkey().to_string());
+    }
+
+    #[test(tokio::test)]
+    async fn status_rate_limit_test() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("status.json");
+        let mut publisher = make_publisher(StatusTarget::File(path.clone()));
+        // the interval is raised to the minimum
+        assert_eq!(publisher.interval, MIN_STATUS_INTERVAL);
+
+        let now = Instant::now();
+        assert!(publisher.maybe_publish(now).await.unwrap());
+        assert!(path.exists());
+        let doc: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(doc["status"]["nodes"].as_array().unwrap().len(), 1);
+
+        assert!(!publisher.maybe_publish(now + Duration::from_secs(5)).await.unwrap());
+        assert!(publisher.maybe_publish(now + MIN_STATUS_INTERVAL).await.unwrap());
+    }
+
+    #[test]
+    fn status_key_test() {
+        let dir = TempDir::new().unwrap();
+        let key = load_or_create_key(dir.path()).unwrap();
+        assert_eq!(load_or_create_key(dir.path()).unwrap(), key);
+        fs::write(dir.path().join(STATUS_KEY_FILE_NAME), "not hex").unwrap();
+        assert!(load_or_create_key(dir.path()).is_err());
+    }
+}
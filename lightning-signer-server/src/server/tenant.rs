@@ -0,0 +1,254 @@
+//! Tenants, so that one signer server can host the nodes of several
+//! customers.
+//!
+//! A tenant owns a set of nodes, and tokens minted for a tenant only reach
+//! the nodes it owns.  Tenant tokens can't hold the node-admin scope, so
+//! only the operator creates nodes and assigns them to tenants.  A tenant
+//! may have a quota of channels over its nodes, and a limit on the sign
+//! requests its tokens make per minute.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use bitcoin::secp256k1::PublicKey;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tonic::Status;
+
+use crate::util::write_private_file;
+
+/// The name of the tenant file in the data directory
+pub const TENANT_FILE_NAME: &str = "tenants.json";
+
+// The methods that aren't confined to a node, and expose the state of all
+// nodes
+const GLOBAL_METHODS: [&str; 2] = ["ListApprovals", "GetStats"];
+
+tokio::task_local! {
+    // The tenant of the token of the request being served, if any
+    static TENANT: Option<String>;
+}
+
+/// Serve a request on behalf of a tenant, see [current_tenant]
+pub fn in_tenant<F: Future>(tenant: Option<String>, f: F) -> impl Future<Output = F::Output> {
+    TENANT.scope(tenant, f)
+}
+
+/// The tenant of the request being served, if its token belongs to one
+pub fn current_tenant() -> Option<String> {
+    TENANT.try_with(|t| t.clone()).ok().flatten()
+}
+
+/// A tenant and its quotas.  A zero quota is unlimited.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Tenant {
+    /// Chosen by the operator
+    pub name: String,
+    /// The nodes owned by the tenant
+    pub node_ids: Vec<PublicKey>,
+    /// The maximum number of channels over all the nodes of the tenant
+    pub max_channels: u32,
+    /// The maximum number of sign requests per minute by the tokens of the
+    /// tenant
+    pub max_signs_per_minute: u32,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct TenantState {
+    tenants: BTreeMap<String, Tenant>,
+}
+
+// The sign requests of a tenant in the current minute
+struct Window {
+    minute: u64,
+    count: u32,
+}
+
+/// The tenants, persisted in the data directory
+pub struct TenantStore {
+    path: PathBuf,
+    state: Mutex<TenantState>,
+    windows: Mutex<BTreeMap<String, Window>>,
+}
+
+impl TenantStore {
+    /// Create a store, loading any tenants from the data directory
+    pub fn new(data_path: &Path) -> io::Result<Self> {
+        let path = data_path.join(TENANT_FILE_NAME);
+        let state = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => TenantState::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(TenantStore { path, state: Mutex::new(state), windows: Mutex::new(BTreeMap::new()) })
+    }
+
+    /// Create or replace a tenant.  A node can only be owned by one tenant.
+    pub fn set(&self, tenant: Tenant) -> Result<(), Status> {
+        if tenant.name.is_empty() {
+            return Err(Status::invalid_argument("a tenant needs a name"));
+        }
+        let mut state = self.state.lock().unwrap();
+        for other in state.tenants.values().filter(|t| t.name != tenant.name) {
+            if let Some(node_id) = tenant.node_ids.iter().find(|n| other.node_ids.contains(n)) {
+                return Err(Status::already_exists(format!(
+                    "node {} is owned by tenant {}",
+                    node_id, other.name
+                )));
+            }
+        }
+        let name = tenant.name.clone();
+        info!("set tenant {} with {} nodes", name, tenant.node_ids.len());
+        state.tenants.insert(name, tenant);
+        self.save(&state)
+    }
+
+    /// Remove a tenant.  The tokens of the tenant stop working.
+    pub fn remove(&self, name: &str) -> Result<(), Status> {
+        let mut state = self.state.lock().unwrap();
+        if state.tenants.remove(name).is_none() {
+            return Err(Status::not_found(format!("no tenant {}", name)));
+        }
+        self.windows.lock().unwrap().remove(name);
+        info!("removed tenant {}", name);
+        self.save(&state)
+    }
+
+    /// The tenants, by name
+    pub fn list(&self) -> Vec<Tenant> {
+        self.state.lock().unwrap().tenants.values().cloned().collect()
+    }
+
+    /// A tenant, by name
+    pub fn get(&self, name: &str) -> Option<Tenant> {
+        self.state.lock().unwrap().tenants.get(name).cloned()
+    }
+
+    /// The tenant owning a node, if any
+    pub fn owner(&self, node_id: &PublicKey) -> Option<Tenant> {
+        let state = self.state.lock().unwrap();
+        state.tenants.values().find(|t| t.node_ids.contains(node_id)).cloned()
+    }
+
+    /// Whether a tenant owns a node
+    pub fn owns(&self, name: &str, node_id: &PublicKey) -> bool {
+        self.get(name).map(|t| t.node_ids.contains(node_id)).unwrap_or(false)
+    }
+
+    /// Check that a token of a tenant may call a gRPC method, counting the
+    /// sign requests against the tenant's limit
+    pub fn admit(&self, name: &str, method: &str, now: u64) -> Result<(), Status> {
+        let max_signs_per_minute = self
+            .get(name)
+            .ok_or_else(|| Status::permission_denied(format!("no tenant {}", name)))?
+            .max_signs_per_minute;
+        if GLOBAL_METHODS.contains(&method) {
+            return Err(Status::permission_denied(format!(
+                "{} is not available to tenants",
+                method
+            )));
+        }
+        if !method.starts_with("Sign") || max_signs_per_minute == 0 {
+            return Ok(());
+        }
+        let mut windows = self.windows.lock().unwrap();
+        let minute = now / 60;
+        let window = windows.entry(name.to_string()).or_insert(Window { minute, count: 0 });
+        if window.minute != minute {
+            *window = Window { minute, count: 0 };
+        }
+        if window.count >= max_signs_per_minute {
+            warn!("tenant {} is over {} signs per minute", name, max_signs_per_minute);
+            return Err(Status::resource_exhausted(format!(
+                "tenant {} is limited to {} signs per minute",
+                name, max_signs_per_minute
+            )));
+        }
+        window.count += 1;
+        Ok(())
+    }
+
+    // The tenants are only readable by the owner, and a crash doesn't
+    // lose them
+    fn save(&self, state: &TenantState) -> Result<(), Status> {
+        serde_json::to_vec_pretty(state)
+            .map_err(io::Error::from)
+            .and_then(|contents| write_private_file(&self.path, &contents))
+            .map_err(|e| {
+                error!("tenant store {}: {}", self.path.display(), e);
+                Status::internal("tenant persist failed")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning_signer::util::test_utils::make_dummy_pubkey;
+    use tempfile::TempDir;
+    use test_log::test;
+    use tonic::Code;
+
+    use super::*;
+
+    fn make_tenant(name: &str, node_ids: Vec<PublicKey>) -> Tenant {
+        Tenant { name: name.to_string(), node_ids, max_channels: 0, max_signs_per_minute: 2 }
+    }
+
+    #[test]
+    fn tenant_store_test() {
+        let dir = TempDir::new().unwrap();
+        let store = TenantStore::new(dir.path()).unwrap();
+        let alice_node = make_dummy_pubkey(0x12);
+        let bob_node = make_dummy_pubkey(0x13);
+        store.set(make_tenant("alice", vec![alice_node])).unwrap();
+        store.set(make_tenant("bob", vec![bob_node])).unwrap();
+        let err = store.set(make_tenant("bob", vec![bob_node, alice_node])).unwrap_err();
+        assert_eq!(err.code(), Code::AlreadyExists);
+        assert!(store.owns("alice", &alice_node));
+        assert!(!store.owns("alice", &bob_node));
+        assert_eq!(store.owner(&bob_node).unwrap().name, "bob");
+
+        // the sign requests are limited per tenant and per minute
+        store.admit("alice", "SignInvoice", 60).unwrap();
+        store.admit("alice", "SignInvoice", 61).unwrap();
+        store.admit("alice", "ListChannels", 62).unwrap();
+        let err = store.admit("alice", "SignInvoice", 62).unwrap_err();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+        store.admit("bob", "SignInvoice", 62).unwrap();
+        store.admit("alice", "SignInvoice", 120).unwrap();
+        assert_eq!(
+            store.admit("alice", "GetStats", 120).unwrap_err().code(),
+            Code::PermissionDenied
+        );
+
+        // the tenants are persisted
+        let store = TenantStore::new(dir.path()).unwrap();
+        assert_eq!(store.list().len(), 2);
+        store.remove("alice").unwrap();
+        assert_eq!(store.admit("alice", "Ping", 120).unwrap_err().code(), Code::PermissionDenied);
+        assert!(store.owner(&alice_node).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tenant_store_mode_test() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new().unwrap();
+        let store = TenantStore::new(dir.path()).unwrap();
+        store.set(make_tenant("alice", vec![make_dummy_pubkey(0x12)])).unwrap();
+        let path = dir.path().join(TENANT_FILE_NAME);
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test(tokio::test)]
+    async fn current_tenant_test() {
+        assert_eq!(current_tenant(), None);
+        let tenant = in_tenant(Some("alice".to_string()), async { current_tenant() }).await;
+        assert_eq!(tenant, Some("alice".to_string()));
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Transaction, Txid};
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hyper::{Body, Client, Method, Request};
+use log::{error, info, warn};
+use rand::{OsRng, Rng};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use lightning_signer::channel::ChannelId;
+use lightning_signer::node::{NodeEvent, NodeEventListener};
+use lightning_signer::signer::multi_signer::MultiSigner;
+use lightning_signer::tx::tx::JusticeOutput;
+use lightning_signer::SendSync;
+
+use crate::util::write_private_file;
+
+/// The name of the upload state file in the data directory
+pub const WATCHTOWER_STATE_FILE_NAME: &str = "watchtower.json";
+
+/// Failed uploads are retried this often
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+// How often to check for new revocations
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const NONCE_LEN: usize = 12;
+
+/// Parse a watchtower `http://` URL
+pub fn parse_tower_url(s: &str) -> Result<Url, String> {
+    if !s.starts_with("http://") {
+        return Err(format!("watchtower {}: only http URLs are supported, use a local proxy", s));
+    }
+    Url::parse(s).map_err(|e| format!("watchtower URL {}: {}", s, e))
+}
+
+/// An encrypted justice transaction, as uploaded to a watchtower.
+///
+/// The tower learns nothing about the channel until the revoked
+/// commitment transaction confirms.  It then matches the `hint`, the
+/// first 16 bytes of the commitment txid, and decrypts the `blob` with
+/// the SHA256 of the whole txid.  The blob is a random 12 byte nonce
+/// followed by the ChaCha20-Poly1305 encryption of the serialized justice
+/// transaction.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JusticeBlob {
+    /// Hex encoded hint
+    pub hint: String,
+    /// Hex encoded encrypted justice transaction
+    pub blob: String,
+}
+
+impl JusticeBlob {
+    /// Encrypt a justice transaction for the breach transaction
+    pub fn encrypt(breach_txid: &Txid, justice_tx: &Transaction) -> Self {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng::new().unwrap().fill_bytes(&mut nonce);
+        let ciphertext = Self::cipher(breach_txid)
+            .encrypt(Nonce::from_slice(&nonce), serialize(justice_tx).as_slice())
+            .expect("encrypt");
+        let mut blob = nonce.to_vec();
+        blob.extend(ciphertext);
+        JusticeBlob { hint: hex::encode(&breach_txid[..16]), blob: hex::encode(blob) }
+    }
+
+    /// Decrypt the justice transaction, as the tower would once it sees
+    /// the breach transaction
+    pub fn decrypt(&self, breach_txid: &Txid) -> Option<Transaction> {
+        if self.hint != hex::encode(&breach_txid[..16]) {
+            return None;
+        }
+        let blob = hex::decode(&self.blob).ok()?;
+        if blob.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = blob.split_at(NONCE_LEN);
+        let plaintext =
+            Self::cipher(breach_txid).decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+        deserialize(&plaintext).ok()
+    }
+
+    fn cipher(breach_txid: &Txid) -> ChaCha20Poly1305 {
+        let key = Sha256Hash::hash(&breach_txid[..]);
+        ChaCha20Poly1305::new(Key::from_slice(&key[..]))
+    }
+}
+
+/// A blob that some towers have not acknowledged yet
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct PendingUpload {
+    blob: JusticeBlob,
+    // The towers that acknowledged the blob
+    acked: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct WatchtowerState {
+    uploads: Vec<PendingUpload>,
+}
+
+struct Revocation {
+    node_id: PublicKey,
+    channel_id: ChannelId,
+    txid: Txid,
+    commit_num: u64,
+    outputs: Vec<JusticeOutput>,
+}
+
+// Queues the revocations of one node.  Called with the channel locked, so
+// the justice transaction is built later.
+struct RevocationListener {
+    node_id: PublicKey,
+    queue: Arc<Mutex<Vec<Revocation>>>,
+}
+
+impl SendSync for RevocationListener {}
+
+impl NodeEventListener for RevocationListener {
+    fn on_event(&self, event: &NodeEvent) {
+        if let NodeEvent::CounterpartyRevocation { channel_id, txid, commit_num, outputs } = event {
+            self.queue.lock().unwrap().push(Revocation {
+                node_id: self.node_id,
+                channel_id: *channel_id,
+                txid: *txid,
+                commit_num: *commit_num,
+                outputs: outputs.clone(),
+            });
+        }
+    }
+}
+
+/// Uploads an encrypted justice transaction to each configured watchtower
+/// whenever a counterparty revokes a commitment.
+///
+/// Blobs are persisted in the data directory until every tower has
+/// acknowledged them, so that uploads are retried across restarts.
+pub struct WatchtowerClient {
+    signer: Arc<MultiSigner>,
+    towers: Vec<Url>,
+    feerate_per_kw: u32,
+    sweep_path: Vec<u32>,
+    path: PathBuf,
+    state: WatchtowerState,
+    revocations: Arc<Mutex<Vec<Revocation>>>,
+    registered: BTreeSet<[u8; 33]>,
+    next_attempt: Option<Instant>,
+}
+
+impl WatchtowerClient {
+    /// Create a client, loading any pending uploads from the data
+    /// directory.  Justice transactions pay `feerate_per_kw` and sweep to
+    /// the layer-1 wallet at `sweep_path`.
+    pub fn new(
+        signer: Arc<MultiSigner>,
+        towers: Vec<Url>,
+        feerate_per_kw: u32,
+        sweep_path: Vec<u32>,
+        data_path: &Path,
+    ) -> io::Result<Self> {
+        let path = data_path.join(WATCHTOWER_STATE_FILE_NAME);
+        let state = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => WatchtowerState::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(WatchtowerClient {
+            signer,
+            towers,
+            feerate_per_kw,
+            sweep_path,
+            path,
+            state,
+            revocations: Arc::new(Mutex::new(Vec::new())),
+            registered: BTreeSet::new(),
+            next_attempt: None,
+        })
+    }
+
+    /// The number of blobs not yet acknowledged by every tower
+    pub fn pending_count(&self) -> usize {
+        self.state.uploads.len()
+    }
+
+    /// Listen for revocations on nodes that were added since the last call
+    pub fn register_nodes(&mut self) {
+        for node_id in self.signer.get_node_ids() {
+            if !self.registered.insert(node_id.serialize()) {
+                continue;
+            }
+            if let Ok(node) = self.signer.get_node(&node_id) {
+                let queue = Arc::clone(&self.revocations);
+                node.add_event_listener(Arc::new(RevocationListener { node_id, queue }));
+            }
+        }
+    }
+
+    /// Build and encrypt the justice transactions of queued revocations,
+    /// and persist them for upload.  Returns the number of new blobs.
+    pub fn process_revocations(&mut self) -> io::Result<usize> {
+        let revocations: Vec<Revocation> = self.revocations.lock().unwrap().drain(..).collect();
+        let mut count = 0;
+        for rev in revocations {
+            let res =
+                self.signer.with_ready_or_closing_channel(&rev.node_id, &rev.channel_id, |chan| {
+                    chan.build_and_sign_justice_tx(
+                        &rev.txid,
+                        rev.commit_num,
+                        &rev.outputs,
+                        self.feerate_per_kw,
+                        &self.sweep_path,
+                    )
+                });
+            match res {
+                Ok(justice_tx) => {
+                    let blob = JusticeBlob::encrypt(&rev.txid, &justice_tx);
+                    self.state.uploads.push(PendingUpload { blob, acked: Vec::new() });
+                    count += 1;
+                }
+                Err(e) => warn!(
+                    "watchtower: no justice tx for {}/{} commit_num {}: {}",
+                    rev.node_id, rev.channel_id, rev.commit_num, e
+                ),
+            }
+        }
+        if count > 0 {
+            self.save()?;
+        }
+        Ok(count)
+    }
+
+    /// Upload the pending blobs to the towers that haven't acknowledged
+    /// them.  Returns the number of blobs that are still pending.
+    pub async fn upload(&mut self) -> io::Result<usize> {
+        let mut changed = false;
+        for upload in self.state.uploads.iter_mut() {
+            for tower in self.towers.iter() {
+                if upload.acked.iter().any(|t| t == tower.as_str()) {
+                    continue;
+                }
+                match Self::post(tower, &upload.blob).await {
+                    Ok(()) => {
+                        upload.acked.push(tower.to_string());
+                        changed = true;
+                    }
+                    Err(e) => warn!("watchtower {}: upload failed: {}", tower, e),
+                }
+            }
+        }
+        let count = self.state.uploads.len();
+        let towers = &self.towers;
+        self.state
+            .uploads
+            .retain(|upload| towers.iter().any(|t| !upload.acked.iter().any(|a| a == t.as_str())));
+        if changed || self.state.uploads.len() != count {
+            self.save()?;
+        }
+        Ok(self.state.uploads.len())
+    }
+
+    async fn post(tower: &Url, blob: &JusticeBlob) -> anyhow::Result<()> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(tower.as_str())
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(blob)?))?;
+        let response = Client::new().request(request).await?;
+        if !response.status().is_success() {
+            bail!("returned {}", response.status());
+        }
+        Ok(())
+    }
+
+    // The pending uploads are only readable by the owner, and a crash
+    // doesn't lose them
+    fn save(&self) -> io::Result<()> {
+        write_private_file(&self.path, &serde_json::to_vec_pretty(&self.state)?)
+    }
+
+    /// Process revocations and upload blobs, forever
+    pub async fn run(mut self) {
+        info!(
+            "uploading justice transactions to {} watchtowers, {} pending",
+            self.towers.len(),
+            self.pending_count()
+        );
+        loop {
+            self.register_nodes();
+            let new_count = self.process_revocations().unwrap_or_else(|e| {
+                error!("watchtower: failed to save state: {}", e);
+                0
+            });
+            let now = Instant::now();
+            let due = self.next_attempt.map(|t| now >= t).unwrap_or(true);
+            if new_count > 0 || due {
+                self.next_attempt = match self.upload().await {
+                    Ok(0) => None,
+                    Ok(_) => Some(now + RETRY_INTERVAL),
+                    Err(e) => {
+                        error!("watchtower: failed to save state: {}", e);
+                        Some(now + RETRY_INTERVAL)
+                    }
+                };
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{OutPoint, TxIn, TxOut};
+    use tempfile::TempDir;
+    use test_log::test;
+
+    use super::*;
+
+    fn make_justice_tx() -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Default::default(),
+                sequence: 0xffff_ffff,
+                witness: vec![vec![1, 2, 3]],
+            }],
+            output: vec![TxOut { value: 1000, script_pubkey: Default::default() }],
+        }
+    }
+
+    #[test]
+    fn watchtower_blob_test() {
+        let breach_txid = make_justice_tx().txid();
+        let justice_tx = make_justice_tx();
+        let blob = JusticeBlob::encrypt(&breach_txid, &justice_tx);
+        assert_eq!(blob.hint.len(), 32);
+        assert_eq!(blob.decrypt(&breach_txid), Some(justice_tx.clone()));
+        // the nonce is random
+        assert_ne!(JusticeBlob::encrypt(&breach_txid, &justice_tx), blob);
+
+        assert_eq!(blob.decrypt(&Txid::default()), None);
+        let mut bad_blob = blob.clone();
+        bad_blob.blob.replace_range(40..42, if &blob.blob[40..42] == "00" { "01" } else { "00" });
+        assert_eq!(bad_blob.decrypt(&breach_txid), None);
+    }
+
+    #[test]
+    fn watchtower_url_test() {
+        assert!(parse_tower_url("http://localhost:9911/blobs").is_ok());
+        assert!(parse_tower_url("https://example.com/blobs").is_err());
+        assert!(parse_tower_url("localhost:9911").is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn watchtower_retry_test() {
+        let dir = TempDir::new().unwrap();
+        let signer = Arc::new(MultiSigner::new());
+        // nothing listens on the discard port
+        let towers = vec![parse_tower_url("http://127.0.0.1:9/blobs").unwrap()];
+        let mut client =
+            WatchtowerClient::new(signer.clone(), towers.clone(), 1000, vec![0], dir.path())
+                .unwrap();
+        let blob = JusticeBlob::encrypt(&make_justice_tx().txid(), &make_justice_tx());
+        client.state.uploads.push(PendingUpload { blob: blob.clone(), acked: Vec::new() });
+        client.save().unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&client.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert_eq!(client.upload().await.unwrap(), 1);
+
+        // the pending upload survives a restart
+        let mut client =
+            WatchtowerClient::new(signer.clone(), towers.clone(), 1000, vec![0], dir.path())
+                .unwrap();
+        assert_eq!(client.pending_count(), 1);
+        assert_eq!(client.state.uploads[0].blob, blob);
+
+        // acknowledged by all towers
+        client.state.uploads[0].acked.push(towers[0].to_string());
+        assert_eq!(client.upload().await.unwrap(), 0);
+        let client = WatchtowerClient::new(signer, towers, 1000, vec![0], dir.path()).unwrap();
+        assert_eq!(client.pending_count(), 0);
+    }
+}
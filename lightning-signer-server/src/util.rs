@@ -1,8 +1,39 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::Path;
+
 #[cfg(feature = "grpc")]
 use serde::ser::SerializeSeq;
 #[cfg(feature = "grpc")]
 use serde::Serializer;
 
+/// Write a file holding a secret, such as a key or a token, readable and
+/// writable only by the owner on Unix.
+///
+/// The contents are written to a temporary file that is renamed into
+/// place, so that a crash doesn't leave a partial file.
+pub fn write_private_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    // A leftover temporary file may have wider permissions, which are
+    // kept when an existing file is opened
+    match fs::remove_file(&tmp_path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
 #[cfg(feature = "grpc")]
 pub fn as_hex<S>(buf: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -22,3 +53,31 @@ where
     }
     seq.end()
 }
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use test_log::test;
+
+    use super::*;
+
+    #[test]
+    fn write_private_file_test() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("secret.key");
+        fs::write(&path, "old").unwrap();
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, "leftover").unwrap();
+
+        write_private_file(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!Path::new(&tmp_path).exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+#[cfg(feature = "backtrace")]
+use backtrace::Backtrace;
+use bitcoin::hashes::hex::ToHex;
+use lightning::ln::PaymentHash;
+
+use ValidationErrorKind::*;
+
+use crate::prelude::*;
+
+/// Kind of validation error
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationErrorKind {
+    /// The transaction could not be parsed or had non-standard elements
+    TransactionFormat(String),
+    /// A scriptPubkey could not be parsed or was non-standard for Lightning
+    ScriptFormat(String),
+    /// A script element didn't match the channel setup
+    Mismatch(String),
+    /// A policy was violated
+    Policy(String),
+    /// A payment is not balanced
+    Unbalanced(String, Vec<PaymentHash>),
+}
+
+/// The kind of policy control that was violated, so that clients can tell
+/// violations apart without parsing the message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolicyTag {
+    /// A violation that is not classified
+    Unclassified,
+    /// A fee, feerate or non-beneficial value is out of range
+    FeeRange,
+    /// A commitment was signed or revoked out of order
+    RevocationOrder,
+    /// A delay or expiry is out of range
+    DelayRange,
+    /// An output is not to our wallet or allowlisted
+    Destination,
+    /// Too many or too valuable HTLCs are in flight
+    HtlcLimit,
+    /// The chain state doesn't allow the operation
+    ChainState,
+    /// The operation requires an approval token
+    ApprovalRequired,
+    /// The channel is due for operator review
+    ChannelReview,
+    /// A failing payment was retried too often
+    PaymentRetry,
+    /// An invoice that was already paid is paid again
+    DuplicatePayment,
+    /// An invoice we sign is over the amount or expiry limits
+    InvoiceLimit,
+    /// Too much value left the wallet within the velocity window
+    Velocity,
+}
+
+impl PolicyTag {
+    /// All the tags
+    pub const ALL: [PolicyTag; 13] = [
+        PolicyTag::Unclassified,
+        PolicyTag::FeeRange,
+        PolicyTag::RevocationOrder,
+        PolicyTag::DelayRange,
+        PolicyTag::Destination,
+        PolicyTag::HtlcLimit,
+        PolicyTag::ChainState,
+        PolicyTag::ApprovalRequired,
+        PolicyTag::ChannelReview,
+        PolicyTag::PaymentRetry,
+        PolicyTag::DuplicatePayment,
+        PolicyTag::InvoiceLimit,
+        PolicyTag::Velocity,
+    ];
+
+    /// The stable name of the tag, as reported to clients
+    pub fn name(&self) -> &'static str {
+        match self {
+            PolicyTag::Unclassified => "unclassified",
+            PolicyTag::FeeRange => "fee-range",
+            PolicyTag::RevocationOrder => "revocation-order",
+            PolicyTag::DelayRange => "delay-range",
+            PolicyTag::Destination => "destination",
+            PolicyTag::HtlcLimit => "htlc-limit",
+            PolicyTag::ChainState => "chain-state",
+            PolicyTag::ApprovalRequired => "approval-required",
+            PolicyTag::ChannelReview => "channel-review",
+            PolicyTag::PaymentRetry => "payment-retry",
+            PolicyTag::DuplicatePayment => "duplicate-payment",
+            PolicyTag::InvoiceLimit => "invoice-limit",
+            PolicyTag::Velocity => "velocity",
+        }
+    }
+
+    /// The tag with the given name
+    pub fn from_name(name: &str) -> Option<PolicyTag> {
+        Self::ALL.iter().find(|t| t.name() == name).copied()
+    }
+}
+
+/// The structured details of a policy violation
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyError {
+    /// The kind of violation
+    pub tag: PolicyTag,
+    /// The offending values, by name
+    pub values: Vec<(String, String)>,
+    /// The channel, if the violation is specific to one
+    pub channel_id: Option<String>,
+}
+
+// Explicit PartialEq which ignores backtrace.
+impl PartialEq for ValidationError {
+    fn eq(&self, other: &ValidationError) -> bool {
+        self.kind == other.kind
+    }
+}
+
+/// Validation error
+#[derive(Clone)]
+pub struct ValidationError {
+    /// The kind of error
+    pub kind: ValidationErrorKind,
+    /// The details of a policy violation, if it was classified
+    pub policy: Option<PolicyError>,
+    /// A non-resolved backtrace
+    #[cfg(feature = "backtrace")]
+    pub bt: Backtrace,
+}
+
+impl ValidationError {
+    /// Resolve the backtrace for display to the user
+    #[cfg(feature = "backtrace")]
+    pub fn resolved_backtrace(&self) -> Backtrace {
+        let mut mve = self.clone();
+        mve.bt.resolve();
+        mve.bt
+    }
+
+    /// Return a new ValidationError with the message prepended
+    pub fn prepend_msg(&self, premsg: String) -> ValidationError {
+        let modkind = match &self.kind {
+            TransactionFormat(s0) => TransactionFormat(premsg + &s0),
+            ScriptFormat(s0) => ScriptFormat(premsg + &s0),
+            Mismatch(s0) => Mismatch(premsg + &s0),
+            Policy(s0) => Policy(premsg + &s0),
+            Unbalanced(s0, hashes) => Unbalanced(premsg + &s0, hashes.clone()),
+        };
+        ValidationError {
+            kind: modkind,
+            policy: self.policy.clone(),
+            #[cfg(feature = "backtrace")]
+            bt: self.bt.clone(),
+        }
+    }
+
+    /// Return this error with the structured details of a policy violation
+    pub fn with_policy(
+        self,
+        tag: PolicyTag,
+        values: Vec<(String, String)>,
+        channel_id: Option<String>,
+    ) -> ValidationError {
+        ValidationError { policy: Some(PolicyError { tag, values, channel_id }), ..self }
+    }
+}
+
+impl core::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{:?}", self.kind)
+    }
+}
+
+impl core::fmt::Debug for ValidationError {
+    #[cfg(not(feature = "backtrace"))]
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("ValidationError").field("kind", &self.kind).finish()
+    }
+    #[cfg(feature = "backtrace")]
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("ValidationError")
+            .field("kind", &self.kind)
+            .field("bt", &self.resolved_backtrace())
+            .finish()
+    }
+}
+
+impl Into<String> for ValidationError {
+    fn into(self) -> String {
+        match self.kind {
+            TransactionFormat(s) => "transaction format: ".to_string() + &s,
+            ScriptFormat(s) => "script format: ".to_string() + &s,
+            Mismatch(s) => "script template mismatch: ".to_string() + &s,
+            Policy(s) => "policy failure: ".to_string() + &s,
+            Unbalanced(s, hashes) => {
+                let hashes: Vec<_> = hashes.iter().map(|h| h.0.to_hex()).collect();
+                format!("unbalanced payments: {} {}", s, hashes.join(", "))
+            }
+        }
+    }
+}
+
+/// A transaction format error
+pub fn transaction_format_error(msg: impl Into<String>) -> ValidationError {
+    ValidationError {
+        kind: TransactionFormat(msg.into()),
+        policy: None,
+        #[cfg(feature = "backtrace")]
+        bt: Backtrace::new_unresolved(),
+    }
+}
+
+/// A script format error
+pub fn script_format_error(msg: impl Into<String>) -> ValidationError {
+    ValidationError {
+        kind: ScriptFormat(msg.into()),
+        policy: None,
+        #[cfg(feature = "backtrace")]
+        bt: Backtrace::new_unresolved(),
+    }
+}
+
+/// A script template mismatch error
+pub fn mismatch_error(msg: impl Into<String>) -> ValidationError {
+    ValidationError {
+        kind: Mismatch(msg.into()),
+        policy: None,
+        #[cfg(feature = "backtrace")]
+        bt: Backtrace::new_unresolved(),
+    }
+}
+
+/// A policy violation error
+pub fn policy_error(msg: impl Into<String>) -> ValidationError {
+    ValidationError {
+        kind: Policy(msg.into()),
+        policy: None,
+        #[cfg(feature = "backtrace")]
+        bt: Backtrace::new_unresolved(),
+    }
+}
+
+/// An unbalanced payments error
+pub fn unbalanced_error(hashes: Vec<PaymentHash>) -> ValidationError {
+    ValidationError {
+        kind: Unbalanced("".to_string(), hashes),
+        policy: None,
+        #[cfg(feature = "backtrace")]
+        bt: Backtrace::new_unresolved(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation_error_test() {
+        assert_eq!(
+            format!("{}", transaction_format_error("testing".to_string())),
+            "TransactionFormat(\"testing\")"
+        );
+        assert_eq!(
+            Into::<String>::into(transaction_format_error("testing".to_string())),
+            "transaction format: testing"
+        );
+        assert_eq!(
+            format!("{}", script_format_error("testing".to_string())),
+            "ScriptFormat(\"testing\")"
+        );
+        assert_eq!(
+            Into::<String>::into(script_format_error("testing".to_string())),
+            "script format: testing"
+        );
+        assert_eq!(format!("{}", mismatch_error("testing".to_string())), "Mismatch(\"testing\")");
+        assert_eq!(
+            Into::<String>::into(mismatch_error("testing".to_string())),
+            "script template mismatch: testing"
+        );
+        assert_eq!(format!("{}", policy_error("testing".to_string())), "Policy(\"testing\")");
+        assert_eq!(
+            Into::<String>::into(policy_error("testing".to_string())),
+            "policy failure: testing"
+        );
+    }
+
+    #[test]
+    fn policy_tag_test() {
+        let ve = policy_error("fee above maximum: 2 > 1").with_policy(
+            PolicyTag::FeeRange,
+            vec![("fee".to_string(), "2".to_string())],
+            None,
+        );
+        // the details don't affect equality
+        assert_eq!(ve, policy_error("fee above maximum: 2 > 1"));
+        let ve = ve.prepend_msg("validate_fee: ".to_string());
+        assert_eq!(ve.policy.as_ref().unwrap().tag, PolicyTag::FeeRange);
+        assert_eq!(ve.policy.unwrap().values, vec![("fee".to_string(), "2".to_string())]);
+
+        for tag in PolicyTag::ALL.iter() {
+            assert_eq!(PolicyTag::from_name(tag.name()), Some(*tag));
+        }
+        assert_eq!(PolicyTag::from_name("bogus"), None);
+    }
+}
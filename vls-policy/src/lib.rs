@@ -0,0 +1,38 @@
+#![crate_name = "vls_policy"]
+
+//! Policy configuration, validation errors and the validator traits of
+//! the validating Lightning signer.
+//!
+//! This crate doesn't depend on the signer core, so that the policy can be
+//! versioned independently.  A policy engine implements
+//! [validator::Validator] and [validator::ValidatorFactory], which are
+//! generic over the types of the signer through [validator::SignerTypes].
+//! The validators that come with the signer live in
+//! `lightning_signer::policy`, which also re-exports the traits.
+
+#![forbid(unsafe_code)]
+#![warn(broken_intra_doc_links)]
+#![warn(missing_docs)]
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "no-std")))]
+compile_error!("at least one of the `std` or `no-std` features must be enabled");
+
+#[macro_use]
+extern crate alloc;
+
+/// Validation errors
+pub mod error;
+/// Custom policy rules written by the operator
+pub mod rules;
+/// The simple policy
+pub mod simple;
+/// The validator traits
+pub mod validator;
+
+/// The version of this crate
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+mod prelude {
+    pub use alloc::{boxed::Box, string::String, string::ToString, vec::Vec};
+}
@@ -0,0 +1,509 @@
+use core::{cmp, fmt};
+
+use crate::prelude::*;
+
+/// A value about the operation being validated, that custom rules can
+/// refer to by name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleVar {
+    /// Whether the operation signs an on-chain transaction, such as a
+    /// funding transaction
+    OnchainTx,
+    /// Whether the operation signs a mutual close
+    MutualClose,
+    /// The value in satoshi of the channels funded or closed
+    ChannelValueSat,
+    /// The value in satoshi sent to destinations outside our wallet
+    ValueSat,
+    /// The fee in satoshi
+    FeeSat,
+    /// Whether a destination is neither our wallet, allowlisted nor a
+    /// channel we fund
+    DestNotAllowlisted,
+}
+
+impl RuleVar {
+    /// All the variables
+    pub const ALL: [RuleVar; 6] = [
+        RuleVar::OnchainTx,
+        RuleVar::MutualClose,
+        RuleVar::ChannelValueSat,
+        RuleVar::ValueSat,
+        RuleVar::FeeSat,
+        RuleVar::DestNotAllowlisted,
+    ];
+
+    /// The name of the variable in rule expressions
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuleVar::OnchainTx => "op.onchain_tx",
+            RuleVar::MutualClose => "op.mutual_close",
+            RuleVar::ChannelValueSat => "channel_value_sat",
+            RuleVar::ValueSat => "value_sat",
+            RuleVar::FeeSat => "fee_sat",
+            RuleVar::DestNotAllowlisted => "dest.not_allowlisted",
+        }
+    }
+
+    /// The variable with the given name
+    pub fn from_name(name: &str) -> Option<RuleVar> {
+        Self::ALL.iter().find(|v| v.name() == name).copied()
+    }
+
+    // Flags are true or false, the other variables are numbers
+    fn is_flag(&self) -> bool {
+        match self {
+            RuleVar::OnchainTx | RuleVar::MutualClose | RuleVar::DestNotAllowlisted => true,
+            RuleVar::ChannelValueSat | RuleVar::ValueSat | RuleVar::FeeSat => false,
+        }
+    }
+}
+
+/// The values of the rule variables for one operation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RuleFacts {
+    /// See [RuleVar::OnchainTx]
+    pub onchain_tx: bool,
+    /// See [RuleVar::MutualClose]
+    pub mutual_close: bool,
+    /// See [RuleVar::ChannelValueSat]
+    pub channel_value_sat: u64,
+    /// See [RuleVar::ValueSat]
+    pub value_sat: u64,
+    /// See [RuleVar::FeeSat]
+    pub fee_sat: u64,
+    /// See [RuleVar::DestNotAllowlisted]
+    pub dest_not_allowlisted: bool,
+}
+
+impl RuleFacts {
+    // Flags are 0 or 1
+    fn value(&self, var: RuleVar) -> u64 {
+        match var {
+            RuleVar::OnchainTx => self.onchain_tx as u64,
+            RuleVar::MutualClose => self.mutual_close as u64,
+            RuleVar::ChannelValueSat => self.channel_value_sat,
+            RuleVar::ValueSat => self.value_sat,
+            RuleVar::FeeSat => self.fee_sat,
+            RuleVar::DestNotAllowlisted => self.dest_not_allowlisted as u64,
+        }
+    }
+}
+
+/// What happens when the condition of a rule holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleAction {
+    /// The operation fails with a policy error
+    Deny,
+    /// The operation fails with a policy error, unless the node has the
+    /// approval token of the transaction, as for
+    /// [crate::simple::SimplePolicy::max_unapproved_value_sat]
+    RequireApproval,
+    /// The operation proceeds, and the match is logged
+    Warn,
+}
+
+impl RuleAction {
+    /// The name of the action in rules
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuleAction::Deny => "deny",
+            RuleAction::RequireApproval => "require_approval",
+            RuleAction::Warn => "warn",
+        }
+    }
+
+    /// The action with the given name
+    pub fn from_name(name: &str) -> Option<RuleAction> {
+        [RuleAction::Deny, RuleAction::RequireApproval, RuleAction::Warn]
+            .iter()
+            .find(|a| a.name() == name)
+            .copied()
+    }
+}
+
+/// A rule that could not be compiled
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuleError {
+    /// The byte offset in the rule where the problem was found
+    pub position: usize,
+    /// What is wrong
+    pub message: String,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CmpOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    Number(u64),
+    Var(RuleVar),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Const(bool),
+    Flag(RuleVar),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Operand, CmpOp, Operand),
+}
+
+impl Expr {
+    fn eval(&self, facts: &RuleFacts) -> bool {
+        match self {
+            Expr::Const(b) => *b,
+            Expr::Flag(var) => facts.value(*var) != 0,
+            Expr::Not(e) => !e.eval(facts),
+            Expr::And(a, b) => a.eval(facts) && b.eval(facts),
+            Expr::Or(a, b) => a.eval(facts) || b.eval(facts),
+            Expr::Compare(a, op, b) => {
+                let value = |operand: &Operand| match operand {
+                    Operand::Number(n) => *n,
+                    Operand::Var(var) => facts.value(*var),
+                };
+                let (a, b) = (value(a), value(b));
+                match op {
+                    CmpOp::Lt => a < b,
+                    CmpOp::Le => a <= b,
+                    CmpOp::Gt => a > b,
+                    CmpOp::Ge => a >= b,
+                    CmpOp::Eq => a == b,
+                    CmpOp::Ne => a != b,
+                }
+            }
+        }
+    }
+}
+
+/// A custom policy rule, `CONDITION => ACTION`, for example
+/// `channel_value_sat > 5_000_000 && dest.not_allowlisted => require_approval`.
+///
+/// The condition combines the [RuleVar] variables with `&&`, `||`, `!`,
+/// parentheses and the comparisons `<`, `<=`, `>`, `>=`, `==` and `!=`.
+/// Numbers may contain `_` separators.  Rules are compiled when the policy
+/// is loaded, so that a mistake is reported at startup.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    source: String,
+    condition: Expr,
+    /// What happens when the condition holds
+    pub action: RuleAction,
+}
+
+impl Rule {
+    /// Compile a rule
+    pub fn parse(source: &str) -> Result<Rule, RuleError> {
+        let mut parser = Parser { tokens: tokenize(source)?, pos: 0, end: source.len() };
+        let condition = parser.expr()?;
+        parser.expect(&Token::Arrow)?;
+        let action = match parser.peek() {
+            Some(Token::Ident(name)) => RuleAction::from_name(name),
+            _ => None,
+        }
+        .ok_or_else(|| parser.error("expected deny, require_approval or warn"))?;
+        parser.pos += 1;
+        if parser.peek().is_some() {
+            return Err(parser.error("unexpected text after the action"));
+        }
+        Ok(Rule { source: source.trim().to_string(), condition, action })
+    }
+
+    /// Whether the condition holds for the operation
+    pub fn matches(&self, facts: &RuleFacts) -> bool {
+        self.condition.eval(facts)
+    }
+
+    /// The rule as written
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(u64),
+    Cmp(CmpOp),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    Arrow,
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, RuleError> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        let start = pos;
+        if c.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == b'_' {
+            while pos < bytes.len()
+                && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_' || bytes[pos] == b'.')
+            {
+                pos += 1;
+            }
+            tokens.push((start, Token::Ident(source[start..pos].to_string())));
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let mut value: u64 = 0;
+            while pos < bytes.len() && (bytes[pos].is_ascii_digit() || bytes[pos] == b'_') {
+                if bytes[pos] != b'_' {
+                    value = value
+                        .checked_mul(10)
+                        .and_then(|v| v.checked_add((bytes[pos] - b'0') as u64))
+                        .ok_or_else(|| RuleError {
+                            position: start,
+                            message: "number too large".to_string(),
+                        })?;
+                }
+                pos += 1;
+            }
+            tokens.push((start, Token::Number(value)));
+            continue;
+        }
+        let (token, len) = match &bytes[pos..cmp::min(pos + 2, bytes.len())] {
+            b"&&" => (Token::And, 2),
+            b"||" => (Token::Or, 2),
+            b"=>" => (Token::Arrow, 2),
+            b"<=" => (Token::Cmp(CmpOp::Le), 2),
+            b">=" => (Token::Cmp(CmpOp::Ge), 2),
+            b"==" => (Token::Cmp(CmpOp::Eq), 2),
+            b"!=" => (Token::Cmp(CmpOp::Ne), 2),
+            _ => match c {
+                b'<' => (Token::Cmp(CmpOp::Lt), 1),
+                b'>' => (Token::Cmp(CmpOp::Gt), 1),
+                b'!' => (Token::Not, 1),
+                b'(' => (Token::LParen, 1),
+                b')' => (Token::RParen, 1),
+                _ => {
+                    return Err(RuleError {
+                        position: start,
+                        message: format!(
+                            "unexpected character {:?}",
+                            source[start..].chars().next().unwrap_or(' ')
+                        ),
+                    })
+                }
+            },
+        };
+        tokens.push((start, token));
+        pos += len;
+    }
+    Ok(tokens)
+}
+
+// A recursive descent parser, from the lowest precedence:
+//   expr    := and ("||" and)*
+//   and     := unary ("&&" unary)*
+//   unary   := "!" unary | primary
+//   primary := "(" expr ")" | "true" | "false" | FLAG | operand CMP operand
+//   operand := NUMBER | VAR
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    // the position reported at the end of the rule
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    // An error at the current token, or at the end of the rule
+    fn error(&self, message: &str) -> RuleError {
+        let position = self.tokens.get(self.pos).map(|(p, _)| *p).unwrap_or(self.end);
+        RuleError { position, message: message.to_string() }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), RuleError> {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}", token_text(token))))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, RuleError> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, RuleError> {
+        let mut left = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, RuleError> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, RuleError> {
+        match self.peek().cloned() {
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(&Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) if name == "true" || name == "false" => {
+                self.pos += 1;
+                Ok(Expr::Const(name == "true"))
+            }
+            Some(Token::Ident(name)) => {
+                let var = RuleVar::from_name(&name)
+                    .ok_or_else(|| self.error(&format!("unknown variable {}", name)))?;
+                if var.is_flag() {
+                    self.pos += 1;
+                    Ok(Expr::Flag(var))
+                } else {
+                    self.comparison()
+                }
+            }
+            Some(Token::Number(_)) => self.comparison(),
+            _ => Err(self.error("expected a condition")),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, RuleError> {
+        let left = self.operand()?;
+        let op = match self.peek() {
+            Some(Token::Cmp(op)) => *op,
+            _ => return Err(self.error("expected a comparison")),
+        };
+        self.pos += 1;
+        let right = self.operand()?;
+        Ok(Expr::Compare(left, op, right))
+    }
+
+    fn operand(&mut self) -> Result<Operand, RuleError> {
+        let operand = match self.peek() {
+            Some(Token::Number(n)) => Operand::Number(*n),
+            Some(Token::Ident(name)) => match RuleVar::from_name(name) {
+                Some(var) if var.is_flag() => {
+                    return Err(self.error(&format!("{} is a flag, not a number", name)))
+                }
+                Some(var) => Operand::Var(var),
+                None => return Err(self.error(&format!("unknown variable {}", name))),
+            },
+            _ => return Err(self.error("expected a number or a variable")),
+        };
+        self.pos += 1;
+        Ok(operand)
+    }
+}
+
+fn token_text(token: &Token) -> &'static str {
+    match token {
+        Token::Arrow => "=>",
+        Token::RParen => ")",
+        _ => "a token",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_test() {
+        let rule = Rule::parse(
+            "channel_value_sat > 5_000_000 && dest.not_allowlisted => require_approval",
+        )
+        .unwrap();
+        assert_eq!(rule.action, RuleAction::RequireApproval);
+        let mut facts = RuleFacts { channel_value_sat: 5_000_000, ..Default::default() };
+        assert!(!rule.matches(&facts));
+        facts.channel_value_sat = 5_000_001;
+        assert!(!rule.matches(&facts));
+        facts.dest_not_allowlisted = true;
+        assert!(rule.matches(&facts));
+
+        // && binds tighter than ||
+        let rule =
+            Rule::parse("op.mutual_close || value_sat >= 10 && fee_sat != 0 => deny").unwrap();
+        assert!(rule.matches(&RuleFacts { mutual_close: true, ..Default::default() }));
+        assert!(!rule.matches(&RuleFacts { value_sat: 10, ..Default::default() }));
+        assert!(rule.matches(&RuleFacts { value_sat: 10, fee_sat: 1, ..Default::default() }));
+
+        let rule = Rule::parse(" !(op.onchain_tx && 1000 < fee_sat) => warn ").unwrap();
+        assert_eq!(rule.source(), "!(op.onchain_tx && 1000 < fee_sat) => warn");
+        assert!(rule.matches(&RuleFacts { fee_sat: 2000, ..Default::default() }));
+        assert!(!rule.matches(&RuleFacts {
+            onchain_tx: true,
+            fee_sat: 2000,
+            ..Default::default()
+        }));
+
+        assert!(Rule::parse("true => deny").unwrap().matches(&RuleFacts::default()));
+    }
+
+    #[test]
+    fn rule_error_test() {
+        let error = |source: &str| Rule::parse(source).unwrap_err().to_string();
+        assert_eq!(error("value > 1 => deny"), "unknown variable value at position 0");
+        assert_eq!(
+            error("value_sat > 1 => block"),
+            "expected deny, require_approval or warn at position 17"
+        );
+        assert_eq!(error("value_sat > 1"), "expected => at position 13");
+        assert_eq!(error("value_sat => deny"), "expected a comparison at position 10");
+        assert_eq!(
+            error("value_sat > op.onchain_tx => deny"),
+            "op.onchain_tx is a flag, not a number at position 12"
+        );
+        assert_eq!(error("(fee_sat > 1 => deny"), "expected ) at position 13");
+        assert_eq!(error("fee_sat = 1 => deny"), "unexpected character '=' at position 8");
+        assert_eq!(
+            error("fee_sat > 99999999999999999999 => deny"),
+            "number too large at position 10"
+        );
+        assert_eq!(
+            error("fee_sat > 1 => deny deny"),
+            "unexpected text after the action at position 20"
+        );
+        assert_eq!(error("=> deny"), "expected a condition at position 0");
+    }
+}
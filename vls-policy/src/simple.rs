@@ -0,0 +1,292 @@
+use bitcoin::Network;
+
+use crate::error::PolicyTag;
+use crate::prelude::*;
+use crate::rules::Rule;
+
+/// How violations of a policy rule are handled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnforcementLevel {
+    /// The operation fails
+    Enforce,
+    /// The violation is logged and counted, and the operation proceeds
+    Warn,
+    /// The violation is ignored
+    Off,
+}
+
+impl EnforcementLevel {
+    /// The name of the level, as used in configuration
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnforcementLevel::Enforce => "enforce",
+            EnforcementLevel::Warn => "warn",
+            EnforcementLevel::Off => "off",
+        }
+    }
+
+    /// The level with the given name
+    pub fn from_name(name: &str) -> Option<EnforcementLevel> {
+        [EnforcementLevel::Enforce, EnforcementLevel::Warn, EnforcementLevel::Off]
+            .iter()
+            .find(|l| l.name() == name)
+            .copied()
+    }
+}
+
+/// A simple policy, enforced by the signer core's `SimpleValidator`
+#[derive(Clone)]
+pub struct SimplePolicy {
+    /// Minimum delay in blocks
+    pub min_delay: u16,
+    /// Maximum delay in blocks
+    pub max_delay: u16,
+    /// Maximum delay in blocks that the counterparty may impose on our
+    /// to_self outputs (counterparty_selected_contest_delay)
+    pub max_counterparty_contest_delay: u16,
+    /// Maximum channel value in satoshi
+    pub max_channel_size_sat: u64,
+    /// Maximum value we push to the counterparty at channel open, in millisatoshi
+    pub max_push_value_msat: u64,
+    /// Maximum value we push to the counterparty at channel open, as a
+    /// percentage of the channel value, at most 100
+    pub max_push_percentage: u8,
+    /// amounts below this number of satoshi are not considered important
+    pub epsilon_sat: u64,
+    /// Maximum number of in-flight HTLCs
+    pub max_htlcs: usize,
+    /// Maximum value of in-flight HTLCs
+    pub max_htlc_value_sat: u64,
+    /// Whether to use knowledge of chain state (e.g. current_height)
+    pub use_chain_state: bool,
+    /// Minimum feerate
+    pub min_feerate_per_kw: u32,
+    /// Maximum feerate
+    pub max_feerate_per_kw: u32,
+    /// Minimum fee in satoshi
+    pub min_fee: u64,
+    /// Maximum fee in satoshi
+    pub max_fee: u64,
+    /// Require invoices for payments, and disallow keysend
+    // TODO secure keysend
+    pub require_invoices: bool,
+    /// Enforce holder balance
+    // TODO incoming payments
+    // TODO routing
+    pub enforce_balance: bool,
+    /// Maximum layer-2 fee
+    pub max_routing_fee_msat: u64,
+    /// Maximum difference in seconds between the host clock and the
+    /// timestamp of the chain tip
+    pub max_clock_skew_secs: u32,
+    /// Refuse time-delay dependent operations if the clock skew is too large,
+    /// or if there is no recent heartbeat
+    pub enforce_clock_skew: bool,
+    /// Grind signatures for a low R value, as bitcoind does.  This costs
+    /// two signing attempts on average.  Channel signatures made by the LDK
+    /// signer are always ground.
+    pub grind_low_r: bool,
+    /// Verify every signature against its message and public key before
+    /// releasing it, to catch faulty hardware.  This costs one signature
+    /// verification per signature.
+    pub verify_signatures: bool,
+    /// Require the funding outpoint to be watched by the chain tracker
+    /// before signing commitments, so that double-spends of the funding of
+    /// zero-conf channels are noticed
+    pub require_funding_watch: bool,
+    /// Minimum depth of the funding transaction, as observed by the chain
+    /// tracker, below which the channel is considered zero-conf.  The
+    /// zero-conf exposure is only enforced if use_chain_state is set.
+    pub min_funding_depth: u16,
+    /// Refuse to sign counterparty commitments beyond the initial one until
+    /// the funding transaction is buried at min_funding_depth
+    pub enforce_funding_depth: bool,
+    /// Maximum value in satoshi that we may receive in a zero-conf channel,
+    /// on top of our initial balance
+    pub max_zero_conf_exposure_sat: u64,
+    /// Refuse to ready a channel unless its funding outpoint and value were
+    /// registered beforehand and then observed on-chain
+    pub require_funding_registration: bool,
+    /// Value in satoshi to destinations outside our wallet that a single
+    /// signing operation may move without an approval token
+    pub max_unapproved_value_sat: u64,
+    /// The HMAC-SHA256 key of the second party who approves signing
+    /// operations above max_unapproved_value_sat.  None disables the
+    /// two-man rule.
+    pub value_approval_key: Option<[u8; 32]>,
+    /// Maximum sum in satoshi of the closing fees of a multi-channel close
+    /// plan
+    pub max_close_plan_fee_sat: u64,
+    /// Maximum value in satoshi that a multi-channel close plan may send to
+    /// destinations outside our wallet
+    pub max_close_plan_non_wallet_sat: u64,
+    /// Flag a channel for operator review once this many blocks have passed
+    /// since it was opened or last reviewed.  Zero disables the check.
+    pub max_channel_age_blocks: u32,
+    /// Flag a channel for operator review once this many blocks have passed
+    /// without a counterparty commitment.  Zero disables the check.
+    pub max_channel_inactivity_blocks: u32,
+    /// Refuse to add HTLCs to a channel that is due for review, until the
+    /// review is acknowledged
+    pub block_htlcs_pending_review: bool,
+    /// Refuse a new attempt at an outgoing payment once this many earlier
+    /// attempts at the same payment hash failed within the retry window.
+    /// Zero disables the check.
+    pub max_payment_retries: u32,
+    /// The number of blocks a failed payment attempt counts against
+    /// `max_payment_retries`
+    pub payment_retry_window_blocks: u32,
+    /// Maximum amount in millisatoshi of an invoice we sign.  Zero disables
+    /// the check.
+    pub max_invoice_amount_msat: u64,
+    /// Maximum expiry in seconds of an invoice we sign.  Zero disables the
+    /// check.
+    pub max_invoice_expiry_secs: u64,
+    /// Maximum value in satoshi that signed on-chain transactions may send
+    /// from the wallet to destinations outside it within the velocity
+    /// window.  Zero disables the check.
+    pub max_onchain_velocity_sat: u64,
+    /// The number of blocks a spend counts against `max_onchain_velocity_sat`,
+    /// at most 2016
+    pub onchain_velocity_window_blocks: u32,
+    /// The enforcement level of the rules with a given tag, for staging new
+    /// rules.  Rules not listed are enforced.  Revocation order is always
+    /// enforced, since signing a revoked state can lose funds.
+    pub enforcement: Vec<(PolicyTag, EnforcementLevel)>,
+    /// Custom rules, checked after the built-in ones when signing on-chain
+    /// transactions and mutual closes
+    pub rules: Vec<Rule>,
+}
+
+impl SimplePolicy {
+    /// Check that the policy can be enforced, when it is built
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_push_percentage > 100 {
+            return Err(format!(
+                "max_push_percentage {} is more than 100",
+                self.max_push_percentage
+            ));
+        }
+        Ok(())
+    }
+
+    /// The enforcement level of the rules with the given tag
+    pub fn enforcement_level(&self, tag: PolicyTag) -> EnforcementLevel {
+        if tag == PolicyTag::RevocationOrder {
+            return EnforcementLevel::Enforce;
+        }
+        self.enforcement
+            .iter()
+            .rev()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, level)| *level)
+            .unwrap_or(EnforcementLevel::Enforce)
+    }
+}
+
+/// Construct a default simple policy
+pub fn make_simple_policy(network: Network) -> SimplePolicy {
+    if network == Network::Bitcoin {
+        SimplePolicy {
+            min_delay: 60,
+            max_delay: 2016, // Match LDK maximum and default
+            max_counterparty_contest_delay: 2016,
+            max_channel_size_sat: 1_000_000_001,
+            max_push_value_msat: 100_000_000,
+            max_push_percentage: 10,
+            epsilon_sat: 1_600_000,
+            max_htlcs: 1000,
+            max_htlc_value_sat: 16_777_216,
+            use_chain_state: false,
+            min_feerate_per_kw: 1000,
+            max_feerate_per_kw: 1000 * 1000,
+            min_fee: 100,
+            max_fee: 1000,
+            require_invoices: false,
+            enforce_balance: false,
+            max_routing_fee_msat: 10000,
+            max_clock_skew_secs: 3 * 3600,
+            enforce_clock_skew: false,
+            grind_low_r: false,
+            verify_signatures: false,
+            require_funding_watch: false,
+            min_funding_depth: 6,
+            enforce_funding_depth: false,
+            max_zero_conf_exposure_sat: 0,
+            require_funding_registration: false,
+            max_unapproved_value_sat: 10_000_000,
+            value_approval_key: None,
+            max_close_plan_fee_sat: 100_000,
+            max_close_plan_non_wallet_sat: 10_000_000,
+            max_channel_age_blocks: 0,
+            max_channel_inactivity_blocks: 0,
+            block_htlcs_pending_review: false,
+            max_payment_retries: 0,
+            payment_retry_window_blocks: 144,
+            max_invoice_amount_msat: 0,
+            max_invoice_expiry_secs: 30 * 24 * 3600,
+            max_onchain_velocity_sat: 0,
+            onchain_velocity_window_blocks: 144,
+            enforcement: vec![],
+            rules: vec![],
+        }
+    } else {
+        SimplePolicy {
+            min_delay: 4,
+            max_delay: 2016, // Match LDK maximum and default
+            max_counterparty_contest_delay: 2016,
+            max_channel_size_sat: 1_000_000_001, // lnd itest: wumbu default + 1
+            max_push_value_msat: 1_000_000_001_000,
+            max_push_percentage: 100,
+            // lnd itest: async_bidirectional_payments (large amount of dust HTLCs) 1_600_000
+            epsilon_sat: 10_000, // c-lightning
+            max_htlcs: 1000,
+            max_htlc_value_sat: 16_777_216, // lnd itest: multi-hop_htlc_error_propagation
+            use_chain_state: false,
+            min_feerate_per_kw: 500,    // c-lightning integration
+            max_feerate_per_kw: 16_000, // c-lightning integration
+            min_fee: 100,
+            max_fee: 200_000, // c-lightning integration 124301
+            require_invoices: false,
+            enforce_balance: false,
+            max_routing_fee_msat: 10000,
+            max_clock_skew_secs: 24 * 3600, // test networks can stall
+            enforce_clock_skew: false,
+            grind_low_r: false,
+            verify_signatures: false,
+            require_funding_watch: false,
+            min_funding_depth: 1,
+            enforce_funding_depth: false,
+            max_zero_conf_exposure_sat: 1_000_000,
+            require_funding_registration: false,
+            max_unapproved_value_sat: 10_000_000,
+            value_approval_key: None,
+            max_close_plan_fee_sat: 2_000_000,
+            max_close_plan_non_wallet_sat: 10_000_000,
+            max_channel_age_blocks: 0,
+            max_channel_inactivity_blocks: 0,
+            block_htlcs_pending_review: false,
+            max_payment_retries: 0,
+            payment_retry_window_blocks: 144,
+            max_invoice_amount_msat: 0,
+            max_invoice_expiry_secs: 30 * 24 * 3600,
+            max_onchain_velocity_sat: 0,
+            onchain_velocity_window_blocks: 144,
+            enforcement: vec![],
+            rules: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_test() {
+        let mut policy = make_simple_policy(Network::Testnet);
+        assert!(policy.validate().is_ok());
+        policy.max_push_percentage = 101;
+        assert_eq!(policy.validate().unwrap_err(), "max_push_percentage 101 is more than 100");
+    }
+}
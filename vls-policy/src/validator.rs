@@ -0,0 +1,423 @@
+use alloc::sync::Arc;
+
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use bitcoin::{self, Network, Script, SigHash, SigHashType, Transaction};
+use lightning::chain::keysinterface::InMemorySigner;
+use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
+
+use crate::error::ValidationError;
+use crate::prelude::*;
+
+/// The types of the signer that a [Validator] checks.
+///
+/// The channel and its state are defined by the signer, which depends on
+/// this crate.  They are associated types here, so that the validator
+/// traits don't depend on the signer.  The signer implements this trait
+/// as `lightning_signer::policy::validator::CoreTypes`.
+pub trait SignerTypes {
+    /// The layer-1 wallet of the node
+    type Wallet: ?Sized;
+    /// A channel, shared by the node, as passed to
+    /// [Validator::validate_onchain_tx]
+    type ChannelRef;
+    /// A ready channel
+    type Channel;
+    /// The channel ID
+    type ChannelId;
+    /// The parameters of a ready channel
+    type ChannelSetup;
+    /// The enforcement state of a channel
+    type EnforcementState;
+    /// The blockchain state of a channel
+    type ChainState;
+    /// A decoded commitment transaction
+    type CommitmentInfo;
+    /// A rebuilt commitment transaction
+    type CommitmentInfo2;
+    /// Our contribution to an interactively constructed transaction
+    type InteractiveFunding;
+    /// A wallet input to an interactively constructed transaction
+    type InteractiveInput;
+    /// An output of an interactively constructed transaction
+    type InteractiveOutput;
+    /// An entry of the payment ledger
+    type PaymentLedgerEntry;
+}
+
+/// A policy checker
+///
+/// Called by the Node and Channel of the signer as needed.
+pub trait Validator<T: SignerTypes> {
+    /// Validate ready channel parameters.
+    /// The holder_shutdown_key_path should be an empty vector if the
+    /// setup.holder_shutdown_script is not set or the address is in
+    /// the allowlist.
+    fn validate_ready_channel(
+        &self,
+        wallet: &T::Wallet,
+        setup: &T::ChannelSetup,
+        holder_shutdown_key_path: &Vec<u32>,
+    ) -> Result<(), ValidationError>;
+
+    /// Validate channel value after it is late-filled
+    fn validate_channel_value(&self, setup: &T::ChannelSetup) -> Result<(), ValidationError>;
+
+    /// Validate an onchain transaction (funding tx, simple sweeps).
+    /// This transaction may fund multiple channels at the same time.
+    ///
+    /// * `channels` the funded channel for each funding output, or
+    ///   None for change outputs
+    /// * `values_sat` - the amount in satoshi per input
+    /// * `opaths` - derivation path for change, one per output,
+    ///   empty for non-change or allowlisted outputs
+    fn validate_onchain_tx(
+        &self,
+        wallet: &T::Wallet,
+        channels: Vec<Option<T::ChannelRef>>,
+        tx: &Transaction,
+        values_sat: &Vec<u64>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<(), ValidationError>;
+
+    /// Validate a funding transaction of channels that we open, on top of
+    /// [Validator::validate_onchain_tx].  All the inputs must be from our
+    /// wallet, and all the outputs must fund a channel or return change
+    /// to our wallet, so that the funds can't be diverted.
+    ///
+    /// * `channels` the funded channel for each funding output, or
+    ///   None for change outputs
+    /// * `inputs` - our wallet inputs, in the order of the transaction inputs
+    /// * `opaths` - derivation path for change, one per output
+    fn validate_funding_tx(
+        &self,
+        wallet: &T::Wallet,
+        channels: &Vec<Option<T::ChannelRef>>,
+        tx: &Transaction,
+        inputs: &Vec<T::InteractiveInput>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<(), ValidationError>;
+
+    /// Validate a wallet input we contribute to an interactive funding
+    /// transaction construction (dual-funding)
+    fn validate_interactive_funding_input(
+        &self,
+        wallet: &T::Wallet,
+        input: &T::InteractiveInput,
+    ) -> Result<(), ValidationError>;
+
+    /// Validate an output we contribute to an interactive funding
+    /// transaction construction
+    fn validate_interactive_funding_output(
+        &self,
+        wallet: &T::Wallet,
+        output: &T::InteractiveOutput,
+    ) -> Result<(), ValidationError>;
+
+    /// Validate a completed interactive funding transaction before signing
+    /// our inputs.
+    ///
+    /// * `chan` - the channel funded by the transaction
+    /// * `funding` - our inputs, outputs and contribution
+    fn validate_interactive_funding_tx(
+        &self,
+        chan: &T::Channel,
+        tx: &Transaction,
+        funding: &T::InteractiveFunding,
+    ) -> Result<(), ValidationError>;
+
+    /// Validate a splice transaction, which spends the current funding
+    /// output of a ready channel into a new funding output.
+    ///
+    /// * `vout` - the index of the new funding output
+    /// * `inputs` - our wallet inputs, spliced into the channel
+    /// * `outputs` - our outputs, spliced out of the channel
+    fn validate_splice_tx(
+        &self,
+        wallet: &T::Wallet,
+        chan: &T::Channel,
+        tx: &Transaction,
+        vout: u32,
+        inputs: &Vec<T::InteractiveInput>,
+        outputs: &Vec<T::InteractiveOutput>,
+    ) -> Result<(), ValidationError>;
+
+    /// Phase 1 CommitmentInfo
+    fn decode_commitment_tx(
+        &self,
+        keys: &InMemorySigner,
+        setup: &T::ChannelSetup,
+        is_counterparty: bool,
+        tx: &bitcoin::Transaction,
+        output_witscripts: &Vec<Vec<u8>>,
+    ) -> Result<T::CommitmentInfo, ValidationError>;
+
+    /// Validate a counterparty commitment
+    fn validate_counterparty_commitment_tx(
+        &self,
+        estate: &T::EnforcementState,
+        commit_num: u64,
+        commitment_point: &PublicKey,
+        setup: &T::ChannelSetup,
+        cstate: &T::ChainState,
+        info2: &T::CommitmentInfo2,
+    ) -> Result<(), ValidationError>;
+
+    /// Validate a holder commitment
+    fn validate_holder_commitment_tx(
+        &self,
+        estate: &T::EnforcementState,
+        commit_num: u64,
+        commitment_point: &PublicKey,
+        setup: &T::ChannelSetup,
+        cstate: &T::ChainState,
+        info2: &T::CommitmentInfo2,
+    ) -> Result<(), ValidationError>;
+
+    /// Check a counterparty's revocation of an old state.
+    /// This also makes a note that the counterparty has committed to their
+    /// current commitment transaction.
+    fn validate_counterparty_revocation(
+        &self,
+        state: &T::EnforcementState,
+        revoke_num: u64,
+        commitment_secret: &SecretKey,
+    ) -> Result<(), ValidationError>;
+
+    /// Phase 1 decoding of 2nd level HTLC tx and validation by recomposition
+    fn decode_and_validate_htlc_tx(
+        &self,
+        is_counterparty: bool,
+        setup: &T::ChannelSetup,
+        txkeys: &TxCreationKeys,
+        tx: &Transaction,
+        redeemscript: &Script,
+        htlc_amount_sat: u64,
+        output_witscript: &Script,
+    ) -> Result<(u32, HTLCOutputInCommitment, SigHash, SigHashType), ValidationError>;
+
+    /// Phase 2 validation of 2nd level HTLC tx
+    fn validate_htlc_tx(
+        &self,
+        setup: &T::ChannelSetup,
+        cstate: &T::ChainState,
+        is_counterparty: bool,
+        htlc: &HTLCOutputInCommitment,
+        feerate_per_kw: u32,
+    ) -> Result<(), ValidationError>;
+
+    /// Phase 1 decoding and recomposition of mutual_close
+    fn decode_and_validate_mutual_close_tx(
+        &self,
+        wallet: &T::Wallet,
+        setup: &T::ChannelSetup,
+        state: &T::EnforcementState,
+        tx: &Transaction,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<ClosingTransaction, ValidationError>;
+
+    /// Phase 2 Validatation of mutual_close
+    fn validate_mutual_close_tx(
+        &self,
+        wallet: &T::Wallet,
+        setup: &T::ChannelSetup,
+        state: &T::EnforcementState,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_shutdown_script: &Option<Script>,
+        counterparty_shutdown_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError>;
+
+    /// Validation of an option_simple_close closing transaction.
+    /// An omitted output has a zero value.
+    #[cfg(feature = "simple_close")]
+    fn validate_simple_close_tx(
+        &self,
+        wallet: &T::Wallet,
+        setup: &T::ChannelSetup,
+        state: &T::EnforcementState,
+        holder_is_closer: bool,
+        locktime: u32,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError>;
+
+    /// Validation of the aggregate fee and non-wallet value of a plan to
+    /// mutually close several channels.  Each close is validated
+    /// separately with [Validator::validate_mutual_close_tx].
+    fn validate_close_plan(&self, fee_sat: u64, non_wallet_sat: u64)
+        -> Result<(), ValidationError>;
+
+    /// Validation of delayed sweep transaction
+    fn validate_delayed_sweep(
+        &self,
+        wallet: &T::Wallet,
+        setup: &T::ChannelSetup,
+        cstate: &T::ChainState,
+        tx: &Transaction,
+        input: usize,
+        amount_sat: u64,
+        key_path: &Vec<u32>,
+    ) -> Result<(), ValidationError>;
+
+    /// Validation of a sweep of the to-remote output of a commitment
+    /// transaction the counterparty broadcast
+    fn validate_counterparty_payment_sweep(
+        &self,
+        wallet: &T::Wallet,
+        setup: &T::ChannelSetup,
+        cstate: &T::ChainState,
+        tx: &Transaction,
+        input: usize,
+        amount_sat: u64,
+        key_path: &Vec<u32>,
+    ) -> Result<(), ValidationError>;
+
+    /// Validation of counterparty htlc sweep transaction (first level
+    /// commitment htlc outputs)
+    fn validate_counterparty_htlc_sweep(
+        &self,
+        wallet: &T::Wallet,
+        setup: &T::ChannelSetup,
+        cstate: &T::ChainState,
+        tx: &Transaction,
+        redeemscript: &Script,
+        input: usize,
+        amount_sat: u64,
+        key_path: &Vec<u32>,
+    ) -> Result<(), ValidationError>;
+
+    /// Validation of justice sweep transaction
+    fn validate_justice_sweep(
+        &self,
+        wallet: &T::Wallet,
+        setup: &T::ChannelSetup,
+        cstate: &T::ChainState,
+        tx: &Transaction,
+        input: usize,
+        amount_sat: u64,
+        key_path: &Vec<u32>,
+    ) -> Result<(), ValidationError>;
+
+    /// Validation of the payment state for a payment hash.
+    /// This could include a payment routed through us, or a payment we
+    /// are making, or both.  If we are not making a payment, then the incoming
+    /// must be greater or equal to the outgoing.  Otherwise, the incoming
+    /// minus outgoing should be enough to pay for the invoice and routing fees,
+    /// but no larger.
+    fn validate_payment_balance(
+        &self,
+        incoming: u64,
+        outgoing: u64,
+        invoiced_amount_msat: Option<u64>,
+    ) -> Result<(), ValidationError>;
+
+    /// Whether the policy specifies that holder balance should be tracked and
+    /// enforced.
+    fn enforce_balance(&self) -> bool {
+        false
+    }
+
+    /// Whether a channel may only be readied once its funding was
+    /// registered with the signer and observed
+    /// on-chain.
+    fn require_funding_registration(&self) -> bool {
+        false
+    }
+
+    /// Whether signatures should be ground until they have a low R value,
+    /// making them one byte smaller.
+    fn grind_low_r(&self) -> bool {
+        false
+    }
+
+    /// Whether every signature should be verified before it is released,
+    /// to catch faulty hardware.
+    fn verify_signatures(&self) -> bool {
+        false
+    }
+
+    /// The reason the channel is due for operator review, if it is, because
+    /// it is too old or has been inactive for too long.
+    fn channel_review_due(
+        &self,
+        _estate: &T::EnforcementState,
+        _cstate: &T::ChainState,
+    ) -> Option<String> {
+        None
+    }
+
+    /// Validate a new attempt at an outgoing payment.
+    ///
+    /// * `attempt_heights` - the heights at which the earlier attempts at
+    ///   the same payment hash started, all of which failed
+    fn validate_payment_retry(
+        &self,
+        _attempt_heights: &[u32],
+        _cstate: &T::ChainState,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    /// Validate a new attempt at an outgoing payment, or an invoice added
+    /// for payment, against its entry in the payment ledger, so that an
+    /// invoice is not paid twice
+    fn validate_payment_duplicate(
+        &self,
+        _entry: &T::PaymentLedgerEntry,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    /// Validate the value that a transaction we sign sends from the layer-1
+    /// wallet to destinations outside it, together with the earlier such
+    /// transactions, against the velocity limit.
+    ///
+    /// * `spends` - the heights and values of the earlier transactions
+    /// * `value_sat` - the value sent by this transaction
+    fn validate_onchain_velocity(
+        &self,
+        _spends: &[(u32, u64)],
+        _value_sat: u64,
+        _cstate: &T::ChainState,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    /// Validate an invoice before we sign it.
+    ///
+    /// * `amount_msat` - the amount of the invoice, zero if it is for any
+    ///   amount
+    /// * `expiry_secs` - the expiry of the invoice, from its timestamp
+    fn validate_invoice(
+        &self,
+        _amount_msat: u64,
+        _expiry_secs: u64,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    /// The minimum initial commitment transaction balance to us, given
+    /// the funding amount.
+    /// The result is in satoshi.
+    fn minimum_initial_balance(&self, holder_value_msat: u64) -> u64;
+
+    /// Check the host clock against the timestamp of the chain tip.
+    ///
+    /// * `clock_skew_secs` - the host clock minus the tip timestamp
+    fn validate_clock_skew(&self, clock_skew_secs: i64) -> Result<(), ValidationError>;
+}
+
+/// A factory for validators
+pub trait ValidatorFactory<T: SignerTypes>: Send + Sync {
+    /// Construct a validator
+    fn make_validator(
+        &self,
+        network: Network,
+        node_id: PublicKey,
+        channel_id: Option<T::ChannelId>,
+    ) -> Arc<dyn Validator<T>>;
+}
@@ -250,15 +250,19 @@ fn from_status(s: Status) -> JSValidationError {
 
 #[wasm_bindgen]
 pub fn make_node() -> JSNode {
-    let config =
-        NodeConfig { network: Network::Testnet, key_derivation_style: KeyDerivationStyle::Native };
+    let config = NodeConfig {
+        network: Network::Testnet,
+        key_derivation_style: KeyDerivationStyle::Native,
+        chain_params: None,
+        checkpoint: None,
+    };
     let mut seed = [0u8; 32];
     randomize_buffer(&mut seed);
     // TODO remove in production :)
     debug!("SEED {}", seed.to_hex());
     let persister: Arc<dyn Persist> = Arc::new(DummyPersister);
     let validator_factory = Arc::new(SimpleValidatorFactory::new());
-    let node = Node::new(config, &seed, &persister, vec![], validator_factory);
+    let node = Node::new(config, &seed, &persister, vec![], validator_factory).expect("new node");
     JSNode { node: Arc::new(node) }
 }
 